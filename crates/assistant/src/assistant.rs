@@ -16,6 +16,7 @@ mod terminal_inline_assistant;
 mod tools;
 mod workflow;
 
+use anyhow::Result;
 pub use assistant_panel::{AssistantPanel, AssistantPanelEvent};
 use assistant_settings::AssistantSettings;
 use assistant_slash_command::SlashCommandRegistry;
@@ -27,7 +28,8 @@ use context_servers::ContextServerRegistry;
 pub use context_store::*;
 use feature_flags::FeatureFlagAppExt;
 use fs::Fs;
-use gpui::{actions, AppContext, Global, SharedString, UpdateGlobal};
+use futures::{future, StreamExt as _};
+use gpui::{actions, AppContext, AsyncAppContext, Global, SharedString, UpdateGlobal};
 use gpui::{impl_actions, Context as _};
 use indexed_docs::IndexedDocsRegistry;
 pub(crate) use inline_assistant::*;
@@ -37,7 +39,10 @@ use language_model::{
 pub(crate) use model_selector::*;
 pub use prompts::PromptBuilder;
 use prompts::PromptLoadingParams;
-use semantic_index::{CloudEmbeddingProvider, SemanticIndex};
+use semantic_index::{
+    CloudEmbeddingProvider, EmbeddingProvider, EmbeddingProviderSettings, LocalEmbeddingModel,
+    LocalEmbeddingProvider, SemanticIndex, SemanticIndexSettings,
+};
 use serde::{Deserialize, Serialize};
 use settings::{update_settings_file, Settings, SettingsStore};
 use slash_command::{
@@ -186,6 +191,61 @@ impl Assistant {
     }
 }
 
+/// The embedding provider that semantic search uses, chosen from the settings and
+/// from whether the user is signed in.
+#[derive(Clone, PartialEq)]
+enum EmbeddingProviderChoice {
+    Configured(EmbeddingProviderSettings),
+    Cloud,
+    Local,
+}
+
+impl EmbeddingProviderChoice {
+    async fn current(client: &Client, cx: &AsyncAppContext) -> Result<Self> {
+        let (provider_settings, local_embeddings) = cx.update(|cx| {
+            let settings = SemanticIndexSettings::get_global(cx);
+            (
+                settings.embedding_provider.clone(),
+                settings.local_embeddings,
+            )
+        })?;
+        // Reading the credentials may prompt for access to the keychain, so it's only
+        // done when they decide between the providers.
+        let signed_in =
+            provider_settings.is_some() || !local_embeddings || client.has_credentials(cx).await;
+        Ok(Self::choose(provider_settings, local_embeddings, signed_in))
+    }
+
+    fn choose(
+        provider_settings: Option<EmbeddingProviderSettings>,
+        local_embeddings: bool,
+        signed_in: bool,
+    ) -> Self {
+        if let Some(provider_settings) = provider_settings {
+            Self::Configured(provider_settings)
+        } else if local_embeddings && !signed_in {
+            // Users who opted in embed on this machine until they sign in, so that
+            // semantic search works without an account.
+            Self::Local
+        } else {
+            Self::Cloud
+        }
+    }
+
+    fn provider(&self, client: &Arc<Client>, cx: &AsyncAppContext) -> Arc<dyn EmbeddingProvider> {
+        match self {
+            Self::Configured(provider_settings) => provider_settings.provider(client.http_client()),
+            Self::Cloud => Arc::new(CloudEmbeddingProvider::new(client.clone())),
+            Self::Local => Arc::new(LocalEmbeddingProvider::new(
+                LocalEmbeddingModel::BgeSmallEnV1_5,
+                paths::embeddings_dir().join("models"),
+                client.http_client(),
+                cx.background_executor().clone(),
+            )),
+        }
+    }
+}
+
 pub fn init(
     fs: Arc<dyn Fs>,
     client: Arc<Client>,
@@ -195,6 +255,7 @@ pub fn init(
     cx.set_global(Assistant::default());
     AssistantSettings::register(cx);
     SlashCommandSettings::register(cx);
+    semantic_index::init(cx);
 
     // TODO: remove this when 0.148.0 is released.
     if AssistantSettings::get_global(cx).using_outdated_settings_version {
@@ -209,14 +270,42 @@ pub fn init(
     cx.spawn(|mut cx| {
         let client = client.clone();
         async move {
-            let embedding_provider = CloudEmbeddingProvider::new(client.clone());
+            let mut provider_choice = EmbeddingProviderChoice::current(&client, &cx).await?;
             let semantic_index = SemanticIndex::new(
                 paths::embeddings_dir().join("semantic-index-db.0.mdb"),
-                Arc::new(embedding_provider),
+                provider_choice.provider(&client, &cx),
                 &mut cx,
             )
             .await?;
-            cx.update(|cx| cx.set_global(semantic_index))
+            cx.update(|cx| cx.set_global(semantic_index))?;
+
+            // Choose the provider again when the user signs in or out, or changes the
+            // settings.
+            let (settings_changed_tx, settings_changed_rx) = futures::channel::mpsc::unbounded();
+            let _settings_subscription = cx.update(|cx| {
+                cx.observe_global::<SettingsStore>(move |_| {
+                    settings_changed_tx.unbounded_send(()).ok();
+                })
+            })?;
+            let status_changed = client
+                .status()
+                .filter(|status| future::ready(status.is_connected() || status.is_signed_out()))
+                .map(|_| ());
+            let mut changes = futures::stream::select(status_changed, settings_changed_rx);
+            while changes.next().await.is_some() {
+                let new_provider_choice = EmbeddingProviderChoice::current(&client, &cx).await?;
+                if new_provider_choice == provider_choice {
+                    continue;
+                }
+                provider_choice = new_provider_choice;
+                let embedding_provider = provider_choice.provider(&client, &cx);
+                cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+                    semantic_index.set_embedding_provider(embedding_provider, cx)
+                })?
+                .await
+                .log_err();
+            }
+            anyhow::Ok(())
         }
     })
     .detach();
@@ -444,3 +533,37 @@ fn init_logger() {
         env_logger::init();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semantic_index::VoyageSettings;
+
+    #[test]
+    fn test_embedding_provider_choice() {
+        let voyage = EmbeddingProviderSettings::Voyage(VoyageSettings::default());
+        for local_embeddings in [false, true] {
+            for signed_in in [false, true] {
+                // A configured provider is used whether or not the user is signed in.
+                assert!(
+                    EmbeddingProviderChoice::choose(
+                        Some(voyage.clone()),
+                        local_embeddings,
+                        signed_in
+                    ) == EmbeddingProviderChoice::Configured(voyage.clone())
+                );
+            }
+        }
+
+        // Embedding locally is opt-in, and only until the user signs in.
+        assert!(
+            EmbeddingProviderChoice::choose(None, false, false) == EmbeddingProviderChoice::Cloud
+        );
+        assert!(
+            EmbeddingProviderChoice::choose(None, true, false) == EmbeddingProviderChoice::Local
+        );
+        assert!(
+            EmbeddingProviderChoice::choose(None, true, true) == EmbeddingProviderChoice::Cloud
+        );
+    }
+}
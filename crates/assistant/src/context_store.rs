@@ -688,7 +688,7 @@ impl ContextStore {
                 let matches = fuzzy::match_strings(
                     &candidates,
                     &query,
-                    false,
+                    fuzzy::CaseSensitivity::Insensitive,
                     100,
                     &Default::default(),
                     executor,
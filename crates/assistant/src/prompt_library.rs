@@ -27,13 +27,7 @@ use release_channel::ReleaseChannel;
 use rope::Rope;
 use serde::{Deserialize, Serialize};
 use settings::Settings;
-use std::{
-    cmp::Reverse,
-    future::Future,
-    path::PathBuf,
-    sync::{atomic::AtomicBool, Arc},
-    time::Duration,
-};
+use std::{cmp::Reverse, future::Future, path::PathBuf, sync::Arc, time::Duration};
 use text::LineEnding;
 use theme::ThemeSettings;
 use ui::{
@@ -1447,9 +1441,9 @@ impl PromptStore {
                 let matches = fuzzy::match_strings(
                     &candidates,
                     &query,
-                    false,
+                    fuzzy::CaseSensitivity::Insensitive,
                     100,
-                    &AtomicBool::default(),
+                    &Default::default(),
                     executor,
                 )
                 .await;
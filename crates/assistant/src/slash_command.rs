@@ -3,7 +3,7 @@ use anyhow::Result;
 use assistant_slash_command::AfterCompletion;
 pub use assistant_slash_command::{SlashCommand, SlashCommandOutput, SlashCommandRegistry};
 use editor::{CompletionProvider, Editor};
-use fuzzy::{match_strings, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatchCandidate};
 use gpui::{AppContext, Model, Task, ViewContext, WeakView, WindowContext};
 use language::{Anchor, Buffer, CodeLabel, Documentation, HighlightId, LanguageServerId, ToPoint};
 use parking_lot::{Mutex, RwLock};
@@ -84,7 +84,7 @@ impl SlashCommandCompletionProvider {
             let matches = match_strings(
                 &candidates,
                 &command_name,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 cx.background_executor().clone(),
@@ -45,6 +45,8 @@ impl DiagnosticsSlashCommand {
                         path_prefix: path_prefix.clone(),
                         is_dir: false, // Diagnostics can't be produced for directories
                         distance_to_relative_ancestor: 0,
+                        git_status: None,
+                        symlink_target: None,
                     })
                     .collect(),
             )
@@ -60,7 +62,11 @@ impl DiagnosticsSlashCommand {
                             .root_entry()
                             .map_or(false, |entry| entry.is_ignored),
                         include_root_name: true,
+                        root_alias: None,
+                        priority_dirs: Vec::new(),
                         candidates: project::Candidates::Entries,
+                        filter: project::CandidateFilter::default(),
+                        resolve_symlinks: false,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -69,11 +75,12 @@ impl DiagnosticsSlashCommand {
             cx.foreground_executor().spawn(async move {
                 fuzzy::match_path_sets(
                     candidate_sets.as_slice(),
-                    query.as_str(),
+                    &fuzzy::PreparedQuery::new(&query),
                     None,
-                    false,
+                    fuzzy::CaseSensitivity::Insensitive,
                     100,
-                    &cancellation_flag,
+                    &Default::default(),
+                    &cancellation_flag.into(),
                     executor,
                 )
                 .await
@@ -138,9 +145,9 @@ impl SlashCommand for DiagnosticsSlashCommand {
                 fuzzy::match_strings(
                     &Options::match_candidates_for_args(),
                     &query,
-                    false,
+                    fuzzy::CaseSensitivity::Insensitive,
                     10,
-                    &cancellation_flag,
+                    &cancellation_flag.into(),
                     executor,
                 )
                 .await
@@ -64,6 +64,8 @@ impl FileSlashCommand {
                             path_prefix: path_prefix.clone(),
                             distance_to_relative_ancestor: 0,
                             is_dir,
+                            git_status: None,
+                            symlink_target: None,
                         })
                     })
                     .collect(),
@@ -81,7 +83,11 @@ impl FileSlashCommand {
                             .root_entry()
                             .map_or(false, |entry| entry.is_ignored),
                         include_root_name: true,
+                        root_alias: None,
+                        priority_dirs: Vec::new(),
                         candidates: project::Candidates::Entries,
+                        filter: project::CandidateFilter::default(),
+                        resolve_symlinks: false,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -90,11 +96,12 @@ impl FileSlashCommand {
             cx.foreground_executor().spawn(async move {
                 fuzzy::match_path_sets(
                     candidate_sets.as_slice(),
-                    query.as_str(),
+                    &fuzzy::PreparedQuery::new(&query),
                     None,
-                    false,
+                    fuzzy::CaseSensitivity::Insensitive,
                     100,
-                    &cancellation_flag,
+                    &Default::default(),
+                    &cancellation_flag.into(),
                     executor,
                 )
                 .await
@@ -8,7 +8,7 @@ use assistant_slash_command::{ArgumentCompletion, SlashCommandOutputSection};
 use feature_flags::FeatureFlag;
 use gpui::{AppContext, Task, WeakView};
 use language::{CodeLabel, LineEnding, LspAdapterDelegate};
-use semantic_index::SemanticIndex;
+use semantic_index::{SearchFilter, SemanticIndex, RELEVANT_SCORE};
 use std::{
     fmt::Write,
     path::PathBuf,
@@ -98,7 +98,14 @@ impl SlashCommand for SearchSlashCommand {
         cx.spawn(|cx| async move {
             let results = project_index
                 .read_with(&cx, |project_index, cx| {
-                    project_index.search(query.clone(), limit.unwrap_or(5), cx)
+                    project_index.search(
+                        query.clone(),
+                        SearchFilter::default(),
+                        limit.unwrap_or(5),
+                        Some(RELEVANT_SCORE),
+                        None,
+                        cx,
+                    )
                 })?
                 .await?;
 
@@ -280,11 +280,12 @@ fn tab_items_for_queries(
                         })
                         .collect::<Vec<_>>();
                     let mut processed_matches = HashSet::default();
+                    let cancel = fuzzy::CancellationToken::from(cancel);
                     let file_queries = queries.iter().map(|query| {
                         fuzzy::match_strings(
                             &match_candidates,
                             query,
-                            true,
+                            fuzzy::CaseSensitivity::Smart,
                             usize::MAX,
                             &cancel,
                             background_executor.clone(),
@@ -0,0 +1,125 @@
+use std::{future, path::Path, sync::Arc};
+
+use anyhow::Result;
+use call::ActiveCall;
+use futures::{future::BoxFuture, FutureExt as _};
+use gpui::{BackgroundExecutor, TestAppContext};
+use semantic_index::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, SearchFilter, SemanticIndex, Status,
+    TextToEmbed,
+};
+use serde_json::json;
+
+use crate::tests::TestServer;
+
+struct TestEmbeddingProvider;
+
+impl EmbeddingProvider for TestEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let embeddings = texts
+            .iter()
+            .map(|to_embed| {
+                let garbage = if to_embed.text.contains("garbage in") {
+                    1.
+                } else {
+                    0.
+                };
+                Embedding::new(vec![garbage, 1. - garbage])
+            })
+            .collect();
+        future::ready(Ok(embeddings)).boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        16
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "test".into(),
+            model: "test".into(),
+            dimensions: 2,
+        }
+    }
+}
+
+#[gpui::test]
+async fn test_index_remote_project(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    // The index is stored in a database on disk.
+    executor.allow_parking();
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/dir",
+            json!({
+                "main.rs": "fn main() {}",
+                "needle.md": "# Needle\n\ngarbage in, garbage out",
+            }),
+        )
+        .await;
+    let (project_a, _) = client_a.build_local_project("/dir", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_dev_server_project(project_id, cx_b).await;
+
+    cx_b.update(semantic_index::init);
+    let db_dir = tempfile::tempdir().unwrap();
+    let mut semantic_index = SemanticIndex::new(
+        db_dir.path().into(),
+        Arc::new(TestEmbeddingProvider),
+        &mut cx_b.to_async(),
+    )
+    .await
+    .unwrap();
+    let project_index = cx_b.update(|cx| semantic_index.project_index(project_b.clone(), cx));
+
+    // The guest indexes the host's files, which it reads through buffers.
+    while project_index
+        .read_with(cx_b, |index, cx| index.path_count(cx))
+        .unwrap()
+        < 2
+    {
+        project_index.next_event::<Status>(cx_b).await;
+    }
+    let results = cx_b
+        .update(|cx| {
+            project_index.read(cx).search(
+                "garbage in, garbage out".into(),
+                SearchFilter::default(),
+                4,
+                None,
+                None,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    assert_eq!(results[0].path.as_ref(), Path::new("needle.md"));
+
+    // Files the host adds are indexed by the guest too.
+    client_a
+        .fs()
+        .insert_file("/dir/haystack.md", b"no needle here".to_vec())
+        .await;
+    while project_index
+        .read_with(cx_b, |index, cx| index.path_count(cx))
+        .unwrap()
+        < 3
+    {
+        project_index.next_event::<Status>(cx_b).await;
+    }
+}
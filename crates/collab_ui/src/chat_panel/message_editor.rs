@@ -295,7 +295,7 @@ impl MessageEditor {
         let matches = fuzzy::match_strings(
             candidates,
             query,
-            true,
+            fuzzy::CaseSensitivity::Smart,
             10,
             &Default::default(),
             cx.background_executor().clone(),
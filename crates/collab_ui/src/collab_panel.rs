@@ -9,7 +9,7 @@ use client::{ChannelId, Client, Contact, ProjectId, User, UserStore};
 use contact_finder::ContactFinder;
 use db::kvp::KEY_VALUE_STORE;
 use editor::{Editor, EditorElement, EditorStyle};
-use fuzzy::{match_strings, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatchCandidate};
 use gpui::{
     actions, anchored, canvas, deferred, div, fill, list, point, prelude::*, px, AnyElement,
     AppContext, AsyncWindowContext, Bounds, ClickEvent, ClipboardItem, DismissEvent, Div,
@@ -405,7 +405,7 @@ impl CollabPanel {
                     let matches = executor.block(match_strings(
                         &self.match_candidates,
                         &query,
-                        true,
+                        CaseSensitivity::Smart,
                         usize::MAX,
                         &Default::default(),
                         executor.clone(),
@@ -449,7 +449,7 @@ impl CollabPanel {
                 let mut matches = executor.block(match_strings(
                     &self.match_candidates,
                     &query,
-                    true,
+                    CaseSensitivity::Smart,
                     usize::MAX,
                     &Default::default(),
                     executor.clone(),
@@ -503,7 +503,7 @@ impl CollabPanel {
                 let matches = executor.block(match_strings(
                     &self.match_candidates,
                     &query,
-                    true,
+                    CaseSensitivity::Smart,
                     usize::MAX,
                     &Default::default(),
                     executor.clone(),
@@ -538,7 +538,7 @@ impl CollabPanel {
             let matches = executor.block(match_strings(
                 &self.match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 executor.clone(),
@@ -621,7 +621,7 @@ impl CollabPanel {
             let matches = executor.block(match_strings(
                 &self.match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 executor.clone(),
@@ -661,7 +661,7 @@ impl CollabPanel {
             let matches = executor.block(match_strings(
                 &self.match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 executor.clone(),
@@ -690,7 +690,7 @@ impl CollabPanel {
             let matches = executor.block(match_strings(
                 &self.match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 executor.clone(),
@@ -728,7 +728,7 @@ impl CollabPanel {
             let matches = executor.block(match_strings(
                 &self.match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
                 &Default::default(),
                 executor.clone(),
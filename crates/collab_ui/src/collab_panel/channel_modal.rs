@@ -3,7 +3,7 @@ use client::{
     proto::{self, ChannelRole, ChannelVisibility},
     ChannelId, User, UserId, UserStore,
 };
-use fuzzy::{match_strings, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatchCandidate};
 use gpui::{
     actions, anchored, deferred, div, AppContext, ClipboardItem, DismissEvent, EventEmitter,
     FocusableView, Model, ParentElement, Render, Styled, Subscription, Task, View, ViewContext,
@@ -282,7 +282,7 @@ impl PickerDelegate for ChannelModalDelegate {
                     let matches = cx.background_executor().block(match_strings(
                         &self.match_candidates,
                         &query,
-                        true,
+                        CaseSensitivity::Smart,
                         usize::MAX,
                         &Default::default(),
                         cx.background_executor().clone(),
@@ -306,7 +306,7 @@ impl PickerDelegate for CommandPaletteDelegate {
                     fuzzy::match_strings(
                         &candidates,
                         &query,
-                        true,
+                        fuzzy::CaseSensitivity::Smart,
                         10000,
                         &Default::default(),
                         executor,
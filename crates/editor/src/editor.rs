@@ -1221,10 +1221,15 @@ impl CompletionsMenu {
 
     pub async fn filter(&mut self, query: Option<&str>, executor: BackgroundExecutor) {
         let mut matches = if let Some(query) = query {
+            let case_sensitivity = if query.chars().any(|c| c.is_uppercase()) {
+                fuzzy::CaseSensitivity::Smart
+            } else {
+                fuzzy::CaseSensitivity::Insensitive
+            };
             fuzzy::match_strings(
                 &self.match_candidates,
                 query,
-                query.chars().any(|c| c.is_uppercase()),
+                case_sensitivity,
                 100,
                 &Default::default(),
                 executor,
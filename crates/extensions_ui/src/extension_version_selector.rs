@@ -4,7 +4,7 @@ use std::sync::Arc;
 use client::ExtensionMetadata;
 use extension::{ExtensionSettings, ExtensionStore};
 use fs::Fs;
-use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatch, StringMatchCandidate};
 use gpui::{
     prelude::*, AppContext, DismissEvent, EventEmitter, FocusableView, Task, View, WeakView,
 };
@@ -139,7 +139,7 @@ impl PickerDelegate for ExtensionVersionSelectorDelegate {
                 match_strings(
                     &candidates,
                     &query,
-                    false,
+                    CaseSensitivity::Insensitive,
                     100,
                     &Default::default(),
                     background_executor,
@@ -12,7 +12,7 @@ use client::ExtensionMetadata;
 use collections::{BTreeMap, BTreeSet};
 use editor::{Editor, EditorElement, EditorStyle};
 use extension::{ExtensionManifest, ExtensionOperation, ExtensionStore};
-use fuzzy::{match_strings, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatchCandidate};
 use gpui::{
     actions, uniform_list, AppContext, EventEmitter, Flatten, FocusableView, InteractiveElement,
     KeyContext, ParentElement, Render, Styled, Task, TextStyle, UniformListScrollHandle, View,
@@ -339,7 +339,7 @@ impl ExtensionsPage {
                 let matches = match_strings(
                     &match_candidates,
                     &search,
-                    false,
+                    CaseSensitivity::Insensitive,
                     match_candidates.len(),
                     &Default::default(),
                     cx.background_executor().clone(),
@@ -1,29 +1,32 @@
 #[cfg(test)]
 mod file_finder_tests;
 
+mod file_finder_settings;
 mod new_path_prompt;
 mod open_path_prompt;
 
-use collections::HashMap;
+use collections::{HashMap, HashSet};
+use db::kvp::KEY_VALUE_STORE;
 use editor::{scroll::Autoscroll, Bias, Editor};
-use fuzzy::{CharBag, PathMatch, PathMatchCandidate};
+use file_finder_settings::FileFinderSettings;
+use futures::{future::Shared, FutureExt as _};
+use fuzzy::{CancellationToken, CharBag, PathMatch, PathMatchCandidate, PathMatcher};
 use gpui::{
-    actions, rems, Action, AnyElement, AppContext, DismissEvent, EventEmitter, FocusHandle,
-    FocusableView, Model, Modifiers, ModifiersChangedEvent, ParentElement, Render, Styled, Task,
-    View, ViewContext, VisualContext, WeakView,
+    actions, rems, Action, AnyElement, AppContext, DismissEvent, EntityId, EventEmitter,
+    FocusHandle, FocusableView, Global, Model, Modifiers, ModifiersChangedEvent, ParentElement,
+    Render, Styled, Task, View, ViewContext, VisualContext, WeakView,
 };
 use new_path_prompt::NewPathPrompt;
 use open_path_prompt::OpenPathPrompt;
 use picker::{Picker, PickerDelegate};
-use project::{PathMatchCandidateSet, Project, ProjectPath, WorktreeId};
-use settings::Settings;
+use project::{PathMatchCandidateSet, Project, ProjectPath, Worktree, WorktreeId};
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
 use std::{
     cmp,
     path::{Path, PathBuf},
-    sync::{
-        atomic::{self, AtomicBool},
-        Arc,
-    },
+    sync::Arc,
+    time::Duration,
 };
 use text::Point;
 use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
@@ -40,6 +43,7 @@ pub struct FileFinder {
 }
 
 pub fn init(cx: &mut AppContext) {
+    FileFinderSettings::register(cx);
     cx.observe_new_views(FileFinder::register).detach();
     cx.observe_new_views(NewPathPrompt::register).detach();
     cx.observe_new_views(OpenPathPrompt::register).detach();
@@ -150,6 +154,55 @@ impl Render for FileFinder {
     }
 }
 
+/// The paths picked from search results in each project, used to rank them higher
+/// when a similar query is typed again.
+#[derive(Default)]
+struct SelectionHistories(HashMap<EntityId, ProjectSelectionHistory>);
+
+impl Global for SelectionHistories {}
+
+#[derive(Default)]
+struct ProjectSelectionHistory {
+    history: fuzzy::SelectionHistory,
+    /// The worktrees whose selections from previous sessions were loaded, or are being
+    /// loaded.
+    loaded_worktrees: HashSet<WorktreeId>,
+    /// The latest write of the selections to the database. Each write waits for the
+    /// one before it, so that an older list of selections can't overwrite a newer one.
+    pending_write: Option<Shared<Task<()>>>,
+}
+
+/// A path picked from the search results of a worktree, as it's kept between sessions.
+#[derive(Serialize, Deserialize)]
+struct SerializedSelection {
+    query: String,
+    path: PathBuf,
+}
+
+/// The key the selections in a worktree are kept under, which is its absolute path
+/// since worktree ids only last for a session.
+fn selection_history_key(worktree_abs_path: &Path) -> String {
+    format!(
+        "file_finder_selection_history-{}",
+        worktree_abs_path.to_string_lossy()
+    )
+}
+
+/// How much a path picked for the same query is favored. Its score is multiplied by
+/// `1 + SELECTION_BONUS`, and every score is then divided by the largest factor the
+/// bonuses can give, so that scores stay between 0 and 1.
+const SELECTION_BONUS: f64 = 1.0;
+
+/// How many directories of recently opened files have their entries matched first in
+/// each worktree. Each of them takes a traversal of its own, so they're kept few.
+const MAX_PRIORITY_DIRS: usize = 8;
+
+/// How long a search can take before the matches found so far are shown. The entries
+/// of the directories of recently opened files are matched first, so that the likeliest
+/// matches are found by then. The search then goes on from where it was, and its
+/// matches replace the ones shown.
+const SEARCH_DEADLINE: Duration = Duration::from_millis(200);
+
 pub struct FileFinderDelegate {
     file_finder: WeakView<FileFinder>,
     workspace: WeakView<Workspace>,
@@ -162,10 +215,18 @@ pub struct FileFinderDelegate {
     matches: Matches,
     selected_index: usize,
     has_changed_selected_index: bool,
-    cancel_flag: Arc<AtomicBool>,
+    cancel_flag: CancellationToken,
+    /// Kept between searches so that its scratch buffers are reused. The search in
+    /// progress holds it, and later searches use a new one until it's given back.
+    path_matcher: Option<PathMatcher>,
+    /// How long a search runs before its first matches are shown, see [`SEARCH_DEADLINE`].
+    search_deadline: Duration,
     history_items: Vec<FoundPath>,
     separate_history: bool,
     first_update: bool,
+    /// The filter of each worktree, with the patterns of its auxiliary ignore files,
+    /// which are only read again once they change.
+    candidate_filters: HashMap<WorktreeId, Shared<Task<project::CandidateFilter>>>,
 }
 
 /// Use a custom ordering for file finder: the regular one
@@ -385,6 +446,7 @@ fn matching_history_items<'a>(
                         .to_lowercase()
                         .chars(),
                 ),
+                symlink_target: None,
             };
             candidates_paths.insert(Arc::clone(&found_path.project.path), found_path);
             Some((found_path.project.worktree_id, candidate))
@@ -400,15 +462,17 @@ fn matching_history_items<'a>(
             },
         );
     let mut matching_history_paths = HashMap::default();
+    let path_query = fuzzy::PreparedQuery::new(query.path_query());
     for (worktree, candidates) in history_items_by_worktrees {
         let max_results = candidates.len() + 1;
         matching_history_paths.extend(
             fuzzy::match_fixed_path_set(
                 candidates,
                 worktree.to_usize(),
-                query.path_query(),
-                false,
+                &path_query,
+                fuzzy::CaseSensitivity::Insensitive,
                 max_results,
+                &Default::default(),
             )
             .into_iter()
             .map(|path_match| {
@@ -484,6 +548,7 @@ impl FileFinderDelegate {
         cx: &mut ViewContext<FileFinder>,
     ) -> Self {
         Self::subscribe_to_updates(&project, cx);
+        Self::load_selection_history(&project, cx);
         Self {
             file_finder,
             workspace,
@@ -496,19 +561,47 @@ impl FileFinderDelegate {
             matches: Matches::default(),
             has_changed_selected_index: false,
             selected_index: 0,
-            cancel_flag: Arc::new(AtomicBool::new(false)),
+            cancel_flag: CancellationToken::new(),
+            path_matcher: Some(PathMatcher::new()),
+            search_deadline: SEARCH_DEADLINE,
             history_items,
             separate_history,
             first_update: true,
+            candidate_filters: HashMap::default(),
         }
     }
 
     fn subscribe_to_updates(project: &Model<Project>, cx: &mut ViewContext<FileFinder>) {
+        cx.observe_global::<SettingsStore>(|file_finder, cx| {
+            file_finder.picker.update(cx, |picker, cx| {
+                picker.delegate.candidate_filters.clear();
+                picker.refresh(cx)
+            })
+        })
+        .detach();
         cx.subscribe(project, |file_finder, _, event, cx| {
             match event {
-                project::Event::WorktreeUpdatedEntries(_, _)
-                | project::Event::WorktreeAdded
-                | project::Event::WorktreeRemoved(_) => file_finder
+                project::Event::WorktreeUpdatedEntries(worktree_id, updated_entries) => {
+                    file_finder.picker.update(cx, |picker, cx| {
+                        let auxiliary_ignore_changed =
+                            updated_entries.iter().any(|(path, _, _)| {
+                                project::AUXILIARY_IGNORE_FILES
+                                    .iter()
+                                    .any(|file_name| path.as_ref() == Path::new(file_name))
+                            });
+                        if auxiliary_ignore_changed {
+                            picker.delegate.candidate_filters.remove(worktree_id);
+                        }
+                        picker.refresh(cx)
+                    })
+                }
+                project::Event::WorktreeRemoved(worktree_id) => {
+                    file_finder.picker.update(cx, |picker, cx| {
+                        picker.delegate.candidate_filters.remove(worktree_id);
+                        picker.refresh(cx)
+                    })
+                }
+                project::Event::WorktreeAdded => file_finder
                     .picker
                     .update(cx, |picker, cx| picker.refresh(cx)),
                 _ => {}
@@ -517,6 +610,177 @@ impl FileFinderDelegate {
         .detach();
     }
 
+    /// Loads the paths picked in the project's worktrees in previous sessions, for the
+    /// worktrees that weren't loaded yet.
+    fn load_selection_history(project: &Model<Project>, cx: &mut ViewContext<FileFinder>) {
+        let project_id = project.entity_id();
+        let worktrees = project
+            .read(cx)
+            .visible_worktrees(cx)
+            .map(|worktree| {
+                let worktree = worktree.read(cx);
+                (worktree.id(), worktree.abs_path())
+            })
+            .collect::<Vec<_>>();
+        let loaded_worktrees = &mut cx
+            .default_global::<SelectionHistories>()
+            .0
+            .entry(project_id)
+            .or_default()
+            .loaded_worktrees;
+        let worktrees = worktrees
+            .into_iter()
+            .filter(|(worktree_id, _)| loaded_worktrees.insert(*worktree_id))
+            .collect::<Vec<_>>();
+        if worktrees.is_empty() {
+            return;
+        }
+
+        let load = cx.background_executor().spawn(async move {
+            worktrees
+                .into_iter()
+                .flat_map(|(worktree_id, abs_path)| {
+                    KEY_VALUE_STORE
+                        .read_kvp(&selection_history_key(&abs_path))
+                        .log_err()
+                        .flatten()
+                        .and_then(|selections| {
+                            serde_json::from_str::<Vec<SerializedSelection>>(&selections).log_err()
+                        })
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(move |selection| (worktree_id, selection))
+                })
+                .collect::<Vec<_>>()
+        });
+        cx.spawn(|_, mut cx| async move {
+            let loaded_selections = load.await;
+            cx.update(|cx| {
+                let project_history = cx
+                    .default_global::<SelectionHistories>()
+                    .0
+                    .entry(project_id)
+                    .or_default();
+                // Selections made while loading are more recent than the loaded ones.
+                let mut history = fuzzy::SelectionHistory::new();
+                for (worktree_id, selection) in &loaded_selections {
+                    history.record(
+                        &selection.query,
+                        worktree_id.to_usize(),
+                        selection.path.as_path().into(),
+                    );
+                }
+                for (query, worktree_id, path) in project_history.history.selections() {
+                    history.record(query, worktree_id, path.clone());
+                }
+                project_history.history = history;
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Records that a path was picked from the results of the query, and stores the
+    /// selections in its worktree for later sessions.
+    fn record_selection(&self, query: &str, path_match: &PathMatch, cx: &mut AppContext) {
+        let Some(worktree) = self
+            .project
+            .read(cx)
+            .worktree_for_id(WorktreeId::from_usize(path_match.worktree_id), cx)
+        else {
+            return;
+        };
+        let worktree_abs_path = worktree.read(cx).abs_path();
+        let executor = cx.background_executor().clone();
+        let project_history = cx
+            .default_global::<SelectionHistories>()
+            .0
+            .entry(self.project.entity_id())
+            .or_default();
+        project_history
+            .history
+            .record(query, path_match.worktree_id, path_match.path.clone());
+        let selections = project_history
+            .history
+            .selections()
+            .filter(|(_, worktree_id, _)| *worktree_id == path_match.worktree_id)
+            .map(|(query, _, path)| SerializedSelection {
+                query: query.to_string(),
+                path: path.to_path_buf(),
+            })
+            .collect::<Vec<_>>();
+        let previous_write = project_history.pending_write.take();
+        let write = executor.spawn(async move {
+            if let Some(previous_write) = previous_write {
+                previous_write.await;
+            }
+            let key = selection_history_key(&worktree_abs_path);
+            if let Some(selections) = serde_json::to_string(&selections).log_err() {
+                KEY_VALUE_STORE.write_kvp(key, selections).await.log_err();
+            }
+        });
+        project_history.pending_write = Some(write.shared());
+    }
+
+    /// The filter for the candidates of a worktree, reading its auxiliary ignore files
+    /// and settings the first time and after they change.
+    fn candidate_filter(
+        &mut self,
+        worktree: &Model<Worktree>,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) -> Shared<Task<project::CandidateFilter>> {
+        let worktree = worktree.read(cx);
+        let fs = self.project.read(cx).fs().clone();
+        let worktree_abs_path = worktree.abs_path();
+        let settings = FileFinderSettings::for_worktree(worktree, cx);
+        let exclude = settings.exclude.clone();
+        let exclude_hidden = settings.exclude_hidden;
+        self.candidate_filters
+            .entry(worktree.id())
+            .or_insert_with(|| {
+                cx.background_executor()
+                    .spawn(async move {
+                        let mut filter = project::CandidateFilter {
+                            exclude: if exclude.is_empty() {
+                                None
+                            } else {
+                                util::paths::PathMatcher::new(&exclude).log_err()
+                            },
+                            exclude_hidden,
+                            ..Default::default()
+                        };
+                        filter
+                            .load_auxiliary_ignore(&worktree_abs_path, fs.as_ref())
+                            .await;
+                        filter
+                    })
+                    .shared()
+            })
+            .clone()
+    }
+
+    /// The directories of the recently opened files in the worktree, most recent first,
+    /// up to [`MAX_PRIORITY_DIRS`].
+    fn recent_directories(&self, worktree_id: WorktreeId) -> Vec<Arc<Path>> {
+        let mut directories = Vec::<Arc<Path>>::new();
+        for history_item in &self.history_items {
+            if directories.len() == MAX_PRIORITY_DIRS {
+                break;
+            }
+            if history_item.project.worktree_id != worktree_id {
+                continue;
+            }
+            let Some(directory) = history_item.project.path.parent() else {
+                continue;
+            };
+            if !directory.as_os_str().is_empty()
+                && !directories.iter().any(|known| known.as_ref() == directory)
+            {
+                directories.push(directory.into());
+            }
+        }
+        directories
+    }
+
     fn spawn_search(
         &mut self,
         query: FileSearchQuery,
@@ -532,7 +796,16 @@ impl FileFinderDelegate {
             .visible_worktrees(cx)
             .collect::<Vec<_>>();
         let include_root_name = worktrees.len() > 1;
-        let candidate_sets = worktrees
+        let mut abbreviations = HashMap::default();
+        for worktree in &worktrees {
+            let settings = FileFinderSettings::for_worktree(worktree.read(cx), cx);
+            abbreviations.extend(settings.abbreviations.clone());
+        }
+        let candidate_filters = worktrees
+            .iter()
+            .map(|worktree| self.candidate_filter(worktree, cx))
+            .collect::<Vec<_>>();
+        let mut candidate_sets = worktrees
             .into_iter()
             .map(|worktree| {
                 let worktree = worktree.read(cx);
@@ -542,29 +815,65 @@ impl FileFinderDelegate {
                         .root_entry()
                         .map_or(false, |entry| entry.is_ignored),
                     include_root_name,
+                    root_alias: self.project.read(cx).worktree_alias(worktree.id()),
+                    priority_dirs: self.recent_directories(worktree.id()),
                     candidates: project::Candidates::Files,
+                    filter: project::CandidateFilter::default(),
+                    resolve_symlinks: true,
                 }
             })
             .collect::<Vec<_>>();
 
         let search_id = util::post_inc(&mut self.search_count);
-        self.cancel_flag.store(true, atomic::Ordering::Relaxed);
-        self.cancel_flag = Arc::new(AtomicBool::new(false));
+        self.cancel_flag.cancel();
+        self.cancel_flag = CancellationToken::new();
         let cancel_flag = self.cancel_flag.clone();
+        let selection_history = cx
+            .try_global::<SelectionHistories>()
+            .and_then(|histories| histories.0.get(&self.project.entity_id()))
+            .map(|project_history| project_history.history.clone());
+        let mut path_matcher = self.path_matcher.take().unwrap_or_default();
+        let search_deadline = self.search_deadline;
         cx.spawn(|picker, mut cx| async move {
-            let matches = fuzzy::match_path_sets(
-                candidate_sets.as_slice(),
-                query.path_query(),
-                relative_to,
-                false,
-                100,
-                &cancel_flag,
-                cx.background_executor().clone(),
-            )
-            .await
-            .into_iter()
-            .map(ProjectPanelOrdMatch);
-            let did_cancel = cancel_flag.load(atomic::Ordering::Relaxed);
+            for (candidate_set, filter) in candidate_sets.iter_mut().zip(candidate_filters) {
+                candidate_set.filter = filter.await;
+            }
+            let executor = cx.background_executor().clone();
+            let matches = path_matcher
+                .match_path_sets_with_partial_results(
+                    candidate_sets.as_slice(),
+                    &fuzzy::PreparedQuery::with_abbreviations(query.path_query(), &abbreviations),
+                    relative_to,
+                    fuzzy::CaseSensitivity::Insensitive,
+                    100,
+                    &fuzzy::PathMatchOptions {
+                        selection_history: selection_history.as_ref(),
+                        selection_bonus: SELECTION_BONUS,
+                        deadline: Some(search_deadline),
+                        match_symlink_targets: true,
+                        ..Default::default()
+                    },
+                    &cancel_flag,
+                    executor,
+                    |partial_matches| {
+                        let partial_matches = partial_matches.into_iter().map(ProjectPanelOrdMatch);
+                        picker
+                            .update(&mut cx, |picker, cx| {
+                                picker.delegate.set_search_matches(
+                                    search_id,
+                                    false,
+                                    query.clone(),
+                                    partial_matches,
+                                    cx,
+                                )
+                            })
+                            .log_err();
+                    },
+                )
+                .await
+                .into_iter()
+                .map(ProjectPanelOrdMatch);
+            let did_cancel = cancel_flag.is_cancelled();
             picker
                 .update(&mut cx, |picker, cx| {
                     picker
@@ -572,6 +881,11 @@ impl FileFinderDelegate {
                         .set_search_matches(search_id, did_cancel, query, matches, cx)
                 })
                 .log_err();
+            picker
+                .update(&mut cx, |picker, _| {
+                    picker.delegate.path_matcher = Some(path_matcher);
+                })
+                .log_err();
         })
     }
 
@@ -673,6 +987,8 @@ impl FileFinderDelegate {
                     is_dir: false, // File finder doesn't support directories
                     path_prefix: "".into(),
                     distance_to_relative_ancestor: usize::MAX,
+                    git_status: None,
+                    symlink_target: None,
                 };
                 if let Some(found_path_match) = &panel_match {
                     path_match
@@ -723,6 +1039,20 @@ impl FileFinderDelegate {
             |file_name| file_name.to_string_lossy().to_string(),
         );
         let file_name_start = path_match.path_prefix.len() + path_string.len() - file_name.len();
+        if let Some(target) = &path_match.symlink_target {
+            // The query matched the path the symlink resolves to, so only the positions
+            // in a file name that both paths share can be highlighted.
+            path_positions = if target.file_name() == path.file_name() {
+                let target_file_name_start = target.to_string_lossy().len() - file_name.len();
+                path_positions
+                    .iter()
+                    .filter(|&&pos| pos >= target_file_name_start)
+                    .map(|pos| pos - target_file_name_start + file_name_start)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+        }
         let file_name_positions = path_positions
             .iter()
             .filter_map(|pos| {
@@ -773,6 +1103,8 @@ impl FileFinderDelegate {
                                     path_prefix: "".into(),
                                     is_dir: false, // File finder doesn't support directories
                                     distance_to_relative_ancestor: usize::MAX,
+                                    git_status: None,
+                                    symlink_target: None,
                                 }));
                             }
                         })
@@ -913,6 +1245,9 @@ impl PickerDelegate for FileFinderDelegate {
 
     fn confirm(&mut self, secondary: bool, cx: &mut ViewContext<Picker<FileFinderDelegate>>) {
         if let Some(m) = self.matches.get(self.selected_index()) {
+            if let (Match::Search(m), Some(query)) = (m, &self.latest_search_query) {
+                self.record_selection(query.path_query(), &m.0, cx);
+            }
             if let Some(workspace) = self.workspace.upgrade() {
                 let open_task = workspace.update(cx, move |workspace, cx| {
                     let split_or_open =
@@ -1095,6 +1430,8 @@ mod tests {
                 path_prefix: Arc::default(),
                 distance_to_relative_ancestor: 0,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             }),
             ProjectPanelOrdMatch(PathMatch {
                 score: 1.0,
@@ -1104,6 +1441,8 @@ mod tests {
                 path_prefix: Arc::default(),
                 distance_to_relative_ancestor: 0,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             }),
             ProjectPanelOrdMatch(PathMatch {
                 score: 1.0,
@@ -1113,6 +1452,8 @@ mod tests {
                 path_prefix: Arc::default(),
                 distance_to_relative_ancestor: 0,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             }),
             ProjectPanelOrdMatch(PathMatch {
                 score: 0.5,
@@ -1122,6 +1463,8 @@ mod tests {
                 path_prefix: Arc::default(),
                 distance_to_relative_ancestor: 0,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             }),
             ProjectPanelOrdMatch(PathMatch {
                 score: 1.0,
@@ -1131,6 +1474,8 @@ mod tests {
                 path_prefix: Arc::default(),
                 distance_to_relative_ancestor: 0,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             }),
         ];
         file_finder_sorted_output.sort_by(|a, b| b.cmp(a));
@@ -1146,6 +1491,8 @@ mod tests {
                     path_prefix: Arc::default(),
                     distance_to_relative_ancestor: 0,
                     is_dir: false,
+                    git_status: None,
+                    symlink_target: None,
                 }),
                 ProjectPanelOrdMatch(PathMatch {
                     score: 1.0,
@@ -1155,6 +1502,8 @@ mod tests {
                     path_prefix: Arc::default(),
                     distance_to_relative_ancestor: 0,
                     is_dir: false,
+                    git_status: None,
+                    symlink_target: None,
                 }),
                 ProjectPanelOrdMatch(PathMatch {
                     score: 1.0,
@@ -1164,6 +1513,8 @@ mod tests {
                     path_prefix: Arc::default(),
                     distance_to_relative_ancestor: 0,
                     is_dir: false,
+                    git_status: None,
+                    symlink_target: None,
                 }),
                 ProjectPanelOrdMatch(PathMatch {
                     score: 0.5,
@@ -1173,6 +1524,8 @@ mod tests {
                     path_prefix: Arc::default(),
                     distance_to_relative_ancestor: 0,
                     is_dir: false,
+                    git_status: None,
+                    symlink_target: None,
                 }),
                 ProjectPanelOrdMatch(PathMatch {
                     score: 0.5,
@@ -1182,6 +1535,8 @@ mod tests {
                     path_prefix: Arc::default(),
                     distance_to_relative_ancestor: 0,
                     is_dir: false,
+                    git_status: None,
+                    symlink_target: None,
                 }),
             ]
         );
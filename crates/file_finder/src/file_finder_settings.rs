@@ -0,0 +1,56 @@
+use anyhow::Result;
+use collections::HashMap;
+use gpui::AppContext;
+use project::Worktree;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsLocation, SettingsSources};
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+pub struct FileFinderSettings {
+    pub exclude: Vec<String>,
+    pub exclude_hidden: bool,
+    pub abbreviations: HashMap<String, String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct FileFinderSettingsContent {
+    /// Globs matching the files and directories to leave out of the file finder's
+    /// results, relative to the root of their worktree.
+    ///
+    /// Default: []
+    pub exclude: Option<Vec<String>>,
+    /// Whether to leave out dotfiles and the entries of dot-directories.
+    ///
+    /// Default: false
+    pub exclude_hidden: Option<bool>,
+    /// Abbreviations that queries are also matched with expanded, such as
+    /// `{"cfg": "config"}`. The best scoring form of the query is kept.
+    ///
+    /// Default: {}
+    pub abbreviations: Option<HashMap<String, String>>,
+}
+
+impl FileFinderSettings {
+    /// The settings in effect for a worktree, including the ones in its own settings file.
+    pub fn for_worktree<'a>(worktree: &Worktree, cx: &'a AppContext) -> &'a Self {
+        Self::get(
+            Some(SettingsLocation {
+                worktree_id: worktree.id(),
+                path: Path::new(""),
+            }),
+            cx,
+        )
+    }
+}
+
+impl Settings for FileFinderSettings {
+    const KEY: Option<&'static str> = Some("file_finder");
+
+    type FileContent = FileFinderSettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        sources.json_merge()
+    }
+}
@@ -39,6 +39,8 @@ async fn test_matching_paths(cx: &mut TestAppContext) {
     cx.simulate_input("bna");
     picker.update(cx, |picker, _| {
         assert_eq!(picker.delegate.matches.len(), 2);
+        // The search gives its matcher back for the next one to reuse.
+        assert!(picker.delegate.path_matcher.is_some());
     });
     cx.dispatch_action(SelectNext);
     cx.dispatch_action(Confirm);
@@ -83,6 +85,52 @@ async fn test_matching_paths(cx: &mut TestAppContext) {
     }
 }
 
+#[gpui::test]
+async fn test_search_resumes_after_deadline(cx: &mut TestAppContext) {
+    let app_state = init_test(cx);
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/root",
+            json!({
+                "a": {
+                    "banana": "",
+                    "bandana": "",
+                },
+                "b": {
+                    "banner": "",
+                    "cabana": "",
+                    "apple": "",
+                }
+            }),
+        )
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/root".as_ref()], cx).await;
+
+    let (picker, _, cx) = build_find_picker(project, cx);
+    picker.update(cx, |picker, _| {
+        picker.delegate.search_deadline = Duration::ZERO;
+    });
+
+    picker
+        .update(cx, |picker, cx| {
+            picker.delegate.update_matches("ban".to_string(), cx)
+        })
+        .await;
+    picker.update(cx, |picker, _| {
+        let delegate = &picker.delegate;
+        assert_eq!(
+            delegate.matches.len(),
+            4,
+            "the search stopped at the deadline should go on to find every match"
+        );
+        assert!(!delegate.latest_search_did_cancel);
+        assert!(delegate.path_matcher.is_some());
+    });
+}
+
 #[gpui::test]
 async fn test_absolute_paths(cx: &mut TestAppContext) {
     let app_state = init_test(cx);
@@ -183,6 +231,58 @@ async fn test_complex_path(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_matching_symlink_targets(cx: &mut TestAppContext) {
+    let app_state = init_test(cx);
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/root",
+            json!({
+                "app": {
+                    "vendor": {},
+                },
+                "libs": {
+                    "design-system": {
+                        "button.tsx": "",
+                    },
+                },
+            }),
+        )
+        .await;
+    app_state
+        .fs
+        .as_fake()
+        .insert_symlink("/root/app/vendor/ui", "../../libs/design-system".into())
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/root".as_ref()], cx).await;
+
+    let (picker, _, cx) = build_find_picker(project, cx);
+
+    cx.simulate_input("design-system/button");
+    picker.update(cx, |picker, _| {
+        let search_matches = collect_search_matches(picker).search_matches;
+        let symlink_match = search_matches
+            .iter()
+            .find(|path_match| path_match.path.as_ref() == Path::new("app/vendor/ui/button.tsx"))
+            .expect("the file should be found through the symlinked directory");
+        assert_eq!(
+            symlink_match.symlink_target.as_deref(),
+            Some(Path::new("libs/design-system/button.tsx"))
+        );
+
+        // Only the file name is shared with the path that was matched.
+        let (file_name, file_name_positions, full_path, full_path_positions) =
+            picker.delegate.labels_for_path_match(symlink_match);
+        assert_eq!(file_name, "button.tsx");
+        assert_eq!(file_name_positions, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(full_path, "app/vendor/ui/");
+        assert!(full_path_positions.is_empty());
+    });
+}
+
 #[gpui::test]
 async fn test_row_column_numbers_query_inside_file(cx: &mut TestAppContext) {
     let app_state = init_test(cx);
@@ -732,6 +832,70 @@ async fn test_query_history(cx: &mut gpui::TestAppContext) {
             );
 }
 
+#[gpui::test]
+async fn test_selection_history_persists(cx: &mut gpui::TestAppContext) {
+    let app_state = init_test(cx);
+
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/selection_history",
+            json!({
+                "src": {
+                    "main.rs": "// Main file",
+                    "mod.rs": "// Mod file",
+                }
+            }),
+        )
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/selection_history".as_ref()], cx).await;
+    let (picker, workspace, cx) = build_find_picker(project.clone(), cx);
+    let worktree_id = cx.read(|cx| {
+        let worktrees = workspace.read(cx).worktrees(cx).collect::<Vec<_>>();
+        worktrees[0].read(cx).id()
+    });
+
+    cx.simulate_input("mod");
+    picker.update(cx, |picker, _| {
+        assert_match_at_position(picker, 0, "mod.rs");
+    });
+    cx.dispatch_action(Confirm);
+    // The selections are written on the database's own thread.
+    cx.executor().allow_parking();
+    let pending_write = cx.read(|cx| {
+        cx.global::<SelectionHistories>().0[&project.entity_id()]
+            .pending_write
+            .clone()
+            .expect("confirming a match should store the selections")
+    });
+    pending_write.await;
+    let key = selection_history_key(Path::new("/selection_history"));
+    assert!(KEY_VALUE_STORE.read_kvp(&key).unwrap().is_some());
+
+    // Forget the selections of this session, like when restarting, so that they're
+    // loaded from the database when the file finder is opened again.
+    cx.update(|cx| cx.remove_global::<SelectionHistories>());
+    open_file_picker(&workspace, cx);
+    cx.run_until_parked();
+    cx.read(|cx| {
+        let selections = cx.global::<SelectionHistories>().0[&project.entity_id()]
+            .history
+            .selections()
+            .map(|(query, worktree_id, path)| (query.to_string(), worktree_id, path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            selections,
+            [(
+                "mod".to_string(),
+                worktree_id.to_usize(),
+                Arc::from(Path::new("src/mod.rs"))
+            )]
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_external_files_history(cx: &mut gpui::TestAppContext) {
     let app_state = init_test(cx);
@@ -1525,6 +1689,145 @@ async fn test_search_results_refreshed_on_worktree_updates(cx: &mut gpui::TestAp
     });
 }
 
+#[gpui::test]
+async fn test_search_results_refreshed_on_auxiliary_ignore_updates(cx: &mut gpui::TestAppContext) {
+    let app_state = init_test(cx);
+
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/src",
+            json!({
+                ".ignore": "main.rs\n",
+                "lib.rs": "// Lib file",
+                "main.rs": "// Bar file",
+            }),
+        )
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/src".as_ref()], cx).await;
+    let (workspace, cx) = cx.add_window_view(|cx| Workspace::test_new(project.clone(), cx));
+
+    let picker = open_file_picker(&workspace, cx);
+    cx.simulate_input("rs");
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 1);
+        assert_match_at_position(finder, 0, "lib.rs");
+    });
+
+    // The ignore file is read again once it changes.
+    app_state
+        .fs
+        .as_fake()
+        .insert_file("/src/.ignore", b"lib.rs\n".to_vec())
+        .await;
+    cx.executor().advance_clock(FS_WATCH_LATENCY);
+
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 1);
+        assert_match_at_position(finder, 0, "main.rs");
+    });
+}
+
+#[gpui::test]
+async fn test_search_results_refreshed_on_settings_changes(cx: &mut gpui::TestAppContext) {
+    let app_state = init_test(cx);
+
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/src",
+            json!({
+                ".config": {
+                    "tool.rs": "",
+                },
+                "generated": {
+                    "schema.rs": "",
+                },
+                "lib.rs": "",
+            }),
+        )
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/src".as_ref()], cx).await;
+    let (workspace, cx) = cx.add_window_view(|cx| Workspace::test_new(project.clone(), cx));
+
+    let picker = open_file_picker(&workspace, cx);
+    cx.simulate_input("rs");
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 3);
+    });
+
+    cx.update(|cx| {
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<FileFinderSettings>(cx, |settings| {
+                settings.exclude = Some(vec!["generated/**".to_string()]);
+                settings.exclude_hidden = Some(true);
+            });
+        });
+    });
+    cx.run_until_parked();
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 1);
+        assert_match_at_position(finder, 0, "lib.rs");
+    });
+
+    cx.update(|cx| {
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<FileFinderSettings>(cx, |settings| {
+                settings.exclude = None;
+            });
+        });
+    });
+    cx.run_until_parked();
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 2);
+    });
+}
+
+#[gpui::test]
+async fn test_query_abbreviations_from_settings(cx: &mut gpui::TestAppContext) {
+    let app_state = init_test(cx);
+
+    app_state
+        .fs
+        .as_fake()
+        .insert_tree(
+            "/src",
+            json!({
+                "kubernetes.yaml": "",
+                "main.rs": "",
+            }),
+        )
+        .await;
+
+    let project = Project::test(app_state.fs.clone(), ["/src".as_ref()], cx).await;
+    let (picker, _, cx) = build_find_picker(project, cx);
+
+    cx.simulate_input("k8s");
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 0);
+    });
+
+    cx.update(|cx| {
+        cx.update_global::<SettingsStore, _>(|store, cx| {
+            store.update_user_settings::<FileFinderSettings>(cx, |settings| {
+                settings.abbreviations = Some(HashMap::from_iter([(
+                    "k8s".to_string(),
+                    "kubernetes".to_string(),
+                )]));
+            });
+        });
+    });
+    cx.run_until_parked();
+    picker.update(cx, |finder, _| {
+        assert_eq!(finder.delegate.matches.len(), 1);
+        assert_match_at_position(finder, 0, "kubernetes.yaml");
+    });
+}
+
 #[gpui::test]
 async fn test_search_results_refreshed_on_adding_and_removing_worktrees(
     cx: &mut gpui::TestAppContext,
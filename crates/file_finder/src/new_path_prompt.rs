@@ -1,15 +1,9 @@
 use futures::channel::oneshot;
-use fuzzy::PathMatch;
+use fuzzy::{CancellationToken, PathMatch};
 use gpui::{HighlightStyle, Model, StyledText};
 use picker::{Picker, PickerDelegate};
 use project::{Entry, PathMatchCandidateSet, Project, ProjectPath, WorktreeId};
-use std::{
-    path::PathBuf,
-    sync::{
-        atomic::{self, AtomicBool},
-        Arc,
-    },
-};
+use std::{path::PathBuf, sync::Arc};
 use ui::{highlight_ranges, prelude::*, LabelLike, ListItemSpacing};
 use ui::{ListItem, ViewContext};
 use util::ResultExt;
@@ -194,7 +188,7 @@ pub struct NewPathDelegate {
     selected_index: usize,
     matches: Vec<Match>,
     last_selected_dir: Option<String>,
-    cancel_flag: Arc<AtomicBool>,
+    cancel_flag: CancellationToken,
     should_dismiss: bool,
 }
 
@@ -219,7 +213,7 @@ impl NewPathPrompt {
                 tx: Some(tx),
                 selected_index: 0,
                 matches: vec![],
-                cancel_flag: Arc::new(AtomicBool::new(false)),
+                cancel_flag: CancellationToken::new(),
                 last_selected_dir: None,
                 should_dismiss: true,
             };
@@ -281,13 +275,17 @@ impl PickerDelegate for NewPathDelegate {
                         .root_entry()
                         .map_or(false, |entry| entry.is_ignored),
                     include_root_name,
+                    root_alias: None,
+                    priority_dirs: Vec::new(),
                     candidates: project::Candidates::Directories,
+                    filter: project::CandidateFilter::default(),
+                    resolve_symlinks: false,
                 }
             })
             .collect::<Vec<_>>();
 
-        self.cancel_flag.store(true, atomic::Ordering::Relaxed);
-        self.cancel_flag = Arc::new(AtomicBool::new(false));
+        self.cancel_flag.cancel();
+        self.cancel_flag = CancellationToken::new();
 
         let cancel_flag = self.cancel_flag.clone();
         let query = query.to_string();
@@ -295,15 +293,16 @@ impl PickerDelegate for NewPathDelegate {
         cx.spawn(|picker, mut cx| async move {
             let matches = fuzzy::match_path_sets(
                 candidate_sets.as_slice(),
-                &dir,
+                &fuzzy::PreparedQuery::new(&dir),
                 None,
-                false,
+                fuzzy::CaseSensitivity::Insensitive,
                 100,
+                &Default::default(),
                 &cancel_flag,
                 cx.background_executor().clone(),
             )
             .await;
-            let did_cancel = cancel_flag.load(atomic::Ordering::Relaxed);
+            let did_cancel = cancel_flag.is_cancelled();
             if did_cancel {
                 return;
             }
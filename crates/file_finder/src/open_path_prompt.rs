@@ -1,13 +1,10 @@
 use futures::channel::oneshot;
-use fuzzy::StringMatchCandidate;
+use fuzzy::{CancellationToken, StringMatchCandidate};
 use picker::{Picker, PickerDelegate};
 use project::DirectoryLister;
 use std::{
     path::{Path, PathBuf},
-    sync::{
-        atomic::{self, AtomicBool},
-        Arc,
-    },
+    sync::Arc,
 };
 use ui::{prelude::*, LabelLike, ListItemSpacing};
 use ui::{ListItem, ViewContext};
@@ -22,7 +19,7 @@ pub struct OpenPathDelegate {
     selected_index: usize,
     directory_state: Option<DirectoryState>,
     matches: Vec<usize>,
-    cancel_flag: Arc<AtomicBool>,
+    cancel_flag: CancellationToken,
     should_dismiss: bool,
 }
 
@@ -54,7 +51,7 @@ impl OpenPathPrompt {
                 selected_index: 0,
                 directory_state: None,
                 matches: Vec::new(),
-                cancel_flag: Arc::new(AtomicBool::new(false)),
+                cancel_flag: CancellationToken::new(),
                 should_dismiss: true,
             };
 
@@ -106,14 +103,14 @@ impl PickerDelegate for OpenPathDelegate {
         } else {
             Some(lister.list_directory(dir.clone(), cx))
         };
-        self.cancel_flag.store(true, atomic::Ordering::Relaxed);
-        self.cancel_flag = Arc::new(AtomicBool::new(false));
+        self.cancel_flag.cancel();
+        self.cancel_flag = CancellationToken::new();
         let cancel_flag = self.cancel_flag.clone();
 
         cx.spawn(|this, mut cx| async move {
             if let Some(query) = query {
                 let paths = query.await;
-                if cancel_flag.load(atomic::Ordering::Relaxed) {
+                if cancel_flag.is_cancelled() {
                     return;
                 }
 
@@ -183,13 +180,13 @@ impl PickerDelegate for OpenPathDelegate {
             let matches = fuzzy::match_strings(
                 match_candidates.as_slice(),
                 &suffix,
-                false,
+                fuzzy::CaseSensitivity::Insensitive,
                 100,
                 &cancel_flag,
                 cx.background_executor().clone(),
             )
             .await;
-            if cancel_flag.load(atomic::Ordering::Relaxed) {
+            if cancel_flag.is_cancelled() {
                 return;
             }
 
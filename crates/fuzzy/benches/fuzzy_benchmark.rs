@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use fuzzy::{
+    match_fixed_path_set, CaseSensitivity, CharBag, PathMatchCandidate, PathMatchOptions,
+    PathMatcher, PreparedQuery, ScoringStrategy,
+};
+
+fn generate_paths(count: usize, depth: usize) -> Vec<PathBuf> {
+    (0..count)
+        .map(|i| {
+            let mut path = PathBuf::new();
+            for level in 0..depth {
+                path.push(format!("directory_{level}_of_item_{i}"));
+            }
+            path.push(format!("source_file_{i}.rs"));
+            path
+        })
+        .collect()
+}
+
+fn scoring_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_fixed_path_set");
+    for depth in [4, 32, 128] {
+        let paths = generate_paths(100, depth);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for scoring_strategy in [ScoringStrategy::Recursive, ScoringStrategy::Iterative] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{scoring_strategy:?}"), depth),
+                &depth,
+                |b, _| {
+                    let options = PathMatchOptions {
+                        scoring_strategy,
+                        ..Default::default()
+                    };
+                    let query = PreparedQuery::new("dirsrcfile");
+                    b.iter(|| {
+                        match_fixed_path_set(
+                            candidates(),
+                            0,
+                            black_box(&query),
+                            CaseSensitivity::Insensitive,
+                            10,
+                            &options,
+                        )
+                    });
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn reuse_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("repeated_queries");
+    let paths = generate_paths(1000, 8);
+    let candidates = paths
+        .iter()
+        .map(|path| PathMatchCandidate {
+            is_dir: false,
+            path,
+            char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+            symlink_target: None,
+        })
+        .collect::<Vec<_>>();
+    // Simulates typing a query one keystroke at a time.
+    let queries = ["d", "di", "dir", "dir7", "dir7s", "dir7sr", "dir7src"].map(PreparedQuery::new);
+    let options = PathMatchOptions::default();
+
+    group.bench_function("fresh_buffers", |b| {
+        b.iter(|| {
+            for query in &queries {
+                match_fixed_path_set(
+                    candidates.clone(),
+                    0,
+                    black_box(query),
+                    CaseSensitivity::Insensitive,
+                    100,
+                    &options,
+                );
+            }
+        });
+    });
+    group.bench_function("reused_buffers", |b| {
+        let mut matcher = PathMatcher::new();
+        b.iter(|| {
+            for query in &queries {
+                matcher.match_fixed_path_set(
+                    candidates.clone(),
+                    0,
+                    black_box(query),
+                    CaseSensitivity::Insensitive,
+                    100,
+                    &options,
+                );
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scoring_benchmarks, reuse_benchmarks);
+criterion_main!(benches);
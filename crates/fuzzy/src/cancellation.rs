@@ -0,0 +1,134 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Signals a running match to stop early, returning the results found so far.
+///
+/// Clones share the same state. Cancelling a token also cancels every token
+/// derived from it with [`CancellationToken::child`], and a token with a deadline
+/// reports itself as cancelled once the deadline has passed.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    state: Arc<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    parent: Option<Arc<State>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token that cancels itself at the given instant.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            state: Arc::new(State {
+                deadline: Some(deadline),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Creates a token that cancels itself after the given duration.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Creates a token that is cancelled along with this one, but can also be
+    /// cancelled on its own without affecting this token.
+    pub fn child(&self) -> Self {
+        self.child_with_state(None)
+    }
+
+    /// Like [`Self::child`], but the child also cancels itself after the given duration.
+    pub fn child_with_timeout(&self, timeout: Duration) -> Self {
+        self.child_with_state(Some(Instant::now() + timeout))
+    }
+
+    fn child_with_state(&self, deadline: Option<Instant>) -> Self {
+        Self {
+            state: Arc::new(State {
+                cancelled: Default::default(),
+                deadline,
+                parent: Some(self.state.clone()),
+            }),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let mut state = &self.state;
+        loop {
+            if state.cancelled.load(Ordering::Relaxed) {
+                return true;
+            }
+            if state
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+            {
+                state.cancelled.store(true, Ordering::Relaxed);
+                return true;
+            }
+            match &state.parent {
+                Some(parent) => state = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Wraps an existing cancellation flag, which is cancelled whenever the token is.
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            state: Arc::new(State {
+                cancelled,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let grandchild = child.child();
+        assert!(!grandchild.is_cancelled());
+
+        child.cancel();
+        assert!(grandchild.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let child = parent.child();
+        parent.cancel();
+        assert!(child.is_cancelled());
+
+        let token = CancellationToken::with_timeout(Duration::ZERO);
+        assert!(token.is_cancelled());
+        let child = CancellationToken::new().child_with_timeout(Duration::from_secs(60));
+        assert!(!child.is_cancelled());
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let token = CancellationToken::from(flag.clone());
+        assert!(!token.is_cancelled());
+        flag.store(true, Ordering::Relaxed);
+        assert!(token.is_cancelled());
+    }
+}
@@ -1,10 +1,19 @@
+mod cancellation;
 mod char_bag;
+mod history;
 mod matcher;
 mod paths;
+#[cfg(test)]
+mod scoring_tests;
 mod strings;
 
+pub use cancellation::CancellationToken;
 pub use char_bag::CharBag;
+pub use history::SelectionHistory;
+pub use matcher::{CaseSensitivity, ScoringStrategy};
 pub use paths::{
-    match_fixed_path_set, match_path_sets, PathMatch, PathMatchCandidate, PathMatchCandidateSet,
+    match_fixed_path_set, match_path_sets, match_path_sets_with_contents,
+    match_recent_and_path_sets, ContentQuery, PathMatch, PathMatchCandidate, PathMatchCandidateSet,
+    PathMatchOptions, PathMatchSource, PathMatcher, PreparedQuery, RankedPathMatch, RecentPath,
 };
 pub use strings::{match_strings, StringMatch, StringMatchCandidate};
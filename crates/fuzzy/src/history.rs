@@ -0,0 +1,118 @@
+use collections::HashMap;
+use std::{collections::VecDeque, path::Path, sync::Arc};
+
+const MAX_SELECTIONS: usize = 128;
+
+/// Remembers which paths were picked for which queries, so that they can be ranked
+/// higher when the same query, or the beginning of it, is typed again.
+#[derive(Clone, Debug, Default)]
+pub struct SelectionHistory {
+    /// Ordered from the least to the most recent selection.
+    selections: VecDeque<Selection>,
+}
+
+#[derive(Clone, Debug)]
+struct Selection {
+    query: String,
+    worktree_id: usize,
+    path: Arc<Path>,
+}
+
+impl SelectionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the path was picked from the results of the query.
+    pub fn record(&mut self, query: &str, worktree_id: usize, path: Arc<Path>) {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+
+        self.selections.retain(|selection| {
+            selection.query != query
+                || selection.worktree_id != worktree_id
+                || selection.path != path
+        });
+        if self.selections.len() == MAX_SELECTIONS {
+            self.selections.pop_front();
+        }
+        self.selections.push_back(Selection {
+            query,
+            worktree_id,
+            path,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    /// The recorded queries with the worktree id and path picked for them, from the
+    /// least to the most recent, like they were passed to [`Self::record`].
+    pub fn selections(&self) -> impl Iterator<Item = (&str, usize, &Arc<Path>)> {
+        self.selections.iter().map(|selection| {
+            (
+                selection.query.as_str(),
+                selection.worktree_id,
+                &selection.path,
+            )
+        })
+    }
+
+    /// Returns how strongly each previously picked path is associated with the query,
+    /// between 0 and 1. Paths picked for exactly this query get the full weight, and
+    /// paths picked for a query that this one is the beginning of get half of it.
+    pub(crate) fn weights(&self, query: &str) -> HashMap<(usize, &Path), f64> {
+        let query = query.trim().to_lowercase();
+        let mut weights = HashMap::default();
+        if query.is_empty() {
+            return weights;
+        }
+
+        for selection in &self.selections {
+            let weight = if selection.query == query {
+                1.0
+            } else if selection.query.starts_with(&query) {
+                0.5
+            } else {
+                continue;
+            };
+            let entry = weights
+                .entry((selection.worktree_id, selection.path.as_ref()))
+                .or_insert(0.0);
+            *entry = f64::max(*entry, weight);
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_weights() {
+        let path = |path: &str| Arc::from(Path::new(path));
+        let mut history = SelectionHistory::new();
+        history.record("main", 0, path("src/main.rs"));
+        history.record("Main", 0, path("src/main.rs"));
+        history.record("mainwin", 1, path("src/main_window.rs"));
+        assert_eq!(history.selections.len(), 2);
+
+        let weights = history.weights("main");
+        assert_eq!(weights.get(&(0, Path::new("src/main.rs"))), Some(&1.0));
+        assert_eq!(
+            weights.get(&(1, Path::new("src/main_window.rs"))),
+            Some(&0.5)
+        );
+        assert!(history.weights("window").is_empty());
+
+        for i in 0..MAX_SELECTIONS {
+            history.record(&format!("query{i}"), 0, path("src/lib.rs"));
+        }
+        assert_eq!(history.selections.len(), MAX_SELECTIONS);
+        assert!(history.weights("main").is_empty());
+    }
+}
@@ -1,124 +1,525 @@
-use std::{
-    borrow::Cow,
-    sync::atomic::{self, AtomicBool},
-};
+use collections::HashMap;
+use std::{borrow::Cow, cmp::Reverse, mem};
 
-use crate::CharBag;
+use crate::{CancellationToken, CharBag};
 
 const BASE_DISTANCE_PENALTY: f64 = 0.6;
 const ADDITIONAL_DISTANCE_PENALTY: f64 = 0.05;
 const MIN_DISTANCE_PENALTY: f64 = 0.2;
 
+/// How the case of query characters is compared against the candidates.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CaseSensitivity {
+    /// Case is ignored.
+    #[default]
+    Insensitive,
+    /// Characters match regardless of case, but matches whose case differs from the
+    /// query are heavily penalized, ranking exact-case matches first.
+    Smart,
+    /// Characters only match when their case is identical to the query.
+    Sensitive,
+}
+
+/// How a candidate is scored against the query.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ScoringStrategy {
+    /// A memoized recursive search, which can prune candidates that won't make it
+    /// into the results.
+    #[default]
+    Recursive,
+    /// A bottom-up search that only tracks where consecutive query characters occur
+    /// rather than a `query.len() * path.len()` memo table, for very long paths.
+    Iterative,
+}
+
 pub struct Matcher<'a> {
+    patterns: &'a [Pattern],
     query: &'a [char],
     lowercase_query: &'a [char],
-    query_char_bag: CharBag,
-    smart_case: bool,
+    case_sensitivity: CaseSensitivity,
     max_results: usize,
+    prioritize_file_names: bool,
+    skip_windows_roots: bool,
+    scoring_strategy: ScoringStrategy,
     min_score: f64,
+    /// The score results have to reach while there's room for more of them.
+    base_min_score: f64,
+    max_results_per_group: Option<usize>,
+    was_cancelled: bool,
+    score_threshold: f64,
+    /// The length of the Windows path prefix of the text being scored, which is never
+    /// matched.
+    root_len: usize,
+    buffers: MatchBuffers,
+}
+
+/// Scratch space used while scoring candidates, which can be carried over from one
+/// [`Matcher`] to the next to avoid reallocating it for every search.
+#[derive(Default)]
+pub struct MatchBuffers {
+    candidate_chars: Vec<char>,
+    lowercase_candidate_chars: Vec<char>,
+    file_name_chars: Vec<char>,
+    lowercase_file_name_chars: Vec<char>,
     match_positions: Vec<usize>,
+    best_match_positions: Vec<usize>,
     last_positions: Vec<usize>,
     score_matrix: Vec<Option<f64>>,
     best_position_matrix: Vec<usize>,
+    /// The iterative scorer's counterpart of `best_position_matrix`: for each query
+    /// character, the best position to match it at for each position the search for
+    /// it can start at, sorted by the latter.
+    best_position_rows: Vec<Vec<(usize, usize)>>,
+    matches: Vec<MatchPosition>,
+    prev_matches: Vec<MatchPosition>,
+}
+
+/// A path position at which a query character occurs, used by the iterative scorer.
+struct MatchPosition {
+    ix: usize,
+    /// The position of the last path separator at or before `ix`.
+    last_slash: usize,
+    /// The best score for matching the rest of the query after this position.
+    score: f64,
+}
+
+/// One alternative of a fuzzy query.
+///
+/// A query such as `user_service|userservice` consists of several patterns, and
+/// each candidate keeps the best score it achieves against any of them.
+pub struct Pattern {
+    query: Vec<char>,
+    lowercase_query: Vec<char>,
+    char_bag: CharBag,
+}
+
+impl Pattern {
+    pub fn new(query: &str) -> Self {
+        let lowercase_query = query.chars().map(lowercase_char).collect::<Vec<_>>();
+        let char_bag = CharBag::from(&lowercase_query[..]);
+        Self {
+            query: query.chars().collect(),
+            lowercase_query,
+            char_bag,
+        }
+    }
+
+    /// Splits the query on `|` into its alternatives, dropping empty ones.
+    pub fn parse_alternatives(query: &str) -> Vec<Self> {
+        query
+            .split('|')
+            .filter(|alternative| !alternative.is_empty())
+            .map(Self::new)
+            .collect()
+    }
+
+    /// Like [`Self::parse_alternatives`], but every alternative containing one of
+    /// the given abbreviations is also matched with them expanded, so that `ctl`
+    /// can find `controller` when the abbreviations map one to the other.
+    pub fn parse_alternatives_with_abbreviations(
+        query: &str,
+        abbreviations: &HashMap<String, String>,
+    ) -> Vec<Self> {
+        let mut abbreviations = abbreviations
+            .iter()
+            .filter(|(abbreviation, _)| !abbreviation.is_empty())
+            .collect::<Vec<_>>();
+        // Prefer the longest abbreviation starting at any given position.
+        abbreviations.sort_by_key(|(abbreviation, _)| Reverse(abbreviation.len()));
+
+        let mut patterns = Vec::new();
+        for alternative in query
+            .split('|')
+            .filter(|alternative| !alternative.is_empty())
+        {
+            patterns.push(Self::new(alternative));
+            if let Some(expanded) = expand_abbreviations(alternative, &abbreviations) {
+                patterns.push(Self::new(&expanded));
+            }
+        }
+        patterns
+    }
+}
+
+/// Replaces the abbreviations found in the query, ignoring ASCII case, returning
+/// `None` if there are none.
+fn expand_abbreviations(query: &str, abbreviations: &[(&String, &String)]) -> Option<String> {
+    let mut expanded = String::new();
+    let mut copied_ix = 0;
+    let mut ix = 0;
+    while ix < query.len() {
+        let abbreviation = abbreviations.iter().find(|(abbreviation, _)| {
+            query
+                .get(ix..ix + abbreviation.len())
+                .map_or(false, |text| text.eq_ignore_ascii_case(abbreviation))
+        });
+        if let Some((abbreviation, expansion)) = abbreviation {
+            expanded.push_str(&query[copied_ix..ix]);
+            expanded.push_str(expansion);
+            ix += abbreviation.len();
+            copied_ix = ix;
+        } else {
+            ix += query[ix..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    if copied_ix == 0 {
+        return None;
+    }
+    expanded.push_str(&query[copied_ix..]);
+    Some(expanded)
 }
 
 pub trait Match: Ord {
     fn score(&self) -> f64;
     fn set_positions(&mut self, positions: Vec<usize>);
+    /// Whether this match counts against the same cap of
+    /// [`Matcher::set_max_results_per_group`] as another one.
+    fn is_in_group_of(&self, _other: &Self) -> bool {
+        false
+    }
 }
 
 pub trait MatchCandidate {
     fn has_chars(&self, bag: CharBag) -> bool;
     fn to_string(&self) -> Cow<'_, str>;
+    /// Whether the prefix passed to [`Matcher::match_candidates`] is matched as part
+    /// of this candidate.
+    fn is_prefixed(&self) -> bool {
+        true
+    }
 }
 
 impl<'a> Matcher<'a> {
     pub fn new(
-        query: &'a [char],
-        lowercase_query: &'a [char],
-        query_char_bag: CharBag,
-        smart_case: bool,
+        patterns: &'a [Pattern],
+        case_sensitivity: CaseSensitivity,
         max_results: usize,
     ) -> Self {
         Self {
-            query,
-            lowercase_query,
-            query_char_bag,
+            patterns,
+            query: &[],
+            lowercase_query: &[],
+            prioritize_file_names: false,
+            skip_windows_roots: false,
+            scoring_strategy: ScoringStrategy::default(),
             min_score: 0.0,
-            last_positions: vec![0; lowercase_query.len()],
-            match_positions: vec![0; query.len()],
-            score_matrix: Vec::new(),
-            best_position_matrix: Vec::new(),
-            smart_case,
+            base_min_score: 0.0,
+            max_results_per_group: None,
+            was_cancelled: false,
+            score_threshold: 0.0,
+            root_len: 0,
+            buffers: MatchBuffers::default(),
+            case_sensitivity,
             max_results,
         }
     }
 
+    /// Scores candidates against their file name first, only falling back to the
+    /// whole path when the file name doesn't match. File name matches always rank
+    /// above whole-path matches.
+    pub fn prioritize_file_names(&mut self, prioritize: bool) {
+        self.prioritize_file_names = prioritize;
+    }
+
+    /// Treats candidates as paths that may start with a Windows drive or UNC prefix,
+    /// which the query is then matched after.
+    pub fn skip_windows_roots(&mut self, skip: bool) {
+        self.skip_windows_roots = skip;
+    }
+
+    /// Drops matches scoring below the given value, which also lets candidates that
+    /// can't reach it be skipped early.
+    pub fn set_min_score(&mut self, min_score: f64) {
+        self.min_score = min_score;
+        self.base_min_score = min_score;
+    }
+
+    /// Keeps at most this many of the results in the same group, as told by
+    /// [`Match::is_in_group_of`], so that the rest of the results are filled with
+    /// matches from other groups.
+    pub fn set_max_results_per_group(&mut self, max_results_per_group: Option<usize>) {
+        self.max_results_per_group = max_results_per_group;
+    }
+
+    /// Whether matching stopped before every candidate was scored, because the
+    /// cancellation token was cancelled.
+    pub fn was_cancelled(&self) -> bool {
+        self.was_cancelled
+    }
+
+    pub fn set_scoring_strategy(&mut self, strategy: ScoringStrategy) {
+        self.scoring_strategy = strategy;
+    }
+
+    /// Scores candidates using buffers left over from a previous matcher.
+    pub fn set_buffers(&mut self, buffers: MatchBuffers) {
+        self.buffers = buffers;
+    }
+
+    pub fn into_buffers(self) -> MatchBuffers {
+        self.buffers
+    }
+
+    /// Scores the candidates, keeping the best `max_results` of them in `results`.
+    ///
+    /// Scores passed to `build_match` lie between 0 and 1, with 1 meaning that the
+    /// query spells out the whole candidate. Scores decrease with the share of the
+    /// matched path components that the query leaves out, and with how far apart
+    /// its characters are spread. `build_match` may adjust a score, but must not
+    /// raise it, since candidates are pruned based on the scores they are given.
     pub fn match_candidates<C: MatchCandidate, R, F>(
         &mut self,
         prefix: &[char],
         lowercase_prefix: &[char],
         candidates: impl Iterator<Item = C>,
         results: &mut Vec<R>,
-        cancel_flag: &AtomicBool,
+        cancel_flag: &CancellationToken,
         build_match: F,
     ) where
         R: Match,
         F: Fn(&C, f64) -> R,
     {
-        let mut candidate_chars = Vec::new();
-        let mut lowercase_candidate_chars = Vec::new();
+        let mut candidate_chars = mem::take(&mut self.buffers.candidate_chars);
+        let mut lowercase_candidate_chars = mem::take(&mut self.buffers.lowercase_candidate_chars);
+        let mut file_name_chars = mem::take(&mut self.buffers.file_name_chars);
+        let mut lowercase_file_name_chars = mem::take(&mut self.buffers.lowercase_file_name_chars);
+        let mut file_name_byte_offset = 0;
 
         for candidate in candidates {
-            if !candidate.has_chars(self.query_char_bag) {
+            if !self
+                .patterns
+                .iter()
+                .any(|pattern| candidate.has_chars(pattern.char_bag))
+            {
                 continue;
             }
 
-            if cancel_flag.load(atomic::Ordering::Relaxed) {
+            if cancel_flag.is_cancelled() {
+                self.was_cancelled = true;
                 break;
             }
 
+            let (prefix, lowercase_prefix) = if candidate.is_prefixed() {
+                (prefix, lowercase_prefix)
+            } else {
+                (&[][..], &[][..])
+            };
+
             candidate_chars.clear();
             lowercase_candidate_chars.clear();
             for c in candidate.to_string().chars() {
                 candidate_chars.push(c);
-                lowercase_candidate_chars.append(&mut c.to_lowercase().collect::<Vec<_>>());
+                lowercase_candidate_chars.push(lowercase_char(c));
             }
 
-            if !self.find_last_positions(lowercase_prefix, &lowercase_candidate_chars) {
-                continue;
+            if self.prioritize_file_names {
+                let file_name_start = candidate_chars
+                    .iter()
+                    .rposition(|c| is_path_separator(*c))
+                    .map_or(0, |ix| ix + 1);
+                file_name_chars.clear();
+                file_name_chars.extend_from_slice(&candidate_chars[file_name_start..]);
+                lowercase_file_name_chars.clear();
+                lowercase_file_name_chars
+                    .extend(file_name_chars.iter().copied().map(lowercase_char));
+                file_name_byte_offset = prefix
+                    .iter()
+                    .chain(&candidate_chars[..file_name_start])
+                    .map(|c| c.len_utf8())
+                    .sum();
             }
 
-            let matrix_len = self.query.len() * (prefix.len() + candidate_chars.len());
-            self.score_matrix.clear();
-            self.score_matrix.resize(matrix_len, None);
-            self.best_position_matrix.clear();
-            self.best_position_matrix.resize(matrix_len, 0);
+            let root_len = if self.skip_windows_roots {
+                windows_root_len(prefix, &candidate_chars)
+            } else {
+                0
+            };
 
-            let score = self.score_match(
-                &candidate_chars,
-                &lowercase_candidate_chars,
-                prefix,
-                lowercase_prefix,
-            );
+            let mut score = 0.0;
+            for pattern in self.patterns {
+                if !candidate.has_chars(pattern.char_bag) {
+                    continue;
+                }
+
+                self.set_pattern(pattern);
+
+                // File name matches are mapped into the upper half of the score range
+                // and whole-path matches into the lower half, so that the former always
+                // rank first.
+                if self.prioritize_file_names {
+                    self.root_len = 0;
+                    let pattern_score = self.score_candidate(
+                        &file_name_chars,
+                        &lowercase_file_name_chars,
+                        &[],
+                        &[],
+                        0.5,
+                        0.5,
+                    );
+                    if pattern_score > 0.0 {
+                        if pattern_score > score {
+                            score = pattern_score;
+                            self.buffers.best_match_positions.clear();
+                            self.buffers.best_match_positions.extend(
+                                self.buffers
+                                    .match_positions
+                                    .iter()
+                                    .map(|position| position + file_name_byte_offset),
+                            );
+                        }
+                        continue;
+                    }
+                }
+
+                let scale = if self.prioritize_file_names { 0.5 } else { 1.0 };
+                self.root_len = root_len;
+                let pattern_score = self.score_candidate(
+                    &candidate_chars,
+                    &lowercase_candidate_chars,
+                    prefix,
+                    lowercase_prefix,
+                    0.0,
+                    scale,
+                );
+                if pattern_score > score {
+                    score = pattern_score;
+                    self.buffers.best_match_positions.clear();
+                    self.buffers
+                        .best_match_positions
+                        .extend_from_slice(&self.buffers.match_positions);
+                }
+            }
 
             if score > 0.0 {
                 let mut mat = build_match(&candidate, score);
+                if mat.score() < self.min_score {
+                    continue;
+                }
                 if let Err(i) = results.binary_search_by(|m| mat.cmp(m)) {
+                    if let Some(max_per_group) = self.max_results_per_group {
+                        let better_in_group = results[..i]
+                            .iter()
+                            .filter(|result| result.is_in_group_of(&mat))
+                            .count();
+                        if better_in_group >= max_per_group {
+                            continue;
+                        }
+                    }
                     if results.len() < self.max_results {
-                        mat.set_positions(self.match_positions.clone());
+                        mat.set_positions(self.buffers.best_match_positions.clone());
                         results.insert(i, mat);
                     } else if i < results.len() {
                         results.pop();
-                        mat.set_positions(self.match_positions.clone());
+                        mat.set_positions(self.buffers.best_match_positions.clone());
                         results.insert(i, mat);
+                    } else {
+                        continue;
                     }
-                    if results.len() == self.max_results {
-                        self.min_score = results.last().unwrap().score();
+                    if let Some(max_per_group) = self.max_results_per_group {
+                        // The new match may push the worst one of its group past the cap,
+                        // making room for a match from another group.
+                        let (better, worse) = results.split_at(i + 1);
+                        if let Some(ix) = worse
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, result)| result.is_in_group_of(&better[i]))
+                            .nth(
+                                max_per_group
+                                    - 1
+                                    - better[..i]
+                                        .iter()
+                                        .filter(|result| result.is_in_group_of(&better[i]))
+                                        .count(),
+                            )
+                            .map(|(ix, _)| i + 1 + ix)
+                        {
+                            results.remove(ix);
+                        }
                     }
+                    self.min_score = if results.len() == self.max_results {
+                        results.last().unwrap().score()
+                    } else {
+                        self.base_min_score
+                    };
                 }
             }
         }
+
+        self.buffers.candidate_chars = candidate_chars;
+        self.buffers.lowercase_candidate_chars = lowercase_candidate_chars;
+        self.buffers.file_name_chars = file_name_chars;
+        self.buffers.lowercase_file_name_chars = lowercase_file_name_chars;
+    }
+
+    /// Scores the current pattern against a candidate, returning `offset + scale * score`
+    /// so that callers can rank different kinds of matches into disjoint score ranges.
+    fn score_candidate(
+        &mut self,
+        path: &[char],
+        lowercase_path: &[char],
+        prefix: &[char],
+        lowercase_prefix: &[char],
+        offset: f64,
+        scale: f64,
+    ) -> f64 {
+        if !self.find_last_positions(lowercase_prefix, lowercase_path) {
+            return 0.0;
+        }
+        if self
+            .buffers
+            .last_positions
+            .first()
+            .map_or(false, |&position| position < self.root_len)
+        {
+            return 0.0;
+        }
+
+        // Translate the lowest score among the results into this range, skipping the
+        // candidate entirely if it cannot make it into the results.
+        self.score_threshold = if self.min_score > offset {
+            (self.min_score - offset) / scale
+        } else {
+            0.0
+        };
+        if self.score_threshold > 1.0 {
+            return 0.0;
+        }
+
+        match self.scoring_strategy {
+            ScoringStrategy::Recursive => {
+                let matrix_len = self.query.len() * (prefix.len() + path.len());
+                self.buffers.score_matrix.clear();
+                self.buffers.score_matrix.resize(matrix_len, None);
+                self.buffers.best_position_matrix.clear();
+                self.buffers.best_position_matrix.resize(matrix_len, 0);
+            }
+            ScoringStrategy::Iterative => {
+                let rows = &mut self.buffers.best_position_rows;
+                if rows.len() < self.query.len() {
+                    rows.resize_with(self.query.len(), Vec::new);
+                }
+                for row in &mut rows[..self.query.len()] {
+                    row.clear();
+                }
+            }
+        }
+
+        let score = self.score_match(path, lowercase_path, prefix, lowercase_prefix);
+        if score > 0.0 {
+            offset + scale * score
+        } else {
+            0.0
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: &'a Pattern) {
+        self.query = &pattern.query;
+        self.lowercase_query = &pattern.lowercase_query;
+        self.buffers
+            .last_positions
+            .resize(self.lowercase_query.len(), 0);
+        self.buffers.match_positions.resize(self.query.len(), 0);
     }
 
     fn find_last_positions(
@@ -129,10 +530,10 @@ impl<'a> Matcher<'a> {
         let mut lowercase_prefix = lowercase_prefix.iter();
         let mut lowercase_candidate = lowercase_candidate.iter();
         for (i, char) in self.lowercase_query.iter().enumerate().rev() {
-            if let Some(j) = lowercase_candidate.rposition(|c| c == char) {
-                self.last_positions[i] = j + lowercase_prefix.len();
-            } else if let Some(j) = lowercase_prefix.rposition(|c| c == char) {
-                self.last_positions[i] = j;
+            if let Some(j) = lowercase_candidate.rposition(|c| query_char_matches(*char, *c)) {
+                self.buffers.last_positions[i] = j + lowercase_prefix.len();
+            } else if let Some(j) = lowercase_prefix.rposition(|c| query_char_matches(*char, *c)) {
+                self.buffers.last_positions[i] = j;
             } else {
                 return false;
             }
@@ -147,26 +548,31 @@ impl<'a> Matcher<'a> {
         prefix: &[char],
         lowercase_prefix: &[char],
     ) -> f64 {
-        let score = self.recursive_score_match(
-            path,
-            path_cased,
-            prefix,
-            lowercase_prefix,
-            0,
-            0,
-            self.query.len() as f64,
-        ) * self.query.len() as f64;
+        let score = match self.scoring_strategy {
+            ScoringStrategy::Recursive => self.recursive_score_match(
+                path,
+                path_cased,
+                prefix,
+                lowercase_prefix,
+                0,
+                self.root_len,
+                self.query.len() as f64,
+            ),
+            ScoringStrategy::Iterative => {
+                self.iterative_score_match(path, path_cased, prefix, lowercase_prefix)
+            }
+        } * self.query.len() as f64;
 
         if score <= 0.0 {
             return 0.0;
         }
 
         let path_len = prefix.len() + path.len();
-        let mut cur_start = 0;
+        let mut cur_start = self.root_len;
         let mut byte_ix = 0;
         let mut char_ix = 0;
         for i in 0..self.query.len() {
-            let match_char_ix = self.best_position_matrix[i * path_len + cur_start];
+            let match_char_ix = self.best_position(i, cur_start, path_len);
             while char_ix < match_char_ix {
                 let ch = prefix
                     .get(char_ix)
@@ -176,12 +582,27 @@ impl<'a> Matcher<'a> {
                 char_ix += 1;
             }
             cur_start = match_char_ix + 1;
-            self.match_positions[i] = byte_ix;
+            self.buffers.match_positions[i] = byte_ix;
         }
 
         score
     }
 
+    /// The best position to match the query character at `query_idx` at when searching
+    /// for it from `path_idx` on, as recorded by the last scoring.
+    fn best_position(&self, query_idx: usize, path_idx: usize, path_len: usize) -> usize {
+        match self.scoring_strategy {
+            ScoringStrategy::Recursive => {
+                self.buffers.best_position_matrix[query_idx * path_len + path_idx]
+            }
+            ScoringStrategy::Iterative => {
+                let row = &self.buffers.best_position_rows[query_idx];
+                row.binary_search_by_key(&path_idx, |&(start, _)| start)
+                    .map_or(0, |ix| row[ix].1)
+            }
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn recursive_score_match(
         &mut self,
@@ -199,117 +620,343 @@ impl<'a> Matcher<'a> {
 
         let path_len = prefix.len() + path.len();
 
-        if let Some(memoized) = self.score_matrix[query_idx * path_len + path_idx] {
+        if let Some(memoized) = self.buffers.score_matrix[query_idx * path_len + path_idx] {
             return memoized;
         }
 
         let mut score = 0.0;
         let mut best_position = 0;
 
-        let query_char = self.lowercase_query[query_idx];
-        let limit = self.last_positions[query_idx];
+        let limit = self.buffers.last_positions[query_idx];
 
-        let mut last_slash = 0;
+        // The root, if any, ends with the separator preceding the first component.
+        let mut last_slash = self.root_len.saturating_sub(1);
         for j in path_idx..=limit {
-            let path_char = if j < prefix.len() {
-                lowercase_prefix[j]
-            } else {
-                path_cased[j - prefix.len()]
-            };
-            let is_path_sep = path_char == '/' || path_char == '\\';
-
-            if query_idx == 0 && is_path_sep {
+            if query_idx == 0 && is_path_separator(char_at(lowercase_prefix, path_cased, j)) {
                 last_slash = j;
             }
 
-            if query_char == path_char || (is_path_sep && query_char == '_' || query_char == '\\') {
-                let curr = if j < prefix.len() {
-                    prefix[j]
-                } else {
-                    path[j - prefix.len()]
-                };
-
-                let mut char_score = 1.0;
-                if j > path_idx {
-                    let last = if j - 1 < prefix.len() {
-                        prefix[j - 1]
-                    } else {
-                        path[j - 1 - prefix.len()]
-                    };
+            let Some(multiplier) = self.char_multiplier(
+                path,
+                path_cased,
+                prefix,
+                lowercase_prefix,
+                query_idx,
+                path_idx,
+                j,
+                last_slash,
+            ) else {
+                continue;
+            };
 
-                    if last == '/' {
-                        char_score = 0.9;
-                    } else if (last == '-' || last == '_' || last == ' ' || last.is_numeric())
-                        || (last.is_lowercase() && curr.is_uppercase())
-                    {
-                        char_score = 0.8;
-                    } else if last == '.' {
-                        char_score = 0.7;
-                    } else if query_idx == 0 {
-                        char_score = BASE_DISTANCE_PENALTY;
-                    } else {
-                        char_score = MIN_DISTANCE_PENALTY.max(
-                            BASE_DISTANCE_PENALTY
-                                - (j - path_idx - 1) as f64 * ADDITIONAL_DISTANCE_PENALTY,
-                        );
+            let mut next_score = 1.0;
+            if self.score_threshold > 0.0 {
+                next_score = cur_score * multiplier;
+                // Scores only decrease. If we can't pass the previous best, bail
+                if next_score < self.score_threshold {
+                    // Ensure that score is non-zero so we use it in the memo table.
+                    if score == 0.0 {
+                        score = 1e-18;
                     }
+                    continue;
                 }
+            }
+
+            let new_score = self.recursive_score_match(
+                path,
+                path_cased,
+                prefix,
+                lowercase_prefix,
+                query_idx + 1,
+                j + 1,
+                next_score,
+            ) * multiplier;
 
-                // Apply a severe penalty if the case doesn't match.
-                // This will make the exact matches have higher score than the case-insensitive and the
-                // path insensitive matches.
-                if (self.smart_case || curr == '/') && self.query[query_idx] != curr {
-                    char_score *= 0.001;
+            if new_score > score {
+                score = new_score;
+                best_position = j;
+                // Optimization: can't score better than 1.
+                if new_score == 1.0 {
+                    break;
                 }
+            }
+        }
+
+        if best_position != 0 {
+            self.buffers.best_position_matrix[query_idx * path_len + path_idx] = best_position;
+        }
 
-                let mut multiplier = char_score;
+        self.buffers.score_matrix[query_idx * path_len + path_idx] = Some(score);
+        score
+    }
 
-                // Scale the score based on how deep within the path we found the match.
-                if query_idx == 0 {
-                    multiplier /= ((prefix.len() + path.len()) - last_slash) as f64;
-                }
+    /// Computes the same scores as [`Self::recursive_score_match`] bottom-up, one query
+    /// character at a time. Rather than a memo table covering every query and path
+    /// position, it only keeps the positions where the current and the previous query
+    /// characters occur, along with the best score for the rest of the query.
+    fn iterative_score_match(
+        &mut self,
+        path: &[char],
+        path_cased: &[char],
+        prefix: &[char],
+        lowercase_prefix: &[char],
+    ) -> f64 {
+        let query_len = self.query.len();
+        if query_len == 0 {
+            return 1.0;
+        }
 
-                let mut next_score = 1.0;
-                if self.min_score > 0.0 {
-                    next_score = cur_score * multiplier;
-                    // Scores only decrease. If we can't pass the previous best, bail
-                    if next_score < self.min_score {
-                        // Ensure that score is non-zero so we use it in the memo table.
-                        if score == 0.0 {
-                            score = 1e-18;
-                        }
+        let mut matches = mem::take(&mut self.buffers.matches);
+        let mut prev_matches = mem::take(&mut self.buffers.prev_matches);
+        self.find_query_char(query_len - 1, lowercase_prefix, path_cased, &mut matches);
+        for position in &mut matches {
+            position.score = 1.0;
+        }
+
+        let mut score = 0.0;
+        for query_idx in (0..query_len).rev() {
+            // Each search for this query character starts right after an occurrence of
+            // the previous one, or at the start of the path for the first character.
+            if query_idx == 0 {
+                prev_matches.clear();
+            } else {
+                self.find_query_char(
+                    query_idx - 1,
+                    lowercase_prefix,
+                    path_cased,
+                    &mut prev_matches,
+                );
+            }
+
+            let search_count = if query_idx == 0 {
+                1
+            } else {
+                prev_matches.len()
+            };
+            for i in 0..search_count {
+                let path_idx = if query_idx == 0 {
+                    self.root_len
+                } else {
+                    prev_matches[i].ix + 1
+                };
+                let mut best_score = 0.0;
+                let mut best_position = 0;
+                let first_match = matches.partition_point(|position| position.ix < path_idx);
+                for position in &matches[first_match..] {
+                    let Some(multiplier) = self.char_multiplier(
+                        path,
+                        path_cased,
+                        prefix,
+                        lowercase_prefix,
+                        query_idx,
+                        path_idx,
+                        position.ix,
+                        position.last_slash,
+                    ) else {
                         continue;
+                    };
+
+                    let new_score = position.score * multiplier;
+                    if new_score > best_score {
+                        best_score = new_score;
+                        best_position = position.ix;
+                        if new_score == 1.0 {
+                            break;
+                        }
                     }
                 }
 
-                let new_score = self.recursive_score_match(
-                    path,
-                    path_cased,
-                    prefix,
-                    lowercase_prefix,
-                    query_idx + 1,
-                    j + 1,
-                    next_score,
-                ) * multiplier;
-
-                if new_score > score {
-                    score = new_score;
-                    best_position = j;
-                    // Optimization: can't score better than 1.
-                    if new_score == 1.0 {
-                        break;
-                    }
+                if best_position != 0 {
+                    self.buffers.best_position_rows[query_idx].push((path_idx, best_position));
+                }
+                if query_idx == 0 {
+                    score = best_score;
+                } else {
+                    prev_matches[i].score = best_score;
                 }
             }
-        }
 
-        if best_position != 0 {
-            self.best_position_matrix[query_idx * path_len + path_idx] = best_position;
+            mem::swap(&mut matches, &mut prev_matches);
         }
 
-        self.score_matrix[query_idx * path_len + path_idx] = Some(score);
+        self.buffers.matches = matches;
+        self.buffers.prev_matches = prev_matches;
         score
     }
+
+    /// Collects the positions up to the query character's last possible position at
+    /// which the character could match.
+    fn find_query_char(
+        &self,
+        query_idx: usize,
+        lowercase_prefix: &[char],
+        path_cased: &[char],
+        positions: &mut Vec<MatchPosition>,
+    ) {
+        let query_char = self.lowercase_query[query_idx];
+        positions.clear();
+        let mut last_slash = self.root_len.saturating_sub(1);
+        for ix in self.root_len..=self.buffers.last_positions[query_idx] {
+            let path_char = char_at(lowercase_prefix, path_cased, ix);
+            if is_path_separator(path_char) {
+                last_slash = ix;
+            }
+            if query_char_matches(query_char, path_char) {
+                positions.push(MatchPosition {
+                    ix,
+                    last_slash,
+                    score: 0.0,
+                });
+            }
+        }
+    }
+
+    /// Returns the score multiplier for matching the query character at `query_idx`
+    /// with the path character at `j`, given that the search started at `path_idx`,
+    /// or `None` if the characters don't match.
+    #[allow(clippy::too_many_arguments)]
+    fn char_multiplier(
+        &self,
+        path: &[char],
+        path_cased: &[char],
+        prefix: &[char],
+        lowercase_prefix: &[char],
+        query_idx: usize,
+        path_idx: usize,
+        j: usize,
+        last_slash: usize,
+    ) -> Option<f64> {
+        let query_char = self.lowercase_query[query_idx];
+        let path_char = char_at(lowercase_prefix, path_cased, j);
+        let is_path_sep = is_path_separator(path_char);
+
+        if !query_char_matches(query_char, path_char) {
+            return None;
+        }
+
+        let curr = char_at(prefix, path, j);
+
+        // Either separator matches the other, whichever platform the path comes from.
+        let case_matches = self.query[query_idx] == curr
+            || (is_path_sep && is_path_separator(self.query[query_idx]));
+        if self.case_sensitivity == CaseSensitivity::Sensitive && !case_matches && !is_path_sep {
+            return None;
+        }
+
+        let mut char_score = 1.0;
+        if j > path_idx {
+            let last = char_at(prefix, path, j - 1);
+
+            if is_path_separator(last) {
+                char_score = 0.9;
+            } else if (last == '-' || last == '_' || last == ' ' || last.is_numeric())
+                || (last.is_lowercase() && curr.is_uppercase())
+            {
+                char_score = 0.8;
+            } else if last == '.' {
+                char_score = 0.7;
+            } else if query_idx == 0 {
+                char_score = BASE_DISTANCE_PENALTY;
+            } else {
+                char_score = MIN_DISTANCE_PENALTY.max(
+                    BASE_DISTANCE_PENALTY - (j - path_idx - 1) as f64 * ADDITIONAL_DISTANCE_PENALTY,
+                );
+            }
+        }
+
+        // Apply a severe penalty if the case doesn't match.
+        // This will make the exact matches have higher score than the case-insensitive and the
+        // path insensitive matches.
+        if (self.case_sensitivity == CaseSensitivity::Smart || is_path_sep) && !case_matches {
+            char_score *= 0.001;
+        }
+
+        let mut multiplier = char_score;
+
+        // Scale the score based on how deep within the path we found the match.
+        if query_idx == 0 {
+            multiplier /= ((prefix.len() + path.len()) - last_slash) as f64;
+        }
+
+        Some(multiplier)
+    }
+}
+
+/// Returns the character at `ix` of the prefix followed by the path.
+fn char_at(prefix: &[char], path: &[char], ix: usize) -> char {
+    if ix < prefix.len() {
+        prefix[ix]
+    } else {
+        path[ix - prefix.len()]
+    }
+}
+
+/// Lowercases a character, keeping only the first character of lowercase forms that
+/// are longer, such as `i̇` for `İ`. This keeps lowercase text aligned with the
+/// original, as the scorer indexes both with the same positions.
+fn lowercase_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_path_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Whether a lowercase query character matches a lowercase path character. Path
+/// separators match any separator, and can also be typed as `_`.
+fn query_char_matches(query_char: char, path_char: char) -> bool {
+    query_char == path_char
+        || (is_path_separator(path_char) && (query_char == '_' || is_path_separator(query_char)))
+}
+
+/// Returns the length of the Windows path prefix that the prefix followed by the path
+/// starts with, including the separator after it, such as `C:\`, `\\server\share\`
+/// or `\\?\UNC\server\share\`. These are recognized on every platform, as the
+/// candidates may come from a remote Windows machine.
+fn windows_root_len(prefix: &[char], path: &[char]) -> usize {
+    let len = prefix.len() + path.len();
+    let get = |ix: usize| (ix < len).then(|| char_at(prefix, path, ix));
+    let is_separator_at = |ix: usize| get(ix).map_or(false, is_path_separator);
+    let is_drive_at =
+        |ix: usize| get(ix).map_or(false, |c| c.is_ascii_alphabetic()) && get(ix + 1) == Some(':');
+    // Returns the position after the given number of components starting at `ix`,
+    // along with the separator following them.
+    let skip_components = |mut ix: usize, count: usize| {
+        for _ in 0..count {
+            while ix < len && !is_separator_at(ix) {
+                ix += 1;
+            }
+            ix = (ix + 1).min(len);
+        }
+        ix
+    };
+
+    let root_len = if is_separator_at(0)
+        && is_separator_at(1)
+        && matches!(get(2), Some('?' | '.'))
+        && is_separator_at(3)
+    {
+        // Verbatim and device paths, such as `\\?\C:\` or `\\?\UNC\server\share\`.
+        let is_unc = (4..7)
+            .map(|ix| get(ix).map(|c| c.to_ascii_uppercase()))
+            .eq([Some('U'), Some('N'), Some('C')])
+            && is_separator_at(7);
+        if is_unc {
+            skip_components(8, 2)
+        } else if is_drive_at(4) {
+            6 + is_separator_at(6) as usize
+        } else {
+            skip_components(4, 1)
+        }
+    } else if is_separator_at(0) && is_separator_at(1) && !is_separator_at(2) {
+        // UNC paths, such as `\\server\share\`.
+        skip_components(2, 2)
+    } else if is_drive_at(0) {
+        2 + is_separator_at(2) as usize
+    } else {
+        0
+    };
+    root_len.min(len)
 }
 
 #[cfg(test)]
@@ -324,22 +971,25 @@ mod tests {
 
     #[test]
     fn test_get_last_positions() {
-        let mut query: &[char] = &['d', 'c'];
-        let mut matcher = Matcher::new(query, query, query.into(), false, 10);
+        let patterns = [Pattern::new("dc")];
+        let mut matcher = Matcher::new(&patterns, CaseSensitivity::Insensitive, 10);
+        matcher.set_pattern(&patterns[0]);
         let result = matcher.find_last_positions(&['a', 'b', 'c'], &['b', 'd', 'e', 'f']);
         assert!(!result);
 
-        query = &['c', 'd'];
-        let mut matcher = Matcher::new(query, query, query.into(), false, 10);
+        let patterns = [Pattern::new("cd")];
+        let mut matcher = Matcher::new(&patterns, CaseSensitivity::Insensitive, 10);
+        matcher.set_pattern(&patterns[0]);
         let result = matcher.find_last_positions(&['a', 'b', 'c'], &['b', 'd', 'e', 'f']);
         assert!(result);
-        assert_eq!(matcher.last_positions, vec![2, 4]);
+        assert_eq!(matcher.buffers.last_positions, vec![2, 4]);
 
-        query = &['z', '/', 'z', 'f'];
-        let mut matcher = Matcher::new(query, query, query.into(), false, 10);
+        let patterns = [Pattern::new("z/zf")];
+        let mut matcher = Matcher::new(&patterns, CaseSensitivity::Insensitive, 10);
+        matcher.set_pattern(&patterns[0]);
         let result = matcher.find_last_positions(&['z', 'e', 'd', '/'], &['z', 'e', 'd', '/', 'f']);
         assert!(result);
-        assert_eq!(matcher.last_positions, vec![0, 3, 4, 8]);
+        assert_eq!(matcher.buffers.last_positions, vec![0, 3, 4, 8]);
     }
 
     #[test]
@@ -359,7 +1009,7 @@ mod tests {
         ];
 
         assert_eq!(
-            match_single_path_query("abc", false, &paths),
+            match_single_path_query("abc", CaseSensitivity::Insensitive, &paths),
             vec![
                 ("abC", vec![0, 1, 2]),
                 ("abcd", vec![0, 1, 2]),
@@ -368,12 +1018,12 @@ mod tests {
             ]
         );
         assert_eq!(
-            match_single_path_query("t/i/a/t/d", false, &paths),
+            match_single_path_query("t/i/a/t/d", CaseSensitivity::Insensitive, &paths),
             vec![("/this/is/a/test/dir", vec![1, 5, 6, 8, 9, 10, 11, 15, 16]),]
         );
 
         assert_eq!(
-            match_single_path_query("tiatd", false, &paths),
+            match_single_path_query("tiatd", CaseSensitivity::Insensitive, &paths),
             vec![
                 ("/test/tiatd", vec![6, 7, 8, 9, 10]),
                 ("/this/is/a/test/dir", vec![1, 6, 9, 11, 16]),
@@ -383,13 +1033,138 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_windows_root_len() {
+        let root_len = |path: &str| {
+            let path = path.chars().collect::<Vec<_>>();
+            windows_root_len(&[], &path)
+        };
+        assert_eq!(root_len(r"src\main.rs"), 0);
+        assert_eq!(root_len("/src/main.rs"), 0);
+        assert_eq!(root_len(r"C:\src\main.rs"), 3);
+        assert_eq!(root_len("c:/src/main.rs"), 3);
+        assert_eq!(root_len("C:"), 2);
+        assert_eq!(root_len(r"\\server\share\src\main.rs"), 15);
+        assert_eq!(root_len(r"\\server"), 8);
+        assert_eq!(root_len(r"\\?\C:\src\main.rs"), 7);
+        assert_eq!(root_len(r"\\?\UNC\server\share\src\main.rs"), 21);
+        assert_eq!(root_len(r"\\.\pipe\name"), 9);
+        assert_eq!(windows_root_len(&['C', ':'], &['\\', 'a']), 3);
+    }
+
+    #[test]
+    fn test_match_windows_paths() {
+        let paths = vec![
+            r"C:\projects\zed\src\main.rs",
+            r"\\?\C:\work\editor.rs",
+            r"\\server\share\code\lib.rs",
+            r"src\components\button.rs",
+        ];
+
+        // Either separator in the query matches either separator in the path.
+        assert_eq!(
+            match_single_path_query("src/main", CaseSensitivity::Insensitive, &paths),
+            vec![(
+                r"C:\projects\zed\src\main.rs",
+                vec![16, 17, 18, 19, 20, 21, 22, 23]
+            )]
+        );
+        assert_eq!(
+            match_single_path_query(r"ts\but", CaseSensitivity::Smart, &paths),
+            vec![(r"src\components\button.rs", vec![12, 13, 14, 15, 16, 17])]
+        );
+        // A separator in the query doesn't match anything else...
+        assert_eq!(
+            match_single_path_query(r"mai\n", CaseSensitivity::Insensitive, &paths),
+            vec![]
+        );
+        // ...and the drive letter, UNC server and verbatim prefix are never matched.
+        assert_eq!(
+            match_single_path_query("c:", CaseSensitivity::Insensitive, &paths),
+            vec![]
+        );
+        assert_eq!(
+            match_single_path_query("serverlib", CaseSensitivity::Insensitive, &paths),
+            vec![]
+        );
+        assert_eq!(
+            match_single_path_query("?edit", CaseSensitivity::Insensitive, &paths),
+            vec![]
+        );
+        assert_eq!(
+            match_single_path_query("edit", CaseSensitivity::Insensitive, &paths),
+            vec![(r"\\?\C:\work\editor.rs", vec![12, 13, 14, 15])]
+        );
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let paths = vec!["src/Button.rs", "src/button.rs", "src/BUTTON.md"];
+
+        assert_eq!(
+            match_single_path_query("button", CaseSensitivity::Insensitive, &paths).len(),
+            3
+        );
+        assert_eq!(
+            match_single_path_query("Button", CaseSensitivity::Smart, &paths)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            vec!["src/Button.rs", "src/button.rs", "src/BUTTON.md"]
+        );
+        assert_eq!(
+            match_single_path_query("Button", CaseSensitivity::Sensitive, &paths),
+            vec![("src/Button.rs", vec![4, 5, 6, 7, 8, 9])]
+        );
+        assert_eq!(
+            match_single_path_query("BU", CaseSensitivity::Sensitive, &paths),
+            vec![("src/BUTTON.md", vec![4, 5])]
+        );
+    }
+
+    #[test]
+    fn test_match_alternatives() {
+        let paths = vec![
+            "src/user_service.rs",
+            "src/userservice.rs",
+            "src/users.rs",
+            "tests/service_user.rs",
+        ];
+
+        assert_eq!(
+            match_single_path_query(
+                "user_service|userservice",
+                CaseSensitivity::Insensitive,
+                &paths
+            ),
+            vec![
+                (
+                    "src/user_service.rs",
+                    vec![4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+                ),
+                (
+                    "src/userservice.rs",
+                    vec![4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+                ),
+            ]
+        );
+
+        // Empty alternatives are ignored, and a candidate matching several
+        // alternatives is only reported once.
+        assert_eq!(
+            match_single_path_query("|users|users.rs|", CaseSensitivity::Insensitive, &paths),
+            match_single_path_query("users.rs|users", CaseSensitivity::Insensitive, &paths),
+        );
+        assert!(match_single_path_query("|", CaseSensitivity::Insensitive, &paths).is_empty());
+    }
+
     #[test]
     fn test_lowercase_longer_than_uppercase() {
         // This character has more chars in lower-case than in upper-case.
         let paths = vec!["\u{0130}"];
         let query = "\u{0130}";
         assert_eq!(
-            match_single_path_query(query, false, &paths),
+            match_single_path_query(query, CaseSensitivity::Insensitive, &paths),
             vec![("\u{0130}", vec![0])]
         );
 
@@ -397,7 +1172,7 @@ mod tests {
         let paths = vec!["i\u{307}"];
         let query = "\u{0130}";
         assert_eq!(
-            match_single_path_query(query, false, &paths),
+            match_single_path_query(query, CaseSensitivity::Insensitive, &paths),
             vec![("i\u{307}", vec![0])]
         );
     }
@@ -412,14 +1187,14 @@ mod tests {
         ];
         assert_eq!("1️⃣".len(), 7);
         assert_eq!(
-            match_single_path_query("bcd", false, &paths),
+            match_single_path_query("bcd", CaseSensitivity::Insensitive, &paths),
             vec![
                 ("αβγδ/bcde", vec![9, 10, 11]),
                 ("aαbβ/cγdδ", vec![3, 7, 10]),
             ]
         );
         assert_eq!(
-            match_single_path_query("cde", false, &paths),
+            match_single_path_query("cde", CaseSensitivity::Insensitive, &paths),
             vec![
                 ("αβγδ/bcde", vec![10, 11, 12]),
                 ("c1️⃣2️⃣3️⃣/d4️⃣5️⃣6️⃣/e7️⃣8️⃣9️⃣/f", vec![0, 23, 46]),
@@ -427,14 +1202,104 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_iterative_scoring() {
+        let paths = [
+            "crates/editor/src/editor.rs",
+            "crates/editor/src/display_map/fold_map.rs",
+            "crates/EditorTests/src/Element.rs",
+            "assets/icons/file_icons/editorconfig.svg",
+            "docs/src/languages/elixir.md",
+            "a/very/deeply/nested/directory/with/a/long/path/to/the/editor_element.rs",
+        ]
+        .map(Path::new);
+        let scored_matches = |query: &str, case_sensitivity, scoring_strategy| {
+            let candidates = paths.iter().map(|path| PathMatchCandidate {
+                is_dir: false,
+                path,
+                char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                symlink_target: None,
+            });
+            crate::match_fixed_path_set(
+                candidates.collect(),
+                0,
+                &crate::PreparedQuery::new(query),
+                case_sensitivity,
+                10,
+                &crate::PathMatchOptions {
+                    scoring_strategy,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .map(|mat| (mat.path, mat.score, mat.positions))
+            .collect::<Vec<_>>()
+        };
+
+        for query in ["ed", "editor", "edel", "E/E", "srcrs", "dmap|elx", "ᴍ"] {
+            for case_sensitivity in [
+                CaseSensitivity::Insensitive,
+                CaseSensitivity::Smart,
+                CaseSensitivity::Sensitive,
+            ] {
+                assert_eq!(
+                    scored_matches(query, case_sensitivity, ScoringStrategy::Iterative),
+                    scored_matches(query, case_sensitivity, ScoringStrategy::Recursive),
+                    "query {query:?} scored differently with {case_sensitivity:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterative_scoring_memory() {
+        let path = Path::new("deeply/nested/").join("directory/".repeat(500) + "editor.rs");
+        let patterns = Pattern::parse_alternatives("editor");
+        let mut matcher = Matcher::new(&patterns, CaseSensitivity::Insensitive, 10);
+        matcher.set_scoring_strategy(ScoringStrategy::Iterative);
+
+        let mut results = Vec::new();
+        matcher.match_candidates(
+            &[],
+            &[],
+            std::iter::once(PathMatchCandidate {
+                is_dir: false,
+                path: &path,
+                char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                symlink_target: None,
+            }),
+            &mut results,
+            &CancellationToken::new(),
+            |candidate, score| PathMatch {
+                score,
+                worktree_id: 0,
+                positions: Vec::new(),
+                path: Arc::from(candidate.path),
+                path_prefix: "".into(),
+                distance_to_relative_ancestor: usize::MAX,
+                is_dir: false,
+                git_status: None,
+                symlink_target: None,
+            },
+        );
+
+        let file_name_start = path.to_string_lossy().len() - "editor.rs".len();
+        assert_eq!(
+            results[0].positions,
+            (file_name_start..file_name_start + 6).collect::<Vec<_>>()
+        );
+        // The memo tables of the recursive scorer are never allocated.
+        let buffers = matcher.into_buffers();
+        assert_eq!(buffers.score_matrix.capacity(), 0);
+        assert_eq!(buffers.best_position_matrix.capacity(), 0);
+    }
+
     fn match_single_path_query<'a>(
         query: &str,
-        smart_case: bool,
+        case_sensitivity: CaseSensitivity,
         paths: &[&'a str],
     ) -> Vec<(&'a str, Vec<usize>)> {
-        let lowercase_query = query.to_lowercase().chars().collect::<Vec<_>>();
-        let query = query.chars().collect::<Vec<_>>();
-        let query_chars = CharBag::from(&lowercase_query[..]);
+        let patterns = Pattern::parse_alternatives(query);
 
         let path_arcs: Vec<Arc<Path>> = paths
             .iter()
@@ -448,12 +1313,14 @@ mod tests {
                 is_dir: false,
                 char_bag,
                 path: &path_arcs[i],
+                symlink_target: None,
             });
         }
 
-        let mut matcher = Matcher::new(&query, &lowercase_query, query_chars, smart_case, 100);
+        let mut matcher = Matcher::new(&patterns, case_sensitivity, 100);
+        matcher.skip_windows_roots(true);
 
-        let cancel_flag = AtomicBool::new(false);
+        let cancel_flag = CancellationToken::new();
         let mut results = Vec::new();
 
         matcher.match_candidates(
@@ -470,6 +1337,8 @@ mod tests {
                 path_prefix: "".into(),
                 distance_to_relative_ancestor: usize::MAX,
                 is_dir: false,
+                git_status: None,
+                symlink_target: None,
             },
         );
 
@@ -1,14 +1,22 @@
+use collections::{HashMap, HashSet};
+use git::repository::GitFileStatus;
 use gpui::BackgroundExecutor;
 use std::{
     borrow::Cow,
-    cmp::{self, Ordering},
-    path::Path,
-    sync::{atomic::AtomicBool, Arc},
+    cmp::Ordering,
+    future::Future,
+    iter, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 use crate::{
-    matcher::{Match, MatchCandidate, Matcher},
-    CharBag,
+    matcher::{
+        CaseSensitivity, Match, MatchBuffers, MatchCandidate, Matcher, Pattern, ScoringStrategy,
+    },
+    CancellationToken, CharBag, SelectionHistory,
 };
 
 #[derive(Clone, Debug)]
@@ -16,10 +24,25 @@ pub struct PathMatchCandidate<'a> {
     pub is_dir: bool,
     pub path: &'a Path,
     pub char_bag: CharBag,
+    /// The path a symlink, or an entry within a symlinked directory, resolves to,
+    /// which can be matched in addition to `path` with
+    /// [`PathMatchOptions::match_symlink_targets`]. It's relative to the root the
+    /// target is found in, and isn't matched with the prefix of its candidate set.
+    pub symlink_target: Option<Cow<'a, Path>>,
+}
+
+/// A candidate together with the representation of it that is being scored.
+struct ScoredPathCandidate<'a> {
+    candidate: PathMatchCandidate<'a>,
+    char_bag: CharBag,
+    matched_symlink_target: Option<Cow<'a, Path>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct PathMatch {
+    /// How well the query matches the path, between 0 and 1, comparable across
+    /// queries. Bonuses from [`PathMatchOptions`] are included, scaled so that only a
+    /// match receiving every bonus can reach 1.
     pub score: f64,
     pub positions: Vec<usize>,
     pub worktree_id: usize,
@@ -29,17 +52,179 @@ pub struct PathMatch {
     /// Number of steps removed from a shared parent with the relative path
     /// Used to order closer paths first in the search list
     pub distance_to_relative_ancestor: usize,
+    /// The git status of the path, if known to the matcher.
+    pub git_status: Option<GitFileStatus>,
+    /// Set when the query matched the target of the symlink at `path` rather than
+    /// `path` itself, in which case `positions` refer to this target, without
+    /// `path_prefix`.
+    pub symlink_target: Option<Arc<Path>>,
+}
+
+/// Optional parameters for [`match_path_sets`] and [`match_fixed_path_set`].
+#[derive(Default)]
+pub struct PathMatchOptions<'a> {
+    /// When set, only candidates whose path satisfies this predicate are considered.
+    /// It runs before any other matching work, so it is a cheap way to exclude
+    /// generated or vendored directories without building a separate snapshot.
+    pub filter: Option<&'a (dyn Fn(&Path) -> bool + Sync)>,
+    /// Match the query against each candidate's file name first, only falling back
+    /// to the whole path if the file name doesn't match. File name matches are
+    /// ranked strictly above whole-path matches.
+    pub prioritize_file_names: bool,
+    /// Git statuses of the candidates, keyed by worktree id and then by path.
+    /// Matches with a status have their score multiplied by `1 + git_status_bonus`,
+    /// which surfaces the files touched in the current change.
+    pub git_statuses: Option<&'a HashMap<usize, HashMap<Arc<Path>, GitFileStatus>>>,
+    pub git_status_bonus: f64,
+    /// The scorer to use. [`ScoringStrategy::Iterative`] needs far less memory for
+    /// worktrees with very long paths.
+    pub scoring_strategy: ScoringStrategy,
+    /// Limits how many of the results of a search may share a
+    /// parent directory. Once a directory reaches the limit, its remaining matches
+    /// make way for lower-scoring matches from other directories.
+    pub max_results_per_directory: Option<usize>,
+    /// Also match symlinks by the path they resolve to, keeping whichever of the
+    /// two matches best. This lets shared packages that are symlinked into a
+    /// repository be found by their canonical names.
+    pub match_symlink_targets: bool,
+    /// Paths previously picked for this query, or for queries it is the beginning
+    /// of. Their scores are multiplied by `1 + selection_bonus * weight`, where the
+    /// weight goes up to 1 depending on how closely the recorded query resembles this
+    /// one. All scores are then divided by the largest factor the bonuses can give.
+    pub selection_history: Option<&'a SelectionHistory>,
+    pub selection_bonus: f64,
+    /// Matches scoring below this are dropped. As scores are comparable across
+    /// queries, this can be used to leave out matches that are too poor to be
+    /// useful. Whole-path matches score at most 0.5 when file names are prioritized.
+    pub min_score: f64,
+    /// Stops matching once this much time has passed, returning the matches found
+    /// so far. [`PathMatcher::is_partial`] tells whether that happened.
+    pub deadline: Option<Duration>,
+}
+
+impl<'a> PathMatchOptions<'a> {
+    fn includes(&self, path: &Path) -> bool {
+        self.filter.map_or(true, |filter| filter(path))
+    }
+
+    fn git_status(&self, worktree_id: usize, path: &Path) -> Option<GitFileStatus> {
+        self.git_statuses?.get(&worktree_id)?.get(path).copied()
+    }
+
+    fn scored_candidates<'b>(
+        &self,
+        candidate: PathMatchCandidate<'b>,
+    ) -> impl Iterator<Item = ScoredPathCandidate<'b>> {
+        let target = candidate
+            .symlink_target
+            .clone()
+            .filter(|_| self.match_symlink_targets)
+            .map(|target| ScoredPathCandidate {
+                candidate: candidate.clone(),
+                char_bag: CharBag::from(target.to_string_lossy().as_ref()),
+                matched_symlink_target: Some(target),
+            });
+        iter::once(ScoredPathCandidate {
+            char_bag: candidate.char_bag,
+            candidate,
+            matched_symlink_target: None,
+        })
+        .chain(target)
+    }
+
+    fn selection_weights(&self, query: &PreparedQuery) -> HashMap<(usize, &Path), f64> {
+        self.selection_history
+            .map(|history| history.weights(&query.text))
+            .unwrap_or_default()
+    }
+
+    /// How many matches to keep for returning `max_results` of them. A symlink can be
+    /// matched both by its path and by its target, which only count as one result, so
+    /// twice as many are kept for removing those duplicates.
+    fn max_matches(&self, max_results: usize) -> usize {
+        if self.match_symlink_targets {
+            max_results.saturating_mul(2)
+        } else {
+            max_results
+        }
+    }
+
+    fn max_score_bonus(&self) -> f64 {
+        let mut factor = 1.0;
+        if self.git_statuses.is_some() {
+            factor *= 1.0 + self.git_status_bonus;
+        }
+        if self.selection_history.is_some() {
+            factor *= 1.0 + self.selection_bonus;
+        }
+        factor - 1.0
+    }
+
+    /// Applies the bonuses to a score, keeping it between 0 and 1.
+    fn score_with_bonus(
+        &self,
+        score: f64,
+        git_status: Option<GitFileStatus>,
+        selection_weight: f64,
+    ) -> f64 {
+        let mut score = score * (1.0 + self.selection_bonus * selection_weight);
+        if git_status.is_some() {
+            score *= 1.0 + self.git_status_bonus;
+        }
+        score / (1.0 + self.max_score_bonus())
+    }
+}
+
+/// A path query that has been parsed into its directory scopes and fuzzy patterns,
+/// so that it can be matched against any number of candidate sets without being
+/// processed again.
+pub struct PreparedQuery {
+    text: String,
+    scopes: Vec<PathBuf>,
+    patterns: Vec<Pattern>,
+}
+
+impl PreparedQuery {
+    pub fn new(query: &str) -> Self {
+        let (scopes, parsed_query) = parse_path_scopes(query);
+        Self {
+            text: query.to_string(),
+            scopes: scopes.into_iter().map(Path::to_path_buf).collect(),
+            patterns: Pattern::parse_alternatives(&parsed_query),
+        }
+    }
+
+    /// Prepares a query that also matches with the given abbreviations expanded, such
+    /// as a project's `cfg` → `config`, keeping whichever form scores best.
+    pub fn with_abbreviations(query: &str, abbreviations: &HashMap<String, String>) -> Self {
+        let (scopes, parsed_query) = parse_path_scopes(query);
+        Self {
+            text: query.to_string(),
+            scopes: scopes.into_iter().map(Path::to_path_buf).collect(),
+            patterns: Pattern::parse_alternatives_with_abbreviations(&parsed_query, abbreviations),
+        }
+    }
 }
 
 pub trait PathMatchCandidateSet<'a>: Send + Sync {
-    type Candidates: Iterator<Item = PathMatchCandidate<'a>>;
+    type Candidates: Iterator<Item = Option<PathMatchCandidate<'a>>>;
     fn id(&self) -> usize;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
     fn prefix(&self) -> Arc<str>;
+    /// Yields the candidates from the given position on, counting every candidate
+    /// included in [`Self::len`]. The ones that shouldn't be matched are yielded as
+    /// `None` rather than skipped, so that the ranges of candidates matched in
+    /// parallel don't overlap.
     fn candidates(&'a self, start: usize) -> Self::Candidates;
+    /// How many of the candidates, from the first one, are the likeliest matches.
+    /// They're split between the threads matching in parallel before any others, so
+    /// that all of them get scored first when matching is time-boxed.
+    fn priority_len(&self) -> usize {
+        0
+    }
 }
 
 impl Match for PathMatch {
@@ -50,6 +235,10 @@ impl Match for PathMatch {
     fn set_positions(&mut self, positions: Vec<usize>) {
         self.positions = positions;
     }
+
+    fn is_in_group_of(&self, other: &Self) -> bool {
+        self.worktree_id == other.worktree_id && self.path.parent() == other.path.parent()
+    }
 }
 
 impl<'a> MatchCandidate for PathMatchCandidate<'a> {
@@ -62,6 +251,23 @@ impl<'a> MatchCandidate for PathMatchCandidate<'a> {
     }
 }
 
+impl<'a> MatchCandidate for ScoredPathCandidate<'a> {
+    fn has_chars(&self, bag: CharBag) -> bool {
+        self.char_bag.is_superset(bag)
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        self.matched_symlink_target
+            .as_deref()
+            .unwrap_or(self.candidate.path)
+            .to_string_lossy()
+    }
+
+    fn is_prefixed(&self) -> bool {
+        self.matched_symlink_target.is_none()
+    }
+}
+
 impl PartialEq for PathMatch {
     fn eq(&self, other: &Self) -> bool {
         self.cmp(other).is_eq()
@@ -91,98 +297,300 @@ impl Ord for PathMatch {
     }
 }
 
-pub fn match_fixed_path_set(
-    candidates: Vec<PathMatchCandidate>,
-    worktree_id: usize,
-    query: &str,
-    smart_case: bool,
-    max_results: usize,
-) -> Vec<PathMatch> {
-    let lowercase_query = query.to_lowercase().chars().collect::<Vec<_>>();
-    let query = query.chars().collect::<Vec<_>>();
-    let query_char_bag = CharBag::from(&lowercase_query[..]);
-
-    let mut matcher = Matcher::new(
-        &query,
-        &lowercase_query,
-        query_char_bag,
-        smart_case,
-        max_results,
-    );
-
-    let mut results = Vec::new();
-    matcher.match_candidates(
-        &[],
-        &[],
-        candidates.into_iter(),
-        &mut results,
-        &AtomicBool::new(false),
-        |candidate, score| PathMatch {
-            score,
-            worktree_id,
-            positions: Vec::new(),
-            is_dir: candidate.is_dir,
-            path: Arc::from(candidate.path),
-            path_prefix: Arc::default(),
-            distance_to_relative_ancestor: usize::MAX,
-        },
-    );
-    results
+/// Matches paths like [`match_fixed_path_set`] and [`match_path_sets`], but keeps the
+/// scratch buffers used for scoring between searches, so that searching again on
+/// every keystroke doesn't reallocate them.
+#[derive(Default)]
+pub struct PathMatcher {
+    buffers: Vec<MatchBuffers>,
+    is_partial: bool,
 }
 
-pub async fn match_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
-    candidate_sets: &'a [Set],
-    query: &str,
-    relative_to: Option<Arc<Path>>,
-    smart_case: bool,
-    max_results: usize,
-    cancel_flag: &AtomicBool,
-    executor: BackgroundExecutor,
-) -> Vec<PathMatch> {
-    let path_count: usize = candidate_sets.iter().map(|s| s.len()).sum();
-    if path_count == 0 {
-        return Vec::new();
+impl PathMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the last search ran out of time before considering every candidate,
+    /// as limited by [`PathMatchOptions::deadline`].
+    pub fn is_partial(&self) -> bool {
+        self.is_partial
     }
 
-    let lowercase_query = query.to_lowercase().chars().collect::<Vec<_>>();
-    let query = query.chars().collect::<Vec<_>>();
+    pub fn match_fixed_path_set(
+        &mut self,
+        candidates: Vec<PathMatchCandidate>,
+        worktree_id: usize,
+        query: &PreparedQuery,
+        case_sensitivity: CaseSensitivity,
+        max_results: usize,
+        options: &PathMatchOptions<'_>,
+    ) -> Vec<PathMatch> {
+        if self.buffers.is_empty() {
+            self.buffers.push(MatchBuffers::default());
+        }
+        let mut matcher = Matcher::new(
+            &query.patterns,
+            case_sensitivity,
+            options.max_matches(max_results),
+        );
+        matcher.prioritize_file_names(options.prioritize_file_names);
+        matcher.skip_windows_roots(true);
+        matcher.set_scoring_strategy(options.scoring_strategy);
+        matcher.set_min_score(options.min_score);
+        matcher.set_max_results_per_group(options.max_results_per_directory);
+        matcher.set_buffers(mem::take(&mut self.buffers[0]));
+        let selection_weights = options.selection_weights(query);
+        let cancel_flag = options
+            .deadline
+            .map(CancellationToken::with_timeout)
+            .unwrap_or_default();
 
-    let lowercase_query = &lowercase_query;
-    let query = &query;
-    let query_char_bag = CharBag::from(&lowercase_query[..]);
+        let mut results = Vec::new();
+        matcher.match_candidates(
+            &[],
+            &[],
+            candidates
+                .into_iter()
+                .filter(|candidate| {
+                    options.includes(candidate.path)
+                        && is_within_scopes(candidate.path, &query.scopes)
+                })
+                .flat_map(|candidate| options.scored_candidates(candidate)),
+            &mut results,
+            &cancel_flag,
+            |scored, score| {
+                let candidate = &scored.candidate;
+                let git_status = options.git_status(worktree_id, candidate.path);
+                let selection_weight = selection_weights
+                    .get(&(worktree_id, candidate.path))
+                    .copied()
+                    .unwrap_or(0.0);
+                PathMatch {
+                    score: options.score_with_bonus(score, git_status, selection_weight),
+                    worktree_id,
+                    positions: Vec::new(),
+                    is_dir: candidate.is_dir,
+                    path: Arc::from(candidate.path),
+                    path_prefix: Arc::default(),
+                    distance_to_relative_ancestor: usize::MAX,
+                    git_status,
+                    symlink_target: scored.matched_symlink_target.as_deref().map(Arc::from),
+                }
+            },
+        );
+        self.is_partial = matcher.was_cancelled();
+        self.buffers[0] = matcher.into_buffers();
+        if options.match_symlink_targets {
+            dedup_symlink_matches(&mut results);
+            results.truncate(max_results);
+        }
+        results
+    }
 
-    let num_cpus = executor.num_cpus().min(path_count);
-    let segment_size = (path_count + num_cpus - 1) / num_cpus;
-    let mut segment_results = (0..num_cpus)
-        .map(|_| Vec::with_capacity(max_results))
-        .collect::<Vec<_>>();
+    #[allow(clippy::too_many_arguments)]
+    pub async fn match_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
+        &mut self,
+        candidate_sets: &'a [Set],
+        query: &PreparedQuery,
+        relative_to: Option<Arc<Path>>,
+        case_sensitivity: CaseSensitivity,
+        max_results: usize,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+        executor: BackgroundExecutor,
+    ) -> Vec<PathMatch> {
+        self.search_path_sets(
+            candidate_sets,
+            query,
+            relative_to,
+            case_sensitivity,
+            max_results,
+            options,
+            cancel_flag,
+            executor,
+            None::<fn(Vec<PathMatch>)>,
+        )
+        .await
+    }
+
+    /// Matches like [`Self::match_path_sets`], but rather than stopping at the
+    /// [`PathMatchOptions::deadline`], passes the matches found by then to
+    /// `on_partial_results` and goes on matching the candidates left from where it
+    /// stopped.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn match_path_sets_with_partial_results<'a, Set: PathMatchCandidateSet<'a>>(
+        &mut self,
+        candidate_sets: &'a [Set],
+        query: &PreparedQuery,
+        relative_to: Option<Arc<Path>>,
+        case_sensitivity: CaseSensitivity,
+        max_results: usize,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+        executor: BackgroundExecutor,
+        on_partial_results: impl FnOnce(Vec<PathMatch>),
+    ) -> Vec<PathMatch> {
+        self.search_path_sets(
+            candidate_sets,
+            query,
+            relative_to,
+            case_sensitivity,
+            max_results,
+            options,
+            cancel_flag,
+            executor,
+            Some(on_partial_results),
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn search_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
+        &mut self,
+        candidate_sets: &'a [Set],
+        query: &PreparedQuery,
+        relative_to: Option<Arc<Path>>,
+        case_sensitivity: CaseSensitivity,
+        max_results: usize,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+        executor: BackgroundExecutor,
+        on_partial_results: Option<impl FnOnce(Vec<PathMatch>)>,
+    ) -> Vec<PathMatch> {
+        self.is_partial = false;
+        let path_count: usize = candidate_sets.iter().map(|s| s.len()).sum();
+        if path_count == 0 {
+            return Vec::new();
+        }
 
-    executor
-        .scoped(|scope| {
-            for (segment_idx, results) in segment_results.iter_mut().enumerate() {
-                let relative_to = relative_to.clone();
-                scope.spawn(async move {
-                    let segment_start = segment_idx * segment_size;
-                    let segment_end = segment_start + segment_size;
-                    let mut matcher = Matcher::new(
+        let deadline_flag = options
+            .deadline
+            .map(|deadline| cancel_flag.child_with_timeout(deadline));
+        let caller_cancel_flag = cancel_flag;
+
+        let max_matches = options.max_matches(max_results);
+        let num_cpus = executor.num_cpus().min(path_count);
+        let segments = split_into_segments(candidate_sets, num_cpus);
+        let mut segment_results = (0..num_cpus)
+            .map(|_| Vec::with_capacity(max_matches))
+            .collect::<Vec<_>>();
+        if self.buffers.len() < num_cpus {
+            self.buffers.resize_with(num_cpus, MatchBuffers::default);
+        }
+
+        let mut unmatched_segments = self
+            .match_segments(
+                candidate_sets,
+                segments,
+                &mut segment_results,
+                query,
+                &relative_to,
+                case_sensitivity,
+                max_matches,
+                options,
+                deadline_flag.as_ref().unwrap_or(cancel_flag),
+                &executor,
+            )
+            .await;
+        let stopped_early = unmatched_segments.iter().any(|ranges| !ranges.is_empty());
+        if stopped_early && !caller_cancel_flag.is_cancelled() {
+            if let Some(on_partial_results) = on_partial_results {
+                on_partial_results(merge_segment_results(
+                    segment_results.clone(),
+                    options,
+                    max_matches,
+                    max_results,
+                ));
+                unmatched_segments = self
+                    .match_segments(
+                        candidate_sets,
+                        unmatched_segments,
+                        &mut segment_results,
                         query,
-                        lowercase_query,
-                        query_char_bag,
-                        smart_case,
-                        max_results,
-                    );
+                        &relative_to,
+                        case_sensitivity,
+                        max_matches,
+                        options,
+                        caller_cancel_flag,
+                        &executor,
+                    )
+                    .await;
+            }
+        }
+        self.is_partial = unmatched_segments.iter().any(|ranges| !ranges.is_empty())
+            && !caller_cancel_flag.is_cancelled();
 
-                    let mut tree_start = 0;
-                    for candidate_set in candidate_sets {
-                        let tree_end = tree_start + candidate_set.len();
+        merge_segment_results(segment_results, options, max_matches, max_results)
+    }
+
+    /// Matches the candidates of each segment in parallel, adding them to the
+    /// segment's results, and returns the ranges of candidates of each segment that
+    /// were left unmatched because matching was cancelled.
+    #[allow(clippy::too_many_arguments)]
+    async fn match_segments<'a, Set: PathMatchCandidateSet<'a>>(
+        &mut self,
+        candidate_sets: &'a [Set],
+        segments: Vec<Vec<(usize, Range<usize>)>>,
+        segment_results: &mut [Vec<PathMatch>],
+        query: &PreparedQuery,
+        relative_to: &Option<Arc<Path>>,
+        case_sensitivity: CaseSensitivity,
+        max_matches: usize,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+        executor: &BackgroundExecutor,
+    ) -> Vec<Vec<(usize, Range<usize>)>> {
+        let patterns = &query.patterns;
+        let scopes = &query.scopes;
+        let selection_weights = &options.selection_weights(query);
+        let mut unmatched_segments = vec![Vec::new(); segments.len()];
+
+        executor
+            .scoped(|scope| {
+                for (((results, buffers), segment), unmatched_ranges) in segment_results
+                    .iter_mut()
+                    .zip(self.buffers.iter_mut())
+                    .zip(segments)
+                    .zip(&mut unmatched_segments)
+                {
+                    let relative_to = relative_to.clone();
+                    scope.spawn(async move {
+                        let mut matcher = Matcher::new(patterns, case_sensitivity, max_matches);
+                        matcher.prioritize_file_names(options.prioritize_file_names);
+                        matcher.skip_windows_roots(true);
+                        matcher.set_scoring_strategy(options.scoring_strategy);
+                        matcher.set_min_score(options.min_score);
+                        matcher.set_max_results_per_group(options.max_results_per_directory);
+                        matcher.set_buffers(mem::take(buffers));
 
-                        if tree_start < segment_end && segment_start < tree_end {
-                            let start = cmp::max(tree_start, segment_start) - tree_start;
-                            let end = cmp::min(tree_end, segment_end) - tree_start;
-                            let candidates = candidate_set.candidates(start).take(end - start);
+                        for (set_ix, range) in segment {
+                            if matcher.was_cancelled() {
+                                unmatched_ranges.push((set_ix, range));
+                                continue;
+                            }
+                            let candidate_set = &candidate_sets[set_ix];
+                            let path_prefix = candidate_set.prefix();
+                            let scopes = scopes
+                                .iter()
+                                .map(|scope| {
+                                    scope.strip_prefix(path_prefix.as_ref()).unwrap_or(scope)
+                                })
+                                .collect::<Vec<_>>();
+                            let mut taken_count = 0;
+                            let candidates = candidate_set
+                                .candidates(range.start)
+                                .take(range.len())
+                                .inspect(|_| taken_count += 1)
+                                .flatten()
+                                .filter(|candidate| {
+                                    options.includes(candidate.path)
+                                        && is_within_scopes(candidate.path, &scopes)
+                                })
+                                .flat_map(|candidate| options.scored_candidates(candidate));
 
                             let worktree_id = candidate_set.id();
-                            let prefix = candidate_set.prefix().chars().collect::<Vec<_>>();
+                            let prefix = path_prefix.chars().collect::<Vec<_>>();
                             let lowercase_prefix = prefix
                                 .iter()
                                 .map(|c| c.to_ascii_lowercase())
@@ -193,46 +601,576 @@ pub async fn match_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
                                 candidates,
                                 results,
                                 cancel_flag,
-                                |candidate, score| PathMatch {
-                                    score,
-                                    worktree_id,
-                                    positions: Vec::new(),
-                                    path: Arc::from(candidate.path),
-                                    is_dir: candidate.is_dir,
-                                    path_prefix: candidate_set.prefix(),
-                                    distance_to_relative_ancestor: relative_to.as_ref().map_or(
-                                        usize::MAX,
-                                        |relative_to| {
-                                            distance_between_paths(
-                                                candidate.path,
-                                                relative_to.as_ref(),
-                                            )
-                                        },
-                                    ),
+                                |scored, score| {
+                                    let candidate = &scored.candidate;
+                                    let git_status =
+                                        options.git_status(worktree_id, candidate.path);
+                                    let selection_weight = selection_weights
+                                        .get(&(worktree_id, candidate.path))
+                                        .copied()
+                                        .unwrap_or(0.0);
+                                    PathMatch {
+                                        score: options.score_with_bonus(
+                                            score,
+                                            git_status,
+                                            selection_weight,
+                                        ),
+                                        worktree_id,
+                                        positions: Vec::new(),
+                                        path: Arc::from(candidate.path),
+                                        is_dir: candidate.is_dir,
+                                        path_prefix: candidate_set.prefix(),
+                                        distance_to_relative_ancestor: relative_to.as_ref().map_or(
+                                            usize::MAX,
+                                            |relative_to| {
+                                                distance_between_paths(
+                                                    candidate.path,
+                                                    relative_to.as_ref(),
+                                                )
+                                            },
+                                        ),
+                                        git_status,
+                                        symlink_target: scored
+                                            .matched_symlink_target
+                                            .as_deref()
+                                            .map(Arc::from),
+                                    }
                                 },
                             );
+                            if matcher.was_cancelled() {
+                                // The candidate the matcher stopped at wasn't scored. If it
+                                // was partly scored, by its symlink target, scoring it again
+                                // gives a match equal to the one already in the results,
+                                // which isn't added twice.
+                                let start = range.start + taken_count.saturating_sub(1);
+                                unmatched_ranges.push((set_ix, start..range.end));
+                            }
                         }
-                        if tree_end >= segment_end {
-                            break;
-                        }
-                        tree_start = tree_end;
-                    }
+                        *buffers = matcher.into_buffers();
+                    })
+                }
+            })
+            .await;
+        unmatched_segments
+    }
+
+    /// Matches recently opened paths together with the candidate sets, ranking both
+    /// with the same scorer. Recent paths are scored as if they were in the candidate
+    /// set of their worktree, and shadow the worktree matches of the same paths.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn match_recent_and_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
+        &mut self,
+        recent_paths: &[RecentPath],
+        candidate_sets: &'a [Set],
+        query: &PreparedQuery,
+        relative_to: Option<Arc<Path>>,
+        case_sensitivity: CaseSensitivity,
+        max_results: usize,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+        executor: BackgroundExecutor,
+    ) -> Vec<RankedPathMatch> {
+        let prefixes = candidate_sets
+            .iter()
+            .map(|set| (set.id(), set.prefix()))
+            .collect::<HashMap<_, _>>();
+        let recent_matches = self.match_recent_paths(
+            recent_paths,
+            &prefixes,
+            query,
+            relative_to.as_deref(),
+            case_sensitivity,
+            options,
+            cancel_flag,
+        );
+        let path_matches = self
+            .match_path_sets(
+                candidate_sets,
+                query,
+                relative_to,
+                case_sensitivity,
+                max_results + recent_matches.len(),
+                options,
+                cancel_flag,
+                executor,
+            )
+            .await;
+        rank_recent_and_path_matches(recent_paths, recent_matches, path_matches, max_results)
+    }
+
+    /// Scores recent paths like the candidates of [`Self::match_path_sets`], with the
+    /// prefix of the candidate set their worktree belongs to.
+    #[allow(clippy::too_many_arguments)]
+    fn match_recent_paths(
+        &mut self,
+        recent_paths: &[RecentPath],
+        prefixes: &HashMap<usize, Arc<str>>,
+        query: &PreparedQuery,
+        relative_to: Option<&Path>,
+        case_sensitivity: CaseSensitivity,
+        options: &PathMatchOptions<'_>,
+        cancel_flag: &CancellationToken,
+    ) -> Vec<PathMatch> {
+        if self.buffers.is_empty() {
+            self.buffers.push(MatchBuffers::default());
+        }
+        let mut matcher = Matcher::new(&query.patterns, case_sensitivity, recent_paths.len());
+        matcher.prioritize_file_names(options.prioritize_file_names);
+        matcher.skip_windows_roots(true);
+        matcher.set_scoring_strategy(options.scoring_strategy);
+        matcher.set_min_score(options.min_score);
+        matcher.set_buffers(mem::take(&mut self.buffers[0]));
+        let selection_weights = options.selection_weights(query);
+
+        let mut worktree_ids = recent_paths
+            .iter()
+            .map(|recent_path| recent_path.worktree_id)
+            .collect::<Vec<_>>();
+        worktree_ids.sort_unstable();
+        worktree_ids.dedup();
+
+        let mut results = Vec::new();
+        for worktree_id in worktree_ids {
+            let path_prefix = prefixes.get(&worktree_id).cloned().unwrap_or_default();
+            let scopes = query
+                .scopes
+                .iter()
+                .map(|scope| scope.strip_prefix(path_prefix.as_ref()).unwrap_or(scope))
+                .collect::<Vec<_>>();
+            let candidates = recent_paths
+                .iter()
+                .filter(|recent_path| recent_path.worktree_id == worktree_id)
+                .map(|recent_path| PathMatchCandidate {
+                    is_dir: false,
+                    path: &recent_path.path,
+                    char_bag: CharBag::from(recent_path.path.to_string_lossy().as_ref()),
+                    symlink_target: None,
                 })
-            }
+                .filter(|candidate| {
+                    options.includes(candidate.path) && is_within_scopes(candidate.path, &scopes)
+                })
+                .flat_map(|candidate| options.scored_candidates(candidate));
+
+            let prefix = path_prefix.chars().collect::<Vec<_>>();
+            let lowercase_prefix = prefix
+                .iter()
+                .map(|c| c.to_ascii_lowercase())
+                .collect::<Vec<_>>();
+            matcher.match_candidates(
+                &prefix,
+                &lowercase_prefix,
+                candidates,
+                &mut results,
+                cancel_flag,
+                |scored, score| {
+                    let candidate = &scored.candidate;
+                    let git_status = options.git_status(worktree_id, candidate.path);
+                    let selection_weight = selection_weights
+                        .get(&(worktree_id, candidate.path))
+                        .copied()
+                        .unwrap_or(0.0);
+                    PathMatch {
+                        score: options.score_with_bonus(score, git_status, selection_weight),
+                        worktree_id,
+                        positions: Vec::new(),
+                        path: Arc::from(candidate.path),
+                        is_dir: candidate.is_dir,
+                        path_prefix: path_prefix.clone(),
+                        distance_to_relative_ancestor: relative_to
+                            .map_or(usize::MAX, |relative_to| {
+                                distance_between_paths(candidate.path, relative_to)
+                            }),
+                        git_status,
+                        symlink_target: None,
+                    }
+                },
+            );
+        }
+        self.buffers[0] = matcher.into_buffers();
+        results
+    }
+}
+
+pub fn match_fixed_path_set(
+    candidates: Vec<PathMatchCandidate>,
+    worktree_id: usize,
+    query: &PreparedQuery,
+    case_sensitivity: CaseSensitivity,
+    max_results: usize,
+    options: &PathMatchOptions<'_>,
+) -> Vec<PathMatch> {
+    PathMatcher::new().match_fixed_path_set(
+        candidates,
+        worktree_id,
+        query,
+        case_sensitivity,
+        max_results,
+        options,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn match_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
+    candidate_sets: &'a [Set],
+    query: &PreparedQuery,
+    relative_to: Option<Arc<Path>>,
+    case_sensitivity: CaseSensitivity,
+    max_results: usize,
+    options: &PathMatchOptions<'_>,
+    cancel_flag: &CancellationToken,
+    executor: BackgroundExecutor,
+) -> Vec<PathMatch> {
+    PathMatcher::new()
+        .match_path_sets(
+            candidate_sets,
+            query,
+            relative_to,
+            case_sensitivity,
+            max_results,
+            options,
+            cancel_flag,
+            executor,
+        )
+        .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn match_recent_and_path_sets<'a, Set: PathMatchCandidateSet<'a>>(
+    recent_paths: &[RecentPath],
+    candidate_sets: &'a [Set],
+    query: &PreparedQuery,
+    relative_to: Option<Arc<Path>>,
+    case_sensitivity: CaseSensitivity,
+    max_results: usize,
+    options: &PathMatchOptions<'_>,
+    cancel_flag: &CancellationToken,
+    executor: BackgroundExecutor,
+) -> Vec<RankedPathMatch> {
+    PathMatcher::new()
+        .match_recent_and_path_sets(
+            recent_paths,
+            candidate_sets,
+            query,
+            relative_to,
+            case_sensitivity,
+            max_results,
+            options,
+            cancel_flag,
+            executor,
+        )
+        .await
+}
+
+/// A recently opened path, such as the path of an open buffer, for ranking together
+/// with worktree paths in [`match_recent_and_path_sets`].
+#[derive(Clone, Debug)]
+pub struct RecentPath {
+    pub worktree_id: usize,
+    pub path: Arc<Path>,
+}
+
+/// Where a [`RankedPathMatch`] was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathMatchSource {
+    /// One of the recent paths, along with its position among them, starting from
+    /// the most recent one.
+    Recent(usize),
+    /// One of the candidate sets.
+    Worktree,
+}
+
+#[derive(Clone, Debug)]
+pub struct RankedPathMatch {
+    pub path_match: PathMatch,
+    pub source: PathMatchSource,
+}
+
+/// Merges the matches of recent paths with those of the candidate sets, best first.
+/// Equal scores are ordered by recency, with recent paths ahead of the others.
+fn rank_recent_and_path_matches(
+    recent_paths: &[RecentPath],
+    recent_matches: Vec<PathMatch>,
+    path_matches: Vec<PathMatch>,
+    max_results: usize,
+) -> Vec<RankedPathMatch> {
+    let mut recency = HashMap::default();
+    for (ix, recent_path) in recent_paths.iter().enumerate() {
+        recency
+            .entry((recent_path.worktree_id, recent_path.path.as_ref()))
+            .or_insert(ix);
+    }
+
+    let mut results = recent_matches
+        .into_iter()
+        .map(|path_match| RankedPathMatch {
+            source: PathMatchSource::Recent(
+                recency[&(path_match.worktree_id, path_match.path.as_ref())],
+            ),
+            path_match,
         })
+        .collect::<Vec<_>>();
+    results.extend(
+        path_matches
+            .into_iter()
+            .filter(|path_match| {
+                !recency.contains_key(&(path_match.worktree_id, path_match.path.as_ref()))
+            })
+            .map(|path_match| RankedPathMatch {
+                path_match,
+                source: PathMatchSource::Worktree,
+            }),
+    );
+    results.sort_by(|a, b| {
+        b.path_match
+            .score
+            .partial_cmp(&a.path_match.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.source.cmp(&b.source))
+            .then_with(|| b.path_match.cmp(&a.path_match))
+    });
+    results.truncate(max_results);
+    results
+}
+
+/// How many occurrences of a [`ContentQuery`] needle still raise a file's score.
+const MAX_COUNTED_OCCURRENCES: usize = 16;
+
+/// A literal string that files matching a path query must contain, for finding files
+/// by both their path and their contents.
+pub struct ContentQuery {
+    needle: String,
+    case_sensitive: bool,
+    max_candidates: usize,
+}
+
+impl ContentQuery {
+    /// Only the best `max_candidates` path matches have their contents checked, which
+    /// bounds the number of files loaded for a single query.
+    pub fn new(needle: &str, case_sensitivity: CaseSensitivity, max_candidates: usize) -> Self {
+        let case_sensitive = match case_sensitivity {
+            CaseSensitivity::Insensitive => false,
+            CaseSensitivity::Smart => needle.chars().any(char::is_uppercase),
+            CaseSensitivity::Sensitive => true,
+        };
+        Self {
+            needle: if case_sensitive {
+                needle.to_string()
+            } else {
+                needle.to_lowercase()
+            },
+            case_sensitive,
+            max_candidates,
+        }
+    }
+
+    /// Scores contents between 0 and 1 by how often they contain the needle, or returns
+    /// `None` if they don't contain it at all. Each occurrence halves the distance to 1.
+    fn score(&self, contents: &str) -> Option<f64> {
+        let contents = if self.case_sensitive {
+            Cow::Borrowed(contents)
+        } else {
+            Cow::Owned(contents.to_lowercase())
+        };
+        let occurrences = contents
+            .matches(self.needle.as_str())
+            .take(MAX_COUNTED_OCCURRENCES)
+            .count();
+        if occurrences == 0 {
+            None
+        } else {
+            Some(1.0 - 0.5_f64.powi(occurrences as i32))
+        }
+    }
+}
+
+/// Matches paths like [`match_path_sets`], then narrows the matches down to the files
+/// containing the needle of `content_query`, if any. Their scores are multiplied by
+/// [`ContentQuery`]'s score of their contents, which are loaded with `load_contents`
+/// one file at a time until the cancellation token is cancelled.
+#[allow(clippy::too_many_arguments)]
+pub async fn match_path_sets_with_contents<'a, Set, F, Fut>(
+    candidate_sets: &'a [Set],
+    query: &PreparedQuery,
+    content_query: Option<&ContentQuery>,
+    relative_to: Option<Arc<Path>>,
+    case_sensitivity: CaseSensitivity,
+    max_results: usize,
+    options: &PathMatchOptions<'_>,
+    cancel_flag: &CancellationToken,
+    executor: BackgroundExecutor,
+    load_contents: F,
+) -> Vec<PathMatch>
+where
+    Set: PathMatchCandidateSet<'a>,
+    F: Fn(&PathMatch) -> Fut,
+    Fut: Future<Output = Option<String>>,
+{
+    let Some(content_query) = content_query.filter(|query| !query.needle.is_empty()) else {
+        return match_path_sets(
+            candidate_sets,
+            query,
+            relative_to,
+            case_sensitivity,
+            max_results,
+            options,
+            cancel_flag,
+            executor,
+        )
         .await;
+    };
+
+    let path_matches = match_path_sets(
+        candidate_sets,
+        query,
+        relative_to,
+        case_sensitivity,
+        max_results.max(content_query.max_candidates),
+        options,
+        cancel_flag,
+        executor,
+    )
+    .await;
+
+    let mut results = Vec::new();
+    for path_match in path_matches {
+        if cancel_flag.is_cancelled() {
+            break;
+        }
+        if path_match.is_dir {
+            continue;
+        }
+        let Some(contents) = load_contents(&path_match).await else {
+            continue;
+        };
+        if let Some(content_score) = content_query.score(&contents) {
+            results.push(PathMatch {
+                score: path_match.score * content_score,
+                ..path_match
+            });
+        }
+    }
+    results.sort_unstable_by(|a, b| b.cmp(a));
+    results.truncate(max_results);
+    results
+}
 
+/// Merges the matches of every segment into the best `max_results` of them.
+fn merge_segment_results(
+    segment_results: Vec<Vec<PathMatch>>,
+    options: &PathMatchOptions<'_>,
+    max_matches: usize,
+    max_results: usize,
+) -> Vec<PathMatch> {
+    // Keep every segment's matches when capping directories, so that there are
+    // matches from other directories to fill the freed slots with, as segments only
+    // cap the directories among their own matches.
+    let merged_len = if options.max_results_per_directory.is_some() {
+        usize::MAX
+    } else {
+        max_matches
+    };
     let mut results = Vec::new();
     for segment_result in segment_results {
         if results.is_empty() {
             results = segment_result;
         } else {
-            util::extend_sorted(&mut results, segment_result, max_results, |a, b| b.cmp(a));
+            util::extend_sorted(&mut results, segment_result, merged_len, |a, b| b.cmp(a));
         }
     }
+    if options.match_symlink_targets {
+        dedup_symlink_matches(&mut results);
+    }
+    if let Some(max_per_directory) = options.max_results_per_directory {
+        cap_results_per_directory(&mut results, max_per_directory);
+    }
+    results.truncate(max_results);
     results
 }
 
+/// Splits the candidates of the sets into the given number of segments of about the
+/// same size, each made of ranges of the candidates of a set, identified by its index.
+/// The prioritized candidates of every set are split first, so that each segment
+/// starts with its share of them.
+fn split_into_segments<'a, Set: PathMatchCandidateSet<'a>>(
+    candidate_sets: &[Set],
+    segment_count: usize,
+) -> Vec<Vec<(usize, Range<usize>)>> {
+    let prioritized = candidate_sets
+        .iter()
+        .map(|set| 0..set.priority_len().min(set.len()))
+        .collect::<Vec<_>>();
+    let rest = candidate_sets
+        .iter()
+        .zip(&prioritized)
+        .map(|(set, prioritized)| prioritized.end..set.len())
+        .collect::<Vec<_>>();
+
+    let mut segments = vec![Vec::new(); segment_count];
+    for ranges in [prioritized, rest] {
+        let count = ranges.iter().map(|range| range.len()).sum::<usize>();
+        let segment_size = (count + segment_count - 1) / segment_count;
+        let mut position = 0;
+        for (set_ix, range) in ranges.into_iter().enumerate() {
+            let mut start = range.start;
+            while start < range.end {
+                let segment_ix = position / segment_size;
+                let end = range
+                    .end
+                    .min(start + (segment_ix + 1) * segment_size - position);
+                segments[segment_ix].push((set_ix, start..end));
+                position += end - start;
+                start = end;
+            }
+        }
+    }
+    segments
+}
+
+/// Symlinks can match both by their path and by their target, in which case only
+/// the better of the two matches is kept.
+fn dedup_symlink_matches(results: &mut Vec<PathMatch>) {
+    let mut seen = HashSet::default();
+    results.retain(|result| seen.insert((result.worktree_id, result.path.clone())));
+}
+
+/// Drops matches, in order, from directories that already have `max_per_directory`
+/// matches ahead of them.
+fn cap_results_per_directory(results: &mut Vec<PathMatch>, max_per_directory: usize) {
+    let mut counts = HashMap::<(usize, Option<&Path>), usize>::default();
+    let mut keep = Vec::with_capacity(results.len());
+    for result in results.iter() {
+        let count = counts
+            .entry((result.worktree_id, result.path.parent()))
+            .or_default();
+        *count += 1;
+        keep.push(*count <= max_per_directory);
+    }
+    let mut keep = keep.into_iter();
+    results.retain(|_| keep.next().unwrap());
+}
+
+/// Splits directory scopes off a path query.
+///
+/// In a query such as `src/ button`, every whitespace-separated token ending in a
+/// path separator restricts the candidates to paths under that directory, and the
+/// remaining tokens form the fuzzy query. A query consisting only of such tokens
+/// is returned unchanged, so that typing `src/` still fuzzy-matches as before.
+fn parse_path_scopes(query: &str) -> (Vec<&Path>, Cow<'_, str>) {
+    let (scopes, rest): (Vec<&str>, Vec<&str>) = query
+        .split_whitespace()
+        .partition(|token| token.ends_with('/') || token.ends_with('\\'));
+    if scopes.is_empty() || rest.is_empty() {
+        return (Vec::new(), Cow::Borrowed(query));
+    }
+
+    let scopes = scopes.into_iter().map(Path::new).collect();
+    (scopes, Cow::Owned(rest.join(" ")))
+}
+
+fn is_within_scopes(path: &Path, scopes: &[impl AsRef<Path>]) -> bool {
+    scopes.is_empty() || scopes.iter().any(|scope| path.starts_with(scope.as_ref()))
+}
+
 /// Compute the distance from a given path to some other path
 /// If there is no shared path, returns usize::MAX
 fn distance_between_paths(path: &Path, relative_to: &Path) -> usize {
@@ -252,10 +1190,881 @@ fn distance_between_paths(path: &Path, relative_to: &Path) -> usize {
 mod tests {
     use std::path::Path;
 
-    use super::distance_between_paths;
+    use super::*;
 
     #[test]
     fn test_distance_between_paths_empty() {
         distance_between_paths(Path::new(""), Path::new(""));
     }
+
+    #[test]
+    fn test_match_path_scopes() {
+        let paths = [
+            "src/button.rs",
+            "src/widgets/button.rs",
+            "tests/button.rs",
+            "docs/src/button.md",
+        ]
+        .map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let matched_paths = |query| {
+            let mut paths = match_fixed_path_set(
+                candidates(),
+                0,
+                &PreparedQuery::new(query),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions::default(),
+            )
+            .into_iter()
+            .map(|path_match| path_match.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            matched_paths("src/ button"),
+            ["src/button.rs", "src/widgets/button.rs"]
+        );
+        assert_eq!(
+            matched_paths("src/widgets/ tests/ button"),
+            ["src/widgets/button.rs", "tests/button.rs"]
+        );
+        // Without a fuzzy query, the scope itself is matched fuzzily.
+        assert_eq!(
+            matched_paths("src/"),
+            [
+                "docs/src/button.md",
+                "src/button.rs",
+                "src/widgets/button.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_path_filter() {
+        let paths = ["src/lib.rs", "target/debug/lib.rs", "vendor/lib.rs"].map(Path::new);
+        let candidates = paths
+            .iter()
+            .map(|path| PathMatchCandidate {
+                is_dir: false,
+                path,
+                char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                symlink_target: None,
+            })
+            .collect::<Vec<_>>();
+        let filter = |path: &Path| !path.starts_with("target") && !path.starts_with("vendor");
+
+        let matches = match_fixed_path_set(
+            candidates,
+            0,
+            &PreparedQuery::new("lib"),
+            CaseSensitivity::Insensitive,
+            10,
+            &PathMatchOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            matches
+                .iter()
+                .map(|path_match| path_match.path.as_ref())
+                .collect::<Vec<_>>(),
+            [Path::new("src/lib.rs")]
+        );
+    }
+
+    #[test]
+    fn test_match_prioritizing_file_names() {
+        let paths = [
+            "crates/lib/src/main.rs",
+            "crates/util/src/library_helpers.rs",
+        ]
+        .map(Path::new);
+        let matches = |prioritize_file_names| {
+            let candidates = paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect();
+            match_fixed_path_set(
+                candidates,
+                0,
+                &PreparedQuery::new("lib"),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    prioritize_file_names,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .map(|path_match| {
+                (
+                    path_match.path.to_string_lossy().into_owned(),
+                    path_match.positions,
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+
+        // Short directory names dominate when matching whole paths...
+        assert_eq!(
+            matches(false),
+            [
+                ("crates/lib/src/main.rs".to_string(), vec![7, 8, 9]),
+                (
+                    "crates/util/src/library_helpers.rs".to_string(),
+                    vec![16, 17, 18]
+                ),
+            ]
+        );
+        // ...but file names rank first when they are prioritized.
+        assert_eq!(
+            matches(true),
+            [
+                (
+                    "crates/util/src/library_helpers.rs".to_string(),
+                    vec![16, 17, 18]
+                ),
+                ("crates/lib/src/main.rs".to_string(), vec![7, 8, 9]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_with_deadline() {
+        let paths = ["src/main.rs", "src/lib.rs"].map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut matcher = PathMatcher::new();
+        let mut match_count = |deadline| {
+            let matches = matcher.match_fixed_path_set(
+                candidates(),
+                0,
+                &PreparedQuery::new("src"),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    deadline,
+                    ..Default::default()
+                },
+            );
+            (matches.len(), matcher.is_partial())
+        };
+
+        assert_eq!(match_count(Some(Duration::from_secs(60))), (2, false));
+        assert_eq!(match_count(Some(Duration::ZERO)), (0, true));
+        assert_eq!(match_count(None), (2, false));
+    }
+
+    #[test]
+    fn test_rank_recent_and_path_matches() {
+        let recent_paths = [(0, "src/main.rs"), (1, "src/main.rs"), (0, "src/lib.rs")].map(
+            |(worktree_id, path)| RecentPath {
+                worktree_id,
+                path: Arc::from(Path::new(path)),
+            },
+        );
+        let paths = ["src/main.rs", "src/lib.rs", "src/lib/main.rs"].map(Path::new);
+        let candidates = paths
+            .iter()
+            .map(|path| PathMatchCandidate {
+                is_dir: false,
+                path,
+                char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                symlink_target: None,
+            })
+            .collect::<Vec<_>>();
+        let query = PreparedQuery::new("main");
+        let options = PathMatchOptions::default();
+        let mut matcher = PathMatcher::new();
+        let recent_matches = matcher.match_recent_paths(
+            &recent_paths,
+            &HashMap::default(),
+            &query,
+            None,
+            CaseSensitivity::Insensitive,
+            &options,
+            &CancellationToken::default(),
+        );
+        let path_matches = matcher.match_fixed_path_set(
+            candidates,
+            0,
+            &query,
+            CaseSensitivity::Insensitive,
+            10,
+            &options,
+        );
+
+        let ranked = rank_recent_and_path_matches(&recent_paths, recent_matches, path_matches, 10)
+            .into_iter()
+            .map(|ranked| {
+                (
+                    ranked.path_match.worktree_id,
+                    ranked.path_match.path.to_string_lossy().into_owned(),
+                    ranked.source,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            [
+                (0, "src/main.rs".to_string(), PathMatchSource::Recent(0)),
+                (1, "src/main.rs".to_string(), PathMatchSource::Recent(1)),
+                (0, "src/lib/main.rs".to_string(), PathMatchSource::Worktree),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_score_scale() {
+        let paths = [
+            "src/main.rs",
+            "src/domain/mapping.rs",
+            "docs/manual/index.md",
+        ]
+        .map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let git_statuses = HashMap::from_iter([(
+            0,
+            HashMap::from_iter([(Arc::from(paths[0]), GitFileStatus::Added)]),
+        )]);
+        let scores = |query: &str, min_score: f64| {
+            match_fixed_path_set(
+                candidates(),
+                0,
+                &PreparedQuery::new(query),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    git_statuses: Some(&git_statuses),
+                    git_status_bonus: 2.0,
+                    min_score,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .map(|m| (m.path.to_string_lossy().into_owned(), m.score))
+            .collect::<Vec<_>>()
+        };
+
+        // Only an exact match of the whole path that also receives every bonus scores 1.
+        let exact = scores("src/main.rs", 0.0);
+        assert_eq!(exact[0], ("src/main.rs".to_string(), 1.0));
+        for query in ["main.rs", "mars", "m", "src/main.rs"] {
+            assert!(scores(query, 0.0)
+                .iter()
+                .all(|(_, score)| *score > 0.0 && *score <= 1.0));
+        }
+
+        // Scores are comparable across queries, so the same threshold drops the poor
+        // matches of both a short and a long query.
+        assert_eq!(scores("m", 0.0).len(), 3);
+        assert_eq!(scores("m", 0.1), [("src/main.rs".to_string(), 0.1125)]);
+        assert_eq!(scores("main.rs", 0.0).len(), 2);
+        assert_eq!(
+            scores("main.rs", 0.1),
+            [("src/main.rs".to_string(), 0.7875)]
+        );
+    }
+
+    #[test]
+    fn test_content_query_score() {
+        let contents =
+            "fn render(cx: &mut WindowContext) {\n    cx.notify();\n    cx.notify();\n}\n";
+
+        let query = ContentQuery::new("notify", CaseSensitivity::Insensitive, 10);
+        assert_eq!(query.score(contents), Some(0.75));
+        assert_eq!(query.score("cx.notify_all()"), Some(0.5));
+        assert_eq!(query.score("fn main() {}"), None);
+
+        let query = ContentQuery::new("windowcontext", CaseSensitivity::Smart, 10);
+        assert_eq!(query.score(contents), Some(0.5));
+        let query = ContentQuery::new("windowContext", CaseSensitivity::Smart, 10);
+        assert_eq!(query.score(contents), None);
+
+        let query = ContentQuery::new("cx", CaseSensitivity::Sensitive, 10);
+        let many = "cx ".repeat(100);
+        assert_eq!(
+            query.score(&many),
+            Some(1.0 - 0.5_f64.powi(MAX_COUNTED_OCCURRENCES as i32))
+        );
+    }
+
+    #[test]
+    fn test_match_with_git_status_bonus() {
+        let paths = ["src/editor.rs", "src/editor_tests.rs"].map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let git_statuses = HashMap::from_iter([(
+            0,
+            HashMap::from_iter([(Arc::from(paths[1]), GitFileStatus::Modified)]),
+        )]);
+
+        let matches = match_fixed_path_set(
+            candidates(),
+            0,
+            &PreparedQuery::new("editor"),
+            CaseSensitivity::Insensitive,
+            10,
+            &PathMatchOptions::default(),
+        );
+        assert_eq!(matches[0].path.as_ref(), paths[0]);
+        assert_eq!(matches[0].git_status, None);
+
+        let matches = match_fixed_path_set(
+            candidates(),
+            0,
+            &PreparedQuery::new("editor"),
+            CaseSensitivity::Insensitive,
+            10,
+            &PathMatchOptions {
+                git_statuses: Some(&git_statuses),
+                git_status_bonus: 1.0,
+                ..Default::default()
+            },
+        );
+        assert_eq!(matches[0].path.as_ref(), paths[1]);
+        assert_eq!(matches[0].git_status, Some(GitFileStatus::Modified));
+        assert_eq!(matches[1].git_status, None);
+    }
+
+    #[test]
+    fn test_match_with_selection_history() {
+        let paths = ["src/editor.rs", "src/editor_tests.rs"].map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut history = SelectionHistory::new();
+        history.record("editor_t", 0, Arc::from(paths[1]));
+        let matches = |query: &str| {
+            match_fixed_path_set(
+                candidates(),
+                0,
+                &PreparedQuery::new(query),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    selection_history: Some(&history),
+                    selection_bonus: 2.0,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .map(|m| m.path)
+            .collect::<Vec<_>>()
+        };
+
+        // The picked path is boosted for the recorded query and the queries leading up to it...
+        assert_eq!(matches("editor")[0].as_ref(), paths[1]);
+        assert_eq!(matches("editor_t")[0].as_ref(), paths[1]);
+        // ...but not for unrelated queries.
+        assert_eq!(matches("edrs")[0].as_ref(), paths[0]);
+    }
+
+    #[test]
+    fn test_match_with_abbreviations() {
+        let paths = ["src/cf_gen.rs", "src/config.rs", "deploy/kubernetes.yaml"].map(Path::new);
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let matched_paths = |query: &PreparedQuery| {
+            match_fixed_path_set(
+                candidates(),
+                0,
+                query,
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions::default(),
+            )
+            .into_iter()
+            .map(|path_match| path_match.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+        };
+        let abbreviations = HashMap::from_iter([
+            ("cfg".to_string(), "config".to_string()),
+            ("k8s".to_string(), "kubernetes".to_string()),
+        ]);
+
+        assert_eq!(
+            matched_paths(&PreparedQuery::new("cfg")),
+            ["src/cf_gen.rs", "src/config.rs"]
+        );
+        assert_eq!(
+            matched_paths(&PreparedQuery::with_abbreviations("cfg", &abbreviations)),
+            ["src/config.rs", "src/cf_gen.rs"]
+        );
+        assert!(matched_paths(&PreparedQuery::new("K8s")).is_empty());
+        assert_eq!(
+            matched_paths(&PreparedQuery::with_abbreviations("K8s", &abbreviations)),
+            ["deploy/kubernetes.yaml"]
+        );
+    }
+
+    #[test]
+    fn test_cap_results_per_directory() {
+        let path_match = |worktree_id, path: &str, score| PathMatch {
+            score,
+            positions: Vec::new(),
+            worktree_id,
+            path: Arc::from(Path::new(path)),
+            path_prefix: Arc::default(),
+            is_dir: false,
+            distance_to_relative_ancestor: usize::MAX,
+            git_status: None,
+            symlink_target: None,
+        };
+        let mut results = vec![
+            path_match(0, "proto/a.rs", 0.9),
+            path_match(0, "proto/b.rs", 0.8),
+            path_match(0, "proto/c.rs", 0.7),
+            path_match(1, "proto/d.rs", 0.6),
+            path_match(0, "src/e.rs", 0.5),
+            path_match(0, "proto/nested/f.rs", 0.4),
+            path_match(0, "proto/g.rs", 0.3),
+        ];
+
+        cap_results_per_directory(&mut results, 2);
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+            [
+                "proto/a.rs",
+                "proto/b.rs",
+                "proto/d.rs",
+                "src/e.rs",
+                "proto/nested/f.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cap_results_per_directory_while_matching() {
+        let paths = [
+            "proto/widget_a.rs",
+            "proto/widget_b.rs",
+            "proto/widget_c.rs",
+            "tests/widget.rs",
+        ]
+        .map(Path::new);
+        let candidates = paths
+            .iter()
+            .map(|path| PathMatchCandidate {
+                is_dir: false,
+                path,
+                char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                symlink_target: None,
+            })
+            .collect::<Vec<_>>();
+
+        // The directory holding more matches than are returned doesn't crowd out the
+        // others before the cap applies.
+        let matches = match_fixed_path_set(
+            candidates,
+            0,
+            &PreparedQuery::new("widget"),
+            CaseSensitivity::Insensitive,
+            2,
+            &PathMatchOptions {
+                max_results_per_directory: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut directories = matches
+            .iter()
+            .map(|path_match| path_match.path.parent().unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        directories.sort();
+        assert_eq!(directories, [Path::new("proto"), Path::new("tests")]);
+    }
+
+    #[test]
+    fn test_match_symlink_targets() {
+        let candidates = || {
+            vec![
+                PathMatchCandidate {
+                    is_dir: false,
+                    path: Path::new("vendor/ui/button.tsx"),
+                    char_bag: CharBag::from("vendor/ui/button.tsx"),
+                    symlink_target: Some(Path::new("shared/design_system/button.tsx").into()),
+                },
+                PathMatchCandidate {
+                    is_dir: false,
+                    path: Path::new("src/design/button.tsx"),
+                    char_bag: CharBag::from("src/design/button.tsx"),
+                    symlink_target: None,
+                },
+            ]
+        };
+        let matches = |query, match_symlink_targets| {
+            match_fixed_path_set(
+                candidates(),
+                0,
+                &PreparedQuery::new(query),
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    match_symlink_targets,
+                    ..Default::default()
+                },
+            )
+            .into_iter()
+            .map(|path_match| {
+                (
+                    path_match.path.to_string_lossy().into_owned(),
+                    path_match.symlink_target,
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+
+        assert!(matches("design_system", false).is_empty());
+        assert_eq!(
+            matches("design_system", true),
+            [(
+                "vendor/ui/button.tsx".to_string(),
+                Some(Arc::from(Path::new("shared/design_system/button.tsx")))
+            )]
+        );
+        // A symlink matching by both its path and its target is only returned once.
+        assert_eq!(matches("button", true).len(), 2);
+    }
+
+    #[test]
+    fn test_match_symlink_targets_fills_results() {
+        let candidates = vec![
+            PathMatchCandidate {
+                is_dir: false,
+                path: Path::new("button.rs"),
+                char_bag: CharBag::from("button.rs"),
+                symlink_target: Some(Path::new("ui/button.rs").into()),
+            },
+            PathMatchCandidate {
+                is_dir: false,
+                path: Path::new("docs/old/widgets/bu_tt_on.md"),
+                char_bag: CharBag::from("docs/old/widgets/bu_tt_on.md"),
+                symlink_target: None,
+            },
+        ];
+
+        // The symlink outscores the other file by both its path and its target, and
+        // still only takes up one of the results.
+        let matched_paths = match_fixed_path_set(
+            candidates,
+            0,
+            &PreparedQuery::new("button"),
+            CaseSensitivity::Insensitive,
+            2,
+            &PathMatchOptions {
+                match_symlink_targets: true,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .map(|path_match| path_match.path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+        assert_eq!(matched_paths, ["button.rs", "docs/old/widgets/bu_tt_on.md"]);
+    }
+
+    struct PathCandidateSet {
+        paths: Vec<&'static Path>,
+    }
+
+    impl<'a> PathMatchCandidateSet<'a> for PathCandidateSet {
+        type Candidates = Box<dyn Iterator<Item = Option<PathMatchCandidate<'a>>> + 'a>;
+
+        fn id(&self) -> usize {
+            0
+        }
+
+        fn len(&self) -> usize {
+            self.paths.len()
+        }
+
+        fn prefix(&self) -> Arc<str> {
+            Arc::default()
+        }
+
+        fn candidates(&'a self, start: usize) -> Self::Candidates {
+            Box::new(self.paths[start..].iter().map(|path| {
+                Some(PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+            }))
+        }
+    }
+
+    #[gpui::test]
+    async fn test_match_path_sets_with_partial_results(cx: &mut gpui::TestAppContext) {
+        let candidate_sets = [PathCandidateSet {
+            paths: [
+                "src/main.rs",
+                "src/lib.rs",
+                "src/widgets/button.rs",
+                "tests/main.rs",
+                "README.md",
+            ]
+            .map(Path::new)
+            .to_vec(),
+        }];
+        let query = PreparedQuery::new("main");
+        let cancel_flag = CancellationToken::new();
+        let matched_paths = |matches: &[PathMatch]| {
+            matches
+                .iter()
+                .map(|path_match| path_match.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let expected = PathMatcher::new()
+            .match_path_sets(
+                &candidate_sets,
+                &query,
+                None,
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions::default(),
+                &cancel_flag,
+                cx.executor(),
+            )
+            .await;
+        assert_eq!(matched_paths(&expected).len(), 2);
+
+        // The deadline passes before the first match, which is found once matching
+        // goes on.
+        let mut matcher = PathMatcher::new();
+        let mut partial_results = None;
+        let results = matcher
+            .match_path_sets_with_partial_results(
+                &candidate_sets,
+                &query,
+                None,
+                CaseSensitivity::Insensitive,
+                10,
+                &PathMatchOptions {
+                    deadline: Some(Duration::ZERO),
+                    ..Default::default()
+                },
+                &cancel_flag,
+                cx.executor(),
+                |results| partial_results = Some(results),
+            )
+            .await;
+        assert_eq!(partial_results.map(|results| results.len()), Some(0));
+        assert_eq!(matched_paths(&results), matched_paths(&expected));
+        assert!(!matcher.is_partial());
+    }
+
+    #[gpui::test]
+    async fn test_reused_path_matcher(cx: &mut gpui::TestAppContext) {
+        let paths = [
+            "src/main.rs",
+            "src/lib.rs",
+            "src/widgets/button.rs",
+            "src/widgets/button_group.rs",
+            "crates/editor/src/display_map/block_map.rs",
+            "tests/main.rs",
+            "README.md",
+        ]
+        .map(Path::new);
+        let candidate_sets = [PathCandidateSet {
+            paths: paths.to_vec(),
+        }];
+        let candidates = || {
+            paths
+                .iter()
+                .map(|path| PathMatchCandidate {
+                    is_dir: false,
+                    path,
+                    char_bag: CharBag::from(path.to_string_lossy().as_ref()),
+                    symlink_target: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let summary = |matches: Vec<PathMatch>| {
+            matches
+                .into_iter()
+                .map(|path_match| {
+                    (
+                        path_match.path.to_string_lossy().into_owned(),
+                        path_match.score,
+                        path_match.positions,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let cancel_flag = CancellationToken::new();
+
+        // Queries longer and shorter than the previous ones, and ones matching nothing,
+        // leave nothing behind in the buffers that changes the next results.
+        let mut matcher = PathMatcher::new();
+        for query in [
+            "editor/src/display_map/block_map",
+            "btn",
+            "src/ button",
+            "zzz",
+            "main",
+            "m",
+        ] {
+            let query = PreparedQuery::new(query);
+            let options = PathMatchOptions::default();
+            let expected = summary(
+                PathMatcher::new()
+                    .match_path_sets(
+                        &candidate_sets,
+                        &query,
+                        None,
+                        CaseSensitivity::Insensitive,
+                        10,
+                        &options,
+                        &cancel_flag,
+                        cx.executor(),
+                    )
+                    .await,
+            );
+            let results = summary(
+                matcher
+                    .match_path_sets(
+                        &candidate_sets,
+                        &query,
+                        None,
+                        CaseSensitivity::Insensitive,
+                        10,
+                        &options,
+                        &cancel_flag,
+                        cx.executor(),
+                    )
+                    .await,
+            );
+            assert_eq!(results, expected);
+
+            let expected = summary(match_fixed_path_set(
+                candidates(),
+                0,
+                &query,
+                CaseSensitivity::Insensitive,
+                10,
+                &options,
+            ));
+            let results = summary(matcher.match_fixed_path_set(
+                candidates(),
+                0,
+                &query,
+                CaseSensitivity::Insensitive,
+                10,
+                &options,
+            ));
+            assert_eq!(results, expected);
+        }
+    }
+
+    struct TestCandidateSet {
+        len: usize,
+        priority_len: usize,
+    }
+
+    impl<'a> PathMatchCandidateSet<'a> for TestCandidateSet {
+        type Candidates = iter::Empty<Option<PathMatchCandidate<'a>>>;
+
+        fn id(&self) -> usize {
+            0
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn prefix(&self) -> Arc<str> {
+            Arc::default()
+        }
+
+        fn candidates(&'a self, _start: usize) -> Self::Candidates {
+            iter::empty()
+        }
+
+        fn priority_len(&self) -> usize {
+            self.priority_len
+        }
+    }
+
+    #[test]
+    fn test_split_into_segments() {
+        let set = |len, priority_len| TestCandidateSet { len, priority_len };
+
+        assert_eq!(
+            split_into_segments(&[set(5, 0), set(3, 0)], 3),
+            [vec![(0, 0..3)], vec![(0, 3..5), (1, 0..1)], vec![(1, 1..3)]]
+        );
+        // Every segment starts with its share of the prioritized candidates.
+        assert_eq!(
+            split_into_segments(&[set(10, 4), set(6, 0)], 2),
+            [vec![(0, 0..2), (0, 4..10)], vec![(0, 2..4), (1, 0..6)]]
+        );
+    }
 }
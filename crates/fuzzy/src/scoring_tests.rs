@@ -0,0 +1,243 @@
+use crate::{
+    match_fixed_path_set, CaseSensitivity, CharBag, PathMatch, PathMatchCandidate,
+    PathMatchOptions, PreparedQuery, ScoringStrategy,
+};
+use proptest::prelude::*;
+use std::{fmt::Write as _, path::Path, path::PathBuf};
+
+fn match_paths(
+    query: &str,
+    paths: &[&str],
+    case_sensitivity: CaseSensitivity,
+    options: &PathMatchOptions,
+) -> Vec<PathMatch> {
+    let candidates = paths
+        .iter()
+        .map(|path| PathMatchCandidate {
+            is_dir: false,
+            path: Path::new(path),
+            char_bag: CharBag::from(path.to_lowercase().as_str()),
+            symlink_target: None,
+        })
+        .collect();
+    match_fixed_path_set(
+        candidates,
+        0,
+        &PreparedQuery::new(query),
+        case_sensitivity,
+        paths.len(),
+        options,
+    )
+}
+
+fn match_path(
+    query: &str,
+    path: &str,
+    strategy: ScoringStrategy,
+    prioritize_file_names: bool,
+) -> Option<PathMatch> {
+    match_paths(
+        query,
+        &[path],
+        CaseSensitivity::Insensitive,
+        &PathMatchOptions {
+            scoring_strategy: strategy,
+            prioritize_file_names,
+            ..Default::default()
+        },
+    )
+    .pop()
+}
+
+fn strategy() -> impl Strategy<Value = ScoringStrategy> {
+    prop_oneof![
+        Just(ScoringStrategy::Recursive),
+        Just(ScoringStrategy::Iterative)
+    ]
+}
+
+fn case_sensitivity() -> impl Strategy<Value = CaseSensitivity> {
+    prop_oneof![
+        Just(CaseSensitivity::Insensitive),
+        Just(CaseSensitivity::Smart),
+        Just(CaseSensitivity::Sensitive),
+    ]
+}
+
+/// Paths made of the characters that the scorer treats specially.
+fn path_like() -> impl Strategy<Value = String> {
+    "[a-cA-C0-9_./\\\\ -]{0,24}"
+}
+
+proptest! {
+    #[test]
+    fn test_arbitrary_unicode_doesnt_panic(
+        query in any::<String>(),
+        path in any::<String>(),
+        case_sensitivity in case_sensitivity(),
+        strategy in strategy(),
+        prioritize_file_names in any::<bool>(),
+    ) {
+        let matches = match_paths(
+            &query,
+            &[&path],
+            case_sensitivity,
+            &PathMatchOptions {
+                scoring_strategy: strategy,
+                prioritize_file_names,
+                ..Default::default()
+            },
+        );
+        for path_match in matches {
+            prop_assert!(path_match.score > 0.0 && path_match.score <= 1.0);
+            prop_assert!(path_match.positions.windows(2).all(|pair| pair[0] < pair[1]));
+            prop_assert!(path_match
+                .positions
+                .iter()
+                .all(|&position| path.is_char_boundary(position) && position < path.len()));
+        }
+    }
+
+    #[test]
+    fn test_case_mapping_edge_cases_dont_panic(
+        query in "[İıiIßẞﬁK/ a\u{307}]{1,4}",
+        path in "[İıiIßẞﬁK/ a\u{307}]{0,8}",
+        case_sensitivity in case_sensitivity(),
+        strategy in strategy(),
+        prioritize_file_names in any::<bool>(),
+    ) {
+        for path_match in match_paths(
+            &query,
+            &[&path],
+            case_sensitivity,
+            &PathMatchOptions {
+                scoring_strategy: strategy,
+                prioritize_file_names,
+                ..Default::default()
+            },
+        ) {
+            prop_assert!(path_match.positions.windows(2).all(|pair| pair[0] < pair[1]));
+            prop_assert!(path_match
+                .positions
+                .iter()
+                .all(|&position| path.is_char_boundary(position) && position < path.len()));
+        }
+    }
+
+    #[test]
+    fn test_positions_point_at_query_chars(
+        query in "[a-c/_.-]{1,6}",
+        path in path_like(),
+        strategy in strategy(),
+        prioritize_file_names in any::<bool>(),
+    ) {
+        if let Some(path_match) = match_path(&query, &path, strategy, prioritize_file_names) {
+            let query = query.to_lowercase();
+            prop_assert_eq!(path_match.positions.len(), query.chars().count());
+            prop_assert!(path_match.positions.windows(2).all(|pair| pair[0] < pair[1]));
+            for (query_char, &position) in query.chars().zip(&path_match.positions) {
+                let path_char = path[position..].chars().next().unwrap().to_ascii_lowercase();
+                let is_separator = |c: char| c == '/' || c == '\\';
+                prop_assert!(
+                    query_char == path_char
+                        || (is_separator(path_char)
+                            && (query_char == '_' || is_separator(query_char))),
+                    "query char {:?} matched {:?}",
+                    query_char,
+                    path_char,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategies_agree(
+        query in "[a-c/_.-]{1,6}",
+        path in path_like(),
+        prioritize_file_names in any::<bool>(),
+    ) {
+        let recursive = match_path(&query, &path, ScoringStrategy::Recursive, prioritize_file_names);
+        let iterative = match_path(&query, &path, ScoringStrategy::Iterative, prioritize_file_names);
+        prop_assert_eq!(
+            recursive.map(|m| (m.score, m.positions)),
+            iterative.map(|m| (m.score, m.positions))
+        );
+    }
+
+    #[test]
+    fn test_score_doesnt_increase_with_unrelated_suffix(
+        query in "[a-c]{1,4}",
+        path in path_like(),
+        suffix in "[d-z0-9.]{1,12}",
+        strategy in strategy(),
+    ) {
+        if let Some(path_match) = match_path(&query, &path, strategy, false) {
+            let extended = match_path(&query, &format!("{path}{suffix}"), strategy, false);
+            prop_assert!(extended.is_some(), "extending the path lost the match");
+            prop_assert!(extended.unwrap().score <= path_match.score);
+        }
+    }
+}
+
+const QUERIES: &[&str] = &[
+    "main",
+    "Cargo.toml",
+    "editor",
+    "proj",
+    "collabtests",
+    "db/queries",
+    "gpui/app",
+    "lsp",
+    "settings",
+    "workspace.rs",
+    "tsx",
+    "ffinder",
+    "term",
+    "migrations/create",
+    "READ",
+];
+
+/// Ranks the paths of a snapshot of this repository for a set of queries, so that
+/// changes to the scorer show up as changes to the rankings.
+#[test]
+fn test_corpus_rankings() {
+    let paths = include_str!("../test_data/zed_paths.txt")
+        .lines()
+        .collect::<Vec<_>>();
+
+    let mut rankings = String::new();
+    for query in QUERIES {
+        writeln!(rankings, "{query}").unwrap();
+        for path_match in match_paths(
+            query,
+            &paths,
+            CaseSensitivity::Smart,
+            &PathMatchOptions::default(),
+        )
+        .iter()
+        .take(5)
+        {
+            writeln!(rankings, "    {}", path_match.path.display()).unwrap();
+        }
+        writeln!(rankings).unwrap();
+    }
+
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("test_data");
+    path.push("golden");
+    path.push("zed_path_rankings.txt");
+
+    let update = std::env::var("UPDATE_GOLDEN")
+        .map(|val| val.to_ascii_lowercase() == "true")
+        .unwrap_or(false);
+    if update {
+        std::fs::create_dir_all(path.parent().unwrap())
+            .expect("could not create golden test data directory");
+        std::fs::write(&path, rankings).expect("could not write out golden data");
+    } else {
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!("could not read golden test data file at {:?}. Did you run the test with UPDATE_GOLDEN=true before?", path);
+        });
+        assert_eq!(rankings, expected, "rankings changed");
+    }
+}
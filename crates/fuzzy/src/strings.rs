@@ -1,6 +1,6 @@
 use crate::{
-    matcher::{Match, MatchCandidate, Matcher},
-    CharBag,
+    matcher::{CaseSensitivity, Match, MatchCandidate, Matcher, Pattern},
+    CancellationToken, CharBag,
 };
 use gpui::BackgroundExecutor;
 use std::{
@@ -8,7 +8,6 @@ use std::{
     cmp::{self, Ordering},
     iter,
     ops::Range,
-    sync::atomic::AtomicBool,
 };
 
 #[derive(Clone, Debug)]
@@ -108,9 +107,9 @@ impl Ord for StringMatch {
 pub async fn match_strings(
     candidates: &[StringMatchCandidate],
     query: &str,
-    smart_case: bool,
+    case_sensitivity: CaseSensitivity,
     max_results: usize,
-    cancel_flag: &AtomicBool,
+    cancel_flag: &CancellationToken,
     executor: BackgroundExecutor,
 ) -> Vec<StringMatch> {
     if candidates.is_empty() || max_results == 0 {
@@ -129,12 +128,10 @@ pub async fn match_strings(
             .collect();
     }
 
-    let lowercase_query = query.to_lowercase().chars().collect::<Vec<_>>();
-    let query = query.chars().collect::<Vec<_>>();
-
-    let lowercase_query = &lowercase_query;
-    let query = &query;
-    let query_char_bag = CharBag::from(&lowercase_query[..]);
+    // Only path queries treat `|` as separating alternatives, since the strings
+    // matched here, like commands and symbols, can contain it.
+    let patterns = [Pattern::new(query)];
+    let patterns = &patterns;
 
     let num_cpus = executor.num_cpus().min(candidates.len());
     let segment_size = (candidates.len() + num_cpus - 1) / num_cpus;
@@ -149,13 +146,7 @@ pub async fn match_strings(
                 scope.spawn(async move {
                     let segment_start = cmp::min(segment_idx * segment_size, candidates.len());
                     let segment_end = cmp::min(segment_start + segment_size, candidates.len());
-                    let mut matcher = Matcher::new(
-                        query,
-                        lowercase_query,
-                        query_char_bag,
-                        smart_case,
-                        max_results,
-                    );
+                    let mut matcher = Matcher::new(patterns, case_sensitivity, max_results);
 
                     matcher.match_candidates(
                         &[],
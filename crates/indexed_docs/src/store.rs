@@ -1,5 +1,4 @@
 use std::path::PathBuf;
-use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
@@ -214,9 +213,9 @@ impl IndexedDocsStore {
             let matches = fuzzy::match_strings(
                 &candidates,
                 &query,
-                false,
+                fuzzy::CaseSensitivity::Insensitive,
                 100,
-                &AtomicBool::default(),
+                &Default::default(),
                 executor,
             )
             .await;
@@ -115,7 +115,11 @@ impl<T> Outline<T> {
     pub async fn search(&self, query: &str, executor: BackgroundExecutor) -> Vec<StringMatch> {
         let query = query.trim_start();
         let is_path_query = query.contains(' ');
-        let smart_case = query.chars().any(|c| c.is_uppercase());
+        let case_sensitivity = if query.chars().any(|c| c.is_uppercase()) {
+            fuzzy::CaseSensitivity::Smart
+        } else {
+            fuzzy::CaseSensitivity::Insensitive
+        };
         let mut matches = fuzzy::match_strings(
             if is_path_query {
                 &self.path_candidates
@@ -123,7 +127,7 @@ impl<T> Outline<T> {
                 &self.candidates
             },
             query,
-            smart_case,
+            case_sensitivity,
             100,
             &Default::default(),
             executor.clone(),
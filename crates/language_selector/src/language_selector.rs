@@ -3,7 +3,7 @@ mod active_buffer_language;
 pub use active_buffer_language::ActiveBufferLanguage;
 use anyhow::anyhow;
 use editor::Editor;
-use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatch, StringMatchCandidate};
 use gpui::{
     actions, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, Model,
     ParentElement, Render, Styled, View, ViewContext, VisualContext, WeakView,
@@ -188,7 +188,7 @@ impl PickerDelegate for LanguageSelectorDelegate {
                 match_strings(
                     &candidates,
                     &query,
-                    false,
+                    CaseSensitivity::Insensitive,
                     100,
                     &Default::default(),
                     background,
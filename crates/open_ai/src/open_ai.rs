@@ -2,11 +2,11 @@ mod supported_countries;
 
 use anyhow::{anyhow, Context, Result};
 use futures::{io::BufReader, stream::BoxStream, AsyncBufReadExt, AsyncReadExt, Stream, StreamExt};
-use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
 use isahc::config::Configurable;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{convert::TryFrom, future::Future, pin::Pin, time::Duration};
+use std::{convert::TryFrom, fmt, future::Future, pin::Pin, time::Duration};
 use strum::EnumIter;
 
 pub use supported_countries::*;
@@ -336,7 +336,8 @@ pub async fn stream_completion(
     }
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum OpenAiEmbeddingModel {
     #[serde(rename = "text-embedding-3-small")]
     TextEmbedding3Small,
@@ -346,7 +347,8 @@ pub enum OpenAiEmbeddingModel {
 
 #[derive(Serialize)]
 struct OpenAiEmbeddingRequest<'a> {
-    model: OpenAiEmbeddingModel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<OpenAiEmbeddingModel>,
     input: Vec<&'a str>,
 }
 
@@ -360,6 +362,22 @@ pub struct OpenAiEmbedding {
     pub embedding: Vec<f32>,
 }
 
+/// The error returned when a request was rejected for exceeding the rate limits of
+/// the API key.
+#[derive(Debug)]
+pub struct RateLimitError {
+    /// How long the API asked to wait before retrying, if it did.
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OpenAI rate limit exceeded")
+    }
+}
+
+impl std::error::Error for RateLimitError {}
+
 pub fn embed<'a>(
     client: &dyn HttpClient,
     api_url: &str,
@@ -367,8 +385,25 @@ pub fn embed<'a>(
     model: OpenAiEmbeddingModel,
     texts: impl IntoIterator<Item = &'a str>,
 ) -> impl 'static + Future<Output = Result<OpenAiEmbeddingResponse>> {
-    let uri = format!("{api_url}/embeddings");
+    embed_with_auth(
+        client,
+        &format!("{api_url}/embeddings"),
+        ("Authorization", &format!("Bearer {}", api_key)),
+        Some(model),
+        texts,
+    )
+}
 
+/// Embeds texts with an API that's compatible with OpenAI's, but authenticates with
+/// the given header. APIs that name the model in the URI, like deployments of OpenAI
+/// models on Azure, are given no model.
+pub fn embed_with_auth<'a>(
+    client: &dyn HttpClient,
+    uri: &str,
+    (auth_header_name, auth_header_value): (&str, &str),
+    model: Option<OpenAiEmbeddingModel>,
+    texts: impl IntoIterator<Item = &'a str>,
+) -> impl 'static + Future<Output = Result<OpenAiEmbeddingResponse>> {
     let request = OpenAiEmbeddingRequest {
         model,
         input: texts.into_iter().collect(),
@@ -378,7 +413,7 @@ pub fn embed<'a>(
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
+        .header(auth_header_name, auth_header_value)
         .body(body)
         .map(|request| client.send(request));
 
@@ -391,6 +426,13 @@ pub fn embed<'a>(
             let response: OpenAiEmbeddingResponse =
                 serde_json::from_str(&body).context("failed to parse OpenAI embedding response")?;
             Ok(response)
+        } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map(Duration::from_secs);
+            Err(RateLimitError { retry_after }.into())
         } else {
             Err(anyhow!(
                 "error during embedding, status: {:?}, body: {:?}",
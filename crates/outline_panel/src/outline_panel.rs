@@ -6,7 +6,7 @@ use std::{
     hash::Hash,
     ops::Range,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicBool, Arc, OnceLock},
+    sync::{Arc, OnceLock},
     time::Duration,
     u32,
 };
@@ -22,7 +22,7 @@ use editor::{
     MultiBufferSnapshot, RangeToAnchorExt,
 };
 use file_icons::FileIcons;
-use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatch, StringMatchCandidate};
 use gpui::{
     actions, anchored, deferred, div, impl_actions, px, uniform_list, Action, AnyElement,
     AppContext, AssetSource, AsyncWindowContext, ClipboardItem, DismissEvent, Div, ElementId,
@@ -3151,9 +3151,9 @@ impl OutlinePanel {
             let mut matched_ids = match_strings(
                 &match_candidates,
                 &query,
-                true,
+                CaseSensitivity::Smart,
                 usize::MAX,
-                &AtomicBool::default(),
+                &Default::default(),
                 cx.background_executor().clone(),
             )
             .await
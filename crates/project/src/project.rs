@@ -40,6 +40,7 @@ use gpui::{
     AnyModel, AppContext, AsyncAppContext, BorrowAppContext, Context, EventEmitter, Model,
     ModelContext, SharedString, Task, WeakModel, WindowContext,
 };
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use itertools::Itertools;
 use language::{
     language_settings::{
@@ -86,7 +87,11 @@ use task::{
 };
 use terminals::Terminals;
 use text::{Anchor, BufferId};
-use util::{defer, paths::compare_paths, ResultExt as _};
+use util::{
+    defer,
+    paths::{compare_paths, PathMatcher},
+    ResultExt as _,
+};
 use worktree::{CreatedEntry, Snapshot, Traversal};
 use worktree_store::{WorktreeStore, WorktreeStoreEvent};
 
@@ -169,6 +174,7 @@ pub struct Project {
     search_history: SearchHistory,
     search_included_history: SearchHistory,
     search_excluded_history: SearchHistory,
+    worktree_aliases: HashMap<WorktreeId, Arc<str>>,
     snippets: Model<SnippetProvider>,
     last_formatting_failure: Option<String>,
     buffers_being_formatted: HashSet<BufferId>,
@@ -696,6 +702,7 @@ impl Project {
                 buffers_being_formatted: Default::default(),
                 search_included_history: Self::new_search_history(),
                 search_excluded_history: Self::new_search_history(),
+                worktree_aliases: HashMap::default(),
             }
         })
     }
@@ -789,6 +796,7 @@ impl Project {
                 buffers_being_formatted: Default::default(),
                 search_included_history: Self::new_search_history(),
                 search_excluded_history: Self::new_search_history(),
+                worktree_aliases: HashMap::default(),
             };
 
             let client: AnyProtoClient = ssh.clone().into();
@@ -969,6 +977,7 @@ impl Project {
                 search_history: Self::new_search_history(),
                 search_included_history: Self::new_search_history(),
                 search_excluded_history: Self::new_search_history(),
+                worktree_aliases: HashMap::default(),
                 environment: ProjectEnvironment::new(&worktree_store, None, cx),
                 remotely_created_buffers: Arc::new(Mutex::new(RemotelyCreatedBuffers::default())),
                 last_formatting_failure: None,
@@ -1367,6 +1376,11 @@ impl Project {
             .clone()
     }
 
+    /// The `user@host:port` of the SSH connection the project is opened over, if any.
+    pub fn ssh_session_connection_string(&self) -> Option<String> {
+        self.ssh_session.as_ref()?.connection_string()
+    }
+
     pub fn replica_id(&self) -> ReplicaId {
         match self.client_state {
             ProjectClientState::Remote { replica_id, .. } => replica_id,
@@ -1470,6 +1484,19 @@ impl Project {
             .map(|tree| tree.read(cx).root_name())
     }
 
+    /// Sets the name shown and matched in place of a worktree's root name, such as
+    /// `@app` for a deeply nested package, or clears it when `alias` is `None`.
+    pub fn set_worktree_alias(&mut self, worktree_id: WorktreeId, alias: Option<Arc<str>>) {
+        match alias {
+            Some(alias) => self.worktree_aliases.insert(worktree_id, alias),
+            None => self.worktree_aliases.remove(&worktree_id),
+        };
+    }
+
+    pub fn worktree_alias(&self, worktree_id: WorktreeId) -> Option<Arc<str>> {
+        self.worktree_aliases.get(&worktree_id).cloned()
+    }
+
     pub fn worktree_for_id(&self, id: WorktreeId, cx: &AppContext) -> Option<Model<Worktree>> {
         self.worktree_store.read(cx).worktree_for_id(id, cx)
     }
@@ -2286,6 +2313,7 @@ impl Project {
     }
 
     fn on_worktree_removed(&mut self, id_to_remove: WorktreeId, cx: &mut ModelContext<Self>) {
+        self.worktree_aliases.remove(&id_to_remove);
         if let Some(dev_server_project_id) = self.dev_server_project_id {
             let paths: Vec<String> = self
                 .visible_worktrees(cx)
@@ -5159,7 +5187,82 @@ pub struct PathMatchCandidateSet {
     pub snapshot: Snapshot,
     pub include_ignored: bool,
     pub include_root_name: bool,
+    /// Prefixed to the candidates in place of the root name, whether or not
+    /// `include_root_name` is set. See [`Project::worktree_alias`].
+    pub root_alias: Option<Arc<str>>,
+    /// Directories whose entries are offered to the matcher before all others, in
+    /// this order, such as recently modified ones. When matching is time-boxed, the
+    /// likeliest matches then get scored before the deadline.
+    pub priority_dirs: Vec<Arc<Path>>,
     pub candidates: Candidates,
+    pub filter: CandidateFilter,
+    /// Whether to look up the path each candidate resolves to through symlinks, for
+    /// [`fuzzy::PathMatchOptions::match_symlink_targets`]. It takes a lookup for the
+    /// directories of every candidate, so it's only worth it where targets are matched.
+    pub resolve_symlinks: bool,
+}
+
+/// Finer-grained control over which entries of a [`PathMatchCandidateSet`]
+/// are offered to the fuzzy matcher, applied on top of `include_ignored`.
+#[derive(Clone, Debug, Default)]
+pub struct CandidateFilter {
+    /// Worktree-relative directories whose ignored entries are considered
+    /// even when the set excludes ignored entries.
+    pub include_ignored_in: Vec<Arc<Path>>,
+    /// Entries matching these globs are never considered.
+    pub exclude: Option<PathMatcher>,
+    /// Whether to skip dotfiles and anything inside a dot-directory.
+    pub exclude_hidden: bool,
+    /// Patterns read from the worktree's auxiliary ignore files, which hide
+    /// entries from the matcher without ignoring them in git.
+    pub auxiliary_ignore: Option<Arc<Gitignore>>,
+}
+
+/// Files at the root of a worktree listing entries to hide from fuzzy matching,
+/// in `.gitignore` syntax. Later files take precedence over earlier ones.
+pub const AUXILIARY_IGNORE_FILES: [&str; 2] = [".ignore", ".fdignore"];
+
+impl CandidateFilter {
+    /// Reads the [`AUXILIARY_IGNORE_FILES`] at the root of a worktree into
+    /// `auxiliary_ignore`, leaving it unset when there are none.
+    pub async fn load_auxiliary_ignore(&mut self, worktree_abs_path: &Path, fs: &dyn Fs) {
+        let mut builder = GitignoreBuilder::new(worktree_abs_path);
+        let mut found = false;
+        for file_name in AUXILIARY_IGNORE_FILES {
+            let abs_path = worktree_abs_path.join(file_name);
+            let Ok(contents) = fs.load(&abs_path).await else {
+                continue;
+            };
+            found = true;
+            for line in contents.lines() {
+                builder.add_line(Some(abs_path.clone()), line).log_err();
+            }
+        }
+        self.auxiliary_ignore = if found {
+            builder.build().log_err().map(Arc::new)
+        } else {
+            None
+        };
+    }
+
+    /// Whether an entry that isn't left out for being ignored should be matched.
+    fn includes_path(&self, path: &Path, is_dir: bool) -> bool {
+        if self.exclude_hidden
+            && path
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+        if self.auxiliary_ignore.as_ref().map_or(false, |ignore| {
+            ignore.matched_path_or_any_parents(path, is_dir).is_ignore()
+        }) {
+            return false;
+        }
+        self.exclude
+            .as_ref()
+            .map_or(true, |exclude| !exclude.is_match(path))
+    }
 }
 
 pub enum Candidates {
@@ -5171,6 +5274,98 @@ pub enum Candidates {
     Entries,
 }
 
+impl PathMatchCandidateSet {
+    /// Ignored entries have to be traversed when any directory opts back into them.
+    fn traverses_ignored(&self) -> bool {
+        self.include_ignored || !self.filter.include_ignored_in.is_empty()
+    }
+
+    /// Whether a traversed entry should be matched. Ignored entries are traversed when
+    /// some directories opt back into them, so the ones outside of those directories
+    /// are left out here.
+    fn includes(&self, entry: &Entry) -> bool {
+        if entry.is_ignored
+            && !self.include_ignored
+            && !self
+                .filter
+                .include_ignored_in
+                .iter()
+                .any(|dir| entry.path.starts_with(dir))
+        {
+            return false;
+        }
+        self.filter.includes_path(&entry.path, entry.is_dir())
+    }
+
+    /// Traverses the candidates in the order of the snapshot, from the given position
+    /// among them.
+    fn traversal(&self, start: usize) -> Traversal {
+        let include_ignored = self.traverses_ignored();
+        match self.candidates {
+            Candidates::Directories => self.snapshot.directories(include_ignored, start),
+            Candidates::Files => self.snapshot.files(include_ignored, start),
+            Candidates::Entries => self.snapshot.entries(include_ignored, start),
+        }
+    }
+
+    /// The positions, in the order of the snapshot, of the candidates to yield: those
+    /// within `priority_dirs` first, in the order of the directories, followed by all
+    /// the others. The entries within a directory are next to each other in the
+    /// snapshot, so finding each directory's takes a couple of seeks.
+    fn candidate_ranges(&self) -> Vec<Range<usize>> {
+        let (mut ranges, prioritized) = self.prioritized_ranges();
+        let len = fuzzy::PathMatchCandidateSet::len(self);
+        ranges.extend(uncovered_ranges(0..len, &prioritized));
+        ranges
+    }
+
+    /// The positions of the candidates within `priority_dirs`, in the order of the
+    /// directories, along with the same ranges sorted by position.
+    fn prioritized_ranges(&self) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+        let (include_files, include_dirs) = match self.candidates {
+            Candidates::Directories => (false, true),
+            Candidates::Files => (true, false),
+            Candidates::Entries => (true, true),
+        };
+        let include_ignored = self.traverses_ignored();
+        let mut ranges = Vec::new();
+        let mut prioritized = Vec::new();
+        for dir in &self.priority_dirs {
+            let mut traversal =
+                self.snapshot
+                    .traverse_from_path(include_files, include_dirs, include_ignored, dir);
+            let start = traversal.start_offset();
+            traversal.advance_past(dir);
+            // Entries within an earlier directory have already been yielded.
+            let new_ranges = uncovered_ranges(start..traversal.start_offset(), &prioritized);
+            prioritized.extend(new_ranges.iter().cloned());
+            prioritized.sort_unstable_by_key(|range| range.start);
+            ranges.extend(new_ranges);
+        }
+        (ranges, prioritized)
+    }
+}
+
+/// The parts of a range that aren't in any of the given ranges, which are sorted and
+/// don't overlap.
+fn uncovered_ranges(range: Range<usize>, covered: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut uncovered = Vec::new();
+    let mut start = range.start;
+    for covered in covered {
+        if covered.start >= range.end {
+            break;
+        }
+        if covered.start > start {
+            uncovered.push(start..covered.start);
+        }
+        start = start.max(covered.end);
+    }
+    if start < range.end {
+        uncovered.push(start..range.end);
+    }
+    uncovered
+}
+
 impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
     type Candidates = PathMatchCandidateSetIter<'a>;
 
@@ -5181,7 +5376,7 @@ impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
     fn len(&self) -> usize {
         match self.candidates {
             Candidates::Files => {
-                if self.include_ignored {
+                if self.traverses_ignored() {
                     self.snapshot.file_count()
                 } else {
                     self.snapshot.visible_file_count()
@@ -5189,7 +5384,7 @@ impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
             }
 
             Candidates::Directories => {
-                if self.include_ignored {
+                if self.traverses_ignored() {
                     self.snapshot.dir_count()
                 } else {
                     self.snapshot.visible_dir_count()
@@ -5197,7 +5392,7 @@ impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
             }
 
             Candidates::Entries => {
-                if self.include_ignored {
+                if self.traverses_ignored() {
                     self.snapshot.entry_count()
                 } else {
                     self.snapshot.visible_entry_count()
@@ -5209,6 +5404,8 @@ impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
     fn prefix(&self) -> Arc<str> {
         if self.snapshot.root_entry().map_or(false, |e| e.is_file()) {
             self.snapshot.root_name().into()
+        } else if let Some(alias) = &self.root_alias {
+            format!("{alias}/").into()
         } else if self.include_root_name {
             format!("{}/", self.snapshot.root_name()).into()
         } else {
@@ -5217,31 +5414,141 @@ impl<'a> fuzzy::PathMatchCandidateSet<'a> for PathMatchCandidateSet {
     }
 
     fn candidates(&'a self, start: usize) -> Self::Candidates {
+        let mut skipped = start;
+        let ranges = self
+            .candidate_ranges()
+            .into_iter()
+            .filter_map(|range| {
+                if skipped >= range.len() {
+                    skipped -= range.len();
+                    None
+                } else {
+                    let range = range.start + skipped..range.end;
+                    skipped = 0;
+                    Some(range)
+                }
+            })
+            .collect::<Vec<_>>();
         PathMatchCandidateSetIter {
-            traversal: match self.candidates {
-                Candidates::Directories => self.snapshot.directories(self.include_ignored, start),
-                Candidates::Files => self.snapshot.files(self.include_ignored, start),
-                Candidates::Entries => self.snapshot.entries(self.include_ignored, start),
-            },
+            set: self,
+            ranges: ranges.into_iter(),
+            traversal: None,
+            ancestor_symlinks: Vec::new(),
         }
     }
+
+    fn priority_len(&self) -> usize {
+        if self.priority_dirs.is_empty() {
+            return 0;
+        }
+        let (ranges, _) = self.prioritized_ranges();
+        ranges.iter().map(|range| range.len()).sum()
+    }
 }
 
+/// Yields every entry of a [`PathMatchCandidateSet`], with `None` for the ones its
+/// filter leaves out, so that positions in it match [`fuzzy::PathMatchCandidateSet::len`].
 pub struct PathMatchCandidateSetIter<'a> {
-    traversal: Traversal<'a>,
+    set: &'a PathMatchCandidateSet,
+    /// The positions of the candidates left to yield, after the current traversal's.
+    ranges: std::vec::IntoIter<Range<usize>>,
+    traversal: Option<std::iter::Take<Traversal<'a>>>,
+    /// The directories containing the latest entry, outermost first, each with the
+    /// nearest symlink among it and the directories containing it.
+    ancestor_symlinks: Vec<(&'a Path, Option<&'a Entry>)>,
+}
+
+impl<'a> PathMatchCandidateSetIter<'a> {
+    /// Finds the symlink that the given entry is, or is within. Entries are yielded
+    /// mostly in the order of their paths, so the directories containing the previous
+    /// entry are kept to only look up the ones that the next entry doesn't share.
+    fn nearest_symlink(&mut self, entry: &'a Entry) -> Option<&'a Entry> {
+        if !self.set.resolve_symlinks {
+            return None;
+        }
+        if entry.is_symlink && entry.canonical_path.is_some() {
+            return Some(entry);
+        }
+        let parent = entry.path.parent()?;
+        while self
+            .ancestor_symlinks
+            .last()
+            .map_or(false, |(dir, _)| !parent.starts_with(dir))
+        {
+            self.ancestor_symlinks.pop();
+        }
+        let new_dirs = parent
+            .ancestors()
+            .take_while(|dir| {
+                self.ancestor_symlinks
+                    .last()
+                    .map_or(true, |(known_dir, _)| dir != known_dir)
+            })
+            .collect::<Vec<_>>();
+        for dir in new_dirs.into_iter().rev() {
+            let outer_symlink = self
+                .ancestor_symlinks
+                .last()
+                .and_then(|(_, symlink)| *symlink);
+            let symlink = self
+                .set
+                .snapshot
+                .entry_for_path(dir)
+                .filter(|dir_entry| dir_entry.is_symlink && dir_entry.canonical_path.is_some())
+                .or(outer_symlink);
+            self.ancestor_symlinks.push((dir, symlink));
+        }
+        self.ancestor_symlinks
+            .last()
+            .and_then(|(_, symlink)| *symlink)
+    }
+}
+
+/// The path an entry resolves to through the given symlink, which is the entry itself
+/// or a directory containing it. It's relative to the worktree's root when it's
+/// within it, or else to the closest directory containing both, such as the root of
+/// a repository that the worktree is a package of.
+fn symlink_target(root: &Path, entry: &Entry, symlink: &Entry) -> Option<PathBuf> {
+    let canonical_path = symlink.canonical_path.as_deref()?;
+    let target = match entry.path.strip_prefix(&symlink.path) {
+        Ok(path_in_symlink) if !path_in_symlink.as_os_str().is_empty() => {
+            canonical_path.join(path_in_symlink)
+        }
+        _ => canonical_path.to_path_buf(),
+    };
+    let relative_target = root
+        .ancestors()
+        .find_map(|ancestor| target.strip_prefix(ancestor).ok())?;
+    Some(relative_target.to_path_buf())
 }
 
 impl<'a> Iterator for PathMatchCandidateSetIter<'a> {
-    type Item = fuzzy::PathMatchCandidate<'a>;
+    type Item = Option<fuzzy::PathMatchCandidate<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.traversal
-            .next()
-            .map(|entry| fuzzy::PathMatchCandidate {
-                is_dir: entry.kind.is_dir(),
-                path: &entry.path,
-                char_bag: entry.char_bag,
-            })
+        let entry = loop {
+            if let Some(entry) = self
+                .traversal
+                .as_mut()
+                .and_then(|traversal| traversal.next())
+            {
+                break entry;
+            }
+            let range = self.ranges.next()?;
+            self.traversal = Some(self.set.traversal(range.start).take(range.len()));
+        };
+        if !self.set.includes(entry) {
+            return Some(None);
+        }
+        let symlink_target = self
+            .nearest_symlink(entry)
+            .and_then(|symlink| symlink_target(self.set.snapshot.abs_path(), entry, symlink));
+        Some(Some(fuzzy::PathMatchCandidate {
+            is_dir: entry.kind.is_dir(),
+            path: &entry.path,
+            char_bag: entry.char_bag,
+            symlink_target: symlink_target.map(Cow::Owned),
+        }))
     }
 }
 
@@ -4548,6 +4548,414 @@ async fn test_search_in_gitignored_dirs(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_path_match_candidate_filter(cx: &mut gpui::TestAppContext) {
+    use fuzzy::PathMatchCandidateSet as _;
+
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            ".gitignore": "target\nnode_modules\n",
+            ".github": {
+                "workflow.yml": "",
+            },
+            "node_modules": {},
+            "src": {
+                "generated": {
+                    "bindings.rs": "",
+                },
+                "main.rs": "",
+            },
+            "target": {},
+        }),
+    )
+    .await;
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let snapshot = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).snapshot()
+    });
+
+    let candidate_paths = |filter: CandidateFilter| {
+        let set = PathMatchCandidateSet {
+            snapshot: snapshot.clone(),
+            include_ignored: false,
+            include_root_name: false,
+            root_alias: None,
+            priority_dirs: Vec::new(),
+            candidates: Candidates::Entries,
+            filter,
+            resolve_symlinks: false,
+        };
+        set.candidates(0)
+            .flatten()
+            .map(|candidate| candidate.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        candidate_paths(CandidateFilter::default()),
+        [
+            "",
+            ".github",
+            ".github/workflow.yml",
+            ".gitignore",
+            "src",
+            "src/generated",
+            "src/generated/bindings.rs",
+            "src/main.rs",
+        ]
+    );
+    assert_eq!(
+        candidate_paths(CandidateFilter {
+            include_ignored_in: vec![Path::new("node_modules").into()],
+            exclude: Some(PathMatcher::new(&["src/generated/**".to_owned()]).unwrap()),
+            exclude_hidden: true,
+            ..Default::default()
+        }),
+        ["", "node_modules", "src", "src/main.rs"]
+    );
+}
+
+#[gpui::test]
+async fn test_path_match_candidate_auxiliary_ignore(cx: &mut gpui::TestAppContext) {
+    use fuzzy::PathMatchCandidateSet as _;
+
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            ".fdignore": "*.snap\n",
+            ".ignore": "fixtures\n",
+            "fixtures": {
+                "large.json": "",
+            },
+            "src": {
+                "main.rs": "",
+                "main.snap": "",
+            },
+        }),
+    )
+    .await;
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let snapshot = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).snapshot()
+    });
+
+    let mut filter = CandidateFilter::default();
+    filter
+        .load_auxiliary_ignore(Path::new("/dir"), fs.as_ref())
+        .await;
+    let set = PathMatchCandidateSet {
+        snapshot,
+        include_ignored: false,
+        include_root_name: false,
+        root_alias: None,
+        priority_dirs: Vec::new(),
+        candidates: Candidates::Files,
+        filter,
+        resolve_symlinks: false,
+    };
+    assert_eq!(
+        set.candidates(0)
+            .flatten()
+            .map(|candidate| candidate.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        [".fdignore", ".ignore", "src/main.rs"]
+    );
+}
+
+#[gpui::test]
+async fn test_path_match_candidate_filter_across_segments(cx: &mut gpui::TestAppContext) {
+    use fuzzy::PathMatchCandidateSet as _;
+
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.rs": "",
+            "b.snap": "",
+            "c.rs": "",
+            "d.snap": "",
+            "e.snap": "",
+            "f.rs": "",
+            "g.snap": "",
+            "h.rs": "",
+        }),
+    )
+    .await;
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let snapshot = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).snapshot()
+    });
+
+    let set = PathMatchCandidateSet {
+        snapshot,
+        include_ignored: false,
+        include_root_name: false,
+        root_alias: None,
+        priority_dirs: Vec::new(),
+        candidates: Candidates::Files,
+        filter: CandidateFilter {
+            exclude: Some(PathMatcher::new(&["*.snap".to_owned()]).unwrap()),
+            ..Default::default()
+        },
+        resolve_symlinks: false,
+    };
+    assert_eq!(set.len(), 8);
+
+    // Candidates are split into segments by their position among all the entries,
+    // like when they're matched in parallel, so no segment may yield the candidates
+    // of the next one.
+    let segment_size = 3;
+    let mut segmented_paths = Vec::new();
+    for segment_start in (0..set.len()).step_by(segment_size) {
+        let segment_end = (segment_start + segment_size).min(set.len());
+        segmented_paths.extend(
+            set.candidates(segment_start)
+                .take(segment_end - segment_start)
+                .flatten()
+                .map(|candidate| candidate.path.to_string_lossy().into_owned()),
+        );
+    }
+    assert_eq!(segmented_paths, ["a.rs", "c.rs", "f.rs", "h.rs"]);
+
+    let cancel_flag = fuzzy::CancellationToken::new();
+    let mut matched_paths = fuzzy::match_path_sets(
+        std::slice::from_ref(&set),
+        &fuzzy::PreparedQuery::new("rs"),
+        None,
+        fuzzy::CaseSensitivity::Insensitive,
+        100,
+        &fuzzy::PathMatchOptions::default(),
+        &cancel_flag,
+        cx.background_executor.clone(),
+    )
+    .await
+    .into_iter()
+    .map(|path_match| path_match.path.to_string_lossy().into_owned())
+    .collect::<Vec<_>>();
+    matched_paths.sort();
+    assert_eq!(matched_paths, ["a.rs", "c.rs", "f.rs", "h.rs"]);
+}
+
+#[gpui::test]
+async fn test_path_match_candidate_priority_dirs(cx: &mut gpui::TestAppContext) {
+    use fuzzy::PathMatchCandidateSet as _;
+
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a": {
+                "one.rs": "",
+                "nested": {
+                    "two.rs": "",
+                },
+            },
+            "b": {
+                "three.rs": "",
+            },
+            "c": {
+                "four.rs": "",
+            },
+        }),
+    )
+    .await;
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let snapshot = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).snapshot()
+    });
+
+    let set = PathMatchCandidateSet {
+        snapshot,
+        include_ignored: false,
+        include_root_name: false,
+        root_alias: None,
+        priority_dirs: vec![
+            Path::new("c").into(),
+            Path::new("a/nested").into(),
+            Path::new("a").into(),
+        ],
+        candidates: Candidates::Files,
+        filter: CandidateFilter::default(),
+        resolve_symlinks: false,
+    };
+    let candidate_paths = |start| {
+        set.candidates(start)
+            .flatten()
+            .map(|candidate| candidate.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        candidate_paths(0),
+        ["c/four.rs", "a/nested/two.rs", "a/one.rs", "b/three.rs"]
+    );
+    assert_eq!(candidate_paths(2), ["a/one.rs", "b/three.rs"]);
+    assert_eq!(set.len(), 4);
+    assert_eq!(set.priority_len(), 3);
+}
+
+#[gpui::test]
+async fn test_path_match_symlink_targets(cx: &mut gpui::TestAppContext) {
+    use fuzzy::PathMatchCandidateSet as _;
+
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/monorepo",
+        json!({
+            "app": {
+                "main.tsx": "",
+                "vendor": {},
+            },
+            "libs": {
+                "design-system": {
+                    "button.tsx": "",
+                },
+            },
+        }),
+    )
+    .await;
+    fs.insert_symlink("/monorepo/app/vendor/ui", "../../libs/design-system".into())
+        .await;
+    let project = Project::test(fs.clone(), ["/monorepo".as_ref()], cx).await;
+    cx.run_until_parked();
+    let snapshot = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).snapshot()
+    });
+
+    let set = PathMatchCandidateSet {
+        snapshot,
+        include_ignored: false,
+        include_root_name: true,
+        root_alias: None,
+        priority_dirs: Vec::new(),
+        candidates: Candidates::Files,
+        filter: CandidateFilter::default(),
+        resolve_symlinks: true,
+    };
+    // Files within a symlinked directory resolve to the same file in the directory
+    // it points to, relative to the root of the worktree.
+    assert_eq!(
+        set.candidates(0)
+            .flatten()
+            .map(|candidate| match candidate.symlink_target {
+                Some(target) => format!("{} -> {}", candidate.path.display(), target.display()),
+                None => candidate.path.display().to_string(),
+            })
+            .collect::<Vec<_>>(),
+        [
+            "app/main.tsx",
+            "app/vendor/ui/button.tsx -> libs/design-system/button.tsx",
+            "libs/design-system/button.tsx",
+        ]
+    );
+
+    let matches = fuzzy::match_path_sets(
+        std::slice::from_ref(&set),
+        &fuzzy::PreparedQuery::new("design-system/button"),
+        None,
+        fuzzy::CaseSensitivity::Insensitive,
+        10,
+        &fuzzy::PathMatchOptions {
+            match_symlink_targets: true,
+            ..Default::default()
+        },
+        &Default::default(),
+        cx.background_executor.clone(),
+    )
+    .await;
+    let symlink_match = matches
+        .iter()
+        .find(|path_match| path_match.path.as_ref() == Path::new("app/vendor/ui/button.tsx"))
+        .unwrap();
+    assert_eq!(
+        symlink_match.symlink_target.as_deref(),
+        Some(Path::new("libs/design-system/button.tsx"))
+    );
+    // The target is matched without the root name prefixed to the paths.
+    assert_eq!(symlink_match.positions.first(), Some(&5));
+
+    // Sets that don't resolve symlinks leave the targets out.
+    let set = PathMatchCandidateSet {
+        resolve_symlinks: false,
+        ..set
+    };
+    assert!(set
+        .candidates(0)
+        .flatten()
+        .all(|candidate| candidate.symlink_target.is_none()));
+}
+
+#[gpui::test]
+async fn test_path_match_root_alias(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.background_executor.clone());
+    fs.insert_tree(
+        "/monorepo/packages/frontend/app",
+        json!({
+            "src": {
+                "main.rs": "",
+            },
+        }),
+    )
+    .await;
+    let project = Project::test(fs.clone(), ["/monorepo/packages/frontend/app".as_ref()], cx).await;
+    let worktree_id = project.read_with(cx, |project, cx| {
+        project.worktrees(cx).next().unwrap().read(cx).id()
+    });
+    project.update(cx, |project, _| {
+        project.set_worktree_alias(worktree_id, Some("@app".into()))
+    });
+
+    let candidate_sets = project.read_with(cx, |project, cx| {
+        let worktree = project.worktrees(cx).next().unwrap();
+        vec![PathMatchCandidateSet {
+            snapshot: worktree.read(cx).snapshot(),
+            include_ignored: false,
+            include_root_name: false,
+            root_alias: project.worktree_alias(worktree_id),
+            priority_dirs: Vec::new(),
+            candidates: Candidates::Files,
+            filter: CandidateFilter::default(),
+            resolve_symlinks: false,
+        }]
+    });
+    let matches = fuzzy::match_path_sets(
+        &candidate_sets,
+        &fuzzy::PreparedQuery::new("@app/main"),
+        None,
+        fuzzy::CaseSensitivity::Insensitive,
+        10,
+        &Default::default(),
+        &Default::default(),
+        cx.background_executor.clone(),
+    )
+    .await;
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path_prefix.as_ref(), "@app/");
+    assert_eq!(matches[0].path.as_ref(), Path::new("src/main.rs"));
+    assert_eq!(matches[0].positions, [0, 1, 2, 3, 4, 9, 10, 11, 12]);
+
+    project.update(cx, |project, _| {
+        project.set_worktree_alias(worktree_id, None)
+    });
+    project.read_with(cx, |project, _| {
+        assert_eq!(project.worktree_alias(worktree_id), None)
+    });
+}
+
 #[gpui::test]
 async fn test_search_ordering(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -63,7 +63,7 @@ impl ProjectSymbolsDelegate {
         let mut visible_matches = cx.background_executor().block(fuzzy::match_strings(
             &self.visible_match_candidates,
             query,
-            false,
+            fuzzy::CaseSensitivity::Insensitive,
             MAX_MATCHES,
             &Default::default(),
             cx.background_executor().clone(),
@@ -71,7 +71,7 @@ impl ProjectSymbolsDelegate {
         let mut external_matches = cx.background_executor().block(fuzzy::match_strings(
             &self.external_match_candidates,
             query,
-            false,
+            fuzzy::CaseSensitivity::Insensitive,
             MAX_MATCHES - visible_matches.len().min(MAX_MATCHES),
             &Default::default(),
             cx.background_executor().clone(),
@@ -321,7 +321,7 @@ mod tests {
                         fuzzy::match_strings(
                             &candidates,
                             &params.query,
-                            true,
+                            fuzzy::CaseSensitivity::Smart,
                             100,
                             &Default::default(),
                             executor.clone(),
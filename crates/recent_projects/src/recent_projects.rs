@@ -236,7 +236,11 @@ impl PickerDelegate for RecentProjectsDelegate {
         cx: &mut ViewContext<Picker<Self>>,
     ) -> gpui::Task<()> {
         let query = query.trim_start();
-        let smart_case = query.chars().any(|c| c.is_uppercase());
+        let case_sensitivity = if query.chars().any(|c| c.is_uppercase()) {
+            fuzzy::CaseSensitivity::Smart
+        } else {
+            fuzzy::CaseSensitivity::Insensitive
+        };
         let candidates = self
             .workspaces
             .iter()
@@ -266,7 +270,7 @@ impl PickerDelegate for RecentProjectsDelegate {
         self.matches = smol::block_on(fuzzy::match_strings(
             candidates.as_slice(),
             query,
-            smart_case,
+            case_sensitivity,
             100,
             &Default::default(),
             cx.background_executor().clone(),
@@ -435,6 +435,16 @@ impl SshSession {
     pub fn ssh_args(&self) -> Vec<String> {
         self.client_socket.as_ref().unwrap().ssh_args()
     }
+
+    /// The `user@host:port` the session is connected to, on the client's side.
+    pub fn connection_string(&self) -> Option<String> {
+        Some(
+            self.client_socket
+                .as_ref()?
+                .connection_options
+                .connection_string(),
+        )
+    }
 }
 
 impl ProtoClient for SshSession {
@@ -4,7 +4,9 @@ use gpui::App;
 use http_client::HttpClientWithUrl;
 use language::language_settings::AllLanguageSettings;
 use project::Project;
-use semantic_index::{OpenAiEmbeddingModel, OpenAiEmbeddingProvider, SemanticIndex};
+use semantic_index::{
+    OpenAiEmbeddingModel, OpenAiEmbeddingProvider, SearchFilter, SemanticIndex, Status,
+};
 use settings::SettingsStore;
 use std::{
     path::{Path, PathBuf},
@@ -21,6 +23,7 @@ fn main() {
         cx.set_global(store);
         language::init(cx);
         Project::init_settings(cx);
+        semantic_index::init(cx);
         SettingsStore::update(cx, |store, cx| {
             store.update_user_settings::<AllLanguageSettings>(cx, |_| {});
         });
@@ -76,7 +79,7 @@ fn main() {
             let (tx, rx) = oneshot::channel();
             let mut tx = Some(tx);
             let subscription = cx.update(|cx| {
-                cx.subscribe(&project_index, move |_, event, _| {
+                cx.subscribe(&project_index, move |_, event: &Status, _| {
                     if let Some(tx) = tx.take() {
                         _ = tx.send(*event);
                     }
@@ -92,7 +95,7 @@ fn main() {
                 .update(|cx| {
                     let project_index = project_index.read(cx);
                     let query = "converting an anchor to a point";
-                    project_index.search(query.into(), 4, cx)
+                    project_index.search(query.into(), SearchFilter::default(), 4, None, None, cx)
                 })
                 .unwrap()
                 .await
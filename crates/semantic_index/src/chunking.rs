@@ -2,13 +2,20 @@ use language::{with_parser, with_query_cursor, Language};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
+    borrow::Cow,
     cmp::{self, Reverse},
     ops::Range,
     path::Path,
     sync::Arc,
 };
-use tree_sitter::QueryCapture;
-use util::ResultExt as _;
+use tree_sitter::{Node, QueryCapture};
+use util::{truncate_and_trailoff, ResultExt as _};
+
+use crate::{
+    chunking_strategy::ChunkingStrategy,
+    notebook::{self, is_notebook, notebook_cells},
+    structured_data::{is_structured_data, structured_data_sections},
+};
 
 #[derive(Copy, Clone)]
 struct ChunkSizeRange {
@@ -20,35 +27,646 @@ const CHUNK_SIZE_RANGE: ChunkSizeRange = ChunkSizeRange {
     min: 1024,
     max: 8192,
 };
+/// The sizes chunks can be configured to, in bytes. Smaller chunks lack context, and
+/// larger ones don't fit the input of some embedding models.
+const CONFIGURABLE_CHUNK_SIZES: Range<usize> = 256..32_768;
+/// The sizes chunks can be configured to, in tokens.
+const CONFIGURABLE_CHUNK_TOKENS: Range<usize> = 64..8192;
+/// The most a chunk can repeat of the previous one, as a percentage of the chunk size.
+const MAX_OVERLAP_PERCENTAGE: f32 = 50.;
+/// The extensions of documentation files, which are chunked by their headings instead of
+/// their syntax, so that chunks end where sections do instead of mid-paragraph.
+const DOCUMENTATION_EXTENSIONS: &[&str] = &["md", "mdx", "rst"];
+/// The most characters of the signature of an outline item to describe chunks with.
+const MAX_SIGNATURE_LEN: usize = 200;
+/// The most lines of an outline item its signature can span.
+const MAX_SIGNATURE_LINES: usize = 8;
+/// The most characters of the documentation of an outline item to describe chunks with.
+const MAX_DOCUMENTATION_LEN: usize = 300;
+
+/// How files are split into chunks. It's recorded in the index of a worktree, whose
+/// files are chunked again when it changes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkConfig {
+    /// The most bytes a chunk spans, not counting what it repeats of the previous one.
+    /// Chunks span at least an eighth of it, unless their file is smaller.
+    pub max_size: usize,
+    /// The most bytes a chunk repeats of the end of the previous one, from the start
+    /// of a line.
+    pub overlap: usize,
+    /// The most tokens of the embedding model a chunk spans, when chunks are sized in
+    /// tokens instead of bytes. Chunks span at least an eighth of it too.
+    pub max_tokens: Option<usize>,
+}
+
+impl ChunkConfig {
+    /// Returns the configuration for chunks of the given size, which overlap by the
+    /// given percentage of it. Chunks are sized in tokens instead when `max_tokens` is
+    /// given, overlapping by the same percentage of `size`.
+    pub fn new(size: usize, max_tokens: Option<usize>, overlap_percentage: f32) -> Self {
+        let max_size = size.clamp(CONFIGURABLE_CHUNK_SIZES.start, CONFIGURABLE_CHUNK_SIZES.end);
+        let overlap_percentage = overlap_percentage.clamp(0., MAX_OVERLAP_PERCENTAGE);
+        Self {
+            max_size,
+            overlap: (max_size as f32 * overlap_percentage / 100.) as usize,
+            max_tokens: max_tokens.map(|max_tokens| {
+                max_tokens.clamp(
+                    CONFIGURABLE_CHUNK_TOKENS.start,
+                    CONFIGURABLE_CHUNK_TOKENS.end,
+                )
+            }),
+        }
+    }
+
+    /// Returns the sizes of chunks, overridden by the sizes of the language's chunking
+    /// strategy, if it has one.
+    fn size_range(&self, strategy: Option<&ChunkingStrategy>) -> ChunkSizeRange {
+        // Strategies size chunks in bytes, which are converted to tokens like
+        // `estimate_token_count` does.
+        let to_unit = |size: usize| match self.max_tokens {
+            Some(_) => size.div_ceil(4),
+            None => size,
+        };
+        let max = match strategy.and_then(|strategy| strategy.max_size) {
+            Some(max_size) => to_unit(
+                max_size.clamp(CONFIGURABLE_CHUNK_SIZES.start, CONFIGURABLE_CHUNK_SIZES.end),
+            ),
+            None => self.max_tokens.unwrap_or(self.max_size),
+        };
+        let min = match strategy.and_then(|strategy| strategy.min_size) {
+            Some(min_size) => to_unit(min_size).min(max),
+            None => max / 8,
+        };
+        ChunkSizeRange { min, max }
+    }
+
+    /// Returns the unit chunks are sized in, counting tokens with the given function.
+    fn unit<'a>(&self, count_tokens: &'a dyn Fn(&str) -> u64) -> ChunkUnit<'a> {
+        if self.max_tokens.is_some() {
+            ChunkUnit::Tokens(count_tokens)
+        } else {
+            ChunkUnit::Bytes
+        }
+    }
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            max_size: CHUNK_SIZE_RANGE.max,
+            overlap: 0,
+            max_tokens: None,
+        }
+    }
+}
+
+/// What the size of chunks is measured in.
+#[derive(Copy, Clone)]
+enum ChunkUnit<'a> {
+    Bytes,
+    /// The tokens of the embedding model, as counted by the given function. Tokens
+    /// are counted a line at a time, so a chunk's size is the sum of its lines'.
+    Tokens(&'a dyn Fn(&str) -> u64),
+}
+
+impl ChunkUnit<'_> {
+    fn len(&self, text: &str) -> usize {
+        match self {
+            ChunkUnit::Bytes => text.len(),
+            ChunkUnit::Tokens(count_tokens) => count_tokens(text) as usize,
+        }
+    }
+
+    /// Returns the length in bytes of the longest start of the text that's at most
+    /// `max` long, ending at a character boundary. Text that's counted in tokens keeps
+    /// at least one character, even if it's longer on its own.
+    fn prefix_len(&self, text: &str, max: usize) -> usize {
+        let mut end = match self {
+            ChunkUnit::Bytes => cmp::min(max, text.len()),
+            ChunkUnit::Tokens(_) => {
+                let char_ends = text
+                    .char_indices()
+                    .map(|(ix, char)| ix + char.len_utf8())
+                    .collect::<Vec<_>>();
+                let fitting = char_ends.partition_point(|end| self.len(&text[..*end]) <= max);
+                char_ends
+                    .get(fitting.saturating_sub(1))
+                    .copied()
+                    .unwrap_or(0)
+            }
+        };
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        end
+    }
+}
+
+/// Measures ranges of a text in the unit its chunks are sized in. The tokens of every
+/// line are counted upfront, so that ranges of whole lines are measured without counting
+/// them again.
+struct TextSizes<'a> {
+    text: &'a str,
+    unit: ChunkUnit<'a>,
+    /// The offset each line starts at, along with the tokens of the lines before it,
+    /// followed by the end of the text and its tokens.
+    line_starts: Vec<(usize, usize)>,
+}
+
+impl<'a> TextSizes<'a> {
+    fn new(text: &'a str, unit: ChunkUnit<'a>) -> Self {
+        let mut line_starts = Vec::new();
+        if let ChunkUnit::Tokens(_) = unit {
+            let mut offset = 0;
+            let mut tokens = 0;
+            for line in text.split_inclusive('\n') {
+                line_starts.push((offset, tokens));
+                offset += line.len();
+                tokens += unit.len(line);
+            }
+            line_starts.push((offset, tokens));
+        }
+        Self {
+            text,
+            unit,
+            line_starts,
+        }
+    }
+
+    /// Measures a range of the text, counting the tokens of the parts of lines at its
+    /// ends on their own.
+    fn len(&self, range: Range<usize>) -> usize {
+        if let ChunkUnit::Bytes = self.unit {
+            return range.len();
+        }
+        let part_len = |range: Range<usize>| {
+            if range.is_empty() {
+                0
+            } else {
+                self.unit.len(&self.text[range])
+            }
+        };
+        let first_line_ix = self
+            .line_starts
+            .partition_point(|(start, _)| *start < range.start);
+        let end_line_ix = self
+            .line_starts
+            .partition_point(|(start, _)| *start <= range.end)
+            - 1;
+        if first_line_ix > end_line_ix {
+            return part_len(range);
+        }
+        let (whole_start, start_tokens) = self.line_starts[first_line_ix];
+        let (whole_end, end_tokens) = self.line_starts[end_line_ix];
+        part_len(range.start..whole_start) + end_tokens - start_tokens
+            + part_len(whole_end..range.end)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
     pub range: Range<usize>,
+    /// Describes where the chunk is, with the path of its file, its language and the
+    /// symbols it's in. It's embedded along with the text of the chunk, since the text
+    /// alone often lacks identifying names.
+    pub header: String,
+    /// The digest of the header and text of the chunk, ignoring formatting. See
+    /// [`chunk_digest`].
     pub digest: [u8; 32],
+    /// How many bytes at the start of the chunk repeat the end of the previous chunk,
+    /// so that results in both chunks can be told apart from distinct results.
+    pub overlap: usize,
+    /// The index of the notebook cell the chunk is part of the source of, for chunks of
+    /// Jupyter notebooks. Their range spans the JSON strings of the source.
+    pub cell: Option<u32>,
+}
+
+impl Chunk {
+    /// Returns the text of the chunk, decoding the source of notebook cells from JSON.
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let text = &text[self.range.clone()];
+        if self.cell.is_some() {
+            Cow::Owned(notebook::decode_source(text))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Returns the header of the chunk followed by its text, as it gets embedded.
+    pub fn text_to_embed(&self, text: &str) -> String {
+        format!("{}{}", self.header, self.text(text))
+    }
+
+    /// Returns the chunk as if its file was moved from one path to another. The digest
+    /// is kept, since it's the digest of the text the chunk was embedded with.
+    pub fn moved(&self, old_path: &Path, new_path: &Path) -> Self {
+        let header = match self.header.strip_prefix(&path_header(old_path)) {
+            Some(rest) => format!("{}{rest}", path_header(new_path)),
+            None => self.header.clone(),
+        };
+        Self {
+            range: self.range.clone(),
+            header,
+            digest: self.digest,
+            overlap: self.overlap,
+            cell: self.cell,
+        }
+    }
+}
+
+/// Returns the digest of the whole text of a file.
+pub fn text_digest(text: &str) -> [u8; 32] {
+    Sha256::digest(text).into()
+}
+
+/// Returns the digest of the header and text of a chunk, which embeddings are cached by.
+/// The text is digested without its blank lines, with its line endings normalized and
+/// every run of whitespace within a line collapsed to a space, so that reformatting a
+/// file keeps the embeddings of its chunks, since they'd barely change.
+pub fn chunk_digest(header: &str, text: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(header);
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        let Some(first_word) = words.next() else {
+            continue;
+        };
+        hasher.update(first_word);
+        for word in words {
+            hasher.update(" ");
+            hasher.update(word);
+        }
+        hasher.update("\n");
+    }
+    hasher.finalize().into()
+}
+
+/// An outline item spanning several lines, like a type or a function.
+struct SyntacticRange {
+    range: Range<usize>,
+    name: Option<String>,
+    /// Where the item itself starts, after the comments preceding it.
+    item_start: usize,
+    /// The declaration of the item, up to where its body starts, on a single line.
+    signature: Option<String>,
+    /// The comments preceding the item, or its docstring, without comment markers.
+    documentation: Option<String>,
 }
 
-pub fn chunk_text(text: &str, language: Option<&Arc<Language>>, path: &Path) -> Vec<Chunk> {
-    chunk_text_with_size_range(text, language, path, CHUNK_SIZE_RANGE)
+impl SyntacticRange {
+    fn new(range: Range<usize>, name: Option<String>) -> Self {
+        Self {
+            item_start: range.start,
+            range,
+            name,
+            signature: None,
+            documentation: None,
+        }
+    }
+}
+
+/// Splits a file into chunks, with the chunking strategy of its language if it has one,
+/// counting the tokens of the embedding model with `count_tokens` when they're sized in
+/// tokens.
+pub fn chunk_text(
+    text: &str,
+    language: Option<&Arc<Language>>,
+    path: &Path,
+    config: ChunkConfig,
+    strategy: Option<&ChunkingStrategy>,
+    count_tokens: &dyn Fn(&str) -> u64,
+) -> Vec<Chunk> {
+    chunk_text_with_size_range(
+        text,
+        language,
+        path,
+        strategy.map_or(&[], |strategy| strategy.node_kinds.as_slice()),
+        config.size_range(strategy),
+        config.unit(count_tokens),
+        config.overlap,
+    )
 }
 
 fn chunk_text_with_size_range(
     text: &str,
     language: Option<&Arc<Language>>,
     path: &Path,
+    node_kinds: &[String],
     size_config: ChunkSizeRange,
+    unit: ChunkUnit,
+    overlap: usize,
 ) -> Vec<Chunk> {
-    let ranges = syntactic_ranges(text, language, path).unwrap_or_default();
-    chunk_text_with_syntactic_ranges(text, &ranges, size_config)
+    if is_notebook(path) {
+        if let Some(chunks) = chunk_notebook(text, path, size_config.max, unit) {
+            return chunks;
+        }
+    }
+
+    let syntactic_ranges = if is_documentation(path) {
+        documentation_ranges(text, path)
+    } else if let Some(sections) = structured_data_sections(text, path) {
+        sections
+            .into_iter()
+            .map(|section| SyntacticRange::new(section.range, Some(section.name)))
+            .collect()
+    } else {
+        syntactic_ranges(text, language, path, node_kinds).unwrap_or_default()
+    };
+    let ranges = syntactic_ranges
+        .iter()
+        .map(|syntactic_range| syntactic_range.range.clone())
+        .collect::<Vec<_>>();
+    let ranges = chunk_text_with_syntactic_ranges(text, &ranges, size_config, unit);
+    overlap_ranges(text, ranges, overlap)
+        .into_iter()
+        .map(|(range, overlap)| {
+            let header = chunk_header(path, language, &syntactic_ranges, &range);
+            Chunk {
+                digest: chunk_digest(&header, &text[range.clone()]),
+                range,
+                header,
+                overlap,
+                cell: None,
+            }
+        })
+        .collect()
+}
+
+/// Splits each cell of a Jupyter notebook into its own chunks, of whole lines of its
+/// source, so that results map back to a cell instead of to the notebook's JSON. Lines
+/// longer than a chunk make up a chunk on their own. Returns `None` if the text isn't
+/// the JSON of a notebook.
+fn chunk_notebook(text: &str, path: &Path, max_size: usize, unit: ChunkUnit) -> Option<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    for (cell_ix, cell) in notebook_cells(text)?.into_iter().enumerate() {
+        let header = format!("{}Cell: {cell_ix} ({})\n\n", path_header(path), cell.kind);
+        let mut lines = cell.lines.into_iter().peekable();
+        while let Some(first_line) = lines.next() {
+            let mut size = unit.len(&text[first_line.clone()]);
+            let mut range = first_line;
+            while let Some(line) = lines.peek() {
+                let line_size = unit.len(&text[range.end..line.end]);
+                if size + line_size > max_size {
+                    break;
+                }
+                size += line_size;
+                range.end = line.end;
+                lines.next();
+            }
+            let mut chunk = Chunk {
+                range,
+                header: header.clone(),
+                digest: [0; 32],
+                overlap: 0,
+                cell: Some(cell_ix as u32),
+            };
+            chunk.digest = chunk_digest(&chunk.header, &chunk.text(text));
+            chunks.push(chunk);
+        }
+    }
+    Some(chunks)
+}
+
+/// Chunks the text of a file as it's read, a block at a time, so that large files are
+/// never held in memory whole. Files are chunked by lines, like files whose syntax isn't
+/// known, since parsing them takes their whole text.
+pub struct StreamingChunker<'a> {
+    path: &'a Path,
+    language: Option<&'a Arc<Language>>,
+    size_range: ChunkSizeRange,
+    unit: ChunkUnit<'a>,
+    overlap: usize,
+    hasher: Sha256,
+    /// The text that wasn't chunked yet, preceded by what the next chunk repeats of the
+    /// previous one.
+    pending: String,
+    /// The offset of the pending text in the file.
+    pending_offset: usize,
+    /// How many bytes at the start of the pending text repeat the previous chunk.
+    pending_overlap: usize,
+    chunks: Vec<(Chunk, String)>,
+}
+
+impl<'a> StreamingChunker<'a> {
+    pub fn new(
+        path: &'a Path,
+        language: Option<&'a Arc<Language>>,
+        config: ChunkConfig,
+        strategy: Option<&ChunkingStrategy>,
+        count_tokens: &'a dyn Fn(&str) -> u64,
+    ) -> Self {
+        Self {
+            path,
+            language,
+            size_range: config.size_range(strategy),
+            unit: config.unit(count_tokens),
+            overlap: config.overlap,
+            hasher: Sha256::new(),
+            pending: String::new(),
+            pending_offset: 0,
+            pending_overlap: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Adds the next block of the text, chunking what can't be part of a later chunk.
+    pub fn push(&mut self, text: &str) {
+        self.hasher.update(text);
+        self.pending.push_str(text);
+        while let Some(end) = self.chunk_end(false) {
+            self.push_chunk(end);
+        }
+    }
+
+    /// Chunks the rest of the text. Returns the digest of the whole text, along with its
+    /// chunks and the text they're embedded with.
+    pub fn finish(mut self) -> ([u8; 32], Vec<(Chunk, String)>) {
+        while let Some(end) = self.chunk_end(true) {
+            self.push_chunk(end);
+        }
+        (self.hasher.finalize().into(), self.chunks)
+    }
+
+    /// Returns where in the pending text the next chunk ends, at the end of a line, or
+    /// within the line if it's too long. Returns `None` if there's no pending text, or
+    /// if all of it fits in the chunk and more text could still be pushed.
+    fn chunk_end(&self, finishing: bool) -> Option<usize> {
+        let start = self.pending_overlap;
+        let pending = &self.pending[start..];
+        if pending.is_empty() {
+            return None;
+        }
+        let max = self.size_range.max;
+        let mut end = 0;
+        match self.unit {
+            ChunkUnit::Bytes => {
+                if pending.len() > max {
+                    end = pending.as_bytes()[..max]
+                        .iter()
+                        .rposition(|byte| *byte == b'\n')
+                        .map_or(0, |ix| ix + 1);
+                } else {
+                    end = pending.len();
+                }
+            }
+            ChunkUnit::Tokens(_) => {
+                let mut size = 0;
+                for line in pending.split_inclusive('\n') {
+                    size += self.unit.len(line);
+                    if size > max {
+                        break;
+                    }
+                    end += line.len();
+                }
+            }
+        }
+
+        if end == pending.len() {
+            finishing.then_some(self.pending.len())
+        } else if end == 0 {
+            Some(start + self.unit.prefix_len(pending, max))
+        } else {
+            Some(start + end)
+        }
+    }
+
+    fn push_chunk(&mut self, end: usize) {
+        let start = self.pending_overlap;
+        let range = self.pending_offset..self.pending_offset + end;
+        let header = chunk_header(self.path, self.language, &[], &range);
+        let text = &self.pending[..end];
+        let text_to_embed = format!("{header}{text}");
+        let chunk = Chunk {
+            digest: chunk_digest(&header, text),
+            range,
+            header,
+            overlap: start,
+            cell: None,
+        };
+        self.chunks.push((chunk, text_to_embed));
+
+        // Keep what the next chunk repeats of this one, like `overlap_ranges` does.
+        let next_start = if self.overlap > 0 {
+            let earliest_start = end.saturating_sub(self.overlap).max(start + 1);
+            self.pending.as_bytes()[earliest_start - 1..end]
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(end, |ix| earliest_start + ix)
+        } else {
+            end
+        };
+        self.pending.drain(..next_start);
+        self.pending_offset += next_start;
+        self.pending_overlap = end - next_start;
+    }
+}
+
+/// Extends every chunk but the first back into the previous chunk, to the first line
+/// starting at most `overlap` bytes before it, so that code spanning the boundary
+/// between two chunks is whole in one of them. Returns the extended ranges, along with
+/// how many bytes each repeats of the previous chunk.
+fn overlap_ranges(
+    text: &str,
+    ranges: Vec<Range<usize>>,
+    overlap: usize,
+) -> Vec<(Range<usize>, usize)> {
+    let mut previous_start = None;
+    ranges
+        .into_iter()
+        .map(|range| {
+            let start = match previous_start {
+                // Chunks never repeat the whole previous chunk.
+                Some(previous_start) if overlap > 0 => {
+                    let earliest_start =
+                        range.start.saturating_sub(overlap).max(previous_start + 1);
+                    text.as_bytes()[earliest_start - 1..range.start]
+                        .iter()
+                        .position(|byte| *byte == b'\n')
+                        .map_or(range.start, |ix| earliest_start + ix)
+                }
+                _ => range.start,
+            };
+            previous_start = Some(range.start);
+            (start..range.end, range.start - start)
+        })
+        .collect()
+}
+
+/// Describes a chunk by the path of its file, its language and the names of the outline
+/// items its start is nested in, outermost first, which are the headings of the sections
+/// it's in for documentation files, and the keys it's in for structured data files. The
+/// signature and documentation of the innermost items it starts within are included too.
+fn chunk_header(
+    path: &Path,
+    language: Option<&Arc<Language>>,
+    syntactic_ranges: &[SyntacticRange],
+    range: &Range<usize>,
+) -> String {
+    let mut header = path_header(path);
+    if let Some(language) = language {
+        header.push_str(&format!("Language: {}\n", language.name()));
+    }
+    let enclosing_ranges = syntactic_ranges
+        .iter()
+        .filter(|syntactic_range| {
+            syntactic_range.range.start <= range.start && range.start < syntactic_range.range.end
+        })
+        .collect::<Vec<_>>();
+    let symbols = enclosing_ranges
+        .iter()
+        .filter_map(|syntactic_range| syntactic_range.name.as_deref())
+        .collect::<Vec<_>>();
+    if !symbols.is_empty() {
+        let label = if is_documentation(path) {
+            "Headings"
+        } else if is_structured_data(path) {
+            "Keys"
+        } else {
+            "Symbols"
+        };
+        header.push_str(&format!("{label}: {}\n", symbols.join(" > ")));
+    }
+
+    // Describe the innermost items the chunk starts within, since their signature and
+    // documentation aren't part of its text, so that natural language queries match
+    // chunks of their implementation.
+    // Describe the innermost item the chunk starts within, leaving out its signature
+    // and documentation when the chunk already contains them.
+    if let Some(item) = enclosing_ranges
+        .iter()
+        .rev()
+        .find(|syntactic_range| syntactic_range.range.start < range.start)
+    {
+        if let Some(signature) = item.signature.as_deref() {
+            if item.item_start < range.start {
+                header.push_str(&format!("Signature: {signature}\n"));
+            }
+        }
+        if let Some(documentation) = item.documentation.as_deref() {
+            header.push_str(&format!("Documentation: {documentation}\n"));
+        }
+    }
+    header.push('\n');
+    header
+}
+
+fn path_header(path: &Path) -> String {
+    format!("Path: {}\n", path.to_string_lossy())
 }
 
 fn syntactic_ranges(
     text: &str,
     language: Option<&Arc<Language>>,
     path: &Path,
-) -> Option<Vec<Range<usize>>> {
+    node_kinds: &[String],
+) -> Option<Vec<SyntacticRange>> {
     let language = language?;
     let grammar = language.grammar()?;
-    let outline = grammar.outline_config.as_ref()?;
+    let outline = if node_kinds.is_empty() {
+        Some(grammar.outline_config.as_ref()?)
+    } else {
+        None
+    };
     let tree = with_parser(|parser| {
         parser.set_language(&grammar.ts_language).log_err()?;
         parser.parse(text, None)
@@ -59,70 +677,473 @@ fn syntactic_ranges(
         return None;
     };
 
-    struct RowInfo {
+    struct RowInfo<'a> {
         offset: usize,
         is_comment: bool,
+        text: &'a str,
     }
 
     let scope = language.default_scope();
     let line_comment_prefixes = scope.line_comment_prefixes();
+    let mut in_block_comment = false;
     let row_infos = text
         .split('\n')
-        .map({
-            let mut offset = 0;
-            move |line| {
-                let line = line.trim_start();
-                let is_comment = line_comment_prefixes
+        .scan(0, |offset, line| {
+            let row_offset = *offset;
+            *offset += line.len() + 1;
+            let line = line.trim();
+            let is_comment = if in_block_comment || line.starts_with("/*") {
+                in_block_comment = !line.ends_with("*/");
+                true
+            } else {
+                line_comment_prefixes
                     .iter()
-                    .any(|prefix| line.starts_with(prefix.as_ref()));
-                let result = RowInfo { offset, is_comment };
-                offset += line.len() + 1;
-                result
-            }
+                    .any(|prefix| line.starts_with(prefix.trim_end()))
+            };
+            Some(RowInfo {
+                offset: row_offset,
+                is_comment,
+                text: line,
+            })
         })
         .collect::<Vec<_>>();
 
-    // Retrieve a list of ranges of outline items (types, functions, etc) in the document.
-    // Omit single-line outline items (e.g. struct fields, constant declarations), because
-    // we'll already be attempting to split on lines.
-    let mut ranges = with_query_cursor(|cursor| {
-        cursor
-            .matches(&outline.query, tree.root_node(), text.as_bytes())
-            .filter_map(|mat| {
-                mat.captures
-                    .iter()
-                    .find_map(|QueryCapture { node, index }| {
-                        if *index == outline.item_capture_ix {
-                            let mut start_offset = node.start_byte();
-                            let mut start_row = node.start_position().row;
-                            let end_offset = node.end_byte();
-                            let end_row = node.end_position().row;
-
-                            // Expand the range to include any preceding comments.
-                            while start_row > 0 && row_infos[start_row - 1].is_comment {
-                                start_offset = row_infos[start_row - 1].offset;
-                                start_row -= 1;
-                            }
-
-                            if end_row > start_row {
-                                return Some(start_offset..end_offset);
-                            }
-                        }
-                        None
-                    })
+    // Returns the range of an item, along with the comments preceding it, or `None` for
+    // single-line items (e.g. struct fields, constant declarations), because we'll
+    // already be attempting to split on lines.
+    let item_range = |node: Node| {
+        let item_start = node.start_byte();
+        let item_row = node.start_position().row;
+        let end_offset = node.end_byte();
+        let end_row = node.end_position().row;
+
+        // Start the range at the start of the item's line, so that chunks starting on
+        // that line start within the item.
+        let line_start = row_infos[item_row].offset;
+        let item_line_start = if text[line_start..item_start].trim().is_empty() {
+            line_start
+        } else {
+            item_start
+        };
+        let mut start_offset = item_line_start;
+        let mut start_row = item_row;
+
+        // Expand the range to include any preceding comments.
+        while start_row > 0 && row_infos[start_row - 1].is_comment {
+            start_offset = row_infos[start_row - 1].offset;
+            start_row -= 1;
+        }
+
+        if end_row <= start_row {
+            return None;
+        }
+        let (signature, body) = item_signature(&text[item_start..end_offset]);
+        let comments = row_infos[start_row..item_row]
+            .iter()
+            .map(|row_info| row_info.text);
+        let documentation =
+            documentation_text(comments, line_comment_prefixes).or_else(|| docstring(body));
+        Some(SyntacticRange {
+            range: start_offset..end_offset,
+            name: None,
+            item_start: item_line_start,
+            signature,
+            documentation,
+        })
+    };
+
+    let mut ranges = if let Some(outline) = outline {
+        // Retrieve a list of ranges of outline items (types, functions, etc) in the
+        // document.
+        with_query_cursor(|cursor| {
+            cursor
+                .matches(&outline.query, tree.root_node(), text.as_bytes())
+                .filter_map(|mat| {
+                    let mut syntactic_range =
+                        mat.captures
+                            .iter()
+                            .find_map(|QueryCapture { node, index }| {
+                                if *index == outline.item_capture_ix {
+                                    item_range(*node)
+                                } else {
+                                    None
+                                }
+                            })?;
+                    syntactic_range.name = mat
+                        .captures
+                        .iter()
+                        .find(|capture| capture.index == outline.name_capture_ix)
+                        .and_then(|capture| text.get(capture.node.byte_range()))
+                        .map(str::to_string);
+                    Some(syntactic_range)
+                })
+                .collect::<Vec<_>>()
+        })
+    } else {
+        // Retrieve the ranges of the nodes of the kinds the language's chunking strategy
+        // splits on, named by their `name` field.
+        let mut ranges = Vec::new();
+        let mut cursor = tree.walk();
+        'walk: loop {
+            let node = cursor.node();
+            if node_kinds.iter().any(|kind| kind == node.kind()) {
+                if let Some(mut syntactic_range) = item_range(node) {
+                    syntactic_range.name = node
+                        .child_by_field_name("name")
+                        .and_then(|name| text.get(name.byte_range()))
+                        .map(str::to_string);
+                    ranges.push(syntactic_range);
+                }
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        ranges
+    };
+
+    ranges.sort_unstable_by_key(|syntactic_range| {
+        (
+            syntactic_range.range.start,
+            Reverse(syntactic_range.range.end),
+        )
+    });
+    Some(ranges)
+}
+
+/// Returns the declaration of an outline item on a single line, from its start to the
+/// end of the line its parameters close on, along with the rest of the item.
+fn item_signature(item_text: &str) -> (Option<String>, &str) {
+    let mut signature = String::new();
+    let mut depth = 0;
+    let mut rest = item_text;
+    for (ix, line) in item_text.split_inclusive('\n').enumerate() {
+        rest = &rest[line.len()..];
+        let line = line.trim();
+        for character in line.chars() {
+            match character {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+        if line.starts_with([')', ']']) {
+            signature.truncate(signature.trim_end_matches(',').len());
+        } else if !signature.is_empty() && !signature.ends_with(['(', '[']) {
+            signature.push(' ');
+        }
+        signature.push_str(line);
+        if depth <= 0 || ix + 1 == MAX_SIGNATURE_LINES {
+            break;
+        }
+    }
+    let signature = signature.trim_end_matches('{').trim_end();
+    let signature =
+        (!signature.is_empty()).then(|| truncate_and_trailoff(signature, MAX_SIGNATURE_LEN));
+    (signature, rest)
+}
+
+/// Returns the text of the first paragraph of comments, without their comment markers,
+/// on a single line.
+fn documentation_text<'a>(
+    comments: impl IntoIterator<Item = &'a str>,
+    line_comment_prefixes: &[Arc<str>],
+) -> Option<String> {
+    let mut documentation = String::new();
+    for comment in comments {
+        let prefix_len = line_comment_prefixes
+            .iter()
+            .map(|prefix| prefix.trim_end())
+            .chain(["/**", "/*", "*/", "*"])
+            .filter(|prefix| comment.starts_with(prefix))
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let line = comment[prefix_len..].trim_end_matches("*/").trim();
+        if line.is_empty() {
+            if documentation.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if !documentation.is_empty() {
+            documentation.push(' ');
+        }
+        documentation.push_str(line);
+    }
+    (!documentation.is_empty())
+        .then(|| truncate_and_trailoff(&documentation, MAX_DOCUMENTATION_LEN))
+}
+
+/// Returns the documentation of an item from the docstring its body starts with, like
+/// in Python.
+fn docstring(body: &str) -> Option<String> {
+    let body = body.trim_start();
+    let quotes = ["\"\"\"", "'''"]
+        .into_iter()
+        .find(|quotes| body.starts_with(quotes))?;
+    let body = &body[quotes.len()..];
+    let docstring = &body[..body.find(quotes)?];
+    documentation_text(docstring.lines().map(str::trim), &[])
+}
+
+fn is_documentation(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            DOCUMENTATION_EXTENSIONS
+                .iter()
+                .any(|documentation_extension| {
+                    extension.eq_ignore_ascii_case(documentation_extension)
+                })
+        })
+}
+
+/// A line of a documentation file, without its line ending or trailing whitespace.
+struct DocumentationLine<'a> {
+    /// The range of the line, including its line ending.
+    range: Range<usize>,
+    text: &'a str,
+}
+
+struct Heading {
+    /// The start of the heading, or of its overline in reStructuredText.
+    offset: usize,
+    /// How deeply the heading is nested, starting at 1.
+    level: usize,
+    name: String,
+}
+
+/// Returns the ranges of the sections of a documentation file, which extend from their
+/// heading to the next heading at the same level or above, named after their heading.
+/// Also returns the unnamed ranges of the paragraphs and code blocks in the sections,
+/// which chunks avoid splitting too.
+fn documentation_ranges(text: &str, path: &Path) -> Vec<SyntacticRange> {
+    let lines = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(DocumentationLine {
+                range: start..*offset,
+                text: line.trim_end(),
             })
-            .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let is_restructured_text = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("rst"));
+    let (headings, blocks) = if is_restructured_text {
+        restructured_text_outline(&lines)
+    } else {
+        markdown_outline(&lines)
+    };
+
+    let mut ranges = headings
+        .iter()
+        .enumerate()
+        .map(|(ix, heading)| {
+            let end = headings[ix + 1..]
+                .iter()
+                .find(|next_heading| next_heading.level <= heading.level)
+                .map_or(text.len(), |next_heading| next_heading.offset);
+            SyntacticRange::new(
+                heading.offset..end,
+                Some(heading.name.clone()).filter(|name| !name.is_empty()),
+            )
+        })
+        .chain(
+            blocks
+                .into_iter()
+                .map(|range| SyntacticRange::new(range, None)),
+        )
+        .collect::<Vec<_>>();
+    ranges.sort_unstable_by_key(|syntactic_range| {
+        (
+            syntactic_range.range.start,
+            Reverse(syntactic_range.range.end),
+        )
     });
+    ranges
+}
 
-    ranges.sort_unstable_by_key(|range| (range.start, Reverse(range.end)));
-    Some(ranges)
+/// Finds the ATX (`## Heading`) and setext (underlined) headings of a Markdown file,
+/// along with its blocks of consecutive lines. Fenced code blocks and front matter are
+/// kept whole, even if they contain blank lines, and can't contain headings.
+fn markdown_outline(lines: &[DocumentationLine]) -> (Vec<Heading>, Vec<Range<usize>>) {
+    let mut headings = Vec::new();
+    let mut blocks = Vec::new();
+    let mut block: Option<Range<usize>> = None;
+    let mut block_first_line = 0;
+    let mut fence = None;
+    let mut in_front_matter = lines.first().map_or(false, |line| line.text == "---");
+    for (ix, line) in lines.iter().enumerate() {
+        if in_front_matter || fence.is_some() {
+            extend_block(&mut block, &line.range);
+            let is_end = if in_front_matter {
+                ix > 0 && line.text == "---"
+            } else {
+                fence.map_or(false, |fence| line.text.trim_start().starts_with(fence))
+            };
+            if is_end {
+                in_front_matter = false;
+                fence = None;
+                blocks.extend(block.take());
+            }
+            continue;
+        }
+
+        let trimmed = line.text.trim_start();
+        if trimmed.is_empty() {
+            blocks.extend(block.take());
+        } else if let Some(marker) = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker))
+        {
+            blocks.extend(block.take());
+            fence = Some(marker);
+            block = Some(line.range.clone());
+        } else if let Some((level, name)) = atx_heading(line.text) {
+            blocks.extend(block.take());
+            headings.push(Heading {
+                offset: line.range.start,
+                level,
+                name: name.to_string(),
+            });
+        } else if let Some(level) = setext_level(trimmed).filter(|_| block.is_some()) {
+            // The underlined lines are the heading, instead of a paragraph.
+            let block = block.take().unwrap();
+            headings.push(Heading {
+                offset: block.start,
+                level,
+                name: lines[block_first_line..ix]
+                    .iter()
+                    .map(|line| line.text.trim())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+        } else {
+            if block.is_none() {
+                block_first_line = ix;
+            }
+            extend_block(&mut block, &line.range);
+        }
+    }
+    blocks.extend(block);
+    (headings, blocks)
+}
+
+/// Returns the level and text of an ATX heading, like `## Installation ##`.
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.bytes().take_while(|byte| *byte == b'#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Returns the level of the heading a line underlines in Markdown, if it's a line of
+/// `=` (level 1) or `-` (level 2).
+fn setext_level(line: &str) -> Option<usize> {
+    if !line.is_empty() && line.bytes().all(|byte| byte == b'=') {
+        Some(1)
+    } else if !line.is_empty() && line.bytes().all(|byte| byte == b'-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Finds the headings of a reStructuredText file, which are lines underlined, or
+/// underlined and overlined, by a repeated punctuation character. Their levels are in
+/// the order each style first appears, as in reStructuredText. Also returns the blocks
+/// of consecutive lines, besides headings.
+fn restructured_text_outline(lines: &[DocumentationLine]) -> (Vec<Heading>, Vec<Range<usize>>) {
+    let mut headings = Vec::new();
+    let mut blocks = Vec::new();
+    let mut block: Option<Range<usize>> = None;
+    let mut styles = Vec::<(u8, bool)>::new();
+    let mut ix = 0;
+    while ix < lines.len() {
+        let line = &lines[ix];
+        let next_line = lines.get(ix + 1).map(|line| line.text);
+        let overlined_heading = rst_adornment(line.text).filter(|&adornment| {
+            next_line.map_or(false, |title| is_rst_title(title, line.text))
+                && lines.get(ix + 2).and_then(|line| rst_adornment(line.text)) == Some(adornment)
+        });
+        let heading = if let Some(adornment) = overlined_heading {
+            Some(((adornment, true), next_line.unwrap_or_default(), 3))
+        } else {
+            next_line
+                .and_then(rst_adornment)
+                .filter(|_| block.is_none() && is_rst_title(line.text, next_line.unwrap()))
+                .map(|adornment| ((adornment, false), line.text, 2))
+        };
+
+        if let Some((style, title, line_count)) = heading {
+            blocks.extend(block.take());
+            let level = match styles.iter().position(|known_style| *known_style == style) {
+                Some(style_ix) => style_ix + 1,
+                None => {
+                    styles.push(style);
+                    styles.len()
+                }
+            };
+            headings.push(Heading {
+                offset: line.range.start,
+                level,
+                name: title.trim().to_string(),
+            });
+            ix += line_count;
+            continue;
+        }
+
+        if line.text.is_empty() {
+            blocks.extend(block.take());
+        } else {
+            extend_block(&mut block, &line.range);
+        }
+        ix += 1;
+    }
+    blocks.extend(block);
+    (headings, blocks)
+}
+
+/// Returns the character a line of reStructuredText repeats, if it's an adornment.
+fn rst_adornment(line: &str) -> Option<u8> {
+    let first = *line.as_bytes().first()?;
+    (line.len() >= 2 && first.is_ascii_punctuation() && line.bytes().all(|byte| byte == first))
+        .then_some(first)
+}
+
+/// Whether a line can be the title of a reStructuredText heading with the given
+/// adornment, which has to be at least as long as the title.
+fn is_rst_title(line: &str, adornment: &str) -> bool {
+    let title = line.trim();
+    !title.is_empty() && rst_adornment(line).is_none() && title.chars().count() <= adornment.len()
 }
 
+fn extend_block(block: &mut Option<Range<usize>>, line: &Range<usize>) {
+    match block {
+        Some(block) => block.end = line.end,
+        None => *block = Some(line.clone()),
+    }
+}
+
+/// Splits a text into the ranges of its chunks.
 fn chunk_text_with_syntactic_ranges(
     text: &str,
     mut syntactic_ranges: &[Range<usize>],
     size_config: ChunkSizeRange,
-) -> Vec<Chunk> {
+    unit: ChunkUnit,
+) -> Vec<Range<usize>> {
+    let sizes = TextSizes::new(text, unit);
     let mut chunks = Vec::new();
     let mut range = 0..0;
     let mut range_end_nesting_depth = 0;
@@ -141,18 +1162,13 @@ fn chunk_text_with_syntactic_ranges(
     while let Some(&line_ix) = line_ixs.peek() {
         // If the current position is beyond the maximum chunk size, then
         // start a new chunk.
-        if line_ix - range.start > size_config.max {
+        if sizes.len(range.start..line_ix) > size_config.max {
             if range.is_empty() {
-                range.end = cmp::min(range.start + size_config.max, line_ix);
-                while !text.is_char_boundary(range.end) {
-                    range.end -= 1;
-                }
+                range.end =
+                    range.start + unit.prefix_len(&text[range.start..line_ix], size_config.max);
             }
 
-            chunks.push(Chunk {
-                range: range.clone(),
-                digest: Sha256::digest(&text[range.clone()]).into(),
-            });
+            chunks.push(range.clone());
             range_end_nesting_depth = 0;
             range.start = range.end;
             continue;
@@ -181,7 +1197,7 @@ fn chunk_text_with_syntactic_ranges(
 
         // Extend the current range to this position, unless an earlier candidate
         // end position was less nested syntactically.
-        if range.len() < size_config.min || nesting_depth <= range_end_nesting_depth {
+        if sizes.len(range.clone()) < size_config.min || nesting_depth <= range_end_nesting_depth {
             range.end = line_ix;
             range_end_nesting_depth = nesting_depth;
         }
@@ -190,10 +1206,7 @@ fn chunk_text_with_syntactic_ranges(
     }
 
     if !range.is_empty() {
-        chunks.push(Chunk {
-            range: range.clone(),
-            digest: Sha256::digest(&text[range]).into(),
-        });
+        chunks.push(range);
     }
 
     chunks
@@ -202,6 +1215,7 @@ fn chunk_text_with_syntactic_ranges(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::usage::estimate_token_count;
     use language::{tree_sitter_rust, Language, LanguageConfig, LanguageMatcher};
     use unindent::Unindent as _;
 
@@ -242,10 +1256,13 @@ mod tests {
             &text,
             Some(&language),
             Path::new("lib.rs"),
+            &[],
             ChunkSizeRange {
                 min: text.find('}').unwrap(),
                 max: text.find("Self {").unwrap(),
             },
+            ChunkUnit::Bytes,
+            0,
         );
 
         // The entire impl cannot fit in a chunk, so it is split.
@@ -260,6 +1277,14 @@ mod tests {
                 "    fn last_name",
             ],
         );
+        assert_eq!(
+            chunks[0].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: Person\n\n"
+        );
+        assert_eq!(
+            chunks[3].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: Person > last_name\nSignature: impl Person\n\n"
+        );
 
         let text = "
             struct T {}
@@ -276,10 +1301,13 @@ mod tests {
             &text,
             Some(&language),
             Path::new("lib.rs"),
+            &[],
             ChunkSizeRange {
                 min: text.find('{').unwrap(),
                 max: text.find('V').unwrap(),
             },
+            ChunkUnit::Bytes,
+            0,
         );
 
         // Two single-line structs can fit in a chunk.
@@ -297,6 +1325,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_chunk_header_with_signature_and_documentation() {
+        let language = rust_language();
+        let text = "
+            impl Person {
+                /// Greets someone by name,
+                /// politely.
+                ///
+                /// Never fails.
+                pub fn greet(
+                    &self,
+                    other: &Person,
+                ) -> String {
+                    let greeting = format!(\"Hello, {}!\", other.name);
+                    let signature = format!(\"From {}\", self.name);
+                    format!(\"{greeting}\\n{signature}\")
+                }
+            }
+        "
+        .unindent();
+        let chunks = chunk_text_with_size_range(
+            &text,
+            Some(&language),
+            Path::new("lib.rs"),
+            &[],
+            ChunkSizeRange { min: 20, max: 100 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_chunks(
+            &text,
+            &chunks,
+            &[
+                "impl Person {",
+                "    pub fn greet(",
+                "        let greeting",
+                "        let signature",
+                "    }",
+            ],
+        );
+        // The first chunk contains the documentation of `greet` and the second its
+        // signature, so they're only described in the headers of the chunks without them.
+        assert_eq!(
+            chunks[0].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: Person\n\n"
+        );
+        assert_eq!(
+            chunks[1].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: Person > greet\n\
+            Documentation: Greets someone by name, politely.\n\n"
+        );
+        assert_eq!(
+            chunks[3].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: Person > greet\n\
+            Signature: pub fn greet(&self, other: &Person) -> String\n\
+            Documentation: Greets someone by name, politely.\n\n"
+        );
+
+        let (signature, body) =
+            item_signature("def parse(text):\n    \"\"\"Parses text.\n\n    More.\"\"\"\n");
+        assert_eq!(signature.as_deref(), Some("def parse(text):"));
+        assert_eq!(docstring(body).as_deref(), Some("Parses text."));
+    }
+
     #[test]
     fn test_chunk_with_long_lines() {
         let language = rust_language();
@@ -313,7 +1405,10 @@ mod tests {
             &text,
             Some(&language),
             Path::new("lib.rs"),
+            &[],
             ChunkSizeRange { min: 32, max: 64 },
+            ChunkUnit::Bytes,
+            0,
         );
 
         // The line is too long to fit in one chunk
@@ -363,7 +1458,7 @@ mod tests {
     #[track_caller]
     fn check_chunk_invariants(text: &str, chunks: &[Chunk]) {
         for (ix, chunk) in chunks.iter().enumerate() {
-            if ix > 0 && chunk.range.start != chunks[ix - 1].range.end {
+            if ix > 0 && chunk.range.start + chunk.overlap != chunks[ix - 1].range.end {
                 panic!("chunk ranges are not contiguous: {:?}", chunks);
             }
         }
@@ -377,14 +1472,526 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_chunk_with_strategy() {
+        let language = rust_language();
+        let text = "
+            impl Person {
+                fn first_name(&self) -> &str {
+                    &self.first_name
+                }
+
+                fn last_name(&self) -> &str {
+                    &self.last_name
+                }
+            }
+        "
+        .unindent();
+
+        // Chunks are split around the functions, instead of the items of the outline.
+        let chunks = chunk_text_with_size_range(
+            &text,
+            Some(&language),
+            Path::new("lib.rs"),
+            &["function_item".to_string()],
+            ChunkSizeRange { min: 16, max: 64 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_chunks(
+            &text,
+            &chunks,
+            &[
+                "impl Person {",
+                "        &self.first_name",
+                "    fn last_name",
+                "}",
+            ],
+        );
+        // The impl isn't one of the kinds of nodes, so it isn't a symbol either.
+        assert_eq!(
+            chunks[2].header,
+            "Path: lib.rs\nLanguage: Rust\nSymbols: last_name\n\n"
+        );
+
+        let strategy = ChunkingStrategy {
+            node_kinds: Vec::new(),
+            min_size: None,
+            max_size: Some(2048),
+        };
+        let size_range = ChunkConfig::default().size_range(Some(&strategy));
+        assert_eq!((size_range.min, size_range.max), (256, 2048));
+        let size_range = ChunkConfig::new(8192, Some(1024), 0.).size_range(Some(&strategy));
+        assert_eq!((size_range.min, size_range.max), (64, 512));
+        let strategy = ChunkingStrategy {
+            min_size: Some(1024),
+            ..strategy
+        };
+        let size_range = ChunkConfig::default().size_range(Some(&strategy));
+        assert_eq!((size_range.min, size_range.max), (1024, 2048));
+        let size_range = ChunkConfig::default().size_range(None);
+        assert_eq!((size_range.min, size_range.max), (1024, 8192));
+    }
+
+    #[test]
+    fn test_chunk_digest_ignores_formatting() {
+        let header = "Path: lib.rs\n\n";
+        let text = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+        let digest = chunk_digest(header, text);
+        for formatted in [
+            "fn add(a: i32, b: i32) -> i32 {\r\n    a + b\r\n}\r\n",
+            "fn add(a: i32, b: i32) -> i32 {  \n\ta + b\n\n}",
+            "fn  add(a: i32,  b: i32) -> i32 {\n        a + b\n}\n\n",
+        ] {
+            assert_eq!(chunk_digest(header, formatted), digest, "{formatted:?}");
+        }
+        assert_ne!(
+            chunk_digest(header, "fn add(a: i32, b: i32) -> i32 {\n    a - b\n}\n"),
+            digest
+        );
+        assert_ne!(
+            chunk_digest(header, "fn add(a: i32, b: i32) -> i32 {\n    ab\n}\n"),
+            digest
+        );
+        assert_ne!(chunk_digest("Path: main.rs\n\n", text), digest);
+    }
+
     #[test]
     fn test_chunk_text() {
         let text = "a\n".repeat(1000);
-        let chunks = chunk_text(&text, None, Path::new("lib.rs"));
+        let chunks = chunk_text(
+            &text,
+            None,
+            Path::new("lib.rs"),
+            ChunkConfig::default(),
+            None,
+            &estimate_token_count,
+        );
         assert_eq!(
             chunks.len(),
             ((2000_f64) / (CHUNK_SIZE_RANGE.max as f64)).ceil() as usize
         );
+        assert_eq!(chunks[0].header, "Path: lib.rs\n\n");
+        assert_eq!(
+            chunks[0].text_to_embed(&text),
+            format!("Path: lib.rs\n\n{}", &text[chunks[0].range.clone()])
+        );
+
+        let moved = chunks[0].moved(Path::new("lib.rs"), Path::new("src/lib.rs"));
+        assert_eq!(moved.header, "Path: src/lib.rs\n\n");
+        assert_eq!(moved.digest, chunks[0].digest);
+    }
+
+    #[test]
+    fn test_chunk_overlap() {
+        let text = (0..100)
+            .map(|ix| format!("line {ix}\n"))
+            .collect::<Vec<_>>()
+            .join("");
+        let chunks = chunk_text_with_size_range(
+            &text,
+            None,
+            Path::new("lib.txt"),
+            &[],
+            ChunkSizeRange { min: 100, max: 200 },
+            ChunkUnit::Bytes,
+            50,
+        );
+        check_chunk_invariants(&text, &chunks);
+        assert_eq!(chunks[0].overlap, 0);
+        for chunk in &chunks[1..] {
+            // Chunks repeat whole lines of the previous chunk.
+            assert!(chunk.overlap > 0 && chunk.overlap <= 50, "{chunk:?}");
+            assert!(text[..chunk.range.start].ends_with('\n'));
+        }
+
+        // Chunks are sized by their own text, without what they repeat.
+        let without_overlap = chunk_text_with_size_range(
+            &text,
+            None,
+            Path::new("lib.txt"),
+            &[],
+            ChunkSizeRange { min: 100, max: 200 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.range.start + chunk.overlap..chunk.range.end)
+                .collect::<Vec<_>>(),
+            without_overlap
+                .iter()
+                .map(|chunk| chunk.range.clone())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            ChunkConfig::new(100, None, 80.),
+            ChunkConfig {
+                max_size: 256,
+                overlap: 128,
+                max_tokens: None,
+            }
+        );
+        assert_eq!(
+            ChunkConfig::new(8192, Some(20_000), 10.),
+            ChunkConfig {
+                max_size: 8192,
+                overlap: 819,
+                max_tokens: Some(8192),
+            }
+        );
+    }
+
+    #[test]
+    fn test_streaming_chunker() {
+        let text = (0..200)
+            .map(|ix| format!("line {ix}: {}\n", "é".repeat(ix % 7)))
+            .collect::<Vec<_>>()
+            .join("");
+        let path = Path::new("server.log");
+        for (max_tokens, overlap) in [(None, 0), (None, 64), (Some(64), 0), (Some(64), 64)] {
+            let config = ChunkConfig {
+                max_size: 256,
+                overlap,
+                max_tokens,
+            };
+            let expected_chunks =
+                chunk_text(&text, None, path, config, None, &estimate_token_count);
+
+            // Blocks end within characters and lines, like blocks read from a file.
+            let mut chunker =
+                StreamingChunker::new(path, None, config, None, &estimate_token_count);
+            let mut block_start = 0;
+            for block_end in (100..text.len()).step_by(100).chain([text.len()]) {
+                let bytes = &text.as_bytes()[block_start..block_end];
+                let valid_len = match std::str::from_utf8(bytes) {
+                    Ok(_) => bytes.len(),
+                    Err(error) => error.valid_up_to(),
+                };
+                chunker.push(&text[block_start..block_start + valid_len]);
+                block_start += valid_len;
+            }
+            let (digest, chunks) = chunker.finish();
+
+            assert_eq!(digest, text_digest(&text));
+            assert_eq!(
+                chunks
+                    .iter()
+                    .map(|(chunk, _)| (chunk.range.clone(), chunk.overlap, chunk.digest))
+                    .collect::<Vec<_>>(),
+                expected_chunks
+                    .iter()
+                    .map(|chunk| (chunk.range.clone(), chunk.overlap, chunk.digest))
+                    .collect::<Vec<_>>()
+            );
+            for (chunk, text_to_embed) in &chunks {
+                assert_eq!(*text_to_embed, chunk.text_to_embed(&text));
+            }
+        }
+
+        // Lines longer than a chunk are split between chunks.
+        let text = "a".repeat(600);
+        let mut chunker = StreamingChunker::new(
+            path,
+            None,
+            ChunkConfig::new(256, None, 0.),
+            None,
+            &estimate_token_count,
+        );
+        chunker.push(&text);
+        let (_, chunks) = chunker.finish();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|(chunk, _)| chunk.range.clone())
+                .collect::<Vec<_>>(),
+            [0..256, 256..512, 512..600]
+        );
+    }
+
+    #[test]
+    fn test_chunk_by_tokens() {
+        // Counts a token per character, so that scripts with more bytes per character
+        // get fewer bytes per chunk.
+        let count_tokens = |text: &str| text.chars().count() as u64;
+        let text = [
+            "The quick brown fox jumps over the lazy dog.\n",
+            "Съешь же ещё этих мягких французских булок.\n",
+            "いろはにほへと ちりぬるを わかよたれそ つねならむ\n",
+            "🦀🦀🦀 crabs 🦀🦀🦀\n",
+        ]
+        .repeat(8)
+        .concat();
+        let path = Path::new("pangrams.txt");
+        let config = ChunkConfig {
+            max_size: 256,
+            overlap: 0,
+            max_tokens: Some(64),
+        };
+        let chunks = chunk_text(&text, None, path, config, None, &count_tokens);
+        check_chunk_invariants(&text, &chunks);
+        for chunk in &chunks {
+            let tokens = count_tokens(&text[chunk.range.clone()]);
+            assert!(tokens <= 64, "{tokens} tokens in {chunk:?}");
+            assert!(text[..chunk.range.end].ends_with('\n'));
+        }
+        // Chunks are sized in tokens, not bytes.
+        let byte_chunks = chunk_text(
+            &text,
+            None,
+            path,
+            ChunkConfig {
+                max_tokens: None,
+                ..config
+            },
+            None,
+            &count_tokens,
+        );
+        assert!(chunks.len() > byte_chunks.len());
+
+        // Lines longer than a chunk are split between characters.
+        let text = "日本語".repeat(50);
+        let chunks = chunk_text(&text, None, path, config, None, &count_tokens);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| count_tokens(&text[chunk.range.clone()]))
+                .collect::<Vec<_>>(),
+            [64, 64, 22]
+        );
+    }
+
+    #[test]
+    fn test_chunk_markdown_by_headings() {
+        let text = "
+            ---
+            title: Guide
+            ---
+
+            # Guide
+
+            Zed is a code editor,
+            built for speed.
+
+            ## Installation
+
+            Run the install script:
+
+            ```sh
+            # Not a heading.
+
+            curl -f https://zed.dev/install.sh | sh
+            ```
+
+            Configuration
+            -------------
+
+            Settings live in settings.json,
+            next to keymap.json.
+
+            # Reference
+
+            Every setting is documented.
+        "
+        .unindent();
+
+        let chunks = chunk_text_with_size_range(
+            &text,
+            None,
+            Path::new("docs/guide.md"),
+            &[],
+            ChunkSizeRange { min: 40, max: 120 },
+            ChunkUnit::Bytes,
+            0,
+        );
+
+        // Chunks end where sections do, instead of mid-paragraph or mid-code block.
+        assert_chunks(
+            &text,
+            &chunks,
+            &["---", "## Installation", "Configuration", "# Reference"],
+        );
+        assert_eq!(
+            chunks[0].header, "Path: docs/guide.md\n\n",
+            "front matter isn't in a section"
+        );
+        assert_eq!(
+            chunks[1].header,
+            "Path: docs/guide.md\nHeadings: Guide > Installation\n\n"
+        );
+        assert_eq!(
+            chunks[2].header,
+            "Path: docs/guide.md\nHeadings: Guide > Configuration\n\n"
+        );
+        assert_eq!(
+            chunks[3].header,
+            "Path: docs/guide.md\nHeadings: Reference\n\n"
+        );
+        assert!(chunks[1]
+            .text_to_embed(&text)
+            .starts_with("Path: docs/guide.md\nHeadings: Guide > Installation\n\n## Installation"));
+    }
+
+    #[test]
+    fn test_chunk_restructured_text_by_headings() {
+        let text = "
+            ======
+            Manual
+            ======
+
+            Introduction
+            ============
+
+            Zed is a code editor,
+            built for speed.
+
+            Setup
+            -----
+
+            Run the install script,
+            then open a project
+            and start typing.
+
+            Usage
+            =====
+
+            Open a project.
+        "
+        .unindent();
+
+        let chunks = chunk_text_with_size_range(
+            &text,
+            None,
+            Path::new("docs/manual.rst"),
+            &[],
+            ChunkSizeRange { min: 40, max: 100 },
+            ChunkUnit::Bytes,
+            0,
+        );
+
+        assert_chunks(&text, &chunks, &["======", "Setup", "Usage"]);
+        assert_eq!(
+            chunks[1].header,
+            "Path: docs/manual.rst\nHeadings: Manual > Introduction > Setup\n\n"
+        );
+        assert_eq!(
+            chunks[2].header,
+            "Path: docs/manual.rst\nHeadings: Manual > Usage\n\n"
+        );
+    }
+
+    #[test]
+    fn test_chunk_notebook() {
+        let text = r##"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": ["# Loading data\n", "With pandas."]
+  },
+  {
+   "cell_type": "code",
+   "execution_count": 1,
+   "metadata": {},
+   "outputs": [],
+   "source": [
+    "import pandas as pd\n",
+    "df = pd.read_csv(\"data.csv\")\n",
+    "df.head()"
+   ]
+  }
+ ],
+ "metadata": {},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+        let chunks = chunk_text_with_size_range(
+            text,
+            None,
+            Path::new("analysis.ipynb"),
+            &[],
+            ChunkSizeRange { min: 16, max: 64 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.cell, chunk.text(text).into_owned()))
+                .collect::<Vec<_>>(),
+            [
+                (Some(0), "# Loading data\nWith pandas.".to_string()),
+                (
+                    Some(1),
+                    "import pandas as pd\ndf = pd.read_csv(\"data.csv\")\n".to_string()
+                ),
+                (Some(1), "df.head()".to_string()),
+            ]
+        );
+        // Ranges are in the JSON of the notebook.
+        assert_eq!(&text[chunks[2].range.clone()], "\"df.head()\"");
+        assert_eq!(
+            chunks[1].text_to_embed(text),
+            "Path: analysis.ipynb\nCell: 1 (code)\n\n\
+            import pandas as pd\ndf = pd.read_csv(\"data.csv\")\n"
+        );
+        assert_ne!(chunks[1].digest, chunks[2].digest);
+
+        // Notebooks that can't be parsed are chunked as text.
+        let text = "{\"cells\": [";
+        let chunks = chunk_text_with_size_range(
+            text,
+            None,
+            Path::new("analysis.ipynb"),
+            &[],
+            ChunkSizeRange { min: 16, max: 64 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_chunks(text, &chunks, &["{"]);
+        assert_eq!(chunks[0].cell, None);
+    }
+
+    #[test]
+    fn test_chunk_structured_data_by_keys() {
+        let text = r#"{
+  "name": "app",
+  "scripts": {
+    "build": "tsc",
+    "test": "jest"
+  },
+  "dependencies": {
+    "react": "^18.0.0",
+    "react-dom": "^18.0.0",
+    "typescript": "^5.0.0"
+  }
+}
+"#;
+        let chunks = chunk_text_with_size_range(
+            text,
+            None,
+            Path::new("package.json"),
+            &[],
+            ChunkSizeRange { min: 20, max: 80 },
+            ChunkUnit::Bytes,
+            0,
+        );
+        assert_chunks(
+            text,
+            &chunks,
+            &["{", "  \"dependencies\"", "    \"typescript\""],
+        );
+        assert_eq!(chunks[0].header, "Path: package.json\n\n");
+        assert_eq!(
+            chunks[1].header,
+            "Path: package.json\nKeys: dependencies\n\n"
+        );
+        assert_eq!(
+            chunks[2].header,
+            "Path: package.json\nKeys: dependencies > typescript\n\n"
+        );
     }
 
     fn rust_language() -> Arc<Language> {
@@ -396,6 +2003,7 @@ mod tests {
                         path_suffixes: vec!["rs".to_string()],
                         ..Default::default()
                     },
+                    line_comments: vec!["// ".into(), "/// ".into()],
                     ..Default::default()
                 },
                 Some(tree_sitter_rust::language()),
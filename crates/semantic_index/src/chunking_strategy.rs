@@ -0,0 +1,66 @@
+use collections::HashMap;
+use gpui::{AppContext, Global};
+use language::LanguageName;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// How the files of a language are split into chunks, instead of at the items of the
+/// language's outline.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkingStrategy {
+    /// The kinds of the syntax nodes chunks should start and end around, like
+    /// `function_definition`, named by their `name` field. The items of the language's
+    /// outline are used when empty.
+    pub node_kinds: Vec<String>,
+    /// The least bytes a chunk spans, unless its file is smaller, instead of an eighth
+    /// of the largest chunk.
+    pub min_size: Option<usize>,
+    /// The most bytes a chunk spans, instead of the `chunk_size` setting. When chunks
+    /// are sized in tokens, sizes are converted at about four bytes per token.
+    pub max_size: Option<usize>,
+}
+
+#[derive(Default)]
+struct GlobalChunkingStrategyRegistry(Arc<ChunkingStrategyRegistry>);
+
+impl Global for GlobalChunkingStrategyRegistry {}
+
+/// The [`ChunkingStrategy`] of each language that has one, which languages and
+/// extensions can register at any time. Files are chunked with the strategy of their
+/// language when they're next indexed.
+#[derive(Default)]
+pub struct ChunkingStrategyRegistry {
+    strategies: RwLock<HashMap<LanguageName, Arc<ChunkingStrategy>>>,
+}
+
+impl ChunkingStrategyRegistry {
+    /// Returns the global [`ChunkingStrategyRegistry`].
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalChunkingStrategyRegistry>().0.clone()
+    }
+
+    /// Returns the global [`ChunkingStrategyRegistry`].
+    ///
+    /// Inserts a default [`ChunkingStrategyRegistry`] if one does not yet exist.
+    pub fn default_global(cx: &mut AppContext) -> Arc<Self> {
+        cx.default_global::<GlobalChunkingStrategyRegistry>()
+            .0
+            .clone()
+    }
+
+    /// Chunks the files of the given language with the given strategy, replacing the
+    /// one it had.
+    pub fn register(&self, language: LanguageName, strategy: ChunkingStrategy) {
+        self.strategies.write().insert(language, Arc::new(strategy));
+    }
+
+    /// Chunks the files of the given language at the items of its outline again.
+    pub fn unregister(&self, language: &LanguageName) {
+        self.strategies.write().remove(language);
+    }
+
+    /// Returns the strategy registered for the given language, if any.
+    pub fn strategy(&self, language: &LanguageName) -> Option<Arc<ChunkingStrategy>> {
+        self.strategies.read().get(language).cloned()
+    }
+}
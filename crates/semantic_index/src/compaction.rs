@@ -0,0 +1,135 @@
+use anyhow::{Context as _, Result};
+use heed::CompactionOption;
+use std::path::Path;
+
+use crate::db_connection::DbConnection;
+
+pub(crate) const DATA_FILE_NAME: &str = "data.mdb";
+/// The compacted copy of the database while it's being written, so that a partial
+/// copy never replaces the database.
+const COMPACTING_FILE_NAME: &str = "data.mdb.compacting";
+/// The compacted copy of the database, waiting to replace it when it's next opened.
+const COMPACTED_FILE_NAME: &str = "data.mdb.compacted";
+
+/// How compacting the database of the semantic index went.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Compaction {
+    /// How many files were removed from the index because they were deleted or
+    /// shouldn't be indexed anymore.
+    pub removed_file_count: usize,
+    /// The size of the database on disk before compacting it, in bytes.
+    pub size_before: u64,
+    /// The size of the compacted copy of the database, in bytes.
+    pub size_after: u64,
+}
+
+impl Compaction {
+    /// Describes the compaction, for users to know how much space it frees.
+    pub fn summary(&self) -> String {
+        let freed_megabytes = self.size_before.saturating_sub(self.size_after) as f64 / 1e6;
+        format!(
+            "Removed {} stale files from the semantic index, which will take {freed_megabytes:.1} MB less once Zed restarts",
+            self.removed_file_count
+        )
+    }
+}
+
+/// The stages of [`crate::SemanticIndex::compact`], reported as it goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactionProgress {
+    CollectingGarbage,
+    Copying { removed_file_count: usize },
+    Finished(Compaction),
+}
+
+/// Writes a copy of the database without its free pages, returning the size of the
+/// database and of the copy. LMDB reuses the pages of deleted data but never gives
+/// them back to the file system, so the database only shrinks by replacing it.
+pub(crate) fn write_compacted_copy(db_connection: &DbConnection) -> Result<(u64, u64)> {
+    let compacting_path = db_connection.path().join(COMPACTING_FILE_NAME);
+    // A copy left by an interrupted compaction is outdated.
+    if compacting_path.exists() {
+        std::fs::remove_file(&compacting_path)?;
+    }
+    let size_before = db_connection.real_disk_size()?;
+    let file = db_connection
+        .copy_to_file(&compacting_path, CompactionOption::Enabled)
+        .context("copying the database")?;
+    file.sync_all()?;
+    let size_after = file.metadata()?.len();
+    std::fs::rename(
+        &compacting_path,
+        db_connection.path().join(COMPACTED_FILE_NAME),
+    )?;
+    Ok((size_before, size_after))
+}
+
+/// Replaces the database at the given path with its compacted copy, if one was
+/// written since the database was last opened. Must be called before opening it.
+pub(crate) fn replace_with_compacted_copy(db_path: &Path) -> Result<()> {
+    let compacted_path = db_path.join(COMPACTED_FILE_NAME);
+    if compacted_path.exists() {
+        std::fs::rename(&compacted_path, db_path.join(DATA_FILE_NAME))
+            .context("replacing the database with its compacted copy")?;
+        log::info!("replaced the semantic index database with its compacted copy");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_connection::{TEST_MAP_SIZE, TEST_MAX_DBS};
+    use heed::types::{Bytes, Str};
+    use std::ops::Bound;
+
+    #[test]
+    fn test_compaction() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let open = || DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+
+        let env = open();
+        let mut txn = env.write_txn().unwrap();
+        let db = env
+            .create_database::<Str, Bytes>(&mut txn, Some("files"))
+            .unwrap();
+        for ix in 0..1000 {
+            db.put(&mut txn, &format!("file {ix}"), &[0; 4096]).unwrap();
+        }
+        txn.commit().unwrap();
+        let mut txn = env.write_txn().unwrap();
+        db.delete_range(&mut txn, &(Bound::Unbounded, Bound::Excluded("file 999")))
+            .unwrap();
+        txn.commit().unwrap();
+
+        let (size_before, size_after) = write_compacted_copy(&env).unwrap();
+        assert!(size_after < size_before / 10);
+        // Writing another copy replaces the previous one.
+        write_compacted_copy(&env).unwrap();
+        env.prepare_for_closing().wait();
+
+        replace_with_compacted_copy(temp_dir.path()).unwrap();
+        assert!(!temp_dir.path().join(COMPACTED_FILE_NAME).exists());
+        let env = open();
+        let txn = env.read_txn().unwrap();
+        let db = env
+            .open_database::<Str, Bytes>(&txn, Some("files"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.len(&txn).unwrap(), 1);
+        assert_eq!(db.get(&txn, "file 999").unwrap(), Some(&[0; 4096][..]));
+    }
+
+    #[test]
+    fn test_compaction_summary() {
+        let compaction = Compaction {
+            removed_file_count: 999,
+            size_before: 5_000_000,
+            size_after: 300_000,
+        };
+        assert_eq!(
+            compaction.summary(),
+            "Removed 999 stale files from the semantic index, which will take 4.7 MB less once Zed restarts"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+/// Expands a range of a text to the whole lines it touches, including their trailing
+/// newlines. Returns the expanded range along with the zero-based rows it spans.
+pub(crate) fn expand_to_lines(text: &str, range: Range<usize>) -> (Range<usize>, Range<u32>) {
+    let mut start = range.start.min(text.len());
+    let mut end = range.end.clamp(start, text.len());
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let start = text[..start].rfind('\n').map_or(0, |ix| ix + 1);
+    let end = if end > start && text[..end].ends_with('\n') {
+        end
+    } else {
+        text[end..].find('\n').map_or(text.len(), |ix| end + ix + 1)
+    };
+
+    let start_row = text[..start].matches('\n').count() as u32;
+    let excerpt = &text[start..end];
+    let mut row_count = excerpt.matches('\n').count() as u32;
+    if !excerpt.is_empty() && !excerpt.ends_with('\n') {
+        row_count += 1;
+    }
+    (start..end, start_row..start_row + row_count)
+}
+
+/// Picks the candidates to fit within a token budget, in order of preference. A
+/// candidate that doesn't fit is skipped whole, so that a less preferred one that
+/// does fit can still be picked.
+pub(crate) fn fit_to_budget<T>(
+    candidates: Vec<T>,
+    token_budget: usize,
+    token_count: impl Fn(&T) -> usize,
+) -> Vec<T> {
+    let mut remaining_budget = token_budget;
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            let token_count = token_count(candidate);
+            if token_count <= remaining_budget {
+                remaining_budget -= token_count;
+                true
+            } else {
+                false
+            }
+        })
+        .collect()
+}
+
+/// Returns the length of the longest prefix of whole lines of a text that fits within
+/// a token budget, counting the tokens of every line separately.
+pub(crate) fn truncate_lines_to_budget(
+    text: &str,
+    token_budget: usize,
+    token_count: impl Fn(&str) -> usize,
+) -> usize {
+    let mut remaining_budget = token_budget;
+    let mut len = 0;
+    for line in text.split_inclusive('\n') {
+        let token_count = token_count(line);
+        if token_count > remaining_budget {
+            break;
+        }
+        remaining_budget -= token_count;
+        len += line.len();
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_to_lines() {
+        let text = "fn a() {}\nfn b() {\n    1\n}\nfn c() {}";
+        assert_eq!(expand_to_lines(text, 0..9), (0..10, 0..1));
+        assert_eq!(expand_to_lines(text, 14..20), (10..25, 1..3));
+        assert_eq!(expand_to_lines(text, 10..27), (10..27, 1..4));
+        assert_eq!(expand_to_lines(text, 30..32), (27..36, 4..5));
+        assert_eq!(expand_to_lines(text, 30..100), (27..36, 4..5));
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let words = |text: &str| text.split_whitespace().count();
+        let candidates = vec!["a b c", "d e f g h", "i j"];
+        assert_eq!(
+            fit_to_budget(candidates.clone(), 6, |text| words(text)),
+            vec!["a b c", "i j"]
+        );
+        assert_eq!(
+            fit_to_budget(candidates.clone(), 100, |text| words(text)),
+            candidates
+        );
+        assert!(fit_to_budget(candidates, 1, |text| words(text)).is_empty());
+
+        assert_eq!(truncate_lines_to_budget("a b\nc d\ne f\n", 5, words), 8);
+        assert_eq!(truncate_lines_to_budget("a b c\nd\n", 2, words), 0);
+    }
+}
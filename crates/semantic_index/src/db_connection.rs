@@ -0,0 +1,683 @@
+use anyhow::{anyhow, Context as _, Result};
+use collections::HashMap;
+use heed::{
+    types::{Bytes, DecodeIgnore, Str},
+    CompactionOption,
+};
+use parking_lot::{Condvar, Mutex};
+use std::{
+    fs::File,
+    ops::{Bound, Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Weak},
+    thread::{self, ThreadId},
+};
+use util::ResultExt;
+
+use crate::{
+    chunking::text_digest,
+    compaction::{self, DATA_FILE_NAME},
+};
+
+#[cfg(test)]
+pub(crate) const TEST_MAP_SIZE: u64 = 64 * 1024 * 1024;
+#[cfg(test)]
+pub(crate) const TEST_MAX_DBS: u32 = 32;
+/// Memory maps must be a multiple of the page size, which is at most 64 KiB.
+const MAP_SIZE_ALIGNMENT: usize = 1024 * 1024;
+/// The shared database held the databases of every worktree before each worktree got
+/// a database of its own, so it still needs room for as many.
+const SHARED_MAX_DBS: u32 = 3000;
+/// Leaves room for more databases than every worktree has.
+const WORKTREE_MAX_DBS: u32 = 32;
+/// How many entries to move from the shared database to a worktree's database per
+/// transaction, which LMDB limits the size of.
+const MOVE_BATCH_SIZE: usize = 4096;
+const WORKTREES_DIR_NAME: &str = "worktrees";
+const WORKTREE_LOCATIONS_DB_NAME: &str = "worktree_locations";
+
+/// The connection to a database of the semantic index, which grows the memory map of
+/// the database when it's full instead of failing to write to it.
+#[derive(Clone)]
+pub(crate) struct DbConnection(Arc<DbConnectionState>);
+
+struct DbConnectionState {
+    env: heed::Env,
+    transactions: OpenTransactions,
+}
+
+/// The connections that are open, so that opening a database that's already open
+/// shares its connection. LMDB only allows opening a database once per process.
+static OPEN_CONNECTIONS: LazyLock<Mutex<HashMap<PathBuf, Weak<DbConnectionState>>>> =
+    LazyLock::new(Default::default);
+
+/// Counts the transactions open in this process, since LMDB can only resize the
+/// memory map while none are. They're counted by thread, so that a thread doesn't wait
+/// for its own transactions to close.
+#[derive(Default)]
+struct OpenTransactions {
+    count_by_thread: Mutex<HashMap<ThreadId, usize>>,
+    closed: Condvar,
+}
+
+/// Keeps the memory map from being resized while a transaction is open.
+struct TransactionGuard<'a> {
+    transactions: &'a OpenTransactions,
+    thread_id: ThreadId,
+}
+
+impl<'a> TransactionGuard<'a> {
+    fn new(transactions: &'a OpenTransactions) -> Self {
+        let thread_id = thread::current().id();
+        *transactions
+            .count_by_thread
+            .lock()
+            .entry(thread_id)
+            .or_default() += 1;
+        Self {
+            transactions,
+            thread_id,
+        }
+    }
+}
+
+impl Drop for TransactionGuard<'_> {
+    fn drop(&mut self) {
+        let mut count_by_thread = self.transactions.count_by_thread.lock();
+        if let Some(count) = count_by_thread.get_mut(&self.thread_id) {
+            *count -= 1;
+            if *count == 0 {
+                count_by_thread.remove(&self.thread_id);
+            }
+        }
+        if count_by_thread.is_empty() {
+            self.transactions.closed.notify_all();
+        }
+    }
+}
+
+pub(crate) struct ReadTxn<'a> {
+    txn: heed::RoTxn<'a>,
+    _guard: TransactionGuard<'a>,
+}
+
+impl<'a> Deref for ReadTxn<'a> {
+    type Target = heed::RoTxn<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.txn
+    }
+}
+
+pub(crate) struct WriteTxn<'a> {
+    txn: heed::RwTxn<'a>,
+    _guard: TransactionGuard<'a>,
+}
+
+impl<'a> WriteTxn<'a> {
+    pub fn commit(self) -> heed::Result<()> {
+        self.txn.commit()
+    }
+}
+
+impl<'a> Deref for WriteTxn<'a> {
+    type Target = heed::RwTxn<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.txn
+    }
+}
+
+impl DerefMut for WriteTxn<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.txn
+    }
+}
+
+impl DbConnection {
+    /// Opens the database in the given directory, creating it if needed, with a memory
+    /// map of at least the given size. The memory map is as large as the database if
+    /// it's larger. Returns the existing connection if the database is already open.
+    pub fn open(path: &Path, map_size: u64, max_dbs: u32) -> Result<Self> {
+        let mut open_connections = OPEN_CONNECTIONS.lock();
+        if let Some(state) = open_connections.get(path).and_then(Weak::upgrade) {
+            return Ok(Self(state));
+        }
+
+        std::fs::create_dir_all(path)?;
+        compaction::replace_with_compacted_copy(path).log_err();
+        let env = unsafe {
+            heed::EnvOpenOptions::new()
+                .map_size(align_map_size(map_size as usize))
+                .max_dbs(max_dbs)
+                .open(path)?
+        };
+        let state = Arc::new(DbConnectionState {
+            env,
+            transactions: OpenTransactions::default(),
+        });
+        open_connections.retain(|_, state| state.strong_count() > 0);
+        open_connections.insert(path.to_path_buf(), Arc::downgrade(&state));
+        Ok(Self(state))
+    }
+
+    pub fn read_txn(&self) -> heed::Result<ReadTxn<'_>> {
+        let guard = TransactionGuard::new(&self.0.transactions);
+        Ok(ReadTxn {
+            txn: self.0.env.read_txn()?,
+            _guard: guard,
+        })
+    }
+
+    pub fn write_txn(&self) -> heed::Result<WriteTxn<'_>> {
+        let guard = TransactionGuard::new(&self.0.transactions);
+        Ok(WriteTxn {
+            txn: self.0.env.write_txn()?,
+            _guard: guard,
+        })
+    }
+
+    /// Writes to the database in a transaction, growing the memory map and writing
+    /// again if the database is full. Must not be called with a transaction open.
+    pub fn write<T>(&self, mut write: impl FnMut(&mut heed::RwTxn) -> Result<T>) -> Result<T> {
+        loop {
+            let (result, map_size) = {
+                let mut txn = self.write_txn()?;
+                let map_size = self.0.env.info().map_size;
+                let result = write(&mut txn).and_then(|value| {
+                    txn.commit()?;
+                    Ok(value)
+                });
+                (result, map_size)
+            };
+            match result {
+                Err(error) if is_map_full(&error) => self.grow(map_size)?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Doubles the memory map that was full at the given size, once every transaction
+    /// is closed. Does nothing if another write grew it meanwhile. Fails if this thread
+    /// has a transaction open, which would never close while waiting.
+    fn grow(&self, full_map_size: usize) -> Result<()> {
+        let mut count_by_thread = self.0.transactions.count_by_thread.lock();
+        if count_by_thread.contains_key(&thread::current().id()) {
+            return Err(anyhow!(
+                "the database is full, and can't grow while this thread reads from it"
+            ));
+        }
+        while !count_by_thread.is_empty() {
+            self.0.transactions.closed.wait(&mut count_by_thread);
+        }
+        if self.0.env.info().map_size > full_map_size {
+            return Ok(());
+        }
+        let map_size = align_map_size(full_map_size.saturating_mul(2));
+        // Safety: no transaction is open, and none can open until `count_by_thread` is
+        // unlocked.
+        unsafe { self.0.env.resize(map_size)? };
+        log::info!("grew the semantic index database to {map_size} bytes");
+        Ok(())
+    }
+
+    /// Copies the database to a file, like [`heed::Env::copy_to_file`], which reads
+    /// it in a transaction of its own.
+    pub fn copy_to_file(&self, path: &Path, option: CompactionOption) -> heed::Result<File> {
+        let _guard = TransactionGuard::new(&self.0.transactions);
+        self.0.env.copy_to_file(path, option)
+    }
+
+    #[cfg(test)]
+    pub fn prepare_for_closing(self) -> heed::EnvClosingEvent {
+        Arc::into_inner(self.0)
+            .expect("the connection is still in use")
+            .env
+            .prepare_for_closing()
+    }
+}
+
+impl Deref for DbConnection {
+    type Target = heed::Env;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.env
+    }
+}
+
+/// The databases of the semantic index: one per worktree, so that indexing worktrees
+/// doesn't serialize on writing to a single database, and one shared by every
+/// worktree, for the embedding cache.
+#[derive(Clone)]
+pub(crate) struct IndexDatabases {
+    path: Arc<Path>,
+    map_size: u64,
+    shared: DbConnection,
+}
+
+impl IndexDatabases {
+    /// Opens the databases in the given directory, or in the default one if none is
+    /// given. Moves them there first if they were somewhere else when they were last
+    /// opened, which is recorded in a file next to the default directory.
+    pub fn open(default_path: &Path, path: Option<&Path>, map_size: u64) -> Result<Self> {
+        let mut location_path = default_path.as_os_str().to_owned();
+        location_path.push(".location");
+        let location_path = PathBuf::from(location_path);
+        let previous_path = std::fs::read_to_string(&location_path)
+            .map_or_else(|_| default_path.to_path_buf(), PathBuf::from);
+        let path = relocate(&previous_path, path.unwrap_or(default_path));
+        if path == default_path {
+            if location_path.exists() {
+                std::fs::remove_file(&location_path)?;
+            }
+        } else {
+            std::fs::write(&location_path, path.to_string_lossy().as_bytes())?;
+        }
+
+        Ok(Self {
+            shared: DbConnection::open(&path, map_size, SHARED_MAX_DBS)?,
+            path: path.into(),
+            map_size,
+        })
+    }
+
+    pub fn shared(&self) -> &DbConnection {
+        &self.shared
+    }
+
+    /// Opens the database of the worktree with the given name, in a directory named
+    /// after a digest of the name, so that it stays the same across sessions. The
+    /// directory is in the given one, or next to the shared database if none is given,
+    /// and is moved there if it was somewhere else when it was last opened. Moves the
+    /// data of the worktree out of the shared database, if it's still there.
+    pub fn open_worktree(&self, db_name: &str, root: Option<&Path>) -> Result<DbConnection> {
+        let dir_name = hex::encode(&text_digest(db_name)[..16]);
+        let default_path = self.worktrees_path().join(&dir_name);
+        let path = root.map_or_else(
+            || default_path.clone(),
+            |root| root.join(WORKTREES_DIR_NAME).join(&dir_name),
+        );
+        let locations = self.worktree_locations()?;
+        let previous_path = {
+            let txn = self.shared.read_txn()?;
+            locations
+                .get(&txn, db_name)?
+                .map_or_else(|| default_path.clone(), PathBuf::from)
+        };
+        let path = relocate(&previous_path, &path);
+        self.shared.write(|txn| {
+            if path == default_path {
+                locations.delete(txn, db_name)?;
+            } else {
+                locations.put(txn, db_name, &path.to_string_lossy())?;
+            }
+            Ok(())
+        })?;
+
+        let db_connection = DbConnection::open(&path, self.map_size, WORKTREE_MAX_DBS)?;
+        move_worktree_databases(&self.shared, &db_connection, db_name)
+            .context("moving the worktree out of the shared database")?;
+        Ok(db_connection)
+    }
+
+    /// Opens every database, including those of worktrees that aren't loaded.
+    pub fn all(&self) -> Result<Vec<DbConnection>> {
+        let mut paths = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(self.worktrees_path()) {
+            for entry in entries {
+                paths.push(entry?.path());
+            }
+        }
+        let locations = self.worktree_locations()?;
+        let txn = self.shared.read_txn()?;
+        for entry in locations.iter(&txn)? {
+            let (_, path) = entry?;
+            paths.push(PathBuf::from(path));
+        }
+        drop(txn);
+        paths.sort();
+        paths.dedup();
+
+        let mut db_connections = vec![self.shared.clone()];
+        for path in paths {
+            if path.is_dir() {
+                db_connections.push(DbConnection::open(&path, self.map_size, WORKTREE_MAX_DBS)?);
+            }
+        }
+        Ok(db_connections)
+    }
+
+    fn worktrees_path(&self) -> PathBuf {
+        self.path.join(WORKTREES_DIR_NAME)
+    }
+
+    /// The directories of the worktrees whose database isn't next to the shared one,
+    /// keyed by the name of the worktree.
+    fn worktree_locations(&self) -> Result<heed::Database<Str, Str>> {
+        self.shared.write(|txn| {
+            Ok(self
+                .shared
+                .create_database(txn, Some(WORKTREE_LOCATIONS_DB_NAME))?)
+        })
+    }
+}
+
+/// Moves the database in one directory to another, returning the directory to open
+/// it in. It stays where it was if it's open or can't be moved, and it isn't moved if
+/// the other directory already has a database, which is opened instead.
+fn relocate(from: &Path, to: &Path) -> PathBuf {
+    if from == to || !from.exists() {
+        return to.to_path_buf();
+    }
+    if to.join(DATA_FILE_NAME).exists() {
+        log::info!("not moving the semantic index in {from:?} to {to:?}, which already has one");
+        return to.to_path_buf();
+    }
+    if OPEN_CONNECTIONS
+        .lock()
+        .get(from)
+        .map_or(false, |state| state.strong_count() > 0)
+    {
+        log::info!("not moving the semantic index in {from:?} to {to:?} while it's open");
+        return from.to_path_buf();
+    }
+    match move_dir(from, to) {
+        Ok(()) => {
+            log::info!("moved the semantic index in {from:?} to {to:?}");
+            to.to_path_buf()
+        }
+        Err(error) => {
+            log::error!("failed to move the semantic index in {from:?} to {to:?}: {error:?}");
+            from.to_path_buf()
+        }
+    }
+}
+
+/// Moves a directory, copying it when it can't be renamed, like across file systems.
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Renaming onto an empty directory fails on some platforms.
+    if to.is_dir() {
+        std::fs::remove_dir(to)?;
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir(from, to).context("copying the directory")?;
+    std::fs::remove_dir_all(from)?;
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            std::fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the databases of a worktree from the shared database, where the databases of
+/// every worktree were kept before each worktree got a database of its own, to the
+/// worktree's database. They're cleared in the shared database once they're moved,
+/// so moving them again after an interruption is safe.
+///
+/// Moves them in a write transaction of the shared database, since LMDB only allows
+/// opening databases in one transaction at a time.
+fn move_worktree_databases(
+    shared: &DbConnection,
+    worktree: &DbConnection,
+    db_name: &str,
+) -> Result<()> {
+    let moved = shared.write(|shared_txn| {
+        // The unnamed database holds the names of the named ones.
+        let Some(names) = shared.open_database::<Str, DecodeIgnore>(shared_txn, None)? else {
+            return Ok(false);
+        };
+        let prefix = format!("{db_name}:");
+        let names = names
+            .iter(shared_txn)?
+            .filter_map(|entry| {
+                let (name, _) = entry.ok()?;
+                (name == db_name || name.starts_with(&prefix)).then(|| name.to_string())
+            })
+            .collect::<Vec<_>>();
+
+        let mut moved = false;
+        for name in names {
+            let Some(source) = shared.open_database::<Bytes, Bytes>(shared_txn, Some(&name))?
+            else {
+                continue;
+            };
+            // Databases that were already moved stay behind, empty.
+            if source.is_empty(shared_txn)? {
+                continue;
+            }
+
+            // LMDB limits the size of transactions, so move the entries in batches.
+            let mut last_key = None::<Vec<u8>>;
+            loop {
+                let start = match &last_key {
+                    Some(last_key) => Bound::Excluded(last_key.as_slice()),
+                    None => Bound::Unbounded,
+                };
+                let batch = source
+                    .range(shared_txn, &(start, Bound::Unbounded))?
+                    .take(MOVE_BATCH_SIZE)
+                    .collect::<heed::Result<Vec<_>>>()?;
+                worktree.write(|txn| {
+                    let destination = worktree.create_database::<Bytes, Bytes>(txn, Some(&name))?;
+                    for (key, value) in &batch {
+                        destination.put(txn, key, value)?;
+                    }
+                    Ok(())
+                })?;
+                if batch.len() < MOVE_BATCH_SIZE {
+                    break;
+                }
+                last_key = batch.last().map(|(key, _)| key.to_vec());
+            }
+            source.clear(shared_txn)?;
+            moved = true;
+        }
+        Ok(moved)
+    })?;
+    if moved {
+        log::info!("moved the index of {db_name} to a database of its own");
+    }
+    Ok(())
+}
+
+fn is_map_full(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<heed::Error>(),
+        Some(heed::Error::Mdb(heed::MdbError::MapFull))
+    )
+}
+
+fn align_map_size(map_size: usize) -> usize {
+    map_size.max(1).div_ceil(MAP_SIZE_ALIGNMENT) * MAP_SIZE_ALIGNMENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heed::{byteorder::BigEndian, types::U32};
+
+    #[test]
+    fn test_grow_when_full() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = DbConnection::open(temp_dir.path(), 1, TEST_MAX_DBS).unwrap();
+        let initial_map_size = db_connection.info().map_size;
+        assert_eq!(initial_map_size, MAP_SIZE_ALIGNMENT);
+
+        let db = db_connection
+            .write(|txn| Ok(db_connection.create_database::<U32<BigEndian>, Bytes>(txn, None)?))
+            .unwrap();
+        for ix in 0..4 {
+            db_connection
+                .write(|txn| {
+                    for key in ix * 256..(ix + 1) * 256 {
+                        db.put(txn, &key, &[0; 4096])?;
+                    }
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(db_connection.info().map_size > initial_map_size);
+        let txn = db_connection.read_txn().unwrap();
+        assert_eq!(db.len(&txn).unwrap(), 1024);
+    }
+
+    #[test]
+    fn test_grow_with_transaction_open_on_thread() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = DbConnection::open(temp_dir.path(), 1, TEST_MAX_DBS).unwrap();
+        let db = db_connection
+            .write(|txn| Ok(db_connection.create_database::<U32<BigEndian>, Bytes>(txn, None)?))
+            .unwrap();
+        let fill = || {
+            db_connection.write(|txn| {
+                for key in 0..1024 {
+                    db.put(txn, &key, &[0; 4096])?;
+                }
+                Ok(())
+            })
+        };
+
+        // Growing the database would wait for the read to finish, which it never would.
+        let txn = db_connection.read_txn().unwrap();
+        assert!(fill().is_err());
+        assert_eq!(db.len(&txn).unwrap(), 0);
+
+        // Reads on other threads are waited for instead.
+        drop(txn);
+        let (read_started_tx, read_started_rx) = std::sync::mpsc::channel();
+        let reader = thread::spawn({
+            let db_connection = db_connection.clone();
+            move || {
+                let _txn = db_connection.read_txn().unwrap();
+                read_started_tx.send(()).unwrap();
+                thread::sleep(std::time::Duration::from_millis(50));
+            }
+        });
+        read_started_rx.recv().unwrap();
+        fill().unwrap();
+        reader.join().unwrap();
+        assert_eq!(db.len(&db_connection.read_txn().unwrap()).unwrap(), 1024);
+    }
+
+    #[test]
+    fn test_move_worktree_databases() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let databases = IndexDatabases::open(temp_dir.path(), None, TEST_MAP_SIZE).unwrap();
+        let shared = databases.shared();
+        let names = ["/a", "/a:embeddings", "/a:files", "/ab:files", "/b"];
+        shared
+            .write(|txn| {
+                for name in names {
+                    let db = shared.create_database::<Str, Bytes>(txn, Some(name))?;
+                    for ix in 0..MOVE_BATCH_SIZE + 1 {
+                        db.put(txn, &format!("{name} {ix}"), name.as_bytes())?;
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        let worktree = databases.open_worktree("/a", None).unwrap();
+        let len = |db_connection: &DbConnection, name| {
+            let txn = db_connection.read_txn().unwrap();
+            db_connection
+                .open_database::<Str, Bytes>(&txn, Some(name))
+                .unwrap()
+                .map_or(0, |db| db.len(&txn).unwrap())
+        };
+        for name in names {
+            let moved = matches!(name, "/a" | "/a:embeddings" | "/a:files");
+            assert_eq!(
+                len(shared, name),
+                if moved { 0 } else { MOVE_BATCH_SIZE as u64 + 1 }
+            );
+            assert_eq!(
+                len(&worktree, name),
+                if moved { MOVE_BATCH_SIZE as u64 + 1 } else { 0 }
+            );
+        }
+
+        // Opening the worktree again finds nothing left to move.
+        assert_eq!(
+            databases.open_worktree("/a", None).unwrap().path(),
+            worktree.path()
+        );
+        assert_eq!(len(&worktree, "/a:files"), MOVE_BATCH_SIZE as u64 + 1);
+        assert_eq!(databases.all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_relocate_databases() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let default_path = temp_dir.path().join("index");
+        let scratch_path = temp_dir.path().join("scratch");
+        let project_path = temp_dir.path().join("project");
+        let write = |db_connection: &DbConnection, value: &str| {
+            db_connection
+                .write(|txn| {
+                    let db = db_connection.create_database::<Str, Str>(txn, Some("db"))?;
+                    db.put(txn, "key", value)?;
+                    Ok(())
+                })
+                .unwrap();
+        };
+        let read = |db_connection: &DbConnection| {
+            let txn = db_connection.read_txn().unwrap();
+            db_connection
+                .open_database::<Str, Str>(&txn, Some("db"))
+                .unwrap()
+                .and_then(|db| db.get(&txn, "key").unwrap().map(str::to_string))
+        };
+
+        let databases = IndexDatabases::open(&default_path, None, TEST_MAP_SIZE).unwrap();
+        write(databases.shared(), "shared");
+        let worktree = databases.open_worktree("/a", None).unwrap();
+        write(&worktree, "a");
+        drop(worktree);
+
+        // Moving the worktree's database into its project keeps its data.
+        let worktree = databases.open_worktree("/a", Some(&project_path)).unwrap();
+        assert!(worktree.path().starts_with(&project_path));
+        assert_eq!(read(&worktree).as_deref(), Some("a"));
+        assert_eq!(databases.all().unwrap().len(), 2);
+        // It stays where it is while it's open, instead of splitting the index.
+        assert_eq!(
+            databases.open_worktree("/a", None).unwrap().path(),
+            worktree.path()
+        );
+        drop(worktree);
+        drop(databases);
+
+        // Moving the shared database moves the databases next to it, and is
+        // remembered across sessions.
+        let databases =
+            IndexDatabases::open(&default_path, Some(&scratch_path), TEST_MAP_SIZE).unwrap();
+        assert!(!default_path.exists());
+        assert_eq!(read(databases.shared()).as_deref(), Some("shared"));
+        let worktree = databases.open_worktree("/a", None).unwrap();
+        assert!(worktree.path().starts_with(&scratch_path));
+        assert_eq!(read(&worktree).as_deref(), Some("a"));
+        drop(worktree);
+        drop(databases);
+
+        let databases = IndexDatabases::open(&default_path, None, TEST_MAP_SIZE).unwrap();
+        assert!(!scratch_path.exists());
+        assert_eq!(read(databases.shared()).as_deref(), Some("shared"));
+        assert_eq!(databases.all().unwrap().len(), 2);
+    }
+}
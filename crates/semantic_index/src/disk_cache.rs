@@ -0,0 +1,452 @@
+use anyhow::{anyhow, Context as _, Result};
+use collections::HashMap;
+use futures::{future::BoxFuture, FutureExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Arc,
+};
+use util::ResultExt;
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimits, ScoreCalibration, TextToEmbed,
+};
+
+/// The batch size of providers that only replay cached embeddings, which don't send
+/// requests anywhere.
+const REPLAY_BATCH_SIZE: usize = 64;
+
+/// Whether a text was embedded as a document or as a search query, which asymmetric
+/// models embed differently.
+#[derive(Clone, Copy)]
+enum EmbeddingKind {
+    Document,
+    Query,
+}
+
+/// A line of the cache file.
+#[derive(Serialize, Deserialize)]
+struct CachedEmbedding {
+    /// The hex-encoded digest of the model, the kind of embedding and the text.
+    key: String,
+    embedding: Embedding,
+}
+
+/// Embeddings returned by embedding providers, kept in a file outside of the index so
+/// that they survive deleting it. Every embedding is appended to the file as a line of
+/// JSON, so the file can be checked in as a fixture too.
+pub struct DiskEmbeddingCache {
+    path: PathBuf,
+    embeddings: Mutex<HashMap<[u8; 32], Embedding>>,
+    file: Mutex<Option<File>>,
+    /// Whether the file ended with a line that was cut short when it was opened, which
+    /// the first embeddings appended to it have to start after.
+    ends_mid_line: bool,
+}
+
+impl DiskEmbeddingCache {
+    /// Reads the embeddings cached in the file at `path`, if it exists. Lines that
+    /// can't be parsed, like one that was cut short when Zed quit, are skipped.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error).with_context(|| format!("reading {path:?}")),
+        };
+        let contents = String::from_utf8_lossy(&contents);
+        let mut embeddings = HashMap::default();
+        for line in contents.lines() {
+            let Some((key, embedding)) = parse_line(line) else {
+                log::warn!("skipping invalid line of embedding cache {path:?}");
+                continue;
+            };
+            embeddings.insert(key, embedding);
+        }
+        Ok(Self {
+            path,
+            embeddings: Mutex::new(embeddings),
+            file: Mutex::new(None),
+            ends_mid_line: !contents.is_empty() && !contents.ends_with('\n'),
+        })
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<Embedding> {
+        self.embeddings.lock().get(key).cloned()
+    }
+
+    /// Appends the embeddings to the file, creating it when the first embeddings are
+    /// cached, so that only replaying embeddings never writes to it.
+    fn insert(&self, embeddings: Vec<([u8; 32], Embedding)>) -> Result<()> {
+        if embeddings.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        let mut file = self.file.lock();
+        if file.is_none() && self.ends_mid_line {
+            lines.push('\n');
+        }
+        for (key, embedding) in &embeddings {
+            lines.push_str(&serde_json::to_string(&CachedEmbedding {
+                key: hex::encode(key),
+                embedding: embedding.clone(),
+            })?);
+            lines.push('\n');
+        }
+
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .with_context(|| format!("opening {:?}", self.path))?,
+            );
+        }
+        file.as_mut().unwrap().write_all(lines.as_bytes())?;
+        drop(file);
+
+        self.embeddings.lock().extend(embeddings);
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<([u8; 32], Embedding)> {
+    let cached: CachedEmbedding = serde_json::from_str(line).ok()?;
+    let key = hex::decode(&cached.key).ok()?.try_into().ok()?;
+    Some((key, cached.embedding))
+}
+
+/// Embeds texts with the embedding provider only if they weren't embedded by the same
+/// model before, replaying the embeddings cached on disk otherwise. Without a provider,
+/// only replays cached embeddings and fails the texts that weren't cached, so that
+/// tests can embed texts like a real model without sending requests.
+pub struct DiskCachedEmbeddingProvider {
+    provider: Option<Arc<dyn EmbeddingProvider>>,
+    model: EmbeddingModelInfo,
+    cache: Arc<DiskEmbeddingCache>,
+}
+
+impl DiskCachedEmbeddingProvider {
+    pub fn new(provider: Arc<dyn EmbeddingProvider>, cache: Arc<DiskEmbeddingCache>) -> Self {
+        Self {
+            model: provider.model_info(),
+            provider: Some(provider),
+            cache,
+        }
+    }
+
+    /// Only replays the embeddings the given model cached.
+    pub fn replay(model: EmbeddingModelInfo, cache: Arc<DiskEmbeddingCache>) -> Self {
+        Self {
+            provider: None,
+            model,
+            cache,
+        }
+    }
+
+    fn key(&self, kind: EmbeddingKind, text: &TextToEmbed) -> [u8; 32] {
+        let kind = match kind {
+            EmbeddingKind::Document => "document",
+            EmbeddingKind::Query => "query",
+        };
+        let mut hasher = Sha256::new();
+        for part in [
+            self.model.provider.as_str(),
+            self.model.model.as_str(),
+            &self.model.dimensions.to_string(),
+            kind,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(text.digest);
+        hasher.finalize().into()
+    }
+
+    /// Returns the key of every text, along with its cached embedding if any, and the
+    /// texts that weren't cached.
+    fn cached<'a>(
+        &self,
+        kind: EmbeddingKind,
+        texts: &[TextToEmbed<'a>],
+    ) -> (Vec<[u8; 32]>, Vec<Option<Embedding>>, Vec<TextToEmbed<'a>>) {
+        let keys = texts
+            .iter()
+            .map(|text| self.key(kind, text))
+            .collect::<Vec<_>>();
+        let embeddings = keys
+            .iter()
+            .map(|key| self.cache.get(key))
+            .collect::<Vec<_>>();
+        let missing_texts = texts
+            .iter()
+            .zip(&embeddings)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(text, _)| TextToEmbed {
+                text: text.text,
+                digest: text.digest,
+            })
+            .collect();
+        (keys, embeddings, missing_texts)
+    }
+
+    /// Fills in the results of embedding the texts that weren't cached, in order,
+    /// caching the embeddings that fit the model.
+    fn fill_in(
+        &self,
+        keys: &[[u8; 32]],
+        cached_embeddings: Vec<Option<Embedding>>,
+        new_results: Vec<Result<Embedding>>,
+    ) -> Vec<Result<Embedding>> {
+        let mut new_results = new_results.into_iter();
+        let mut new_embeddings = Vec::new();
+        let results = keys
+            .iter()
+            .zip(cached_embeddings)
+            .map(|(key, cached_embedding)| match cached_embedding {
+                Some(embedding) => Ok(embedding),
+                None => {
+                    let result = new_results
+                        .next()
+                        .unwrap_or_else(|| Err(anyhow!("embedding provider returned no result")));
+                    if let Ok(embedding) = &result {
+                        if embedding.len() == self.model.dimensions {
+                            new_embeddings.push((*key, embedding.clone()));
+                        }
+                    }
+                    result
+                }
+            })
+            .collect();
+        self.cache.insert(new_embeddings).log_err();
+        results
+    }
+
+    fn missing_provider(&self) -> anyhow::Error {
+        anyhow!(
+            "no embedding by {:?} is cached for the text, and there's no provider to embed it",
+            self.model
+        )
+    }
+}
+
+impl EmbeddingProvider for DiskCachedEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_with_results(texts)
+            .map(|results| results.into_iter().collect())
+            .boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let (keys, cached_embeddings, missing_queries) =
+                self.cached(EmbeddingKind::Query, queries);
+            let mut new_results = Vec::new();
+            if !missing_queries.is_empty() {
+                let provider = self
+                    .provider
+                    .as_ref()
+                    .ok_or_else(|| self.missing_provider())?;
+                new_results.extend(
+                    provider
+                        .embed_query(&missing_queries)
+                        .await?
+                        .into_iter()
+                        .map(Ok),
+                );
+            }
+            self.fill_in(&keys, cached_embeddings, new_results)
+                .into_iter()
+                .collect()
+        }
+        .boxed()
+    }
+
+    fn embed_with_results<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Vec<Result<Embedding>>> {
+        async move {
+            let (keys, cached_embeddings, missing_texts) =
+                self.cached(EmbeddingKind::Document, texts);
+            let new_results = if missing_texts.is_empty() {
+                Vec::new()
+            } else if let Some(provider) = &self.provider {
+                provider.embed_with_results(&missing_texts).await
+            } else {
+                missing_texts
+                    .iter()
+                    .map(|_| Err(self.missing_provider()))
+                    .collect()
+            };
+            self.fill_in(&keys, cached_embeddings, new_results)
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.provider
+            .as_ref()
+            .map_or(REPLAY_BATCH_SIZE, |provider| provider.batch_size())
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        self.model.clone()
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        self.provider
+            .as_ref()
+            .map_or(ScoreCalibration::UNCALIBRATED, |provider| {
+                provider.score_calibration()
+            })
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        self.provider
+            .as_ref()
+            .map_or(RateLimits::default(), |provider| provider.rate_limits())
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        self.provider.as_ref()?.max_input_tokens()
+    }
+
+    fn count_tokens(&self, text: &str) -> u64 {
+        match &self.provider {
+            Some(provider) => provider.count_tokens(text),
+            None => crate::usage::estimate_token_count(text),
+        }
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        match &self.provider {
+            Some(provider) => provider.price_per_million_tokens(),
+            None => Some(0.),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        future,
+        sync::atomic::{AtomicUsize, Ordering::SeqCst},
+    };
+
+    /// Embeds texts by their length, counting how many it embedded.
+    #[derive(Default)]
+    struct CountingEmbeddingProvider {
+        embedded_count: AtomicUsize,
+    }
+
+    impl EmbeddingProvider for CountingEmbeddingProvider {
+        fn embed<'a>(
+            &'a self,
+            texts: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            self.embedded_count.fetch_add(texts.len(), SeqCst);
+            let embeddings = texts
+                .iter()
+                .map(|to_embed| Embedding::new(vec![to_embed.text.len() as f32, 1.]))
+                .collect();
+            future::ready(Ok(embeddings)).boxed()
+        }
+
+        fn embed_query<'a>(
+            &'a self,
+            queries: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            self.embedded_count.fetch_add(queries.len(), SeqCst);
+            let embeddings = queries
+                .iter()
+                .map(|to_embed| Embedding::new(vec![1., to_embed.text.len() as f32]))
+                .collect();
+            future::ready(Ok(embeddings)).boxed()
+        }
+
+        fn batch_size(&self) -> usize {
+            16
+        }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            EmbeddingModelInfo {
+                provider: "test".into(),
+                model: "test".into(),
+                dimensions: 2,
+            }
+        }
+    }
+
+    #[gpui::test]
+    async fn test_disk_cached_embedding_provider() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("cache").join("embeddings.jsonl");
+        let provider = Arc::new(CountingEmbeddingProvider::default());
+        let cached_provider = DiskCachedEmbeddingProvider::new(
+            provider.clone(),
+            Arc::new(DiskEmbeddingCache::open(path.clone()).unwrap()),
+        );
+
+        let texts = ["a", "abc"].map(TextToEmbed::new);
+        let embeddings = cached_provider.embed(&texts).await.unwrap();
+        assert_eq!(provider.embedded_count.load(SeqCst), 2);
+        // Only the texts that weren't embedded before are sent to the provider.
+        let more_texts = ["abc", "abcde"].map(TextToEmbed::new);
+        let more_embeddings = cached_provider.embed(&more_texts).await.unwrap();
+        assert_eq!(provider.embedded_count.load(SeqCst), 3);
+        assert_eq!(more_embeddings[0], embeddings[1]);
+        // Queries are cached apart from documents.
+        let query_embeddings = cached_provider.embed_query(&texts[1..]).await.unwrap();
+        assert_eq!(provider.embedded_count.load(SeqCst), 4);
+        assert_ne!(query_embeddings[0], embeddings[1]);
+
+        // Cut the last line short, like when Zed quits while writing it.
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, format!("{contents}{{\"key\":")).unwrap();
+
+        let replaying_provider = DiskCachedEmbeddingProvider::replay(
+            provider.model_info(),
+            Arc::new(DiskEmbeddingCache::open(path.clone()).unwrap()),
+        );
+        assert_eq!(replaying_provider.embed(&texts).await.unwrap(), embeddings);
+        assert_eq!(
+            replaying_provider.embed_query(&texts[1..]).await.unwrap(),
+            query_embeddings
+        );
+        let results = replaying_provider
+            .embed_with_results(&["abcde", "never embedded"].map(TextToEmbed::new))
+            .await;
+        assert_eq!(results[0].as_ref().unwrap(), &more_embeddings[1]);
+        assert!(results[1].is_err());
+        assert_eq!(provider.embedded_count.load(SeqCst), 4);
+
+        // Embeddings cached after the line that was cut short can be read back.
+        let cached_provider = DiskCachedEmbeddingProvider::new(
+            provider.clone(),
+            Arc::new(DiskEmbeddingCache::open(path.clone()).unwrap()),
+        );
+        let new_texts = ["never embedded"].map(TextToEmbed::new);
+        let new_embeddings = cached_provider.embed(&new_texts).await.unwrap();
+        let replaying_provider = DiskCachedEmbeddingProvider::replay(
+            provider.model_info(),
+            Arc::new(DiskEmbeddingCache::open(path).unwrap()),
+        );
+        assert_eq!(
+            replaying_provider.embed(&new_texts).await.unwrap(),
+            new_embeddings
+        );
+        assert_eq!(replaying_provider.embed(&texts).await.unwrap(), embeddings);
+    }
+}
@@ -1,16 +1,33 @@
+mod aws_auth;
+mod azure_open_ai;
+mod bedrock;
 mod cloud;
+mod cohere;
+mod local;
 mod ollama;
 mod open_ai;
+mod vertex_ai;
+mod voyage;
 
+pub use azure_open_ai::*;
+pub use bedrock::*;
 pub use cloud::*;
+pub use cohere::*;
+pub use local::*;
 pub use ollama::*;
 pub use open_ai::*;
 use sha2::{Digest, Sha256};
+pub use vertex_ai::*;
+pub use voyage::*;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures::{future::BoxFuture, FutureExt};
+use http_client::HttpClient;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fmt, future};
+use std::{fmt, future, sync::Arc};
+
+use crate::{usage::estimate_token_count, RateLimited, RateLimits};
 
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Embedding(Vec<f32>);
@@ -32,11 +49,15 @@ impl Embedding {
         Self(embedding)
     }
 
-    fn len(&self) -> usize {
+    pub(crate) fn len(&self) -> usize {
         self.0.len()
     }
 
-    pub fn similarity(self, other: &Embedding) -> f32 {
+    pub(crate) fn as_slice(&self) -> &[f32] {
+        &self.0
+    }
+
+    pub fn similarity(&self, other: &Embedding) -> f32 {
         debug_assert_eq!(self.0.len(), other.0.len());
         self.0
             .iter()
@@ -68,10 +89,264 @@ impl fmt::Display for Embedding {
     }
 }
 
+/// The calibrated score above which search results are considered relevant, whatever
+/// embedding model produced them.
+pub const RELEVANT_SCORE: f32 = 0.75;
+/// The calibrated score of texts that are unrelated to each other.
+pub(crate) const UNRELATED_SCORE: f32 = 0.5;
+
+/// Maps the cosine similarities produced by an embedding model onto scores between 0
+/// and 1 that mean the same thing across models, since models differ widely in how
+/// similar they consider unrelated texts to be.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreCalibration {
+    /// The similarity typical of unrelated texts, which is calibrated to 0.5.
+    pub unrelated_similarity: f32,
+    /// The lowest similarity typical of relevant texts, which is calibrated to
+    /// [`RELEVANT_SCORE`].
+    pub relevant_similarity: f32,
+}
+
+impl ScoreCalibration {
+    /// Rescales similarities from -1..1 to 0..1 without accounting for the model.
+    pub const UNCALIBRATED: Self = Self {
+        unrelated_similarity: 0.,
+        relevant_similarity: 0.5,
+    };
+
+    /// Interpolates linearly between the calibration points, preserving the order of
+    /// similarities.
+    pub fn calibrate(&self, similarity: f32) -> f32 {
+        let points = [
+            (-1., 0.),
+            (self.unrelated_similarity, UNRELATED_SCORE),
+            (self.relevant_similarity, RELEVANT_SCORE),
+            (1., 1.),
+        ];
+        let similarity = similarity.clamp(-1., 1.);
+        for window in points.windows(2) {
+            let ((start_similarity, start_score), (end_similarity, end_score)) =
+                (window[0], window[1]);
+            if similarity <= end_similarity {
+                return start_score
+                    + (similarity - start_similarity) / (end_similarity - start_similarity)
+                        * (end_score - start_score);
+            }
+        }
+        1.
+    }
+}
+
+/// Identifies the model that produced some embeddings. Embeddings are only comparable
+/// with embeddings produced by the same model.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingModelInfo {
+    pub provider: String,
+    pub model: String,
+    pub dimensions: usize,
+}
+
+impl EmbeddingModelInfo {
+    /// Checks that an embedding has the model's dimensions, so that a misbehaving
+    /// provider can't store embeddings that can't be compared with the rest.
+    pub(crate) fn validate(&self, embedding: Embedding) -> Result<Embedding> {
+        if embedding.len() == self.dimensions {
+            Ok(embedding)
+        } else {
+            Err(anyhow!(
+                "embedding provider returned an embedding with {} dimensions, expected {}",
+                embedding.len(),
+                self.dimensions
+            ))
+        }
+    }
+}
+
+/// An embedding provider to use instead of Zed's, configured in the settings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum EmbeddingProviderSettings {
+    /// An OpenAI model deployed to Azure.
+    #[serde(rename = "azure_openai")]
+    AzureOpenAi(AzureOpenAiSettings),
+    /// A model on Google Cloud's Vertex AI.
+    #[serde(rename = "vertex_ai")]
+    VertexAi(VertexAiSettings),
+    /// A model on AWS Bedrock.
+    #[serde(rename = "bedrock")]
+    Bedrock(BedrockSettings),
+    /// A code model from Voyage AI.
+    #[serde(rename = "voyage")]
+    Voyage(VoyageSettings),
+    /// A model from Cohere.
+    #[serde(rename = "cohere")]
+    Cohere(CohereSettings),
+}
+
+impl EmbeddingProviderSettings {
+    pub fn provider(&self, client: Arc<dyn HttpClient>) -> Arc<dyn EmbeddingProvider> {
+        match self {
+            Self::AzureOpenAi(settings) => {
+                Arc::new(AzureOpenAiEmbeddingProvider::new(client, settings.clone()))
+            }
+            Self::VertexAi(settings) => {
+                Arc::new(VertexAiEmbeddingProvider::new(client, settings.clone()))
+            }
+            Self::Bedrock(settings) => {
+                Arc::new(BedrockEmbeddingProvider::new(client, settings.clone()))
+            }
+            Self::Voyage(settings) => {
+                Arc::new(VoyageEmbeddingProvider::new(client, settings.clone()))
+            }
+            Self::Cohere(settings) => {
+                Arc::new(CohereEmbeddingProvider::new(client, settings.clone()))
+            }
+        }
+    }
+}
+
 /// Trait for embedding providers. Texts in, vectors out.
 pub trait EmbeddingProvider: Sync + Send {
+    /// Embeds documents, like the chunks of files that get indexed.
     fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>>;
     fn batch_size(&self) -> usize;
+    fn model_info(&self) -> EmbeddingModelInfo;
+
+    /// Embeds search queries, to compare with the embeddings of documents. Asymmetric
+    /// models embed queries differently from the documents they retrieve, and retrieve
+    /// worse when told the wrong one. By default, embeds queries like documents.
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed(queries)
+    }
+
+    /// Embeds a batch of texts, failing only the texts that can't be embedded rather
+    /// than the whole batch, like texts that are too long for the model. By default,
+    /// embeds the texts one at a time when the batch fails, unless it failed for
+    /// exceeding the rate limits, which sending more requests would only make worse.
+    fn embed_with_results<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Vec<Result<Embedding>>> {
+        async move {
+            let error = match self.embed(texts).await {
+                Ok(embeddings) if embeddings.len() == texts.len() => {
+                    return embeddings.into_iter().map(Ok).collect();
+                }
+                Ok(embeddings) => anyhow!(
+                    "embedding provider returned unexpected embedding count {}, expected {}",
+                    embeddings.len(),
+                    texts.len()
+                ),
+                Err(error) => error,
+            };
+            if texts.len() == 1 || error.is::<RateLimited>() {
+                let message = error.to_string();
+                return std::iter::once(Err(error))
+                    .chain(texts[1..].iter().map(|_| Err(anyhow!("{message}"))))
+                    .collect();
+            }
+
+            let mut results = Vec::with_capacity(texts.len());
+            for text in texts.chunks(1) {
+                let result = self.embed(text).await.and_then(|mut embeddings| {
+                    embeddings
+                        .pop()
+                        .filter(|_| embeddings.is_empty())
+                        .ok_or_else(|| anyhow!("embedding provider returned no embedding"))
+                });
+                results.push(result);
+            }
+            results
+        }
+        .boxed()
+    }
+
+    /// Embeds a batch of texts like [`Self::embed_with_results`], along with the model
+    /// that embedded them, which only differs from [`Self::model_info`] for providers
+    /// that fail over to other models.
+    fn embed_with_model<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, (EmbeddingModelInfo, Vec<Result<Embedding>>)> {
+        async move { (self.model_info(), self.embed_with_results(texts).await) }.boxed()
+    }
+
+    /// Embeds search queries like [`Self::embed_query`], along with the model that
+    /// embedded them and how to score similarities to them.
+    fn embed_query_with_model<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<(EmbeddingModelInfo, ScoreCalibration, Vec<Embedding>)>> {
+        async move {
+            let embeddings = self.embed_query(queries).await?;
+            Ok((self.model_info(), self.score_calibration(), embeddings))
+        }
+        .boxed()
+    }
+
+    /// The model the next request will be embedded with.
+    fn active_model(&self) -> EmbeddingModelInfo {
+        self.model_info()
+    }
+
+    /// How the similarities between this provider's embeddings map onto scores.
+    fn score_calibration(&self) -> ScoreCalibration {
+        ScoreCalibration::UNCALIBRATED
+    }
+
+    /// How fast the provider accepts requests, unless overridden by the `rate_limits`
+    /// setting.
+    fn rate_limits(&self) -> RateLimits {
+        RateLimits::default()
+    }
+
+    /// The most tokens the provider accepts in one request, across all of its texts,
+    /// if it limits them separately from [`Self::batch_size`].
+    fn max_input_tokens(&self) -> Option<u64> {
+        None
+    }
+
+    /// Counts the tokens the provider's model splits a text into. By default, estimates
+    /// them from the length of the text.
+    fn count_tokens(&self, text: &str) -> u64 {
+        estimate_token_count(text)
+    }
+
+    /// What the provider charges to embed a million tokens, in US dollars, if it's
+    /// known. Models that run on this machine cost nothing.
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Splits texts into the batches to embed them in, packing as many texts into each as
+/// fit in both the provider's batch size and its token limit. Texts over the token
+/// limit on their own get a batch to themselves, for the provider to truncate or fail.
+pub(crate) fn batches<'a, 'b>(
+    provider: &dyn EmbeddingProvider,
+    texts: &'b [TextToEmbed<'a>],
+) -> Vec<&'b [TextToEmbed<'a>]> {
+    let max_len = provider.batch_size().max(1);
+    let max_tokens = provider.max_input_tokens();
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut batch_tokens = 0;
+    for (ix, to_embed) in texts.iter().enumerate() {
+        let tokens = provider.count_tokens(to_embed.text);
+        let over_tokens = max_tokens.map_or(false, |max_tokens| batch_tokens + tokens > max_tokens);
+        if ix > start && (ix - start == max_len || over_tokens) {
+            batches.push(&texts[start..ix]);
+            start = ix;
+            batch_tokens = 0;
+        }
+        batch_tokens += tokens;
+    }
+    if start < texts.len() {
+        batches.push(&texts[start..]);
+    }
+    batches
 }
 
 #[derive(Debug)]
@@ -110,6 +385,14 @@ impl EmbeddingProvider for FakeEmbeddingProvider {
     fn batch_size(&self) -> usize {
         16
     }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "fake".into(),
+            model: "fake".into(),
+            dimensions: 1536,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -122,4 +405,135 @@ mod test {
         let value: f32 = 1.0 / 3.0_f32.sqrt();
         assert_eq!(normalized, Embedding(vec![value; 3]));
     }
+
+    #[gpui::test]
+    fn test_score_calibration() {
+        let uncalibrated = ScoreCalibration::UNCALIBRATED;
+        assert_eq!(uncalibrated.calibrate(-1.), 0.);
+        assert_eq!(uncalibrated.calibrate(0.), 0.5);
+        assert_eq!(uncalibrated.calibrate(0.5), RELEVANT_SCORE);
+        assert_eq!(uncalibrated.calibrate(1.), 1.);
+
+        let calibration = ScoreCalibration {
+            unrelated_similarity: 0.2,
+            relevant_similarity: 0.4,
+        };
+        assert_eq!(calibration.calibrate(0.2), 0.5);
+        assert_eq!(calibration.calibrate(0.4), RELEVANT_SCORE);
+        assert_eq!(calibration.calibrate(1.5), 1.);
+        assert!(calibration.calibrate(0.3) > 0.5 && calibration.calibrate(0.3) < 0.75);
+        assert!(calibration.calibrate(0.7) > calibration.calibrate(0.6));
+    }
+
+    struct FailingEmbeddingProvider;
+
+    impl EmbeddingProvider for FailingEmbeddingProvider {
+        fn embed<'a>(
+            &'a self,
+            texts: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            let embeddings = texts
+                .iter()
+                .map(|to_embed| match to_embed.text {
+                    "too long" => Err(anyhow!("text is too long")),
+                    "rate limited" => Err(RateLimited { retry_after: None }.into()),
+                    text => Ok(Embedding::new(vec![text.len() as f32, 1.])),
+                })
+                .collect();
+            future::ready(embeddings).boxed()
+        }
+
+        fn batch_size(&self) -> usize {
+            16
+        }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            EmbeddingModelInfo {
+                provider: "test".into(),
+                model: "test".into(),
+                dimensions: 2,
+            }
+        }
+    }
+
+    struct TokenLimitedEmbeddingProvider;
+
+    impl EmbeddingProvider for TokenLimitedEmbeddingProvider {
+        fn embed<'a>(
+            &'a self,
+            texts: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            FailingEmbeddingProvider.embed(texts)
+        }
+
+        fn batch_size(&self) -> usize {
+            3
+        }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            FailingEmbeddingProvider.model_info()
+        }
+
+        fn max_input_tokens(&self) -> Option<u64> {
+            Some(10)
+        }
+
+        fn count_tokens(&self, text: &str) -> u64 {
+            text.len() as u64
+        }
+    }
+
+    #[gpui::test]
+    fn test_batches() {
+        let batch_texts = |texts: &[&'static str]| {
+            let texts = texts
+                .iter()
+                .copied()
+                .map(TextToEmbed::new)
+                .collect::<Vec<_>>();
+            batches(&TokenLimitedEmbeddingProvider, &texts)
+                .into_iter()
+                .map(|batch| {
+                    batch
+                        .iter()
+                        .map(|to_embed| to_embed.text)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Batches are limited by the batch size.
+        assert_eq!(
+            batch_texts(&["a", "b", "c", "d"]),
+            [vec!["a", "b", "c"], vec!["d"]]
+        );
+        // And by the token limit, with texts over it alone in their batch.
+        assert_eq!(
+            batch_texts(&["aaaa", "bbbb", "cccc", "dddddddddddd", "e"]),
+            [
+                vec!["aaaa", "bbbb"],
+                vec!["cccc"],
+                vec!["dddddddddddd"],
+                vec!["e"]
+            ]
+        );
+        assert!(batch_texts(&[]).is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_embed_with_results() {
+        let provider = FailingEmbeddingProvider;
+
+        let texts = ["a", "too long", "abc"].map(TextToEmbed::new);
+        let results = provider.embed_with_results(&texts).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &Embedding::new(vec![1., 1.]));
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &Embedding::new(vec![3., 1.]));
+
+        // Batches that were rate limited aren't retried one text at a time.
+        let texts = ["a", "rate limited"].map(TextToEmbed::new);
+        let results = provider.embed_with_results(&texts).await;
+        assert!(results.iter().all(Result::is_err));
+    }
 }
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Context as _, Result};
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// The credentials to sign requests to AWS with.
+#[derive(Clone)]
+pub(crate) struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// When the credentials expire, if they're temporary.
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+/// The output of `aws configure export-credentials --format process`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    expiration: Option<DateTime<Utc>>,
+}
+
+impl AwsCredentials {
+    /// Loads the credentials from the environment variables the AWS CLI reads, or
+    /// otherwise from the AWS CLI, which resolves profiles, SSO sessions, and roles.
+    pub async fn load(profile: Option<&str>) -> Result<Self> {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Self {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+                expiration: None,
+            });
+        }
+
+        let mut command = smol::process::Command::new("aws");
+        command.args(["configure", "export-credentials", "--format", "process"]);
+        if let Some(profile) = profile {
+            command.args(["--profile", profile]);
+        }
+        let output = command.output().await.context("running the AWS CLI")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "the AWS CLI failed to export credentials: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let credentials: ProcessCredentials = serde_json::from_slice(&output.stdout)
+            .context("parsing the credentials from the AWS CLI")?;
+        Ok(Self {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: credentials.session_token,
+            expiration: credentials.expiration,
+        })
+    }
+}
+
+/// Signs a request to an AWS service with Signature Version 4. The path must already
+/// be URI-encoded. Returns the headers to send along with the `Content-Type` header,
+/// which is signed too, as is the host.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sign_request(
+    credentials: &AwsCredentials,
+    service: &str,
+    region: &str,
+    host: &str,
+    path: &str,
+    content_type: &str,
+    body: &[u8],
+    timestamp: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = timestamp.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = timestamp.format("%Y%m%d").to_string();
+
+    let mut headers = vec![
+        ("content-type", content_type.to_string()),
+        ("host", host.to_string()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(session_token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", session_token.clone()));
+    }
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    // Services other than S3 expect the path to be encoded twice.
+    let canonical_path = path
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    let canonical_request = format!(
+        "POST\n{canonical_path}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex::encode(Sha256::digest(body))
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let sign = |key: &[u8], data: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+            .as_ref()
+            .to_vec()
+    };
+    let mut signing_key = format!("AWS4{}", credentials.secret_access_key).into_bytes();
+    for part in [date.as_str(), region, service, "aws4_request"] {
+        signing_key = sign(&signing_key, part);
+    }
+    let signature = hex::encode(sign(&signing_key, &string_to_sign));
+
+    let mut signed_request_headers = vec![
+        ("x-amz-date", amz_date),
+        (
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+                Signature={signature}",
+                credentials.access_key_id
+            ),
+        ),
+    ];
+    if let Some(session_token) = &credentials.session_token {
+        signed_request_headers.push(("x-amz-security-token", session_token.clone()));
+    }
+    signed_request_headers
+}
+
+/// Percent-encodes everything but the characters AWS leaves unencoded.
+pub(crate) fn uri_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    #[test]
+    fn test_sign_request() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+            expiration: None,
+        };
+        let path = format!(
+            "/model/{}/invoke",
+            uri_encode("amazon.titan-embed-text-v2:0")
+        );
+        assert_eq!(path, "/model/amazon.titan-embed-text-v2%3A0/invoke");
+        let headers = sign_request(
+            &credentials,
+            "bedrock",
+            "us-east-1",
+            "bedrock-runtime.us-east-1.amazonaws.com",
+            &path,
+            "application/json",
+            br#"{"inputText":"fn main() {}"}"#,
+            Utc.with_ymd_and_hms(2024, 8, 1, 12, 30, 0).unwrap(),
+        );
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date", "20240801T123000Z".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 \
+                    Credential=AKIDEXAMPLE/20240801/us-east-1/bedrock/aws4_request, \
+                    SignedHeaders=content-type;host;x-amz-date, \
+                    Signature=a7882de95994f4e6d82bff7ee5124e2ce3bd40df7c6542dd60972ef3010ccdce"
+                        .to_string()
+                )
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, FutureExt};
+use http_client::HttpClient;
+use open_ai::OpenAiEmbeddingModel;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::open_ai::{
+    embeddings_from_response, model_info, price_per_million_tokens, score_calibration,
+};
+use crate::{Embedding, EmbeddingModelInfo, EmbeddingProvider, ScoreCalibration, TextToEmbed};
+
+/// The version of the Azure OpenAI API to use when the settings don't name one.
+const DEFAULT_API_VERSION: &str = "2024-02-01";
+/// The environment variable to read the API key of the Azure OpenAI resource from.
+const API_KEY_VAR: &str = "AZURE_OPENAI_API_KEY";
+/// The resource to request Microsoft Entra ID tokens for.
+const TOKEN_RESOURCE: &str = "https://cognitiveservices.azure.com";
+/// How long before tokens expire to replace them, so that none expires mid-request.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+/// How long to use tokens for when the Azure CLI doesn't say when they expire. Tokens
+/// last at least an hour.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// An OpenAI embedding model deployed to an Azure OpenAI resource, which is how many
+/// organizations have to use OpenAI models.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AzureOpenAiSettings {
+    /// The endpoint of the resource, like "https://my-resource.openai.azure.com".
+    pub endpoint: String,
+    /// The name of the deployment of the model.
+    pub deployment: String,
+    /// The model that's deployed.
+    pub model: OpenAiEmbeddingModel,
+    /// The version of the Azure OpenAI API.
+    ///
+    /// Default: 2024-02-01
+    #[serde(default)]
+    pub api_version: Option<String>,
+    /// How to authenticate with the resource.
+    ///
+    /// Default: api_key
+    #[serde(default)]
+    pub auth: AzureOpenAiAuth,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AzureOpenAiAuth {
+    /// An API key of the resource, from the `AZURE_OPENAI_API_KEY` environment variable.
+    #[default]
+    ApiKey,
+    /// A Microsoft Entra ID token of the user signed into the Azure CLI, for resources
+    /// that don't accept API keys.
+    AzureCli,
+}
+
+pub struct AzureOpenAiEmbeddingProvider {
+    client: Arc<dyn HttpClient>,
+    settings: AzureOpenAiSettings,
+    access_token: smol::lock::Mutex<Option<AccessToken>>,
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: SystemTime,
+}
+
+/// The output of `az account get-access-token`.
+#[derive(Deserialize)]
+struct AzureCliAccessToken {
+    #[serde(rename = "accessToken")]
+    access_token: String,
+    /// When the token expires, in seconds since the Unix epoch. Older versions of the
+    /// Azure CLI leave it out.
+    expires_on: Option<u64>,
+}
+
+impl AzureOpenAiEmbeddingProvider {
+    pub fn new(client: Arc<dyn HttpClient>, settings: AzureOpenAiSettings) -> Self {
+        Self {
+            client,
+            settings,
+            access_token: Default::default(),
+        }
+    }
+
+    /// Azure routes requests to a model by the name of its deployment, rather than the
+    /// name of the model.
+    fn uri(&self) -> String {
+        format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            self.settings.endpoint.trim_end_matches('/'),
+            self.settings.deployment,
+            self.settings
+                .api_version
+                .as_deref()
+                .unwrap_or(DEFAULT_API_VERSION)
+        )
+    }
+
+    async fn auth_header(&self) -> Result<(&'static str, String)> {
+        match self.settings.auth {
+            AzureOpenAiAuth::ApiKey => {
+                let api_key = std::env::var(API_KEY_VAR)
+                    .with_context(|| format!("{API_KEY_VAR} isn't set"))?;
+                Ok(("api-key", api_key))
+            }
+            AzureOpenAiAuth::AzureCli => {
+                let token = self.access_token().await?;
+                Ok(("Authorization", format!("Bearer {token}")))
+            }
+        }
+    }
+
+    /// Returns a token from the Azure CLI, reusing it until it's about to expire.
+    async fn access_token(&self) -> Result<String> {
+        let mut access_token = self.access_token.lock().await;
+        if let Some(access_token) = access_token.as_ref() {
+            if access_token.is_fresh(SystemTime::now()) {
+                return Ok(access_token.token.clone());
+            }
+        }
+
+        let output = smol::process::Command::new("az")
+            .args(["account", "get-access-token", "--resource", TOKEN_RESOURCE])
+            .args(["--output", "json"])
+            .output()
+            .await
+            .context("running the Azure CLI")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "the Azure CLI failed to get an access token: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let new_token = AccessToken::from_azure_cli_output(&output.stdout, SystemTime::now())?;
+        let token = new_token.token.clone();
+        *access_token = Some(new_token);
+        Ok(token)
+    }
+}
+
+impl AccessToken {
+    /// Parses the output of `az account get-access-token`, run at `now`.
+    fn from_azure_cli_output(output: &[u8], now: SystemTime) -> Result<Self> {
+        let response: AzureCliAccessToken = serde_json::from_slice(output)
+            .context("parsing the access token from the Azure CLI")?;
+        let expires_at = match response.expires_on {
+            Some(expires_on) => UNIX_EPOCH + Duration::from_secs(expires_on),
+            None => now + DEFAULT_TOKEN_LIFETIME,
+        };
+        Ok(Self {
+            token: response.access_token,
+            expires_at,
+        })
+    }
+
+    /// Whether the token can still be used at `now`, rather than replaced.
+    fn is_fresh(&self, now: SystemTime) -> bool {
+        self.expires_at > now + TOKEN_REFRESH_MARGIN
+    }
+}
+
+impl EmbeddingProvider for AzureOpenAiEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let (auth_header_name, auth_header_value) = self.auth_header().await?;
+            let response = open_ai::embed_with_auth(
+                self.client.as_ref(),
+                &self.uri(),
+                (auth_header_name, &auth_header_value),
+                None,
+                texts.iter().map(|to_embed| to_embed.text),
+            )
+            .await;
+            embeddings_from_response(response)
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // From https://learn.microsoft.com/en-us/azure/ai-services/openai/reference#embeddings
+        2048
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        model_info("azure_openai", self.settings.model)
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        score_calibration(self.settings.model)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        Some(price_per_million_tokens(self.settings.model))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_client::FakeHttpClient;
+
+    fn provider(endpoint: &str, api_version: Option<&str>) -> AzureOpenAiEmbeddingProvider {
+        AzureOpenAiEmbeddingProvider::new(
+            FakeHttpClient::with_404_response(),
+            AzureOpenAiSettings {
+                endpoint: endpoint.into(),
+                deployment: "embeddings".into(),
+                model: OpenAiEmbeddingModel::TextEmbedding3Small,
+                api_version: api_version.map(Into::into),
+                auth: AzureOpenAiAuth::AzureCli,
+            },
+        )
+    }
+
+    #[test]
+    fn test_uri() {
+        assert_eq!(
+            provider("https://my-resource.openai.azure.com/", None).uri(),
+            "https://my-resource.openai.azure.com/openai/deployments/embeddings/embeddings\
+            ?api-version=2024-02-01"
+        );
+        assert_eq!(
+            provider("https://my-resource.openai.azure.com", Some("2024-06-01")).uri(),
+            "https://my-resource.openai.azure.com/openai/deployments/embeddings/embeddings\
+            ?api-version=2024-06-01"
+        );
+    }
+
+    #[test]
+    fn test_access_token_expiry() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // Tokens are used until they're about to expire.
+        let token = AccessToken::from_azure_cli_output(
+            br#"{"accessToken": "token", "expires_on": 1700003600, "tokenType": "Bearer"}"#,
+            now,
+        )
+        .unwrap();
+        assert_eq!(token.token, "token");
+        assert!(token.is_fresh(now));
+        assert!(token.is_fresh(now + Duration::from_secs(50 * 60)));
+        assert!(!token.is_fresh(now + Duration::from_secs(56 * 60)));
+
+        // Tokens that don't say when they expire are replaced early.
+        let token =
+            AccessToken::from_azure_cli_output(br#"{"accessToken": "token"}"#, now).unwrap();
+        assert!(token.is_fresh(now));
+        assert!(!token.is_fresh(now + Duration::from_secs(26 * 60)));
+
+        assert!(AccessToken::from_azure_cli_output(b"ERROR: Please run 'az login'", now).is_err());
+    }
+
+    #[gpui::test]
+    async fn test_access_token_is_reused() {
+        let provider = provider("https://my-resource.openai.azure.com", None);
+        *provider.access_token.lock().await = Some(AccessToken {
+            token: "cached".into(),
+            expires_at: SystemTime::now() + Duration::from_secs(60 * 60),
+        });
+        // The Azure CLI isn't run while the cached token is fresh.
+        assert_eq!(provider.access_token().await.unwrap(), "cached");
+        assert_eq!(
+            provider.auth_header().await.unwrap(),
+            ("Authorization", "Bearer cached".to_string())
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Context as _, Result};
+use chrono::Utc;
+use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::aws_auth::{sign_request, uri_encode, AwsCredentials};
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimited, RateLimits, ScoreCalibration,
+    TextToEmbed,
+};
+
+/// How many minutes before they expire to replace temporary credentials, so that none
+/// expire mid-request.
+const CREDENTIALS_REFRESH_MARGIN_MINUTES: i64 = 5;
+/// The longest text Cohere models on Bedrock accept, in characters.
+const COHERE_MAX_TEXT_LEN: usize = 2048;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum BedrockEmbeddingModel {
+    #[serde(rename = "amazon.titan-embed-text-v2:0")]
+    TitanEmbedTextV2,
+    #[serde(rename = "cohere.embed-english-v3")]
+    CohereEmbedEnglishV3,
+    #[serde(rename = "cohere.embed-multilingual-v3")]
+    CohereEmbedMultilingualV3,
+}
+
+impl BedrockEmbeddingModel {
+    fn id(&self) -> &'static str {
+        match self {
+            Self::TitanEmbedTextV2 => "amazon.titan-embed-text-v2:0",
+            Self::CohereEmbedEnglishV3 => "cohere.embed-english-v3",
+            Self::CohereEmbedMultilingualV3 => "cohere.embed-multilingual-v3",
+        }
+    }
+}
+
+/// An embedding model on AWS Bedrock.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BedrockSettings {
+    /// The AWS region to use the model in, like "us-east-1".
+    pub region: String,
+    /// The model to use, which has to be enabled in the region.
+    pub model: BedrockEmbeddingModel,
+    /// The profile of the AWS CLI to get credentials from, unless they're in the
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` environment variables.
+    ///
+    /// Default: null
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+pub struct BedrockEmbeddingProvider {
+    client: Arc<dyn HttpClient>,
+    settings: BedrockSettings,
+    credentials: smol::lock::Mutex<Option<AwsCredentials>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TitanEmbeddingRequest<'a> {
+    input_text: &'a str,
+    dimensions: usize,
+    normalize: bool,
+}
+
+#[derive(Deserialize)]
+struct TitanEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct CohereEmbeddingRequest<'a> {
+    texts: Vec<&'a str>,
+    input_type: &'static str,
+    truncate: &'static str,
+}
+
+#[derive(Deserialize)]
+struct CohereEmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl BedrockEmbeddingProvider {
+    pub fn new(client: Arc<dyn HttpClient>, settings: BedrockSettings) -> Self {
+        Self {
+            client,
+            settings,
+            credentials: Default::default(),
+        }
+    }
+
+    /// Returns the credentials to sign requests with, loading them again once
+    /// temporary credentials are about to expire.
+    async fn credentials(&self) -> Result<AwsCredentials> {
+        let mut credentials = self.credentials.lock().await;
+        if let Some(credentials) = credentials.as_ref() {
+            let expired = credentials.expiration.map_or(false, |expiration| {
+                expiration - chrono::Duration::minutes(CREDENTIALS_REFRESH_MARGIN_MINUTES)
+                    <= Utc::now()
+            });
+            if !expired {
+                return Ok(credentials.clone());
+            }
+        }
+        let loaded = AwsCredentials::load(self.settings.profile.as_deref())
+            .await
+            .context("loading AWS credentials")?;
+        *credentials = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    async fn invoke<T: DeserializeOwned>(&self, request: &impl Serialize) -> Result<T> {
+        let credentials = self.credentials().await?;
+        let region = &self.settings.region;
+        let host = format!("bedrock-runtime.{region}.amazonaws.com");
+        let path = format!("/model/{}/invoke", uri_encode(self.settings.model.id()));
+        let body = serde_json::to_vec(request)?;
+        let headers = sign_request(
+            &credentials,
+            "bedrock",
+            region,
+            &host,
+            &path,
+            "application/json",
+            &body,
+            Utc::now(),
+        );
+
+        let mut request = HttpRequest::builder()
+            .method(Method::POST)
+            .uri(format!("https://{host}{path}"))
+            .header("Content-Type", "application/json");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let mut response = self
+            .client
+            .send(request.body(AsyncBody::from(body))?)
+            .await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        if response.status().is_success() {
+            serde_json::from_str(&body).context("failed to parse Bedrock embedding response")
+        } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            Err(RateLimited { retry_after: None }.into())
+        } else {
+            Err(anyhow!(
+                "error during embedding, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+
+    /// Embeds texts as documents or queries, which only Cohere models tell apart, as
+    /// "search_document" and "search_query".
+    async fn embed_as(
+        &self,
+        texts: &[TextToEmbed<'_>],
+        input_type: &'static str,
+    ) -> Result<Vec<Embedding>> {
+        match self.settings.model {
+            // Titan models embed one text per request.
+            BedrockEmbeddingModel::TitanEmbedTextV2 => {
+                futures::future::try_join_all(texts.iter().map(|to_embed| async {
+                    let response: TitanEmbeddingResponse = self
+                        .invoke(&TitanEmbeddingRequest {
+                            input_text: to_embed.text,
+                            dimensions: self.model_info().dimensions,
+                            normalize: true,
+                        })
+                        .await?;
+                    Ok(Embedding::new(response.embedding))
+                }))
+                .await
+            }
+            BedrockEmbeddingModel::CohereEmbedEnglishV3
+            | BedrockEmbeddingModel::CohereEmbedMultilingualV3 => {
+                let request = CohereEmbeddingRequest {
+                    texts: texts
+                        .iter()
+                        .map(|to_embed| truncate(to_embed.text, COHERE_MAX_TEXT_LEN))
+                        .collect(),
+                    input_type,
+                    truncate: "END",
+                };
+                let response: CohereEmbeddingResponse = self.invoke(&request).await?;
+                Ok(response
+                    .embeddings
+                    .into_iter()
+                    .map(Embedding::new)
+                    .collect())
+            }
+        }
+    }
+}
+
+impl EmbeddingProvider for BedrockEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(texts, "search_document").boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(queries, "search_query").boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        match self.settings.model {
+            BedrockEmbeddingModel::TitanEmbedTextV2 => 16,
+            // From https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-embed.html
+            BedrockEmbeddingModel::CohereEmbedEnglishV3
+            | BedrockEmbeddingModel::CohereEmbedMultilingualV3 => 96,
+        }
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "bedrock".into(),
+            model: self.settings.model.id().into(),
+            dimensions: 1024,
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        match self.settings.model {
+            BedrockEmbeddingModel::TitanEmbedTextV2 => ScoreCalibration {
+                unrelated_similarity: 0.15,
+                relevant_similarity: 0.4,
+            },
+            BedrockEmbeddingModel::CohereEmbedEnglishV3
+            | BedrockEmbeddingModel::CohereEmbedMultilingualV3 => ScoreCalibration {
+                unrelated_similarity: 0.2,
+                relevant_similarity: 0.45,
+            },
+        }
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // The default quotas of on-demand throughput, from
+        // https://docs.aws.amazon.com/bedrock/latest/userguide/quotas.html
+        RateLimits {
+            requests_per_minute: Some(2_000),
+            tokens_per_minute: Some(300_000),
+        }
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        // On-demand prices, from https://aws.amazon.com/bedrock/pricing
+        Some(match self.settings.model {
+            BedrockEmbeddingModel::TitanEmbedTextV2 => 0.02,
+            BedrockEmbeddingModel::CohereEmbedEnglishV3
+            | BedrockEmbeddingModel::CohereEmbedMultilingualV3 => 0.1,
+        })
+    }
+}
+
+/// Truncates the text to at most the given length in bytes, at a character boundary.
+fn truncate(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
@@ -1,4 +1,4 @@
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{Embedding, EmbeddingModelInfo, EmbeddingProvider, ScoreCalibration, TextToEmbed};
 use anyhow::{anyhow, Context, Result};
 use client::{proto, Client};
 use collections::HashMap;
@@ -90,4 +90,20 @@ impl EmbeddingProvider for CloudEmbeddingProvider {
     fn batch_size(&self) -> usize {
         2048
     }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "zed.dev".into(),
+            model: self.model.clone(),
+            dimensions: 1536,
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        // The same as OpenAI's text-embedding-3-small, which this provider uses.
+        ScoreCalibration {
+            unrelated_similarity: 0.1,
+            relevant_similarity: 0.4,
+        }
+    }
 }
@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimited, RateLimits, ScoreCalibration,
+    TextToEmbed,
+};
+
+const COHERE_API_URL: &str = "https://api.cohere.com/v1/embed";
+/// The environment variable to read the Cohere API key from, which Cohere's SDKs read
+/// too.
+const API_KEY_VAR: &str = "CO_API_KEY";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum CohereEmbeddingModel {
+    #[default]
+    #[serde(rename = "embed-english-v3.0")]
+    EmbedEnglishV3,
+    #[serde(rename = "embed-multilingual-v3.0")]
+    EmbedMultilingualV3,
+    #[serde(rename = "embed-english-light-v3.0")]
+    EmbedEnglishLightV3,
+    #[serde(rename = "embed-multilingual-light-v3.0")]
+    EmbedMultilingualLightV3,
+}
+
+impl CohereEmbeddingModel {
+    fn id(&self) -> &'static str {
+        match self {
+            Self::EmbedEnglishV3 => "embed-english-v3.0",
+            Self::EmbedMultilingualV3 => "embed-multilingual-v3.0",
+            Self::EmbedEnglishLightV3 => "embed-english-light-v3.0",
+            Self::EmbedMultilingualLightV3 => "embed-multilingual-light-v3.0",
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        match self {
+            Self::EmbedEnglishV3 | Self::EmbedMultilingualV3 => 1024,
+            Self::EmbedEnglishLightV3 | Self::EmbedMultilingualLightV3 => 384,
+        }
+    }
+}
+
+/// An embedding model from Cohere, with the API key in the `CO_API_KEY` environment
+/// variable.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CohereSettings {
+    /// The model to use.
+    ///
+    /// Default: embed-english-v3.0
+    #[serde(default)]
+    pub model: CohereEmbeddingModel,
+}
+
+pub struct CohereEmbeddingProvider {
+    client: Arc<dyn HttpClient>,
+    settings: CohereSettings,
+}
+
+#[derive(Serialize)]
+struct CohereEmbeddingRequest<'a> {
+    texts: Vec<&'a str>,
+    model: &'static str,
+    input_type: CohereInputType,
+    truncate: &'static str,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CohereInputType {
+    SearchDocument,
+    SearchQuery,
+}
+
+#[derive(Deserialize)]
+struct CohereEmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl CohereEmbeddingProvider {
+    pub fn new(client: Arc<dyn HttpClient>, settings: CohereSettings) -> Self {
+        Self { client, settings }
+    }
+
+    async fn embed_as(
+        &self,
+        texts: &[TextToEmbed<'_>],
+        input_type: CohereInputType,
+    ) -> Result<Vec<Embedding>> {
+        let api_key =
+            std::env::var(API_KEY_VAR).with_context(|| format!("{API_KEY_VAR} isn't set"))?;
+        let request = CohereEmbeddingRequest {
+            texts: texts.iter().map(|to_embed| to_embed.text).collect(),
+            model: self.settings.model.id(),
+            input_type,
+            // Embed the start of texts that are too long, rather than failing them.
+            truncate: "END",
+        };
+        let request = HttpRequest::builder()
+            .method(Method::POST)
+            .uri(COHERE_API_URL)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {api_key}"))
+            .body(AsyncBody::from(serde_json::to_string(&request)?))?;
+        let mut response = self.client.send(request).await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        if response.status().is_success() {
+            let response: CohereEmbeddingResponse =
+                serde_json::from_str(&body).context("failed to parse Cohere embedding response")?;
+            Ok(response
+                .embeddings
+                .into_iter()
+                .map(Embedding::new)
+                .collect())
+        } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            Err(RateLimited { retry_after: None }.into())
+        } else {
+            Err(anyhow!(
+                "error during embedding, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
+impl EmbeddingProvider for CohereEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(texts, CohereInputType::SearchDocument)
+            .boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(queries, CohereInputType::SearchQuery).boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // From https://docs.cohere.com/reference/embed
+        96
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "cohere".into(),
+            model: self.settings.model.id().into(),
+            dimensions: self.settings.model.dimensions(),
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        ScoreCalibration {
+            unrelated_similarity: 0.2,
+            relevant_similarity: 0.45,
+        }
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // The limit of production keys, from https://docs.cohere.com/docs/rate-limits
+        RateLimits {
+            requests_per_minute: Some(2_000),
+            tokens_per_minute: None,
+        }
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        // Every embedding model costs the same, from https://cohere.com/pricing
+        Some(0.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_client::{FakeHttpClient, Response};
+    use parking_lot::Mutex;
+
+    /// A response of the embed API, as sent by Cohere.
+    const RESPONSE: &str = r#"{
+        "id": "2ee4b1ba-7e2c-4c9e-a8a4-3e4a7a3e3b8e",
+        "texts": ["fn main() {}"],
+        "embeddings": [[0.6, 0.8]],
+        "meta": {"api_version": {"version": "1"}, "billed_units": {"input_tokens": 4}},
+        "response_type": "embeddings_floats"
+    }"#;
+
+    #[gpui::test]
+    async fn test_embed_documents_and_queries() {
+        std::env::set_var(API_KEY_VAR, "test-key");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = FakeHttpClient::create({
+            let requests = requests.clone();
+            move |request| {
+                let requests = requests.clone();
+                async move {
+                    assert_eq!(request.uri(), COHERE_API_URL);
+                    assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+                    let mut body = String::new();
+                    request.into_body().read_to_string(&mut body).await?;
+                    requests
+                        .lock()
+                        .push(serde_json::from_str::<serde_json::Value>(&body).unwrap());
+                    Ok(Response::builder().status(200).body(RESPONSE.into())?)
+                }
+            }
+        });
+        let provider = CohereEmbeddingProvider::new(client, CohereSettings::default());
+
+        let embeddings = provider
+            .embed(&[TextToEmbed::new("fn main() {}")])
+            .await
+            .unwrap();
+        assert_eq!(embeddings, vec![Embedding::new(vec![0.6, 0.8])]);
+        provider
+            .embed_query(&[TextToEmbed::new("entry point")])
+            .await
+            .unwrap();
+
+        // Documents and queries are embedded differently.
+        assert_eq!(
+            *requests.lock(),
+            vec![
+                serde_json::json!({
+                    "texts": ["fn main() {}"],
+                    "model": "embed-english-v3.0",
+                    "input_type": "search_document",
+                    "truncate": "END",
+                }),
+                serde_json::json!({
+                    "texts": ["entry point"],
+                    "model": "embed-english-v3.0",
+                    "input_type": "search_query",
+                    "truncate": "END",
+                }),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_rate_limited() {
+        std::env::set_var(API_KEY_VAR, "test-key");
+        let client = FakeHttpClient::create(|_| async move {
+            Ok(Response::builder()
+                .status(429)
+                .body(r#"{"message": "You are using a Trial key, which is limited."}"#.into())?)
+        });
+        let provider = CohereEmbeddingProvider::new(client, CohereSettings::default());
+        let error = provider
+            .embed(&[TextToEmbed::new("fn main() {}")])
+            .await
+            .unwrap_err();
+        assert!(error.is::<RateLimited>());
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{anyhow, Context as _, Result};
+use candle_core::{Device, IndexOp as _, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use futures::{future::BoxFuture, AsyncReadExt as _, AsyncWriteExt as _, FutureExt};
+use gpui::BackgroundExecutor;
+use http_client::HttpClient;
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokenizers::{Encoding, PaddingParams, Tokenizer, TruncationParams};
+
+use crate::{Embedding, EmbeddingModelInfo, EmbeddingProvider, ScoreCalibration, TextToEmbed};
+
+/// The longest text the models can embed, in tokens. Longer texts are truncated.
+const MAX_TOKENS: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalEmbeddingModel {
+    BgeSmallEnV1_5,
+    GteSmall,
+}
+
+/// How a model turns the embeddings of the tokens of a text into the embedding of
+/// the text.
+#[derive(Clone, Copy)]
+enum Pooling {
+    /// Uses the embedding of the classification token at the start of the text.
+    Cls,
+    /// Averages the embeddings of every token.
+    Mean,
+}
+
+impl LocalEmbeddingModel {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::BgeSmallEnV1_5 => "bge-small-en-v1.5",
+            Self::GteSmall => "gte-small",
+        }
+    }
+
+    fn repository(&self) -> &'static str {
+        match self {
+            Self::BgeSmallEnV1_5 => "BAAI/bge-small-en-v1.5",
+            Self::GteSmall => "thenlper/gte-small",
+        }
+    }
+
+    fn pooling(&self) -> Pooling {
+        match self {
+            Self::BgeSmallEnV1_5 => Pooling::Cls,
+            Self::GteSmall => Pooling::Mean,
+        }
+    }
+
+    /// The commit of the model's repository that its files are downloaded from, so
+    /// that they're the ones whose digests are listed in [`Self::files`].
+    fn revision(&self) -> &'static str {
+        // TODO: Pin the commits of the repositories, as shown on their Hugging Face pages.
+        match self {
+            Self::BgeSmallEnV1_5 => "<bge-small-en-v1.5 commit>",
+            Self::GteSmall => "<gte-small commit>",
+        }
+    }
+
+    /// The files of the model at [`Self::revision`], which are checked against their
+    /// digests before the model is loaded.
+    fn files(&self) -> [ModelFile; 3] {
+        // TODO: Fill in the SHA-256 digests of the files at the pinned commits. A
+        // mismatch fails with the digest of the downloaded file.
+        match self {
+            Self::BgeSmallEnV1_5 => [
+                ModelFile::new("config.json", "<sha256>"),
+                ModelFile::new("tokenizer.json", "<sha256>"),
+                ModelFile::new("model.safetensors", "<sha256>"),
+            ],
+            Self::GteSmall => [
+                ModelFile::new("config.json", "<sha256>"),
+                ModelFile::new("tokenizer.json", "<sha256>"),
+                ModelFile::new("model.safetensors", "<sha256>"),
+            ],
+        }
+    }
+}
+
+/// A file of a model, as published on Hugging Face, with the hex-encoded SHA-256
+/// digest of its contents.
+#[derive(Clone, Copy)]
+struct ModelFile {
+    name: &'static str,
+    sha256: &'static str,
+}
+
+impl ModelFile {
+    const fn new(name: &'static str, sha256: &'static str) -> Self {
+        Self { name, sha256 }
+    }
+}
+
+/// Embeds texts on this machine with a small sentence-transformer, so that semantic
+/// search works offline and without an account. The model is downloaded from Hugging
+/// Face the first time it's used, and runs on the CPU.
+pub struct LocalEmbeddingProvider {
+    model: LocalEmbeddingModel,
+    models_dir: PathBuf,
+    client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+    loaded_model: smol::lock::OnceCell<Arc<LoadedModel>>,
+}
+
+impl LocalEmbeddingProvider {
+    /// Creates a provider that keeps the models it downloads in the given directory.
+    pub fn new(
+        model: LocalEmbeddingModel,
+        models_dir: PathBuf,
+        client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        Self {
+            model,
+            models_dir,
+            client,
+            executor,
+            loaded_model: smol::lock::OnceCell::new(),
+        }
+    }
+
+    /// Loads the model, downloading it if needed. Loading is tried again on the next
+    /// batch if it fails, like while offline.
+    async fn load(&self) -> Result<Arc<LoadedModel>> {
+        self.loaded_model
+            .get_or_try_init(|| async {
+                let model_dir = self
+                    .download()
+                    .await
+                    .with_context(|| format!("downloading {}", self.model.name()))?;
+                let loaded_model = self
+                    .executor
+                    .spawn(async move { LoadedModel::load(&model_dir) })
+                    .await
+                    .with_context(|| format!("loading {}", self.model.name()))?;
+                anyhow::Ok(Arc::new(loaded_model))
+            })
+            .await
+            .cloned()
+    }
+
+    /// Downloads the files of the model that weren't downloaded yet, returning the
+    /// directory they're in.
+    async fn download(&self) -> Result<PathBuf> {
+        let model_dir = self.models_dir.join(self.model.name());
+        download_model_files(
+            self.client.as_ref(),
+            &format!(
+                "https://huggingface.co/{}/resolve/{}",
+                self.model.repository(),
+                self.model.revision()
+            ),
+            &self.model.files(),
+            &model_dir,
+        )
+        .await?;
+        Ok(model_dir)
+    }
+}
+
+/// Downloads the files that aren't in the directory yet from under the given URL.
+/// Every file, downloaded or not, is checked against its digest, as the model's
+/// weights are mapped into memory as they are. Files that don't match are
+/// downloaded again, while downloads that don't match fail.
+async fn download_model_files(
+    client: &dyn HttpClient,
+    base_url: &str,
+    files: &[ModelFile],
+    model_dir: &Path,
+) -> Result<()> {
+    smol::fs::create_dir_all(model_dir).await?;
+    for file in files {
+        let path = model_dir.join(file.name);
+        if smol::fs::metadata(&path).await.is_ok() {
+            if file_sha256(&path).await? == file.sha256 {
+                continue;
+            }
+            log::warn!(
+                "{} doesn't match its digest, downloading it again",
+                path.display()
+            );
+        }
+
+        let url = format!("{base_url}/{}", file.name);
+        log::info!("downloading {url}");
+        let mut response = client.get(&url, Default::default(), true).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "downloading {url} failed with status {}",
+                response.status()
+            ));
+        }
+        // Download to another file first, so that an interrupted download isn't
+        // mistaken for the whole file.
+        let download_path = model_dir.join(format!("{}.download", file.name));
+        let mut download = smol::fs::File::create(&download_path).await?;
+        futures::io::copy(response.body_mut(), &mut download).await?;
+        download.flush().await?;
+        drop(download);
+        let sha256 = file_sha256(&download_path).await?;
+        if sha256 != file.sha256 {
+            smol::fs::remove_file(&download_path).await.ok();
+            return Err(anyhow!(
+                "{url} has the SHA-256 digest {sha256}, expected {}",
+                file.sha256
+            ));
+        }
+        smol::fs::rename(&download_path, &path).await?;
+    }
+    Ok(())
+}
+
+/// The hex-encoded SHA-256 digest of a file's contents.
+async fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = smol::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer).await?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let loaded_model = self.load().await?;
+            let texts = texts
+                .iter()
+                .map(|to_embed| to_embed.text.to_string())
+                .collect::<Vec<_>>();
+            let pooling = self.model.pooling();
+            self.executor
+                .spawn(async move { loaded_model.embed(texts, pooling) })
+                .await
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        32
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "local".into(),
+            model: self.model.name().into(),
+            dimensions: 384,
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        match self.model {
+            LocalEmbeddingModel::BgeSmallEnV1_5 => ScoreCalibration {
+                unrelated_similarity: 0.45,
+                relevant_similarity: 0.7,
+            },
+            LocalEmbeddingModel::GteSmall => ScoreCalibration {
+                unrelated_similarity: 0.7,
+                relevant_similarity: 0.82,
+            },
+        }
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        Some(0.)
+    }
+}
+
+struct LoadedModel {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl LoadedModel {
+    fn load(model_dir: &Path) -> Result<Self> {
+        let device = Device::Cpu;
+        let config: Config =
+            serde_json::from_str(&std::fs::read_to_string(model_dir.join("config.json"))?)?;
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|error| anyhow!(error))?;
+        tokenizer
+            .with_padding(Some(PaddingParams::default()))
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_TOKENS,
+                ..Default::default()
+            }))
+            .map_err(|error| anyhow!(error))?;
+        // Safety: the file was checked against its digest, and isn't modified while
+        // it's mapped, since it's only ever replaced as a whole.
+        let weights = unsafe {
+            VarBuilder::from_mmaped_safetensors(
+                &[model_dir.join("model.safetensors")],
+                DTYPE,
+                &device,
+            )?
+        };
+        let bert = BertModel::load(weights, &config)?;
+        Ok(Self {
+            bert,
+            tokenizer,
+            device,
+        })
+    }
+
+    fn embed(&self, texts: Vec<String>, pooling: Pooling) -> Result<Vec<Embedding>> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts, true)
+            .map_err(|error| anyhow!(error))?;
+        // Encodings are padded to the same length, so they stack into one tensor.
+        let stack = |tokens: fn(&Encoding) -> &[u32]| -> Result<Tensor> {
+            let rows = encodings
+                .iter()
+                .map(|encoding| Tensor::new(tokens(encoding), &self.device))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Ok(Tensor::stack(&rows, 0)?)
+        };
+        let token_ids = stack(Encoding::get_ids)?;
+        let token_type_ids = stack(Encoding::get_type_ids)?;
+        let attention_mask = stack(Encoding::get_attention_mask)?;
+
+        let token_embeddings =
+            self.bert
+                .forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+        let embeddings = match pooling {
+            Pooling::Cls => token_embeddings.i((.., 0))?,
+            Pooling::Mean => {
+                // Leave the padding out of the average.
+                let mask = attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+                token_embeddings
+                    .broadcast_mul(&mask)?
+                    .sum(1)?
+                    .broadcast_div(&mask.sum(1)?)?
+            }
+        };
+        Ok(embeddings
+            .to_vec2::<f32>()?
+            .into_iter()
+            .map(Embedding::new)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_client::{FakeHttpClient, Response};
+    use std::{mem, sync::Mutex};
+
+    fn sha256(contents: &str) -> &'static str {
+        hex::encode(Sha256::digest(contents.as_bytes())).leak()
+    }
+
+    #[test]
+    fn test_download_model_files() {
+        let requested_paths = Arc::new(Mutex::new(Vec::new()));
+        let served_config = Arc::new(Mutex::new("{}".to_string()));
+        let client = FakeHttpClient::create({
+            let requested_paths = requested_paths.clone();
+            let served_config = served_config.clone();
+            move |request| {
+                let path = request.uri().path().to_string();
+                requested_paths.lock().unwrap().push(path.clone());
+                let body = match path.as_str() {
+                    "/model/abc123/config.json" => served_config.lock().unwrap().clone(),
+                    "/model/abc123/tokenizer.json" => "tokenizer".to_string(),
+                    _ => String::new(),
+                };
+                async move { Ok(Response::builder().status(200).body(body.into())?) }
+            }
+        });
+        let files = [
+            ModelFile::new("config.json", sha256("{}")),
+            ModelFile::new("tokenizer.json", sha256("tokenizer")),
+        ];
+        let temp_dir = tempfile::tempdir().unwrap();
+        let model_dir = temp_dir.path().join("model");
+        let download = || {
+            smol::block_on(download_model_files(
+                client.as_ref(),
+                "https://test.example/model/abc123",
+                &files,
+                &model_dir,
+            ))
+        };
+        let take_requested_paths = || mem::take(&mut *requested_paths.lock().unwrap());
+
+        // The files are downloaded from the pinned revision.
+        download().unwrap();
+        assert_eq!(
+            take_requested_paths(),
+            ["/model/abc123/config.json", "/model/abc123/tokenizer.json"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(model_dir.join("tokenizer.json")).unwrap(),
+            "tokenizer"
+        );
+
+        // Files that were downloaded aren't downloaded again.
+        download().unwrap();
+        assert!(take_requested_paths().is_empty());
+
+        // Files that were changed since are downloaded again.
+        std::fs::write(model_dir.join("config.json"), "{\"changed\": true}").unwrap();
+        download().unwrap();
+        assert_eq!(take_requested_paths(), ["/model/abc123/config.json"]);
+        assert_eq!(
+            std::fs::read_to_string(model_dir.join("config.json")).unwrap(),
+            "{}"
+        );
+
+        // Downloads that don't match their digest fail without being kept.
+        std::fs::remove_file(model_dir.join("config.json")).unwrap();
+        *served_config.lock().unwrap() = "{\"tampered\": true}".to_string();
+        assert!(download().is_err());
+        assert!(!model_dir.join("config.json").exists());
+        assert!(!model_dir.join("config.json.download").exists());
+    }
+}
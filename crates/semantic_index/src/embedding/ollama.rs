@@ -4,7 +4,7 @@ use http_client::HttpClient;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{Embedding, EmbeddingModelInfo, EmbeddingProvider, ScoreCalibration, TextToEmbed};
 
 pub enum OllamaEmbeddingModel {
     NomicEmbedText,
@@ -31,15 +31,18 @@ impl OllamaEmbeddingProvider {
     pub fn new(client: Arc<dyn HttpClient>, model: OllamaEmbeddingModel) -> Self {
         Self { client, model }
     }
+
+    fn model_name(&self) -> &'static str {
+        match self.model {
+            OllamaEmbeddingModel::NomicEmbedText => "nomic-embed-text",
+            OllamaEmbeddingModel::MxbaiEmbedLarge => "mxbai-embed-large",
+        }
+    }
 }
 
 impl EmbeddingProvider for OllamaEmbeddingProvider {
     fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
-        //
-        let model = match self.model {
-            OllamaEmbeddingModel::NomicEmbedText => "nomic-embed-text",
-            OllamaEmbeddingModel::MxbaiEmbedLarge => "mxbai-embed-large",
-        };
+        let model = self.model_name();
 
         futures::future::try_join_all(texts.iter().map(|to_embed| {
             let request = OllamaEmbeddingRequest {
@@ -71,4 +74,33 @@ impl EmbeddingProvider for OllamaEmbeddingProvider {
         // TODO: Figure out decent value
         10
     }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        let dimensions = match self.model {
+            OllamaEmbeddingModel::NomicEmbedText => 768,
+            OllamaEmbeddingModel::MxbaiEmbedLarge => 1024,
+        };
+        EmbeddingModelInfo {
+            provider: "ollama".into(),
+            model: self.model_name().into(),
+            dimensions,
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        match self.model {
+            OllamaEmbeddingModel::NomicEmbedText => ScoreCalibration {
+                unrelated_similarity: 0.35,
+                relevant_similarity: 0.6,
+            },
+            OllamaEmbeddingModel::MxbaiEmbedLarge => ScoreCalibration {
+                unrelated_similarity: 0.3,
+                relevant_similarity: 0.6,
+            },
+        }
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        Some(0.)
+    }
 }
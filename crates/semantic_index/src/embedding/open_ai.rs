@@ -1,4 +1,7 @@
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimited, RateLimits, ScoreCalibration,
+    TextToEmbed,
+};
 use anyhow::Result;
 use futures::{future::BoxFuture, FutureExt};
 use http_client::HttpClient;
@@ -37,19 +40,91 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
             self.model,
             texts.iter().map(|to_embed| to_embed.text),
         );
-        async move {
-            let response = embed.await?;
-            Ok(response
-                .data
-                .into_iter()
-                .map(|data| Embedding::new(data.embedding))
-                .collect())
-        }
-        .boxed()
+        async move { embeddings_from_response(embed.await) }.boxed()
     }
 
     fn batch_size(&self) -> usize {
         // From https://platform.openai.com/docs/api-reference/embeddings/create
         2048
     }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        model_info("openai", self.model)
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        score_calibration(self.model)
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // The limits of the lowest paid tier, from https://platform.openai.com/docs/guides/rate-limits
+        RateLimits {
+            requests_per_minute: Some(3_000),
+            tokens_per_minute: Some(1_000_000),
+        }
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        // From https://platform.openai.com/docs/api-reference/embeddings/create
+        Some(300_000)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        Some(price_per_million_tokens(self.model))
+    }
+}
+
+/// Converts the response of an API compatible with OpenAI's to embeddings, turning
+/// rate limit errors into [`RateLimited`] so that the requests are retried later.
+pub(super) fn embeddings_from_response(
+    response: Result<open_ai::OpenAiEmbeddingResponse>,
+) -> Result<Vec<Embedding>> {
+    let response =
+        response.map_err(
+            |error| match error.downcast_ref::<open_ai::RateLimitError>() {
+                Some(rate_limit_error) => RateLimited {
+                    retry_after: rate_limit_error.retry_after,
+                }
+                .into(),
+                None => error,
+            },
+        )?;
+    Ok(response
+        .data
+        .into_iter()
+        .map(|data| Embedding::new(data.embedding))
+        .collect())
+}
+
+pub(super) fn model_info(provider: &str, model: OpenAiEmbeddingModel) -> EmbeddingModelInfo {
+    let (model, dimensions) = match model {
+        OpenAiEmbeddingModel::TextEmbedding3Small => ("text-embedding-3-small", 1536),
+        OpenAiEmbeddingModel::TextEmbedding3Large => ("text-embedding-3-large", 3072),
+    };
+    EmbeddingModelInfo {
+        provider: provider.into(),
+        model: model.into(),
+        dimensions,
+    }
+}
+
+/// From https://openai.com/api/pricing, which Azure matches.
+pub(super) fn price_per_million_tokens(model: OpenAiEmbeddingModel) -> f64 {
+    match model {
+        OpenAiEmbeddingModel::TextEmbedding3Small => 0.02,
+        OpenAiEmbeddingModel::TextEmbedding3Large => 0.13,
+    }
+}
+
+pub(super) fn score_calibration(model: OpenAiEmbeddingModel) -> ScoreCalibration {
+    match model {
+        OpenAiEmbeddingModel::TextEmbedding3Small => ScoreCalibration {
+            unrelated_similarity: 0.1,
+            relevant_similarity: 0.4,
+        },
+        OpenAiEmbeddingModel::TextEmbedding3Large => ScoreCalibration {
+            unrelated_similarity: 0.05,
+            relevant_similarity: 0.35,
+        },
+    }
 }
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimited, RateLimits, ScoreCalibration,
+    TextToEmbed,
+};
+
+/// How long to use access tokens from the Google Cloud CLI for, which doesn't say when
+/// they expire. They last an hour.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum VertexAiEmbeddingModel {
+    #[default]
+    #[serde(rename = "text-embedding-004")]
+    TextEmbedding004,
+    #[serde(rename = "text-multilingual-embedding-002")]
+    TextMultilingualEmbedding002,
+}
+
+impl VertexAiEmbeddingModel {
+    fn id(&self) -> &'static str {
+        match self {
+            Self::TextEmbedding004 => "text-embedding-004",
+            Self::TextMultilingualEmbedding002 => "text-multilingual-embedding-002",
+        }
+    }
+}
+
+/// An embedding model on Google Cloud's Vertex AI.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VertexAiSettings {
+    /// The ID of the Google Cloud project to use the model in.
+    pub project: String,
+    /// The region to use the model in.
+    ///
+    /// Default: us-central1
+    #[serde(default = "default_location")]
+    pub location: String,
+    /// The model to use.
+    ///
+    /// Default: text-embedding-004
+    #[serde(default)]
+    pub model: VertexAiEmbeddingModel,
+}
+
+fn default_location() -> String {
+    "us-central1".into()
+}
+
+/// Embeds texts with Vertex AI, authenticating as the user signed into the Google
+/// Cloud CLI.
+pub struct VertexAiEmbeddingProvider {
+    client: Arc<dyn HttpClient>,
+    settings: VertexAiSettings,
+    access_token: smol::lock::Mutex<Option<(String, Instant)>>,
+}
+
+#[derive(Serialize)]
+struct VertexAiEmbeddingRequest<'a> {
+    instances: Vec<VertexAiEmbeddingInstance<'a>>,
+    parameters: VertexAiEmbeddingParameters,
+}
+
+#[derive(Serialize)]
+struct VertexAiEmbeddingInstance<'a> {
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VertexAiEmbeddingParameters {
+    auto_truncate: bool,
+}
+
+#[derive(Deserialize)]
+struct VertexAiEmbeddingResponse {
+    predictions: Vec<VertexAiPrediction>,
+}
+
+#[derive(Deserialize)]
+struct VertexAiPrediction {
+    embeddings: VertexAiEmbedding,
+}
+
+#[derive(Deserialize)]
+struct VertexAiEmbedding {
+    values: Vec<f32>,
+}
+
+impl VertexAiEmbeddingProvider {
+    pub fn new(client: Arc<dyn HttpClient>, settings: VertexAiSettings) -> Self {
+        Self {
+            client,
+            settings,
+            access_token: Default::default(),
+        }
+    }
+
+    fn uri(&self) -> String {
+        let VertexAiSettings {
+            project,
+            location,
+            model,
+        } = &self.settings;
+        format!(
+            "https://{location}-aiplatform.googleapis.com/v1/projects/{project}\
+            /locations/{location}/publishers/google/models/{}:predict",
+            model.id()
+        )
+    }
+
+    /// Returns a token from the Google Cloud CLI, reusing it until it's about to
+    /// expire.
+    async fn access_token(&self) -> Result<String> {
+        let mut access_token = self.access_token.lock().await;
+        if let Some((token, fetched_at)) = access_token.as_ref() {
+            if fetched_at.elapsed() < TOKEN_LIFETIME {
+                return Ok(token.clone());
+            }
+        }
+
+        let output = smol::process::Command::new("gcloud")
+            .args(["auth", "print-access-token"])
+            .output()
+            .await
+            .context("running the Google Cloud CLI")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "the Google Cloud CLI failed to get an access token: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let token = String::from_utf8(output.stdout)?.trim().to_string();
+        *access_token = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+}
+
+impl EmbeddingProvider for VertexAiEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let access_token = self.access_token().await?;
+            let request = VertexAiEmbeddingRequest {
+                instances: texts
+                    .iter()
+                    .map(|to_embed| VertexAiEmbeddingInstance {
+                        content: to_embed.text,
+                    })
+                    .collect(),
+                // Embed the start of texts that are too long, rather than failing them.
+                parameters: VertexAiEmbeddingParameters {
+                    auto_truncate: true,
+                },
+            };
+            let request = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(self.uri())
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {access_token}"))
+                .body(AsyncBody::from(serde_json::to_string(&request)?))?;
+            let mut response = self.client.send(request).await?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            if response.status().is_success() {
+                let response: VertexAiEmbeddingResponse = serde_json::from_str(&body)
+                    .context("failed to parse Vertex AI embedding response")?;
+                Ok(response
+                    .predictions
+                    .into_iter()
+                    .map(|prediction| Embedding::new(prediction.embeddings.values))
+                    .collect())
+            } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                Err(RateLimited { retry_after: None }.into())
+            } else {
+                Err(anyhow!(
+                    "error during embedding, status: {:?}, body: {:?}",
+                    response.status(),
+                    body
+                ))
+            }
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // Requests are limited to 250 texts and 20,000 tokens, from
+        // https://cloud.google.com/vertex-ai/generative-ai/docs/embeddings/get-text-embeddings
+        16
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "vertex_ai".into(),
+            model: self.settings.model.id().into(),
+            dimensions: 768,
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        ScoreCalibration {
+            unrelated_similarity: 0.45,
+            relevant_similarity: 0.65,
+        }
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // The default quota of online prediction requests per base model and region.
+        RateLimits {
+            requests_per_minute: Some(1_500),
+            tokens_per_minute: None,
+        }
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        Some(20_000)
+    }
+}
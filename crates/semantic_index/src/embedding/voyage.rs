@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimited, RateLimits, ScoreCalibration,
+    TextToEmbed,
+};
+
+const VOYAGE_API_URL: &str = "https://api.voyageai.com/v1/embeddings";
+/// The environment variable to read the Voyage AI API key from.
+const API_KEY_VAR: &str = "VOYAGE_API_KEY";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum VoyageEmbeddingModel {
+    #[default]
+    #[serde(rename = "voyage-code-3")]
+    VoyageCode3,
+    #[serde(rename = "voyage-code-2")]
+    VoyageCode2,
+}
+
+impl VoyageEmbeddingModel {
+    fn id(&self) -> &'static str {
+        match self {
+            Self::VoyageCode3 => "voyage-code-3",
+            Self::VoyageCode2 => "voyage-code-2",
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        match self {
+            Self::VoyageCode3 => 1024,
+            Self::VoyageCode2 => 1536,
+        }
+    }
+}
+
+/// A code embedding model from Voyage AI, with the API key in the `VOYAGE_API_KEY`
+/// environment variable.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VoyageSettings {
+    /// The model to use.
+    ///
+    /// Default: voyage-code-3
+    #[serde(default)]
+    pub model: VoyageEmbeddingModel,
+}
+
+pub struct VoyageEmbeddingProvider {
+    client: Arc<dyn HttpClient>,
+    settings: VoyageSettings,
+}
+
+#[derive(Serialize)]
+struct VoyageEmbeddingRequest<'a> {
+    input: Vec<&'a str>,
+    model: &'static str,
+    input_type: VoyageInputType,
+    truncation: bool,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum VoyageInputType {
+    Document,
+    Query,
+}
+
+#[derive(Deserialize)]
+struct VoyageEmbeddingResponse {
+    data: Vec<VoyageEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct VoyageEmbedding {
+    embedding: Vec<f32>,
+}
+
+impl VoyageEmbeddingProvider {
+    pub fn new(client: Arc<dyn HttpClient>, settings: VoyageSettings) -> Self {
+        Self { client, settings }
+    }
+
+    async fn embed_as(
+        &self,
+        texts: &[TextToEmbed<'_>],
+        input_type: VoyageInputType,
+    ) -> Result<Vec<Embedding>> {
+        let api_key =
+            std::env::var(API_KEY_VAR).with_context(|| format!("{API_KEY_VAR} isn't set"))?;
+        let request = VoyageEmbeddingRequest {
+            input: texts.iter().map(|to_embed| to_embed.text).collect(),
+            model: self.settings.model.id(),
+            input_type,
+            // Embed the start of texts that are too long, rather than failing them.
+            truncation: true,
+        };
+        let request = HttpRequest::builder()
+            .method(Method::POST)
+            .uri(VOYAGE_API_URL)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {api_key}"))
+            .body(AsyncBody::from(serde_json::to_string(&request)?))?;
+        let mut response = self.client.send(request).await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        if response.status().is_success() {
+            let response: VoyageEmbeddingResponse = serde_json::from_str(&body)
+                .context("failed to parse Voyage AI embedding response")?;
+            Ok(response
+                .data
+                .into_iter()
+                .map(|data| Embedding::new(data.embedding))
+                .collect())
+        } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            Err(RateLimited { retry_after: None }.into())
+        } else {
+            Err(anyhow!(
+                "error during embedding, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
+impl EmbeddingProvider for VoyageEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(texts, VoyageInputType::Document).boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_as(queries, VoyageInputType::Query).boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // From https://docs.voyageai.com/reference/embeddings-api
+        1_000
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        EmbeddingModelInfo {
+            provider: "voyage".into(),
+            model: self.settings.model.id().into(),
+            dimensions: self.settings.model.dimensions(),
+        }
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        ScoreCalibration {
+            unrelated_similarity: 0.3,
+            relevant_similarity: 0.5,
+        }
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        // The limits of the first usage tier, from https://docs.voyageai.com/docs/rate-limits
+        RateLimits {
+            requests_per_minute: Some(2_000),
+            tokens_per_minute: Some(3_000_000),
+        }
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        Some(120_000)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        // From https://docs.voyageai.com/docs/pricing
+        Some(match self.settings.model {
+            VoyageEmbeddingModel::VoyageCode3 => 0.18,
+            VoyageEmbeddingModel::VoyageCode2 => 0.12,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_client::{FakeHttpClient, Response};
+    use parking_lot::Mutex;
+
+    /// A response of the embeddings API, as sent by Voyage AI.
+    const RESPONSE: &str = r#"{
+        "object": "list",
+        "data": [{"object": "embedding", "embedding": [0.6, 0.8], "index": 0}],
+        "model": "voyage-code-3",
+        "usage": {"total_tokens": 4}
+    }"#;
+
+    #[gpui::test]
+    async fn test_embed_documents_and_queries() {
+        std::env::set_var(API_KEY_VAR, "test-key");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = FakeHttpClient::create({
+            let requests = requests.clone();
+            move |request| {
+                let requests = requests.clone();
+                async move {
+                    assert_eq!(request.uri(), VOYAGE_API_URL);
+                    assert_eq!(request.headers()["Authorization"], "Bearer test-key");
+                    let mut body = String::new();
+                    request.into_body().read_to_string(&mut body).await?;
+                    requests
+                        .lock()
+                        .push(serde_json::from_str::<serde_json::Value>(&body).unwrap());
+                    Ok(Response::builder().status(200).body(RESPONSE.into())?)
+                }
+            }
+        });
+        let provider = VoyageEmbeddingProvider::new(client, VoyageSettings::default());
+
+        let embeddings = provider
+            .embed(&[TextToEmbed::new("fn main() {}")])
+            .await
+            .unwrap();
+        assert_eq!(embeddings, vec![Embedding::new(vec![0.6, 0.8])]);
+        provider
+            .embed_query(&[TextToEmbed::new("entry point")])
+            .await
+            .unwrap();
+
+        // Documents and queries are embedded differently.
+        assert_eq!(
+            *requests.lock(),
+            vec![
+                serde_json::json!({
+                    "input": ["fn main() {}"],
+                    "model": "voyage-code-3",
+                    "input_type": "document",
+                    "truncation": true,
+                }),
+                serde_json::json!({
+                    "input": ["entry point"],
+                    "model": "voyage-code-3",
+                    "input_type": "query",
+                    "truncation": true,
+                }),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_rate_limited() {
+        std::env::set_var(API_KEY_VAR, "test-key");
+        let client = FakeHttpClient::create(|_| async move {
+            Ok(Response::builder()
+                .status(429)
+                .body(r#"{"detail": "You have exceeded your rate limit."}"#.into())?)
+        });
+        let provider = VoyageEmbeddingProvider::new(client, VoyageSettings::default());
+        let error = provider
+            .embed(&[TextToEmbed::new("fn main() {}")])
+            .await
+            .unwrap_err();
+        assert!(error.is::<RateLimited>());
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use collections::HashMap;
+use heed::{types::SerdeBincode, BytesDecode, BytesEncode};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::{
+    kv_store::{KvStorage, KvStore},
+    shared_embeddings::SharedEmbeddings,
+    Embedding, EmbeddingModelInfo,
+};
+
+/// How many embeddings to keep in memory. Embeddings evicted from memory can still be
+/// read back from the database.
+const RECENT_EMBEDDING_CAPACITY: usize = 4096;
+
+/// The embeddings of chunks by the digest of their text, shared by every worktree so
+/// that identical content is only embedded once per model. Recently used embeddings
+/// are kept in memory, and every embedding is persisted.
+pub(crate) struct EmbeddingCache {
+    store: Arc<dyn KvStore>,
+    recent: Mutex<RecentEmbeddings>,
+    /// The store shared with the rest of a team to pull missing embeddings from.
+    shared: Mutex<Option<SharedEmbeddings>>,
+}
+
+#[derive(Default)]
+struct RecentEmbeddings {
+    embeddings: HashMap<[u8; 32], (Embedding, u64)>,
+    clock: u64,
+}
+
+impl RecentEmbeddings {
+    fn get(&mut self, digest: &[u8; 32]) -> Option<Embedding> {
+        self.clock += 1;
+        let (embedding, last_used) = self.embeddings.get_mut(digest)?;
+        *last_used = self.clock;
+        Some(embedding.clone())
+    }
+
+    fn insert(&mut self, digest: [u8; 32], embedding: Embedding) {
+        self.clock += 1;
+        self.embeddings.insert(digest, (embedding, self.clock));
+        if self.embeddings.len() > RECENT_EMBEDDING_CAPACITY {
+            // Evict the least recently used quarter at once, so that eviction is rare.
+            let mut last_used = self
+                .embeddings
+                .values()
+                .map(|(_, last_used)| *last_used)
+                .collect::<Vec<_>>();
+            let evicted_count = RECENT_EMBEDDING_CAPACITY / 4;
+            let (_, threshold, _) = last_used.select_nth_unstable(evicted_count);
+            let threshold = *threshold;
+            self.embeddings
+                .retain(|_, (_, last_used)| *last_used > threshold);
+        }
+    }
+}
+
+impl EmbeddingCache {
+    pub fn new(storage: &KvStorage, model: &EmbeddingModelInfo) -> Result<Self> {
+        let store = storage.open(&format!(
+            "embedding_cache:{}:{}:{}",
+            model.provider, model.model, model.dimensions
+        ))?;
+        Ok(Self {
+            store,
+            recent: Mutex::new(RecentEmbeddings::default()),
+            shared: Mutex::new(None),
+        })
+    }
+
+    pub fn set_shared(&self, shared: Option<SharedEmbeddings>) {
+        *self.shared.lock() = shared;
+    }
+
+    pub fn shared(&self) -> Option<SharedEmbeddings> {
+        self.shared.lock().clone()
+    }
+
+    /// Returns the cached embedding of every digest, if any.
+    pub fn get(&self, digests: &[[u8; 32]]) -> Result<Vec<Option<Embedding>>> {
+        let mut embeddings = {
+            let mut recent = self.recent.lock();
+            digests
+                .iter()
+                .map(|digest| recent.get(digest))
+                .collect::<Vec<_>>()
+        };
+        if embeddings.iter().all(Option::is_some) {
+            return Ok(embeddings);
+        }
+
+        let missing_digests = digests
+            .iter()
+            .zip(&embeddings)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(digest, _)| digest.as_slice())
+            .collect::<Vec<_>>();
+        let mut stored_embeddings = self.store.get_many(&missing_digests)?.into_iter();
+        let mut recent = self.recent.lock();
+        for (digest, embedding) in digests.iter().zip(&mut embeddings) {
+            if embedding.is_none() {
+                *embedding = stored_embeddings
+                    .next()
+                    .flatten()
+                    .map(|bytes| SerdeBincode::<Embedding>::bytes_decode(&bytes))
+                    .transpose()
+                    .map_err(|error| anyhow!(error))?;
+                if let Some(embedding) = embedding {
+                    recent.insert(*digest, embedding.clone());
+                }
+            }
+        }
+        Ok(embeddings)
+    }
+
+    pub fn insert(&self, embeddings: &[([u8; 32], Embedding)]) -> Result<()> {
+        // Encoded like LMDB databases encode values, so that the embeddings cached by
+        // earlier versions can still be read.
+        let encoded_embeddings = embeddings
+            .iter()
+            .map(|(_, embedding)| {
+                SerdeBincode::<Embedding>::bytes_encode(embedding).map_err(|error| anyhow!(error))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let entries = embeddings
+            .iter()
+            .zip(&encoded_embeddings)
+            .map(|((digest, _), encoded_embedding)| (digest.as_slice(), encoded_embedding.as_ref()))
+            .collect::<Vec<_>>();
+        self.store.put_many(&entries)?;
+
+        let mut recent = self.recent.lock();
+        for (digest, embedding) in embeddings {
+            recent.insert(*digest, embedding.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_embeddings_eviction() {
+        let digest = |ix: usize| {
+            let mut digest = [0; 32];
+            digest[..8].copy_from_slice(&ix.to_le_bytes());
+            digest
+        };
+
+        let mut recent = RecentEmbeddings::default();
+        for ix in 0..RECENT_EMBEDDING_CAPACITY {
+            recent.insert(digest(ix), Embedding::new(vec![1.]));
+        }
+        // Using the first embedding keeps it from being evicted with the other old ones.
+        assert!(recent.get(&digest(0)).is_some());
+        recent.insert(digest(RECENT_EMBEDDING_CAPACITY), Embedding::new(vec![1.]));
+
+        assert!(recent.embeddings.len() <= RECENT_EMBEDDING_CAPACITY);
+        assert!(recent.get(&digest(0)).is_some());
+        assert!(recent.get(&digest(1)).is_none());
+        assert!(recent.get(&digest(RECENT_EMBEDDING_CAPACITY)).is_some());
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Result};
+use heed::{
+    types::{Bytes, SerdeBincode, Str},
+    BytesDecode, BytesEncode,
+};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{marker::PhantomData, ops::Bound};
+
+pub(crate) const KEY_LEN: usize = 32;
+
+/// Encrypts the index of worktrees that opt into it, with a key kept in the OS keychain.
+///
+/// Values are encrypted with ChaCha20-Poly1305, bound to the key they're stored under so
+/// that they can't be swapped. Keys that have to be looked up, like the terms of the
+/// keyword index, are replaced by a keyed digest instead. Separate keys are derived for
+/// each, so that neither use weakens the other.
+#[derive(Clone, Copy)]
+pub(crate) struct IndexCipher {
+    encryption_key: [u8; KEY_LEN],
+    digest_key: [u8; KEY_LEN],
+}
+
+impl IndexCipher {
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        let derive = |label: &[u8]| {
+            let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), label);
+            let mut derived = [0; KEY_LEN];
+            derived.copy_from_slice(&tag.as_ref()[..KEY_LEN]);
+            derived
+        };
+        Self {
+            encryption_key: derive(b"semantic index encryption"),
+            digest_key: derive(b"semantic index digests"),
+        }
+    }
+
+    pub fn generate_key() -> Result<[u8; KEY_LEN]> {
+        let mut key = [0; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| anyhow!("failed to generate an encryption key"))?;
+        Ok(key)
+    }
+
+    /// Identifies the key without revealing it, to tell whether an index was encrypted
+    /// with it.
+    pub fn fingerprint(&self) -> String {
+        self.digest("fingerprint")
+    }
+
+    /// Returns a keyed digest of the text, to store in its place where it's looked up.
+    pub fn digest(&self, text: &str) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.digest_key);
+        hex::encode(&hmac::sign(&key, text.as_bytes()).as_ref()[..16])
+    }
+
+    /// Encrypts a value stored under the given key, prefixing it with a random nonce.
+    pub fn seal(&self, key: &str, value: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("failed to generate a nonce"))?;
+        let mut sealed = value.to_vec();
+        self.aead_key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("failed to encrypt the value of {key:?}"))?;
+        sealed.splice(0..0, nonce);
+        Ok(sealed)
+    }
+
+    /// Decrypts a value sealed by [`Self::seal`] under the same key.
+    pub fn open(&self, key: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let error = || anyhow!("failed to decrypt the value of {key:?}");
+        if sealed.len() < NONCE_LEN {
+            return Err(error());
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| error())?;
+        let mut value = sealed.to_vec();
+        let len = self
+            .aead_key()?
+            .open_in_place(nonce, Aad::from(key.as_bytes()), &mut value)
+            .map_err(|_| error())?
+            .len();
+        value.truncate(len);
+        Ok(value)
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &self.encryption_key)
+            .map_err(|_| anyhow!("invalid encryption key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+/// A database of values serialized with bincode, like
+/// `heed::Database<Str, SerdeBincode<T>>`, that encrypts them when given a cipher.
+/// Values are stored exactly as `SerdeBincode` stores them otherwise.
+pub(crate) struct EncryptedDatabase<T> {
+    db: heed::Database<Str, Bytes>,
+    cipher: Option<IndexCipher>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for EncryptedDatabase<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for EncryptedDatabase<T> {}
+
+impl<T> EncryptedDatabase<T>
+where
+    T: 'static + Serialize + DeserializeOwned,
+{
+    pub fn create(
+        env: &heed::Env,
+        txn: &mut heed::RwTxn,
+        name: &str,
+        cipher: Option<IndexCipher>,
+    ) -> Result<Self> {
+        Ok(Self {
+            db: env.create_database(txn, Some(name))?,
+            cipher,
+            _value: PhantomData,
+        })
+    }
+
+    pub fn get(&self, txn: &heed::RoTxn, key: &str) -> Result<Option<T>> {
+        self.db
+            .get(txn, key)?
+            .map(|bytes| self.decode(key, bytes))
+            .transpose()
+    }
+
+    pub fn put(&self, txn: &mut heed::RwTxn, key: &str, value: &T) -> Result<()> {
+        let bytes = SerdeBincode::<T>::bytes_encode(value).map_err(|error| anyhow!(error))?;
+        match &self.cipher {
+            Some(cipher) => self.db.put(txn, key, &cipher.seal(key, &bytes)?)?,
+            None => self.db.put(txn, key, &bytes)?,
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, txn: &mut heed::RwTxn, key: &str) -> Result<bool> {
+        Ok(self.db.delete(txn, key)?)
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<usize> {
+        Ok(self.db.delete_range(txn, range)?)
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        Ok(self.db.clear(txn)?)
+    }
+
+    pub fn is_empty(&self, txn: &heed::RoTxn) -> Result<bool> {
+        Ok(self.db.is_empty(txn)?)
+    }
+
+    pub fn len(&self, txn: &heed::RoTxn) -> Result<u64> {
+        Ok(self.db.len(txn)?)
+    }
+
+    pub fn iter<'txn>(
+        &self,
+        txn: &'txn heed::RoTxn,
+    ) -> Result<impl Iterator<Item = Result<(&'txn str, T)>> + 'txn> {
+        let this = *self;
+        Ok(self
+            .db
+            .iter(txn)?
+            .map(move |entry| this.decode_entry(entry)))
+    }
+
+    pub fn range<'txn>(
+        &self,
+        txn: &'txn heed::RoTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<impl Iterator<Item = Result<(&'txn str, T)>> + 'txn> {
+        let this = *self;
+        Ok(self
+            .db
+            .range(txn, range)?
+            .map(move |entry| this.decode_entry(entry)))
+    }
+
+    pub fn prefix_iter<'txn>(
+        &self,
+        txn: &'txn heed::RoTxn,
+        prefix: &str,
+    ) -> Result<impl Iterator<Item = Result<(&'txn str, T)>> + 'txn> {
+        let this = *self;
+        Ok(self
+            .db
+            .prefix_iter(txn, prefix)?
+            .map(move |entry| this.decode_entry(entry)))
+    }
+
+    fn decode_entry<'txn>(
+        &self,
+        entry: heed::Result<(&'txn str, &'txn [u8])>,
+    ) -> Result<(&'txn str, T)> {
+        let (key, bytes) = entry?;
+        Ok((key, self.decode(key, bytes)?))
+    }
+
+    fn decode(&self, key: &str, bytes: &[u8]) -> Result<T> {
+        let opened;
+        let bytes = match &self.cipher {
+            Some(cipher) => {
+                opened = cipher.open(key, bytes)?;
+                opened.as_slice()
+            }
+            None => bytes,
+        };
+        SerdeBincode::<T>::bytes_decode(bytes).map_err(|error| anyhow!(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_connection::{DbConnection, TEST_MAP_SIZE, TEST_MAX_DBS};
+
+    #[test]
+    fn test_index_cipher() {
+        let cipher = IndexCipher::new(&IndexCipher::generate_key().unwrap());
+        let sealed = cipher.seal("a.rs", b"fn main() {}").unwrap();
+        assert!(!sealed
+            .windows(b"main".len())
+            .any(|window| window == b"main"));
+        assert_eq!(cipher.open("a.rs", &sealed).unwrap(), b"fn main() {}");
+        // Values can't be read under another key, or with another cipher.
+        assert!(cipher.open("b.rs", &sealed).is_err());
+        let other_cipher = IndexCipher::new(&IndexCipher::generate_key().unwrap());
+        assert!(other_cipher.open("a.rs", &sealed).is_err());
+        assert_ne!(cipher.fingerprint(), other_cipher.fingerprint());
+
+        assert_eq!(cipher.digest("main"), cipher.digest("main"));
+        assert_ne!(cipher.digest("main"), cipher.digest("fn"));
+        assert_ne!(cipher.digest("main"), other_cipher.digest("main"));
+    }
+
+    #[test]
+    fn test_encrypted_database() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let cipher = IndexCipher::new(&IndexCipher::generate_key().unwrap());
+        let mut txn = db_connection.write_txn().unwrap();
+        for (name, cipher) in [("plain", None), ("encrypted", Some(cipher))] {
+            let db = EncryptedDatabase::<String>::create(&db_connection, &mut txn, name, cipher)
+                .unwrap();
+            db.put(&mut txn, "a", &"fn main() {}".to_string()).unwrap();
+            db.put(&mut txn, "b", &"struct Point;".to_string()).unwrap();
+            assert_eq!(db.get(&txn, "a").unwrap().as_deref(), Some("fn main() {}"));
+            assert_eq!(
+                db.range(&txn, &(Bound::Excluded("a"), Bound::Unbounded))
+                    .unwrap()
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap(),
+                [("b", "struct Point;".to_string())]
+            );
+
+            // Only the encrypted database hides the values it stores.
+            let raw = db_connection
+                .open_database::<Str, Bytes>(&txn, Some(name))
+                .unwrap()
+                .unwrap();
+            let raw_value = raw.get(&txn, "a").unwrap().unwrap();
+            let contains_text = raw_value
+                .windows(b"main".len())
+                .any(|window| window == b"main");
+            assert_eq!(contains_text, cipher.is_none());
+        }
+    }
+}
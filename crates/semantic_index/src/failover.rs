@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use futures::{
+    future::{self, BoxFuture, Either},
+    FutureExt,
+};
+use gpui::BackgroundExecutor;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimits, ScoreCalibration, TextToEmbed,
+};
+
+/// How many requests in a row a provider has to fail before failing over to the next.
+const FAILOVER_THRESHOLD: u32 = 3;
+/// How long a request can take before it counts as failed, so that a provider that
+/// stopped responding is failed over like one that returns errors.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How long to wait after failing over before trying the first provider again.
+pub(crate) const PRIMARY_RETRY_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Which provider of a chain requests are sent to.
+#[derive(Debug, Default, PartialEq)]
+struct FailoverState {
+    active: usize,
+    /// How many requests in a row the active provider failed.
+    consecutive_failures: u32,
+    /// When requests last failed over, or when the first provider last failed to
+    /// recover, unless requests are sent to the first provider.
+    failed_over_at: Option<Instant>,
+}
+
+impl FailoverState {
+    /// Returns the provider to send a request to at `now`, which is the first one
+    /// whenever it's due to be tried again.
+    fn select(&self, now: Instant) -> usize {
+        match self.failed_over_at {
+            Some(failed_over_at)
+                if now.saturating_duration_since(failed_over_at) >= PRIMARY_RETRY_INTERVAL =>
+            {
+                0
+            }
+            _ => self.active,
+        }
+    }
+
+    /// Records whether a request to the given provider succeeded, failing over to the
+    /// next of `provider_count` providers once the active one failed too many times
+    /// in a row.
+    fn record(&mut self, provider_ix: usize, succeeded: bool, provider_count: usize, now: Instant) {
+        if provider_ix < self.active {
+            if succeeded {
+                *self = Self::default();
+            } else {
+                self.failed_over_at = Some(now);
+            }
+        } else if provider_ix == self.active {
+            if succeeded {
+                self.consecutive_failures = 0;
+            } else {
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= FAILOVER_THRESHOLD
+                    && self.active + 1 < provider_count
+                {
+                    self.active += 1;
+                    self.consecutive_failures = 0;
+                    self.failed_over_at = Some(now);
+                }
+            }
+        }
+    }
+}
+
+/// Sends requests to the first of a chain of embedding providers that works, so that
+/// indexing continues with another model while a provider is down. The first provider
+/// is tried again periodically, and requests go back to it once it works.
+///
+/// Its model is the model of the index, so embeddings from the other providers can
+/// only be compared with queries embedded by the same provider, and are replaced once
+/// the first provider is back.
+pub(crate) struct FallbackEmbeddingProvider {
+    providers: Vec<Arc<dyn EmbeddingProvider>>,
+    state: Mutex<FailoverState>,
+    executor: BackgroundExecutor,
+}
+
+impl FallbackEmbeddingProvider {
+    pub fn new(providers: Vec<Arc<dyn EmbeddingProvider>>, executor: BackgroundExecutor) -> Self {
+        debug_assert!(!providers.is_empty());
+        Self {
+            providers,
+            state: Mutex::new(FailoverState::default()),
+            executor,
+        }
+    }
+
+    /// Sends a request to the provider it's due to go to, counting it as failed if it
+    /// times out. Returns which provider it was sent to, along with its result.
+    async fn send<'a, T>(
+        &'a self,
+        request: impl FnOnce(&'a dyn EmbeddingProvider) -> BoxFuture<'a, T>,
+        timed_out: impl FnOnce() -> T,
+        succeeded: impl FnOnce(&T) -> bool,
+    ) -> (usize, T) {
+        let provider_ix = self.state.lock().select(self.executor.now());
+        let provider = self.providers[provider_ix].as_ref();
+        let timeout = self.executor.timer(REQUEST_TIMEOUT);
+        let result = match future::select(request(provider), timeout).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => timed_out(),
+        };
+
+        let mut state = self.state.lock();
+        let active = state.active;
+        state.record(
+            provider_ix,
+            succeeded(&result),
+            self.providers.len(),
+            self.executor.now(),
+        );
+        if state.active > active {
+            log::warn!(
+                "failing over from embedding provider {:?} to {:?} after {FAILOVER_THRESHOLD} \
+                failed requests",
+                self.providers[active].model_info(),
+                self.providers[state.active].model_info()
+            );
+        } else if state.active < active {
+            log::info!(
+                "embedding provider {:?} recovered",
+                self.providers[state.active].model_info()
+            );
+        }
+        (provider_ix, result)
+    }
+}
+
+fn timeout_error() -> anyhow::Error {
+    anyhow!("embedding request timed out after {REQUEST_TIMEOUT:?}")
+}
+
+impl EmbeddingProvider for FallbackEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.send(
+            |provider| provider.embed(texts),
+            || Err(timeout_error()),
+            Result::is_ok,
+        )
+        .map(|(_, result)| result)
+        .boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.embed_query_with_model(queries)
+            .map(|result| result.map(|(_, _, embeddings)| embeddings))
+            .boxed()
+    }
+
+    fn embed_with_results<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Vec<Result<Embedding>>> {
+        self.embed_with_model(texts)
+            .map(|(_, results)| results)
+            .boxed()
+    }
+
+    fn embed_with_model<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, (EmbeddingModelInfo, Vec<Result<Embedding>>)> {
+        async move {
+            // Batches where only some texts failed, like for being too long, don't
+            // count as failed requests.
+            let (provider_ix, results) = self
+                .send(
+                    |provider| provider.embed_with_results(texts),
+                    || texts.iter().map(|_| Err(timeout_error())).collect(),
+                    |results: &Vec<Result<Embedding>>| results.iter().any(Result::is_ok),
+                )
+                .await;
+            (self.providers[provider_ix].model_info(), results)
+        }
+        .boxed()
+    }
+
+    fn embed_query_with_model<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<(EmbeddingModelInfo, ScoreCalibration, Vec<Embedding>)>> {
+        async move {
+            let (provider_ix, result) = self
+                .send(
+                    |provider| provider.embed_query(queries),
+                    || Err(timeout_error()),
+                    Result::is_ok,
+                )
+                .await;
+            let provider = &self.providers[provider_ix];
+            Ok((provider.model_info(), provider.score_calibration(), result?))
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.providers
+            .iter()
+            .map(|provider| provider.batch_size())
+            .min()
+            .unwrap_or(1)
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        self.providers[0].model_info()
+    }
+
+    fn active_model(&self) -> EmbeddingModelInfo {
+        let provider_ix = self.state.lock().select(self.executor.now());
+        self.providers[provider_ix].model_info()
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        self.providers[0].score_calibration()
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        self.providers[0].rate_limits()
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        self.providers
+            .iter()
+            .filter_map(|provider| provider.max_input_tokens())
+            .min()
+    }
+
+    fn count_tokens(&self, text: &str) -> u64 {
+        self.providers
+            .iter()
+            .map(|provider| provider.count_tokens(text))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        self.providers[0].price_per_million_tokens()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover_state() {
+        let start = Instant::now();
+        let mut state = FailoverState::default();
+
+        // Failures that aren't in a row don't fail over.
+        state.record(0, false, 3, start);
+        state.record(0, false, 3, start);
+        state.record(0, true, 3, start);
+        state.record(0, false, 3, start);
+        assert_eq!(state.select(start), 0);
+
+        state.record(0, false, 3, start);
+        state.record(0, false, 3, start);
+        assert_eq!(state.select(start), 1);
+        // Requests that were sent before failing over don't count against the next
+        // provider.
+        state.record(0, false, 3, start);
+        assert_eq!(state.consecutive_failures, 0);
+
+        // The first provider is tried again once it's due, and requests keep going
+        // to the active provider for a while longer if it still fails.
+        let retry_at = start + PRIMARY_RETRY_INTERVAL;
+        assert_eq!(state.select(retry_at - Duration::from_secs(1)), 1);
+        assert_eq!(state.select(retry_at), 0);
+        state.record(0, false, 3, retry_at);
+        assert_eq!(state.select(retry_at), 1);
+
+        // The last provider is kept however often it fails.
+        for _ in 0..FAILOVER_THRESHOLD * 2 {
+            state.record(state.active, false, 3, retry_at);
+        }
+        assert_eq!(state.active, 2);
+
+        let recover_at = retry_at + PRIMARY_RETRY_INTERVAL;
+        assert_eq!(state.select(recover_at), 0);
+        state.record(0, true, 3, recover_at);
+        assert_eq!(state, FailoverState::default());
+    }
+}
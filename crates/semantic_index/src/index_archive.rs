@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::Path, sync::Arc};
+
+use crate::{Embedding, EmbeddingModelInfo};
+
+/// The version of the archive format, bumped whenever it changes incompatibly.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// A portable copy of the embeddings of a project's worktrees, which can be exported
+/// on one machine, like a CI server, and imported on another so that it doesn't embed
+/// the same files again.
+///
+/// Embeddings are imported by the digest of the text they were computed from, so they
+/// are reused for any chunk with the same text, whatever its path or modification time.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IndexArchive {
+    version: u32,
+    model: EmbeddingModelInfo,
+    worktrees: Vec<ArchivedWorktree>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivedWorktree {
+    pub root_name: String,
+    pub files: Vec<ArchivedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivedFile {
+    /// The path of the file, relative to the root of its worktree.
+    pub path: Arc<Path>,
+    pub chunks: Vec<ArchivedChunk>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivedChunk {
+    pub range: Range<usize>,
+    pub digest: [u8; 32],
+    pub embedding: Embedding,
+}
+
+impl IndexArchive {
+    pub fn new(model: EmbeddingModelInfo, worktrees: Vec<ArchivedWorktree>) -> Self {
+        Self {
+            version: ARCHIVE_VERSION,
+            model,
+            worktrees,
+        }
+    }
+
+    /// Reads an archive, checking that its embeddings were produced by the given model.
+    pub fn parse(json: &str, model: &EmbeddingModelInfo) -> Result<Self> {
+        let archive = serde_json::from_str::<Self>(json)?;
+        if archive.version != ARCHIVE_VERSION {
+            return Err(anyhow!(
+                "unsupported index archive version {}, expected {}",
+                archive.version,
+                ARCHIVE_VERSION
+            ));
+        }
+        if archive.model != *model {
+            return Err(anyhow!(
+                "index archive was embedded with {:?}, but the current model is {:?}",
+                archive.model,
+                model
+            ));
+        }
+        Ok(archive)
+    }
+
+    /// Returns the embedding of every chunk in the archive, by the digest of its text.
+    pub fn into_embeddings(self) -> Vec<([u8; 32], Embedding)> {
+        self.worktrees
+            .into_iter()
+            .flat_map(|worktree| worktree.files)
+            .flat_map(|file| file.chunks)
+            .map(|chunk| (chunk.digest, chunk.embedding))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_index_archive() {
+        let model = EmbeddingModelInfo {
+            provider: "test".into(),
+            model: "test".into(),
+            dimensions: 2,
+        };
+        let archive = IndexArchive::new(
+            model.clone(),
+            vec![ArchivedWorktree {
+                root_name: "project".into(),
+                files: vec![ArchivedFile {
+                    path: Path::new("src/main.rs").into(),
+                    chunks: vec![ArchivedChunk {
+                        range: 0..10,
+                        digest: [1; 32],
+                        embedding: Embedding::new(vec![1., 0.]),
+                    }],
+                }],
+            }],
+        );
+        let json = serde_json::to_string(&archive).unwrap();
+
+        let embeddings = IndexArchive::parse(&json, &model)
+            .unwrap()
+            .into_embeddings();
+        assert_eq!(embeddings, vec![([1; 32], Embedding::new(vec![1., 0.]))]);
+
+        let other_model = EmbeddingModelInfo {
+            dimensions: 3,
+            ..model
+        };
+        assert!(IndexArchive::parse(&json, &other_model).is_err());
+    }
+}
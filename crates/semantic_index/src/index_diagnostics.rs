@@ -0,0 +1,107 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::{chunking::ChunkConfig, retry_backlog::FailedFile, EmbeddingModelInfo, Status};
+
+/// The state of the index of a project, exported from the debug view as JSON to attach
+/// to bug reports.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct IndexDiagnostics {
+    pub status: Status,
+    /// The model the embedding provider embeds files with.
+    pub embedding_model: EmbeddingModelInfo,
+    /// The size in bytes of the database shared by every worktree, which holds the
+    /// embedding cache.
+    pub shared_database_size: Option<u64>,
+    /// The worktrees whose index is loaded.
+    pub worktrees: Vec<WorktreeDiagnostics>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct WorktreeDiagnostics {
+    pub abs_path: PathBuf,
+    pub file_count: u64,
+    pub chunk_count: u64,
+    pub skipped_file_count: usize,
+    /// The files embedded by a fallback provider, which are embedded again once the
+    /// embedding provider is back.
+    pub fallback_file_count: u64,
+    /// The files that are being indexed.
+    pub pending_file_count: usize,
+    /// The files in the retry backlog, which failed to embed.
+    pub failed_files: Vec<FailedFile>,
+    /// The size in bytes of the worktree's database.
+    pub database_size: u64,
+    /// The model the files are embedded with.
+    pub embedding_model: EmbeddingModelInfo,
+    /// Whether the worktree is pinned to another model than the current one.
+    pub pinned: bool,
+    pub chunk_config: ChunkConfig,
+    /// The id of the worktree's latest scan.
+    pub scan_id: usize,
+    /// The id of the last scan of the worktree whose files were all indexed.
+    pub fully_indexed_scan_id: Option<usize>,
+    pub fully_indexed_at: Option<SystemTime>,
+    /// Whether a scan of every file started without finishing.
+    pub scan_in_progress: bool,
+}
+
+impl IndexDiagnostics {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{num::NonZeroUsize, path::Path};
+
+    #[test]
+    fn test_diagnostics_to_json() {
+        let model = EmbeddingModelInfo {
+            provider: "openai".into(),
+            model: "text-embedding-3-small".into(),
+            dimensions: 1536,
+        };
+        let diagnostics = IndexDiagnostics {
+            status: Status::RetryPending {
+                file_count: NonZeroUsize::new(1).unwrap(),
+            },
+            embedding_model: model.clone(),
+            shared_database_size: Some(4096),
+            worktrees: vec![WorktreeDiagnostics {
+                abs_path: PathBuf::from("/project"),
+                file_count: 2,
+                chunk_count: 5,
+                skipped_file_count: 0,
+                fallback_file_count: 0,
+                pending_file_count: 0,
+                failed_files: vec![FailedFile {
+                    path: Path::new("src/main.rs").into(),
+                    failure_count: 3,
+                    failed_at: SystemTime::UNIX_EPOCH,
+                }],
+                database_size: 8192,
+                embedding_model: model,
+                pinned: false,
+                chunk_config: ChunkConfig::new(1024, None, 0.),
+                scan_id: 7,
+                fully_indexed_scan_id: Some(6),
+                fully_indexed_at: None,
+                scan_in_progress: false,
+            }],
+        };
+
+        let json =
+            serde_json::from_str::<serde_json::Value>(&diagnostics.to_json().unwrap()).unwrap();
+        assert_eq!(json["status"]["RetryPending"]["file_count"], 1);
+        assert_eq!(json["embedding_model"]["model"], "text-embedding-3-small");
+        let worktree = &json["worktrees"][0];
+        assert_eq!(worktree["chunk_count"], 5);
+        assert_eq!(worktree["failed_files"][0]["path"], "src/main.rs");
+        assert_eq!(worktree["failed_files"][0]["failure_count"], 3);
+        assert_eq!(worktree["fully_indexed_scan_id"], 6);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{ops::AddAssign, time::Duration};
+
+use crate::EmbeddingProvider;
+
+/// What indexing files from scratch would take, estimated by reading and chunking them
+/// without sending anything to the embedding provider. Chunks whose embeddings were
+/// cached by another index still count, so the estimate is an upper bound.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndexEstimate {
+    /// The files that would be embedded.
+    pub file_count: u64,
+    /// The files that would be skipped, like binary or generated files.
+    pub skipped_file_count: u64,
+    pub chunk_count: u64,
+    pub token_count: u64,
+    /// The fewest requests the chunks fit in, given the provider's batch size and
+    /// token limit.
+    pub request_count: u64,
+    /// What embedding the chunks would cost, in US dollars, if the provider's prices
+    /// are known.
+    pub cost: Option<f64>,
+    /// How long the provider's rate limits would make embedding the chunks take at
+    /// least, if it has any.
+    pub duration: Option<Duration>,
+}
+
+impl IndexEstimate {
+    /// Counts a file that would be embedded, given the tokens of each of its chunks.
+    pub(crate) fn add_file(&mut self, chunk_token_counts: impl IntoIterator<Item = u64>) {
+        self.file_count += 1;
+        for token_count in chunk_token_counts {
+            self.chunk_count += 1;
+            self.token_count += token_count;
+        }
+    }
+
+    pub(crate) fn add_skipped_file(&mut self) {
+        self.skipped_file_count += 1;
+    }
+
+    /// Projects the requests, cost and time it would take the provider to embed the
+    /// chunks that were counted.
+    pub(crate) fn project(self, provider: &dyn EmbeddingProvider) -> Self {
+        let batch_size = provider.batch_size().max(1) as u64;
+        let mut request_count = self.chunk_count.div_ceil(batch_size);
+        if let Some(max_input_tokens) = provider.max_input_tokens() {
+            request_count = request_count.max(self.token_count.div_ceil(max_input_tokens.max(1)));
+        }
+
+        let rate_limits = provider.rate_limits();
+        let minutes = |count: u64, per_minute: Option<u32>| {
+            per_minute.map(|per_minute| count as f64 / per_minute.max(1) as f64)
+        };
+        let duration = match (
+            minutes(request_count, rate_limits.requests_per_minute),
+            minutes(self.token_count, rate_limits.tokens_per_minute),
+        ) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (minutes, None) | (None, minutes) => minutes,
+        };
+
+        Self {
+            request_count,
+            cost: provider
+                .price_per_million_tokens()
+                .map(|price| self.token_count as f64 / 1_000_000. * price),
+            duration: duration.map(|minutes| Duration::from_secs_f64(minutes * 60.)),
+            ..self
+        }
+    }
+
+    /// Describes the estimate, for users to decide whether to exclude files before
+    /// indexing them.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Indexing {} files would embed {} chunks, about {} tokens in {} requests",
+            self.file_count, self.chunk_count, self.token_count, self.request_count
+        );
+        if let Some(cost) = self.cost {
+            if cost > 0. && cost < 0.01 {
+                summary.push_str(", costing less than $0.01");
+            } else {
+                summary.push_str(&format!(", costing about ${cost:.2}"));
+            }
+        }
+        if let Some(duration) = self.duration {
+            let minutes = duration.as_secs().div_ceil(60);
+            if minutes <= 1 {
+                summary.push_str(", taking about a minute");
+            } else if minutes < 120 {
+                summary.push_str(&format!(", taking about {minutes} minutes"));
+            } else {
+                summary.push_str(&format!(", taking about {} hours", minutes.div_ceil(60)));
+            }
+        }
+        if self.skipped_file_count > 0 {
+            summary.push_str(&format!(
+                ". {} more files would be skipped",
+                self.skipped_file_count
+            ));
+        }
+        summary
+    }
+}
+
+impl AddAssign for IndexEstimate {
+    /// Adds up the files, chunks and tokens of two estimates, which have to be projected
+    /// again.
+    fn add_assign(&mut self, other: Self) {
+        self.file_count += other.file_count;
+        self.skipped_file_count += other.skipped_file_count;
+        self.chunk_count += other.chunk_count;
+        self.token_count += other.token_count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedding, EmbeddingModelInfo, RateLimits, TextToEmbed};
+    use anyhow::Result;
+    use futures::future::BoxFuture;
+
+    struct TestProvider {
+        max_input_tokens: Option<u64>,
+        rate_limits: RateLimits,
+        price_per_million_tokens: Option<f64>,
+    }
+
+    impl EmbeddingProvider for TestProvider {
+        fn embed<'a>(
+            &'a self,
+            _texts: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            unimplemented!()
+        }
+
+        fn batch_size(&self) -> usize {
+            100
+        }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            EmbeddingModelInfo {
+                provider: "test".into(),
+                model: "test".into(),
+                dimensions: 2,
+            }
+        }
+
+        fn rate_limits(&self) -> RateLimits {
+            self.rate_limits
+        }
+
+        fn max_input_tokens(&self) -> Option<u64> {
+            self.max_input_tokens
+        }
+
+        fn price_per_million_tokens(&self) -> Option<f64> {
+            self.price_per_million_tokens
+        }
+    }
+
+    #[test]
+    fn test_project_estimate() {
+        let mut estimate = IndexEstimate::default();
+        for _ in 0..500 {
+            estimate.add_file([1_000, 1_000]);
+        }
+        estimate.add_skipped_file();
+        let mut other_estimate = IndexEstimate::default();
+        other_estimate.add_file([500]);
+        estimate += other_estimate;
+        assert_eq!(estimate.file_count, 501);
+        assert_eq!(estimate.skipped_file_count, 1);
+        assert_eq!(estimate.chunk_count, 1_001);
+        assert_eq!(estimate.token_count, 1_000_500);
+
+        // Without limits on tokens, requests are only limited by the batch size.
+        let projected = estimate.project(&TestProvider {
+            max_input_tokens: None,
+            rate_limits: RateLimits::default(),
+            price_per_million_tokens: None,
+        });
+        assert_eq!(projected.request_count, 11);
+        assert_eq!(projected.cost, None);
+        assert_eq!(projected.duration, None);
+
+        let projected = estimate.project(&TestProvider {
+            max_input_tokens: Some(50_000),
+            rate_limits: RateLimits {
+                requests_per_minute: Some(10),
+                tokens_per_minute: Some(100_000),
+            },
+            price_per_million_tokens: Some(0.1),
+        });
+        assert_eq!(projected.request_count, 21);
+        assert!((projected.cost.unwrap() - 0.10005).abs() < 1e-9);
+        // Embedding takes as long as the slowest of the rate limits allows.
+        assert_eq!(projected.duration.unwrap().as_secs(), 600);
+    }
+
+    #[test]
+    fn test_estimate_summary() {
+        let mut estimate = IndexEstimate {
+            file_count: 120,
+            chunk_count: 480,
+            token_count: 200_000,
+            request_count: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            estimate.summary(),
+            "Indexing 120 files would embed 480 chunks, about 200000 tokens in 3 requests"
+        );
+
+        estimate.skipped_file_count = 4;
+        estimate.cost = Some(0.004);
+        estimate.duration = Some(Duration::from_secs(30));
+        assert_eq!(
+            estimate.summary(),
+            "Indexing 120 files would embed 480 chunks, about 200000 tokens in 3 requests, \
+            costing less than $0.01, taking about a minute. 4 more files would be skipped"
+        );
+
+        estimate.cost = Some(12.345);
+        estimate.duration = Some(Duration::from_secs(3 * 60 * 60));
+        assert_eq!(
+            estimate.summary(),
+            "Indexing 120 files would embed 480 chunks, about 200000 tokens in 3 requests, \
+            costing about $12.35, taking about 3 hours. 4 more files would be skipped"
+        );
+    }
+}
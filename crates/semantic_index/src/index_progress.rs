@@ -0,0 +1,94 @@
+use anyhow::Result;
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+const CHECKPOINT_KEY: &str = "checkpoint";
+
+/// How far indexing a worktree got, persisted so that it's known after Zed restarts.
+///
+/// Every stage of indexing keeps its own progress: embeddings are cached as soon as
+/// they're computed, and embedded files are committed in batches along with the
+/// modification time they were indexed at. So scanning the worktree again after an
+/// interruption only sends the files that weren't committed, and only embeds the
+/// chunks that weren't cached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct IndexCheckpoint {
+    /// Whether a scan of every file started without finishing, like when Zed quit
+    /// while indexing a large worktree for the first time.
+    pub scan_in_progress: bool,
+    /// The id of the last scan of the worktree whose files were all indexed. Scan ids
+    /// restart when the worktree is loaded, so this is only kept for a session.
+    pub fully_indexed_scan_id: Option<usize>,
+    /// When the files of the worktree were last all indexed.
+    pub fully_indexed_at: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct IndexProgress {
+    db: heed::Database<Str, SerdeBincode<IndexCheckpoint>>,
+}
+
+impl IndexProgress {
+    pub fn create(env: &heed::Env, txn: &mut heed::RwTxn, name: &str) -> Result<Self> {
+        let db = env.create_database(txn, Some(&format!("{name}:index_progress")))?;
+        Ok(Self { db })
+    }
+
+    /// Returns the checkpoint of the previous session, if any.
+    pub fn load(&self, txn: &heed::RoTxn) -> Result<IndexCheckpoint> {
+        let checkpoint = self.db.get(txn, CHECKPOINT_KEY)?.unwrap_or_default();
+        Ok(IndexCheckpoint {
+            fully_indexed_scan_id: None,
+            ..checkpoint
+        })
+    }
+
+    pub fn save(&self, txn: &mut heed::RwTxn, checkpoint: &IndexCheckpoint) -> Result<()> {
+        self.db.put(txn, CHECKPOINT_KEY, checkpoint)?;
+        Ok(())
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.db.clear(txn)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_progress() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = unsafe {
+            heed::EnvOpenOptions::new()
+                .max_dbs(1)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let mut txn = db_connection.write_txn().unwrap();
+        let progress = IndexProgress::create(&db_connection, &mut txn, "worktree").unwrap();
+        assert_eq!(progress.load(&txn).unwrap(), IndexCheckpoint::default());
+
+        let now = SystemTime::now();
+        let checkpoint = IndexCheckpoint {
+            scan_in_progress: true,
+            fully_indexed_scan_id: Some(3),
+            fully_indexed_at: Some(now),
+        };
+        progress.save(&mut txn, &checkpoint).unwrap();
+        assert_eq!(
+            progress.load(&txn).unwrap(),
+            IndexCheckpoint {
+                scan_in_progress: true,
+                fully_indexed_scan_id: None,
+                fully_indexed_at: Some(now),
+            }
+        );
+
+        progress.clear(&mut txn).unwrap();
+        assert_eq!(progress.load(&txn).unwrap(), IndexCheckpoint::default());
+    }
+}
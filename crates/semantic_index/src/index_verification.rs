@@ -0,0 +1,89 @@
+use project::WorktreeId;
+use std::{path::Path, sync::Arc};
+
+/// The problems found by comparing the index of a worktree with its files, which
+/// [`crate::ProjectIndex::repair`] fixes by removing the files from the index and
+/// indexing them again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexVerification {
+    pub worktree_id: WorktreeId,
+    /// Indexed files that were deleted, or that shouldn't be indexed anymore.
+    pub orphaned_paths: Vec<Arc<Path>>,
+    /// Files that should be indexed but aren't, and aren't being indexed either.
+    /// Files that failed to embed aren't missing, since they're retried later.
+    pub missing_paths: Vec<Arc<Path>>,
+    /// Indexed files whose text changed since they were indexed, even though their
+    /// modification time didn't.
+    pub mismatched_paths: Vec<Arc<Path>>,
+}
+
+impl IndexVerification {
+    pub fn new(worktree_id: WorktreeId) -> Self {
+        Self {
+            worktree_id,
+            orphaned_paths: Vec::new(),
+            missing_paths: Vec::new(),
+            mismatched_paths: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphaned_paths.is_empty()
+            && self.missing_paths.is_empty()
+            && self.mismatched_paths.is_empty()
+    }
+}
+
+/// Describes what repairing the index of a project's worktrees did.
+pub(crate) fn repair_summary(verifications: &[IndexVerification]) -> String {
+    let count = |paths: fn(&IndexVerification) -> &Vec<Arc<Path>>| {
+        verifications
+            .iter()
+            .map(|verification| paths(verification).len())
+            .sum::<usize>()
+    };
+    let orphaned_count = count(|verification| &verification.orphaned_paths);
+    let reindexed_count = count(|verification| &verification.missing_paths)
+        + count(|verification| &verification.mismatched_paths);
+    match (orphaned_count, reindexed_count) {
+        (0, 0) => "The semantic index has no problems".to_string(),
+        (orphaned_count, 0) => {
+            format!("Removed {orphaned_count} stale files from the semantic index")
+        }
+        (0, reindexed_count) => format!("Reindexing {reindexed_count} files"),
+        (orphaned_count, reindexed_count) => {
+            format!("Removed {orphaned_count} stale files, reindexing {reindexed_count} files")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_summary() {
+        let worktree_id = WorktreeId::from_usize(1);
+        let mut verification = IndexVerification::new(worktree_id);
+        assert!(verification.is_empty());
+        assert_eq!(
+            repair_summary(&[verification.clone()]),
+            "The semantic index has no problems"
+        );
+
+        verification
+            .orphaned_paths
+            .push(Path::new("deleted.rs").into());
+        let mut other_verification = IndexVerification::new(WorktreeId::from_usize(2));
+        other_verification
+            .missing_paths
+            .push(Path::new("new.rs").into());
+        other_verification
+            .mismatched_paths
+            .push(Path::new("changed.rs").into());
+        assert_eq!(
+            repair_summary(&[verification, other_verification]),
+            "Removed 1 stale files, reindexing 2 files"
+        );
+    }
+}
@@ -0,0 +1,1558 @@
+use crate::{
+    chunking::{chunk_text, text_digest, Chunk, ChunkConfig, StreamingChunker},
+    db_key_for_path, embedding,
+    embedding_cache::EmbeddingCache,
+    failover::PRIMARY_RETRY_INTERVAL,
+    index_progress::IndexCheckpoint,
+    indexing_profiler::{IndexingProfiler, IndexingStage},
+    keyword_index::{chunk_terms, file_terms, FileTerms},
+    quantization::StoredEmbedding,
+    retry_backlog::FailedFile,
+    search_filter::ResolvedSearchFilter,
+    skipped_files::{file_text, read_file_text, FileLimits},
+    usage::UsageLog,
+    ChunkingStrategy, ChunkingStrategyRegistry, Embedding, EmbeddingModelInfo, EmbeddingProvider,
+    EmbeddingUsage, IndexingEventKind, IndexingEvents, SemanticIndexSettings, SkipReason,
+    TextToEmbed, WorktreeIndex,
+};
+use anyhow::{anyhow, Context as _, Result};
+use collections::{BTreeMap, Bound, HashMap, HashSet};
+use futures::{future::Either, stream::StreamExt};
+use futures_batch::ChunksTimeoutStreamExt;
+use gpui::{AppContext, AsyncAppContext, Task, WeakModel};
+use language::Language;
+use parking_lot::Mutex;
+use project::{Entry, PathChange, ProjectEntryId, UpdatedEntriesSet};
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+use smol::channel;
+use std::{
+    cmp::Ordering,
+    future::Future,
+    io::Read,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime},
+};
+use util::ResultExt;
+use worktree::Snapshot;
+
+/// A request to update the index of a worktree.
+pub(crate) enum IndexRequest {
+    /// Index the entries that changed on disk.
+    UpdatedEntries(UpdatedEntriesSet),
+    /// Compare every entry with the index, because the files to index changed, or
+    /// because a remote worktree changed without saying which entries did.
+    Rescan,
+}
+
+/// How long to wait for more changes after a worktree changes, so that bursts of
+/// changes, like when switching branches or building, are indexed at once.
+const UPDATE_DEBOUNCE: Duration = Duration::from_millis(250);
+/// The longest to wait for a burst of changes to end before indexing them anyway.
+const MAX_UPDATE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// The changes of several index requests, coalesced by path.
+#[derive(Default)]
+struct PendingChanges {
+    rescan: bool,
+    changes: BTreeMap<Arc<Path>, (ProjectEntryId, PathChange)>,
+}
+
+impl PendingChanges {
+    fn push(&mut self, request: IndexRequest) {
+        match request {
+            IndexRequest::Rescan => {
+                // A rescan finds every change anyway.
+                self.rescan = true;
+                self.changes.clear();
+            }
+            IndexRequest::UpdatedEntries(_) if self.rescan => {}
+            IndexRequest::UpdatedEntries(updated_entries) => {
+                for (path, entry_id, change) in updated_entries.iter() {
+                    if *change == PathChange::Loaded {
+                        // Entries found by the initial scan of the worktree may have been
+                        // found after the index was first compared with the worktree, so
+                        // compare them again, which skips the files that are indexed.
+                        self.rescan = true;
+                        self.changes.clear();
+                        return;
+                    }
+                    // The latest change to a path wins, so removing a file cancels
+                    // indexing it if it was added or updated before.
+                    self.changes.insert(path.clone(), (*entry_id, *change));
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.rescan && self.changes.is_empty()
+    }
+
+    /// Returns the entries that changed, or `None` if every entry must be scanned.
+    fn into_updated_entries(self) -> Option<UpdatedEntriesSet> {
+        (!self.rescan).then(|| {
+            self.changes
+                .into_iter()
+                .map(|(path, (entry_id, change))| (path, entry_id, change))
+                .collect()
+        })
+    }
+}
+
+impl WorktreeIndex {
+    pub(crate) async fn index_entries(
+        this: WeakModel<Self>,
+        index_requests: channel::Receiver<IndexRequest>,
+        resumed: channel::Receiver<()>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        Self::wait_until_resumed(&this, &resumed, &cx).await?;
+        Self::index_all_entries(&this, &mut cx).await?;
+        this.update(&mut cx, |this, _| this.embedding_model_changed = false)?;
+        Self::schedule_retry(&this, &mut cx).await.log_err();
+
+        while let Ok(request) = index_requests.recv().await {
+            let mut pending_changes = PendingChanges::default();
+            pending_changes.push(request);
+            if pending_changes.is_empty() {
+                continue;
+            }
+            if Self::wait_until_resumed(&this, &resumed, &cx).await? {
+                // Coalesce the requests that were left in the channel while paused, by
+                // scanning for every change at once.
+                while index_requests.try_recv().is_ok() {}
+                pending_changes.push(IndexRequest::Rescan);
+            } else {
+                Self::debounce(&index_requests, &mut pending_changes, &cx).await;
+            }
+
+            if let Some(updated_entries) = pending_changes.into_updated_entries() {
+                let (scan_id, index) = this.update(&mut cx, |this, cx| {
+                    (
+                        this.worktree.read(cx).scan_id(),
+                        this.index_updated_entries(updated_entries, cx),
+                    )
+                })?;
+                // Indexing the changes of a fully indexed worktree keeps it fully indexed.
+                if index.await.log_err().is_some()
+                    && this.read_with(&cx, |this, _| {
+                        this.checkpoint.fully_indexed_scan_id.is_some()
+                    })?
+                {
+                    Self::save_checkpoint(&this, &mut cx, |checkpoint| {
+                        checkpoint.fully_indexed_scan_id = Some(scan_id);
+                        checkpoint.fully_indexed_at = Some(SystemTime::now());
+                    })
+                    .await
+                    .log_err();
+                }
+            } else {
+                Self::index_all_entries(&this, &mut cx).await?;
+            }
+            Self::schedule_retry(&this, &mut cx).await.log_err();
+        }
+
+        Ok(())
+    }
+
+    /// Counts the files in the retry backlog, and schedules a rescan to retry them once
+    /// the first of them is due. Files that failed aren't in the index, so the rescan
+    /// embeds them again along with any other changes.
+    ///
+    /// Files embedded by a fallback provider are embedded again by a rescan too, once
+    /// the embedding provider is due to be tried again.
+    async fn schedule_retry(this: &WeakModel<Self>, cx: &mut AsyncAppContext) -> Result<()> {
+        let (db_connection, retry_backlog, fallback_db) = this.read_with(cx, |this, _| {
+            (
+                this.db_connection.clone(),
+                this.retry_backlog,
+                this.fallback_db,
+            )
+        })?;
+        let (failed_files, has_fallback_files) = cx
+            .background_executor()
+            .spawn({
+                let db_connection = db_connection.clone();
+                async move {
+                    let txn = db_connection.read_txn()?;
+                    anyhow::Ok((retry_backlog.all(&txn)?, !fallback_db.is_empty(&txn)?))
+                }
+            })
+            .await?;
+
+        // Forget the files that were deleted or stopped being indexed since they failed,
+        // which rescans won't retry.
+        let (failed_files, stale_files): (Vec<_>, Vec<_>) = this.read_with(cx, |this, cx| {
+            let worktree = this.worktree.read(cx);
+            let index_filter = this.index_filter();
+            failed_files.into_iter().partition(|file| {
+                worktree.entry_for_path(&file.path).is_some()
+                    && index_filter.matches(&file.path, None)
+            })
+        })?;
+        if !stale_files.is_empty() {
+            cx.background_executor()
+                .spawn(async move {
+                    db_connection.write(|txn| {
+                        for file in &stale_files {
+                            retry_backlog.remove(txn, &db_key_for_path(&file.path))?;
+                        }
+                        Ok(())
+                    })
+                })
+                .await?;
+        }
+
+        this.update(cx, |this, cx| {
+            this.failed_file_count = failed_files.len();
+            this.events.send(IndexingEventKind::Idle);
+            let reembed_at = has_fallback_files.then(|| SystemTime::now() + PRIMARY_RETRY_INTERVAL);
+            this.retry_failed_files = failed_files
+                .iter()
+                .map(FailedFile::retry_at)
+                .chain(reembed_at)
+                .min()
+                .map(|retry_at| {
+                    let delay = retry_at
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default();
+                    if failed_files.is_empty() {
+                        log::info!(
+                            "re-embedding files embedded by a fallback provider in {delay:?}"
+                        );
+                    } else {
+                        log::info!(
+                            "retrying {} files that failed to embed in {delay:?}",
+                            failed_files.len()
+                        );
+                    }
+                    let index_requests_tx = this.index_requests_tx.clone();
+                    let timer = cx.background_executor().timer(delay);
+                    cx.background_executor().spawn(async move {
+                        timer.await;
+                        index_requests_tx.send(IndexRequest::Rescan).await.ok();
+                    })
+                });
+        })
+    }
+
+    /// Compares every file of the worktree with the index, checkpointing the scan so
+    /// that it's known whether it was interrupted.
+    async fn index_all_entries(this: &WeakModel<Self>, cx: &mut AsyncAppContext) -> Result<()> {
+        Self::save_checkpoint(this, cx, |checkpoint| checkpoint.scan_in_progress = true)
+            .await
+            .log_err();
+        let (scan_id, index) = this.update(cx, |this, cx| {
+            (
+                this.worktree.read(cx).scan_id(),
+                this.index_entries_changed_on_disk(cx),
+            )
+        })?;
+        if index.await.log_err().is_some() {
+            Self::save_checkpoint(this, cx, |checkpoint| {
+                checkpoint.scan_in_progress = false;
+                checkpoint.fully_indexed_scan_id = Some(scan_id);
+                checkpoint.fully_indexed_at = Some(SystemTime::now());
+            })
+            .await
+            .log_err();
+        }
+        Ok(())
+    }
+
+    async fn save_checkpoint(
+        this: &WeakModel<Self>,
+        cx: &mut AsyncAppContext,
+        update: impl FnOnce(&mut IndexCheckpoint),
+    ) -> Result<()> {
+        let (db_connection, index_progress, checkpoint) = this.update(cx, |this, _| {
+            update(&mut this.checkpoint);
+            (
+                this.db_connection.clone(),
+                this.index_progress,
+                this.checkpoint,
+            )
+        })?;
+        cx.background_executor()
+            .spawn(async move { db_connection.write(|txn| index_progress.save(txn, &checkpoint)) })
+            .await
+    }
+
+    /// Waits for a burst of changes to end, coalescing the requests sent meanwhile
+    /// with the pending changes.
+    async fn debounce(
+        index_requests: &channel::Receiver<IndexRequest>,
+        pending_changes: &mut PendingChanges,
+        cx: &AsyncAppContext,
+    ) {
+        let executor = cx.background_executor();
+        let deadline = executor.now() + MAX_UPDATE_DEBOUNCE;
+        loop {
+            let delay = UPDATE_DEBOUNCE.min(deadline.saturating_duration_since(executor.now()));
+            if delay.is_zero() {
+                break;
+            }
+            executor.timer(delay).await;
+
+            let mut received_requests = false;
+            while let Ok(request) = index_requests.try_recv() {
+                pending_changes.push(request);
+                received_requests = true;
+            }
+            if !received_requests {
+                break;
+            }
+        }
+    }
+
+    /// Waits until indexing isn't paused, returning whether it was.
+    async fn wait_until_resumed(
+        this: &WeakModel<Self>,
+        resumed: &channel::Receiver<()>,
+        cx: &AsyncAppContext,
+    ) -> Result<bool> {
+        let mut was_paused = false;
+        while this.read_with(cx, |this, _| this.paused || this.pinned_model.is_some())? {
+            was_paused = true;
+            resumed.recv().await?;
+        }
+        Ok(was_paused)
+    }
+
+    fn index_entries_changed_on_disk(&self, cx: &AppContext) -> impl Future<Output = Result<()>> {
+        let worktree = self.worktree.read(cx).snapshot();
+        let worktree_abs_path = worktree.abs_path().clone();
+        let scan = self.scan_entries(worktree, self.open_paths(cx), cx);
+        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
+        let embed = Self::embed_files(
+            self.embedding_provider.clone(),
+            self.embedding_cache.clone(),
+            self.usage_log.clone(),
+            chunk.files,
+            SemanticIndexSettings::get_global(cx).max_concurrent_embedding_requests,
+            self.events.clone(),
+            cx,
+        );
+        let persist = self.persist_embeddings(
+            scan.deleted_entry_ranges,
+            embed.files,
+            scan.moved_files,
+            embed.fallback_files,
+            embed.failed_files,
+            cx,
+        );
+        async move {
+            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
+            Ok(())
+        }
+    }
+
+    fn index_updated_entries(
+        &self,
+        updated_entries: UpdatedEntriesSet,
+        cx: &AppContext,
+    ) -> impl Future<Output = Result<()>> {
+        let worktree = self.worktree.read(cx).snapshot();
+        let worktree_abs_path = worktree.abs_path().clone();
+        let scan = self.scan_updated_entries(worktree, updated_entries.clone(), cx);
+        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
+        let embed = Self::embed_files(
+            self.embedding_provider.clone(),
+            self.embedding_cache.clone(),
+            self.usage_log.clone(),
+            chunk.files,
+            SemanticIndexSettings::get_global(cx).max_concurrent_embedding_requests,
+            self.events.clone(),
+            cx,
+        );
+        let persist = self.persist_embeddings(
+            scan.deleted_entry_ranges,
+            embed.files,
+            scan.moved_files,
+            embed.fallback_files,
+            embed.failed_files,
+            cx,
+        );
+        async move {
+            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
+            Ok(())
+        }
+    }
+
+    /// Returns the paths of the worktree's files that are open in the project.
+    fn open_paths(&self, cx: &AppContext) -> Vec<Arc<Path>> {
+        let Some(project) = self.project.upgrade() else {
+            return Vec::new();
+        };
+        let worktree_id = self.worktree.read(cx).id();
+        project
+            .read(cx)
+            .opened_buffers(cx)
+            .into_iter()
+            .filter_map(|buffer| {
+                let file = buffer.read(cx).file()?;
+                (file.worktree_id(cx) == worktree_id).then(|| file.path().clone())
+            })
+            .collect()
+    }
+
+    /// Compares every file of the worktree with the index, sending the files that
+    /// changed to be indexed. The files most likely to be searched, the open ones and
+    /// the most recently modified ones, are sent before the rest, so that they're
+    /// searchable early when indexing a large worktree.
+    fn scan_entries(
+        &self,
+        worktree: Snapshot,
+        open_paths: Vec<Arc<Path>>,
+        cx: &AppContext,
+    ) -> ScanEntries {
+        let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
+        let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
+        // Moved files are indexed like any other file, since scanning every entry
+        // doesn't tell where files were moved from.
+        let (_, moved_files_rx) = channel::bounded(1);
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
+        let fallback_db = self.fallback_db;
+        let active_model = self.embedding_provider.active_model();
+        let entries_being_indexed = self.entry_ids_being_indexed.clone();
+        let filter = self.index_filter();
+        let profiler = IndexingProfiler::from_settings(Self::settings(&self.worktree, cx));
+        let task = cx.background_executor().spawn(async move {
+            let timer = profiler.start(IndexingStage::Scan);
+            // Compare the files with the index before sending any of them, so that the
+            // read transaction isn't kept open while waiting for them to be indexed,
+            // which would keep the database from growing when it's full.
+            let (updated_entry_ids, deletion_ranges) = {
+                let txn = db_connection
+                    .read_txn()
+                    .context("failed to create read transaction")?;
+                let mut updated_entry_ids = Vec::new();
+                let mut deletion_ranges = Vec::new();
+
+                // Files embedded by a fallback provider are up to date until the model
+                // that embeds files changes, which re-embeds them once the embedding
+                // provider is back.
+                let mut fallback_mtimes = BTreeMap::new();
+                for fallback_entry in fallback_db.iter(&txn)? {
+                    let (key, FallbackEmbeddedFile { model, file }) = fallback_entry?;
+                    let saved_mtime = if model == active_model {
+                        file.mtime
+                    } else {
+                        None
+                    };
+                    fallback_mtimes.insert(key.to_string(), saved_mtime);
+                }
+
+                let mut prioritized_entry_ids = HashSet::default();
+                for entry in prioritized_entries(&worktree, &open_paths) {
+                    if !filter.matches(&entry.path, None) {
+                        continue;
+                    }
+                    let db_key = db_key_for_path(&entry.path);
+                    let saved_mtime = match db.get(&txn, &db_key)? {
+                        Some(embedded_file) => embedded_file.mtime,
+                        None => fallback_mtimes.get(&db_key).copied().flatten(),
+                    };
+                    if entry.mtime != saved_mtime {
+                        prioritized_entry_ids.insert(entry.id);
+                        updated_entry_ids.push(entry.id);
+                    }
+                }
+
+                let mut db_entries = db
+                    .iter(&txn)
+                    .context("failed to create iterator")?
+                    .peekable();
+
+                let mut deletion_range: Option<(Bound<&str>, Bound<&str>)> = None;
+                // Files that aren't indexed anymore get deleted like files that don't exist.
+                for entry in worktree
+                    .files(false, 0)
+                    .filter(|entry| filter.matches(&entry.path, None))
+                {
+                    let entry_db_key = db_key_for_path(&entry.path);
+
+                    let mut saved_mtime = None;
+                    while let Some(db_entry) = db_entries.peek() {
+                        match db_entry {
+                            Ok((db_path, db_embedded_file)) => {
+                                match (*db_path).cmp(&entry_db_key) {
+                                    Ordering::Less => {
+                                        if let Some(deletion_range) = deletion_range.as_mut() {
+                                            deletion_range.1 = Bound::Included(db_path);
+                                        } else {
+                                            deletion_range = Some((
+                                                Bound::Included(db_path),
+                                                Bound::Included(db_path),
+                                            ));
+                                        }
+
+                                        db_entries.next();
+                                    }
+                                    Ordering::Equal => {
+                                        if let Some(deletion_range) = deletion_range.take() {
+                                            deletion_ranges.push((
+                                                deletion_range.0.map(ToString::to_string),
+                                                deletion_range.1.map(ToString::to_string),
+                                            ));
+                                        }
+                                        saved_mtime = db_embedded_file.mtime;
+                                        db_entries.next();
+                                        break;
+                                    }
+                                    Ordering::Greater => {
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(_) => return Err(db_entries.next().unwrap().unwrap_err())?,
+                        }
+                    }
+
+                    // Deletion ranges span the keys between files in the index, so they
+                    // can't span files embedded by a fallback provider.
+                    if let Some(fallback_mtime) = fallback_mtimes.remove(&entry_db_key) {
+                        if let Some(deletion_range) = deletion_range.take() {
+                            deletion_ranges.push((
+                                deletion_range.0.map(ToString::to_string),
+                                deletion_range.1.map(ToString::to_string),
+                            ));
+                        }
+                        saved_mtime = saved_mtime.or(fallback_mtime);
+                    }
+
+                    if entry.mtime != saved_mtime && !prioritized_entry_ids.contains(&entry.id) {
+                        updated_entry_ids.push(entry.id);
+                    }
+                }
+
+                if let Some(db_entry) = db_entries.next() {
+                    let (db_path, _) = db_entry?;
+                    deletion_ranges.push((Bound::Included(db_path.to_string()), Bound::Unbounded));
+                }
+                for fallback_key in fallback_mtimes.into_keys() {
+                    deletion_ranges.push((
+                        Bound::Included(fallback_key.clone()),
+                        Bound::Included(fallback_key),
+                    ));
+                }
+                (updated_entry_ids, deletion_ranges)
+            };
+            timer.finish(format_args!(
+                "updated_files={} deleted_ranges={}",
+                updated_entry_ids.len(),
+                deletion_ranges.len()
+            ));
+
+            for deletion_range in deletion_ranges {
+                deleted_entry_ranges_tx.send(deletion_range).await?;
+            }
+            for entry_id in updated_entry_ids {
+                if let Some(entry) = worktree.entry_for_id(entry_id) {
+                    let handle = entries_being_indexed.insert(entry.id);
+                    updated_entries_tx.send((entry.clone(), handle)).await?;
+                }
+            }
+
+            Ok(())
+        });
+
+        ScanEntries {
+            updated_entries: updated_entries_rx,
+            deleted_entry_ranges: deleted_entry_ranges_rx,
+            moved_files: moved_files_rx,
+            task,
+        }
+    }
+
+    fn scan_updated_entries(
+        &self,
+        worktree: Snapshot,
+        updated_entries: UpdatedEntriesSet,
+        cx: &AppContext,
+    ) -> ScanEntries {
+        let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
+        let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
+        let (moved_files_tx, moved_files_rx) = channel::unbounded();
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
+        let full_precision_embeddings = self.full_precision_embeddings;
+        let file_loader = self.file_loader.clone();
+        let entries_being_indexed = self.entry_ids_being_indexed.clone();
+        let filter = self.index_filter();
+        let settings = Self::settings(&self.worktree, cx);
+        let file_limits = FileLimits::from_settings(settings);
+        let profiler = IndexingProfiler::from_settings(settings);
+        let task = cx.background_executor().spawn(async move {
+            let timer = profiler.start(IndexingStage::Scan);
+            // Files keep their entry id when they're moved, so a file that was removed
+            // from one path and added at another with the same id was likely moved. It
+            // keeps its embeddings if its text didn't change, instead of being embedded
+            // again, which matters when moving a directory of a large worktree.
+            let removed_paths = updated_entries
+                .iter()
+                .filter(|(_, _, change)| *change == PathChange::Removed)
+                .map(|(path, entry_id, _)| (*entry_id, path))
+                .collect::<HashMap<_, _>>();
+            let mut moved_entry_ids = HashSet::default();
+            for (path, entry_id, change) in updated_entries.iter() {
+                let Some(old_path) = removed_paths.get(entry_id) else {
+                    continue;
+                };
+                if !matches!(change, PathChange::Added | PathChange::AddedOrUpdated)
+                    || !filter.matches(path, None)
+                {
+                    continue;
+                }
+                let Some(entry) = worktree
+                    .entry_for_id(*entry_id)
+                    .filter(|entry| entry.is_file())
+                else {
+                    continue;
+                };
+
+                let moved_file = async {
+                    let old_file = {
+                        let txn = db_connection.read_txn()?;
+                        let old_key = db_key_for_path(old_path);
+                        match full_precision_embeddings.get(&txn, &old_key)? {
+                            Some(old_file) => Some(old_file),
+                            None => db.get(&txn, &old_key)?,
+                        }
+                    };
+                    // Files without chunks are cheap to index again.
+                    let Some(old_file) = old_file.filter(|file| !file.chunks.is_empty()) else {
+                        return Ok(None);
+                    };
+                    let bytes = file_loader
+                        .load(
+                            worktree.id(),
+                            worktree.abs_path(),
+                            &entry.path,
+                            file_limits.max_size(&entry.path).saturating_add(1),
+                        )
+                        .await?;
+                    let Ok(text) = file_text(&entry.path, bytes, file_limits) else {
+                        return Ok(None);
+                    };
+                    if text_digest(&text) != old_file.digest {
+                        return Ok(None);
+                    }
+
+                    let chunks = old_file
+                        .chunks
+                        .into_iter()
+                        .map(|mut chunk| {
+                            chunk.chunk = chunk.chunk.moved(old_path, &entry.path);
+                            chunk
+                        })
+                        .collect::<Vec<_>>();
+                    let terms = file_terms(
+                        entry.path.clone(),
+                        &text,
+                        &chunks
+                            .iter()
+                            .map(|chunk| chunk.chunk.clone())
+                            .collect::<Vec<_>>(),
+                    );
+                    let file = EmbeddedFile {
+                        path: entry.path.clone(),
+                        mtime: entry.mtime,
+                        digest: old_file.digest,
+                        chunks,
+                    };
+                    anyhow::Ok(Some((file, terms)))
+                }
+                .await
+                .log_err()
+                .flatten();
+
+                if let Some((file, terms)) = moved_file {
+                    log::debug!(
+                        "reusing the embeddings of {old_path:?} for {:?}",
+                        entry.path
+                    );
+                    moved_entry_ids.insert(entry.id);
+                    let handle = entries_being_indexed.insert(entry.id);
+                    moved_files_tx.send((file, terms, None, handle)).await?;
+                }
+            }
+            drop(moved_files_tx);
+            timer.finish(format_args!(
+                "updated_entries={} moved_files={}",
+                updated_entries.len(),
+                moved_entry_ids.len()
+            ));
+
+            for (path, entry_id, status) in updated_entries.iter() {
+                match status {
+                    project::PathChange::Added
+                    | project::PathChange::Updated
+                    | project::PathChange::AddedOrUpdated
+                        if filter.matches(path, None) =>
+                    {
+                        if let Some(entry) = worktree.entry_for_id(*entry_id) {
+                            if entry.is_file() && !moved_entry_ids.contains(&entry.id) {
+                                let handle = entries_being_indexed.insert(entry.id);
+                                updated_entries_tx.send((entry.clone(), handle)).await?;
+                            }
+                        }
+                    }
+                    project::PathChange::Added
+                    | project::PathChange::Updated
+                    | project::PathChange::AddedOrUpdated
+                    | project::PathChange::Removed => {
+                        // Files that aren't indexed anymore may have been indexed before.
+                        let db_path = db_key_for_path(path);
+                        deleted_entry_ranges_tx
+                            .send((Bound::Included(db_path.clone()), Bound::Included(db_path)))
+                            .await?;
+                    }
+                    project::PathChange::Loaded => {
+                        // Do nothing.
+                    }
+                }
+            }
+
+            Ok(())
+        });
+
+        ScanEntries {
+            updated_entries: updated_entries_rx,
+            deleted_entry_ranges: deleted_entry_ranges_rx,
+            moved_files: moved_files_rx,
+            task,
+        }
+    }
+
+    fn chunk_files(
+        &self,
+        worktree_abs_path: Arc<Path>,
+        entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
+        cx: &AppContext,
+    ) -> ChunkFiles {
+        let language_registry = self.language_registry.clone();
+        let file_loader = self.file_loader.clone();
+        let worktree_id = self.worktree.read(cx).id();
+        let embedding_provider = self.embedding_provider.clone();
+        let chunk_config = self.chunk_config;
+        let chunking_strategies = ChunkingStrategyRegistry::global(cx);
+        let settings = Self::settings(&self.worktree, cx);
+        let file_limits = FileLimits::from_settings(settings);
+        let max_indexing_threads = settings.max_indexing_threads;
+        let profiler = IndexingProfiler::from_settings(settings);
+        let events = self.events.clone();
+        let chunking = AtomicBool::new(false);
+        let (chunked_files_tx, chunked_files_rx) = channel::bounded(2048);
+        let task = cx.spawn(|cx| async move {
+            cx.background_executor()
+                .scoped(|cx| {
+                    let thread_count = max_indexing_threads
+                        .map_or(cx.num_cpus(), |max| max.clamp(1, cx.num_cpus()));
+                    for _ in 0..thread_count {
+                        cx.spawn(async {
+                            while let Ok((entry, handle)) = entries.recv().await {
+                                if !chunking.swap(true, SeqCst) {
+                                    events.send(IndexingEventKind::Chunking);
+                                }
+                                let timer = profiler.start(IndexingStage::Chunk);
+                                let entry_abs_path = worktree_abs_path.join(&entry.path);
+                                let language = language_registry
+                                    .language_for_file_path(&entry.path)
+                                    .await
+                                    .ok();
+                                let strategy = language.as_ref().and_then(|language| {
+                                    chunking_strategies.strategy(&language.name())
+                                });
+                                let failed = |path: Arc<Path>, error: anyhow::Error| {
+                                    log::error!("{error:?}");
+                                    events.send(IndexingEventKind::Failed {
+                                        path,
+                                        error: format!("{error:#}"),
+                                    });
+                                };
+                                let reader = match file_loader
+                                    .open(worktree_id, &worktree_abs_path, &entry.path)
+                                    .await
+                                    .with_context(|| {
+                                        format!("failed to open path {entry_abs_path:?}")
+                                    }) {
+                                    Ok(reader) => reader,
+                                    Err(error) => {
+                                        failed(entry.path, error);
+                                        continue;
+                                    }
+                                };
+                                let chunked_file = match ChunkedFile::read(
+                                    reader,
+                                    entry.path.clone(),
+                                    entry.mtime,
+                                    handle,
+                                    language.as_ref(),
+                                    chunk_config,
+                                    strategy.as_deref(),
+                                    &|text| embedding_provider.count_tokens(text),
+                                    file_limits,
+                                )
+                                .with_context(|| format!("failed to read path {entry_abs_path:?}"))
+                                {
+                                    Ok(chunked_file) => chunked_file,
+                                    Err(error) => {
+                                        failed(entry.path, error);
+                                        continue;
+                                    }
+                                };
+                                if let Some(skip_reason) = chunked_file.skip_reason {
+                                    log::debug!("skipping {entry_abs_path:?}: {skip_reason}");
+                                }
+                                timer.finish(format_args!(
+                                    "path={:?} chunks={}",
+                                    chunked_file.path,
+                                    chunked_file.chunks.len()
+                                ));
+
+                                if chunked_files_tx.send(chunked_file).await.is_err() {
+                                    return;
+                                }
+                            }
+                        });
+                    }
+                })
+                .await;
+            Ok(())
+        });
+
+        ChunkFiles {
+            files: chunked_files_rx,
+            task,
+        }
+    }
+
+    /// Embeds the chunks of files, sending up to `max_concurrent_requests` batches to
+    /// the embedding provider at once.
+    pub(crate) fn embed_files(
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        usage_log: UsageLog,
+        chunked_files: channel::Receiver<ChunkedFile>,
+        max_concurrent_requests: usize,
+        events: IndexingEvents,
+        cx: &AppContext,
+    ) -> EmbedFiles {
+        let embedding_provider = embedding_provider.clone();
+        let profiler = IndexingProfiler::from_settings(SemanticIndexSettings::get_global(cx));
+        let (embedded_files_tx, embedded_files_rx) = channel::bounded(512);
+        let (fallback_files_tx, fallback_files_rx) = channel::bounded(512);
+        let (failed_files_tx, failed_files_rx) = channel::unbounded();
+        let task = cx.background_executor().spawn(async move {
+            let mut chunked_file_batches =
+                chunked_files.chunks_timeout(512, Duration::from_secs(2));
+            let mut done_count = 0;
+            let mut total_count = 0;
+            while let Some(chunked_files) = chunked_file_batches.next().await {
+                // View the batch of files as a vec of chunks
+                // Flatten out to a vec of chunks that we can subdivide into batch sized pieces
+                // Once those are done, reassemble them back into the files in which they belong
+                // If any embeddings fail for a file, the entire file is discarded
+
+                let chunks: Vec<TextToEmbed> = chunked_files
+                    .iter()
+                    .flat_map(|file| &file.chunks)
+                    .map(|(chunk, chunk_text)| TextToEmbed {
+                        text: chunk_text,
+                        digest: chunk.digest,
+                    })
+                    .collect::<Vec<_>>();
+
+                // Only embed the chunks whose content wasn't embedded before, in any worktree.
+                let digests = chunks.iter().map(|chunk| chunk.digest).collect::<Vec<_>>();
+                let mut embeddings: Vec<Option<Embedding>> = embedding_cache
+                    .get(&digests)
+                    .log_err()
+                    .unwrap_or_else(|| vec![None; chunks.len()]);
+
+                // Then pull what's missing from the team's shared store, if any.
+                let shared = embedding_cache.shared();
+                if let Some(shared) = &shared {
+                    let (missing_ixs, missing_digests): (Vec<usize>, Vec<[u8; 32]>) = digests
+                        .iter()
+                        .enumerate()
+                        .filter(|(ix, _)| embeddings[*ix].is_none())
+                        .map(|(ix, digest)| (ix, *digest))
+                        .unzip();
+                    if !missing_digests.is_empty() {
+                        if let Some(shared_embeddings) =
+                            shared.store.get(&missing_digests).await.log_err()
+                        {
+                            let mut pulled = Vec::new();
+                            for ((ix, digest), embedding) in missing_ixs
+                                .into_iter()
+                                .zip(missing_digests)
+                                .zip(shared_embeddings)
+                            {
+                                if let Some(embedding) = embedding {
+                                    pulled.push((digest, embedding.clone()));
+                                    embeddings[ix] = Some(embedding);
+                                }
+                            }
+                            embedding_cache.insert(&pulled).log_err();
+                        }
+                    }
+                }
+
+                let (uncached_ixs, uncached_chunks): (Vec<usize>, Vec<TextToEmbed>) = chunks
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(ix, _)| embeddings[*ix].is_none())
+                    .unzip();
+                total_count += embeddings.len();
+                done_count += embeddings.len() - uncached_chunks.len();
+                events.send(IndexingEventKind::Embedding {
+                    done: done_count,
+                    total: total_count,
+                });
+
+                // The fallback model that embedded each chunk, if the embedding
+                // provider failed over.
+                let mut fallback_models: Vec<Option<Arc<EmbeddingModelInfo>>> =
+                    vec![None; embeddings.len()];
+                // Why each chunk that failed to embed did.
+                let mut errors: Vec<Option<String>> = vec![None; embeddings.len()];
+                let model = embedding_provider.model_info();
+                let provider = embedding_provider.as_ref();
+                let usage_log = &usage_log;
+                let mut uncached_ixs = uncached_ixs.into_iter();
+                let batch_requests = embedding::batches(provider, &uncached_chunks)
+                    .into_iter()
+                    .map(|embedding_batch| {
+                        let batch_ixs = uncached_ixs
+                            .by_ref()
+                            .take(embedding_batch.len())
+                            .collect::<Vec<_>>();
+                        async move {
+                            let timer = profiler.start(IndexingStage::Embed);
+                            let (batch_model, batch_results) =
+                                provider.embed_with_model(embedding_batch).await;
+                            timer.finish(format_args!("chunks={}", embedding_batch.len()));
+                            usage_log
+                                .record(EmbeddingUsage::request(
+                                    embedding_batch.iter().map(|chunk| chunk.text),
+                                ))
+                                .log_err();
+                            (embedding_batch, batch_ixs, batch_model, batch_results)
+                        }
+                    });
+                // Batches are sent concurrently, but their results are handled in order.
+                let mut batch_responses =
+                    futures::stream::iter(batch_requests).buffered(max_concurrent_requests.max(1));
+                while let Some((embedding_batch, batch_ixs, batch_model, batch_results)) =
+                    batch_responses.next().await
+                {
+                    let fallback_model = (batch_model != model).then(|| Arc::new(batch_model));
+
+                    // Keep the chunks that were embedded, even if others in the batch
+                    // failed, so that retrying their files only embeds the failed ones.
+                    let mut new_embeddings = Vec::new();
+                    let mut failure_count = 0;
+                    let mut first_error = None;
+                    for ((ix, chunk), result) in batch_ixs
+                        .into_iter()
+                        .zip(embedding_batch)
+                        .zip(batch_results)
+                    {
+                        let batch_model = fallback_model.as_deref().unwrap_or(&model);
+                        match result.and_then(|embedding| batch_model.validate(embedding)) {
+                            Ok(embedding) => {
+                                // Embeddings from a fallback model aren't cached, since
+                                // they can't be reused for the index.
+                                if fallback_model.is_none() {
+                                    new_embeddings.push((chunk.digest, embedding.clone()));
+                                }
+                                embeddings[ix] = Some(embedding);
+                                fallback_models[ix] = fallback_model.clone();
+                            }
+                            Err(error) => {
+                                failure_count += 1;
+                                errors[ix] = Some(format!("{error:#}"));
+                                first_error.get_or_insert(error);
+                            }
+                        }
+                    }
+                    if let Some(error) = first_error {
+                        log::error!(
+                            "failed to embed {failure_count} of {} chunks: {error:?}",
+                            embedding_batch.len()
+                        );
+                    }
+                    done_count += embedding_batch.len();
+                    events.send(IndexingEventKind::Embedding {
+                        done: done_count,
+                        total: total_count,
+                    });
+                    if !new_embeddings.is_empty() {
+                        embedding_cache.insert(&new_embeddings).log_err();
+                        if let Some(shared) = shared.as_ref().filter(|shared| shared.push) {
+                            shared.store.put(&new_embeddings).await.log_err();
+                        }
+                    }
+                }
+
+                let mut embeddings = embeddings.into_iter().zip(fallback_models).zip(errors);
+                for chunked_file in chunked_files {
+                    let terms = chunked_file.terms;
+                    let mut embedded_file = EmbeddedFile {
+                        path: chunked_file.path,
+                        mtime: chunked_file.mtime,
+                        digest: chunked_file.digest,
+                        chunks: Vec::new(),
+                    };
+
+                    let mut embedded_all_chunks = true;
+                    let mut file_models = Vec::<Option<Arc<EmbeddingModelInfo>>>::new();
+                    let mut file_error = None;
+                    for ((chunk, _), ((embedding, fallback_model), error)) in
+                        chunked_file.chunks.into_iter().zip(embeddings.by_ref())
+                    {
+                        if let Some(error) = error {
+                            file_error.get_or_insert(error);
+                        }
+                        if let Some(embedding) = embedding {
+                            embedded_file.chunks.push(EmbeddedChunk {
+                                chunk,
+                                embedding: embedding.into(),
+                            });
+                            if !file_models.contains(&fallback_model) {
+                                file_models.push(fallback_model);
+                            }
+                        } else {
+                            embedded_all_chunks = false;
+                        }
+                    }
+
+                    // A file whose chunks were embedded by different models can't be
+                    // searched, so it's retried like a file that failed to embed.
+                    if embedded_all_chunks && file_models.len() <= 1 {
+                        match file_models.pop().flatten() {
+                            None => {
+                                embedded_files_tx
+                                    .send((
+                                        embedded_file,
+                                        terms,
+                                        chunked_file.skip_reason,
+                                        chunked_file.handle,
+                                    ))
+                                    .await?;
+                            }
+                            Some(model) => {
+                                let file = FallbackEmbeddedFile {
+                                    model: model.as_ref().clone(),
+                                    file: embedded_file,
+                                };
+                                fallback_files_tx
+                                    .send((
+                                        file,
+                                        terms,
+                                        chunked_file.skip_reason,
+                                        chunked_file.handle,
+                                    ))
+                                    .await?;
+                            }
+                        }
+                    } else {
+                        events.send(IndexingEventKind::Failed {
+                            path: embedded_file.path.clone(),
+                            error: file_error.unwrap_or_else(|| {
+                                "chunks were embedded by different models".into()
+                            }),
+                        });
+                        failed_files_tx
+                            .send((embedded_file.path, chunked_file.handle))
+                            .await?;
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        EmbedFiles {
+            files: embedded_files_rx,
+            fallback_files: fallback_files_rx,
+            failed_files: failed_files_rx,
+            task,
+        }
+    }
+
+    pub(crate) fn persist_embeddings(
+        &self,
+        mut deleted_entry_ranges: channel::Receiver<(Bound<String>, Bound<String>)>,
+        embedded_files: channel::Receiver<(
+            EmbeddedFile,
+            FileTerms,
+            Option<SkipReason>,
+            IndexingEntryHandle,
+        )>,
+        moved_files: channel::Receiver<(
+            EmbeddedFile,
+            FileTerms,
+            Option<SkipReason>,
+            IndexingEntryHandle,
+        )>,
+        fallback_files: channel::Receiver<(
+            FallbackEmbeddedFile,
+            FileTerms,
+            Option<SkipReason>,
+            IndexingEntryHandle,
+        )>,
+        failed_files: channel::Receiver<(Arc<Path>, IndexingEntryHandle)>,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
+        let fallback_db = self.fallback_db;
+        let keyword_index = self.keyword_index;
+        let vector_index = self.vector_index;
+        let full_precision_embeddings = self.full_precision_embeddings;
+        let skipped_files = self.skipped_files;
+        let retry_backlog = self.retry_backlog;
+        let quantization = SemanticIndexSettings::get_global(cx).quantization;
+        let profiler = IndexingProfiler::from_settings(Self::settings(&self.worktree, cx));
+        cx.background_executor().spawn(async move {
+            while let Some(deletion_range) = deleted_entry_ranges.next().await {
+                let start = deletion_range.0.as_ref().map(|start| start.as_str());
+                let end = deletion_range.1.as_ref().map(|end| end.as_str());
+                log::debug!("deleting embeddings in range {:?}", &(start, end));
+                db_connection.write(|txn| {
+                    db.delete_range(txn, &(start, end))?;
+                    fallback_db.delete_range(txn, &(start, end))?;
+                    keyword_index.delete_range(txn, &(start, end))?;
+                    let mut vector_meta = vector_index.load_meta(txn)?;
+                    vector_index.delete_range(txn, &mut vector_meta, &(start, end))?;
+                    vector_index.save_meta(txn, &vector_meta)?;
+                    full_precision_embeddings.delete_range(txn, &(start, end))?;
+                    skipped_files.delete_range(txn, &(start, end))?;
+                    retry_backlog.delete_range(txn, &(start, end))?;
+                    Ok(())
+                })?;
+            }
+
+            // Files embedded by a fallback provider are persisted as they come along with
+            // the others, since embedding waits for them to be received.
+            let mut embedded_files = futures::stream::select(
+                futures::stream::select(embedded_files, moved_files).map(Either::Left),
+                fallback_files.map(Either::Right),
+            )
+            .chunks_timeout(4096, Duration::from_secs(2));
+            while let Some(embedded_files) = embedded_files.next().await {
+                let timer = profiler.start(IndexingStage::Persist);
+                db_connection.write(|txn| {
+                    let mut vector_meta = vector_index.load_meta(txn)?;
+                    for embedded_file in &embedded_files {
+                        match embedded_file {
+                            Either::Left((file, terms, skip_reason, _)) => {
+                                log::debug!("saving embedding for file {:?}", file.path);
+                                let key = db_key_for_path(&file.path);
+                                skipped_files.insert(txn, &key, &file.path, *skip_reason)?;
+                                retry_backlog.remove(txn, &key)?;
+                                fallback_db.delete(txn, &key)?;
+                                full_precision_embeddings.insert(txn, &key, file, quantization)?;
+                                let file = file.quantize(quantization);
+                                db.put(txn, &key, &file)?;
+                                keyword_index.insert(txn, &key, terms)?;
+                                vector_index.insert(txn, &mut vector_meta, &key, &file)?;
+                            }
+                            // The file is taken out of the index, where it's outdated,
+                            // until the embedding provider is back to embed it again.
+                            Either::Right((file, terms, skip_reason, _)) => {
+                                log::debug!(
+                                    "saving embedding for file {:?} from fallback model {:?}",
+                                    file.file.path,
+                                    file.model
+                                );
+                                let key = db_key_for_path(&file.file.path);
+                                skipped_files.insert(txn, &key, &file.file.path, *skip_reason)?;
+                                retry_backlog.remove(txn, &key)?;
+                                db.delete(txn, &key)?;
+                                vector_index.delete(txn, &mut vector_meta, &key)?;
+                                full_precision_embeddings.delete_range(
+                                    txn,
+                                    &(Bound::Included(key.as_str()), Bound::Included(key.as_str())),
+                                )?;
+                                fallback_db.put(txn, &key, file)?;
+                                keyword_index.insert(txn, &key, terms)?;
+                            }
+                        }
+                    }
+                    if vector_meta.needs_training() {
+                        vector_index.train(txn, &mut vector_meta, db)?;
+                    }
+                    vector_index.save_meta(txn, &vector_meta)
+                })?;
+
+                timer.finish(format_args!("files={}", embedded_files.len()));
+                drop(embedded_files);
+            }
+
+            // Embedding is done once it stops sending embedded files, so every file that
+            // failed to embed is in the channel by now.
+            let failed_files = failed_files.collect::<Vec<_>>().await;
+            if !failed_files.is_empty() {
+                let failed_at = SystemTime::now();
+                db_connection.write(|txn| {
+                    for (path, _) in &failed_files {
+                        let key = db_key_for_path(path);
+                        let failure_count =
+                            retry_backlog.insert_failure(txn, &key, path, failed_at)?;
+                        log::debug!("failed to embed {path:?} {failure_count} times in a row");
+                    }
+                    Ok(())
+                })?;
+                drop(failed_files);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+struct ScanEntries {
+    updated_entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
+    deleted_entry_ranges: channel::Receiver<(Bound<String>, Bound<String>)>,
+    /// Files that were moved without changing, along with the embeddings they had
+    /// at their previous path.
+    moved_files: channel::Receiver<(
+        EmbeddedFile,
+        FileTerms,
+        Option<SkipReason>,
+        IndexingEntryHandle,
+    )>,
+    task: Task<Result<()>>,
+}
+
+struct ChunkFiles {
+    files: channel::Receiver<ChunkedFile>,
+    task: Task<Result<()>>,
+}
+
+/// Files larger than this are read and chunked a block at a time, by lines, instead of
+/// being read whole and chunked by their syntax.
+const STREAMING_CHUNKING_LEN: u64 = 4 * 1024 * 1024;
+
+pub(crate) struct ChunkedFile {
+    pub path: Arc<Path>,
+    pub mtime: Option<SystemTime>,
+    pub handle: IndexingEntryHandle,
+    /// The digest of the whole text of the file.
+    pub digest: [u8; 32],
+    /// The chunks of the file, along with the text they're embedded with. The text of
+    /// the file isn't kept, so that large files aren't held in memory whole.
+    pub chunks: Vec<(Chunk, String)>,
+    pub terms: FileTerms,
+    pub skip_reason: Option<SkipReason>,
+}
+
+impl ChunkedFile {
+    pub fn new(
+        path: Arc<Path>,
+        mtime: Option<SystemTime>,
+        handle: IndexingEntryHandle,
+        text: &str,
+        chunks: Vec<Chunk>,
+    ) -> Self {
+        Self {
+            digest: text_digest(text),
+            terms: file_terms(path.clone(), text, &chunks),
+            chunks: chunks
+                .into_iter()
+                .map(|chunk| {
+                    let text_to_embed = chunk.text_to_embed(text);
+                    (chunk, text_to_embed)
+                })
+                .collect(),
+            path,
+            mtime,
+            handle,
+            skip_reason: None,
+        }
+    }
+
+    /// Returns a skipped file, which is indexed as empty so that it isn't read again
+    /// until it changes.
+    fn skipped(
+        path: Arc<Path>,
+        mtime: Option<SystemTime>,
+        handle: IndexingEntryHandle,
+        skip_reason: SkipReason,
+    ) -> Self {
+        Self {
+            skip_reason: Some(skip_reason),
+            ..Self::new(path, mtime, handle, "", Vec::new())
+        }
+    }
+
+    /// Reads and chunks a file, unless it should be skipped. Files larger than
+    /// `STREAMING_CHUNKING_LEN` are chunked as they're read.
+    fn read(
+        reader: impl Read,
+        path: Arc<Path>,
+        mtime: Option<SystemTime>,
+        handle: IndexingEntryHandle,
+        language: Option<&Arc<Language>>,
+        chunk_config: ChunkConfig,
+        strategy: Option<&ChunkingStrategy>,
+        count_tokens: &dyn Fn(&str) -> u64,
+        file_limits: FileLimits,
+    ) -> std::io::Result<Self> {
+        // Never read much more than the largest file to index.
+        let mut reader = reader.take(file_limits.max_size(&path).saturating_add(1));
+        let mut bytes = Vec::new();
+        (&mut reader)
+            .take(STREAMING_CHUNKING_LEN + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 <= STREAMING_CHUNKING_LEN {
+            return Ok(match file_text(&path, bytes, file_limits) {
+                Ok(text) => {
+                    let chunks =
+                        chunk_text(&text, language, &path, chunk_config, strategy, count_tokens);
+                    Self::new(path, mtime, handle, &text, chunks)
+                }
+                Err(skip_reason) => Self::skipped(path, mtime, handle, skip_reason),
+            });
+        }
+
+        let mut chunker =
+            StreamingChunker::new(&path, language, chunk_config, strategy, count_tokens);
+        let reader = std::io::Cursor::new(bytes).chain(reader);
+        if let Err(skip_reason) =
+            read_file_text(reader, &path, file_limits, |text| chunker.push(text))?
+        {
+            return Ok(Self::skipped(path, mtime, handle, skip_reason));
+        }
+        let (digest, chunks) = chunker.finish();
+        let terms = FileTerms {
+            path: path.clone(),
+            chunks: chunks
+                .iter()
+                .map(|(chunk, text_to_embed)| {
+                    chunk_terms(chunk, &text_to_embed[chunk.header.len()..])
+                })
+                .collect(),
+        };
+        Ok(Self {
+            path,
+            mtime,
+            handle,
+            digest,
+            chunks,
+            terms,
+            skip_reason: None,
+        })
+    }
+}
+
+pub(crate) struct EmbedFiles {
+    pub files: channel::Receiver<(
+        EmbeddedFile,
+        FileTerms,
+        Option<SkipReason>,
+        IndexingEntryHandle,
+    )>,
+    /// The files embedded by a fallback provider, which are kept apart from the index.
+    pub fallback_files: channel::Receiver<(
+        FallbackEmbeddedFile,
+        FileTerms,
+        Option<SkipReason>,
+        IndexingEntryHandle,
+    )>,
+    /// The files that had a chunk fail to embed, which are left out of the index.
+    pub failed_files: channel::Receiver<(Arc<Path>, IndexingEntryHandle)>,
+    pub task: Task<Result<()>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct EmbeddedFile {
+    pub path: Arc<Path>,
+    pub mtime: Option<SystemTime>,
+    /// The digest of the file's text, to recognize the file when it's moved.
+    pub digest: [u8; 32],
+    pub chunks: Vec<EmbeddedChunk>,
+}
+
+/// A file embedded by a fallback provider, which is kept out of the index since its
+/// embeddings can't be compared with those of the index's model.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FallbackEmbeddedFile {
+    pub model: EmbeddingModelInfo,
+    pub file: EmbeddedFile,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct EmbeddedChunk {
+    pub chunk: Chunk,
+    pub embedding: StoredEmbedding,
+}
+
+/// The set of entries that are currently being indexed.
+pub(crate) struct IndexingEntrySet {
+    entry_ids: Mutex<HashSet<ProjectEntryId>>,
+    events: IndexingEvents,
+}
+
+/// When dropped, removes the entry from the set of entries that are being indexed.
+#[derive(Clone)]
+pub(crate) struct IndexingEntryHandle {
+    entry_id: ProjectEntryId,
+    set: Weak<IndexingEntrySet>,
+}
+
+impl IndexingEntrySet {
+    pub fn new(events: IndexingEvents) -> Self {
+        Self {
+            entry_ids: Default::default(),
+            events,
+        }
+    }
+
+    pub fn insert(self: &Arc<Self>, entry_id: ProjectEntryId) -> IndexingEntryHandle {
+        let remaining_count = {
+            let mut entry_ids = self.entry_ids.lock();
+            entry_ids.insert(entry_id);
+            entry_ids.len()
+        };
+        self.events
+            .send(IndexingEventKind::Scanning { remaining_count });
+        IndexingEntryHandle {
+            entry_id,
+            set: Arc::downgrade(self),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entry_ids.lock().len()
+    }
+
+    pub fn contains(&self, entry_id: ProjectEntryId) -> bool {
+        self.entry_ids.lock().contains(&entry_id)
+    }
+}
+
+impl Drop for IndexingEntryHandle {
+    fn drop(&mut self) {
+        if let Some(set) = self.set.upgrade() {
+            let remaining_count = {
+                let mut entry_ids = set.entry_ids.lock();
+                entry_ids.remove(&self.entry_id);
+                entry_ids.len()
+            };
+            set.events
+                .send(IndexingEventKind::Scanning { remaining_count });
+        }
+    }
+}
+
+/// How many of the most recently modified files of a worktree to index first.
+const RECENTLY_MODIFIED_FILE_COUNT: usize = 256;
+
+/// Returns the files of a worktree to index before the others, in order: the open
+/// files, then the most recently modified ones.
+fn prioritized_entries<'a>(worktree: &'a Snapshot, open_paths: &[Arc<Path>]) -> Vec<&'a Entry> {
+    let mut recent_entries = worktree
+        .files(false, 0)
+        .filter(|entry| entry.mtime.is_some())
+        .collect::<Vec<_>>();
+    if recent_entries.len() > RECENTLY_MODIFIED_FILE_COUNT {
+        recent_entries
+            .select_nth_unstable_by(RECENTLY_MODIFIED_FILE_COUNT, |a, b| b.mtime.cmp(&a.mtime));
+        recent_entries.truncate(RECENTLY_MODIFIED_FILE_COUNT);
+    }
+    recent_entries.sort_by(|a, b| b.mtime.cmp(&a.mtime));
+
+    let mut entries = open_paths
+        .iter()
+        .filter_map(|path| worktree.entry_for_path(path))
+        .filter(|entry| entry.is_file())
+        .collect::<Vec<_>>();
+    for entry in recent_entries {
+        if !entries.iter().any(|open_entry| open_entry.id == entry.id) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// Returns the entry of an indexed file, unless it was deleted or shouldn't be
+/// indexed anymore.
+pub(crate) fn indexed_entry<'a>(
+    worktree: &'a Snapshot,
+    filter: &ResolvedSearchFilter,
+    path: &Path,
+) -> Option<&'a Entry> {
+    worktree
+        .entry_for_path(path)
+        .filter(|entry| entry.is_file() && filter.matches(&entry.path, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesce_pending_changes() {
+        let update = |changes: &[(&str, usize, PathChange)]| {
+            IndexRequest::UpdatedEntries(
+                changes
+                    .iter()
+                    .map(|(path, entry_id, change)| {
+                        (
+                            Arc::from(Path::new(path)),
+                            ProjectEntryId::from_proto(*entry_id as u64),
+                            *change,
+                        )
+                    })
+                    .collect(),
+            )
+        };
+
+        let mut pending_changes = PendingChanges::default();
+        pending_changes.push(update(&[
+            ("a.rs", 1, PathChange::Added),
+            ("b.rs", 2, PathChange::Updated),
+        ]));
+        pending_changes.push(update(&[
+            ("a.rs", 1, PathChange::Removed),
+            ("b.rs", 2, PathChange::Updated),
+            ("c.rs", 3, PathChange::AddedOrUpdated),
+        ]));
+        let updated_entries = pending_changes.into_updated_entries().unwrap();
+        assert_eq!(
+            updated_entries
+                .iter()
+                .map(|(path, entry_id, change)| (
+                    path.to_string_lossy().into_owned(),
+                    entry_id.to_proto(),
+                    *change
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a.rs".into(), 1, PathChange::Removed),
+                ("b.rs".into(), 2, PathChange::Updated),
+                ("c.rs".into(), 3, PathChange::AddedOrUpdated),
+            ]
+        );
+
+        let mut pending_changes = PendingChanges::default();
+        pending_changes.push(update(&[("a.rs", 1, PathChange::Added)]));
+        pending_changes.push(IndexRequest::Rescan);
+        pending_changes.push(update(&[("b.rs", 2, PathChange::Added)]));
+        assert!(pending_changes.into_updated_entries().is_none());
+
+        let mut pending_changes = PendingChanges::default();
+        pending_changes.push(update(&[("a.rs", 1, PathChange::Loaded)]));
+        assert!(pending_changes.into_updated_entries().is_none());
+    }
+}
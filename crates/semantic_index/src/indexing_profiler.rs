@@ -0,0 +1,76 @@
+use std::{fmt, time::Instant};
+
+use crate::SemanticIndexSettings;
+
+/// The log target of the timings of indexing, for filtering them in or out, like with
+/// `RUST_LOG=semantic_index::profile=debug`.
+pub(crate) const PROFILE_LOG_TARGET: &str = "semantic_index::profile";
+
+/// A stage of indexing a worktree's files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum IndexingStage {
+    Scan,
+    Chunk,
+    Embed,
+    Persist,
+}
+
+impl fmt::Display for IndexingStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexingStage::Scan => write!(f, "scan"),
+            IndexingStage::Chunk => write!(f, "chunk"),
+            IndexingStage::Embed => write!(f, "embed"),
+            IndexingStage::Persist => write!(f, "persist"),
+        }
+    }
+}
+
+/// Logs how long each stage of indexing takes. Timings are logged at the info level
+/// with the `profile_indexing` setting, so that they reach the log file, and at the
+/// debug level otherwise.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct IndexingProfiler {
+    level: log::Level,
+}
+
+impl IndexingProfiler {
+    pub fn from_settings(settings: &SemanticIndexSettings) -> Self {
+        Self {
+            level: if settings.profile_indexing {
+                log::Level::Info
+            } else {
+                log::Level::Debug
+            },
+        }
+    }
+
+    /// Starts timing a stage, which is logged with the given fields once it's done.
+    pub fn start(&self, stage: IndexingStage) -> StageTimer {
+        StageTimer {
+            level: self.level,
+            stage,
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Times a stage of indexing started with [`IndexingProfiler::start`].
+pub(crate) struct StageTimer {
+    level: log::Level,
+    stage: IndexingStage,
+    start: Instant,
+}
+
+impl StageTimer {
+    /// Logs how long the stage took, along with what it did.
+    pub fn finish(self, fields: fmt::Arguments) {
+        log::log!(
+            target: PROFILE_LOG_TARGET,
+            self.level,
+            "{} {fields} elapsed={:?}",
+            self.stage,
+            self.start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use anyhow::Result;
+use collections::{HashMap, HashSet};
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    ops::{Bound, Range},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{chunking::Chunk, encryption::IndexCipher};
+
+/// Controls how quickly repeated occurrences of a term stop raising a chunk's score.
+const BM25_K1: f32 = 1.2;
+/// Controls how strongly scores are normalized by chunk length.
+const BM25_B: f32 = 0.75;
+const STATS_KEY: &str = "stats";
+
+/// A full-text index of the chunks of a worktree's files, ranking them with BM25.
+///
+/// Postings are keyed by term followed by the file they occur in, so that all the
+/// postings for a term can be read with a single prefix scan. Terms are stored as a
+/// keyed digest when the index is encrypted.
+#[derive(Clone, Copy)]
+pub(crate) struct KeywordIndex {
+    files: heed::Database<Str, SerdeBincode<KeywordFile>>,
+    postings: heed::Database<Str, SerdeBincode<Vec<Posting>>>,
+    stats: heed::Database<Str, SerdeBincode<KeywordStats>>,
+    cipher: Option<IndexCipher>,
+}
+
+/// The terms of the chunks of a file, as they're passed to [`KeywordIndex::insert`].
+pub(crate) struct FileTerms {
+    pub path: Arc<Path>,
+    pub chunks: Vec<ChunkTerms>,
+}
+
+pub(crate) struct ChunkTerms {
+    pub range: Range<usize>,
+    pub cell: Option<u32>,
+    pub length: u32,
+    pub frequencies: HashMap<String, u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeywordFile {
+    path: Arc<Path>,
+    chunks: Vec<KeywordChunk>,
+    terms: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeywordChunk {
+    range: Range<usize>,
+    cell: Option<u32>,
+    length: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Posting {
+    chunk_ix: u32,
+    frequency: u32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct KeywordStats {
+    chunk_count: u64,
+    total_length: u64,
+}
+
+impl KeywordIndex {
+    pub fn create(
+        env: &heed::Env,
+        txn: &mut heed::RwTxn,
+        name: &str,
+        cipher: Option<IndexCipher>,
+    ) -> Result<Self> {
+        Ok(Self {
+            files: env.create_database(txn, Some(&format!("{name}:keyword_files")))?,
+            postings: env.create_database(txn, Some(&format!("{name}:keyword_postings")))?,
+            stats: env.create_database(txn, Some(&format!("{name}:keyword_stats")))?,
+            cipher,
+        })
+    }
+
+    /// Indexes the chunks of a file, replacing whatever was indexed for it before.
+    pub fn insert(&self, txn: &mut heed::RwTxn, db_key: &str, file: &FileTerms) -> Result<()> {
+        self.delete(txn, db_key)?;
+
+        let mut stats = self.stats.get(txn, STATS_KEY)?.unwrap_or_default();
+        let mut postings = HashMap::<String, Vec<Posting>>::default();
+        let mut chunks = Vec::with_capacity(file.chunks.len());
+        for (chunk_ix, chunk) in file.chunks.iter().enumerate() {
+            stats.chunk_count += 1;
+            stats.total_length += chunk.length as u64;
+            for (term, &frequency) in &chunk.frequencies {
+                postings
+                    .entry(self.stored_term(term))
+                    .or_default()
+                    .push(Posting {
+                        chunk_ix: chunk_ix as u32,
+                        frequency,
+                    });
+            }
+            chunks.push(KeywordChunk {
+                range: chunk.range.clone(),
+                cell: chunk.cell,
+                length: chunk.length,
+            });
+        }
+
+        for (term, postings) in &postings {
+            self.postings
+                .put(txn, &posting_key(term, db_key), postings)?;
+        }
+        self.files.put(
+            txn,
+            db_key,
+            &KeywordFile {
+                path: file.path.clone(),
+                chunks,
+                terms: postings.into_keys().collect(),
+            },
+        )?;
+        self.stats.put(txn, STATS_KEY, &stats)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, txn: &mut heed::RwTxn, db_key: &str) -> Result<()> {
+        let Some(file) = self.files.get(txn, db_key)? else {
+            return Ok(());
+        };
+        for term in &file.terms {
+            self.postings.delete(txn, &posting_key(term, db_key))?;
+        }
+        self.files.delete(txn, db_key)?;
+
+        let mut stats = self.stats.get(txn, STATS_KEY)?.unwrap_or_default();
+        for chunk in &file.chunks {
+            stats.chunk_count = stats.chunk_count.saturating_sub(1);
+            stats.total_length = stats.total_length.saturating_sub(chunk.length as u64);
+        }
+        self.stats.put(txn, STATS_KEY, &stats)?;
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<()> {
+        let db_keys = self
+            .files
+            .range(txn, range)?
+            .map(|entry| Ok(entry?.0.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        for db_key in db_keys {
+            self.delete(txn, &db_key)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.files.clear(txn)?;
+        self.postings.clear(txn)?;
+        self.stats.clear(txn)?;
+        Ok(())
+    }
+
+    /// Returns the best matching chunks for the query, as the path of their file, their
+    /// range, their notebook cell if any, and their BM25 score.
+    pub fn search(
+        &self,
+        txn: &heed::RoTxn,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<(Arc<Path>, Range<usize>, Option<u32>, f32)>> {
+        let stats = self.stats.get(txn, STATS_KEY)?.unwrap_or_default();
+        if stats.chunk_count == 0 {
+            return Ok(Vec::new());
+        }
+        let chunk_count = stats.chunk_count as f32;
+        let average_length = stats.total_length as f32 / chunk_count;
+
+        let mut files = HashMap::<String, Option<KeywordFile>>::default();
+        let mut scores = HashMap::<(String, u32), f32>::default();
+        for term in tokenize(query).into_iter().collect::<HashSet<_>>() {
+            let prefix = posting_key(&self.stored_term(&term), "");
+            let mut term_postings = Vec::new();
+            for entry in self.postings.prefix_iter(txn, &prefix)? {
+                let (key, postings) = entry?;
+                term_postings.push((key[prefix.len()..].to_string(), postings));
+            }
+
+            let document_frequency = term_postings
+                .iter()
+                .map(|(_, postings)| postings.len())
+                .sum::<usize>() as f32;
+            let idf =
+                ((chunk_count - document_frequency + 0.5) / (document_frequency + 0.5) + 1.).ln();
+            for (db_key, postings) in term_postings {
+                if !files.contains_key(&db_key) {
+                    files.insert(db_key.clone(), self.files.get(txn, &db_key)?);
+                }
+                let Some(file) = &files[&db_key] else {
+                    continue;
+                };
+                for posting in postings {
+                    let Some(chunk) = file.chunks.get(posting.chunk_ix as usize) else {
+                        continue;
+                    };
+                    *scores
+                        .entry((db_key.clone(), posting.chunk_ix))
+                        .or_default() += bm25_term_score(
+                        idf,
+                        posting.frequency as f32,
+                        chunk.length as f32,
+                        average_length,
+                    );
+                }
+            }
+        }
+
+        let mut results = scores.into_iter().collect::<Vec<_>>();
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        results.truncate(limit);
+        Ok(results
+            .into_iter()
+            .filter_map(|((db_key, chunk_ix), score)| {
+                let file = files.get(&db_key)?.as_ref()?;
+                let chunk = file.chunks.get(chunk_ix as usize)?;
+                Some((file.path.clone(), chunk.range.clone(), chunk.cell, score))
+            })
+            .collect())
+    }
+
+    fn stored_term(&self, term: &str) -> String {
+        match &self.cipher {
+            Some(cipher) => cipher.digest(term),
+            None => term.to_string(),
+        }
+    }
+}
+
+fn posting_key(term: &str, db_key: &str) -> String {
+    format!("{term}\0{db_key}")
+}
+
+fn bm25_term_score(idf: f32, frequency: f32, length: f32, average_length: f32) -> f32 {
+    idf * frequency * (BM25_K1 + 1.)
+        / (frequency + BM25_K1 * (1. - BM25_B + BM25_B * length / average_length))
+}
+
+/// Collects the terms of each chunk of a file. What a chunk repeats of the previous one
+/// is left out, so that every occurrence of a term is counted and found once.
+pub(crate) fn file_terms(path: Arc<Path>, text: &str, chunks: &[Chunk]) -> FileTerms {
+    FileTerms {
+        path,
+        chunks: chunks
+            .iter()
+            .map(|chunk| chunk_terms(chunk, &chunk.text(text)))
+            .collect(),
+    }
+}
+
+/// Collects the terms of a chunk from its text, like `file_terms`.
+pub(crate) fn chunk_terms(chunk: &Chunk, chunk_text: &str) -> ChunkTerms {
+    // Chunks of notebook cells never overlap.
+    let terms = tokenize(&chunk_text[chunk.overlap..]);
+    let mut frequencies = HashMap::default();
+    for term in &terms {
+        *frequencies.entry(term.clone()).or_default() += 1;
+    }
+    ChunkTerms {
+        range: chunk.range.start + chunk.overlap..chunk.range.end,
+        cell: chunk.cell,
+        length: terms.len() as u32,
+        frequencies,
+    }
+}
+
+/// Splits text into lowercase terms. Identifiers are indexed whole, and also split
+/// into the words they're made of, at underscores and case changes, so that both
+/// `EmbeddingProvider` and `provider` find `embedding_provider`.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for identifier in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        let words = identifier_words(identifier);
+        if words.len() > 1 {
+            terms.push(identifier.trim_matches('_').to_lowercase());
+        }
+        terms.extend(words.into_iter().map(str::to_lowercase));
+    }
+    terms
+}
+
+/// Splits an identifier into words, at underscores and at case changes such as the
+/// ones in `camelCase` and `HTTPServer`.
+fn identifier_words(identifier: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in identifier.split('_').filter(|part| !part.is_empty()) {
+        let chars = part.char_indices().collect::<Vec<_>>();
+        let mut word_start = 0;
+        for (ix, &(offset, c)) in chars.iter().enumerate().skip(1) {
+            let previous = chars[ix - 1].1;
+            let next = chars.get(ix + 1).map(|&(_, c)| c);
+            let is_boundary = (c.is_uppercase() && previous.is_lowercase())
+                || (c.is_uppercase()
+                    && previous.is_uppercase()
+                    && next.map_or(false, char::is_lowercase))
+                || (c.is_alphabetic() != previous.is_alphabetic());
+            if is_boundary {
+                words.push(&part[word_start..offset]);
+                word_start = offset;
+            }
+        }
+        words.push(&part[word_start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("fn embed_files(provider: Arc<dyn EmbeddingProvider>)"),
+            [
+                "fn",
+                "embed_files",
+                "embed",
+                "files",
+                "provider",
+                "arc",
+                "dyn",
+                "embeddingprovider",
+                "embedding",
+                "provider",
+            ]
+        );
+        assert_eq!(
+            tokenize("HTTPServer2"),
+            ["httpserver2", "http", "server", "2"]
+        );
+        assert_eq!(tokenize("__init__"), ["init"]);
+    }
+
+    #[test]
+    fn test_keyword_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let env = unsafe {
+            heed::EnvOpenOptions::new()
+                .map_size(1024 * 1024 * 1024)
+                .max_dbs(8)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let cipher = IndexCipher::new(&IndexCipher::generate_key().unwrap());
+        for (name, cipher) in [("worktree", None), ("encrypted", Some(cipher))] {
+            let mut txn = env.write_txn().unwrap();
+            let index = KeywordIndex::create(&env, &mut txn, name, cipher).unwrap();
+
+            let chunk = |range: Range<usize>| Chunk {
+                range,
+                header: String::new(),
+                digest: Default::default(),
+                overlap: 0,
+                cell: None,
+            };
+            let text = "fn parse_config() {}\nfn render() {}\n";
+            let terms = file_terms(
+                Path::new("src/config.rs").into(),
+                text,
+                &[chunk(0..21), chunk(21..36)],
+            );
+            index.insert(&mut txn, "src\0config.rs", &terms).unwrap();
+            let text = "# Config\nHow to configure rendering.\n";
+            let terms = file_terms(Path::new("README.md").into(), text, &[chunk(0..37)]);
+            index.insert(&mut txn, "README.md", &terms).unwrap();
+            txn.commit().unwrap();
+
+            let search = |query| {
+                let txn = env.read_txn().unwrap();
+                index
+                    .search(&txn, query, 10)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(path, range, _, _)| (path.to_string_lossy().into_owned(), range))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                search("parseConfig"),
+                [
+                    ("src/config.rs".to_string(), 0..21),
+                    ("README.md".to_string(), 0..37)
+                ]
+            );
+            assert_eq!(search("render"), [("src/config.rs".to_string(), 21..36)]);
+
+            // Encrypted indices don't store the terms themselves.
+            let txn = env.read_txn().unwrap();
+            let stores_terms = index
+                .postings
+                .iter(&txn)
+                .unwrap()
+                .any(|entry| entry.unwrap().0.starts_with("render\0"));
+            assert_eq!(stores_terms, cipher.is_none());
+            drop(txn);
+
+            let mut txn = env.write_txn().unwrap();
+            index
+                .delete_range(&mut txn, &(Bound::Included("src"), Bound::Unbounded))
+                .unwrap();
+            txn.commit().unwrap();
+            assert_eq!(search("config"), [("README.md".to_string(), 0..37)]);
+            assert!(search("render").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bm25_term_score() {
+        let rare = bm25_term_score(2.0, 1., 10., 10.);
+        let common = bm25_term_score(0.5, 1., 10., 10.);
+        assert!(rare > common);
+
+        let repeated = bm25_term_score(2.0, 4., 10., 10.);
+        assert!(repeated > rare && repeated < 4. * rare);
+
+        let long = bm25_term_score(2.0, 1., 40., 10.);
+        assert!(long < rare);
+    }
+}
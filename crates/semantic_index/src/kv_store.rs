@@ -0,0 +1,177 @@
+use anyhow::Result;
+use heed::types::Bytes;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlez::connection::Connection;
+use std::{path::Path, sync::Arc};
+
+use crate::db_connection::DbConnection;
+
+/// The database to store the parts of the index that were moved to [`KvStore`] in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// LMDB, which maps the database into memory.
+    #[default]
+    Lmdb,
+    /// SQLite, for platforms and file systems where memory maps are a problem, like
+    /// network file systems.
+    Sqlite,
+}
+
+/// A persistent map from byte keys to byte values, for the parts of the index that
+/// don't depend on a particular database.
+pub(crate) trait KvStore: Send + Sync {
+    /// Returns the value of every key, if any, as of a single point in time.
+    fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>>;
+
+    /// Inserts every entry atomically, replacing the values of existing keys.
+    fn put_many(&self, entries: &[(&[u8], &[u8])]) -> Result<()>;
+}
+
+/// Opens the stores of a backend by name.
+#[derive(Clone)]
+pub(crate) enum KvStorage {
+    Lmdb(DbConnection),
+    Sqlite(Arc<Mutex<Connection>>),
+}
+
+impl KvStorage {
+    /// Opens the storage of the given backend. SQLite databases are kept in a file
+    /// next to the LMDB database.
+    pub fn new(backend: StorageBackend, db_connection: &DbConnection) -> Result<Self> {
+        match backend {
+            StorageBackend::Lmdb => Ok(Self::Lmdb(db_connection.clone())),
+            StorageBackend::Sqlite => Self::open_sqlite(&db_connection.path().join("index.sqlite")),
+        }
+    }
+
+    fn open_sqlite(path: &Path) -> Result<Self> {
+        Self::migrate_sqlite(Connection::open_file(&path.to_string_lossy()))
+    }
+
+    #[cfg(test)]
+    fn open_test_sqlite(name: &str) -> Result<Self> {
+        Self::migrate_sqlite(Connection::open_memory(Some(name)))
+    }
+
+    fn migrate_sqlite(connection: Connection) -> Result<Self> {
+        connection.migrate(
+            "semantic_index",
+            &["CREATE TABLE entries (
+                store TEXT NOT NULL,
+                key BLOB NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (store, key)
+            ) STRICT, WITHOUT ROWID;"],
+        )?;
+        Ok(Self::Sqlite(Arc::new(Mutex::new(connection))))
+    }
+
+    /// Opens the store of the given name, creating it if needed.
+    pub fn open(&self, name: &str) -> Result<Arc<dyn KvStore>> {
+        match self {
+            Self::Lmdb(db_connection) => {
+                let db = db_connection.write(|txn| {
+                    Ok(db_connection.create_database::<Bytes, Bytes>(txn, Some(name))?)
+                })?;
+                Ok(Arc::new(LmdbStore {
+                    db_connection: db_connection.clone(),
+                    db,
+                }))
+            }
+            Self::Sqlite(connection) => Ok(Arc::new(SqliteStore {
+                connection: connection.clone(),
+                name: name.to_string(),
+            })),
+        }
+    }
+}
+
+struct LmdbStore {
+    db_connection: DbConnection,
+    db: heed::Database<Bytes, Bytes>,
+}
+
+impl KvStore for LmdbStore {
+    fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let txn = self.db_connection.read_txn()?;
+        keys.iter()
+            .map(|key| Ok(self.db.get(&txn, key)?.map(<[u8]>::to_vec)))
+            .collect()
+    }
+
+    fn put_many(&self, entries: &[(&[u8], &[u8])]) -> Result<()> {
+        self.db_connection.write(|txn| {
+            for (key, value) in entries {
+                self.db.put(txn, key, value)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A store in a table of a SQLite database shared by every store, since store names
+/// aren't valid table names.
+struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    name: String,
+}
+
+impl KvStore for SqliteStore {
+    fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let connection = self.connection.lock();
+        connection.with_savepoint("get_many", || {
+            let mut select = connection.select_row_bound::<(&str, &[u8]), Vec<u8>>(
+                "SELECT value FROM entries WHERE store = ? AND key = ?",
+            )?;
+            keys.iter().map(|key| select((&self.name, key))).collect()
+        })
+    }
+
+    fn put_many(&self, entries: &[(&[u8], &[u8])]) -> Result<()> {
+        let connection = self.connection.lock();
+        connection.with_savepoint("put_many", || {
+            let mut insert = connection.exec_bound::<(&str, &[u8], &[u8])>(
+                "INSERT OR REPLACE INTO entries (store, key, value) VALUES (?, ?, ?)",
+            )?;
+            for (key, value) in entries {
+                insert((&self.name, key, value))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_connection::{TEST_MAP_SIZE, TEST_MAX_DBS};
+
+    #[test]
+    fn test_kv_stores() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        for storage in [
+            KvStorage::new(StorageBackend::Lmdb, &db_connection).unwrap(),
+            KvStorage::open_test_sqlite("test_kv_stores").unwrap(),
+        ] {
+            let store = storage.open("store").unwrap();
+            let other_store = storage.open("other store").unwrap();
+            store.put_many(&[(b"a", b"1"), (b"b", b"2")]).unwrap();
+            other_store.put_many(&[(b"a", b"3")]).unwrap();
+            store.put_many(&[(b"b", b"4")]).unwrap();
+
+            assert_eq!(
+                store.get_many(&[b"a", b"b", b"c"]).unwrap(),
+                [Some(b"1".to_vec()), Some(b"4".to_vec()), None]
+            );
+            assert_eq!(
+                other_store.get_many(&[b"a", b"b"]).unwrap(),
+                [Some(b"3".to_vec()), None]
+            );
+        }
+    }
+}
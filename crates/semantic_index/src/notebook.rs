@@ -0,0 +1,120 @@
+use std::{ops::Range, path::Path};
+
+use crate::structured_data::JsonScanner;
+
+/// A cell of a Jupyter notebook.
+pub(crate) struct NotebookCell {
+    /// The type of the cell, like "code" or "markdown".
+    pub kind: String,
+    /// The ranges of the JSON strings the source of the cell is made of, in the text of
+    /// the notebook. Sources are usually split into one string per line.
+    pub lines: Vec<Range<usize>>,
+}
+
+pub(crate) fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("ipynb"))
+}
+
+/// Finds the cells of a notebook in its JSON, without decoding their sources, so that
+/// they can be mapped back to the text of the notebook. Returns `None` if the text
+/// isn't the JSON of a notebook.
+pub(crate) fn notebook_cells(text: &str) -> Option<Vec<NotebookCell>> {
+    let mut scanner = JsonScanner::new(text);
+    let mut cells = None;
+    scanner.object(|scanner, key| {
+        if key != "cells" {
+            return scanner.skip_value();
+        }
+        let mut notebook_cells = Vec::new();
+        scanner.array(|scanner| {
+            let mut kind = None;
+            let mut lines = Vec::new();
+            scanner.object(|scanner, key| match key {
+                "cell_type" => {
+                    kind = serde_json::from_str(&text[scanner.string()?]).ok();
+                    Some(())
+                }
+                "source" if scanner.peek()? == b'[' => scanner.array(|scanner| {
+                    lines.push(scanner.string()?);
+                    Some(())
+                }),
+                "source" => {
+                    lines.push(scanner.string()?);
+                    Some(())
+                }
+                _ => scanner.skip_value(),
+            })?;
+            notebook_cells.push(NotebookCell { kind: kind?, lines });
+            Some(())
+        })?;
+        cells = Some(notebook_cells);
+        Some(())
+    })?;
+    cells
+}
+
+/// Decodes the source of a cell from the JSON strings in a range of the notebook,
+/// which are separated by commas. Returns the JSON as is if it can't be decoded.
+pub(crate) fn decode_source(json: &str) -> String {
+    serde_json::from_str::<Vec<String>>(&format!("[{json}]"))
+        .map(|lines| lines.concat())
+        .unwrap_or_else(|_| json.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notebook_cells() {
+        let text = r##"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": ["# Loading \"data\"\n", "With pandas."]
+  },
+  {
+   "cell_type": "code",
+   "execution_count": 1,
+   "metadata": {"tags": [], "collapsed": false},
+   "outputs": [{"output_type": "stream", "text": ["[1, 2]\n"]}],
+   "source": "import pandas as pd"
+  },
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": []}
+ ],
+ "metadata": {"kernelspec": {"language": "python"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+        let cells = notebook_cells(text).unwrap();
+        assert_eq!(
+            cells
+                .iter()
+                .map(|cell| cell.kind.as_str())
+                .collect::<Vec<_>>(),
+            ["markdown", "code", "code"]
+        );
+        assert_eq!(
+            cells[0]
+                .lines
+                .iter()
+                .map(|range| &text[range.clone()])
+                .collect::<Vec<_>>(),
+            [r##""# Loading \"data\"\n""##, r#""With pandas.""#]
+        );
+        assert_eq!(
+            decode_source(&text[cells[0].lines[0].start..cells[0].lines[1].end]),
+            "# Loading \"data\"\nWith pandas."
+        );
+        assert_eq!(
+            decode_source(&text[cells[1].lines[0].clone()]),
+            "import pandas as pd"
+        );
+        assert!(cells[2].lines.is_empty());
+
+        assert!(notebook_cells("{\"cells\": [{\"source\": []}]}").is_none());
+        assert!(notebook_cells("not json").is_none());
+    }
+}
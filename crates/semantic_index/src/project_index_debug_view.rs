@@ -1,20 +1,35 @@
-use crate::ProjectIndex;
+use crate::{
+    chunking::{chunk_digest, text_digest},
+    indexing::EmbeddedChunk,
+    notebook::decode_source,
+    ExportIndexDiagnostics, ProjectIndex, SearchFilter, SkipReason, Status,
+};
+use collections::HashMap;
+use editor::Editor;
 use gpui::{
-    canvas, div, list, uniform_list, AnyElement, AppContext, CursorStyle, EventEmitter,
-    FocusHandle, FocusableView, IntoElement, ListOffset, ListState, Model, MouseMoveEvent, Render,
-    UniformListScrollHandle, View,
+    canvas, div, list, uniform_list, AnyElement, AppContext, ClipboardItem, CursorStyle, EntityId,
+    EventEmitter, FocusHandle, FocusableView, IntoElement, ListOffset, ListState, Model,
+    MouseMoveEvent, Render, UniformListScrollHandle, View,
 };
 use project::WorktreeId;
 use settings::Settings;
-use std::{path::Path, sync::Arc};
+use std::{ops::Range, path::Path, sync::Arc};
 use theme::ThemeSettings;
-use ui::prelude::*;
+use ui::{prelude::*, Tooltip};
+use util::ResultExt;
 use workspace::item::Item;
 
+/// How many results a search from the debug view shows.
+const SEARCH_RESULT_LIMIT: usize = 20;
+/// How many chunks are ranked by keywords to find the lexical scores of the results.
+const KEYWORD_CANDIDATE_LIMIT: usize = 200;
+
 pub struct ProjectIndexDebugView {
     index: Model<ProjectIndex>,
     rows: Vec<Row>,
     selected_path: Option<PathState>,
+    search: Option<SearchState>,
+    query_editor: View<Editor>,
     hovered_row_ix: Option<usize>,
     focus_handle: FocusHandle,
     list_scroll_handle: UniformListScrollHandle,
@@ -23,24 +38,66 @@ pub struct ProjectIndexDebugView {
 
 struct PathState {
     path: Arc<Path>,
-    chunks: Vec<SharedString>,
+    /// Whether the file changed on disk since it was indexed.
+    stale: bool,
+    chunks: Vec<ChunkDetails>,
+    list_state: ListState,
+}
+
+/// A chunk of the selected file, as it's stored in the index.
+struct ChunkDetails {
+    range: Range<usize>,
+    digest: SharedString,
+    dimensions: usize,
+    /// Whether the text of the chunk on disk differs from the text it was embedded
+    /// with, ignoring formatting.
+    stale: bool,
+    embedded_text: SharedString,
+}
+
+struct SearchState {
+    query: SharedString,
+    results: Vec<ResultBreakdown>,
     list_state: ListState,
 }
 
+/// What went into the score of a search result, to tell why it ranked where it did.
+struct ResultBreakdown {
+    path: Arc<Path>,
+    range: Range<usize>,
+    score: f32,
+    /// The factor the score was scaled by for the recency and location of the file.
+    weight: f32,
+    /// The keyword score of the result's best chunk, unless none of its chunks were
+    /// among the best keyword matches of the query.
+    lexical_score: Option<f32>,
+    text: SharedString,
+    /// The texts embedded for the chunks of the result, including their headers.
+    embedded_texts: Vec<SharedString>,
+}
+
 enum Row {
     Worktree(Arc<Path>),
     Entry(WorktreeId, Arc<Path>),
+    Skipped(Arc<Path>, SkipReason),
 }
 
 impl ProjectIndexDebugView {
     pub fn new(index: Model<ProjectIndex>, cx: &mut ViewContext<Self>) -> Self {
+        let query_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Search the index…", cx);
+            editor
+        });
         let mut this = Self {
             rows: Vec::new(),
             list_scroll_handle: UniformListScrollHandle::new(),
             selected_path: None,
+            search: None,
+            query_editor,
             hovered_row_ix: None,
             focus_handle: cx.focus_handle(),
-            _subscription: cx.subscribe(&index, |this, _, _, cx| this.update_rows(cx)),
+            _subscription: cx.subscribe(&index, |this, _, _: &Status, cx| this.update_rows(cx)),
             index,
         };
         this.update_rows(cx);
@@ -53,18 +110,24 @@ impl ProjectIndexDebugView {
             let mut rows = Vec::new();
 
             for index in worktree_indices {
-                let (root_path, worktree_id, worktree_paths) =
+                let (root_path, worktree_id, worktree_paths, skipped_paths) =
                     index.read_with(&cx, |index, cx| {
                         let worktree = index.worktree.read(cx);
-                        (worktree.abs_path(), worktree.id(), index.paths(cx))
+                        (
+                            worktree.abs_path(),
+                            worktree.id(),
+                            index.paths(cx),
+                            index.skipped_files(cx),
+                        )
                     })?;
+                let skipped_paths = skipped_paths.await?.into_iter().collect::<HashMap<_, _>>();
                 rows.push(Row::Worktree(root_path));
-                rows.extend(
-                    worktree_paths
-                        .await?
-                        .into_iter()
-                        .map(|path| Row::Entry(worktree_id, path)),
-                );
+                rows.extend(worktree_paths.await?.into_iter().map(|path| {
+                    match skipped_paths.get(&path) {
+                        Some(skip_reason) => Row::Skipped(path, *skip_reason),
+                        None => Row::Entry(worktree_id, path),
+                    }
+                }));
             }
 
             this.update(&mut cx, |this, cx| {
@@ -82,26 +145,28 @@ impl ProjectIndexDebugView {
         cx: &mut ViewContext<Self>,
     ) -> Option<()> {
         let project_index = self.index.read(cx);
-        let fs = project_index.fs.clone();
         let worktree_index = project_index.worktree_index(worktree_id, cx)?.read(cx);
         let root_path = worktree_index.worktree.read(cx).abs_path();
-        let chunks = worktree_index.chunks_for_path(file_path.clone(), cx);
+        let file_loader = worktree_index.file_loader.clone();
+        let embedded_file = worktree_index.embedded_file(file_path.clone(), cx);
 
         cx.spawn(|this, mut cx| async move {
-            let chunks = chunks.await?;
-            let content = fs.load(&root_path.join(&file_path)).await?;
-            let chunks = chunks
-                .into_iter()
-                .map(|chunk| {
-                    let mut start = chunk.chunk.range.start.min(content.len());
-                    let mut end = chunk.chunk.range.end.min(content.len());
-                    while !content.is_char_boundary(start) {
-                        start += 1;
-                    }
-                    while !content.is_char_boundary(end) {
-                        end -= 1;
-                    }
-                    content[start..end].to_string().into()
+            let embedded_file = embedded_file.await?;
+            let content = file_loader
+                .load_text(worktree_id, &root_path, &file_path)
+                .await?;
+            // Files that didn't change keep the digests of their chunks when they're
+            // moved, even though their headers change.
+            let stale = text_digest(&content) != embedded_file.digest;
+            let chunks = embedded_file
+                .chunks
+                .iter()
+                .map(|chunk| ChunkDetails {
+                    range: chunk.chunk.range.clone(),
+                    digest: hex::encode(&chunk.chunk.digest[..8]).into(),
+                    dimensions: chunk.embedding.dimensions(),
+                    stale: stale && is_chunk_stale(&content, chunk),
+                    embedded_text: embedded_text(&content, chunk),
                 })
                 .collect::<Vec<_>>();
 
@@ -109,6 +174,7 @@ impl ProjectIndexDebugView {
                 let view = cx.view().downgrade();
                 this.selected_path = Some(PathState {
                     path: file_path,
+                    stale,
                     list_state: ListState::new(
                         chunks.len(),
                         gpui::ListAlignment::Top,
@@ -147,10 +213,14 @@ impl ProjectIndexDebugView {
                 h_flex()
                     .justify_between()
                     .child(format!(
-                        "chunk {} of {}. length: {}",
+                        "chunk {} of {}. range: {}..{}. digest: {}. dimensions: {}{}",
                         ix + 1,
                         state.chunks.len(),
-                        chunk.len(),
+                        chunk.range.start,
+                        chunk.range.end,
+                        chunk.digest,
+                        chunk.dimensions,
+                        if chunk.stale { ". stale" } else { "" },
                     ))
                     .child(
                         h_flex()
@@ -174,7 +244,7 @@ impl ProjectIndexDebugView {
                 div()
                     .bg(colors.editor_background)
                     .text_xs()
-                    .child(chunk.clone()),
+                    .child(chunk.embedded_text.clone()),
             )
             .into_any_element()
     }
@@ -187,11 +257,265 @@ impl ProjectIndexDebugView {
             })
         }
     }
+
+    /// Copies a JSON report of the state of the index to the clipboard, for attaching
+    /// to bug reports.
+    fn export_diagnostics(&mut self, _: &ExportIndexDiagnostics, cx: &mut ViewContext<Self>) {
+        let diagnostics = self.index.read(cx).diagnostics(cx);
+        cx.spawn(|_, mut cx| async move {
+            let json = diagnostics.await?.to_json()?;
+            cx.update(|cx| cx.write_to_clipboard(ClipboardItem::new_string(json)))
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Searches the index for the query, breaking down the score of each result.
+    fn search(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
+        let query = self.query_editor.read(cx).text(cx);
+        if query.trim().is_empty() {
+            self.search.take();
+            cx.notify();
+            return;
+        }
+
+        let project_index = self.index.read(cx);
+        let search = project_index.search(
+            query.clone(),
+            SearchFilter::default(),
+            SEARCH_RESULT_LIMIT,
+            None,
+            None,
+            cx,
+        );
+        let keyword_search = project_index.keyword_search(&query, KEYWORD_CANDIDATE_LIMIT, cx);
+        cx.spawn(|this, mut cx| async move {
+            let (results, keyword_results) = futures::try_join!(search, keyword_search)?;
+            let lexical_scores = keyword_results
+                .into_iter()
+                .map(|result| {
+                    (
+                        (result.worktree.entity_id(), result.path, result.range),
+                        result.score,
+                    )
+                })
+                .collect::<HashMap<(EntityId, Arc<Path>, Range<usize>), f32>>();
+
+            let mut breakdowns = Vec::new();
+            for result in results {
+                let Some((embedded_file, content)) = this.update(&mut cx, |this, cx| {
+                    let worktree = result.worktree.read(cx);
+                    let worktree_id = worktree.id();
+                    let root_path = worktree.abs_path();
+                    let index = this.index.read(cx).worktree_index(worktree_id, cx)?;
+                    let index = index.read(cx);
+                    let file_loader = index.file_loader.clone();
+                    let path = result.path.clone();
+                    Some((index.embedded_file(path.clone(), cx), async move {
+                        file_loader.load_text(worktree_id, &root_path, &path).await
+                    }))
+                })?
+                else {
+                    continue;
+                };
+                let Some(content) = content.await.log_err() else {
+                    continue;
+                };
+                // Results in files with unsaved changes were embedded on the fly, and
+                // aren't in the index.
+                let chunks = embedded_file
+                    .await
+                    .map(|file| file.chunks)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|chunk| {
+                        chunk.chunk.cell == result.cell
+                            && chunk.chunk.range.start >= result.range.start
+                            && chunk.chunk.range.end <= result.range.end
+                    })
+                    .collect::<Vec<_>>();
+                let lexical_score = chunks
+                    .iter()
+                    .filter_map(|chunk| {
+                        let key = (
+                            result.worktree.entity_id(),
+                            result.path.clone(),
+                            chunk.chunk.range.clone(),
+                        );
+                        lexical_scores.get(&key).copied()
+                    })
+                    .reduce(f32::max);
+                breakdowns.push(ResultBreakdown {
+                    text: text_in_range(&content, &result.range, result.cell.is_some()).into(),
+                    embedded_texts: chunks
+                        .iter()
+                        .map(|chunk| embedded_text(&content, chunk))
+                        .collect(),
+                    path: result.path,
+                    range: result.range,
+                    score: result.score,
+                    weight: result.weight,
+                    lexical_score,
+                });
+            }
+
+            this.update(&mut cx, |this, cx| {
+                let view = cx.view().downgrade();
+                this.search = Some(SearchState {
+                    query: query.into(),
+                    list_state: ListState::new(
+                        breakdowns.len(),
+                        gpui::ListAlignment::Top,
+                        px(100.),
+                        move |ix, cx| {
+                            if let Some(view) = view.upgrade() {
+                                view.update(cx, |view, cx| view.render_search_result(ix, cx))
+                            } else {
+                                div().into_any()
+                            }
+                        },
+                    ),
+                    results: breakdowns,
+                });
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn render_search_result(&mut self, ix: usize, cx: &mut ViewContext<Self>) -> AnyElement {
+        let buffer_font = ThemeSettings::get_global(cx).buffer_font.clone();
+        let Some(state) = &self.search else {
+            return div().into_any();
+        };
+
+        let colors = cx.theme().colors();
+        let result = &state.results[ix];
+        // The score is the similarity scaled by the weight of the file.
+        let similarity = if result.weight > 0. {
+            result.score / result.weight
+        } else {
+            0.
+        };
+        let lexical_score = match result.lexical_score {
+            Some(score) => format!("{score:.3}"),
+            None => "none".to_string(),
+        };
+
+        v_flex()
+            .text_ui(cx)
+            .w_full()
+            .pb_2()
+            .font(buffer_font)
+            .child(format!(
+                "{}. {} ({}..{})",
+                ix + 1,
+                result.path.to_string_lossy(),
+                result.range.start,
+                result.range.end,
+            ))
+            .child(
+                Label::new(format!(
+                    "score: {:.3} = similarity {similarity:.3} × weight {:.3}. \
+                    lexical score: {lexical_score}",
+                    result.score, result.weight,
+                ))
+                .color(Color::Muted),
+            )
+            .child(
+                div()
+                    .bg(colors.editor_background)
+                    .text_xs()
+                    .child(result.text.clone()),
+            )
+            .children(
+                result
+                    .embedded_texts
+                    .iter()
+                    .enumerate()
+                    .map(|(chunk_ix, embedded_text)| {
+                        v_flex()
+                            .child(
+                                Label::new(format!(
+                                    "embedded text of chunk {} of {}",
+                                    chunk_ix + 1,
+                                    result.embedded_texts.len()
+                                ))
+                                .color(Color::Muted),
+                            )
+                            .child(
+                                div()
+                                    .bg(colors.editor_background)
+                                    .text_xs()
+                                    .child(embedded_text.clone()),
+                            )
+                    }),
+            )
+            .into_any_element()
+    }
+}
+
+/// Returns the text of a file in the given range, narrowed to character boundaries.
+/// The range of a notebook cell is in the JSON of its source, which is decoded.
+fn text_in_range(content: &str, range: &Range<usize>, is_cell: bool) -> String {
+    let mut start = range.start.min(content.len());
+    let mut end = range.end.min(content.len());
+    while !content.is_char_boundary(start) {
+        start += 1;
+    }
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = &content[start..end.max(start)];
+    if is_cell {
+        decode_source(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Returns whether the text of a chunk in the file's current content differs from the
+/// text it was embedded with.
+fn is_chunk_stale(content: &str, chunk: &EmbeddedChunk) -> bool {
+    let chunk = &chunk.chunk;
+    let in_bounds = content.get(chunk.range.clone()).is_some();
+    !in_bounds || chunk_digest(&chunk.header, &chunk.text(content)) != chunk.digest
+}
+
+/// Returns the text that was embedded for a chunk, starting with its header.
+fn embedded_text(content: &str, chunk: &EmbeddedChunk) -> SharedString {
+    let text = text_in_range(content, &chunk.chunk.range, chunk.chunk.cell.is_some());
+    format!("{}{}", chunk.chunk.header, text).into()
 }
 
 impl Render for ProjectIndexDebugView {
     fn render(&mut self, cx: &mut gpui::ViewContext<'_, Self>) -> impl IntoElement {
-        if let Some(selected_path) = self.selected_path.as_ref() {
+        let content = if let Some(search) = self.search.as_ref() {
+            v_flex()
+                .child(
+                    div()
+                        .id("search-query")
+                        .child(
+                            h_flex()
+                                .justify_between()
+                                .child(format!(
+                                    "{} results for \"{}\"",
+                                    search.results.len(),
+                                    search.query
+                                ))
+                                .child("x"),
+                        )
+                        .border_b_1()
+                        .border_color(cx.theme().colors().border)
+                        .cursor(CursorStyle::PointingHand)
+                        .on_click(cx.listener(|this, _, cx| {
+                            this.search.take();
+                            cx.notify();
+                        })),
+                )
+                .child(list(search.list_state.clone()).size_full())
+                .size_full()
+                .into_any_element()
+        } else if let Some(selected_path) = self.selected_path.as_ref() {
             v_flex()
                 .child(
                     div()
@@ -199,7 +523,15 @@ impl Render for ProjectIndexDebugView {
                         .child(
                             h_flex()
                                 .justify_between()
-                                .child(selected_path.path.to_string_lossy().to_string())
+                                .child(format!(
+                                    "{}{}",
+                                    selected_path.path.to_string_lossy(),
+                                    if selected_path.stale {
+                                        " (changed on disk since it was indexed)"
+                                    } else {
+                                        ""
+                                    }
+                                ))
                                 .child("x"),
                         )
                         .border_b_1()
@@ -226,6 +558,13 @@ impl Render for ProjectIndexDebugView {
                             Row::Worktree(root_path) => div()
                                 .id(ix)
                                 .child(Label::new(root_path.to_string_lossy().to_string())),
+                            Row::Skipped(file_path, skip_reason) => div().id(ix).pl_8().child(
+                                Label::new(format!(
+                                    "{} (skipped: {skip_reason})",
+                                    file_path.to_string_lossy()
+                                ))
+                                .color(Color::Muted),
+                            ),
                             Row::Entry(worktree_id, file_path) => div()
                                 .id(ix)
                                 .pl_8()
@@ -264,7 +603,29 @@ impl Render for ProjectIndexDebugView {
             )
             .size_full()
             .into_any_element()
-        }
+        };
+
+        v_flex()
+            .key_context("ProjectIndexDebugView")
+            .on_action(cx.listener(Self::search))
+            .on_action(cx.listener(Self::export_diagnostics))
+            .child(
+                h_flex()
+                    .p_1()
+                    .gap_2()
+                    .border_b_1()
+                    .border_color(cx.theme().colors().border)
+                    .child(div().flex_1().child(self.query_editor.clone()))
+                    .child(
+                        Button::new("export-diagnostics", "Copy Diagnostics")
+                            .tooltip(|cx| Tooltip::text("Copy the state of the index as JSON", cx))
+                            .on_click(cx.listener(|this, _, cx| {
+                                this.export_diagnostics(&ExportIndexDiagnostics, cx)
+                            })),
+                    ),
+            )
+            .child(content)
+            .size_full()
     }
 }
 
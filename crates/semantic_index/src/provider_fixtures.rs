@@ -0,0 +1,400 @@
+//! Fixtures of the HTTP requests embedding providers send to their APIs, to test
+//! providers against responses recorded from the real APIs without sending requests.
+//!
+//! Tests create providers with [`FixtureEmbeddingProvider::new`], which replays the
+//! provider's fixture, and record it again against the real API when the
+//! `RECORD_PROVIDER_FIXTURES` environment variable is set, like after changing the
+//! requests the provider sends:
+//!
+//! ```sh
+//! RECORD_PROVIDER_FIXTURES=1 OPENAI_API_KEY=... cargo test -p semantic_index openai
+//! ```
+
+use anyhow::{anyhow, Context as _, Result};
+use collections::{BTreeMap, VecDeque};
+use futures::{future::BoxFuture, AsyncReadExt as _, FutureExt};
+use http_client::{AsyncBody, HttpClient, HttpClientWithUrl, Request, Response, Uri};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    Embedding, EmbeddingModelInfo, EmbeddingProvider, RateLimits, ScoreCalibration, TextToEmbed,
+};
+
+/// The environment variable that makes tests record fixtures instead of replaying them.
+const RECORD_VAR: &str = "RECORD_PROVIDER_FIXTURES";
+
+/// A request a provider sent and the response it got. Request headers aren't recorded,
+/// since they hold credentials.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    uri: String,
+    request_body: String,
+    status: u16,
+    response_headers: BTreeMap<String, String>,
+    response_body: String,
+}
+
+/// Returns the path of the fixture with the given name.
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixture")
+        .join("providers")
+        .join(format!("{name}.json"))
+}
+
+async fn read_body(mut body: AsyncBody) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Sends requests with another client, recording every request and its response.
+struct RecordingHttpClient {
+    client: Arc<dyn HttpClient>,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl HttpClient for RecordingHttpClient {
+    fn send(
+        &self,
+        request: Request<AsyncBody>,
+    ) -> BoxFuture<'static, Result<Response<AsyncBody>, http_client::Error>> {
+        let client = self.client.clone();
+        let interactions = self.interactions.clone();
+        async move {
+            let (parts, body) = request.into_parts();
+            let request_body = read_body(body).await?;
+            let method = parts.method.to_string();
+            let uri = parts.uri.to_string();
+            let response = client
+                .send(Request::from_parts(
+                    parts,
+                    AsyncBody::from(request_body.clone()),
+                ))
+                .await?;
+            let (parts, body) = response.into_parts();
+            let response_body = read_body(body).await?;
+            interactions.lock().push(Interaction {
+                method,
+                uri,
+                request_body: String::from_utf8_lossy(&request_body).into_owned(),
+                status: parts.status.as_u16(),
+                response_headers: parts
+                    .headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect(),
+                response_body: String::from_utf8_lossy(&response_body).into_owned(),
+            });
+            Ok(Response::from_parts(parts, AsyncBody::from(response_body)))
+        }
+        .boxed()
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        self.client.proxy()
+    }
+}
+
+/// Responds to requests with the recorded responses, in order, failing requests that
+/// differ from the ones that were recorded.
+struct ReplayingHttpClient {
+    interactions: Arc<Mutex<VecDeque<Interaction>>>,
+}
+
+impl HttpClient for ReplayingHttpClient {
+    fn send(
+        &self,
+        request: Request<AsyncBody>,
+    ) -> BoxFuture<'static, Result<Response<AsyncBody>, http_client::Error>> {
+        let interactions = self.interactions.clone();
+        async move {
+            let (parts, body) = request.into_parts();
+            let request_body = read_body(body).await?;
+            let request_body = String::from_utf8_lossy(&request_body);
+            let interaction = interactions.lock().pop_front().ok_or_else(|| {
+                std::io::Error::other(format!("unexpected request to {}", parts.uri))
+            })?;
+            if interaction.method != parts.method.as_str()
+                || parts.uri != interaction.uri.as_str()
+                || interaction.request_body != request_body
+            {
+                return Err(std::io::Error::other(format!(
+                    "request {} {} {request_body:?} differs from the recorded request {} {} {:?}, \
+                    record the fixture again with {RECORD_VAR}=1",
+                    parts.method,
+                    parts.uri,
+                    interaction.method,
+                    interaction.uri,
+                    interaction.request_body
+                ))
+                .into());
+            }
+            let mut response = Response::builder().status(interaction.status);
+            for (name, value) in &interaction.response_headers {
+                response = response.header(name, value);
+            }
+            Ok(response.body(AsyncBody::from(interaction.response_body))?)
+        }
+        .boxed()
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        None
+    }
+}
+
+/// Wraps a provider whose requests are either recorded to a fixture, which is saved
+/// after every request, or replayed from it.
+pub struct FixtureEmbeddingProvider {
+    provider: Arc<dyn EmbeddingProvider>,
+    /// The path to save the fixture to and the requests recorded so far, if recording.
+    recording: Option<(PathBuf, Arc<Mutex<Vec<Interaction>>>)>,
+}
+
+impl FixtureEmbeddingProvider {
+    /// Replays the fixture with the given name, unless `RECORD_PROVIDER_FIXTURES` is
+    /// set, in which case requests are sent to the real API and recorded.
+    pub fn new(
+        name: &str,
+        create_provider: impl FnOnce(Arc<dyn HttpClient>) -> Arc<dyn EmbeddingProvider>,
+    ) -> Result<Self> {
+        let path = fixture_path(name);
+        if std::env::var_os(RECORD_VAR).is_some() {
+            let client = Arc::new(HttpClientWithUrl::new("http://localhost", None, None));
+            Ok(Self::record(path, client, create_provider))
+        } else {
+            Self::replay(&path, create_provider)
+        }
+    }
+
+    /// Sends the provider's requests with the given client, recording them to the
+    /// fixture at `path`.
+    pub fn record(
+        path: PathBuf,
+        client: Arc<dyn HttpClient>,
+        create_provider: impl FnOnce(Arc<dyn HttpClient>) -> Arc<dyn EmbeddingProvider>,
+    ) -> Self {
+        let interactions = Arc::new(Mutex::new(Vec::new()));
+        let client = Arc::new(RecordingHttpClient {
+            client,
+            interactions: interactions.clone(),
+        });
+        Self {
+            provider: create_provider(client),
+            recording: Some((path, interactions)),
+        }
+    }
+
+    /// Responds to the provider's requests with the ones recorded to the fixture at
+    /// `path`.
+    pub fn replay(
+        path: &Path,
+        create_provider: impl FnOnce(Arc<dyn HttpClient>) -> Arc<dyn EmbeddingProvider>,
+    ) -> Result<Self> {
+        let fixture = fs::read_to_string(path).with_context(|| {
+            format!("reading provider fixture {path:?}, record it with {RECORD_VAR}=1")
+        })?;
+        let interactions: VecDeque<Interaction> = serde_json::from_str(&fixture)?;
+        let client = Arc::new(ReplayingHttpClient {
+            interactions: Arc::new(Mutex::new(interactions)),
+        });
+        Ok(Self {
+            provider: create_provider(client),
+            recording: None,
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some((path, interactions)) = &self.recording else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let fixture = serde_json::to_string_pretty(&*interactions.lock())?;
+        fs::write(path, fixture).with_context(|| format!("writing provider fixture {path:?}"))
+    }
+}
+
+impl EmbeddingProvider for FixtureEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let result = self.provider.embed(texts).await;
+            self.save()?;
+            result
+        }
+        .boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let result = self.provider.embed_query(queries).await;
+            self.save()?;
+            result
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.provider.batch_size()
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        self.provider.model_info()
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        self.provider.score_calibration()
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        self.provider.rate_limits()
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        self.provider.max_input_tokens()
+    }
+
+    fn count_tokens(&self, text: &str) -> u64 {
+        self.provider.count_tokens(text)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        self.provider.price_per_million_tokens()
+    }
+}
+
+/// Checks what every embedding provider has to do for the index to work: return one
+/// embedding of the model's dimensions per text, embed queries, and score texts about
+/// the query above unrelated ones.
+pub async fn check_embedding_provider(provider: &dyn EmbeddingProvider) -> Result<()> {
+    let texts = [
+        "fn add(a: i32, b: i32) -> i32 { a + b }",
+        "The quick brown fox jumps over the lazy dog.",
+    ]
+    .map(TextToEmbed::new);
+    let embeddings = provider.embed(&texts).await?;
+    if embeddings.len() != texts.len() {
+        return Err(anyhow!(
+            "expected {} embeddings, got {}",
+            texts.len(),
+            embeddings.len()
+        ));
+    }
+    let model = provider.model_info();
+    for embedding in &embeddings {
+        if embedding.len() != model.dimensions {
+            return Err(anyhow!(
+                "expected embeddings of {} dimensions, got {}",
+                model.dimensions,
+                embedding.len()
+            ));
+        }
+    }
+
+    let query = provider
+        .embed_query(&[TextToEmbed::new("a function that adds two numbers")])
+        .await?
+        .pop()
+        .context("no query embedding")?;
+    let calibration = provider.score_calibration();
+    let relevant_score = calibration.calibrate(query.similarity(&embeddings[0]));
+    let unrelated_score = calibration.calibrate(query.similarity(&embeddings[1]));
+    if relevant_score <= unrelated_score {
+        return Err(anyhow!(
+            "expected the relevant text to score above the unrelated one, got \
+            {relevant_score} and {unrelated_score}"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OpenAiEmbeddingModel, OpenAiEmbeddingProvider};
+    use http_client::FakeHttpClient;
+
+    fn fake_openai_provider(client: Arc<dyn HttpClient>) -> Arc<dyn EmbeddingProvider> {
+        Arc::new(OpenAiEmbeddingProvider::new(
+            client,
+            OpenAiEmbeddingModel::TextEmbedding3Small,
+            "https://api.openai.com/v1".into(),
+            "test-key".into(),
+        ))
+    }
+
+    /// Checks OpenAI's API with its fixture, which is skipped until it's recorded.
+    #[gpui::test]
+    async fn test_openai_fixture() {
+        if !fixture_path("openai").exists() && std::env::var_os(RECORD_VAR).is_none() {
+            return;
+        }
+        let provider = FixtureEmbeddingProvider::new("openai", |client| {
+            Arc::new(OpenAiEmbeddingProvider::new(
+                client,
+                OpenAiEmbeddingModel::TextEmbedding3Small,
+                "https://api.openai.com/v1".into(),
+                std::env::var("OPENAI_API_KEY").unwrap_or_default(),
+            ))
+        })
+        .unwrap();
+        check_embedding_provider(&provider).await.unwrap();
+    }
+
+    #[gpui::test]
+    async fn test_record_and_replay_fixture() {
+        // Stands in for OpenAI's API, embedding texts about adding near each other.
+        let api = FakeHttpClient::create(|request| async move {
+            let body = read_body(request.into_body()).await?;
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let data = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|input| {
+                    let mut embedding = vec![0.; 1536];
+                    embedding[0] = 1.;
+                    if input.as_str().unwrap().contains("add") {
+                        embedding[1] = 1.;
+                    }
+                    serde_json::json!({ "embedding": embedding })
+                })
+                .collect::<Vec<_>>();
+            Ok(Response::builder()
+                .status(200)
+                .body(serde_json::json!({ "data": data }).to_string().into())?)
+        });
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("openai.json");
+        let provider = FixtureEmbeddingProvider::record(path.clone(), api, fake_openai_provider);
+        check_embedding_provider(&provider).await.unwrap();
+        let embeddings = provider.embed(&[TextToEmbed::new("add")]).await.unwrap();
+
+        // The same requests get the same responses without the API.
+        let provider = FixtureEmbeddingProvider::replay(&path, fake_openai_provider).unwrap();
+        check_embedding_provider(&provider).await.unwrap();
+        assert_eq!(
+            provider.embed(&[TextToEmbed::new("add")]).await.unwrap(),
+            embeddings
+        );
+
+        // But requests that weren't recorded fail.
+        let provider = FixtureEmbeddingProvider::replay(&path, fake_openai_provider).unwrap();
+        assert!(provider.embed(&[TextToEmbed::new("sub")]).await.is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    db_connection::DbConnection, embedding_cache::EmbeddingCache,
+    failover::FallbackEmbeddingProvider, kv_store::KvStorage,
+    rate_limit::RateLimitedEmbeddingProvider, shared_embeddings::SharedEmbeddings,
+    DiskCachedEmbeddingProvider, DiskEmbeddingCache, EmbeddingModelInfo, EmbeddingProvider,
+    HttpEmbeddingStore, SemanticIndex, SemanticIndexSettings, StorageBackend,
+};
+#[cfg(any(test, feature = "test-support"))]
+use crate::{Reranker, SharedEmbeddingStore};
+#[cfg(any(test, feature = "test-support"))]
+use anyhow::anyhow;
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, Task};
+use settings::Settings;
+use std::sync::Arc;
+
+impl SemanticIndex {
+    /// Keeps to the provider's rate limits, which users may have raised or lowered to
+    /// match their plan, and fails over to the fallback providers of the settings.
+    /// Embeddings cached on disk don't count against the rate limits.
+    pub(crate) fn wrap_embedding_provider(
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        disk_cache: Option<&Arc<DiskEmbeddingCache>>,
+        cx: &AppContext,
+    ) -> Arc<dyn EmbeddingProvider> {
+        let settings = SemanticIndexSettings::get_global(cx);
+        let rate_limited = |provider: Arc<dyn EmbeddingProvider>| {
+            let rate_limits = settings
+                .rate_limits
+                .get(&provider.model_info().provider)
+                .copied()
+                .unwrap_or_else(|| provider.rate_limits());
+            let provider = Arc::new(RateLimitedEmbeddingProvider::new(
+                provider,
+                rate_limits,
+                cx.background_executor().clone(),
+            )) as Arc<dyn EmbeddingProvider>;
+            match disk_cache {
+                Some(disk_cache) => Arc::new(DiskCachedEmbeddingProvider::new(
+                    provider,
+                    disk_cache.clone(),
+                )),
+                None => provider,
+            }
+        };
+        if settings.fallback_embedding_providers.is_empty() {
+            return rate_limited(embedding_provider);
+        }
+        let providers = std::iter::once(embedding_provider)
+            .chain(
+                settings
+                    .fallback_embedding_providers
+                    .iter()
+                    .map(|provider_settings| provider_settings.provider(cx.http_client())),
+            )
+            .map(rate_limited)
+            .collect();
+        Arc::new(FallbackEmbeddingProvider::new(
+            providers,
+            cx.background_executor().clone(),
+        ))
+    }
+
+    pub(crate) async fn open_embedding_cache(
+        db_connection: DbConnection,
+        storage_backend: StorageBackend,
+        embedding_model: EmbeddingModelInfo,
+    ) -> Result<EmbeddingCache> {
+        let storage =
+            KvStorage::new(storage_backend, &db_connection).context("opening embedding cache")?;
+        EmbeddingCache::new(&storage, &embedding_model).context("opening embedding cache")
+    }
+
+    /// Returns the store of embeddings shared with the rest of the team set in the
+    /// settings, if any, for the embeddings of the given model.
+    pub(crate) fn shared_embeddings_from_settings(
+        model: EmbeddingModelInfo,
+        cx: &AppContext,
+    ) -> Option<SharedEmbeddings> {
+        let settings = SemanticIndexSettings::get_global(cx)
+            .shared_embedding_store
+            .as_ref()?;
+        Some(SharedEmbeddings {
+            store: Arc::new(HttpEmbeddingStore::new(
+                cx.http_client(),
+                settings.url.clone(),
+                model,
+            )),
+            push: settings.push,
+        })
+    }
+
+    /// Switches every project's index to another embedding provider, like when the
+    /// user signs in. Their worktrees are loaded again, embedding the files embedded
+    /// by another model again unless the worktree is pinned to it.
+    pub fn set_embedding_provider(
+        &mut self,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        cx: &mut AppContext,
+    ) -> Task<Result<()>> {
+        let embedding_provider =
+            Self::wrap_embedding_provider(embedding_provider, self.disk_cache.as_ref(), cx);
+        let open_embedding_cache = cx.background_executor().spawn(Self::open_embedding_cache(
+            self.databases.shared().clone(),
+            self.storage_backend,
+            embedding_provider.model_info(),
+        ));
+        cx.spawn(|mut cx| async move {
+            let embedding_cache = Arc::new(open_embedding_cache.await?);
+            cx.update_global::<Self, _>(|this, cx| {
+                // The team's shared embeddings only apply to the model they were
+                // embedded with.
+                let model = embedding_provider.model_info();
+                let shared = this
+                    .embedding_cache
+                    .shared()
+                    .filter(|shared| shared.store.model_info() == model)
+                    .or_else(|| Self::shared_embeddings_from_settings(model, cx));
+                embedding_cache.set_shared(shared);
+                this.embedding_provider = embedding_provider;
+                this.embedding_cache = embedding_cache;
+                for project_index in this.project_indices.values() {
+                    project_index.update(cx, |project_index, cx| {
+                        project_index.set_embedding_provider(
+                            this.embedding_provider.clone(),
+                            this.embedding_cache.clone(),
+                            cx,
+                        );
+                    });
+                }
+            })
+        })
+    }
+
+    /// Sets the reranker that re-scores the best results of every project's searches,
+    /// or stops reranking them.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn set_reranker(&mut self, reranker: Option<Arc<dyn Reranker>>, cx: &mut AppContext) {
+        for project_index in self.project_indices.values() {
+            project_index.update(cx, |project_index, _| {
+                project_index.reranker = reranker.clone();
+            });
+        }
+        self.reranker = reranker;
+    }
+
+    /// Sets the store of embeddings shared with the rest of a team, which content is
+    /// looked up in before being embedded locally, or stops using it, instead of the
+    /// one in the settings. When `push` is true, the embeddings computed locally are
+    /// shared to the store too.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn set_shared_embedding_store(
+        &mut self,
+        store: Option<Arc<dyn SharedEmbeddingStore>>,
+        push: bool,
+    ) -> Result<()> {
+        if let Some(store) = &store {
+            let model = self.embedding_provider.model_info();
+            if store.model_info() != model {
+                return Err(anyhow!(
+                    "shared embeddings were embedded with {:?}, but the current model is {:?}",
+                    store.model_info(),
+                    model
+                ));
+            }
+        }
+        self.embedding_cache
+            .set_shared(store.map(|store| SharedEmbeddings { store, push }));
+        Ok(())
+    }
+}
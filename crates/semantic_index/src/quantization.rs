@@ -0,0 +1,285 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ops::{Bound, Range};
+
+use crate::{
+    encryption::{EncryptedDatabase, IndexCipher},
+    indexing::EmbeddedFile,
+    Embedding,
+};
+
+/// How embeddings are quantized before being persisted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingQuantization {
+    /// Store every dimension as a 32-bit float.
+    #[default]
+    None,
+    /// Store every dimension as an 8-bit integer, scaled by the largest one.
+    Int8,
+    /// Store only the sign of every dimension, as a single bit. The best candidates of
+    /// a search are re-ranked against full-precision embeddings kept on the side.
+    Binary,
+}
+
+/// An embedding as it is persisted, either at full precision or quantized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum StoredEmbedding {
+    Full(Embedding),
+    Int8 { scale: f32, values: Vec<i8> },
+    Binary { dimensions: u32, bits: Vec<u64> },
+}
+
+impl From<Embedding> for StoredEmbedding {
+    fn from(embedding: Embedding) -> Self {
+        Self::Full(embedding)
+    }
+}
+
+impl StoredEmbedding {
+    /// Quantizes a full-precision embedding. Embeddings that are already quantized are
+    /// returned as they are.
+    pub fn quantize(&self, quantization: EmbeddingQuantization) -> Self {
+        let Self::Full(embedding) = self else {
+            return self.clone();
+        };
+        let dimensions = embedding.as_slice();
+        match quantization {
+            EmbeddingQuantization::None => self.clone(),
+            EmbeddingQuantization::Int8 => {
+                let max = dimensions
+                    .iter()
+                    .fold(0f32, |max, dimension| max.max(dimension.abs()));
+                let scale = if max > 0. { max / i8::MAX as f32 } else { 1. };
+                let values = dimensions
+                    .iter()
+                    .map(|dimension| (dimension / scale).round() as i8)
+                    .collect();
+                Self::Int8 { scale, values }
+            }
+            EmbeddingQuantization::Binary => {
+                let mut bits = vec![0u64; dimensions.len().div_ceil(64)];
+                for (ix, dimension) in dimensions.iter().enumerate() {
+                    if *dimension > 0. {
+                        bits[ix / 64] |= 1 << (ix % 64);
+                    }
+                }
+                Self::Binary {
+                    dimensions: dimensions.len() as u32,
+                    bits,
+                }
+            }
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Binary { .. })
+    }
+
+    /// How many dimensions the embedding has, whether it's quantized or not. It can only
+    /// be compared with embeddings of as many dimensions.
+    pub fn dimensions(&self) -> usize {
+        match self {
+            Self::Full(embedding) => embedding.len(),
+            Self::Int8 { values, .. } => values.len(),
+            Self::Binary { dimensions, .. } => *dimensions as usize,
+        }
+    }
+
+    /// Estimates the cosine similarity between this embedding and a full-precision one,
+    /// without dequantizing it first.
+    pub fn similarity(&self, other: &Embedding) -> f32 {
+        match self {
+            Self::Full(embedding) => embedding.similarity(other),
+            Self::Int8 { scale, values } => {
+                let dot = values
+                    .iter()
+                    .zip(other.as_slice())
+                    .map(|(value, other)| *value as f32 * other)
+                    .sum::<f32>();
+                dot * scale
+            }
+            Self::Binary { dimensions, bits } => {
+                let dot = other
+                    .as_slice()
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, other)| {
+                        if bits[ix / 64] & (1 << (ix % 64)) != 0 {
+                            *other
+                        } else {
+                            -other
+                        }
+                    })
+                    .sum::<f32>();
+                dot / (*dimensions as f32).sqrt()
+            }
+        }
+    }
+
+    /// Reconstructs a normalized full-precision approximation of this embedding.
+    pub fn dequantize(&self) -> Embedding {
+        match self {
+            Self::Full(embedding) => embedding.clone(),
+            Self::Int8 { scale, values } => {
+                Embedding::new(values.iter().map(|value| *value as f32 * scale).collect())
+            }
+            Self::Binary { dimensions, bits } => Embedding::new(
+                (0..*dimensions as usize)
+                    .map(|ix| {
+                        if bits[ix / 64] & (1 << (ix % 64)) != 0 {
+                            1.
+                        } else {
+                            -1.
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl EmbeddedFile {
+    pub(crate) fn quantize(&self, quantization: EmbeddingQuantization) -> Self {
+        Self {
+            path: self.path.clone(),
+            mtime: self.mtime,
+            digest: self.digest,
+            chunks: self
+                .chunks
+                .iter()
+                .map(|chunk| {
+                    let mut chunk = chunk.clone();
+                    chunk.embedding = chunk.embedding.quantize(quantization);
+                    chunk
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The full-precision embeddings of files whose stored embeddings are binary, which
+/// are too coarse to rank the best candidates of a search on their own.
+#[derive(Clone, Copy)]
+pub(crate) struct FullPrecisionEmbeddings {
+    db: EncryptedDatabase<EmbeddedFile>,
+}
+
+impl FullPrecisionEmbeddings {
+    pub fn create(
+        env: &heed::Env,
+        txn: &mut heed::RwTxn,
+        name: &str,
+        cipher: Option<IndexCipher>,
+    ) -> Result<Self> {
+        let db = EncryptedDatabase::create(
+            env,
+            txn,
+            &format!("{name}:full_precision_embeddings"),
+            cipher,
+        )?;
+        Ok(Self { db })
+    }
+
+    /// Keeps the full-precision embeddings of a file if it is going to be stored with
+    /// the given quantization, and forgets them otherwise.
+    pub fn insert(
+        &self,
+        txn: &mut heed::RwTxn,
+        db_key: &str,
+        file: &EmbeddedFile,
+        quantization: EmbeddingQuantization,
+    ) -> Result<()> {
+        if quantization == EmbeddingQuantization::Binary {
+            self.db.put(txn, db_key, file)?;
+        } else {
+            self.db.delete(txn, db_key)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<()> {
+        self.db.delete_range(txn, range)?;
+        Ok(())
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.db.clear(txn)?;
+        Ok(())
+    }
+
+    pub fn get(&self, txn: &heed::RoTxn, db_key: &str) -> Result<Option<EmbeddedFile>> {
+        self.db.get(txn, db_key)
+    }
+
+    /// Returns the full-precision similarity between the query and the chunk of a file
+    /// with the given range, if it is known.
+    pub fn similarity(
+        &self,
+        txn: &heed::RoTxn,
+        db_key: &str,
+        range: &Range<usize>,
+        query: &Embedding,
+    ) -> Result<Option<f32>> {
+        let Some(file) = self.db.get(txn, db_key)? else {
+            return Ok(None);
+        };
+        Ok(file
+            .chunks
+            .iter()
+            .find(|chunk| chunk.chunk.range == *range)
+            .map(|chunk| chunk.embedding.similarity(query)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(seed: usize) -> Embedding {
+        Embedding::new(
+            (0..96)
+                .map(|ix| (((ix + 1) * (seed + 3)) % 17) as f32 - 8.)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_int8_quantization() {
+        let a = embedding(1);
+        let b = embedding(2);
+        let quantized = StoredEmbedding::from(a.clone()).quantize(EmbeddingQuantization::Int8);
+        assert_eq!(quantized.dimensions(), 96);
+        assert!((quantized.similarity(&b) - a.similarity(&b)).abs() < 0.01);
+        assert!((quantized.dequantize().similarity(&a) - 1.).abs() < 0.001);
+        assert_eq!(quantized.quantize(EmbeddingQuantization::Binary), quantized);
+    }
+
+    #[test]
+    fn test_binary_quantization() {
+        let a = embedding(1);
+        let quantized = StoredEmbedding::from(a.clone()).quantize(EmbeddingQuantization::Binary);
+        assert!(quantized.is_binary());
+        let StoredEmbedding::Binary { dimensions, bits } = &quantized else {
+            unreachable!()
+        };
+        assert_eq!(*dimensions, 96);
+        assert_eq!(bits.len(), 2);
+        assert_eq!(quantized.dimensions(), 96);
+
+        // The signs agree with the original embedding, so it scores well against it...
+        let dequantized = quantized.dequantize();
+        for (original, sign) in a.as_slice().iter().zip(dequantized.as_slice()) {
+            assert!(*original == 0. || original.signum() == sign.signum());
+        }
+        assert!(quantized.similarity(&a) > 0.8);
+        // ...and scores are ordered like full-precision ones for very different embeddings.
+        let opposite = Embedding::new(a.as_slice().iter().map(|value| -value).collect());
+        assert!(quantized.similarity(&a) > quantized.similarity(&opposite));
+    }
+}
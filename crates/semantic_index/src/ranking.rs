@@ -0,0 +1,250 @@
+use crate::UNRELATED_SCORE;
+use collections::HashMap;
+#[cfg(any(test, feature = "test-support"))]
+use std::hash::Hash;
+use std::{
+    cmp::Ordering,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use util::{paths::PathMatcher, ResultExt};
+
+/// Dampens the weight of the top ranks in [`reciprocal_rank_fusion`], so that a
+/// result ranked highly by several searches beats one ranked first by a single one.
+#[cfg(any(test, feature = "test-support"))]
+const RECIPROCAL_RANK_CONSTANT: f32 = 60.0;
+
+/// Fuses several rankings of the same kind of results into one, scoring each result
+/// by the sum of `1 / (k + rank)` over the rankings it appears in. Results are
+/// identified across rankings by `key`, and the first occurrence of each is kept.
+#[cfg(any(test, feature = "test-support"))]
+pub(crate) fn reciprocal_rank_fusion<T, K: Eq + Hash>(
+    rankings: impl IntoIterator<Item = Vec<T>>,
+    key: impl Fn(&T) -> K,
+    limit: usize,
+) -> Vec<(T, f32)> {
+    let mut fused = Vec::<(T, f32)>::new();
+    let mut indices_by_key = HashMap::default();
+    for ranking in rankings {
+        for (rank, result) in ranking.into_iter().enumerate() {
+            let score = 1. / (RECIPROCAL_RANK_CONSTANT + rank as f32 + 1.);
+            let ix = *indices_by_key.entry(key(&result)).or_insert_with(|| {
+                fused.push((result, 0.));
+                fused.len() - 1
+            });
+            fused[ix].1 += score;
+        }
+    }
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    fused.truncate(limit);
+    fused
+}
+
+/// Selects up to `limit` candidates with maximal marginal relevance: one by one, picks
+/// the candidate maximizing `lambda * relevance - (1 - lambda) * similarity`, where
+/// `similarity` is its greatest similarity to the candidates picked before it. This
+/// keeps near duplicates of the best candidates from crowding out everything else.
+pub(crate) fn maximal_marginal_relevance<T>(
+    mut candidates: Vec<T>,
+    lambda: f32,
+    limit: usize,
+    relevance: impl Fn(&T) -> f32,
+    similarity: impl Fn(&T, &T) -> f32,
+) -> Vec<T> {
+    let mut max_similarities = vec![0f32; candidates.len()];
+    let mut selected = Vec::with_capacity(limit.min(candidates.len()));
+    while selected.len() < limit {
+        let Some((ix, _)) = candidates
+            .iter()
+            .zip(&max_similarities)
+            .map(|(candidate, max_similarity)| {
+                lambda * relevance(candidate) - (1. - lambda) * max_similarity
+            })
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        else {
+            break;
+        };
+        let candidate = candidates.swap_remove(ix);
+        max_similarities.swap_remove(ix);
+        for (other, max_similarity) in candidates.iter().zip(&mut max_similarities) {
+            *max_similarity = max_similarity.max(similarity(other, &candidate));
+        }
+        selected.push(candidate);
+    }
+    selected
+}
+
+/// Lowers the calibrated score of a result by how much more similar than unrelated text
+/// it is to a negative query, so that a search can steer away from some topic. Results
+/// unrelated to the negative query keep their score.
+pub(crate) fn penalize_score(score: f32, negative_score: Option<f32>) -> f32 {
+    match negative_score {
+        Some(negative_score) => score - (negative_score - UNRELATED_SCORE).max(0.),
+        None => score,
+    }
+}
+
+/// Weights the scores of results by how recently their file was modified, and by the
+/// directories it's in.
+pub(crate) struct RankingWeights {
+    recency_weight: f32,
+    recency_half_life: Duration,
+    path_weights: Vec<(PathMatcher, f32)>,
+    now: SystemTime,
+}
+
+impl RankingWeights {
+    pub fn new(
+        recency_weight: f32,
+        recency_half_life: Duration,
+        path_weights: &HashMap<String, f32>,
+        now: SystemTime,
+    ) -> Self {
+        Self {
+            recency_weight: recency_weight.clamp(0., 1.),
+            recency_half_life,
+            path_weights: path_weights
+                .iter()
+                .filter_map(|(glob, weight)| {
+                    Some((PathMatcher::new(&[glob.clone()]).log_err()?, *weight))
+                })
+                .collect(),
+            now,
+        }
+    }
+
+    /// Returns the factor to scale the scores of a file's results by. A file loses up to
+    /// the recency weight of its scores as it ages, half of it after one half-life, and
+    /// the weight of every glob matching it or a directory it's in is applied on top.
+    /// Files without a modification time, like unsaved buffers, count as new.
+    pub fn weight(&self, path: &Path, mtime: Option<SystemTime>) -> f32 {
+        let mut weight = 1.;
+        if self.recency_weight > 0. && !self.recency_half_life.is_zero() {
+            if let Some(age) = mtime.and_then(|mtime| self.now.duration_since(mtime).ok()) {
+                let half_lives = age.as_secs_f32() / self.recency_half_life.as_secs_f32();
+                weight *= 1. - self.recency_weight * (1. - 0.5f32.powf(half_lives));
+            }
+        }
+        for (globs, path_weight) in &self.path_weights {
+            if path
+                .ancestors()
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .any(|ancestor| globs.is_match(ancestor))
+            {
+                weight *= path_weight;
+            }
+        }
+        weight
+    }
+}
+
+/// Splits a query into the lowercase words it's made of, for matching it literally.
+#[cfg(any(test, feature = "test-support"))]
+pub(crate) fn keyword_terms(query: &str) -> Vec<String> {
+    let mut terms = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    terms.sort_unstable();
+    terms.dedup();
+    terms
+}
+
+/// Scores text by the fraction of the terms it contains, between 0 and 1.
+pub(crate) fn keyword_score(text: &str, terms: &[String]) -> f32 {
+    if terms.is_empty() {
+        return 0.;
+    }
+    let text = text.to_lowercase();
+    let matched_count = terms
+        .iter()
+        .filter(|term| text.contains(term.as_str()))
+        .count();
+    matched_count as f32 / terms.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranking_weights() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let day = Duration::from_secs(24 * 60 * 60);
+        let path_weights =
+            HashMap::from_iter([("vendor".to_string(), 0.5), ("**/*.rs".to_string(), 2.)]);
+        let weights = RankingWeights::new(0.4, day, &path_weights, now);
+
+        let path = Path::new("src/main.ts");
+        assert_eq!(weights.weight(path, None), 1.);
+        assert_eq!(weights.weight(path, Some(now)), 1.);
+        assert!((weights.weight(path, Some(now - day)) - 0.8).abs() < 1e-6);
+        assert!((weights.weight(path, Some(now - day * 2)) - 0.7).abs() < 1e-6);
+        assert!((weights.weight(path, Some(now - day * 365)) - 0.6).abs() < 1e-6);
+
+        assert_eq!(weights.weight(Path::new("vendor/lib/a.ts"), None), 0.5);
+        assert_eq!(weights.weight(Path::new("vendor/lib/a.rs"), None), 1.);
+        assert_eq!(weights.weight(Path::new("src/vendor.ts"), None), 1.);
+    }
+
+    #[test]
+    fn test_penalize_score() {
+        assert_eq!(penalize_score(0.8, None), 0.8);
+        assert_eq!(penalize_score(0.8, Some(0.4)), 0.8);
+        assert_eq!(penalize_score(0.8, Some(0.5)), 0.8);
+        assert!((penalize_score(0.8, Some(0.75)) - 0.55).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let fused = reciprocal_rank_fusion(
+            [vec!["a", "b", "c"], vec!["c", "d"], vec![]],
+            |result| *result,
+            3,
+        );
+        let order = fused.iter().map(|(result, _)| *result).collect::<Vec<_>>();
+        assert_eq!(order, ["c", "a", "b"]);
+        assert_eq!(fused[0].1, 1. / 63. + 1. / 61.);
+    }
+
+    #[test]
+    fn test_maximal_marginal_relevance() {
+        // Candidates are (name, relevance, cluster), and candidates in the same
+        // cluster are near duplicates of each other.
+        let candidates = vec![
+            ("a1", 0.9, 0),
+            ("a2", 0.85, 0),
+            ("b", 0.6, 1),
+            ("a3", 0.8, 0),
+        ];
+        let select = |lambda| {
+            maximal_marginal_relevance(
+                candidates.clone(),
+                lambda,
+                3,
+                |candidate| candidate.1,
+                |a, b| if a.2 == b.2 { 1. } else { 0. },
+            )
+            .into_iter()
+            .map(|candidate| candidate.0)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(select(1.), ["a1", "a2", "a3"]);
+        assert_eq!(select(0.5), ["a1", "b", "a2"]);
+        assert_eq!(
+            maximal_marginal_relevance(vec![1, 2], 0.5, 5, |_| 0., |_, _| 0.).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_keyword_score() {
+        let terms = keyword_terms("Garbage in, garbage OUT");
+        assert_eq!(terms, ["garbage", "in", "out"]);
+        assert_eq!(keyword_score("src/garbage_collector.rs", &terms), 1. / 3.);
+        assert_eq!(keyword_score("docs/garbage-in.md", &terms), 2. / 3.);
+        assert_eq!(keyword_score("README.md", &[]), 0.);
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use gpui::BackgroundExecutor;
+use parking_lot::Mutex;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{Embedding, EmbeddingModelInfo, EmbeddingProvider, ScoreCalibration, TextToEmbed};
+
+/// The window that rate limits are counted over.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// How many times a rate limited request is retried before failing its files.
+const MAX_RETRIES: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How fast an embedding provider accepts requests. Limits that are `None` aren't
+/// enforced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+/// The error embedding providers return when a request was rejected for exceeding
+/// their rate limits, so that it's retried later instead of failing its files.
+#[derive(Debug)]
+pub struct RateLimited {
+    /// How long the provider asked to wait before retrying, if it did.
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "embedding provider rate limit exceeded")
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// The requests sent within the last [`RATE_LIMIT_WINDOW`], along with how many
+/// tokens each sent.
+struct RequestWindow {
+    limits: RateLimits,
+    requests: VecDeque<(Instant, u64)>,
+}
+
+impl RequestWindow {
+    fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            requests: VecDeque::new(),
+        }
+    }
+
+    /// Records a request sending the given number of tokens if it fits within the
+    /// limits at `now`, or returns how long to wait until it would. A request with
+    /// more tokens than the limit is sent once no other request is in the window.
+    fn try_acquire(&mut self, now: Instant, token_count: u64) -> Result<(), Duration> {
+        while let Some((sent_at, _)) = self.requests.front() {
+            if now.saturating_duration_since(*sent_at) < RATE_LIMIT_WINDOW {
+                break;
+            }
+            self.requests.pop_front();
+        }
+
+        let limits = self.limits;
+        let fits = |request_count: u64, window_token_count: u64| {
+            limits
+                .requests_per_minute
+                .map_or(true, |limit| request_count < limit.max(1) as u64)
+                && limits.tokens_per_minute.map_or(true, |limit| {
+                    window_token_count == 0 || window_token_count + token_count <= limit as u64
+                })
+        };
+
+        // Find how many of the oldest requests must leave the window for this one to fit.
+        let mut request_count = self.requests.len() as u64;
+        let mut window_token_count = self.requests.iter().map(|(_, tokens)| tokens).sum();
+        let mut wait_until = None;
+        for (sent_at, tokens) in &self.requests {
+            if fits(request_count, window_token_count) {
+                break;
+            }
+            request_count -= 1;
+            window_token_count -= tokens;
+            wait_until = Some(*sent_at + RATE_LIMIT_WINDOW);
+        }
+
+        if let Some(wait_until) = wait_until {
+            Err(wait_until.saturating_duration_since(now))
+        } else {
+            self.requests.push_back((now, token_count));
+            Ok(())
+        }
+    }
+}
+
+/// How long to wait before the given retry of a request, doubling with every retry.
+/// The delay is jittered by a random fraction between 0 and 1, so that concurrent
+/// requests don't all retry at once.
+fn backoff(attempt: u32, jitter: f64) -> Duration {
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_BACKOFF);
+    backoff.mul_f64(0.5 + jitter / 2.)
+}
+
+/// Wraps an embedding provider to keep its requests within its rate limits, and to
+/// retry the requests it rejects anyway with exponential backoff.
+pub(crate) struct RateLimitedEmbeddingProvider {
+    provider: Arc<dyn EmbeddingProvider>,
+    window: Mutex<RequestWindow>,
+    executor: BackgroundExecutor,
+}
+
+impl RateLimitedEmbeddingProvider {
+    pub fn new(
+        provider: Arc<dyn EmbeddingProvider>,
+        limits: RateLimits,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        Self {
+            provider,
+            window: Mutex::new(RequestWindow::new(limits)),
+            executor,
+        }
+    }
+}
+
+impl RateLimitedEmbeddingProvider {
+    /// Sends a request once it fits in the rate limits, retrying it with backoff for as
+    /// long as the provider says it's rate limited.
+    async fn send<'a>(
+        &'a self,
+        texts: &'a [TextToEmbed<'a>],
+        request: impl Fn() -> BoxFuture<'a, Result<Vec<Embedding>>>,
+    ) -> Result<Vec<Embedding>> {
+        let token_count = texts
+            .iter()
+            .map(|to_embed| self.provider.count_tokens(to_embed.text))
+            .sum();
+        let mut attempt = 0;
+        loop {
+            loop {
+                let acquired = self
+                    .window
+                    .lock()
+                    .try_acquire(self.executor.now(), token_count);
+                match acquired {
+                    Ok(()) => break,
+                    Err(delay) => self.executor.timer(delay).await,
+                }
+            }
+
+            let error = match request().await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(error) => error,
+            };
+            let Some(rate_limited) = error.downcast_ref::<RateLimited>() else {
+                return Err(error);
+            };
+            if attempt == MAX_RETRIES {
+                return Err(error);
+            }
+            let delay = rate_limited
+                .retry_after
+                .unwrap_or_else(|| backoff(attempt, rand::thread_rng().gen()));
+            log::warn!("embedding request was rate limited, retrying in {delay:?}");
+            self.executor.timer(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl EmbeddingProvider for RateLimitedEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.send(texts, || self.provider.embed(texts)).boxed()
+    }
+
+    fn embed_query<'a>(
+        &'a self,
+        queries: &'a [TextToEmbed<'a>],
+    ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        self.send(queries, || self.provider.embed_query(queries))
+            .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.provider.batch_size()
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        self.provider.model_info()
+    }
+
+    fn score_calibration(&self) -> ScoreCalibration {
+        self.provider.score_calibration()
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        self.window.lock().limits
+    }
+
+    fn max_input_tokens(&self) -> Option<u64> {
+        self.provider.max_input_tokens()
+    }
+
+    fn count_tokens(&self, text: &str) -> u64 {
+        self.provider.count_tokens(text)
+    }
+
+    fn price_per_million_tokens(&self) -> Option<f64> {
+        self.provider.price_per_million_tokens()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_window() {
+        let start = Instant::now();
+        let mut window = RequestWindow::new(RateLimits {
+            requests_per_minute: Some(3),
+            tokens_per_minute: Some(100),
+        });
+
+        assert_eq!(window.try_acquire(start, 40), Ok(()));
+        let second = start + Duration::from_secs(10);
+        assert_eq!(window.try_acquire(second, 40), Ok(()));
+        // Too many tokens, until the first request leaves the window.
+        let third = start + Duration::from_secs(20);
+        assert_eq!(window.try_acquire(third, 40), Err(Duration::from_secs(40)));
+        assert_eq!(window.try_acquire(third, 20), Ok(()));
+        // Too many requests, even though the tokens fit.
+        assert_eq!(window.try_acquire(third, 0), Err(Duration::from_secs(40)));
+        assert_eq!(window.try_acquire(start + RATE_LIMIT_WINDOW, 0), Ok(()));
+        // Requests over the token limit wait for every other request to leave the window.
+        assert_eq!(
+            window.try_acquire(start + RATE_LIMIT_WINDOW, 500),
+            Err(Duration::from_secs(20))
+        );
+        assert_eq!(
+            window.try_acquire(start + RATE_LIMIT_WINDOW * 2, 500),
+            Ok(())
+        );
+
+        let mut unlimited = RequestWindow::new(RateLimits::default());
+        for _ in 0..1000 {
+            assert_eq!(unlimited.try_acquire(start, 1_000_000), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0, 1.), Duration::from_secs(1));
+        assert_eq!(backoff(3, 1.), Duration::from_secs(8));
+        assert_eq!(backoff(3, 0.), Duration::from_secs(4));
+        assert_eq!(backoff(10, 1.), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX, 0.), MAX_BACKOFF / 2);
+    }
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+/// Trait for second-stage rerankers. A reranker scores how relevant a text is to a
+/// query by reading both together, like a cross-encoder model or a language model
+/// does, which ranks more accurately than comparing embeddings but is too slow to run
+/// on more than the best few candidates of a search.
+pub trait Reranker: Sync + Send {
+    /// Scores every text by its relevance to the query, between 0 and 1, returning
+    /// the scores in the order of the texts.
+    fn rerank<'a>(
+        &'a self,
+        query: &'a str,
+        texts: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Vec<f32>>>;
+    /// The most texts to score in a single call.
+    fn batch_size(&self) -> usize;
+}
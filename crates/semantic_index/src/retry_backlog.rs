@@ -0,0 +1,139 @@
+use anyhow::Result;
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Bound,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// A file that couldn't be embedded, like when the embedding provider was unreachable.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FailedFile {
+    pub path: Arc<Path>,
+    /// How many times in a row the file failed to embed.
+    pub failure_count: u32,
+    pub failed_at: SystemTime,
+}
+
+impl FailedFile {
+    /// When to retry embedding the file, waiting twice as long after every failure.
+    pub fn retry_at(&self) -> SystemTime {
+        let delay = INITIAL_RETRY_DELAY
+            .saturating_mul(1 << self.failure_count.saturating_sub(1).min(16))
+            .min(MAX_RETRY_DELAY);
+        self.failed_at + delay
+    }
+}
+
+/// The files of a worktree that couldn't be embedded. They stay out of the index
+/// until they're retried successfully.
+#[derive(Clone, Copy)]
+pub(crate) struct RetryBacklog {
+    db: heed::Database<Str, SerdeBincode<FailedFile>>,
+}
+
+impl RetryBacklog {
+    pub fn create(env: &heed::Env, txn: &mut heed::RwTxn, name: &str) -> Result<Self> {
+        let db = env.create_database(txn, Some(&format!("{name}:retry_backlog")))?;
+        Ok(Self { db })
+    }
+
+    /// Records that a file failed to embed, returning how many times in a row it has.
+    pub fn insert_failure(
+        &self,
+        txn: &mut heed::RwTxn,
+        db_key: &str,
+        path: &Arc<Path>,
+        failed_at: SystemTime,
+    ) -> Result<u32> {
+        let failure_count = self
+            .db
+            .get(txn, db_key)?
+            .map_or(0, |file| file.failure_count)
+            + 1;
+        let file = FailedFile {
+            path: path.clone(),
+            failure_count,
+            failed_at,
+        };
+        self.db.put(txn, db_key, &file)?;
+        Ok(failure_count)
+    }
+
+    /// Records that a file doesn't need to be retried, because it was embedded or
+    /// isn't indexed anymore.
+    pub fn remove(&self, txn: &mut heed::RwTxn, db_key: &str) -> Result<()> {
+        self.db.delete(txn, db_key)?;
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<()> {
+        self.db.delete_range(txn, range)?;
+        Ok(())
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.db.clear(txn)?;
+        Ok(())
+    }
+
+    pub fn all(&self, txn: &heed::RoTxn) -> Result<Vec<FailedFile>> {
+        self.db
+            .iter(txn)?
+            .map(|entry| Ok(entry?.1))
+            .collect::<Result<Vec<_>>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_backlog() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = unsafe {
+            heed::EnvOpenOptions::new()
+                .max_dbs(1)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let mut txn = db_connection.write_txn().unwrap();
+        let backlog = RetryBacklog::create(&db_connection, &mut txn, "worktree").unwrap();
+
+        let now = SystemTime::now();
+        let a: Arc<Path> = Path::new("a.rs").into();
+        let b: Arc<Path> = Path::new("b.rs").into();
+        assert_eq!(backlog.insert_failure(&mut txn, "a", &a, now).unwrap(), 1);
+        assert_eq!(backlog.insert_failure(&mut txn, "a", &a, now).unwrap(), 2);
+        assert_eq!(backlog.insert_failure(&mut txn, "b", &b, now).unwrap(), 1);
+        backlog.remove(&mut txn, "b").unwrap();
+
+        let failed_files = backlog.all(&txn).unwrap();
+        assert_eq!(
+            failed_files,
+            vec![FailedFile {
+                path: a.clone(),
+                failure_count: 2,
+                failed_at: now,
+            }]
+        );
+        assert_eq!(failed_files[0].retry_at(), now + INITIAL_RETRY_DELAY * 2);
+
+        let failed_often = FailedFile {
+            path: a,
+            failure_count: u32::MAX,
+            failed_at: now,
+        };
+        assert_eq!(failed_often.retry_at(), now + MAX_RETRY_DELAY);
+    }
+}
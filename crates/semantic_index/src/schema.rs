@@ -0,0 +1,262 @@
+use anyhow::Result;
+use heed::{
+    byteorder::BigEndian,
+    types::{Bytes, Str, U32},
+};
+
+const VERSION_KEY: &str = "version";
+/// The version of databases written before their format was versioned.
+const UNVERSIONED: u32 = 1;
+
+/// The format of the index of a worktree. Bump its version whenever anything in the
+/// index is serialized differently, and add the migration from the previous version,
+/// so that indices written by earlier versions of Zed are upgraded instead of failing
+/// to deserialize.
+pub(crate) const WORKTREE_SCHEMA: Schema = Schema {
+    version: 5,
+    migrations: &[
+        // The embedding model was stored under a key naming the format of embedded
+        // chunks, which changing re-embedded every file.
+        Migration::Upgrade(|env, txn, name| {
+            rename_key(
+                env,
+                txn,
+                &format!("{name}:embedding_model"),
+                "embedding_model:v3",
+                "embedding_model",
+            )
+        }),
+        // Chunks record how much they repeat of the previous chunk.
+        Migration::Reindex,
+        // Chunks record the notebook cell they're in.
+        Migration::Reindex,
+        // The chunking configuration records the tokens chunks are sized in, if any.
+        Migration::Upgrade(|env, txn, name| {
+            append_to_value(env, txn, &format!("{name}:chunking"), "chunk_config", &[0])
+        }),
+    ],
+};
+
+/// The format of a database, along with how to upgrade it from every earlier version.
+pub(crate) struct Schema {
+    pub version: u32,
+    /// The migration to each version from the one before it, starting with the
+    /// migration to version 2.
+    pub migrations: &'static [Migration],
+}
+
+/// How to bring a database from one version of its format to the next.
+pub(crate) enum Migration {
+    /// Rewrites the data of the database with the given name.
+    Upgrade(fn(&heed::Env, &mut heed::RwTxn, &str) -> Result<()>),
+    /// Drops the data, to be indexed again, for changes that can't be upgraded.
+    Reindex,
+}
+
+/// Brings the database with the given name to the current version of its format,
+/// recording the version in the database. Returns whether the data has to be dropped
+/// and indexed again, which is also the case for databases written by a newer version
+/// of Zed, whose format is unknown.
+pub(crate) fn migrate(
+    env: &heed::Env,
+    txn: &mut heed::RwTxn,
+    name: &str,
+    schema: &Schema,
+    is_empty: bool,
+) -> Result<bool> {
+    debug_assert_eq!(schema.migrations.len() as u32, schema.version - UNVERSIONED);
+    let versions =
+        env.create_database::<Str, U32<BigEndian>>(txn, Some(&format!("{name}:schema")))?;
+    let version = match versions.get(txn, VERSION_KEY)? {
+        Some(version) => version,
+        None if is_empty => schema.version,
+        None => UNVERSIONED,
+    };
+
+    let reindex = if version > schema.version {
+        log::info!(
+            "re-indexing {name} because it was written by a newer version of Zed, \
+            in version {version} of the format"
+        );
+        true
+    } else {
+        let migrations = &schema.migrations[(version - UNVERSIONED) as usize..];
+        if migrations
+            .iter()
+            .any(|migration| matches!(migration, Migration::Reindex))
+        {
+            log::info!("re-indexing {name} to upgrade it from version {version} of the format");
+            true
+        } else {
+            for migration in migrations {
+                if let Migration::Upgrade(upgrade) = migration {
+                    upgrade(env, txn, name)?;
+                }
+            }
+            if !migrations.is_empty() {
+                log::info!(
+                    "upgraded {name} from version {version} to version {} of the format",
+                    schema.version
+                );
+            }
+            false
+        }
+    };
+    versions.put(txn, VERSION_KEY, &schema.version)?;
+    Ok(reindex)
+}
+
+/// Moves a value to another key of a database, if the database exists.
+fn rename_key(
+    env: &heed::Env,
+    txn: &mut heed::RwTxn,
+    db_name: &str,
+    old_key: &str,
+    new_key: &str,
+) -> Result<()> {
+    let Some(db) = env.open_database::<Str, Bytes>(txn, Some(db_name))? else {
+        return Ok(());
+    };
+    if let Some(value) = db.get(txn, old_key)?.map(<[u8]>::to_vec) {
+        db.put(txn, new_key, &value)?;
+        db.delete(txn, old_key)?;
+    }
+    Ok(())
+}
+
+/// Appends bytes to a value of a database, if the database and the value exist, like
+/// the tag of a `None` appended to a value serialized with a new optional field.
+fn append_to_value(
+    env: &heed::Env,
+    txn: &mut heed::RwTxn,
+    db_name: &str,
+    key: &str,
+    suffix: &[u8],
+) -> Result<()> {
+    let Some(db) = env.open_database::<Str, Bytes>(txn, Some(db_name))? else {
+        return Ok(());
+    };
+    if let Some(mut value) = db.get(txn, key)?.map(<[u8]>::to_vec) {
+        value.extend_from_slice(suffix);
+        db.put(txn, key, &value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunking::ChunkConfig,
+        db_connection::{DbConnection, TEST_MAP_SIZE, TEST_MAX_DBS},
+    };
+    use heed::types::SerdeBincode;
+
+    fn version(env: &heed::Env, txn: &heed::RoTxn, name: &str) -> Option<u32> {
+        env.open_database::<Str, U32<BigEndian>>(txn, Some(&format!("{name}:schema")))
+            .unwrap()?
+            .get(txn, VERSION_KEY)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_worktree_schema() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let env = DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let mut txn = env.write_txn().unwrap();
+
+        // New databases start at the current version, without migrating.
+        assert!(!migrate(&env, &mut txn, "new", &WORKTREE_SCHEMA, true).unwrap());
+        assert_eq!(version(&env, &txn, "new"), Some(WORKTREE_SCHEMA.version));
+
+        // Databases from before the format was versioned are upgraded to version 2.
+        let version_2 = Schema {
+            version: 2,
+            migrations: &WORKTREE_SCHEMA.migrations[..1],
+        };
+        let embedding_models = env
+            .create_database::<Str, Bytes>(&mut txn, Some("old:embedding_model"))
+            .unwrap();
+        embedding_models
+            .put(&mut txn, "embedding_model:v3", b"model")
+            .unwrap();
+        assert!(!migrate(&env, &mut txn, "old", &version_2, false).unwrap());
+        assert_eq!(version(&env, &txn, "old"), Some(2));
+        assert_eq!(
+            embedding_models.get(&txn, "embedding_model").unwrap(),
+            Some(&b"model"[..])
+        );
+        assert_eq!(
+            embedding_models.get(&txn, "embedding_model:v3").unwrap(),
+            None
+        );
+        // Migrating again does nothing.
+        assert!(!migrate(&env, &mut txn, "old", &version_2, false).unwrap());
+        assert_eq!(
+            embedding_models.get(&txn, "embedding_model").unwrap(),
+            Some(&b"model"[..])
+        );
+
+        // Chunks are serialized differently since version 3.
+        assert!(migrate(&env, &mut txn, "old", &WORKTREE_SCHEMA, false).unwrap());
+        assert_eq!(version(&env, &txn, "old"), Some(WORKTREE_SCHEMA.version));
+
+        // Chunking configurations serialized before chunks could be sized in tokens
+        // are upgraded to size them in bytes.
+        env.create_database::<Str, U32<BigEndian>>(&mut txn, Some("chunked:schema"))
+            .unwrap()
+            .put(&mut txn, VERSION_KEY, &4)
+            .unwrap();
+        env.create_database::<Str, SerdeBincode<(usize, usize)>>(
+            &mut txn,
+            Some("chunked:chunking"),
+        )
+        .unwrap()
+        .put(&mut txn, "chunk_config", &(4096, 512))
+        .unwrap();
+        assert!(!migrate(&env, &mut txn, "chunked", &WORKTREE_SCHEMA, false).unwrap());
+        let chunk_config = env
+            .open_database::<Str, SerdeBincode<ChunkConfig>>(&txn, Some("chunked:chunking"))
+            .unwrap()
+            .unwrap()
+            .get(&txn, "chunk_config")
+            .unwrap();
+        assert_eq!(
+            chunk_config,
+            Some(ChunkConfig {
+                max_size: 4096,
+                overlap: 512,
+                max_tokens: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_reindex_on_incompatible_schema() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let env = DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let mut txn = env.write_txn().unwrap();
+
+        let schema = Schema {
+            version: 6,
+            migrations: &[
+                Migration::Upgrade(|_, _, _| Ok(())),
+                Migration::Reindex,
+                Migration::Upgrade(|_, _, _| Ok(())),
+                Migration::Upgrade(|_, _, _| Ok(())),
+                Migration::Upgrade(|_, _, _| Ok(())),
+            ],
+        };
+        assert!(migrate(&env, &mut txn, "worktree", &schema, false).unwrap());
+        assert_eq!(version(&env, &txn, "worktree"), Some(6));
+        assert!(!migrate(&env, &mut txn, "worktree", &schema, false).unwrap());
+
+        // Databases written by a newer version of Zed are indexed again, in the older
+        // format.
+        assert!(migrate(&env, &mut txn, "worktree", &WORKTREE_SCHEMA, false).unwrap());
+        assert_eq!(
+            version(&env, &txn, "worktree"),
+            Some(WORKTREE_SCHEMA.version)
+        );
+    }
+}
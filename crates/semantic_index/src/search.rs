@@ -0,0 +1,1112 @@
+#[cfg(any(test, feature = "test-support"))]
+mod extensions;
+
+use crate::{
+    chunking::{chunk_text, ChunkConfig},
+    db_connection::DbConnection,
+    db_key_for_path, embedding,
+    embedding_cache::EmbeddingCache,
+    indexing::{EmbeddedChunk, FallbackEmbeddedFile},
+    quantization::FullPrecisionEmbeddings,
+    ranking::{keyword_score, maximal_marginal_relevance, penalize_score, RankingWeights},
+    search_filter::ResolvedSearchFilter,
+    ChunkingStrategyRegistry, Embedding, EmbeddingModelInfo, EmbeddingProvider,
+    EmbeddingQuantization, FileLoader, ProjectIndex, Reranker, ScoreCalibration, SearchFilter,
+    SemanticIndexSettings, TextToEmbed, WorktreeIndex, WorktreeIndexHandle,
+};
+use anyhow::{anyhow, Context as _, Result};
+use collections::{HashMap, HashSet};
+use futures::FutureExt;
+use gpui::{AppContext, Model, Task};
+use language::BufferSnapshot;
+use project::{Worktree, WorktreeId};
+use settings::Settings;
+use smol::channel;
+use std::{
+    cmp::Ordering,
+    future::Future,
+    ops::Range,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use util::ResultExt;
+
+#[cfg(any(test, feature = "test-support"))]
+pub use extensions::{ContextExcerpt, ProjectSearchResult};
+
+/// How many candidates are re-ranked against full-precision embeddings per requested
+/// result, when embeddings are binary quantized.
+const BINARY_RERANK_CANDIDATE_FACTOR: usize = 4;
+/// How many candidates maximal marginal relevance picks from per requested result.
+const MMR_CANDIDATE_FACTOR: usize = 4;
+/// How many candidates a [`Reranker`] re-scores per requested result.
+const RERANKER_CANDIDATE_FACTOR: usize = 5;
+/// How many more candidates are kept when results must contain some terms, since
+/// many of the most similar chunks may not contain any of them.
+const REQUIRED_TERMS_CANDIDATE_FACTOR: usize = 8;
+
+/// A snapshot of an open buffer with unsaved changes, searched in place of the
+/// stale chunks of its file in the index.
+struct DirtyBuffer {
+    worktree_id: WorktreeId,
+    path: Arc<Path>,
+    snapshot: BufferSnapshot,
+    /// How the index of the buffer's worktree chunks files.
+    chunk_config: ChunkConfig,
+}
+
+/// A result of [`ProjectIndex::search`] while it's being ranked.
+struct SearchCandidate {
+    result: WorktreeSearchResult,
+    /// Whether the score was estimated from a binary quantized embedding.
+    is_binary: bool,
+    /// The calibrated similarity of the chunk with the query, which the score is
+    /// `weight` times.
+    similarity: f32,
+    /// The factor the score was scaled by for the recency and location of the file.
+    weight: f32,
+    /// The embedding of the chunk, kept when results are diversified.
+    embedding: Option<Embedding>,
+}
+
+/// Loads the texts of the files of search candidates, starting from the given texts of
+/// dirty buffers. Files that fail to load are left out.
+async fn load_candidate_texts(
+    candidates: &[SearchCandidate],
+    mut file_texts: HashMap<(WorktreeId, Arc<Path>), Arc<str>>,
+    file_loaders: &HashMap<WorktreeId, (Arc<Path>, FileLoader)>,
+) -> HashMap<(WorktreeId, Arc<Path>), Arc<str>> {
+    let paths = candidates
+        .iter()
+        .map(|candidate| (candidate.result.worktree_id, candidate.result.path.clone()))
+        .filter(|key| !file_texts.contains_key(key))
+        .collect::<HashSet<_>>();
+    for (worktree_id, path) in paths {
+        let Some((worktree_abs_path, file_loader)) = file_loaders.get(&worktree_id) else {
+            continue;
+        };
+        if let Some(text) = file_loader
+            .load_text(worktree_id, worktree_abs_path, &path)
+            .await
+            .log_err()
+        {
+            file_texts.insert((worktree_id, path), text.into());
+        }
+    }
+    file_texts
+}
+
+/// Re-scores candidates by how relevant a reranker finds the text of their chunk,
+/// scaled by their weight. Candidates whose text isn't loaded are left out, and the
+/// candidates are left untouched if reranking fails.
+async fn rerank_candidates(
+    candidates: &mut Vec<SearchCandidate>,
+    query: &str,
+    reranker: &dyn Reranker,
+    file_texts: &HashMap<(WorktreeId, Arc<Path>), Arc<str>>,
+) -> Result<()> {
+    let texts = candidates
+        .iter()
+        .map(|candidate| {
+            let result = &candidate.result;
+            file_texts
+                .get(&(result.worktree_id, result.path.clone()))?
+                .get(result.range.clone())
+        })
+        .collect::<Vec<_>>();
+    let texts_to_rerank = texts.iter().flatten().copied().collect::<Vec<_>>();
+    let mut scores = Vec::with_capacity(texts_to_rerank.len());
+    for batch in texts_to_rerank.chunks(reranker.batch_size()) {
+        scores.extend(reranker.rerank(query, batch).await?);
+    }
+    if scores.len() != texts_to_rerank.len() {
+        return Err(anyhow!(
+            "reranker returned unexpected score count {}, expected {}",
+            scores.len(),
+            texts_to_rerank.len()
+        ));
+    }
+
+    let mut texts = texts.into_iter();
+    let mut scores = scores.into_iter();
+    candidates.retain_mut(|candidate| {
+        if texts.next().flatten().is_none() {
+            return false;
+        }
+        candidate.result.score = scores.next().unwrap_or(0.) * candidate.weight;
+        true
+    });
+    Ok(())
+}
+
+/// The embeddings of a search query and of its negative query, if any.
+#[derive(Clone)]
+struct QueryEmbeddings {
+    query: Embedding,
+    negative: Option<Embedding>,
+    /// The model that embedded the query, which is only the model of the index
+    /// when the embedding provider didn't fail over.
+    model: EmbeddingModelInfo,
+    score_calibration: ScoreCalibration,
+}
+
+impl QueryEmbeddings {
+    /// Scores an embedded text against the query, penalized by its similarity to the
+    /// negative query.
+    fn score(
+        &self,
+        similarity: impl Fn(&Embedding) -> f32,
+        score_calibration: ScoreCalibration,
+    ) -> f32 {
+        let score = score_calibration.calibrate(similarity(&self.query));
+        let negative_score = self
+            .negative
+            .as_ref()
+            .map(|negative| score_calibration.calibrate(similarity(negative)));
+        penalize_score(score, negative_score)
+    }
+}
+
+/// Re-scores the candidates whose embeddings are binary quantized against their
+/// full-precision embeddings, since their quantized scores are only rough estimates.
+fn rerank_binary_candidates(
+    candidates: &mut [SearchCandidate],
+    rerank_sources: &HashMap<WorktreeId, (DbConnection, FullPrecisionEmbeddings)>,
+    query_embedding: &QueryEmbeddings,
+    score_calibration: ScoreCalibration,
+) -> Result<()> {
+    for (worktree_id, (db_connection, full_precision_embeddings)) in rerank_sources {
+        let mut candidates = candidates
+            .iter_mut()
+            .filter(|candidate| candidate.is_binary && candidate.result.worktree_id == *worktree_id)
+            .peekable();
+        if candidates.peek().is_none() {
+            continue;
+        }
+
+        let txn = db_connection
+            .read_txn()
+            .context("failed to create read transaction")?;
+        for SearchCandidate {
+            result,
+            similarity,
+            weight,
+            ..
+        } in candidates
+        {
+            let db_key = db_key_for_path(&result.path);
+            let similarity = |embedding: &Embedding| {
+                full_precision_embeddings.similarity(&txn, &db_key, &result.range, embedding)
+            };
+            if let Some(score) = similarity(&query_embedding.query)? {
+                let negative_score = match &query_embedding.negative {
+                    Some(negative) => similarity(negative)?
+                        .map(|negative_score| score_calibration.calibrate(negative_score)),
+                    None => None,
+                };
+                *similarity = penalize_score(score_calibration.calibrate(score), negative_score);
+                result.score = *similarity * *weight;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merges the results whose ranges overlap or touch within the same file into a single
+/// result spanning all of them, scored by the best of them. Consecutive chunks tend to
+/// rank highly together, and are more useful as one excerpt than as several.
+///
+/// The merged results are ordered by file and range, not by score.
+fn merge_adjacent_results(mut candidates: Vec<SearchCandidate>) -> Vec<SearchCandidate> {
+    candidates.sort_unstable_by(|a, b| {
+        let (a, b) = (&a.result, &b.result);
+        (a.worktree_id, &a.path, a.range.start).cmp(&(b.worktree_id, &b.path, b.range.start))
+    });
+    let mut merged_candidates = Vec::<SearchCandidate>::with_capacity(candidates.len());
+    for candidate in candidates {
+        if let Some(previous) = merged_candidates.last_mut() {
+            let (previous_result, result) = (&mut previous.result, &candidate.result);
+            if previous_result.worktree_id == result.worktree_id
+                && previous_result.path == result.path
+                && result.range.start <= previous_result.range.end
+            {
+                previous_result.range.end = previous_result.range.end.max(result.range.end);
+                if result.score > previous_result.score {
+                    previous_result.score = result.score;
+                    previous.is_binary = candidate.is_binary;
+                    previous.similarity = candidate.similarity;
+                    previous.embedding = candidate.embedding;
+                }
+                continue;
+            }
+        }
+        merged_candidates.push(candidate);
+    }
+    merged_candidates
+}
+
+pub struct SearchResult {
+    pub worktree: Model<Worktree>,
+    pub path: Arc<Path>,
+    pub range: Range<usize>,
+    /// The index of the cell the result is in, for results in Jupyter notebooks, whose
+    /// range is in the JSON of the cell's source.
+    pub cell: Option<u32>,
+    pub score: f32,
+    /// The factor [`ProjectIndex::search`] scaled the score by for the recency and
+    /// location of the file. The score is otherwise the similarity of the result with
+    /// the query, or its reranker score. It's 1 for other searches.
+    pub weight: f32,
+}
+
+pub struct WorktreeSearchResult {
+    pub worktree_id: WorktreeId,
+    pub path: Arc<Path>,
+    pub range: Range<usize>,
+    pub cell: Option<u32>,
+    pub score: f32,
+}
+
+impl ProjectIndex {
+    /// Returns snapshots of the open buffers with unsaved changes that belong to an
+    /// indexed worktree and match the filter.
+    fn dirty_buffers(&self, filter: &ResolvedSearchFilter, cx: &AppContext) -> Vec<DirtyBuffer> {
+        let Some(project) = self.project.upgrade() else {
+            return Vec::new();
+        };
+        let project = project.read(cx);
+        project
+            .opened_buffers(cx)
+            .into_iter()
+            .filter_map(|buffer| {
+                let buffer = buffer.read(cx);
+                if !buffer.is_dirty() {
+                    return None;
+                }
+                let file = buffer.file()?;
+                let worktree_id = file.worktree_id(cx);
+                let worktree = project.worktree_for_id(worktree_id, cx)?;
+                let WorktreeIndexHandle::Loaded { index } =
+                    self.worktree_indices.get(&worktree.entity_id())?
+                else {
+                    return None;
+                };
+                let index = index.read(cx);
+                if !index.index_filter().matches(file.path(), None)
+                    || !filter.matches(file.path(), Some(SystemTime::now()))
+                {
+                    return None;
+                }
+                Some(DirtyBuffer {
+                    worktree_id,
+                    path: file.path().clone(),
+                    snapshot: buffer.snapshot(),
+                    chunk_config: index.chunk_config,
+                })
+            })
+            .collect()
+    }
+
+    /// Chunks and embeds the current contents of dirty buffers, sending their chunks
+    /// along with the ones read from the index. Their chunks have no modification
+    /// time, since they're newer than the file. Resolves to the paths of the buffers
+    /// whose chunks were sent, which replace the chunks stored for them.
+    ///
+    /// Buffers that fail to embed, or that are embedded by another model than the
+    /// query, are left to their stored chunks.
+    fn embed_dirty_buffers(
+        dirty_buffers: Vec<DirtyBuffer>,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        query_model: impl Future<Output = Option<EmbeddingModelInfo>> + Send + 'static,
+        chunks_tx: channel::Sender<(WorktreeId, Arc<Path>, Option<SystemTime>, EmbeddedChunk)>,
+        cx: &AppContext,
+    ) -> Task<Arc<HashSet<(WorktreeId, Arc<Path>)>>> {
+        let chunking_strategies = ChunkingStrategyRegistry::global(cx);
+        cx.background_executor().spawn(async move {
+            let mut embedded_buffers = Vec::new();
+            for buffer in dirty_buffers {
+                let text = buffer.snapshot.text();
+                let language = buffer.snapshot.language();
+                let strategy =
+                    language.and_then(|language| chunking_strategies.strategy(&language.name()));
+                let chunks = chunk_text(
+                    &text,
+                    language,
+                    &buffer.path,
+                    buffer.chunk_config,
+                    strategy.as_deref(),
+                    &|text| embedding_provider.count_tokens(text),
+                );
+                let chunk_texts = chunks
+                    .iter()
+                    .map(|chunk| chunk.text_to_embed(&text))
+                    .collect::<Vec<_>>();
+                let texts = chunks
+                    .iter()
+                    .zip(&chunk_texts)
+                    .map(|(chunk, chunk_text)| TextToEmbed {
+                        text: chunk_text,
+                        digest: chunk.digest,
+                    })
+                    .collect::<Vec<_>>();
+                match Self::embed_dirty_chunks(
+                    &texts,
+                    embedding_provider.as_ref(),
+                    &embedding_cache,
+                )
+                .await
+                {
+                    Ok(Some((buffer_model, embeddings))) => {
+                        embedded_buffers.push((buffer, buffer_model, chunks, embeddings));
+                    }
+                    Ok(None) => {}
+                    Err(error) => {
+                        log::error!(
+                            "failed to embed unsaved changes to {:?}: {error:?}",
+                            buffer.path
+                        );
+                    }
+                }
+            }
+
+            let mut embedded_paths = HashSet::default();
+            let Some(query_model) = query_model.await else {
+                return Arc::new(embedded_paths);
+            };
+            for (buffer, buffer_model, chunks, embeddings) in embedded_buffers {
+                if buffer_model != query_model {
+                    continue;
+                }
+                for (chunk, embedding) in chunks.into_iter().zip(embeddings) {
+                    let chunk = EmbeddedChunk {
+                        chunk,
+                        embedding: embedding.into(),
+                    };
+                    if chunks_tx
+                        .send((buffer.worktree_id, buffer.path.clone(), None, chunk))
+                        .await
+                        .is_err()
+                    {
+                        return Arc::new(embedded_paths);
+                    }
+                }
+                embedded_paths.insert((buffer.worktree_id, buffer.path));
+            }
+            Arc::new(embedded_paths)
+        })
+    }
+
+    /// Embeds the chunks of a dirty buffer, reusing the cached embeddings of the ones
+    /// whose content was embedded before, and caching the others. Returns `None` when
+    /// the provider fails over to another model partway, since the chunks' embeddings
+    /// can't be compared with each other then.
+    async fn embed_dirty_chunks(
+        texts: &[TextToEmbed<'_>],
+        embedding_provider: &dyn EmbeddingProvider,
+        embedding_cache: &EmbeddingCache,
+    ) -> Result<Option<(EmbeddingModelInfo, Vec<Embedding>)>> {
+        let model = embedding_provider.model_info();
+        let digests = texts.iter().map(|text| text.digest).collect::<Vec<_>>();
+        let mut embeddings = embedding_cache
+            .get(&digests)
+            .log_err()
+            .unwrap_or_else(|| vec![None; texts.len()]);
+        // Cached embeddings are the current model's.
+        let mut buffer_model = embeddings
+            .iter()
+            .any(Option::is_some)
+            .then(|| model.clone());
+        let (uncached_ixs, uncached_texts): (Vec<usize>, Vec<TextToEmbed>) = texts
+            .iter()
+            .enumerate()
+            .filter(|(ix, _)| embeddings[*ix].is_none())
+            .map(|(ix, text)| {
+                (
+                    ix,
+                    TextToEmbed {
+                        text: text.text,
+                        digest: text.digest,
+                    },
+                )
+            })
+            .unzip();
+
+        let mut uncached_ixs = uncached_ixs.into_iter();
+        for batch in embedding::batches(embedding_provider, &uncached_texts) {
+            let (batch_model, results) = embedding_provider.embed_with_model(batch).await;
+            // The provider may fail over between batches.
+            if *buffer_model.get_or_insert_with(|| batch_model.clone()) != batch_model {
+                return Ok(None);
+            }
+            let mut new_embeddings = Vec::new();
+            for ((ix, text), result) in uncached_ixs.by_ref().zip(batch).zip(results) {
+                let embedding = batch_model.validate(result?)?;
+                // Embeddings from a fallback model aren't cached, since they can't be
+                // reused for the index.
+                if batch_model == model {
+                    new_embeddings.push((text.digest, embedding.clone()));
+                }
+                embeddings[ix] = Some(embedding);
+            }
+            if !new_embeddings.is_empty() {
+                embedding_cache.insert(&new_embeddings).log_err();
+            }
+        }
+
+        let embeddings = embeddings
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("embedding provider returned too few embeddings"))?;
+        Ok(Some((buffer_model.unwrap_or(model), embeddings)))
+    }
+
+    /// Searches semantically for the chunks of the files matching the filter that are
+    /// most similar to the query. Scores are calibrated for the embedding provider, so
+    /// that results scoring below [`RELEVANT_SCORE`] are unlikely to be relevant.
+    /// Chunks whose calibrated similarity with the query is below `min_score` are
+    /// omitted, before their scores are weighted and adjacent ones are merged.
+    ///
+    /// When `mmr_lambda` is given, results are diversified with maximal marginal
+    /// relevance, trading relevance for novelty: 1 selects results by relevance only,
+    /// and lower values increasingly avoid results similar to those already selected.
+    ///
+    /// Open buffers with unsaved changes are re-chunked and re-embedded on the fly, so
+    /// that their results reflect, and their ranges point into, their current contents.
+    /// Their stored chunks are searched instead when that fails.
+    pub fn search(
+        &self,
+        query: String,
+        filter: SearchFilter,
+        limit: usize,
+        min_score: Option<f32>,
+        mmr_lambda: Option<f32>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let negative_query = filter.negative_query.clone();
+        let required_terms = filter
+            .required_terms
+            .iter()
+            .map(|term| term.to_lowercase())
+            .collect::<Vec<_>>();
+        let filter = Arc::new(filter.resolve(&self.language_registry));
+        // When the filter narrows the search down to some directories, scanning them is
+        // exact and usually faster than probing clusters for matching files.
+        let directory_prefixes = filter.directory_prefixes();
+        let dirty_buffers = self.dirty_buffers(&filter, cx);
+        // Chunks of dirty buffers are checked for the required terms and reranked by
+        // their unsaved contents.
+        let reranker = self.reranker.clone();
+        let dirty_texts = if required_terms.is_empty() && reranker.is_none() {
+            HashMap::default()
+        } else {
+            dirty_buffers
+                .iter()
+                .map(|buffer| {
+                    (
+                        (buffer.worktree_id, buffer.path.clone()),
+                        Arc::<str>::from(buffer.snapshot.text()),
+                    )
+                })
+                .collect()
+        };
+        let embedding_provider = self.embedding_provider.clone();
+        let query_embedding = cx
+            .background_executor()
+            .spawn({
+                let query = query.clone();
+                async move {
+                    let mut texts = vec![TextToEmbed::new(&query)];
+                    if let Some(negative_query) = &negative_query {
+                        texts.push(TextToEmbed::new(negative_query));
+                    }
+                    let (model, score_calibration, query_embeddings) =
+                        embedding_provider.embed_query_with_model(&texts).await?;
+                    let mut query_embeddings = query_embeddings.into_iter();
+                    let query = query_embeddings
+                        .next()
+                        .ok_or_else(|| anyhow!("no embedding for query"))?;
+                    let negative = if negative_query.is_some() {
+                        Some(
+                            query_embeddings
+                                .next()
+                                .ok_or_else(|| anyhow!("no embedding for negative query"))?,
+                        )
+                    } else {
+                        None
+                    };
+                    anyhow::Ok(QueryEmbeddings {
+                        query,
+                        negative,
+                        model,
+                        score_calibration,
+                    })
+                }
+            })
+            .map(|result| result.map_err(Arc::new))
+            .shared();
+        let settings = SemanticIndexSettings::get_global(cx);
+        let approximate_search = settings.approximate_search && directory_prefixes.is_none();
+        let recency_half_life =
+            Duration::try_from_secs_f32(settings.recency_half_life_days.max(0.) * 24. * 60. * 60.)
+                .unwrap_or_default();
+        let ranking_weights = RankingWeights::new(
+            settings.recency_weight,
+            recency_half_life,
+            &settings.path_weights,
+            SystemTime::now(),
+        );
+        let db_key_prefixes: Arc<[String]> = match directory_prefixes {
+            Some(prefixes) => prefixes
+                .iter()
+                .map(|prefix| prefix.replace('/', "\0"))
+                .collect(),
+            None => Arc::new([String::new()]),
+        };
+        // A reranker re-scores more results than are returned, since it may rank some
+        // of them far differently than their embeddings do.
+        let retrieval_limit = if reranker.is_some() {
+            limit * RERANKER_CANDIDATE_FACTOR
+        } else {
+            limit
+        };
+        let mut candidate_limit = if mmr_lambda.is_some() {
+            retrieval_limit * MMR_CANDIDATE_FACTOR
+        } else if settings.quantization == EmbeddingQuantization::Binary {
+            retrieval_limit * BINARY_RERANK_CANDIDATE_FACTOR
+        } else {
+            retrieval_limit
+        };
+        if !required_terms.is_empty() {
+            candidate_limit *= REQUIRED_TERMS_CANDIDATE_FACTOR;
+        }
+
+        let (chunks_tx, chunks_rx) = channel::bounded(1024);
+        // The stored chunks of dirty buffers are only left out once their current
+        // contents have been embedded.
+        let dirty_paths = Self::embed_dirty_buffers(
+            dirty_buffers,
+            self.embedding_provider.clone(),
+            self.embedding_cache.clone(),
+            query_embedding
+                .clone()
+                .map(|query_embedding| query_embedding.ok().map(|query| query.model)),
+            chunks_tx.clone(),
+            cx,
+        )
+        .shared();
+        let mut worktree_scan_tasks = Vec::new();
+        for worktree_index in self.worktree_indices.values() {
+            let worktree_index = worktree_index.clone();
+            let chunks_tx = chunks_tx.clone();
+            let query_embedding = query_embedding.clone();
+            let filter = filter.clone();
+            let db_key_prefixes = db_key_prefixes.clone();
+            let dirty_paths = dirty_paths.clone();
+            worktree_scan_tasks.push(cx.spawn(|cx| async move {
+                let index = match worktree_index {
+                    WorktreeIndexHandle::Loading { index } => {
+                        index.clone().await.map_err(|error| anyhow!(error))?
+                    }
+                    WorktreeIndexHandle::Loaded { index } => index.clone(),
+                };
+
+                index
+                    .read_with(&cx, |index, cx| {
+                        let worktree_id = index.worktree.read(cx).id();
+                        let db_connection = index.db_connection.clone();
+                        let db = index.db;
+                        let fallback_db = index.fallback_db;
+                        let index_model = index.embedding_model();
+                        let vector_index = index.vector_index;
+                        let full_precision_embeddings = index.full_precision_embeddings;
+                        let rerank_source = (
+                            worktree_id,
+                            db_connection.clone(),
+                            full_precision_embeddings,
+                        );
+                        cx.background_executor().spawn(async move {
+                            let query_embedding =
+                                query_embedding.await.map_err(|error| anyhow!(error))?;
+                            let dirty_paths = dirty_paths.await;
+                            let txn = db_connection
+                                .read_txn()
+                                .context("failed to create read transaction")?;
+
+                            // Files embedded by a fallback provider can only be compared
+                            // with a query embedded by the same provider.
+                            for db_key_prefix in db_key_prefixes.iter() {
+                                let fallback_entries = fallback_db
+                                    .prefix_iter(&txn, db_key_prefix)
+                                    .context("failed to iterate database")?;
+                                for fallback_entry in fallback_entries {
+                                    let (_key, FallbackEmbeddedFile { model, file }) =
+                                        fallback_entry?;
+                                    if model != query_embedding.model
+                                        || !filter.matches(&file.path, file.mtime)
+                                        || dirty_paths.contains(&(worktree_id, file.path.clone()))
+                                    {
+                                        continue;
+                                    }
+                                    for chunk in file.chunks {
+                                        chunks_tx
+                                            .send((
+                                                worktree_id,
+                                                file.path.clone(),
+                                                file.mtime,
+                                                chunk,
+                                            ))
+                                            .await?;
+                                    }
+                                }
+                            }
+                            if query_embedding.model != index_model {
+                                return Ok(rerank_source);
+                            }
+
+                            if approximate_search {
+                                if let Some(entries) = vector_index
+                                    .approximate_neighbors(&txn, &query_embedding.query)?
+                                {
+                                    for entry in entries {
+                                        if !filter.matches(&entry.path, entry.mtime)
+                                            || dirty_paths
+                                                .contains(&(worktree_id, entry.path.clone()))
+                                        {
+                                            continue;
+                                        }
+                                        for chunk in entry.chunks {
+                                            chunks_tx
+                                                .send((
+                                                    worktree_id,
+                                                    entry.path.clone(),
+                                                    entry.mtime,
+                                                    chunk,
+                                                ))
+                                                .await?;
+                                        }
+                                    }
+                                    return Ok(rerank_source);
+                                }
+                            }
+
+                            for db_key_prefix in db_key_prefixes.iter() {
+                                let db_entries = db
+                                    .prefix_iter(&txn, db_key_prefix)
+                                    .context("failed to iterate database")?;
+                                for db_entry in db_entries {
+                                    let (_key, db_embedded_file) = db_entry?;
+                                    if !filter
+                                        .matches(&db_embedded_file.path, db_embedded_file.mtime)
+                                        || dirty_paths
+                                            .contains(&(worktree_id, db_embedded_file.path.clone()))
+                                    {
+                                        continue;
+                                    }
+                                    for chunk in db_embedded_file.chunks {
+                                        chunks_tx
+                                            .send((
+                                                worktree_id,
+                                                db_embedded_file.path.clone(),
+                                                db_embedded_file.mtime,
+                                                chunk,
+                                            ))
+                                            .await?;
+                                    }
+                                }
+                            }
+                            anyhow::Ok(rerank_source)
+                        })
+                    })?
+                    .await
+            }));
+        }
+        drop(chunks_tx);
+
+        let project = self.project.clone();
+        let file_loaders = self.file_loaders(cx);
+        cx.spawn(|cx| async move {
+            #[cfg(debug_assertions)]
+            let embedding_query_start = std::time::Instant::now();
+            log::info!("Searching for {query}");
+
+            let query_embedding = query_embedding.await.map_err(|error| anyhow!(error))?;
+            let score_calibration = query_embedding.score_calibration;
+
+            let mut results_by_worker = Vec::new();
+            for _ in 0..cx.background_executor().num_cpus() {
+                results_by_worker.push(Vec::<SearchCandidate>::new());
+            }
+
+            #[cfg(debug_assertions)]
+            let search_start = std::time::Instant::now();
+
+            cx.background_executor()
+                .scoped(|cx| {
+                    for results in results_by_worker.iter_mut() {
+                        cx.spawn(async {
+                            let mut mismatched_chunk_count = 0;
+                            while let Ok((worktree_id, path, mtime, chunk)) = chunks_rx.recv().await
+                            {
+                                if filter.excludes_chunk(worktree_id, &path, &chunk.chunk.range) {
+                                    continue;
+                                }
+                                // Embeddings with other dimensions than the query's can't be
+                                // compared with it.
+                                if chunk.embedding.dimensions() != query_embedding.query.len() {
+                                    mismatched_chunk_count += 1;
+                                    continue;
+                                }
+                                let similarity = query_embedding.score(
+                                    |embedding| chunk.embedding.similarity(embedding),
+                                    score_calibration,
+                                );
+                                // Binary embeddings only estimate the similarity, so
+                                // they're checked once it's computed exactly.
+                                if !chunk.embedding.is_binary()
+                                    && min_score.map_or(false, |min_score| similarity < min_score)
+                                {
+                                    continue;
+                                }
+                                let weight = ranking_weights.weight(&path, mtime);
+                                let score = similarity * weight;
+                                let ix = match results.binary_search_by(|probe| {
+                                    score
+                                        .partial_cmp(&probe.result.score)
+                                        .unwrap_or(Ordering::Equal)
+                                }) {
+                                    Ok(ix) | Err(ix) => ix,
+                                };
+                                if ix >= candidate_limit {
+                                    continue;
+                                }
+                                results.insert(
+                                    ix,
+                                    SearchCandidate {
+                                        result: WorktreeSearchResult {
+                                            worktree_id,
+                                            path: path.clone(),
+                                            range: chunk.chunk.range.clone(),
+                                            cell: chunk.chunk.cell,
+                                            score,
+                                        },
+                                        is_binary: chunk.embedding.is_binary(),
+                                        similarity,
+                                        weight,
+                                        embedding: mmr_lambda.map(|_| chunk.embedding.dequantize()),
+                                    },
+                                );
+                                results.truncate(candidate_limit);
+                            }
+                            if mismatched_chunk_count > 0 {
+                                log::warn!(
+                                    "skipped {mismatched_chunk_count} chunks embedded with other \
+                                    dimensions than the query's {}",
+                                    query_embedding.query.len()
+                                );
+                            }
+                        });
+                    }
+                })
+                .await;
+
+            let mut rerank_sources = HashMap::default();
+            for scan_task in futures::future::join_all(worktree_scan_tasks).await {
+                if let Some((worktree_id, db_connection, full_precision_embeddings)) =
+                    scan_task.log_err()
+                {
+                    rerank_sources.insert(worktree_id, (db_connection, full_precision_embeddings));
+                }
+            }
+            // The results of dirty buffers that are left to their stored chunks point
+            // into their saved contents.
+            let dirty_paths = dirty_paths.await;
+            let mut file_texts = dirty_texts;
+            file_texts.retain(|path, _| dirty_paths.contains(path));
+
+            let mut candidates = results_by_worker.into_iter().flatten().collect::<Vec<_>>();
+            if !required_terms.is_empty() {
+                file_texts = load_candidate_texts(&candidates, file_texts, &file_loaders).await;
+                candidates.retain(|candidate| {
+                    let result = &candidate.result;
+                    file_texts
+                        .get(&(result.worktree_id, result.path.clone()))
+                        .and_then(|text| text.get(result.range.clone()))
+                        .map_or(false, |text| keyword_score(text, &required_terms) > 0.)
+                });
+            }
+
+            let mut results = cx
+                .background_executor()
+                .spawn(async move {
+                    rerank_binary_candidates(
+                        &mut candidates,
+                        &rerank_sources,
+                        &query_embedding,
+                        score_calibration,
+                    )?;
+                    if let Some(min_score) = min_score {
+                        candidates.retain(|candidate| candidate.similarity >= min_score);
+                    }
+                    let mut candidates = merge_adjacent_results(candidates);
+                    if let Some(mmr_lambda) = mmr_lambda {
+                        candidates = maximal_marginal_relevance(
+                            candidates,
+                            mmr_lambda,
+                            retrieval_limit,
+                            |candidate| candidate.result.score,
+                            |a, b| match (&a.embedding, &b.embedding) {
+                                (Some(a), Some(b)) => score_calibration.calibrate(a.similarity(b)),
+                                _ => 0.,
+                            },
+                        );
+                    }
+                    anyhow::Ok(candidates)
+                })
+                .await?;
+
+            if let Some(reranker) = reranker {
+                results.sort_unstable_by(|a, b| {
+                    b.result
+                        .score
+                        .partial_cmp(&a.result.score)
+                        .unwrap_or(Ordering::Equal)
+                });
+                results.truncate(retrieval_limit);
+                file_texts = load_candidate_texts(&results, file_texts, &file_loaders).await;
+                rerank_candidates(&mut results, &query, reranker.as_ref(), &file_texts)
+                    .await
+                    .log_err();
+            }
+
+            project.read_with(&cx, |project, cx| {
+                let mut search_results = results
+                    .into_iter()
+                    .filter_map(|SearchCandidate { result, weight, .. }| {
+                        Some(SearchResult {
+                            worktree: project.worktree_for_id(result.worktree_id, cx)?,
+                            path: result.path,
+                            range: result.range,
+                            cell: result.cell,
+                            score: result.score,
+                            weight,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                search_results.sort_unstable_by(|a, b| {
+                    b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+                });
+                search_results.truncate(limit);
+
+                #[cfg(debug_assertions)]
+                {
+                    let search_elapsed = search_start.elapsed();
+                    log::debug!(
+                        "searched {} entries in {:?}",
+                        search_results.len(),
+                        search_elapsed
+                    );
+                    let embedding_query_elapsed = embedding_query_start.elapsed();
+                    log::debug!("embedding query took {:?}", embedding_query_elapsed);
+                }
+
+                search_results
+            })
+        })
+    }
+
+    /// Ranks chunks by how relevant the words they contain are to the query, using
+    /// BM25. Scores aren't normalized, and are only meaningful relative to each other.
+    pub fn keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let query: Arc<str> = query.into();
+        self.search_worktree_indices(limit, cx, |index, cx| {
+            index.keyword_search(query.clone(), limit, cx)
+        })
+    }
+
+    /// Runs a search on every loaded worktree index, returning the best `limit` results
+    /// across all of them.
+    fn search_worktree_indices(
+        &self,
+        limit: usize,
+        cx: &AppContext,
+        search: impl Fn(
+            &WorktreeIndex,
+            &AppContext,
+        ) -> Task<Result<Vec<(Arc<Path>, Range<usize>, Option<u32>, f32)>>>,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let worktree_searches = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let index = index.read(cx);
+                let worktree = index.worktree.clone();
+                let matches = search(index, cx);
+                async move {
+                    anyhow::Ok(
+                        matches
+                            .await?
+                            .into_iter()
+                            .map(|(path, range, cell, score)| SearchResult {
+                                worktree: worktree.clone(),
+                                path,
+                                range,
+                                cell,
+                                score,
+                                weight: 1.,
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+
+        cx.background_executor().spawn(async move {
+            let mut results = Vec::new();
+            for worktree_results in futures::future::try_join_all(worktree_searches).await? {
+                results.extend(worktree_results);
+            }
+            results.sort_by(|a, b| {
+                b.score
+                    .partial_cmp(&a.score)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| a.path.as_os_str().len().cmp(&b.path.as_os_str().len()))
+            });
+            results.truncate(limit);
+            anyhow::Ok(results)
+        })
+    }
+}
+
+impl WorktreeIndex {
+    fn keyword_search(
+        &self,
+        query: Arc<str>,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<(Arc<Path>, Range<usize>, Option<u32>, f32)>>> {
+        let connection = self.db_connection.clone();
+        let keyword_index = self.keyword_index;
+        cx.background_executor().spawn(async move {
+            let txn = connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            keyword_index.search(&txn, &query, limit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{self, BoxFuture};
+
+    #[test]
+    fn test_merge_adjacent_results() {
+        let result =
+            |worktree_id: usize, path: &str, range: Range<usize>, score: f32| SearchCandidate {
+                result: WorktreeSearchResult {
+                    worktree_id: WorktreeId::from_usize(worktree_id),
+                    path: Path::new(path).into(),
+                    range,
+                    cell: None,
+                    score,
+                },
+                is_binary: false,
+                similarity: score,
+                weight: 1.,
+                embedding: None,
+            };
+        let merged = merge_adjacent_results(vec![
+            result(1, "a.rs", 20..30, 0.7),
+            result(1, "b.rs", 10..20, 0.6),
+            result(1, "a.rs", 10..20, 0.8),
+            result(1, "a.rs", 25..40, 0.5),
+            result(1, "a.rs", 50..60, 0.9),
+            result(1, "b.rs", 20..30, 0.65),
+            result(2, "a.rs", 10..20, 0.4),
+        ]);
+        assert_eq!(
+            merged
+                .iter()
+                .map(|SearchCandidate { result, .. }| (
+                    result.worktree_id.to_usize(),
+                    result.path.to_string_lossy().into_owned(),
+                    result.range.clone(),
+                    result.score
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "a.rs".into(), 10..40, 0.8),
+                (1, "a.rs".into(), 50..60, 0.9),
+                (1, "b.rs".into(), 10..30, 0.65),
+                (2, "a.rs".into(), 10..20, 0.4),
+            ]
+        );
+    }
+
+    struct TestReranker;
+
+    impl Reranker for TestReranker {
+        fn rerank<'a>(
+            &'a self,
+            query: &'a str,
+            texts: &'a [&'a str],
+        ) -> BoxFuture<'a, Result<Vec<f32>>> {
+            let scores = texts
+                .iter()
+                .map(|text| if text.contains(query) { 0.9 } else { 0.1 })
+                .collect();
+            future::ready(Ok(scores)).boxed()
+        }
+
+        fn batch_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[gpui::test]
+    async fn test_rerank_candidates() {
+        let worktree_id = WorktreeId::from_usize(1);
+        let candidate =
+            |path: &str, range: Range<usize>, score: f32, weight: f32| SearchCandidate {
+                result: WorktreeSearchResult {
+                    worktree_id,
+                    path: Path::new(path).into(),
+                    range,
+                    cell: None,
+                    score,
+                },
+                is_binary: false,
+                similarity: score / weight,
+                weight,
+                embedding: None,
+            };
+        let file_texts = HashMap::from_iter([(
+            (worktree_id, Arc::<Path>::from(Path::new("a.rs"))),
+            Arc::<str>::from("fn parse() {}\nfn render() {}\nfn parse_all() {}\n"),
+        )]);
+        let mut candidates = vec![
+            candidate("a.rs", 14..29, 0.9, 1.),
+            candidate("b.rs", 0..10, 0.8, 1.),
+            candidate("a.rs", 0..14, 0.7, 1.),
+            candidate("a.rs", 29..47, 0.6, 0.5),
+        ];
+        rerank_candidates(&mut candidates, "parse", &TestReranker, &file_texts)
+            .await
+            .unwrap();
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|SearchCandidate { result, .. }| (result.range.clone(), result.score))
+                .collect::<Vec<_>>(),
+            vec![(14..29, 0.1), (0..14, 0.9), (29..47, 0.45)]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Searches built on [`ProjectIndex::search`] that nothing in Zed calls yet. They're
+//! only compiled for tests and with the `test-support` feature until they have callers.
+
+use crate::{
+    context_budget::{expand_to_lines, fit_to_budget, truncate_lines_to_budget},
+    ranking::{keyword_score, keyword_terms, reciprocal_rank_fusion},
+    ProjectIndex, SearchFilter, SearchResult, SemanticIndex, WorktreeIndex, UNRELATED_SCORE,
+};
+use anyhow::{Context as _, Result};
+use collections::HashMap;
+use gpui::{AppContext, Model, Task};
+use project::{Project, Worktree};
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use util::ResultExt;
+
+/// How many candidates each search of [`ProjectIndex::hybrid_search`] contributes per
+/// requested result.
+const HYBRID_SEARCH_CANDIDATE_FACTOR: usize = 4;
+/// How many results [`ProjectIndex::retrieve_context`] fits within its token budget.
+const CONTEXT_CANDIDATE_LIMIT: usize = 32;
+
+/// An excerpt of a file retrieved by [`ProjectIndex::retrieve_context`]. It displays
+/// as a fenced code block, preceded by its path and lines.
+#[derive(Clone)]
+pub struct ContextExcerpt {
+    pub worktree: Model<Worktree>,
+    /// The path of the file, starting with the name of its worktree.
+    pub full_path: PathBuf,
+    /// The path of the file, relative to its worktree.
+    pub path: Arc<Path>,
+    pub range: Range<usize>,
+    /// The zero-based rows spanned by the excerpt, excluding the end.
+    pub rows: Range<u32>,
+    pub text: String,
+    pub score: f32,
+}
+
+impl fmt::Display for ContextExcerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}:{}-{}",
+            self.full_path.display(),
+            self.rows.start + 1,
+            self.rows.end
+        )?;
+        writeln!(f, "```")?;
+        write!(f, "{}", self.text)?;
+        if !self.text.ends_with('\n') {
+            writeln!(f)?;
+        }
+        writeln!(f, "```")
+    }
+}
+
+/// A result of [`SemanticIndex::search_all`], along with the project it was found in.
+pub struct ProjectSearchResult {
+    pub project: Model<Project>,
+    pub result: SearchResult,
+}
+
+impl SemanticIndex {
+    /// Searches every project that has an index, ranking their results together.
+    pub fn search_all(
+        &self,
+        query: String,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<ProjectSearchResult>>> {
+        let project_searches = self
+            .project_indices
+            .iter()
+            .filter_map(|(project, project_index)| {
+                let project = project.upgrade()?;
+                let search = project_index.read(cx).search(
+                    query.clone(),
+                    SearchFilter::default(),
+                    limit,
+                    None,
+                    None,
+                    cx,
+                );
+                Some(async move {
+                    anyhow::Ok(
+                        search
+                            .await?
+                            .into_iter()
+                            .map(|result| ProjectSearchResult {
+                                project: project.clone(),
+                                result,
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        cx.background_executor().spawn(async move {
+            let mut results = Vec::new();
+            for project_results in futures::future::join_all(project_searches).await {
+                // A project failing to search shouldn't hide the results of the others.
+                if let Some(project_results) = project_results.log_err() {
+                    results.extend(project_results);
+                }
+            }
+            results.sort_by(|a, b| {
+                b.result
+                    .score
+                    .partial_cmp(&a.result.score)
+                    .unwrap_or(Ordering::Equal)
+            });
+            results.truncate(limit);
+            Ok(results)
+        })
+    }
+}
+
+impl ProjectIndex {
+    /// Retrieves the excerpts of the project most relevant to the query, to insert into
+    /// a prompt. Excerpts are expanded to whole lines, and as many of them as fit within
+    /// the token budget are returned, most relevant first, counting the tokens of their
+    /// attribution too. Excerpts that don't fit are left out whole, unless none fit, in
+    /// which case the most relevant one is truncated.
+    pub fn retrieve_context(
+        &self,
+        query: String,
+        token_budget: usize,
+        count_tokens: Arc<dyn Fn(&str) -> usize + Send + Sync>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<ContextExcerpt>>> {
+        let search = self.search(
+            query,
+            SearchFilter::default(),
+            CONTEXT_CANDIDATE_LIMIT,
+            Some(UNRELATED_SCORE),
+            None,
+            cx,
+        );
+        // Search results in dirty buffers point into their unsaved contents.
+        let unfiltered = SearchFilter::default().resolve(&self.language_registry);
+        let mut file_texts = self
+            .dirty_buffers(&unfiltered, cx)
+            .into_iter()
+            .map(|buffer| {
+                (
+                    (buffer.worktree_id, buffer.path),
+                    Arc::<str>::from(buffer.snapshot.text()),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let file_loaders = self.file_loaders(cx);
+        cx.spawn(|cx| async move {
+            let mut excerpts = Vec::new();
+            for result in search.await? {
+                let (worktree_id, full_path) = result.worktree.read_with(&cx, |worktree, _| {
+                    (
+                        worktree.id(),
+                        Path::new(worktree.root_name()).join(&result.path),
+                    )
+                })?;
+                let key = (worktree_id, result.path.clone());
+                let text = if let Some(text) = file_texts.get(&key) {
+                    text.clone()
+                } else {
+                    let Some((worktree_abs_path, file_loader)) = file_loaders.get(&worktree_id)
+                    else {
+                        continue;
+                    };
+                    let Some(text) = file_loader
+                        .load_text(worktree_id, worktree_abs_path, &result.path)
+                        .await
+                        .log_err()
+                    else {
+                        continue;
+                    };
+                    file_texts.entry(key).or_insert(text.into()).clone()
+                };
+                let (range, rows) = expand_to_lines(&text, result.range);
+                excerpts.push(ContextExcerpt {
+                    worktree: result.worktree,
+                    full_path,
+                    path: result.path,
+                    text: text[range.clone()].to_string(),
+                    range,
+                    rows,
+                    score: result.score,
+                });
+            }
+
+            let excerpt_tokens = |excerpt: &ContextExcerpt| count_tokens(&excerpt.to_string());
+            let fitting_excerpts = fit_to_budget(excerpts.clone(), token_budget, excerpt_tokens);
+            if !fitting_excerpts.is_empty() {
+                return Ok(fitting_excerpts);
+            }
+            let Some(mut excerpt) = excerpts.into_iter().next() else {
+                return Ok(Vec::new());
+            };
+            let text = std::mem::take(&mut excerpt.text);
+            let attribution_tokens = excerpt_tokens(&excerpt);
+            let len = truncate_lines_to_budget(
+                &text,
+                token_budget.saturating_sub(attribution_tokens),
+                count_tokens.as_ref(),
+            );
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            excerpt.text = text[..len].to_string();
+            excerpt.range.end = excerpt.range.start + len;
+            excerpt.rows.end = excerpt.rows.start + excerpt.text.lines().count() as u32;
+            Ok(vec![excerpt])
+        })
+    }
+
+    /// Searches semantically, by keywords, and for the words of the query in file paths,
+    /// fusing the rankings of the three searches. This finds exact identifiers that are
+    /// missed by semantic search alone. Scores are reciprocal rank fusion scores, which
+    /// are only meaningful relative to each other.
+    pub fn hybrid_search(
+        &self,
+        query: String,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let candidate_count = limit * HYBRID_SEARCH_CANDIDATE_FACTOR;
+        let path_search = self.path_keyword_search(&query, candidate_count, cx);
+        let keyword_search = self.keyword_search(&query, candidate_count, cx);
+        let semantic_search = self.search(
+            query,
+            SearchFilter::default(),
+            candidate_count,
+            None,
+            None,
+            cx,
+        );
+        cx.background_executor().spawn(async move {
+            let (semantic_results, keyword_results, path_results) =
+                futures::try_join!(semantic_search, keyword_search, path_search)?;
+            Ok(reciprocal_rank_fusion(
+                [semantic_results, keyword_results, path_results],
+                |result| {
+                    (
+                        result.worktree.entity_id(),
+                        result.path.clone(),
+                        result.range.start,
+                        result.range.end,
+                    )
+                },
+                limit,
+            )
+            .into_iter()
+            .map(|(result, score)| SearchResult {
+                score,
+                weight: 1.,
+                ..result
+            })
+            .collect())
+        })
+    }
+
+    /// Ranks indexed files by how many of the words of the query their paths contain,
+    /// returning the first chunk of each.
+    fn path_keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let terms: Arc<[String]> = keyword_terms(query).into();
+        self.search_worktree_indices(limit, cx, |index, cx| {
+            index.path_keyword_matches(terms.clone(), cx)
+        })
+    }
+}
+
+impl WorktreeIndex {
+    /// Returns the paths containing any of the terms, along with the range and cell of
+    /// their first chunk and the [`keyword_score`] of the path.
+    fn path_keyword_matches(
+        &self,
+        terms: Arc<[String]>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<(Arc<Path>, Range<usize>, Option<u32>, f32)>>> {
+        let connection = self.db_connection.clone();
+        let db = self.db;
+        cx.background_executor().spawn(async move {
+            let txn = connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let mut matches = Vec::new();
+            for entry in db.iter(&txn)? {
+                let (_key, embedded_file) = entry?;
+                let score = keyword_score(&embedded_file.path.to_string_lossy(), &terms);
+                if score > 0. {
+                    if let Some(chunk) = embedded_file.chunks.first() {
+                        matches.push((
+                            embedded_file.path.clone(),
+                            chunk.chunk.range.clone(),
+                            chunk.chunk.cell,
+                            score,
+                        ));
+                    }
+                }
+            }
+            Ok(matches)
+        })
+    }
+}
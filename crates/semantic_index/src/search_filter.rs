@@ -0,0 +1,225 @@
+use language::{LanguageName, LanguageRegistry};
+use project::{ProjectPath, WorktreeId};
+use std::{ops::Range, path::Path, sync::Arc, time::SystemTime};
+use util::paths::{PathExt, PathMatcher};
+
+/// Restricts a search to some of the indexed files.
+#[derive(Clone, Debug, Default)]
+pub struct SearchFilter {
+    /// Only search files matching one of these globs, or in a directory that does.
+    /// Every file is searched when empty.
+    pub include_globs: PathMatcher,
+    /// Never search files matching one of these globs, or in a directory that does.
+    pub exclude_globs: PathMatcher,
+    /// Only search files in one of these languages. Files in any language are searched
+    /// when empty.
+    pub languages: Vec<LanguageName>,
+    /// Only search files modified after this time.
+    pub modified_after: Option<SystemTime>,
+    /// Never return chunks overlapping this range of a file.
+    pub excluded_range: Option<(ProjectPath, Range<usize>)>,
+    /// Only return chunks containing at least one of these terms, ignoring case. Any
+    /// chunk may be returned when empty.
+    pub required_terms: Vec<String>,
+    /// Rank chunks similar to this query lower, like "tests" when searching for
+    /// "authentication middleware" but not its tests.
+    pub negative_query: Option<String>,
+}
+
+impl SearchFilter {
+    /// Resolves the languages of the filter to the path suffixes of their files, so
+    /// that paths can be checked against it without loading any language.
+    pub(crate) fn resolve(self, language_registry: &Arc<LanguageRegistry>) -> ResolvedSearchFilter {
+        let language_path_suffixes = if self.languages.is_empty() {
+            None
+        } else {
+            Some(
+                self.languages
+                    .iter()
+                    .filter_map(|name| language_registry.available_language_for_name(name))
+                    .flat_map(|language| language.matcher().path_suffixes.clone())
+                    .collect(),
+            )
+        };
+        ResolvedSearchFilter {
+            include_globs: self.include_globs,
+            exclude_globs: self.exclude_globs,
+            language_path_suffixes,
+            modified_after: self.modified_after,
+            excluded_range: self.excluded_range,
+        }
+    }
+}
+
+pub(crate) struct ResolvedSearchFilter {
+    include_globs: PathMatcher,
+    exclude_globs: PathMatcher,
+    language_path_suffixes: Option<Vec<String>>,
+    modified_after: Option<SystemTime>,
+    excluded_range: Option<(ProjectPath, Range<usize>)>,
+}
+
+impl ResolvedSearchFilter {
+    pub fn matches(&self, path: &Path, mtime: Option<SystemTime>) -> bool {
+        if let Some(modified_after) = self.modified_after {
+            if mtime.map_or(true, |mtime| mtime <= modified_after) {
+                return false;
+            }
+        }
+
+        if let Some(path_suffixes) = &self.language_path_suffixes {
+            let candidates = [
+                path.extension_or_hidden_file_name(),
+                path.file_name().and_then(|name| name.to_str()),
+                path.to_str(),
+            ];
+            if !path_suffixes
+                .iter()
+                .any(|suffix| candidates.contains(&Some(suffix.as_str())))
+            {
+                return false;
+            }
+        }
+
+        let mut ancestors = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty());
+        if ancestors
+            .clone()
+            .any(|ancestor| self.exclude_globs.is_match(ancestor))
+        {
+            return false;
+        }
+        self.include_globs.sources().is_empty()
+            || ancestors.any(|ancestor| self.include_globs.is_match(ancestor))
+    }
+
+    pub fn excludes_chunk(
+        &self,
+        worktree_id: WorktreeId,
+        path: &Path,
+        range: &Range<usize>,
+    ) -> bool {
+        self.excluded_range
+            .as_ref()
+            .map_or(false, |(excluded_path, excluded_range)| {
+                excluded_path.worktree_id == worktree_id
+                    && excluded_path.path.as_ref() == path
+                    && range.start < excluded_range.end
+                    && excluded_range.start < range.end
+            })
+    }
+
+    /// Returns the directories that every included file is in, based on the literal
+    /// directories the include globs start with, or `None` if any file may be included.
+    /// Nested directories are omitted, so that no file is under more than one of them.
+    pub fn directory_prefixes(&self) -> Option<Vec<String>> {
+        let mut prefixes = Vec::new();
+        for glob in self.include_globs.sources() {
+            // Globs without special characters also match paths ending with them, so
+            // they can't be narrowed down to a directory.
+            let literal_len = glob.find(['*', '?', '[', '{', '\\'])?;
+            let directory_len = glob[..literal_len].rfind('/')? + 1;
+            prefixes.push(glob[..directory_len].to_string());
+        }
+        if prefixes.is_empty() {
+            return None;
+        }
+
+        prefixes.sort();
+        let mut directory_prefixes = Vec::<String>::new();
+        for prefix in prefixes {
+            if !directory_prefixes
+                .last()
+                .map_or(false, |last| prefix.starts_with(last.as_str()))
+            {
+                directory_prefixes.push(prefix);
+            }
+        }
+        Some(directory_prefixes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> ResolvedSearchFilter {
+        let globs = |globs: &[&str]| {
+            PathMatcher::new(
+                &globs
+                    .iter()
+                    .map(|glob| glob.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()
+        };
+        ResolvedSearchFilter {
+            include_globs: globs(include),
+            exclude_globs: globs(exclude),
+            language_path_suffixes: None,
+            modified_after: None,
+            excluded_range: None,
+        }
+    }
+
+    #[test]
+    fn test_search_filter_matches() {
+        let path = Path::new("crates/editor/src/editor.rs");
+        assert!(filter(&[], &[]).matches(path, None));
+        assert!(filter(&["crates/editor/**"], &[]).matches(path, None));
+        assert!(filter(&["crates/editor"], &[]).matches(path, None));
+        assert!(!filter(&["crates/project/**"], &[]).matches(path, None));
+        assert!(!filter(&["crates/**"], &["**/src"]).matches(path, None));
+
+        let mut rust_files = filter(&[], &[]);
+        rust_files.language_path_suffixes = Some(vec!["rs".into()]);
+        assert!(rust_files.matches(path, None));
+        assert!(!rust_files.matches(Path::new("README.md"), None));
+
+        let now = SystemTime::now();
+        let mut recent_files = filter(&[], &[]);
+        recent_files.modified_after = Some(now - Duration::from_secs(60));
+        assert!(recent_files.matches(path, Some(now)));
+        assert!(!recent_files.matches(path, Some(now - Duration::from_secs(120))));
+        assert!(!recent_files.matches(path, None));
+    }
+
+    #[test]
+    fn test_search_filter_excludes_chunk() {
+        let worktree_id = WorktreeId::from_usize(1);
+        let path = Path::new("src/main.rs");
+        assert!(!filter(&[], &[]).excludes_chunk(worktree_id, path, &(0..10)));
+
+        let mut selection_filter = filter(&[], &[]);
+        selection_filter.excluded_range = Some((
+            ProjectPath {
+                worktree_id,
+                path: path.into(),
+            },
+            10..20,
+        ));
+        assert!(selection_filter.excludes_chunk(worktree_id, path, &(15..30)));
+        assert!(selection_filter.excludes_chunk(worktree_id, path, &(0..11)));
+        assert!(!selection_filter.excludes_chunk(worktree_id, path, &(0..10)));
+        assert!(!selection_filter.excludes_chunk(worktree_id, path, &(20..30)));
+        assert!(!selection_filter.excludes_chunk(WorktreeId::from_usize(2), path, &(10..20)));
+        assert!(!selection_filter.excludes_chunk(worktree_id, Path::new("src/lib.rs"), &(10..20)));
+    }
+
+    #[test]
+    fn test_search_filter_directory_prefixes() {
+        assert_eq!(filter(&[], &[]).directory_prefixes(), None);
+        assert_eq!(filter(&["crates/editor"], &[]).directory_prefixes(), None);
+        assert_eq!(filter(&["**/*.rs"], &[]).directory_prefixes(), None);
+        assert_eq!(
+            filter(
+                &["crates/editor/**", "crates/*/src/**", "docs/*.md"],
+                &["x/**"]
+            )
+            .directory_prefixes(),
+            Some(vec!["crates/".to_string(), "docs/".to_string()])
+        );
+    }
+}
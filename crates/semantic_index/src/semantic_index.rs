@@ -1,43 +1,130 @@
 mod chunking;
+mod chunking_strategy;
+mod compaction;
+#[cfg(any(test, feature = "test-support"))]
+mod context_budget;
+mod db_connection;
+mod disk_cache;
 mod embedding;
+mod embedding_cache;
+mod encryption;
+mod failover;
+mod index_archive;
+mod index_diagnostics;
+mod index_estimate;
+mod index_progress;
+mod index_verification;
+mod indexing;
+mod indexing_profiler;
+mod keyword_index;
+mod kv_store;
+mod notebook;
 mod project_index_debug_view;
+#[cfg(test)]
+mod provider_fixtures;
+mod provider_selection;
+mod quantization;
+mod ranking;
+mod rate_limit;
+mod reranker;
+mod retry_backlog;
+mod schema;
+mod search;
+mod search_filter;
+mod semantic_index_settings;
+mod shared_embeddings;
+mod skipped_files;
+mod structured_data;
+mod throttle;
+mod usage;
+mod vector_index;
+mod workspace_actions;
 
 use anyhow::{anyhow, Context as _, Result};
-use chunking::{chunk_text, Chunk};
+use chunking::{chunk_text, text_digest, ChunkConfig};
 use collections::{Bound, HashMap, HashSet};
+use db_connection::{DbConnection, IndexDatabases};
 pub use embedding::*;
+use embedding_cache::EmbeddingCache;
+use encryption::{EncryptedDatabase, IndexCipher, KEY_LEN};
 use fs::Fs;
-use futures::{future::Shared, stream::StreamExt, FutureExt};
-use futures_batch::ChunksTimeoutStreamExt;
+use futures::{channel::oneshot, future::Shared, stream::StreamExt, FutureExt};
 use gpui::{
     AppContext, AsyncAppContext, BorrowAppContext, Context, Entity, EntityId, EventEmitter, Global,
     Model, ModelContext, Subscription, Task, WeakModel,
 };
 use heed::types::{SerdeBincode, Str};
+use index_archive::{ArchivedChunk, ArchivedFile, ArchivedWorktree, IndexArchive};
+use index_diagnostics::{IndexDiagnostics, WorktreeDiagnostics};
+use index_progress::{IndexCheckpoint, IndexProgress};
+use indexing::{indexed_entry, EmbeddedFile, FallbackEmbeddedFile, IndexRequest, IndexingEntrySet};
+use keyword_index::KeywordIndex;
 use language::LanguageRegistry;
-use parking_lot::Mutex;
-use project::{Entry, Project, ProjectEntryId, UpdatedEntriesSet, Worktree, WorktreeId};
+use project::{Project, ProjectPath, Worktree, WorktreeId};
+use quantization::FullPrecisionEmbeddings;
+use retry_backlog::RetryBacklog;
+use schema::WORKTREE_SCHEMA;
+use search_filter::ResolvedSearchFilter;
 use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsLocation, SettingsStore};
+use skipped_files::{file_text, FileLimits, SkippedFiles};
 use smol::channel;
 use std::{
-    cmp::Ordering,
-    future::Future,
-    iter,
+    io::Read,
     num::NonZeroUsize,
-    ops::Range,
     path::{Path, PathBuf},
-    sync::{Arc, Weak},
-    time::{Duration, SystemTime},
+    sync::Arc,
+    time::Duration,
 };
-use util::ResultExt;
-use worktree::Snapshot;
-
+use throttle::{ResourceMonitor, ThrottlePolicy, THROTTLE_CHECK_INTERVAL};
+use usage::{merge_daily_usage, UsageLog};
+use util::{paths::PathMatcher, ResultExt};
+use vector_index::VectorIndex;
+use workspace::Workspace;
+
+pub use chunking_strategy::{ChunkingStrategy, ChunkingStrategyRegistry};
+pub use compaction::{Compaction, CompactionProgress};
+pub use disk_cache::{DiskCachedEmbeddingProvider, DiskEmbeddingCache};
+pub use index_estimate::IndexEstimate;
+pub use index_verification::IndexVerification;
+pub use kv_store::StorageBackend;
 pub use project_index_debug_view::ProjectIndexDebugView;
+pub use quantization::EmbeddingQuantization;
+pub use rate_limit::{RateLimited, RateLimits};
+pub use reranker::Reranker;
+#[cfg(any(test, feature = "test-support"))]
+pub use search::{ContextExcerpt, ProjectSearchResult};
+pub use search::{SearchResult, WorktreeSearchResult};
+pub use search_filter::SearchFilter;
+pub use semantic_index_settings::SemanticIndexSettings;
+pub use shared_embeddings::{
+    HttpEmbeddingStore, SharedEmbeddingStore, SharedEmbeddingStoreSettings,
+};
+pub use skipped_files::SkipReason;
+pub use throttle::ThrottleReason;
+pub use usage::{DailyUsage, EmbeddingUsage};
+pub use workspace_actions::{
+    CompactIndex, EstimateIndex, ExportIndex, ExportIndexDiagnostics, ImportIndex, PauseIndexing,
+    ReembedWithCurrentModel, RepairIndex, ResumeIndexing, ShowEmbeddingUsage,
+};
+
+pub fn init(cx: &mut AppContext) {
+    SemanticIndexSettings::register(cx);
+    ChunkingStrategyRegistry::default_global(cx);
+    cx.observe_new_views(|workspace: &mut Workspace, _| workspace_actions::register(workspace))
+        .detach();
+}
 
 pub struct SemanticIndex {
     embedding_provider: Arc<dyn EmbeddingProvider>,
-    db_connection: heed::Env,
+    embedding_cache: Arc<EmbeddingCache>,
+    disk_cache: Option<Arc<DiskEmbeddingCache>>,
+    storage_backend: StorageBackend,
+    reranker: Option<Arc<dyn Reranker>>,
+    databases: IndexDatabases,
     project_indices: HashMap<WeakModel<Project>, Model<ProjectIndex>>,
+    throttle_reason: Option<ThrottleReason>,
+    _monitor_resources: Task<()>,
 }
 
 impl Global for SemanticIndex {}
@@ -48,27 +135,119 @@ impl SemanticIndex {
         embedding_provider: Arc<dyn EmbeddingProvider>,
         cx: &mut AsyncAppContext,
     ) -> Result<Self> {
-        let db_connection = cx
+        let (map_size, storage_backend, database_path, embedding_cache_path) = cx.update(|cx| {
+            let settings = SemanticIndexSettings::get_global(cx);
+            (
+                settings.initial_database_size,
+                settings.storage_backend,
+                // Relative paths only apply to the worktrees of projects.
+                settings
+                    .database_path
+                    .as_deref()
+                    .and_then(|path| resolve_database_path(path, None)),
+                settings
+                    .embedding_cache_path
+                    .as_deref()
+                    .and_then(|path| resolve_database_path(path, None)),
+            )
+        })?;
+        let databases = cx
             .background_executor()
-            .spawn(async move {
-                std::fs::create_dir_all(&db_path)?;
-                unsafe {
-                    heed::EnvOpenOptions::new()
-                        .map_size(1024 * 1024 * 1024)
-                        .max_dbs(3000)
-                        .open(db_path)
-                }
-            })
+            .spawn(
+                async move { IndexDatabases::open(&db_path, database_path.as_deref(), map_size) },
+            )
             .await
             .context("opening database connection")?;
+        let disk_cache = match embedding_cache_path {
+            Some(path) => Some(Arc::new(
+                cx.background_executor()
+                    .spawn(async move { DiskEmbeddingCache::open(path) })
+                    .await
+                    .context("opening embedding cache file")?,
+            )),
+            None => None,
+        };
+        let embedding_provider = cx.update(|cx| {
+            Self::wrap_embedding_provider(embedding_provider, disk_cache.as_ref(), cx)
+        })?;
+        let embedding_cache = cx
+            .background_executor()
+            .spawn(Self::open_embedding_cache(
+                databases.shared().clone(),
+                storage_backend,
+                embedding_provider.model_info(),
+            ))
+            .await?;
+        cx.update(|cx| {
+            embedding_cache.set_shared(Self::shared_embeddings_from_settings(
+                embedding_provider.model_info(),
+                cx,
+            ))
+        })?;
 
         Ok(SemanticIndex {
-            db_connection,
+            databases,
             embedding_provider,
+            embedding_cache: Arc::new(embedding_cache),
+            disk_cache,
+            storage_backend,
+            reranker: None,
             project_indices: HashMap::default(),
+            throttle_reason: None,
+            _monitor_resources: cx.spawn(Self::monitor_resources),
         })
     }
 
+    /// Periodically checks whether indexing should yield to the rest of the system,
+    /// according to the settings, pausing or resuming every project's indexing.
+    async fn monitor_resources(mut cx: AsyncAppContext) {
+        let mut monitor = ResourceMonitor::new();
+        loop {
+            let Ok(policy) = cx
+                .update(|cx| ThrottlePolicy::from_settings(SemanticIndexSettings::get_global(cx)))
+            else {
+                break;
+            };
+            let throttle_reason;
+            (monitor, throttle_reason) = cx
+                .background_executor()
+                .spawn(async move {
+                    let throttle_reason = monitor.throttle_reason(policy);
+                    (monitor, throttle_reason)
+                })
+                .await;
+            let updated = cx.update(|cx| {
+                if cx.has_global::<SemanticIndex>() {
+                    cx.update_global::<SemanticIndex, _>(|this, cx| {
+                        this.set_throttle_reason(throttle_reason, cx);
+                    });
+                }
+            });
+            if updated.is_err() {
+                break;
+            }
+            cx.background_executor()
+                .timer(THROTTLE_CHECK_INTERVAL)
+                .await;
+        }
+    }
+
+    fn set_throttle_reason(&mut self, reason: Option<ThrottleReason>, cx: &mut AppContext) {
+        if self.throttle_reason == reason {
+            return;
+        }
+        match reason {
+            Some(reason) => log::info!("pausing indexing: {reason:?}"),
+            None => log::info!("resuming throttled indexing"),
+        }
+        self.throttle_reason = reason;
+        for project_index in self.project_indices.values() {
+            project_index.update(cx, |project_index, cx| {
+                project_index.set_throttle_reason(reason, cx);
+            });
+        }
+    }
+
     pub fn project_index(
         &mut self,
         project: Model<Project>,
@@ -90,29 +269,138 @@ impl SemanticIndex {
             .entry(project.downgrade())
             .or_insert_with(|| {
                 cx.new_model(|cx| {
-                    ProjectIndex::new(
+                    let mut project_index = ProjectIndex::new(
                         project,
-                        self.db_connection.clone(),
+                        self.databases.clone(),
                         self.embedding_provider.clone(),
+                        self.embedding_cache.clone(),
+                        self.reranker.clone(),
                         cx,
-                    )
+                    );
+                    project_index.set_throttle_reason(self.throttle_reason, cx);
+                    project_index
                 })
             })
             .clone()
     }
+
+    /// Writes the embeddings of a project's indexed worktrees to an archive at the given
+    /// path, so that they can be imported on another machine.
+    pub fn export(
+        &self,
+        project: &Model<Project>,
+        path: PathBuf,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let Some(project_index) = self.project_indices.get(&project.downgrade()) else {
+            return Task::ready(Err(anyhow!("project is not indexed")));
+        };
+        let worktrees = project_index.read(cx).archived_worktrees(cx);
+        let model = self.embedding_provider.model_info();
+        cx.background_executor().spawn(async move {
+            let archive = IndexArchive::new(model, worktrees.await?);
+            std::fs::write(&path, serde_json::to_vec(&archive)?)
+                .with_context(|| format!("writing index archive to {path:?}"))
+        })
+    }
+
+    /// Imports the embeddings of an archive written by [`Self::export`], so that chunks
+    /// with the same text aren't embedded again when indexing any project. The archive
+    /// must have been embedded with the current model. Returns how many embeddings
+    /// were imported.
+    pub fn import(&self, path: PathBuf, cx: &AppContext) -> Task<Result<usize>> {
+        let embedding_cache = self.embedding_cache.clone();
+        let model = self.embedding_provider.model_info();
+        cx.background_executor().spawn(async move {
+            let json = std::fs::read_to_string(&path)
+                .with_context(|| format!("reading index archive from {path:?}"))?;
+            let embeddings = IndexArchive::parse(&json, &model)?.into_embeddings();
+            embedding_cache.insert(&embeddings)?;
+            Ok(embeddings.len())
+        })
+    }
+
+    /// Removes stale files from the index of every project, then writes a compacted
+    /// copy of every database, reporting each stage to `progress`. LMDB never gives the
+    /// space of deleted data back, so the copies replace the databases when Zed restarts.
+    /// Indexing continues meanwhile, but what it writes after the copy is lost, and
+    /// indexed again from the files once the database is replaced.
+    pub fn compact(
+        &self,
+        progress: impl 'static + Fn(CompactionProgress) + Send,
+        cx: &mut AppContext,
+    ) -> Task<Result<Compaction>> {
+        progress(CompactionProgress::CollectingGarbage);
+        let collect_garbage = self
+            .project_indices
+            .values()
+            .map(|project_index| {
+                project_index.update(cx, |project_index, cx| project_index.collect_garbage(cx))
+            })
+            .collect::<Vec<_>>();
+        let databases = self.databases.clone();
+        cx.background_executor().spawn(async move {
+            let removed_file_count = futures::future::join_all(collect_garbage)
+                .await
+                .into_iter()
+                .filter_map(|removed_file_count| removed_file_count.log_err())
+                .sum();
+            progress(CompactionProgress::Copying { removed_file_count });
+            let (mut size_before, mut size_after) = (0, 0);
+            for db_connection in databases.all()? {
+                let (db_size_before, db_size_after) =
+                    compaction::write_compacted_copy(&db_connection)?;
+                size_before += db_size_before;
+                size_after += db_size_after;
+            }
+            let compaction = Compaction {
+                removed_file_count,
+                size_before,
+                size_after,
+            };
+            log::info!("compacted the semantic index: {compaction:?}");
+            progress(CompactionProgress::Finished(compaction));
+            Ok(compaction)
+        })
+    }
+
+    /// Returns how much the embedding provider was used to index a project, by day.
+    pub fn usage_stats(
+        &self,
+        project: &Model<Project>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<DailyUsage>>> {
+        let Some(project_index) = self.project_indices.get(&project.downgrade()) else {
+            return Task::ready(Err(anyhow!("project is not indexed")));
+        };
+        project_index.read(cx).usage_stats(cx)
+    }
 }
 
+/// How many files a worktree needs to have for what indexing it takes to be estimated
+/// before it's indexed for the first time.
+const LARGE_WORKTREE_FILE_COUNT: usize = 10_000;
+
 pub struct ProjectIndex {
-    db_connection: heed::Env,
+    databases: IndexDatabases,
     project: WeakModel<Project>,
     worktree_indices: HashMap<EntityId, WorktreeIndexHandle>,
     language_registry: Arc<LanguageRegistry>,
     fs: Arc<dyn Fs>,
     last_status: Status,
-    status_tx: channel::Sender<()>,
+    status_tx: channel::Sender<IndexingEvent>,
+    usage_tx: channel::Sender<EmbeddingUsage>,
     embedding_provider: Arc<dyn EmbeddingProvider>,
+    embedding_cache: Arc<EmbeddingCache>,
+    reranker: Option<Arc<dyn Reranker>>,
+    paused: bool,
+    throttle_reason: Option<ThrottleReason>,
+    /// The worktrees to embed again with the current model when they're next loaded,
+    /// even if they're pinned to another model.
+    worktrees_to_reembed: HashSet<EntityId>,
     _maintain_status: Task<()>,
-    _subscription: Subscription,
+    _report_usage: Task<()>,
+    _subscriptions: Vec<Subscription>,
 }
 
 #[derive(Clone)]
@@ -128,33 +416,52 @@ enum WorktreeIndexHandle {
 impl ProjectIndex {
     fn new(
         project: Model<Project>,
-        db_connection: heed::Env,
+        databases: IndexDatabases,
         embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        reranker: Option<Arc<dyn Reranker>>,
         cx: &mut ModelContext<Self>,
     ) -> Self {
         let language_registry = project.read(cx).languages().clone();
         let fs = project.read(cx).fs().clone();
         let (status_tx, mut status_rx) = channel::unbounded();
+        let (usage_tx, mut usage_rx) = channel::unbounded();
         let mut this = ProjectIndex {
-            db_connection,
+            databases,
             project: project.downgrade(),
             worktree_indices: HashMap::default(),
             language_registry,
             fs,
             status_tx,
+            usage_tx,
             last_status: Status::Idle,
             embedding_provider,
-            _subscription: cx.subscribe(&project, Self::handle_project_event),
+            embedding_cache,
+            reranker,
+            paused: false,
+            throttle_reason: None,
+            worktrees_to_reembed: HashSet::default(),
+            _subscriptions: vec![
+                cx.subscribe(&project, Self::handle_project_event),
+                cx.observe_global::<SettingsStore>(Self::update_worktree_indices),
+            ],
             _maintain_status: cx.spawn(|this, mut cx| async move {
-                while status_rx.next().await.is_some() {
+                while let Some(event) = status_rx.next().await {
                     if this
-                        .update(&mut cx, |this, cx| this.update_status(cx))
+                        .update(&mut cx, |this, cx| this.handle_indexing_event(event, cx))
                         .is_err()
                     {
                         break;
                     }
                 }
             }),
+            _report_usage: cx.spawn(|this, mut cx| async move {
+                while let Some(usage) = usage_rx.next().await {
+                    if this.update(&mut cx, |_, cx| cx.emit(usage)).is_err() {
+                        break;
+                    }
+                }
+            }),
         };
         this.update_worktree_indices(cx);
         this
@@ -164,6 +471,48 @@ impl ProjectIndex {
         self.last_status
     }
 
+    /// Stops indexing changes to the project's files until [`Self::resume`] is called.
+    /// Files that are being indexed when pausing still get indexed, and files that
+    /// change while paused are all indexed at once when resuming.
+    pub fn pause(&mut self, cx: &mut ModelContext<Self>) {
+        self.set_paused(true, cx);
+    }
+
+    pub fn resume(&mut self, cx: &mut ModelContext<Self>) {
+        self.set_paused(false, cx);
+    }
+
+    fn set_paused(&mut self, paused: bool, cx: &mut ModelContext<Self>) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        self.pause_worktree_indices(cx);
+    }
+
+    fn set_throttle_reason(&mut self, reason: Option<ThrottleReason>, cx: &mut ModelContext<Self>) {
+        if self.throttle_reason == reason {
+            return;
+        }
+        self.throttle_reason = reason;
+        self.pause_worktree_indices(cx);
+    }
+
+    /// Whether indexing is paused, either by the user or because it's throttled.
+    fn indexing_paused(&self) -> bool {
+        self.paused || self.throttle_reason.is_some()
+    }
+
+    fn pause_worktree_indices(&mut self, cx: &mut ModelContext<Self>) {
+        let paused = self.indexing_paused();
+        for worktree_index in self.worktree_indices.values() {
+            if let WorktreeIndexHandle::Loaded { index } = worktree_index {
+                index.update(cx, |index, _| index.set_paused(paused));
+            }
+        }
+        self.update_status(cx);
+    }
+
     pub fn project(&self) -> WeakModel<Project> {
         self.project.clone()
     }
@@ -186,6 +535,18 @@ impl ProjectIndex {
         }
     }
 
+    fn set_embedding_provider(
+        &mut self,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.embedding_provider = embedding_provider;
+        self.embedding_cache = embedding_cache;
+        self.worktree_indices.clear();
+        self.update_worktree_indices(cx);
+    }
+
     fn update_worktree_indices(&mut self, cx: &mut ModelContext<Self>) {
         let Some(project) = self.project.upgrade() else {
             return;
@@ -195,7 +556,11 @@ impl ProjectIndex {
             .read(cx)
             .visible_worktrees(cx)
             .filter_map(|worktree| {
-                if worktree.read(cx).is_local() {
+                let location = SettingsLocation {
+                    worktree_id: worktree.read(cx).id(),
+                    path: Path::new(""),
+                };
+                if SemanticIndexSettings::get(Some(location), cx).enabled {
                     Some((worktree.entity_id(), worktree))
                 } else {
                     None
@@ -205,28 +570,39 @@ impl ProjectIndex {
 
         self.worktree_indices
             .retain(|worktree_id, _| worktrees.contains_key(worktree_id));
+        let paused = self.indexing_paused();
         for (worktree_id, worktree) in worktrees {
             self.worktree_indices.entry(worktree_id).or_insert_with(|| {
+                let reembed = self.worktrees_to_reembed.remove(&worktree_id);
                 let worktree_index = WorktreeIndex::load(
                     worktree.clone(),
-                    self.db_connection.clone(),
+                    self.project.clone(),
+                    self.databases.clone(),
                     self.language_registry.clone(),
                     self.fs.clone(),
                     self.status_tx.clone(),
+                    self.usage_tx.clone(),
                     self.embedding_provider.clone(),
+                    self.embedding_cache.clone(),
+                    paused,
+                    reembed,
                     cx,
                 );
 
                 let load_worktree = cx.spawn(|this, mut cx| async move {
                     let result = match worktree_index.await {
                         Ok(worktree_index) => {
-                            this.update(&mut cx, |this, _| {
+                            this.update(&mut cx, |this, cx| {
                                 this.worktree_indices.insert(
                                     worktree_id,
                                     WorktreeIndexHandle::Loaded {
                                         index: worktree_index.clone(),
                                     },
                                 );
+                                // Indexing may have been paused or resumed while loading.
+                                let paused = this.indexing_paused();
+                                worktree_index.update(cx, |index, _| index.set_paused(paused));
+                                this.estimate_first_index(&worktree_index, cx);
                             })?;
                             Ok(worktree_index)
                         }
@@ -252,9 +628,17 @@ impl ProjectIndex {
         self.update_status(cx);
     }
 
+    fn handle_indexing_event(&mut self, event: IndexingEvent, cx: &mut ModelContext<Self>) {
+        self.update_status(cx);
+        cx.emit(event);
+    }
+
     fn update_status(&mut self, cx: &mut ModelContext<Self>) {
         let mut indexing_count = 0;
+        let mut failed_file_count = 0;
         let mut any_loading = false;
+        let mut any_reembedding = false;
+        let mut pinned_count = 0;
 
         for index in self.worktree_indices.values_mut() {
             match index {
@@ -263,15 +647,33 @@ impl ProjectIndex {
                     break;
                 }
                 WorktreeIndexHandle::Loaded { index, .. } => {
-                    indexing_count += index.read(cx).entry_ids_being_indexed.len();
+                    let index = index.read(cx);
+                    indexing_count += index.entry_ids_being_indexed.len();
+                    failed_file_count += index.failed_file_count;
+                    any_reembedding |= index.embedding_model_changed;
+                    if index.pinned_model.is_some() {
+                        pinned_count += 1;
+                    }
                 }
             }
         }
 
-        let status = if any_loading {
+        let status = if self.paused {
+            Status::Paused
+        } else if let Some(reason) = self.throttle_reason {
+            Status::Throttled { reason }
+        } else if any_loading {
             Status::Loading
         } else if let Some(remaining_count) = NonZeroUsize::new(indexing_count) {
-            Status::Scanning { remaining_count }
+            if any_reembedding {
+                Status::Reembedding { remaining_count }
+            } else {
+                Status::Scanning { remaining_count }
+            }
+        } else if let Some(worktree_count) = NonZeroUsize::new(pinned_count) {
+            Status::Pinned { worktree_count }
+        } else if let Some(file_count) = NonZeroUsize::new(failed_file_count) {
+            Status::RetryPending { file_count }
         } else {
             Status::Idle
         };
@@ -282,140 +684,7 @@ impl ProjectIndex {
         }
     }
 
-    pub fn search(
-        &self,
-        query: String,
-        limit: usize,
-        cx: &AppContext,
-    ) -> Task<Result<Vec<SearchResult>>> {
-        let (chunks_tx, chunks_rx) = channel::bounded(1024);
-        let mut worktree_scan_tasks = Vec::new();
-        for worktree_index in self.worktree_indices.values() {
-            let worktree_index = worktree_index.clone();
-            let chunks_tx = chunks_tx.clone();
-            worktree_scan_tasks.push(cx.spawn(|cx| async move {
-                let index = match worktree_index {
-                    WorktreeIndexHandle::Loading { index } => {
-                        index.clone().await.map_err(|error| anyhow!(error))?
-                    }
-                    WorktreeIndexHandle::Loaded { index } => index.clone(),
-                };
-
-                index
-                    .read_with(&cx, |index, cx| {
-                        let worktree_id = index.worktree.read(cx).id();
-                        let db_connection = index.db_connection.clone();
-                        let db = index.db;
-                        cx.background_executor().spawn(async move {
-                            let txn = db_connection
-                                .read_txn()
-                                .context("failed to create read transaction")?;
-                            let db_entries = db.iter(&txn).context("failed to iterate database")?;
-                            for db_entry in db_entries {
-                                let (_key, db_embedded_file) = db_entry?;
-                                for chunk in db_embedded_file.chunks {
-                                    chunks_tx
-                                        .send((worktree_id, db_embedded_file.path.clone(), chunk))
-                                        .await?;
-                                }
-                            }
-                            anyhow::Ok(())
-                        })
-                    })?
-                    .await
-            }));
-        }
-        drop(chunks_tx);
-
-        let project = self.project.clone();
-        let embedding_provider = self.embedding_provider.clone();
-        cx.spawn(|cx| async move {
-            #[cfg(debug_assertions)]
-            let embedding_query_start = std::time::Instant::now();
-            log::info!("Searching for {query}");
-
-            let query_embeddings = embedding_provider
-                .embed(&[TextToEmbed::new(&query)])
-                .await?;
-            let query_embedding = query_embeddings
-                .into_iter()
-                .next()
-                .ok_or_else(|| anyhow!("no embedding for query"))?;
-
-            let mut results_by_worker = Vec::new();
-            for _ in 0..cx.background_executor().num_cpus() {
-                results_by_worker.push(Vec::<WorktreeSearchResult>::new());
-            }
-
-            #[cfg(debug_assertions)]
-            let search_start = std::time::Instant::now();
-
-            cx.background_executor()
-                .scoped(|cx| {
-                    for results in results_by_worker.iter_mut() {
-                        cx.spawn(async {
-                            while let Ok((worktree_id, path, chunk)) = chunks_rx.recv().await {
-                                let score = chunk.embedding.similarity(&query_embedding);
-                                let ix = match results.binary_search_by(|probe| {
-                                    score.partial_cmp(&probe.score).unwrap_or(Ordering::Equal)
-                                }) {
-                                    Ok(ix) | Err(ix) => ix,
-                                };
-                                results.insert(
-                                    ix,
-                                    WorktreeSearchResult {
-                                        worktree_id,
-                                        path: path.clone(),
-                                        range: chunk.chunk.range.clone(),
-                                        score,
-                                    },
-                                );
-                                results.truncate(limit);
-                            }
-                        });
-                    }
-                })
-                .await;
-
-            for scan_task in futures::future::join_all(worktree_scan_tasks).await {
-                scan_task.log_err();
-            }
-
-            project.read_with(&cx, |project, cx| {
-                let mut search_results = Vec::with_capacity(results_by_worker.len() * limit);
-                for worker_results in results_by_worker {
-                    search_results.extend(worker_results.into_iter().filter_map(|result| {
-                        Some(SearchResult {
-                            worktree: project.worktree_for_id(result.worktree_id, cx)?,
-                            path: result.path,
-                            range: result.range,
-                            score: result.score,
-                        })
-                    }));
-                }
-                search_results.sort_unstable_by(|a, b| {
-                    b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
-                });
-                search_results.truncate(limit);
-
-                #[cfg(debug_assertions)]
-                {
-                    let search_elapsed = search_start.elapsed();
-                    log::debug!(
-                        "searched {} entries in {:?}",
-                        search_results.len(),
-                        search_elapsed
-                    );
-                    let embedding_query_elapsed = embedding_query_start.elapsed();
-                    log::debug!("embedding query took {:?}", embedding_query_elapsed);
-                }
-
-                search_results
-            })
-        })
-    }
-
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-support"))]
     pub fn path_count(&self, cx: &AppContext) -> Result<u64> {
         let mut result = 0;
         for worktree_index in self.worktree_indices.values() {
@@ -441,6 +710,224 @@ impl ProjectIndex {
         None
     }
 
+    /// Compares the index of every loaded worktree with its files, without changing
+    /// anything.
+    pub fn verify(&self, cx: &AppContext) -> Task<Result<Vec<IndexVerification>>> {
+        let verifications = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| index.read(cx).verify(cx))
+            .collect::<Vec<_>>();
+        cx.background_executor()
+            .spawn(async move { futures::future::try_join_all(verifications).await })
+    }
+
+    /// Removes the files that were deleted or shouldn't be indexed anymore from the
+    /// index of every loaded worktree, returning how many were removed.
+    pub fn collect_garbage(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<usize>> {
+        let collections = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| index.update(cx, |index, cx| index.collect_garbage(cx)))
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            Ok(futures::future::try_join_all(collections)
+                .await?
+                .into_iter()
+                .sum())
+        })
+    }
+
+    /// Compares the index of every loaded worktree with its files, removing the files
+    /// that shouldn't be indexed and indexing the ones that are missing or outdated.
+    /// Returns the problems that were fixed.
+    pub fn repair(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<Vec<IndexVerification>>> {
+        let repairs = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| index.update(cx, |index, cx| index.repair(cx)))
+            .collect::<Vec<_>>();
+        cx.background_executor()
+            .spawn(async move { futures::future::try_join_all(repairs).await })
+    }
+
+    /// Drops the index of every loaded worktree that was kept for another embedding
+    /// model because of the `pin_embedding_model` setting, and embeds its files again
+    /// with the current model. Returns how many worktrees are embedded again.
+    pub fn reembed_pinned_worktrees(&mut self, cx: &mut ModelContext<Self>) -> usize {
+        let pinned_worktrees = self
+            .worktree_indices
+            .iter()
+            .filter_map(|(worktree_id, index)| match index {
+                WorktreeIndexHandle::Loaded { index } if index.read(cx).pinned_model.is_some() => {
+                    Some(*worktree_id)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for worktree_id in &pinned_worktrees {
+            self.worktree_indices.remove(worktree_id);
+            self.worktrees_to_reembed.insert(*worktree_id);
+        }
+        self.update_worktree_indices(cx);
+        self.update_status(cx);
+        pinned_worktrees.len()
+    }
+
+    /// Estimates what indexing every file of the project's worktrees from scratch would
+    /// take, including the API cost and time, without calling the embedding provider.
+    /// Worktrees that are still loading are estimated once they're loaded.
+    pub fn estimate(&self, cx: &AppContext) -> Task<Result<IndexEstimate>> {
+        let worktree_estimates = self
+            .worktree_indices
+            .values()
+            .cloned()
+            .map(|worktree_index| {
+                cx.spawn(|cx| async move {
+                    let index = match worktree_index {
+                        WorktreeIndexHandle::Loading { index } => {
+                            index.await.map_err(|error| anyhow!(error))?
+                        }
+                        WorktreeIndexHandle::Loaded { index } => index,
+                    };
+                    let estimate = index.read_with(&cx, |index, cx| index.estimate(cx))?;
+                    anyhow::Ok(estimate.await)
+                })
+            })
+            .collect::<Vec<_>>();
+        let embedding_provider = self.embedding_provider.clone();
+        cx.background_executor().spawn(async move {
+            let mut estimate = IndexEstimate::default();
+            for worktree_estimate in futures::future::try_join_all(worktree_estimates).await? {
+                estimate += worktree_estimate;
+            }
+            Ok(estimate.project(embedding_provider.as_ref()))
+        })
+    }
+
+    /// Describes the state of the index of every loaded worktree, for attaching to bug
+    /// reports.
+    pub(crate) fn diagnostics(&self, cx: &AppContext) -> Task<Result<IndexDiagnostics>> {
+        let worktree_diagnostics = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| index.read(cx).diagnostics(cx))
+            .collect::<Vec<_>>();
+        let status = self.status();
+        let embedding_model = self.embedding_provider.model_info();
+        let shared_db = self.databases.shared().clone();
+        cx.background_executor().spawn(async move {
+            Ok(IndexDiagnostics {
+                status,
+                embedding_model,
+                shared_database_size: shared_db.real_disk_size().log_err(),
+                worktrees: futures::future::try_join_all(worktree_diagnostics).await?,
+            })
+        })
+    }
+
+    /// Estimates what indexing a worktree that was never indexed takes once all of its
+    /// files were found, and emits the estimate if the worktree is large, so that users
+    /// can pause indexing and exclude files before most of them are embedded.
+    fn estimate_first_index(&self, index: &Model<WorktreeIndex>, cx: &mut ModelContext<Self>) {
+        if !index.read(cx).never_indexed {
+            return;
+        }
+        let scan_complete = index
+            .read(cx)
+            .worktree
+            .read(cx)
+            .as_local()
+            .map(|worktree| worktree.scan_complete());
+        let index = index.downgrade();
+        cx.spawn(|this, mut cx| async move {
+            if let Some(scan_complete) = scan_complete {
+                scan_complete.await;
+            }
+            let estimate = index.update(&mut cx, |index, cx| {
+                let filter = index.index_filter();
+                let file_count = index
+                    .worktree
+                    .read(cx)
+                    .files(false, 0)
+                    .filter(|entry| filter.matches(&entry.path, None))
+                    .count();
+                (file_count >= LARGE_WORKTREE_FILE_COUNT).then(|| index.estimate(cx))
+            })?;
+            let Some(estimate) = estimate else {
+                return Ok(());
+            };
+            let estimate = estimate.await;
+            this.update(&mut cx, |this, cx| {
+                let estimate = estimate.project(this.embedding_provider.as_ref());
+                log::info!("indexing a large worktree: {}", estimate.summary());
+                cx.emit(estimate);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Returns how much the embedding provider was used to index the project's
+    /// worktrees, by day.
+    pub fn usage_stats(&self, cx: &AppContext) -> Task<Result<Vec<DailyUsage>>> {
+        let usage_logs = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let index = index.read(cx);
+                (index.db_connection.clone(), index.usage_log.clone())
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let mut daily_usage = Vec::new();
+            for (db_connection, usage_log) in usage_logs {
+                let txn = db_connection.read_txn()?;
+                daily_usage.extend(usage_log.daily(&txn)?);
+            }
+            Ok(merge_daily_usage(daily_usage))
+        })
+    }
+
+    /// Reads the embeddings of every loaded worktree index, to export them.
+    fn archived_worktrees(&self, cx: &AppContext) -> Task<Result<Vec<ArchivedWorktree>>> {
+        // Worktrees pinned to another model would mix its embeddings with the current
+        // model's.
+        let worktrees = self
+            .worktree_indices(cx)
+            .into_iter()
+            .filter(|index| index.read(cx).pinned_model.is_none())
+            .map(|index| {
+                let index = index.read(cx);
+                let root_name = index.worktree.read(cx).root_name().to_string();
+                let files = index.archived_files(cx);
+                async move {
+                    anyhow::Ok(ArchivedWorktree {
+                        root_name,
+                        files: files.await?,
+                    })
+                }
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor()
+            .spawn(async move { futures::future::try_join_all(worktrees).await })
+    }
+
+    /// Returns the absolute path of every loaded worktree, along with how to read its
+    /// files.
+    fn file_loaders(&self, cx: &AppContext) -> HashMap<WorktreeId, (Arc<Path>, FileLoader)> {
+        self.worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let index = index.read(cx);
+                let worktree = index.worktree.read(cx);
+                (
+                    worktree.id(),
+                    (worktree.abs_path(), index.file_loader.clone()),
+                )
+            })
+            .collect()
+    }
+
     pub(crate) fn worktree_indices(&self, cx: &AppContext) -> Vec<Model<WorktreeIndex>> {
         let mut result = self
             .worktree_indices
@@ -458,75 +945,561 @@ impl ProjectIndex {
     }
 }
 
-pub struct SearchResult {
-    pub worktree: Model<Worktree>,
-    pub path: Arc<Path>,
-    pub range: Range<usize>,
-    pub score: f32,
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Status {
+    Idle,
+    Loading,
+    Scanning {
+        remaining_count: NonZeroUsize,
+    },
+    /// Like [`Status::Scanning`], but re-embedding every file because the embedding
+    /// model changed since the files were indexed.
+    Reembedding {
+        remaining_count: NonZeroUsize,
+    },
+    /// Indexing was paused with [`ProjectIndex::pause`].
+    Paused,
+    /// Indexing was paused to leave resources to the rest of the system, and
+    /// resumes once they're available again.
+    Throttled {
+        reason: ThrottleReason,
+    },
+    /// Some files couldn't be embedded, and are left out of the index until they're
+    /// retried later.
+    RetryPending {
+        file_count: NonZeroUsize,
+    },
+    /// Some worktrees were embedded with another model than the current one, and kept
+    /// because of the `pin_embedding_model` setting. Searches leave them out until
+    /// they're embedded again with [`ProjectIndex::reembed_pinned_worktrees`].
+    Pinned {
+        worktree_count: NonZeroUsize,
+    },
 }
 
-pub struct WorktreeSearchResult {
+/// Emitted by [`ProjectIndex`] as a worktree is indexed, with what indexing is doing
+/// in more detail than its [`Status`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexingEvent {
     pub worktree_id: WorktreeId,
-    pub path: Arc<Path>,
-    pub range: Range<usize>,
-    pub score: f32,
+    pub kind: IndexingEventKind,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub enum Status {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexingEventKind {
+    /// The worktree's files are being compared with the index, and `remaining_count`
+    /// of the ones that changed are left to index.
+    Scanning { remaining_count: usize },
+    /// Files that changed are being read and split into chunks.
+    Chunking,
+    /// Chunks are being embedded, `done` of the `total` chunked so far.
+    Embedding { done: usize, total: usize },
+    /// A file couldn't be indexed. Files that failed to embed are retried later.
+    Failed { path: Arc<Path>, error: String },
+    /// The worktree's changes were indexed, and nothing is left to index until it
+    /// changes again.
     Idle,
-    Loading,
-    Scanning { remaining_count: NonZeroUsize },
+}
+
+/// Sends the [`IndexingEvent`]s of a worktree to its [`ProjectIndex`].
+#[derive(Clone)]
+struct IndexingEvents {
+    worktree_id: WorktreeId,
+    tx: channel::Sender<IndexingEvent>,
+}
+
+impl IndexingEvents {
+    fn send(&self, kind: IndexingEventKind) {
+        self.tx
+            .try_send(IndexingEvent {
+                worktree_id: self.worktree_id,
+                kind,
+            })
+            .ok();
+    }
 }
 
 impl EventEmitter<Status> for ProjectIndex {}
 
+/// Emitted as worktrees are indexed.
+impl EventEmitter<IndexingEvent> for ProjectIndex {}
+
+/// Emitted after every request to the embedding provider, with what it used.
+impl EventEmitter<EmbeddingUsage> for ProjectIndex {}
+
+/// Emitted with what indexing a large worktree will take, when it was never indexed.
+impl EventEmitter<IndexEstimate> for ProjectIndex {}
+
+const EMBEDDING_MODEL_KEY: &str = "embedding_model";
+/// The key of the [`ChunkConfig`] a worktree's files were chunked with.
+const CHUNK_CONFIG_KEY: &str = "chunk_config";
+/// The key of the fingerprint of the key a worktree's index is encrypted with, which is
+/// missing when the index isn't encrypted.
+const ENCRYPTION_KEY_FINGERPRINT_KEY: &str = "key_fingerprint";
+/// The keychain entry of the key the index of worktrees that opt into encryption is
+/// encrypted with.
+const ENCRYPTION_KEY_URL: &str = "zed://semantic-index";
+
+/// The key the index is encrypted with once it's loaded, so that the keychain is only
+/// asked for it once, and worktrees loading at once don't each create a different one.
+static INDEX_CIPHER: smol::lock::OnceCell<IndexCipher> = smol::lock::OnceCell::new();
+
+/// Loads the key the index is encrypted with from the keychain, creating it if needed.
+async fn load_index_cipher(cx: &AsyncAppContext) -> Result<IndexCipher> {
+    INDEX_CIPHER
+        .get_or_try_init(|| async {
+            let credentials = cx
+                .update(|cx| cx.read_credentials(ENCRYPTION_KEY_URL))?
+                .await?;
+            let key = match credentials {
+                Some((_, key)) => <[u8; KEY_LEN]>::try_from(key.as_slice())
+                    .map_err(|_| anyhow!("the encryption key in the keychain is invalid"))?,
+                None => {
+                    let key = IndexCipher::generate_key()?;
+                    cx.update(|cx| {
+                        cx.write_credentials(ENCRYPTION_KEY_URL, "semantic_index", &key)
+                    })?
+                    .await?;
+                    key
+                }
+            };
+            anyhow::Ok(IndexCipher::new(&key))
+        })
+        .await
+        .copied()
+}
+
+/// Resolves the `database_path` setting, expanding `~` and environment variables.
+/// Relative paths are relative to the root of the worktree they're set for, if any.
+fn resolve_database_path(database_path: &str, worktree_root: Option<&Path>) -> Option<PathBuf> {
+    let path = PathBuf::from(shellexpand::full(database_path).log_err()?.as_ref());
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(worktree_root?.join(path))
+    }
+}
+
 struct WorktreeIndex {
     worktree: Model<Worktree>,
-    db_connection: heed::Env,
-    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    project: WeakModel<Project>,
+    db_connection: DbConnection,
+    db: EncryptedDatabase<EmbeddedFile>,
+    /// The files embedded by a fallback provider while the embedding provider was down,
+    /// which are re-embedded once it's back.
+    fallback_db: EncryptedDatabase<FallbackEmbeddedFile>,
+    keyword_index: KeywordIndex,
+    vector_index: VectorIndex,
+    full_precision_embeddings: FullPrecisionEmbeddings,
+    skipped_files: SkippedFiles,
+    retry_backlog: RetryBacklog,
+    usage_log: UsageLog,
+    index_progress: IndexProgress,
+    checkpoint: IndexCheckpoint,
     language_registry: Arc<LanguageRegistry>,
-    fs: Arc<dyn Fs>,
+    file_loader: FileLoader,
     embedding_provider: Arc<dyn EmbeddingProvider>,
+    embedding_cache: Arc<EmbeddingCache>,
     entry_ids_being_indexed: Arc<IndexingEntrySet>,
+    /// How many files are in the retry backlog, as of the end of the last indexing.
+    failed_file_count: usize,
+    events: IndexingEvents,
+    index_requests_tx: channel::Sender<IndexRequest>,
+    /// Rescans the worktree to retry the files in the backlog once they're due.
+    retry_failed_files: Option<Task<()>>,
+    /// Whether the files were embedded with a different model when the index was
+    /// loaded, until they've all been re-embedded.
+    embedding_model_changed: bool,
+    /// The model the files were embedded with, when it's another model than the
+    /// current one and the worktree is pinned to it. Nothing is indexed meanwhile.
+    pinned_model: Option<EmbeddingModelInfo>,
+    /// How files are chunked, as configured when the index was loaded.
+    chunk_config: ChunkConfig,
+    /// Whether no indexing of the worktree had started when the index was loaded, so
+    /// that every file is embedded from scratch.
+    never_indexed: bool,
+    paused: bool,
+    resume_tx: channel::Sender<()>,
+    /// The files to index, or all of them if empty.
+    include_globs: PathMatcher,
+    /// The files to never index, even if they match `include_globs`.
+    exclude_globs: PathMatcher,
     _index_entries: Task<Result<()>>,
-    _subscription: Subscription,
+    _collect_garbage: Task<Result<()>>,
+    _subscriptions: Vec<Subscription>,
 }
 
-impl WorktreeIndex {
-    pub fn load(
-        worktree: Model<Worktree>,
-        db_connection: heed::Env,
-        language_registry: Arc<LanguageRegistry>,
+/// How often to remove the files that were deleted or shouldn't be indexed anymore
+/// from the index of a worktree, in case their changes were missed.
+const GARBAGE_COLLECTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Reads the contents of the files of a worktree to index them.
+#[derive(Clone)]
+enum FileLoader {
+    /// Reads files from the file system, for local worktrees.
+    Fs(Arc<dyn Fs>),
+    /// Asks the foreground to open files as buffers of the project, whose contents are
+    /// streamed from the host, for remote worktrees.
+    Buffers(channel::Sender<(ProjectPath, oneshot::Sender<Result<String>>)>),
+}
+
+impl FileLoader {
+    fn new(
+        worktree: &Model<Worktree>,
+        project: WeakModel<Project>,
         fs: Arc<dyn Fs>,
-        status_tx: channel::Sender<()>,
-        embedding_provider: Arc<dyn EmbeddingProvider>,
-        cx: &mut AppContext,
-    ) -> Task<Result<Model<Self>>> {
-        let worktree_abs_path = worktree.read(cx).abs_path();
-        cx.spawn(|mut cx| async move {
-            let db = cx
-                .background_executor()
-                .spawn({
-                    let db_connection = db_connection.clone();
-                    async move {
-                        let mut txn = db_connection.write_txn()?;
-                        let db_name = worktree_abs_path.to_string_lossy();
-                        let db = db_connection.create_database(&mut txn, Some(&db_name))?;
-                        txn.commit()?;
-                        anyhow::Ok(db)
+        cx: &AppContext,
+    ) -> Self {
+        if worktree.read(cx).is_local() {
+            return Self::Fs(fs);
+        }
+
+        let (requests_tx, requests_rx) =
+            channel::unbounded::<(ProjectPath, oneshot::Sender<Result<String>>)>();
+        // Each buffer is streamed from the host whole, so only open a few at a time.
+        const MAX_CONCURRENT_BUFFER_OPENS: usize = 16;
+        for _ in 0..MAX_CONCURRENT_BUFFER_OPENS {
+            let requests_rx = requests_rx.clone();
+            let project = project.clone();
+            cx.spawn(|mut cx| async move {
+                while let Ok((project_path, contents_tx)) = requests_rx.recv().await {
+                    let Ok(open_buffer) = project
+                        .update(&mut cx, |project, cx| project.open_buffer(project_path, cx))
+                    else {
+                        break;
+                    };
+                    let contents = async {
+                        let buffer = open_buffer.await?;
+                        buffer.read_with(&cx, |buffer, _| buffer.text())
+                    }
+                    .await;
+                    contents_tx.send(contents).ok();
+                }
+            })
+            .detach();
+        }
+        Self::Buffers(requests_tx)
+    }
+
+    /// Reads the whole text of a file.
+    async fn load_text(
+        &self,
+        worktree_id: WorktreeId,
+        worktree_abs_path: &Path,
+        path: &Arc<Path>,
+    ) -> Result<String> {
+        let bytes = self
+            .load(worktree_id, worktree_abs_path, path, u64::MAX)
+            .await?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// Opens a file to read it a block at a time. Files of remote worktrees are still
+    /// read whole, since buffers are streamed from the host whole.
+    async fn open(
+        &self,
+        worktree_id: WorktreeId,
+        worktree_abs_path: &Path,
+        path: &Arc<Path>,
+    ) -> Result<Box<dyn Read>> {
+        match self {
+            FileLoader::Fs(fs) => fs.open_sync(&worktree_abs_path.join(path)).await,
+            FileLoader::Buffers(_) => {
+                let bytes = self
+                    .load(worktree_id, worktree_abs_path, path, u64::MAX)
+                    .await?;
+                Ok(Box::new(std::io::Cursor::new(bytes)))
+            }
+        }
+    }
+
+    /// Reads up to `max_len` bytes of a file.
+    async fn load(
+        &self,
+        worktree_id: WorktreeId,
+        worktree_abs_path: &Path,
+        path: &Arc<Path>,
+        max_len: u64,
+    ) -> Result<Vec<u8>> {
+        match self {
+            FileLoader::Fs(fs) => {
+                let mut bytes = Vec::new();
+                fs.open_sync(&worktree_abs_path.join(path))
+                    .await?
+                    .take(max_len)
+                    .read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            FileLoader::Buffers(requests) => {
+                let (contents_tx, contents_rx) = oneshot::channel();
+                let project_path = ProjectPath {
+                    worktree_id,
+                    path: path.clone(),
+                };
+                requests.send((project_path, contents_tx)).await?;
+                let mut bytes = contents_rx.await??.into_bytes();
+                bytes.truncate(max_len.try_into().unwrap_or(usize::MAX));
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+impl WorktreeIndex {
+    /// Opens the index of a worktree. Files embedded with another model than the current
+    /// one are embedded again, unless the worktree is pinned to their model and
+    /// `reembed` is false.
+    #[allow(clippy::too_many_arguments)]
+    pub fn load(
+        worktree: Model<Worktree>,
+        project: WeakModel<Project>,
+        databases: IndexDatabases,
+        language_registry: Arc<LanguageRegistry>,
+        fs: Arc<dyn Fs>,
+        status_tx: channel::Sender<IndexingEvent>,
+        usage_tx: channel::Sender<EmbeddingUsage>,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        paused: bool,
+        reembed: bool,
+        cx: &mut AppContext,
+    ) -> Task<Result<Model<Self>>> {
+        let worktree_abs_path = worktree.read(cx).abs_path();
+        // Remote worktrees are named after their host and their path on it, which may
+        // also exist on this machine or on other hosts.
+        let db_name = if worktree.read(cx).is_local() {
+            worktree_abs_path.to_string_lossy().into_owned()
+        } else {
+            let host = project
+                .upgrade()
+                .and_then(|project| remote_host_name(project.read(cx)))
+                .unwrap_or_default();
+            format!("remote:{host}:{}", worktree_abs_path.to_string_lossy())
+        };
+        let file_loader = FileLoader::new(&worktree, project.clone(), fs, cx);
+        let embedding_model = embedding_provider.model_info();
+        let encrypt = Self::settings(&worktree, cx).encrypt;
+        let pin_embedding_model = Self::settings(&worktree, cx).pin_embedding_model && !reembed;
+        let chunk_config = {
+            let settings = Self::settings(&worktree, cx);
+            ChunkConfig::new(
+                settings.chunk_size,
+                settings.chunk_tokens,
+                settings.chunk_overlap,
+            )
+        };
+        let database_root = Self::database_root(&worktree, cx);
+        cx.spawn(|mut cx| async move {
+            let cipher = if encrypt {
+                Some(
+                    load_index_cipher(&cx)
+                        .await
+                        .context("loading the encryption key")?,
+                )
+            } else {
+                None
+            };
+            let (
+                db_connection,
+                db,
+                fallback_db,
+                keyword_index,
+                vector_index,
+                full_precision_embeddings,
+                skipped_files,
+                retry_backlog,
+                usage_log,
+                index_progress,
+                checkpoint,
+                embedding_model_changed,
+                pinned_model,
+                chunk_config,
+            ) = cx
+                .background_executor()
+                .spawn(async move {
+                    let db_connection =
+                        databases.open_worktree(&db_name, database_root.as_deref())?;
+                    let mut txn = db_connection.write_txn()?;
+                    let db = EncryptedDatabase::create(&db_connection, &mut txn, &db_name, cipher)?;
+                    let fallback_db = EncryptedDatabase::create(
+                        &db_connection,
+                        &mut txn,
+                        &format!("{db_name}:fallback_embeddings"),
+                        cipher,
+                    )?;
+                    let keyword_index =
+                        KeywordIndex::create(&db_connection, &mut txn, &db_name, cipher)?;
+                    let vector_index =
+                        VectorIndex::create(&db_connection, &mut txn, &db_name, db, cipher)?;
+                    let full_precision_embeddings = FullPrecisionEmbeddings::create(
+                        &db_connection,
+                        &mut txn,
+                        &db_name,
+                        cipher,
+                    )?;
+                    let skipped_files = SkippedFiles::create(&db_connection, &mut txn, &db_name)?;
+                    let retry_backlog = RetryBacklog::create(&db_connection, &mut txn, &db_name)?;
+                    let usage_log = UsageLog::create(&db_connection, &mut txn, &db_name, usage_tx)?;
+                    let index_progress = IndexProgress::create(&db_connection, &mut txn, &db_name)?;
+                    let schema_changed = schema::migrate(
+                        &db_connection,
+                        &mut txn,
+                        &db_name,
+                        &WORKTREE_SCHEMA,
+                        db.is_empty(&txn)?,
+                    )?;
+
+                    // An index encrypted with another key, or encrypted when it shouldn't
+                    // be or the other way around, can't be read, so drop it.
+                    let encryption = db_connection.create_database::<Str, Str>(
+                        &mut txn,
+                        Some(&format!("{db_name}:encryption")),
+                    )?;
+                    let key_fingerprint = cipher.map(|cipher| cipher.fingerprint());
+                    let encryption_changed = encryption
+                        .get(&txn, ENCRYPTION_KEY_FINGERPRINT_KEY)?
+                        != key_fingerprint.as_deref()
+                        && !db.is_empty(&txn)?;
+
+                    // Embeddings from different models can't be compared, so drop the
+                    // existing ones and re-embed every file when the model changes, or
+                    // when they don't have the model's dimensions, unless the worktree is
+                    // pinned to the model it was embedded with.
+                    let embedding_models = db_connection
+                        .create_database::<Str, SerdeBincode<EmbeddingModelInfo>>(
+                            &mut txn,
+                            Some(&format!("{db_name}:embedding_model")),
+                        )?;
+                    let stored_model = embedding_models.get(&txn, EMBEDDING_MODEL_KEY)?;
+                    // Indices in an earlier format, like those storing embeddings before
+                    // they could be quantized, may not deserialize.
+                    let stored_dimensions = if encryption_changed || schema_changed {
+                        None
+                    } else {
+                        stored_embedding_dimensions(&db, &txn)?
+                    };
+                    // Files chunked differently would keep their old chunks until they
+                    // change, so chunk every file again. Embeddings of the chunks that
+                    // didn't change are still in the embedding cache.
+                    let chunk_configs = db_connection
+                        .create_database::<Str, SerdeBincode<ChunkConfig>>(
+                            &mut txn,
+                            Some(&format!("{db_name}:chunking")),
+                        )?;
+                    let chunking_changed = chunk_configs
+                        .get(&txn, CHUNK_CONFIG_KEY)?
+                        .unwrap_or_default()
+                        != chunk_config
+                        && !db.is_empty(&txn)?;
+
+                    let embedding_model_changed = (stored_model.as_ref() != Some(&embedding_model)
+                        || stored_dimensions
+                            .map_or(false, |dimensions| dimensions != embedding_model.dimensions))
+                        && !db.is_empty(&txn)?;
+                    let pinned_model = stored_model.filter(|stored_model| {
+                        pin_embedding_model
+                            && embedding_model_changed
+                            && !encryption_changed
+                            && !schema_changed
+                            && !chunking_changed
+                            && *stored_model != embedding_model
+                    });
+                    let embedding_model_changed = embedding_model_changed && pinned_model.is_none();
+
+                    if let Some(pinned_model) = &pinned_model {
+                        log::warn!(
+                            "keeping the index of {db_name}, which is pinned to {pinned_model:?}, \
+                            out of searches until it's embedded with {embedding_model:?}"
+                        );
+                    } else if embedding_model_changed {
+                        log::info!(
+                            "re-embedding {db_name} because the embedding model changed to {:?}",
+                            embedding_model
+                        );
+                    } else if encryption_changed {
+                        log::info!("re-indexing {db_name} because its encryption changed");
+                    } else if chunking_changed {
+                        log::info!(
+                            "re-indexing {db_name} because chunking changed to {chunk_config:?}"
+                        );
+                    }
+                    if embedding_model_changed
+                        || encryption_changed
+                        || schema_changed
+                        || chunking_changed
+                    {
+                        db.clear(&mut txn)?;
+                        fallback_db.clear(&mut txn)?;
+                        keyword_index.clear(&mut txn)?;
+                        vector_index.clear(&mut txn)?;
+                        full_precision_embeddings.clear(&mut txn)?;
+                        skipped_files.clear(&mut txn)?;
+                        retry_backlog.clear(&mut txn)?;
+                        index_progress.clear(&mut txn)?;
                     }
+                    if pinned_model.is_none() {
+                        embedding_models.put(&mut txn, EMBEDDING_MODEL_KEY, &embedding_model)?;
+                    }
+                    chunk_configs.put(&mut txn, CHUNK_CONFIG_KEY, &chunk_config)?;
+                    match &key_fingerprint {
+                        Some(key_fingerprint) => encryption.put(
+                            &mut txn,
+                            ENCRYPTION_KEY_FINGERPRINT_KEY,
+                            key_fingerprint,
+                        )?,
+                        None => {
+                            encryption.delete(&mut txn, ENCRYPTION_KEY_FINGERPRINT_KEY)?;
+                        }
+                    }
+
+                    let checkpoint = index_progress.load(&txn)?;
+                    if checkpoint.scan_in_progress {
+                        log::info!("resuming interrupted indexing of {db_name}");
+                    }
+
+                    txn.commit()?;
+                    anyhow::Ok((
+                        db_connection,
+                        db,
+                        fallback_db,
+                        keyword_index,
+                        vector_index,
+                        full_precision_embeddings,
+                        skipped_files,
+                        retry_backlog,
+                        usage_log,
+                        index_progress,
+                        checkpoint,
+                        embedding_model_changed,
+                        pinned_model,
+                        chunk_config,
+                    ))
                 })
                 .await?;
             cx.new_model(|cx| {
                 Self::new(
                     worktree,
+                    project,
                     db_connection,
                     db,
+                    fallback_db,
+                    keyword_index,
+                    vector_index,
+                    full_precision_embeddings,
+                    skipped_files,
+                    retry_backlog,
+                    usage_log,
+                    index_progress,
+                    checkpoint,
+                    embedding_model_changed,
+                    pinned_model,
+                    chunk_config,
                     status_tx,
                     language_registry,
-                    fs,
+                    file_loader,
                     embedding_provider,
+                    embedding_cache,
+                    paused,
                     cx,
                 )
             })
@@ -536,376 +1509,458 @@ impl WorktreeIndex {
     #[allow(clippy::too_many_arguments)]
     fn new(
         worktree: Model<Worktree>,
-        db_connection: heed::Env,
-        db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
-        status: channel::Sender<()>,
+        project: WeakModel<Project>,
+        db_connection: DbConnection,
+        db: EncryptedDatabase<EmbeddedFile>,
+        fallback_db: EncryptedDatabase<FallbackEmbeddedFile>,
+        keyword_index: KeywordIndex,
+        vector_index: VectorIndex,
+        full_precision_embeddings: FullPrecisionEmbeddings,
+        skipped_files: SkippedFiles,
+        retry_backlog: RetryBacklog,
+        usage_log: UsageLog,
+        index_progress: IndexProgress,
+        checkpoint: IndexCheckpoint,
+        embedding_model_changed: bool,
+        pinned_model: Option<EmbeddingModelInfo>,
+        chunk_config: ChunkConfig,
+        status: channel::Sender<IndexingEvent>,
         language_registry: Arc<LanguageRegistry>,
-        fs: Arc<dyn Fs>,
+        file_loader: FileLoader,
         embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<EmbeddingCache>,
+        paused: bool,
         cx: &mut ModelContext<Self>,
     ) -> Self {
-        let (updated_entries_tx, updated_entries_rx) = channel::unbounded();
-        let _subscription = cx.subscribe(&worktree, move |_this, _worktree, event, _cx| {
-            if let worktree::Event::UpdatedEntries(update) = event {
-                _ = updated_entries_tx.try_send(update.clone());
-            }
-        });
+        let (index_requests_tx, index_requests_rx) = channel::unbounded();
+        let (resume_tx, resume_rx) = channel::unbounded();
+        let (include_globs, exclude_globs) = Self::indexed_globs(&worktree, cx);
+        let events = IndexingEvents {
+            worktree_id: worktree.read(cx).id(),
+            tx: status,
+        };
+        let _subscriptions = vec![
+            cx.subscribe(&worktree, {
+                let index_requests_tx = index_requests_tx.clone();
+                move |_this, worktree, event, cx| {
+                    if let worktree::Event::UpdatedEntries(update) = event {
+                        // Remote worktrees don't report which entries changed.
+                        let request = if update.is_empty() && !worktree.read(cx).is_local() {
+                            IndexRequest::Rescan
+                        } else {
+                            IndexRequest::UpdatedEntries(update.clone())
+                        };
+                        _ = index_requests_tx.try_send(request);
+                    }
+                }
+            }),
+            cx.observe_global::<SettingsStore>(move |this, cx| {
+                let (include_globs, exclude_globs) = Self::indexed_globs(&this.worktree, cx);
+                if include_globs != this.include_globs || exclude_globs != this.exclude_globs {
+                    this.include_globs = include_globs;
+                    this.exclude_globs = exclude_globs;
+                    _ = this.index_requests_tx.try_send(IndexRequest::Rescan);
+                }
+            }),
+        ];
 
         Self {
             db_connection,
             db,
+            fallback_db,
+            keyword_index,
+            vector_index,
+            full_precision_embeddings,
+            skipped_files,
+            retry_backlog,
+            usage_log,
+            index_progress,
+            checkpoint,
             worktree,
+            project,
             language_registry,
-            fs,
+            file_loader,
             embedding_provider,
-            entry_ids_being_indexed: Arc::new(IndexingEntrySet::new(status)),
-            _index_entries: cx.spawn(|this, cx| Self::index_entries(this, updated_entries_rx, cx)),
-            _subscription,
+            embedding_cache,
+            entry_ids_being_indexed: Arc::new(IndexingEntrySet::new(events.clone())),
+            failed_file_count: 0,
+            events,
+            index_requests_tx,
+            retry_failed_files: None,
+            embedding_model_changed,
+            pinned_model,
+            chunk_config,
+            never_indexed: checkpoint.fully_indexed_at.is_none() && !checkpoint.scan_in_progress,
+            paused,
+            resume_tx,
+            include_globs,
+            exclude_globs,
+            _index_entries: cx
+                .spawn(|this, cx| Self::index_entries(this, index_requests_rx, resume_rx, cx)),
+            _collect_garbage: cx.spawn(|this, mut cx| async move {
+                loop {
+                    cx.background_executor()
+                        .timer(GARBAGE_COLLECTION_INTERVAL)
+                        .await;
+                    let collect_garbage =
+                        this.update(&mut cx, |this, cx| this.collect_garbage(cx))?;
+                    collect_garbage.await.log_err();
+                }
+            }),
+            _subscriptions,
         }
     }
 
-    async fn index_entries(
-        this: WeakModel<Self>,
-        updated_entries: channel::Receiver<UpdatedEntriesSet>,
-        mut cx: AsyncAppContext,
-    ) -> Result<()> {
-        let index = this.update(&mut cx, |this, cx| this.index_entries_changed_on_disk(cx))?;
-        index.await.log_err();
+    /// The model the files in the index were embedded with, which only queries embedded
+    /// with the same model can be compared with.
+    fn embedding_model(&self) -> EmbeddingModelInfo {
+        self.pinned_model
+            .clone()
+            .unwrap_or_else(|| self.embedding_provider.model_info())
+    }
 
-        while let Ok(updated_entries) = updated_entries.recv().await {
-            let index = this.update(&mut cx, |this, cx| {
-                this.index_updated_entries(updated_entries, cx)
-            })?;
-            index.await.log_err();
-        }
+    fn settings<'a>(worktree: &Model<Worktree>, cx: &'a AppContext) -> &'a SemanticIndexSettings {
+        let location = SettingsLocation {
+            worktree_id: worktree.read(cx).id(),
+            path: Path::new(""),
+        };
+        SemanticIndexSettings::get(Some(location), cx)
+    }
 
-        Ok(())
+    /// The directory to keep the index of the worktree in, if its settings move it.
+    fn database_root(worktree: &Model<Worktree>, cx: &AppContext) -> Option<PathBuf> {
+        let database_path = Self::settings(worktree, cx).database_path.as_deref()?;
+        let worktree = worktree.read(cx);
+        // Only local worktrees have a root on this machine for paths to be relative to.
+        let worktree_root = worktree.is_local().then(|| worktree.abs_path());
+        resolve_database_path(database_path, worktree_root.as_deref())
     }
 
-    fn index_entries_changed_on_disk(&self, cx: &AppContext) -> impl Future<Output = Result<()>> {
-        let worktree = self.worktree.read(cx).snapshot();
-        let worktree_abs_path = worktree.abs_path().clone();
-        let scan = self.scan_entries(worktree, cx);
-        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
-        let embed = Self::embed_files(self.embedding_provider.clone(), chunk.files, cx);
-        let persist = self.persist_embeddings(scan.deleted_entry_ranges, embed.files, cx);
-        async move {
-            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
-            Ok(())
+    fn indexed_globs(worktree: &Model<Worktree>, cx: &AppContext) -> (PathMatcher, PathMatcher) {
+        let settings = Self::settings(worktree, cx);
+        let path_matcher = |globs: &[String]| PathMatcher::new(globs).log_err().unwrap_or_default();
+        let mut exclude = settings.exclude.clone();
+        // Keep an index inside its worktree from indexing itself.
+        if let Some(database_root) = Self::database_root(worktree, cx) {
+            if let Ok(relative_root) = database_root.strip_prefix(worktree.read(cx).abs_path()) {
+                if !relative_root.as_os_str().is_empty() {
+                    exclude.push(relative_root.to_string_lossy().into_owned());
+                }
+            }
         }
+        (path_matcher(&settings.include), path_matcher(&exclude))
     }
 
-    fn index_updated_entries(
-        &self,
-        updated_entries: UpdatedEntriesSet,
-        cx: &AppContext,
-    ) -> impl Future<Output = Result<()>> {
-        let worktree = self.worktree.read(cx).snapshot();
-        let worktree_abs_path = worktree.abs_path().clone();
-        let scan = self.scan_updated_entries(worktree, updated_entries.clone(), cx);
-        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
-        let embed = Self::embed_files(self.embedding_provider.clone(), chunk.files, cx);
-        let persist = self.persist_embeddings(scan.deleted_entry_ranges, embed.files, cx);
-        async move {
-            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
-            Ok(())
-        }
+    /// Returns a filter matching the files that should be indexed.
+    fn index_filter(&self) -> Arc<ResolvedSearchFilter> {
+        let filter = SearchFilter {
+            include_globs: self.include_globs.clone(),
+            exclude_globs: self.exclude_globs.clone(),
+            ..Default::default()
+        };
+        Arc::new(filter.resolve(&self.language_registry))
     }
 
-    fn scan_entries(&self, worktree: Snapshot, cx: &AppContext) -> ScanEntries {
-        let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
-        let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
+    /// Compares the index with the files of the worktree. Files that changed since
+    /// they were indexed aren't problems, since they're indexed again on their own.
+    fn verify(&self, cx: &AppContext) -> Task<Result<IndexVerification>> {
+        let worktree = self.worktree.read(cx).snapshot();
         let db_connection = self.db_connection.clone();
         let db = self.db;
+        let fallback_db = self.fallback_db;
+        let retry_backlog = self.retry_backlog;
+        let file_loader = self.file_loader.clone();
         let entries_being_indexed = self.entry_ids_being_indexed.clone();
-        let task = cx.background_executor().spawn(async move {
-            let txn = db_connection
-                .read_txn()
-                .context("failed to create read transaction")?;
-            let mut db_entries = db
-                .iter(&txn)
-                .context("failed to create iterator")?
-                .move_between_keys()
-                .peekable();
-
-            let mut deletion_range: Option<(Bound<&str>, Bound<&str>)> = None;
-            for entry in worktree.files(false, 0) {
-                let entry_db_key = db_key_for_path(&entry.path);
-
-                let mut saved_mtime = None;
-                while let Some(db_entry) = db_entries.peek() {
-                    match db_entry {
-                        Ok((db_path, db_embedded_file)) => match (*db_path).cmp(&entry_db_key) {
-                            Ordering::Less => {
-                                if let Some(deletion_range) = deletion_range.as_mut() {
-                                    deletion_range.1 = Bound::Included(db_path);
-                                } else {
-                                    deletion_range =
-                                        Some((Bound::Included(db_path), Bound::Included(db_path)));
-                                }
-
-                                db_entries.next();
-                            }
-                            Ordering::Equal => {
-                                if let Some(deletion_range) = deletion_range.take() {
-                                    deleted_entry_ranges_tx
-                                        .send((
-                                            deletion_range.0.map(ToString::to_string),
-                                            deletion_range.1.map(ToString::to_string),
-                                        ))
-                                        .await?;
-                                }
-                                saved_mtime = db_embedded_file.mtime;
-                                db_entries.next();
-                                break;
-                            }
-                            Ordering::Greater => {
-                                break;
-                            }
-                        },
-                        Err(_) => return Err(db_entries.next().unwrap().unwrap_err())?,
-                    }
+        let filter = self.index_filter();
+        let settings = Self::settings(&self.worktree, cx);
+        let file_limits = FileLimits::from_settings(settings);
+        cx.background_executor().spawn(async move {
+            let (indexed_files, failed_paths) = {
+                let txn = db_connection
+                    .read_txn()
+                    .context("failed to create read transaction")?;
+                // Files embedded by a fallback provider count as indexed until they're
+                // embedded again.
+                let fallback_files = fallback_db
+                    .iter(&txn)?
+                    .map(|entry| entry.map(|(key, fallback)| (key, fallback.file)));
+                let indexed_files = db
+                    .iter(&txn)?
+                    .chain(fallback_files)
+                    .map(|entry| {
+                        let (key, file) = entry?;
+                        Ok((
+                            key.to_string(),
+                            file.path,
+                            file.mtime,
+                            file.digest,
+                            !file.chunks.is_empty(),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let failed_paths = retry_backlog
+                    .all(&txn)?
+                    .into_iter()
+                    .map(|file| file.path)
+                    .collect::<HashSet<_>>();
+                (indexed_files, failed_paths)
+            };
+
+            let mut verification = IndexVerification::new(worktree.id());
+            let mut indexed_keys = HashSet::default();
+            for (key, path, mtime, digest, has_chunks) in indexed_files {
+                let Some(entry) = indexed_entry(&worktree, &filter, &path) else {
+                    verification.orphaned_paths.push(path);
+                    continue;
+                };
+                indexed_keys.insert(key);
+                // Files without chunks were skipped, so their text wasn't digested.
+                if entry.mtime != mtime || !has_chunks {
+                    continue;
                 }
-
-                if entry.mtime != saved_mtime {
-                    let handle = entries_being_indexed.insert(entry.id);
-                    updated_entries_tx.send((entry.clone(), handle)).await?;
+                let Some(text) = file_loader
+                    .load(
+                        worktree.id(),
+                        worktree.abs_path(),
+                        &path,
+                        file_limits.max_size(&path).saturating_add(1),
+                    )
+                    .await
+                    .ok()
+                    .and_then(|bytes| file_text(&path, bytes, file_limits).ok())
+                else {
+                    continue;
+                };
+                if text_digest(&text) != digest {
+                    verification.mismatched_paths.push(path);
                 }
             }
 
-            if let Some(db_entry) = db_entries.next() {
-                let (db_path, _) = db_entry?;
-                deleted_entry_ranges_tx
-                    .send((Bound::Included(db_path.to_string()), Bound::Unbounded))
-                    .await?;
+            for entry in worktree
+                .files(false, 0)
+                .filter(|entry| filter.matches(&entry.path, None))
+            {
+                if !indexed_keys.contains(&db_key_for_path(&entry.path))
+                    && !failed_paths.contains(&entry.path)
+                    && !entries_being_indexed.contains(entry.id)
+                {
+                    verification.missing_paths.push(entry.path.clone());
+                }
             }
 
-            Ok(())
-        });
-
-        ScanEntries {
-            updated_entries: updated_entries_rx,
-            deleted_entry_ranges: deleted_entry_ranges_rx,
-            task,
-        }
+            Ok(verification)
+        })
     }
 
-    fn scan_updated_entries(
-        &self,
-        worktree: Snapshot,
-        updated_entries: UpdatedEntriesSet,
-        cx: &AppContext,
-    ) -> ScanEntries {
-        let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
-        let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
-        let entries_being_indexed = self.entry_ids_being_indexed.clone();
-        let task = cx.background_executor().spawn(async move {
-            for (path, entry_id, status) in updated_entries.iter() {
-                match status {
-                    project::PathChange::Added
-                    | project::PathChange::Updated
-                    | project::PathChange::AddedOrUpdated => {
-                        if let Some(entry) = worktree.entry_for_id(*entry_id) {
-                            if entry.is_file() {
-                                let handle = entries_being_indexed.insert(entry.id);
-                                updated_entries_tx.send((entry.clone(), handle)).await?;
-                            }
-                        }
-                    }
-                    project::PathChange::Removed => {
-                        let db_path = db_key_for_path(path);
-                        deleted_entry_ranges_tx
-                            .send((Bound::Included(db_path.clone()), Bound::Included(db_path)))
-                            .await?;
-                    }
-                    project::PathChange::Loaded => {
-                        // Do nothing.
-                    }
-                }
+    /// Removes the orphaned and mismatched files found by [`Self::verify`] from the
+    /// index, and rescans the worktree to index the mismatched and missing ones.
+    fn repair(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<IndexVerification>> {
+        let verify = self.verify(cx);
+        cx.spawn(|this, mut cx| async move {
+            let verification = verify.await?;
+            if verification.is_empty() {
+                return Ok(verification);
             }
+            log::info!("repairing the index: {verification:?}");
+
+            let delete = this.update(&mut cx, |this, cx| {
+                this.delete_paths(
+                    verification
+                        .orphaned_paths
+                        .iter()
+                        .chain(&verification.mismatched_paths),
+                    cx,
+                )
+            })?;
+            delete.await?;
 
-            Ok(())
-        });
-
-        ScanEntries {
-            updated_entries: updated_entries_rx,
-            deleted_entry_ranges: deleted_entry_ranges_rx,
-            task,
-        }
+            this.update(&mut cx, |this, _| {
+                this.index_requests_tx.try_send(IndexRequest::Rescan).ok();
+            })?;
+            Ok(verification)
+        })
     }
 
-    fn chunk_files(
-        &self,
-        worktree_abs_path: Arc<Path>,
-        entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
-        cx: &AppContext,
-    ) -> ChunkFiles {
+    /// Estimates what indexing every file of the worktree from scratch would take,
+    /// reading and chunking the files like indexing does without embedding them.
+    fn estimate(&self, cx: &AppContext) -> Task<IndexEstimate> {
+        let worktree = self.worktree.read(cx).snapshot();
+        let filter = self.index_filter();
+        let file_loader = self.file_loader.clone();
         let language_registry = self.language_registry.clone();
-        let fs = self.fs.clone();
-        let (chunked_files_tx, chunked_files_rx) = channel::bounded(2048);
-        let task = cx.spawn(|cx| async move {
-            cx.background_executor()
-                .scoped(|cx| {
-                    for _ in 0..cx.num_cpus() {
-                        cx.spawn(async {
-                            while let Ok((entry, handle)) = entries.recv().await {
-                                let entry_abs_path = worktree_abs_path.join(&entry.path);
-                                let Some(text) = fs
-                                    .load(&entry_abs_path)
-                                    .await
-                                    .with_context(|| {
-                                        format!("failed to read path {entry_abs_path:?}")
-                                    })
-                                    .log_err()
-                                else {
-                                    continue;
-                                };
-                                let language = language_registry
-                                    .language_for_file_path(&entry.path)
-                                    .await
-                                    .ok();
-                                let chunked_file = ChunkedFile {
-                                    chunks: chunk_text(&text, language.as_ref(), &entry.path),
-                                    handle,
-                                    path: entry.path,
-                                    mtime: entry.mtime,
-                                    text,
-                                };
-
-                                if chunked_files_tx.send(chunked_file).await.is_err() {
-                                    return;
-                                }
-                            }
-                        });
-                    }
-                })
-                .await;
-            Ok(())
-        });
-
-        ChunkFiles {
-            files: chunked_files_rx,
-            task,
-        }
-    }
-
-    fn embed_files(
-        embedding_provider: Arc<dyn EmbeddingProvider>,
-        chunked_files: channel::Receiver<ChunkedFile>,
-        cx: &AppContext,
-    ) -> EmbedFiles {
-        let embedding_provider = embedding_provider.clone();
-        let (embedded_files_tx, embedded_files_rx) = channel::bounded(512);
-        let task = cx.background_executor().spawn(async move {
-            let mut chunked_file_batches =
-                chunked_files.chunks_timeout(512, Duration::from_secs(2));
-            while let Some(chunked_files) = chunked_file_batches.next().await {
-                // View the batch of files as a vec of chunks
-                // Flatten out to a vec of chunks that we can subdivide into batch sized pieces
-                // Once those are done, reassemble them back into the files in which they belong
-                // If any embeddings fail for a file, the entire file is discarded
-
-                let chunks: Vec<TextToEmbed> = chunked_files
-                    .iter()
-                    .flat_map(|file| {
-                        file.chunks.iter().map(|chunk| TextToEmbed {
-                            text: &file.text[chunk.range.clone()],
-                            digest: chunk.digest,
-                        })
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut embeddings: Vec<Option<Embedding>> = Vec::new();
-                for embedding_batch in chunks.chunks(embedding_provider.batch_size()) {
-                    if let Some(batch_embeddings) =
-                        embedding_provider.embed(embedding_batch).await.log_err()
-                    {
-                        if batch_embeddings.len() == embedding_batch.len() {
-                            embeddings.extend(batch_embeddings.into_iter().map(Some));
-                            continue;
-                        }
-                        log::error!(
-                            "embedding provider returned unexpected embedding count {}, expected {}",
-                            batch_embeddings.len(), embedding_batch.len()
+        let embedding_provider = self.embedding_provider.clone();
+        let chunk_config = self.chunk_config;
+        let chunking_strategies = ChunkingStrategyRegistry::global(cx);
+        let settings = Self::settings(&self.worktree, cx);
+        let file_limits = FileLimits::from_settings(settings);
+        cx.background_executor().spawn(async move {
+            let mut estimate = IndexEstimate::default();
+            for entry in worktree
+                .files(false, 0)
+                .filter(|entry| filter.matches(&entry.path, None))
+            {
+                let Some(bytes) = file_loader
+                    .load(
+                        worktree.id(),
+                        worktree.abs_path(),
+                        &entry.path,
+                        file_limits.max_size(&entry.path).saturating_add(1),
+                    )
+                    .await
+                    .log_err()
+                else {
+                    continue;
+                };
+                match file_text(&entry.path, bytes, file_limits) {
+                    Ok(text) => {
+                        let language = language_registry
+                            .language_for_file_path(&entry.path)
+                            .await
+                            .ok();
+                        let strategy = language
+                            .as_ref()
+                            .and_then(|language| chunking_strategies.strategy(&language.name()));
+                        let chunks = chunk_text(
+                            &text,
+                            language.as_ref(),
+                            &entry.path,
+                            chunk_config,
+                            strategy.as_deref(),
+                            &|text| embedding_provider.count_tokens(text),
                         );
+                        estimate.add_file(chunks.iter().map(|chunk| {
+                            embedding_provider.count_tokens(&chunk.text_to_embed(&text))
+                        }));
                     }
-
-                    embeddings.extend(iter::repeat(None).take(embedding_batch.len()));
-                }
-
-                let mut embeddings = embeddings.into_iter();
-                for chunked_file in chunked_files {
-                    let mut embedded_file = EmbeddedFile {
-                        path: chunked_file.path,
-                        mtime: chunked_file.mtime,
-                        chunks: Vec::new(),
-                    };
-
-                    let mut embedded_all_chunks = true;
-                    for (chunk, embedding) in
-                        chunked_file.chunks.into_iter().zip(embeddings.by_ref())
-                    {
-                        if let Some(embedding) = embedding {
-                            embedded_file
-                                .chunks
-                                .push(EmbeddedChunk { chunk, embedding });
-                        } else {
-                            embedded_all_chunks = false;
-                        }
-                    }
-
-                    if embedded_all_chunks {
-                        embedded_files_tx
-                            .send((embedded_file, chunked_file.handle))
-                            .await?;
-                    }
+                    Err(_) => estimate.add_skipped_file(),
                 }
             }
-            Ok(())
-        });
-
-        EmbedFiles {
-            files: embedded_files_rx,
-            task,
-        }
+            estimate
+        })
     }
 
-    fn persist_embeddings(
-        &self,
-        mut deleted_entry_ranges: channel::Receiver<(Bound<String>, Bound<String>)>,
-        embedded_files: channel::Receiver<(EmbeddedFile, IndexingEntryHandle)>,
-        cx: &AppContext,
-    ) -> Task<Result<()>> {
+    /// Describes the state of the index, reading the counts from the database.
+    fn diagnostics(&self, cx: &AppContext) -> Task<Result<WorktreeDiagnostics>> {
+        let worktree = self.worktree.read(cx);
+        let abs_path = worktree.abs_path().to_path_buf();
+        let scan_id = worktree.scan_id();
         let db_connection = self.db_connection.clone();
         let db = self.db;
+        let fallback_db = self.fallback_db;
+        let skipped_files = self.skipped_files;
+        let retry_backlog = self.retry_backlog;
+        let pending_file_count = self.entry_ids_being_indexed.len();
+        let embedding_model = self.embedding_model();
+        let pinned = self.pinned_model.is_some();
+        let chunk_config = self.chunk_config;
+        let checkpoint = self.checkpoint;
         cx.background_executor().spawn(async move {
-            while let Some(deletion_range) = deleted_entry_ranges.next().await {
-                let mut txn = db_connection.write_txn()?;
-                let start = deletion_range.0.as_ref().map(|start| start.as_str());
-                let end = deletion_range.1.as_ref().map(|end| end.as_str());
-                log::debug!("deleting embeddings in range {:?}", &(start, end));
-                db.delete_range(&mut txn, &(start, end))?;
-                txn.commit()?;
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let mut file_count = 0;
+            let mut chunk_count = 0;
+            for entry in db.iter(&txn)? {
+                let (_, file) = entry?;
+                file_count += 1;
+                chunk_count += file.chunks.len() as u64;
             }
+            Ok(WorktreeDiagnostics {
+                abs_path,
+                file_count,
+                chunk_count,
+                skipped_file_count: skipped_files.all(&txn)?.len(),
+                fallback_file_count: fallback_db.len(&txn)?,
+                pending_file_count,
+                failed_files: retry_backlog.all(&txn)?,
+                database_size: db_connection.real_disk_size()?,
+                embedding_model,
+                pinned,
+                chunk_config,
+                scan_id,
+                fully_indexed_scan_id: checkpoint.fully_indexed_scan_id,
+                fully_indexed_at: checkpoint.fully_indexed_at,
+                scan_in_progress: checkpoint.scan_in_progress,
+            })
+        })
+    }
 
-            let mut embedded_files = embedded_files.chunks_timeout(4096, Duration::from_secs(2));
-            while let Some(embedded_files) = embedded_files.next().await {
-                let mut txn = db_connection.write_txn()?;
-                for (file, _) in &embedded_files {
-                    log::debug!("saving embedding for file {:?}", file.path);
-                    let key = db_key_for_path(&file.path);
-                    db.put(&mut txn, &key, file)?;
+    /// Removes the files that were deleted or shouldn't be indexed anymore from the
+    /// index, returning how many were removed. Scanning every file removes them too,
+    /// but only happens when the worktree is loaded or the files to index change.
+    fn collect_garbage(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<usize>> {
+        let worktree = self.worktree.read(cx);
+        // Files that weren't scanned yet would look deleted.
+        if worktree.completed_scan_id() < worktree.scan_id() {
+            return Task::ready(Ok(0));
+        }
+        let worktree = worktree.snapshot();
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
+        let filter = self.index_filter();
+        let orphaned_paths = cx.background_executor().spawn(async move {
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let mut orphaned_paths = Vec::new();
+            for entry in db.iter(&txn)? {
+                let (_, file) = entry?;
+                if indexed_entry(&worktree, &filter, &file.path).is_none() {
+                    orphaned_paths.push(file.path);
                 }
-                txn.commit()?;
-
-                drop(embedded_files);
-                log::debug!("committed");
             }
-
-            Ok(())
+            anyhow::Ok(orphaned_paths)
+        });
+        cx.spawn(|this, mut cx| async move {
+            let orphaned_paths = orphaned_paths.await?;
+            if orphaned_paths.is_empty() {
+                return Ok(0);
+            }
+            log::info!(
+                "removing {} stale files from the index",
+                orphaned_paths.len()
+            );
+            let delete = this.update(&mut cx, |this, cx| this.delete_paths(&orphaned_paths, cx))?;
+            delete.await?;
+            Ok(orphaned_paths.len())
         })
     }
 
+    /// Removes files from every database of the index.
+    fn delete_paths<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Arc<Path>>,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::unbounded();
+        for path in paths {
+            let db_key = db_key_for_path(path);
+            deleted_entry_ranges_tx
+                .try_send((Bound::Included(db_key.clone()), Bound::Included(db_key)))
+                .ok();
+        }
+        drop(deleted_entry_ranges_tx);
+        let (_, embedded_files_rx) = channel::bounded(1);
+        let (_, moved_files_rx) = channel::bounded(1);
+        let (_, fallback_files_rx) = channel::bounded(1);
+        let (_, failed_files_rx) = channel::bounded(1);
+        self.persist_embeddings(
+            deleted_entry_ranges_rx,
+            embedded_files_rx,
+            moved_files_rx,
+            fallback_files_rx,
+            failed_files_rx,
+            cx,
+        )
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            self.resume_tx.try_send(()).ok();
+        }
+    }
+
     fn paths(&self, cx: &AppContext) -> Task<Result<Vec<Arc<Path>>>> {
         let connection = self.db_connection.clone();
         let db = self.db;
@@ -922,26 +1977,64 @@ impl WorktreeIndex {
         })
     }
 
-    fn chunks_for_path(
-        &self,
-        path: Arc<Path>,
-        cx: &AppContext,
-    ) -> Task<Result<Vec<EmbeddedChunk>>> {
+    fn skipped_files(&self, cx: &AppContext) -> Task<Result<Vec<(Arc<Path>, SkipReason)>>> {
+        let connection = self.db_connection.clone();
+        let skipped_files = self.skipped_files;
+        cx.background_executor().spawn(async move {
+            let txn = connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            skipped_files.all(&txn)
+        })
+    }
+
+    /// Reads the embeddings of every indexed file, at full precision when they're
+    /// stored binary quantized. Int8 quantized embeddings are dequantized.
+    fn archived_files(&self, cx: &AppContext) -> Task<Result<Vec<ArchivedFile>>> {
+        let connection = self.db_connection.clone();
+        let db = self.db;
+        let full_precision_embeddings = self.full_precision_embeddings;
+        cx.background_executor().spawn(async move {
+            let txn = connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let mut files = Vec::new();
+            for entry in db.iter(&txn)? {
+                let (key, file) = entry?;
+                if file.chunks.is_empty() {
+                    continue;
+                }
+                let file = full_precision_embeddings.get(&txn, key)?.unwrap_or(file);
+                files.push(ArchivedFile {
+                    path: file.path,
+                    chunks: file
+                        .chunks
+                        .into_iter()
+                        .map(|chunk| ArchivedChunk {
+                            range: chunk.chunk.range,
+                            digest: chunk.chunk.digest,
+                            embedding: chunk.embedding.dequantize(),
+                        })
+                        .collect(),
+                });
+            }
+            Ok(files)
+        })
+    }
+
+    fn embedded_file(&self, path: Arc<Path>, cx: &AppContext) -> Task<Result<EmbeddedFile>> {
         let connection = self.db_connection.clone();
         let db = self.db;
         cx.background_executor().spawn(async move {
             let tx = connection
                 .read_txn()
                 .context("failed to create read transaction")?;
-            Ok(db
-                .get(&tx, &db_key_for_path(&path))?
-                .ok_or_else(|| anyhow!("no such path"))?
-                .chunks
-                .clone())
+            db.get(&tx, &db_key_for_path(&path))?
+                .ok_or_else(|| anyhow!("no such path"))
         })
     }
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-support"))]
     fn path_count(&self) -> Result<u64> {
         let txn = self
             .db_connection
@@ -951,84 +2044,31 @@ impl WorktreeIndex {
     }
 }
 
-struct ScanEntries {
-    updated_entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
-    deleted_entry_ranges: channel::Receiver<(Bound<String>, Bound<String>)>,
-    task: Task<Result<()>>,
-}
-
-struct ChunkFiles {
-    files: channel::Receiver<ChunkedFile>,
-    task: Task<Result<()>>,
-}
-
-struct ChunkedFile {
-    pub path: Arc<Path>,
-    pub mtime: Option<SystemTime>,
-    pub handle: IndexingEntryHandle,
-    pub text: String,
-    pub chunks: Vec<Chunk>,
-}
-
-struct EmbedFiles {
-    files: channel::Receiver<(EmbeddedFile, IndexingEntryHandle)>,
-    task: Task<Result<()>>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddedFile {
-    path: Arc<Path>,
-    mtime: Option<SystemTime>,
-    chunks: Vec<EmbeddedChunk>,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct EmbeddedChunk {
-    chunk: Chunk,
-    embedding: Embedding,
-}
-
-/// The set of entries that are currently being indexed.
-struct IndexingEntrySet {
-    entry_ids: Mutex<HashSet<ProjectEntryId>>,
-    tx: channel::Sender<()>,
-}
-
-/// When dropped, removes the entry from the set of entries that are being indexed.
-#[derive(Clone)]
-struct IndexingEntryHandle {
-    entry_id: ProjectEntryId,
-    set: Weak<IndexingEntrySet>,
-}
-
-impl IndexingEntrySet {
-    fn new(tx: channel::Sender<()>) -> Self {
-        Self {
-            entry_ids: Default::default(),
-            tx,
-        }
-    }
-
-    fn insert(self: &Arc<Self>, entry_id: ProjectEntryId) -> IndexingEntryHandle {
-        self.entry_ids.lock().insert(entry_id);
-        self.tx.send_blocking(()).ok();
-        IndexingEntryHandle {
-            entry_id,
-            set: Arc::downgrade(self),
+/// Returns how many dimensions the embeddings in a worktree's index have, from its first
+/// embedded chunk, or `None` if it has none.
+fn stored_embedding_dimensions(
+    db: &EncryptedDatabase<EmbeddedFile>,
+    txn: &heed::RoTxn,
+) -> Result<Option<usize>> {
+    for entry in db.iter(txn)? {
+        let (_, file) = entry?;
+        if let Some(chunk) = file.chunks.first() {
+            return Ok(Some(chunk.embedding.dimensions()));
         }
     }
-
-    pub fn len(&self) -> usize {
-        self.entry_ids.lock().len()
-    }
+    Ok(None)
 }
 
-impl Drop for IndexingEntryHandle {
-    fn drop(&mut self) {
-        if let Some(set) = self.set.upgrade() {
-            set.tx.send_blocking(()).ok();
-            set.entry_ids.lock().remove(&self.entry_id);
-        }
+/// Identifies the host of a remote project across sessions: the SSH connection it's
+/// opened over, its dev server project, or the user sharing it.
+fn remote_host_name(project: &Project) -> Option<String> {
+    if let Some(connection_string) = project.ssh_session_connection_string() {
+        Some(format!("ssh:{connection_string}"))
+    } else if let Some(dev_server_project_id) = project.dev_server_project_id() {
+        Some(format!("dev_server_project:{}", dev_server_project_id.0))
+    } else {
+        let host = project.host()?;
+        Some(format!("user:{}", host.user_id))
     }
 }
 
@@ -1039,12 +2079,18 @@ fn db_key_for_path(path: &Arc<Path>) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chunking::Chunk;
+    use db_connection::{TEST_MAP_SIZE, TEST_MAX_DBS};
     use futures::{future::BoxFuture, FutureExt};
     use gpui::TestAppContext;
+    use indexing::ChunkedFile;
+    use kv_store::KvStorage;
     use language::language_settings::AllLanguageSettings;
-    use project::Project;
+    use parking_lot::Mutex;
+    use project::{Project, ProjectEntryId};
     use settings::SettingsStore;
-    use std::{future, path::Path, sync::Arc};
+    use shared_embeddings::SharedEmbeddings;
+    use std::{future, ops::Range, path::Path, sync::Arc, time::SystemTime};
 
     fn init_test(cx: &mut TestAppContext) {
         cx.update(|cx| {
@@ -1052,6 +2098,7 @@ mod tests {
             cx.set_global(store);
             language::init(cx);
             Project::init_settings(cx);
+            crate::init(cx);
             SettingsStore::update(cx, |store, cx| {
                 store.update_user_settings::<AllLanguageSettings>(cx, |_| {});
             });
@@ -1090,6 +2137,14 @@ mod tests {
         fn batch_size(&self) -> usize {
             self.batch_size
         }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            EmbeddingModelInfo {
+                provider: "test".into(),
+                model: "test".into(),
+                dimensions: (self.compute_embedding)("").map_or(0, |embedding| embedding.len()),
+            }
+        }
     }
 
     #[gpui::test]
@@ -1143,14 +2198,14 @@ mod tests {
             .unwrap()
             == 0
         {
-            project_index.next_event(cx).await;
+            project_index.next_event::<Status>(cx).await;
         }
 
         let results = cx
             .update(|cx| {
                 let project_index = project_index.read(cx);
                 let query = "garbage in, garbage out";
-                project_index.search(query.into(), 4, cx)
+                project_index.search(query.into(), SearchFilter::default(), 4, None, None, cx)
             })
             .await
             .unwrap();
@@ -1184,74 +2239,836 @@ mod tests {
     }
 
     #[gpui::test]
-    async fn test_embed_files(cx: &mut TestAppContext) {
+    async fn test_search_dirty_buffer_that_fails_to_embed(cx: &mut TestAppContext) {
         cx.executor().allow_parking();
 
-        let provider = Arc::new(TestEmbeddingProvider::new(3, |text| {
-            if text.contains('g') {
-                Err(anyhow!("cannot embed text containing a 'g' character"))
-            } else {
-                Ok(Embedding::new(
-                    ('a'..='z')
-                        .map(|char| text.chars().filter(|c| *c == char).count() as f32)
-                        .collect(),
-                ))
-            }
-        }));
+        init_test(cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
 
-        let (indexing_progress_tx, _) = channel::unbounded();
-        let indexing_entries = Arc::new(IndexingEntrySet::new(indexing_progress_tx));
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                if text.contains("unembeddable") {
+                    return Err(anyhow!("failed to embed"));
+                }
+                let embedding = if text.contains("garbage in") {
+                    vec![0.9, 0.9]
+                } else {
+                    vec![-0.9, -0.9]
+                };
+                Ok(Embedding::new(embedding))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+
+        let project_path = Path::new("./fixture");
+
+        let project = cx
+            .spawn(|mut cx| async move { Project::example([project_path], &mut cx).await })
+            .await;
+
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            == 0
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+
+        let worktree_id = project.read_with(cx, |project, cx| {
+            project.worktrees(cx).next().unwrap().read(cx).id()
+        });
+        let buffer = project
+            .update(cx, |project, cx| {
+                project.open_buffer((worktree_id, "needle.md"), cx)
+            })
+            .await
+            .unwrap();
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "unembeddable\n")], None, cx);
+        });
+
+        // The stored chunks of the buffer are searched, since its unsaved contents
+        // couldn't be embedded.
+        let results = cx
+            .update(|cx| {
+                let project_index = project_index.read(cx);
+                let query = "garbage in, garbage out";
+                project_index.search(query.into(), SearchFilter::default(), 4, None, None, cx)
+            })
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .any(|result| result.path.as_ref() == Path::new("needle.md")));
+    }
+
+    #[gpui::test]
+    async fn test_search_dirty_buffer(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        init_test(cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let embedding = if text.contains("zebra") {
+                    vec![0.9, 0.9]
+                } else {
+                    vec![-0.9, -0.9]
+                };
+                Ok(Embedding::new(embedding))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+
+        let project_path = Path::new("./fixture");
+
+        let project = cx
+            .spawn(|mut cx| async move { Project::example([project_path], &mut cx).await })
+            .await;
+
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            == 0
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+
+        let worktree_id = project.read_with(cx, |project, cx| {
+            project.worktrees(cx).next().unwrap().read(cx).id()
+        });
+        let buffer = project
+            .update(cx, |project, cx| {
+                project.open_buffer((worktree_id, "needle.md"), cx)
+            })
+            .await
+            .unwrap();
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "zebra crossing\n")], None, cx);
+        });
+
+        // The unsaved contents of the buffer are searched, and the results point into
+        // them rather than into the file on disk.
+        let results = cx
+            .update(|cx| {
+                let project_index = project_index.read(cx);
+                project_index.search("zebra".into(), SearchFilter::default(), 4, None, None, cx)
+            })
+            .await
+            .unwrap();
+        let top_result = results
+            .iter()
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+            .unwrap();
+        assert_eq!(top_result.path.as_ref(), Path::new("needle.md"));
+        let text = buffer.read_with(cx, |buffer, _| buffer.text());
+        assert!(text[top_result.range.clone()].contains("zebra crossing"));
+    }
+
+    #[gpui::test]
+    async fn test_search_min_score_ignores_weights(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        init_test(cx);
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<SemanticIndexSettings>(cx, |settings| {
+                    settings.path_weights = Some(HashMap::from_iter([("needle.md".into(), 0.5)]));
+                });
+            });
+        });
+
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let embedding = if text.contains("garbage in") {
+                    vec![0.9, 0.9]
+                } else {
+                    vec![-0.9, -0.9]
+                };
+                Ok(Embedding::new(embedding))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+
+        let project_path = Path::new("./fixture");
+
+        let project = cx
+            .spawn(|mut cx| async move { Project::example([project_path], &mut cx).await })
+            .await;
+
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            == 0
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+
+        // The needle is similar enough to the query, even though its score is halved by
+        // the weight of its path.
+        let results = cx
+            .update(|cx| {
+                let project_index = project_index.read(cx);
+                let query = "garbage in, garbage out";
+                project_index.search(
+                    query.into(),
+                    SearchFilter::default(),
+                    4,
+                    Some(0.9),
+                    None,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        assert!(!results.is_empty());
+        for result in &results {
+            assert_eq!(result.path.as_ref(), Path::new("needle.md"));
+            assert_eq!(result.weight, 0.5);
+            assert!(result.score < 0.9);
+        }
+    }
+
+    #[gpui::test]
+    async fn test_reindex_database_from_before_quantization(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        init_test(cx);
+
+        // How files were stored before embeddings could be quantized, and before the
+        // format of the database was versioned.
+        #[derive(Serialize)]
+        struct UnquantizedFile {
+            path: Arc<Path>,
+            mtime: Option<SystemTime>,
+            chunks: Vec<UnquantizedChunk>,
+        }
+        #[derive(Serialize)]
+        struct UnquantizedChunk {
+            range: Range<usize>,
+            digest: [u8; 32],
+            embedding: Vec<f32>,
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project = cx
+            .spawn(
+                |mut cx| async move { Project::example([Path::new("./fixture")], &mut cx).await },
+            )
+            .await;
+        cx.update(|cx| {
+            let language_registry = project.read(cx).languages().clone();
+            let node_runtime = project.read(cx).node_runtime().unwrap().clone();
+            languages::init(language_registry, node_runtime, cx);
+        });
+        let worktree_abs_path = project.read_with(cx, |project, cx| {
+            project.worktrees(cx).next().unwrap().read(cx).abs_path()
+        });
+
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        db_connection
+            .write(|txn| {
+                let db = db_connection.create_database::<Str, SerdeBincode<UnquantizedFile>>(
+                    txn,
+                    Some(&worktree_abs_path.to_string_lossy()),
+                )?;
+                let path: Arc<Path> = Path::new("stale.md").into();
+                db.put(
+                    txn,
+                    &db_key_for_path(&path),
+                    &UnquantizedFile {
+                        path: path.clone(),
+                        mtime: None,
+                        chunks: vec![UnquantizedChunk {
+                            range: 0..32,
+                            digest: [0; 32],
+                            embedding: vec![0.6, 0.8, 0., 0.],
+                        }],
+                    },
+                )?;
+                Ok(())
+            })
+            .unwrap();
+        drop(db_connection);
+
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                Ok(Embedding::new(vec![garbage, 1. - garbage]))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        // The old index is dropped and every file is indexed again, instead of failing
+        // to read the old embeddings.
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            < 2
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+        assert_eq!(
+            project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap(),
+            2
+        );
+
+        let results = cx
+            .update(|cx| {
+                project_index.read(cx).search(
+                    "garbage in, garbage out".into(),
+                    SearchFilter::default(),
+                    4,
+                    None,
+                    None,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        assert_eq!(results[0].path.to_string_lossy(), "needle.md");
+        assert!(results
+            .iter()
+            .all(|result| result.path.as_ref() != Path::new("stale.md")));
+    }
+
+    #[gpui::test]
+    async fn test_rename_unchanged_file_reuses_embeddings(cx: &mut TestAppContext) {
+        use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+        cx.executor().allow_parking();
+
+        init_test(cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            project_dir.path().join("needle.md"),
+            "# Needle\n\ngarbage in, garbage out\n",
+        )
+        .unwrap();
+        std::fs::write(project_dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let embed_count = Arc::new(AtomicUsize::new(0));
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, {
+                let embed_count = embed_count.clone();
+                move |text| {
+                    if !text.is_empty() {
+                        embed_count.fetch_add(1, SeqCst);
+                    }
+                    let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                    Ok(Embedding::new(vec![garbage, 1. - garbage]))
+                }
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+
+        let project_path = project_dir.path().to_path_buf();
+        let project = cx
+            .spawn(
+                |mut cx| async move { Project::example([project_path.as_path()], &mut cx).await },
+            )
+            .await;
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            < 2
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+        let embeds_before_rename = embed_count.load(SeqCst);
+        assert!(embeds_before_rename > 0);
+
+        std::fs::rename(
+            project_dir.path().join("needle.md"),
+            project_dir.path().join("haystack.md"),
+        )
+        .unwrap();
+
+        // Subscribe before searching, so that indexing can't finish unnoticed while
+        // the search runs.
+        loop {
+            let next_event = project_index.next_event::<Status>(cx);
+            let results = cx
+                .update(|cx| {
+                    project_index.read(cx).search(
+                        "garbage in, garbage out".into(),
+                        SearchFilter::default(),
+                        4,
+                        None,
+                        None,
+                        cx,
+                    )
+                })
+                .await
+                .unwrap();
+            if results
+                .iter()
+                .all(|result| result.path.as_ref() != Path::new("needle.md"))
+                && results
+                    .iter()
+                    .any(|result| result.path.as_ref() == Path::new("haystack.md"))
+            {
+                break;
+            }
+            next_event.await;
+        }
+
+        // The moved file kept its chunks, so nothing had to be embedded again.
+        assert_eq!(embed_count.load(SeqCst), embeds_before_rename);
+        assert_eq!(
+            project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap(),
+            2
+        );
+    }
+
+    #[gpui::test]
+    async fn test_reembed_when_embedding_model_changes(cx: &mut TestAppContext) {
+        use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+        cx.executor().allow_parking();
+
+        init_test(cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                Ok(Embedding::new(vec![garbage, 1. - garbage]))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+        cx.update(|cx| cx.set_global(semantic_index));
+
+        let project = cx
+            .spawn(
+                |mut cx| async move { Project::example([Path::new("./fixture")], &mut cx).await },
+            )
+            .await;
+        let project_index = cx.update(|cx| {
+            cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+                semantic_index.project_index(project.clone(), cx)
+            })
+        });
+        while project_index
+            .read_with(cx, |index, cx| index.path_count(cx))
+            .unwrap()
+            < 2
+        {
+            project_index.next_event::<Status>(cx).await;
+        }
+
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = cx.update(|cx| {
+            let statuses = statuses.clone();
+            cx.subscribe(&project_index, move |_, status: &Status, _| {
+                statuses.lock().push(*status);
+            })
+        });
+
+        // The new model has more dimensions, so none of the stored embeddings can be
+        // compared with its queries.
+        let embed_count = Arc::new(AtomicUsize::new(0));
+        cx.update(|cx| {
+            let embed_count = embed_count.clone();
+            cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+                semantic_index.set_embedding_provider(
+                    Arc::new(TestEmbeddingProvider::new(16, move |text| {
+                        if !text.is_empty() {
+                            embed_count.fetch_add(1, SeqCst);
+                        }
+                        let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                        Ok(Embedding::new(vec![garbage, 1. - garbage, 0.5]))
+                    })),
+                    cx,
+                )
+            })
+        })
+        .await
+        .unwrap();
+
+        let stored_dimensions = |cx: &mut TestAppContext| {
+            project_index.read_with(cx, |project_index, cx| {
+                let worktree_id = project.read(cx).worktrees(cx).next()?.read(cx).id();
+                let worktree_index = project_index.worktree_index(worktree_id, cx)?;
+                let worktree_index = worktree_index.read(cx);
+                let txn = worktree_index.db_connection.read_txn().unwrap();
+                let mut dimensions = Vec::new();
+                for entry in worktree_index.db.iter(&txn).unwrap() {
+                    let (_, file) = entry.unwrap();
+                    dimensions.extend(file.chunks.iter().map(|chunk| chunk.embedding.dimensions()));
+                }
+                Some(dimensions)
+            })
+        };
+        loop {
+            let next_event = project_index.next_event::<Status>(cx);
+            let is_idle = project_index.read_with(cx, |index, _| index.status()) == Status::Idle;
+            if is_idle
+                && project_index.read_with(cx, |index, cx| index.path_count(cx).unwrap()) == 2
+            {
+                break;
+            }
+            next_event.await;
+        }
+
+        // Every file was embedded again, and the old model's embeddings are gone.
+        assert!(embed_count.load(SeqCst) > 0);
+        let dimensions = stored_dimensions(cx).unwrap();
+        assert!(!dimensions.is_empty());
+        assert!(dimensions.iter().all(|dimensions| *dimensions == 3));
+        assert!(statuses
+            .lock()
+            .iter()
+            .any(|status| matches!(status, Status::Reembedding { .. })));
+
+        let results = cx
+            .update(|cx| {
+                project_index.read(cx).search(
+                    "garbage in, garbage out".into(),
+                    SearchFilter::default(),
+                    4,
+                    None,
+                    None,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        assert_eq!(results[0].path.to_string_lossy(), "needle.md");
+    }
+
+    #[gpui::test]
+    async fn test_index_include_and_exclude_globs(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        init_test(cx);
+        let set_globs = |include: &[&str], exclude: &[&str], cx: &mut TestAppContext| {
+            let include = include.iter().map(|glob| glob.to_string()).collect();
+            let exclude = exclude.iter().map(|glob| glob.to_string()).collect();
+            cx.update(|cx| {
+                SettingsStore::update(cx, |store, cx| {
+                    store.update_user_settings::<SemanticIndexSettings>(cx, |settings| {
+                        settings.include = Some(include);
+                        settings.exclude = Some(exclude);
+                    });
+                });
+            });
+        };
+        set_globs(&[], &["**/*.md"], cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                Ok(Embedding::new(vec![garbage, 1. - garbage]))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+        let project = cx
+            .spawn(
+                |mut cx| async move { Project::example([Path::new("./fixture")], &mut cx).await },
+            )
+            .await;
+        let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+
+        let indexed_paths = |cx: &mut TestAppContext| {
+            project_index.read_with(cx, |project_index, cx| {
+                if project_index.status() != Status::Idle {
+                    return None;
+                }
+                let worktree_id = project.read(cx).worktrees(cx).next()?.read(cx).id();
+                let worktree_index = project_index.worktree_index(worktree_id, cx)?;
+                let worktree_index = worktree_index.read(cx);
+                let txn = worktree_index.db_connection.read_txn().unwrap();
+                let mut paths = Vec::new();
+                for entry in worktree_index.db.iter(&txn).unwrap() {
+                    let (_, file) = entry.unwrap();
+                    paths.push(file.path.to_string_lossy().into_owned());
+                }
+                Some(paths)
+            })
+        };
+
+        // Excluded files are never indexed.
+        loop {
+            let next_event = project_index.next_event::<Status>(cx);
+            if indexed_paths(cx) == Some(vec!["main.rs".to_string()]) {
+                break;
+            }
+            next_event.await;
+        }
+
+        // Once the globs change, the files that aren't indexed anymore are removed,
+        // and the ones that are indexed now are added.
+        set_globs(&["**/*.md"], &[], cx);
+        loop {
+            let next_event = project_index.next_event::<Status>(cx);
+            if indexed_paths(cx) == Some(vec!["needle.md".to_string()]) {
+                break;
+            }
+            next_event.await;
+        }
+
+        let results = cx
+            .update(|cx| {
+                project_index.read(cx).search(
+                    "garbage in, garbage out".into(),
+                    SearchFilter::default(),
+                    4,
+                    None,
+                    None,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .all(|result| result.path.as_ref() == Path::new("needle.md")));
+    }
+
+    #[gpui::test]
+    async fn test_search_all(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        init_test(cx);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut semantic_index = SemanticIndex::new(
+            temp_dir.path().into(),
+            Arc::new(TestEmbeddingProvider::new(16, |text| {
+                let garbage = if text.contains("garbage in") { 1. } else { 0. };
+                Ok(Embedding::new(vec![garbage, 1. - garbage]))
+            })),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+
+        let project_dirs = [
+            ("needle.md", "garbage in, garbage out"),
+            ("haystack.md", "garbage in, garbage out, again"),
+        ]
+        .map(|(path, text)| {
+            let project_dir = tempfile::tempdir().unwrap();
+            std::fs::write(project_dir.path().join(path), text).unwrap();
+            std::fs::write(project_dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+            project_dir
+        });
+        let mut projects = Vec::new();
+        for project_dir in &project_dirs {
+            let project_path = project_dir.path().to_path_buf();
+            let project =
+                cx.spawn(|mut cx| async move {
+                    Project::example([project_path.as_path()], &mut cx).await
+                })
+                .await;
+            let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+            while project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap()
+                < 2
+            {
+                project_index.next_event::<Status>(cx).await;
+            }
+            projects.push(project);
+        }
+
+        // The best results of every project are ranked together.
+        let results = cx
+            .update(|cx| semantic_index.search_all("garbage in, garbage out".into(), 2, cx))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let mut found = results
+            .iter()
+            .map(|result| {
+                let project_ix = projects
+                    .iter()
+                    .position(|project| *project == result.project)
+                    .unwrap();
+                (
+                    project_ix,
+                    result.result.path.to_string_lossy().into_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![(0, "needle.md".to_string()), (1, "haystack.md".to_string())]
+        );
+        assert!(results[0].result.score >= results[1].result.score);
+    }
+
+    #[gpui::test]
+    async fn test_embed_files(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        let provider = Arc::new(TestEmbeddingProvider::new(3, |text| {
+            if text.contains('g') {
+                Err(anyhow!("cannot embed text containing a 'g' character"))
+            } else {
+                Ok(Embedding::new(
+                    ('a'..='z')
+                        .map(|char| text.chars().filter(|c| *c == char).count() as f32)
+                        .collect(),
+                ))
+            }
+        }));
+
+        let (events_tx, mut events_rx) = channel::unbounded();
+        let events = IndexingEvents {
+            worktree_id: WorktreeId::from_usize(1),
+            tx: events_tx,
+        };
+        let indexing_entries = Arc::new(IndexingEntrySet::new(events.clone()));
 
         let (chunked_files_tx, chunked_files_rx) = channel::unbounded::<ChunkedFile>();
         chunked_files_tx
-            .send_blocking(ChunkedFile {
-                path: Path::new("test1.md").into(),
-                mtime: None,
-                handle: indexing_entries.insert(ProjectEntryId::from_proto(0)),
-                text: "abcdefghijklmnop".to_string(),
-                chunks: [0..4, 4..8, 8..12, 12..16]
+            .send_blocking(ChunkedFile::new(
+                Path::new("test1.md").into(),
+                None,
+                indexing_entries.insert(ProjectEntryId::from_proto(0)),
+                "abcdefghijklmnop",
+                [0..4, 4..8, 8..12, 12..16]
                     .into_iter()
                     .map(|range| Chunk {
                         range,
+                        header: String::new(),
                         digest: Default::default(),
+                        overlap: 0,
+                        cell: None,
                     })
                     .collect(),
-            })
+            ))
             .unwrap();
         chunked_files_tx
-            .send_blocking(ChunkedFile {
-                path: Path::new("test2.md").into(),
-                mtime: None,
-                handle: indexing_entries.insert(ProjectEntryId::from_proto(1)),
-                text: "qrstuvwxyz".to_string(),
-                chunks: [0..4, 4..8, 8..10]
+            .send_blocking(ChunkedFile::new(
+                Path::new("test2.md").into(),
+                None,
+                indexing_entries.insert(ProjectEntryId::from_proto(1)),
+                "qrstuvwxyz",
+                [0..4, 4..8, 8..10]
                     .into_iter()
                     .map(|range| Chunk {
                         range,
+                        header: String::new(),
                         digest: Default::default(),
+                        overlap: 0,
+                        cell: None,
                     })
                     .collect(),
-            })
+            ))
             .unwrap();
         chunked_files_tx.close();
 
-        let embed_files_task =
-            cx.update(|cx| WorktreeIndex::embed_files(provider.clone(), chunked_files_rx, cx));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let embedding_cache = Arc::new(
+            EmbeddingCache::new(
+                &KvStorage::Lmdb(db_connection.clone()),
+                &provider.model_info(),
+            )
+            .unwrap(),
+        );
+        let (usage_tx, _usage_rx) = channel::unbounded();
+        let mut txn = db_connection.write_txn().unwrap();
+        let usage_log = UsageLog::create(&db_connection, &mut txn, "test", usage_tx).unwrap();
+        txn.commit().unwrap();
+
+        let embed_files_task = cx.update(|cx| {
+            WorktreeIndex::embed_files(
+                provider.clone(),
+                embedding_cache,
+                usage_log.clone(),
+                chunked_files_rx,
+                2,
+                events,
+                cx,
+            )
+        });
         embed_files_task.task.await.unwrap();
 
         let mut embedded_files_rx = embed_files_task.files;
         let mut embedded_files = Vec::new();
-        while let Some((embedded_file, _)) = embedded_files_rx.next().await {
+        while let Some((embedded_file, _, _, _)) = embedded_files_rx.next().await {
             embedded_files.push(embedded_file);
         }
 
         assert_eq!(embedded_files.len(), 1);
         assert_eq!(embedded_files[0].path.as_ref(), Path::new("test2.md"));
+        // The file with a chunk that failed is left out, to be retried later.
+        let failed_files = embed_files_task
+            .failed_files
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(failed_files, vec![Arc::from(Path::new("test1.md"))]);
+        drop(indexing_entries);
+        let mut failures = Vec::new();
+        let mut last_progress = None;
+        while let Some(event) = events_rx.next().await {
+            match event.kind {
+                IndexingEventKind::Failed { path, error } => failures.push((path, error)),
+                IndexingEventKind::Embedding { done, total } => last_progress = Some((done, total)),
+                _ => {}
+            }
+        }
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0.as_ref(), Path::new("test1.md"));
+        assert!(failures[0]
+            .1
+            .contains("cannot embed text containing a 'g' character"));
+        assert_eq!(last_progress, Some((7, 7)));
         assert_eq!(
             embedded_files[0]
                 .chunks
                 .iter()
-                .map(|embedded_chunk| { embedded_chunk.embedding.clone() })
+                .map(|embedded_chunk| { embedded_chunk.embedding.dequantize() })
                 .collect::<Vec<Embedding>>(),
             vec![
                 (provider.compute_embedding)("qrst").unwrap(),
@@ -1259,5 +3076,153 @@ mod tests {
                 (provider.compute_embedding)("yz").unwrap(),
             ],
         );
+
+        // Requests that failed were still sent, so they count towards usage.
+        let daily_usage = usage_log.daily(&db_connection.read_txn().unwrap()).unwrap();
+        assert_eq!(daily_usage.len(), 1);
+        assert_eq!(daily_usage[0].usage.request_count, 3);
+        assert_eq!(daily_usage[0].usage.chunk_count, 7);
+    }
+
+    struct TestSharedEmbeddingStore {
+        embeddings: Mutex<HashMap<[u8; 32], Embedding>>,
+        model: EmbeddingModelInfo,
+    }
+
+    impl SharedEmbeddingStore for TestSharedEmbeddingStore {
+        fn get<'a>(
+            &'a self,
+            digests: &'a [[u8; 32]],
+        ) -> BoxFuture<'a, Result<Vec<Option<Embedding>>>> {
+            let embeddings = self.embeddings.lock();
+            let embeddings = digests
+                .iter()
+                .map(|digest| embeddings.get(digest).cloned())
+                .collect();
+            future::ready(Ok(embeddings)).boxed()
+        }
+
+        fn put<'a>(&'a self, embeddings: &'a [([u8; 32], Embedding)]) -> BoxFuture<'a, Result<()>> {
+            self.embeddings.lock().extend(embeddings.iter().cloned());
+            future::ready(Ok(())).boxed()
+        }
+
+        fn model_info(&self) -> EmbeddingModelInfo {
+            self.model.clone()
+        }
+    }
+
+    #[gpui::test]
+    async fn test_embed_files_with_shared_store(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+
+        // Chunks with an 'a' can only be embedded by pulling them from the shared store.
+        let provider = Arc::new(TestEmbeddingProvider::new(3, |text| {
+            if text.contains('a') {
+                Err(anyhow!("cannot embed text containing an 'a' character"))
+            } else {
+                Ok(Embedding::new(
+                    ('a'..='z')
+                        .map(|char| text.chars().filter(|c| *c == char).count() as f32)
+                        .collect(),
+                ))
+            }
+        }));
+        let shared_embedding = Embedding::new((0..26).map(|ix| ix as f32).collect());
+        let store = Arc::new(TestSharedEmbeddingStore {
+            embeddings: Mutex::new(HashMap::from_iter([([1; 32], shared_embedding.clone())])),
+            model: provider.model_info(),
+        });
+
+        let (events_tx, _) = channel::unbounded();
+        let events = IndexingEvents {
+            worktree_id: WorktreeId::from_usize(1),
+            tx: events_tx,
+        };
+        let indexing_entries = Arc::new(IndexingEntrySet::new(events.clone()));
+        let (chunked_files_tx, chunked_files_rx) = channel::unbounded::<ChunkedFile>();
+        chunked_files_tx
+            .send_blocking(ChunkedFile::new(
+                Path::new("test.md").into(),
+                None,
+                indexing_entries.insert(ProjectEntryId::from_proto(0)),
+                "abcdqrst",
+                vec![
+                    Chunk {
+                        range: 0..4,
+                        header: String::new(),
+                        digest: [1; 32],
+                        overlap: 0,
+                        cell: None,
+                    },
+                    Chunk {
+                        range: 4..8,
+                        header: String::new(),
+                        digest: [2; 32],
+                        overlap: 0,
+                        cell: None,
+                    },
+                ],
+            ))
+            .unwrap();
+        chunked_files_tx.close();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let embedding_cache = Arc::new(
+            EmbeddingCache::new(
+                &KvStorage::Lmdb(db_connection.clone()),
+                &provider.model_info(),
+            )
+            .unwrap(),
+        );
+        let (usage_tx, _usage_rx) = channel::unbounded();
+        let mut txn = db_connection.write_txn().unwrap();
+        let usage_log = UsageLog::create(&db_connection, &mut txn, "test", usage_tx).unwrap();
+        txn.commit().unwrap();
+        embedding_cache.set_shared(Some(SharedEmbeddings {
+            store: store.clone(),
+            push: true,
+        }));
+
+        let embed_files_task = cx.update(|cx| {
+            WorktreeIndex::embed_files(
+                provider.clone(),
+                embedding_cache.clone(),
+                usage_log,
+                chunked_files_rx,
+                2,
+                events,
+                cx,
+            )
+        });
+        embed_files_task.task.await.unwrap();
+
+        let mut embedded_files_rx = embed_files_task.files;
+        let mut embedded_files = Vec::new();
+        while let Some((embedded_file, _, _, _)) = embedded_files_rx.next().await {
+            embedded_files.push(embedded_file);
+        }
+
+        let local_embedding = (provider.compute_embedding)("qrst").unwrap();
+        assert_eq!(embedded_files.len(), 1);
+        assert_eq!(
+            embedded_files[0]
+                .chunks
+                .iter()
+                .map(|embedded_chunk| embedded_chunk.embedding.dequantize())
+                .collect::<Vec<Embedding>>(),
+            vec![shared_embedding.clone(), local_embedding.clone()],
+        );
+        // Pulled embeddings are cached locally, and local ones are pushed to the store.
+        assert_eq!(
+            embedding_cache.get(&[[1; 32]]).unwrap(),
+            vec![Some(shared_embedding)]
+        );
+        assert_eq!(
+            store.embeddings.lock().get(&[2; 32]),
+            Some(&local_embedding)
+        );
     }
 }
@@ -0,0 +1,256 @@
+use anyhow::Result;
+use collections::HashMap;
+use gpui::AppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsSources};
+
+use crate::{
+    EmbeddingProviderSettings, EmbeddingQuantization, RateLimits, SharedEmbeddingStoreSettings,
+    StorageBackend,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct SemanticIndexSettings {
+    pub enabled: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub max_file_size: u64,
+    pub max_data_file_size: u64,
+    pub max_line_length: usize,
+    pub chunk_size: usize,
+    pub chunk_tokens: Option<usize>,
+    pub chunk_overlap: f32,
+    pub approximate_search: bool,
+    pub quantization: EmbeddingQuantization,
+    pub recency_weight: f32,
+    pub recency_half_life_days: f32,
+    pub path_weights: HashMap<String, f32>,
+    pub rate_limits: HashMap<String, RateLimits>,
+    pub max_concurrent_embedding_requests: usize,
+    pub max_indexing_threads: Option<usize>,
+    pub pause_on_battery: bool,
+    pub max_cpu_usage: Option<f32>,
+    pub profile_indexing: bool,
+    pub initial_database_size: u64,
+    pub storage_backend: StorageBackend,
+    pub encrypt: bool,
+    pub database_path: Option<String>,
+    pub embedding_cache_path: Option<String>,
+    pub embedding_provider: Option<EmbeddingProviderSettings>,
+    pub local_embeddings: bool,
+    pub fallback_embedding_providers: Vec<EmbeddingProviderSettings>,
+    pub pin_embedding_model: bool,
+    pub shared_embedding_store: Option<SharedEmbeddingStoreSettings>,
+}
+
+/// Semantic index configuration.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SemanticIndexSettingsContent {
+    /// Whether to index the files of projects for semantic search. Set it to false in
+    /// a project's settings to never index that project.
+    ///
+    /// Default: true
+    pub enabled: Option<bool>,
+    /// Globs matching the files to index, relative to the root of their worktree.
+    /// Files in a directory matching one of them are indexed too. Every file is
+    /// indexed when empty.
+    ///
+    /// Default: []
+    pub include: Option<Vec<String>>,
+    /// Globs matching the files to never index, even if they match `include`. Files
+    /// in a directory matching one of them aren't indexed either.
+    ///
+    /// Default: ["**/*.min.js", "**/*.min.css", "**/*.map", "**/*.lock", "**/package-lock.json",
+    /// "**/npm-shrinkwrap.json", "**/pnpm-lock.yaml", "**/go.sum"]
+    pub exclude: Option<Vec<String>>,
+    /// The size in bytes of the largest file to index. Larger files are skipped.
+    ///
+    /// Default: 1048576
+    pub max_file_size: Option<u64>,
+    /// The size in bytes of the largest data file to index, like JSON, YAML, TOML or
+    /// CSV. Larger ones are skipped, since they're usually generated, like fixtures and
+    /// dumps, and would take many embeddings to index.
+    ///
+    /// Default: 262144
+    pub max_data_file_size: Option<u64>,
+    /// The length in bytes of the longest line of a file to index. Files with longer
+    /// lines, like minified code, are skipped.
+    ///
+    /// Default: 1000
+    pub max_line_length: Option<usize>,
+    /// The size in bytes of the largest chunk files are split into, between 256 and
+    /// 32768. Smaller chunks make search results more precise, and larger ones give
+    /// them more context. Changing it indexes the project again once it's next opened,
+    /// only embedding the chunks that changed.
+    ///
+    /// Default: 8192
+    pub chunk_size: Option<usize>,
+    /// The number of tokens of the embedding model in the largest chunk files are split
+    /// into, between 64 and 8192, to size chunks in tokens instead of in bytes. Sizing
+    /// them in tokens keeps chunks of text with more bytes per token, like text in
+    /// non-Latin scripts, within the limits of the model. Chunks are sized by
+    /// `chunk_size` when null. Changing it indexes the project again once it's next
+    /// opened.
+    ///
+    /// Default: null
+    pub chunk_tokens: Option<usize>,
+    /// The percentage of a chunk's size it can repeat of the end of the previous chunk,
+    /// up to 50, so that code spanning two chunks is whole in one of them. Changing it
+    /// indexes the project again once it's next opened.
+    ///
+    /// Default: 0
+    pub chunk_overlap: Option<f32>,
+    /// Whether to search large worktrees approximately, by only comparing the query
+    /// to the embeddings in the clusters closest to it. Worktrees with fewer than
+    /// 10,000 chunks are always searched exhaustively.
+    ///
+    /// Default: true
+    pub approximate_search: Option<bool>,
+    /// How to quantize embeddings before storing them, trading accuracy for a smaller
+    /// index: "none", "int8" (4x smaller), or "binary" (32x smaller). Only affects
+    /// files indexed after it changes.
+    ///
+    /// Default: none
+    pub quantization: Option<EmbeddingQuantization>,
+    /// How much of their score search results lose as their file ages, between 0
+    /// and 1. Results keep their score regardless of age when 0.
+    ///
+    /// Default: 0
+    pub recency_weight: Option<f32>,
+    /// After how many days since their file was modified search results lose half of
+    /// the `recency_weight` of their score.
+    ///
+    /// Default: 30
+    pub recency_half_life_days: Option<f32>,
+    /// Weights to scale the scores of search results by, keyed by globs matching
+    /// their file or a directory it's in, like `{ "vendor": 0.5, "src": 1.2 }`.
+    ///
+    /// Default: {}
+    pub path_weights: Option<HashMap<String, f32>>,
+    /// The rate limits of embedding providers, keyed by the name of the provider, like
+    /// `{ "openai": { "requests_per_minute": 500, "tokens_per_minute": 200000 } }`.
+    /// Providers without limits here keep to the limits of their lowest paid tier.
+    /// Takes effect when Zed restarts.
+    ///
+    /// Default: {}
+    pub rate_limits: Option<HashMap<String, RateLimits>>,
+    /// How many requests to send to the embedding provider at once while indexing,
+    /// within its rate limits.
+    ///
+    /// Default: 4
+    pub max_concurrent_embedding_requests: Option<usize>,
+    /// How many files to read and chunk at once while indexing. Uses every core
+    /// when null.
+    ///
+    /// Default: null
+    pub max_indexing_threads: Option<usize>,
+    /// Whether to pause indexing while the machine runs on battery power, resuming
+    /// when it's plugged in.
+    ///
+    /// Default: false
+    pub pause_on_battery: Option<bool>,
+    /// The percentage of the CPU other processes can use before indexing pauses,
+    /// like while building, resuming once they use less. Never pauses when null.
+    ///
+    /// Default: null
+    pub max_cpu_usage: Option<f32>,
+    /// Whether to write how long each stage of indexing takes for each file to Zed's
+    /// log, for finding what makes indexing slow. Timings are otherwise only logged
+    /// at the debug level, under the `semantic_index::profile` target.
+    ///
+    /// Default: false
+    pub profile_indexing: Option<bool>,
+    /// The size in bytes each index database, one per worktree and one shared by
+    /// every worktree, can reach before it has to grow, which it does on its own
+    /// when it's full. Takes effect when Zed restarts.
+    ///
+    /// Default: 268435456
+    pub initial_database_size: Option<u64>,
+    /// The database to store the embedding cache in: "lmdb", or "sqlite" where LMDB's
+    /// memory maps are a problem, like on network file systems. The rest of the index
+    /// is stored in LMDB. Takes effect when Zed restarts.
+    ///
+    /// Default: lmdb
+    pub storage_backend: Option<StorageBackend>,
+    /// Whether to encrypt the index of a project's files, with a key kept in the OS
+    /// keychain. Set it in a project's settings to only encrypt that project. Changing
+    /// it indexes the project again once it's next opened. Compact the index after
+    /// turning it on to remove what was stored unencrypted before.
+    ///
+    /// The chunks, their embeddings and the keyword index are encrypted. What's kept
+    /// about the files themselves isn't:
+    /// - the paths of the indexed files,
+    /// - how many chunks each file has, and which clusters of the approximate search
+    ///   index they're in,
+    /// - the files that failed to embed or were skipped, and why,
+    /// - how far indexing got, and how many tokens were embedded each day,
+    /// - the embeddings in the cache shared by every project.
+    ///
+    /// Default: false
+    pub encrypt: Option<bool>,
+    /// The directory to keep the index in, like on a scratch disk, instead of Zed's
+    /// data directory. Set it in a project's settings to only move the index of that
+    /// project, which a relative path puts inside the project, like for containers
+    /// that only mount the project. Existing indices are moved to it. Takes effect
+    /// when Zed restarts, or for a project, when the project is next opened.
+    ///
+    /// Default: null
+    pub database_path: Option<String>,
+    /// A file to keep every embedding returned by the embedding providers in, apart
+    /// from the index, so that files aren't embedded again, and paid for again, after
+    /// the index is deleted. Texts embedded by the same model before are never sent
+    /// to the provider again. Takes effect when Zed restarts.
+    ///
+    /// Default: null
+    pub embedding_cache_path: Option<String>,
+    /// The embedding provider to use instead of Zed's: an OpenAI model deployed to
+    /// Azure, like `{ "azure_openai": { "endpoint": "https://my-resource.openai.azure.com",
+    /// "deployment": "my-deployment", "model": "text-embedding-3-small" } }`, a model on
+    /// Vertex AI, like `{ "vertex_ai": { "project": "my-project" } }`, a model on AWS
+    /// Bedrock, like `{ "bedrock": { "region": "us-east-1", "model":
+    /// "amazon.titan-embed-text-v2:0" } }`, a Voyage AI model, like `{ "voyage": {
+    /// "model": "voyage-code-3" } }`, or a Cohere model, like `{ "cohere": { "model":
+    /// "embed-english-v3.0" } }`. Changing it embeds every file again.
+    ///
+    /// Default: null
+    pub embedding_provider: Option<EmbeddingProviderSettings>,
+    /// Whether to embed files on this machine when no `embedding_provider` is set and
+    /// you aren't signed in to Zed, with a small model downloaded from Hugging Face the
+    /// first time it's used. Signing in or out embeds every file again with the other
+    /// model.
+    ///
+    /// Default: false
+    pub local_embeddings: Option<bool>,
+    /// The embedding providers to fail over to, in order, when the embedding provider
+    /// keeps failing or timing out, configured like `embedding_provider`. Files
+    /// embedded by them are embedded again once the embedding provider works again.
+    /// Takes effect when Zed restarts.
+    ///
+    /// Default: []
+    pub fallback_embedding_providers: Option<Vec<EmbeddingProviderSettings>>,
+    /// Whether to keep the index of a project's files when the embedding model changes,
+    /// instead of embedding every file again. Set it in a project's settings to only
+    /// keep that project's index, for switching back to its model later. Searches leave
+    /// the project out until it's embedded with the current model, with the
+    /// `semantic index: reembed with current model` action.
+    ///
+    /// Default: false
+    pub pin_embedding_model: Option<bool>,
+    /// The store of embeddings shared with the rest of a team, served over HTTP,
+    /// like `{ "url": "https://embeddings.example.com", "push": true }`. Content is
+    /// looked up in it before being embedded, so that it's only paid for once per
+    /// team. Takes effect when Zed restarts.
+    ///
+    /// Default: null
+    pub shared_embedding_store: Option<SharedEmbeddingStoreSettings>,
+}
+
+impl Settings for SemanticIndexSettings {
+    const KEY: Option<&'static str> = Some("semantic_index");
+    type FileContent = SemanticIndexSettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        sources.json_merge()
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt, FutureExt};
+use http_client::HttpClient;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{Embedding, EmbeddingModelInfo};
+
+/// Trait for stores of embeddings shared by a team, like an object store or a collab
+/// server, so that content one person embedded doesn't need to be embedded again by
+/// everyone else. Embeddings are looked up by the digest of the text they were
+/// computed from, and a store only holds the embeddings of a single model.
+pub trait SharedEmbeddingStore: Sync + Send {
+    /// Returns the shared embedding of every digest, if any, in the order of the digests.
+    fn get<'a>(&'a self, digests: &'a [[u8; 32]]) -> BoxFuture<'a, Result<Vec<Option<Embedding>>>>;
+    /// Shares embeddings that were computed locally.
+    fn put<'a>(&'a self, embeddings: &'a [([u8; 32], Embedding)]) -> BoxFuture<'a, Result<()>>;
+    fn model_info(&self) -> EmbeddingModelInfo;
+}
+
+/// A shared store to pull embeddings from before embedding content locally, and
+/// optionally push the embeddings computed locally to.
+#[derive(Clone)]
+pub(crate) struct SharedEmbeddings {
+    pub store: Arc<dyn SharedEmbeddingStore>,
+    pub push: bool,
+}
+
+/// A shared store served over HTTP, configured in the settings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SharedEmbeddingStoreSettings {
+    /// The URL of the store, which embeddings are fetched from at `{url}/get`.
+    pub url: String,
+    /// Whether to share the embeddings computed locally to the store too.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub push: bool,
+}
+
+/// A shared store served over HTTP. Embeddings are fetched by posting the hex digests
+/// of their texts to `{url}/get`, and shared by posting them to `{url}/put`, along
+/// with the model that computed them.
+pub struct HttpEmbeddingStore {
+    client: Arc<dyn HttpClient>,
+    url: String,
+    model: EmbeddingModelInfo,
+}
+
+#[derive(Serialize)]
+struct GetEmbeddingsRequest<'a> {
+    model: &'a EmbeddingModelInfo,
+    digests: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct GetEmbeddingsResponse {
+    embeddings: Vec<Option<Vec<f32>>>,
+}
+
+#[derive(Serialize)]
+struct PutEmbeddingsRequest<'a> {
+    model: &'a EmbeddingModelInfo,
+    embeddings: Vec<SharedEmbedding<'a>>,
+}
+
+#[derive(Serialize)]
+struct SharedEmbedding<'a> {
+    digest: String,
+    embedding: &'a Embedding,
+}
+
+impl HttpEmbeddingStore {
+    pub fn new(client: Arc<dyn HttpClient>, url: String, model: EmbeddingModelInfo) -> Self {
+        Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            model,
+        }
+    }
+
+    async fn post(&self, endpoint: &str, body: String) -> Result<String> {
+        let mut response = self
+            .client
+            .post_json(&format!("{}/{endpoint}", self.url), body.into())
+            .await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "shared embedding store responded with {}: {body}",
+                response.status()
+            ));
+        }
+        Ok(body)
+    }
+}
+
+impl SharedEmbeddingStore for HttpEmbeddingStore {
+    fn get<'a>(&'a self, digests: &'a [[u8; 32]]) -> BoxFuture<'a, Result<Vec<Option<Embedding>>>> {
+        async move {
+            let request = GetEmbeddingsRequest {
+                model: &self.model,
+                digests: digests.iter().map(hex::encode).collect(),
+            };
+            let body = self.post("get", serde_json::to_string(&request)?).await?;
+            let response: GetEmbeddingsResponse =
+                serde_json::from_str(&body).context("invalid shared embeddings response")?;
+            if response.embeddings.len() != digests.len() {
+                return Err(anyhow!(
+                    "shared embedding store returned {} embeddings, expected {}",
+                    response.embeddings.len(),
+                    digests.len()
+                ));
+            }
+            Ok(response
+                .embeddings
+                .into_iter()
+                .map(|embedding| {
+                    // Embeddings of another size can't be compared with local ones.
+                    embedding
+                        .filter(|embedding| embedding.len() == self.model.dimensions)
+                        .map(Embedding::new)
+                })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, embeddings: &'a [([u8; 32], Embedding)]) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let request = PutEmbeddingsRequest {
+                model: &self.model,
+                embeddings: embeddings
+                    .iter()
+                    .map(|(digest, embedding)| SharedEmbedding {
+                        digest: hex::encode(digest),
+                        embedding,
+                    })
+                    .collect(),
+            };
+            self.post("put", serde_json::to_string(&request)?).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn model_info(&self) -> EmbeddingModelInfo {
+        self.model.clone()
+    }
+}
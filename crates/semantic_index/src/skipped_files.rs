@@ -0,0 +1,324 @@
+use anyhow::Result;
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{self, Read},
+    ops::Bound,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{notebook::is_notebook, SemanticIndexSettings};
+
+/// How many bytes at the start of a file are checked for null bytes.
+const BINARY_DETECTION_LEN: usize = 8192;
+/// How many bytes of a file are read at once when reading it a block at a time.
+const READ_BLOCK_LEN: usize = 64 * 1024;
+/// The extensions of data files, which are skipped at a smaller size than other files,
+/// since large ones are usually generated, like fixtures and dumps.
+const DATA_EXTENSIONS: &[&str] = &[
+    "json", "jsonl", "ndjson", "yaml", "yml", "toml", "csv", "tsv", "xml",
+];
+
+/// Why a file wasn't indexed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// The file is larger than the `max_file_size` setting.
+    TooLarge,
+    /// The file contains null bytes or isn't valid UTF-8.
+    Binary,
+    /// The file has a line longer than the `max_line_length` setting, like minified code.
+    LongLines,
+    /// The file is data, like JSON or CSV, larger than the `max_data_file_size` setting.
+    LargeData,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::TooLarge => write!(f, "too large"),
+            SkipReason::Binary => write!(f, "binary"),
+            SkipReason::LongLines => write!(f, "lines too long"),
+            SkipReason::LargeData => write!(f, "large data file"),
+        }
+    }
+}
+
+/// The limits files are skipped past, from the settings of their worktree.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FileLimits {
+    pub max_file_size: u64,
+    pub max_data_file_size: u64,
+    pub max_line_length: usize,
+}
+
+impl FileLimits {
+    pub fn from_settings(settings: &SemanticIndexSettings) -> Self {
+        Self {
+            max_file_size: settings.max_file_size,
+            max_data_file_size: settings.max_data_file_size,
+            max_line_length: settings.max_line_length,
+        }
+    }
+
+    /// Returns the size in bytes of the largest file at the path to index.
+    pub fn max_size(&self, path: &Path) -> u64 {
+        if is_data(path) {
+            self.max_file_size.min(self.max_data_file_size)
+        } else {
+            self.max_file_size
+        }
+    }
+}
+
+fn is_data(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            DATA_EXTENSIONS
+                .iter()
+                .any(|data_extension| extension.eq_ignore_ascii_case(data_extension))
+        })
+}
+
+/// Decodes the contents of a file to index, unless it should be skipped. Files larger
+/// than their `max_size` may be passed truncated to just over it. Notebooks can have
+/// long lines, since their outputs, like images, aren't indexed.
+pub(crate) fn file_text(
+    path: &Path,
+    bytes: Vec<u8>,
+    limits: FileLimits,
+) -> Result<String, SkipReason> {
+    if bytes.len() as u64 > limits.max_file_size {
+        return Err(SkipReason::TooLarge);
+    }
+    if bytes.len() as u64 > limits.max_size(path) {
+        return Err(SkipReason::LargeData);
+    }
+    if bytes[..bytes.len().min(BINARY_DETECTION_LEN)].contains(&0) {
+        return Err(SkipReason::Binary);
+    }
+    let text = String::from_utf8(bytes).map_err(|_| SkipReason::Binary)?;
+    if !is_notebook(path) && text.lines().any(|line| line.len() > limits.max_line_length) {
+        return Err(SkipReason::LongLines);
+    }
+    Ok(text)
+}
+
+/// Reads the contents of a file to index a block at a time, passing its text to
+/// `on_text`, unless it should be skipped like with `file_text`. Text passed before a
+/// reason to skip the file is found should be discarded. Never reads much more than
+/// the `max_size` of the file.
+pub(crate) fn read_file_text(
+    reader: impl Read,
+    path: &Path,
+    limits: FileLimits,
+    mut on_text: impl FnMut(&str),
+) -> io::Result<Result<(), SkipReason>> {
+    let mut reader = reader.take(limits.max_size(path).saturating_add(1));
+    let check_line_length = !is_notebook(path);
+    let mut block = Vec::with_capacity(READ_BLOCK_LEN);
+    let mut len = 0;
+    let mut line_len = 0;
+    loop {
+        // Bytes of a character split between blocks are kept at the start of the block.
+        let kept_len = block.len();
+        let read_len = (&mut reader)
+            .take((READ_BLOCK_LEN - kept_len) as u64)
+            .read_to_end(&mut block)?;
+        let binary_detection_len = BINARY_DETECTION_LEN.saturating_sub(len);
+        len += read_len;
+        if len as u64 > limits.max_file_size {
+            return Ok(Err(SkipReason::TooLarge));
+        }
+        if len as u64 > limits.max_size(path) {
+            return Ok(Err(SkipReason::LargeData));
+        }
+        let new_bytes = &block[kept_len..];
+        if new_bytes[..new_bytes.len().min(binary_detection_len)].contains(&0) {
+            return Ok(Err(SkipReason::Binary));
+        }
+
+        let text = match std::str::from_utf8(&block) {
+            Ok(text) => text,
+            Err(error) if error.error_len().is_none() && read_len > 0 => {
+                std::str::from_utf8(&block[..error.valid_up_to()]).unwrap()
+            }
+            Err(_) => return Ok(Err(SkipReason::Binary)),
+        };
+        if check_line_length {
+            for (ix, line) in text.split('\n').enumerate() {
+                line_len = if ix == 0 {
+                    line_len + line.len()
+                } else {
+                    line.len()
+                };
+                if line_len > limits.max_line_length {
+                    return Ok(Err(SkipReason::LongLines));
+                }
+            }
+        }
+        on_text(text);
+
+        if read_len == 0 {
+            return Ok(Ok(()));
+        }
+        block.drain(..text.len());
+    }
+}
+
+/// The files of a worktree that were skipped when indexing it, along with why.
+#[derive(Clone, Copy)]
+pub(crate) struct SkippedFiles {
+    db: heed::Database<Str, SerdeBincode<(Arc<Path>, SkipReason)>>,
+}
+
+impl SkippedFiles {
+    pub fn create(env: &heed::Env, txn: &mut heed::RwTxn, name: &str) -> Result<Self> {
+        let db = env.create_database(txn, Some(&format!("{name}:skipped_files")))?;
+        Ok(Self { db })
+    }
+
+    /// Records why a file was skipped, or that it wasn't.
+    pub fn insert(
+        &self,
+        txn: &mut heed::RwTxn,
+        db_key: &str,
+        path: &Arc<Path>,
+        skip_reason: Option<SkipReason>,
+    ) -> Result<()> {
+        if let Some(skip_reason) = skip_reason {
+            self.db.put(txn, db_key, &(path.clone(), skip_reason))?;
+        } else {
+            self.db.delete(txn, db_key)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<()> {
+        self.db.delete_range(txn, range)?;
+        Ok(())
+    }
+
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.db.clear(txn)?;
+        Ok(())
+    }
+
+    pub fn all(&self, txn: &heed::RoTxn) -> Result<Vec<(Arc<Path>, SkipReason)>> {
+        self.db
+            .iter(txn)?
+            .map(|entry| Ok(entry?.1))
+            .collect::<Result<Vec<_>>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: FileLimits = FileLimits {
+        max_file_size: 1024,
+        max_data_file_size: 64,
+        max_line_length: 80,
+    };
+
+    #[test]
+    fn test_file_text() {
+        let path = Path::new("main.rs");
+        let text = "fn main() {\n    println!(\"hello\");\n}\n";
+        assert_eq!(file_text(path, text.into(), LIMITS).as_deref(), Ok(text));
+        assert_eq!(
+            file_text(
+                path,
+                text.into(),
+                FileLimits {
+                    max_file_size: 16,
+                    ..LIMITS
+                }
+            ),
+            Err(SkipReason::TooLarge)
+        );
+        assert_eq!(
+            file_text(path, b"\x7fELF\0\0\0".to_vec(), LIMITS),
+            Err(SkipReason::Binary)
+        );
+        assert_eq!(
+            file_text(path, vec![0xff, 0xfe, b'a'], LIMITS),
+            Err(SkipReason::Binary)
+        );
+        assert_eq!(
+            file_text(path, "var a=1;".repeat(20).into(), LIMITS),
+            Err(SkipReason::LongLines)
+        );
+        // Outputs of notebooks, like images, are on long lines.
+        let notebook = format!("{{\"outputs\": [\"{}\"]}}", "A".repeat(100));
+        assert_eq!(
+            file_text(Path::new("plot.ipynb"), notebook.clone().into(), LIMITS).as_deref(),
+            Ok(notebook.as_str())
+        );
+
+        let data = "{\"id\": 1}\n".repeat(10);
+        assert_eq!(
+            file_text(
+                Path::new("fixtures/users.JSON"),
+                data.clone().into(),
+                LIMITS
+            ),
+            Err(SkipReason::LargeData)
+        );
+        assert_eq!(
+            file_text(Path::new("fixtures/users.js"), data.clone().into(), LIMITS).as_deref(),
+            Ok(data.as_str())
+        );
+    }
+
+    #[test]
+    fn test_read_file_text() {
+        fn read(path: &str, bytes: &[u8], limits: FileLimits) -> Result<String, SkipReason> {
+            let mut text = String::new();
+            read_file_text(bytes, Path::new(path), limits, |block| text.push_str(block))
+                .unwrap()
+                .map(|_| text)
+        }
+
+        let limits = FileLimits {
+            max_file_size: 1024 * 1024,
+            max_data_file_size: 1024,
+            max_line_length: 80,
+        };
+        // Characters are split between blocks.
+        let text = format!("x{}\n", "é".repeat(30)).repeat(READ_BLOCK_LEN / 30);
+        assert_eq!(read("names.txt", text.as_bytes(), limits), Ok(text.clone()));
+        assert_eq!(
+            read(
+                "names.txt",
+                text.as_bytes(),
+                FileLimits {
+                    max_file_size: 1024,
+                    ..limits
+                }
+            ),
+            Err(SkipReason::TooLarge)
+        );
+        assert_eq!(
+            read("names.csv", text.as_bytes(), limits),
+            Err(SkipReason::LargeData)
+        );
+        assert_eq!(
+            read("names.txt", text.replace('\n', " ").as_bytes(), limits),
+            Err(SkipReason::LongLines)
+        );
+        assert_eq!(
+            read("names.txt", &[b'a', 0xc3], limits),
+            Err(SkipReason::Binary)
+        );
+        assert_eq!(read("names.txt", b"a\0b", limits), Err(SkipReason::Binary));
+        assert_eq!(read("empty.txt", b"", limits), Ok(String::new()));
+    }
+}
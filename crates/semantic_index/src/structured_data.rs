@@ -0,0 +1,518 @@
+use std::{cmp::Reverse, ops::Range, path::Path};
+
+/// A key of a structured data file, along with its value.
+pub(crate) struct DataSection {
+    /// The range of the key and its value, spanning whole lines when they start and end
+    /// their lines.
+    pub range: Range<usize>,
+    pub name: String,
+}
+
+enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+fn data_format(path: &Path) -> Option<DataFormat> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "json" => Some(DataFormat::Json),
+        "yaml" | "yml" => Some(DataFormat::Yaml),
+        "toml" => Some(DataFormat::Toml),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_structured_data(path: &Path) -> bool {
+    data_format(path).is_some()
+}
+
+/// Returns the sections of a JSON, YAML or TOML file, one for every key whose value is
+/// on lines of its own, so that chunks end where values do. Sections of nested keys are
+/// nested in the sections of their parents, and TOML tables are sections of their own.
+/// Returns `None` if the file isn't structured data, or if it can't be parsed.
+pub(crate) fn structured_data_sections(text: &str, path: &Path) -> Option<Vec<DataSection>> {
+    let mut sections = match data_format(path)? {
+        DataFormat::Json => json_sections(text)?,
+        DataFormat::Yaml => yaml_sections(text),
+        DataFormat::Toml => toml_sections(text),
+    };
+    sections.sort_unstable_by_key(|section| (section.range.start, Reverse(section.range.end)));
+    Some(sections)
+}
+
+fn json_sections(text: &str) -> Option<Vec<DataSection>> {
+    fn object_sections(scanner: &mut JsonScanner, sections: &mut Vec<DataSection>) -> Option<()> {
+        scanner.fields(|scanner, key| {
+            let name = serde_json::from_str::<String>(&scanner.text[key.clone()]).ok()?;
+            if scanner.peek()? == b'{' {
+                object_sections(scanner, sections)?;
+            } else {
+                scanner.skip_value()?;
+            }
+            sections.push(DataSection {
+                range: whole_lines(scanner.text, key.start..scanner.offset),
+                name,
+            });
+            Some(())
+        })
+    }
+
+    let mut scanner = JsonScanner::new(text);
+    let mut sections = Vec::new();
+    if scanner.peek()? != b'{' {
+        return None;
+    }
+    object_sections(&mut scanner, &mut sections)?;
+    scanner.peek().is_none().then_some(sections)
+}
+
+/// Extends a range of JSON to the start of its line and past the comma after it to the
+/// end of its line, if there's nothing else on them.
+fn whole_lines(text: &str, range: Range<usize>) -> Range<usize> {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |ix| ix + 1);
+    let start = if text[line_start..range.start].trim().is_empty() {
+        line_start
+    } else {
+        range.start
+    };
+    let rest = text[range.end..].trim_start_matches([' ', '\t']);
+    let rest = rest.strip_prefix(',').unwrap_or(rest);
+    let rest = rest.trim_start_matches([' ', '\t', '\r']);
+    let end = if let Some(after_line) = rest.strip_prefix('\n') {
+        text.len() - after_line.len()
+    } else if rest.is_empty() {
+        text.len()
+    } else {
+        range.end
+    };
+    start..end
+}
+
+/// A line of a YAML or TOML file.
+struct DataLine<'a> {
+    /// The range of the line, including its line ending.
+    range: Range<usize>,
+    text: &'a str,
+    indent: usize,
+}
+
+fn data_lines(text: &str) -> impl Iterator<Item = DataLine> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        let text = line.trim_end();
+        Some(DataLine {
+            range: start..*offset,
+            text: text.trim_start(),
+            indent: text.len() - text.trim_start().len(),
+        })
+    })
+}
+
+/// A section whose end hasn't been found yet.
+struct OpenSection {
+    start: usize,
+    indent: usize,
+    name: String,
+}
+
+/// Finds the keys of a YAML file, whose values extend to the next line indented as much
+/// as the key or less. Items of sequences can be indented as much as their key.
+fn yaml_sections(text: &str) -> Vec<DataSection> {
+    let mut sections = Vec::new();
+    let mut open_sections = Vec::<OpenSection>::new();
+    let mut block_scalar_indent = None;
+    let mut content_end = 0;
+    for line in data_lines(text) {
+        if line.text.is_empty() {
+            continue;
+        }
+        if let Some(indent) = block_scalar_indent {
+            if line.indent > indent {
+                content_end = line.range.end;
+                continue;
+            }
+            block_scalar_indent = None;
+        }
+        if line.text.starts_with('#') {
+            continue;
+        }
+
+        let is_document_marker = line.indent == 0 && matches!(line.text, "---" | "...");
+        // The keys of mappings in sequences are indented past the `-` of their item.
+        let (indent, key_indent, key_text) = match line.text.strip_prefix('-') {
+            Some(item) if item.is_empty() || item.starts_with(' ') => {
+                let item_indent = item.len() - item.trim_start().len();
+                let indent = line.indent + 1;
+                (indent, indent + item_indent, item.trim_start())
+            }
+            _ => (line.indent, line.indent, line.text),
+        };
+        while let Some(open_section) = open_sections.last() {
+            if !is_document_marker && open_section.indent < indent {
+                break;
+            }
+            let open_section = open_sections.pop().unwrap();
+            sections.push(DataSection {
+                range: open_section.start..content_end,
+                name: open_section.name,
+            });
+        }
+        content_end = line.range.end;
+        if is_document_marker {
+            continue;
+        }
+
+        if let Some((name, value)) = yaml_key_value(key_text) {
+            if value.starts_with(['|', '>']) {
+                block_scalar_indent = Some(key_indent);
+            }
+            open_sections.push(OpenSection {
+                start: line.range.start,
+                indent: key_indent,
+                name,
+            });
+        }
+    }
+    for open_section in open_sections.into_iter().rev() {
+        sections.push(DataSection {
+            range: open_section.start..content_end,
+            name: open_section.name,
+        });
+    }
+    sections
+}
+
+/// Splits a line of YAML into its key and value, if it's a key of a mapping.
+fn yaml_key_value(text: &str) -> Option<(String, &str)> {
+    let (key, rest) = if let Some(quote) = text.chars().next().filter(|c| matches!(c, '"' | '\'')) {
+        let end = text[1..].find(quote)? + 1;
+        (&text[1..end], text[end + 1..].strip_prefix(':')?)
+    } else {
+        if text.starts_with(['#', '[', '{', '&', '*', '!', '|', '>', '%', '@', '`']) {
+            return None;
+        }
+        let colon = text
+            .match_indices(':')
+            .map(|(ix, _)| ix)
+            .find(|ix| text[ix + 1..].is_empty() || text[ix + 1..].starts_with(' '))?;
+        (text[..colon].trim_end(), &text[colon + 1..])
+    };
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((key.to_string(), rest.trim_start())).filter(|(key, _)| !key.is_empty())
+}
+
+/// Finds the tables of a TOML file, which extend to the next table, along with the keys
+/// in and before them, which extend to the next key.
+fn toml_sections(text: &str) -> Vec<DataSection> {
+    let mut sections = Vec::new();
+    let mut open_table = None::<OpenSection>;
+    let mut open_key = None::<OpenSection>;
+    let mut multiline_string = None;
+    let mut content_end = 0;
+    for line in data_lines(text) {
+        if let Some(delimiter) = multiline_string {
+            if line.text.matches(delimiter).count() % 2 == 1 {
+                multiline_string = None;
+            }
+            content_end = line.range.end;
+            continue;
+        }
+        if line.text.is_empty() || line.text.starts_with('#') {
+            continue;
+        }
+
+        let table = toml_table(line.text);
+        let key = match table {
+            Some(_) => None,
+            None => line
+                .text
+                .split_once('=')
+                .map(|(key, _)| key.trim())
+                .filter(|key| is_toml_key(key)),
+        };
+        let open_sections = if table.is_some() {
+            [open_key.take(), open_table.take()]
+        } else if key.is_some() {
+            [open_key.take(), None]
+        } else {
+            [None, None]
+        };
+        for open_section in open_sections.into_iter().flatten() {
+            sections.push(DataSection {
+                range: open_section.start..content_end,
+                name: open_section.name,
+            });
+        }
+        content_end = line.range.end;
+
+        if let Some(table) = table {
+            open_table = Some(OpenSection {
+                start: line.range.start,
+                indent: 0,
+                name: table.to_string(),
+            });
+        } else if let Some(key) = key {
+            open_key = Some(OpenSection {
+                start: line.range.start,
+                indent: 0,
+                name: key.to_string(),
+            });
+            let value = line.text.split_once('=').map_or("", |(_, value)| value);
+            multiline_string = ["\"\"\"", "'''"]
+                .into_iter()
+                .find(|delimiter| value.matches(delimiter).count() % 2 == 1);
+        }
+    }
+    for open_section in [open_key, open_table].into_iter().flatten() {
+        sections.push(DataSection {
+            range: open_section.start..content_end,
+            name: open_section.name,
+        });
+    }
+    sections
+}
+
+/// Returns the name of the table a line of TOML is the header of, if it is one.
+fn toml_table(text: &str) -> Option<&str> {
+    let text = text.strip_prefix('[')?;
+    let (name, rest) = text.strip_prefix('[').unwrap_or(text).split_once(']')?;
+    let rest = rest.trim_start_matches(']').trim_start();
+    let name = name.trim();
+    (is_toml_key(name) && (rest.is_empty() || rest.starts_with('#'))).then_some(name)
+}
+
+fn is_toml_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ' ' | '"' | '\''))
+}
+
+/// Walks through JSON, keeping track of the offset of every value.
+pub(crate) struct JsonScanner<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> JsonScanner<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text, offset: 0 }
+    }
+
+    /// Returns the next byte that isn't whitespace, skipping the whitespace.
+    pub fn peek(&mut self) -> Option<u8> {
+        let bytes = self.text.as_bytes();
+        while bytes.get(self.offset)?.is_ascii_whitespace() {
+            self.offset += 1;
+        }
+        bytes.get(self.offset).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        (self.peek()? == byte).then(|| self.offset += 1)
+    }
+
+    /// Returns the range of a string, including its quotes.
+    pub fn string(&mut self) -> Option<Range<usize>> {
+        self.eat(b'"')?;
+        let start = self.offset - 1;
+        let bytes = self.text.as_bytes();
+        loop {
+            match *bytes.get(self.offset)? {
+                b'\\' => self.offset += 2,
+                b'"' => {
+                    self.offset += 1;
+                    return Some(start..self.offset);
+                }
+                _ => self.offset += 1,
+            }
+        }
+    }
+
+    /// Calls `field` with the key of every field of an object, to scan its value.
+    pub fn object(&mut self, mut field: impl FnMut(&mut Self, &str) -> Option<()>) -> Option<()> {
+        self.fields(|scanner, key| {
+            let key = serde_json::from_str::<String>(&scanner.text[key]).ok()?;
+            field(scanner, &key)
+        })
+    }
+
+    /// Calls `field` with the range of the key of every field of an object, including
+    /// its quotes, to scan its value.
+    fn fields(
+        &mut self,
+        mut field: impl FnMut(&mut Self, Range<usize>) -> Option<()>,
+    ) -> Option<()> {
+        self.eat(b'{')?;
+        if self.eat(b'}').is_some() {
+            return Some(());
+        }
+        loop {
+            let key = self.string()?;
+            self.eat(b':')?;
+            field(self, key)?;
+            if self.eat(b',').is_none() {
+                return self.eat(b'}');
+            }
+        }
+    }
+
+    /// Calls `element` to scan every element of an array.
+    pub fn array(&mut self, mut element: impl FnMut(&mut Self) -> Option<()>) -> Option<()> {
+        self.eat(b'[')?;
+        if self.eat(b']').is_some() {
+            return Some(());
+        }
+        loop {
+            element(self)?;
+            if self.eat(b',').is_none() {
+                return self.eat(b']');
+            }
+        }
+    }
+
+    pub fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'{' => self.object(|scanner, _| scanner.skip_value()),
+            b'[' => self.array(Self::skip_value),
+            b'"' => self.string().map(drop),
+            _ => {
+                // Numbers, booleans and null.
+                let bytes = self.text.as_bytes();
+                let start = self.offset;
+                while bytes.get(self.offset).map_or(false, |byte| {
+                    !matches!(byte, b',' | b'}' | b']') && !byte.is_ascii_whitespace()
+                }) {
+                    self.offset += 1;
+                }
+                (self.offset > start).then_some(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section_texts<'a>(text: &'a str, path: &str) -> Vec<(&'a str, String)> {
+        structured_data_sections(text, Path::new(path))
+            .unwrap()
+            .into_iter()
+            .map(|section| (&text[section.range], section.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_json_sections() {
+        let text = concat!(
+            "{\n",
+            "  \"name\": \"app\",\n",
+            "  \"scripts\": {\n",
+            "    \"build\": \"tsc\"\n",
+            "  },\n",
+            "  \"files\": [\"dist\"], \"private\": true\n",
+            "}\n",
+        );
+        assert_eq!(
+            section_texts(text, "package.json"),
+            [
+                ("  \"name\": \"app\",\n", "name".to_string()),
+                (
+                    "  \"scripts\": {\n    \"build\": \"tsc\"\n  },\n",
+                    "scripts".to_string()
+                ),
+                ("    \"build\": \"tsc\"\n", "build".to_string()),
+                ("  \"files\": [\"dist\"]", "files".to_string()),
+                ("\"private\": true\n", "private".to_string()),
+            ]
+        );
+        assert!(structured_data_sections("[1, 2]", Path::new("data.json")).is_none());
+        assert!(structured_data_sections("{\"a\": 1", Path::new("data.json")).is_none());
+        assert!(structured_data_sections("{}", Path::new("main.rs")).is_none());
+    }
+
+    #[test]
+    fn test_yaml_sections() {
+        let text = concat!(
+            "# CI\n",
+            "name: CI\n",
+            "jobs:\n",
+            "  test:\n",
+            "    steps:\n",
+            "    - uses: actions/checkout@v4\n",
+            "    - run: |\n",
+            "        cargo test\n",
+            "        key: not a key\n",
+            "\n",
+            "  lint:\n",
+            "    runs-on: ubuntu-latest\n",
+            "---\n",
+            "\"quoted key\": 1\n",
+        );
+        assert_eq!(
+            section_texts(text, "ci.yml")
+                .into_iter()
+                .map(|(text, name)| (text.lines().count(), name))
+                .collect::<Vec<_>>(),
+            [
+                (1, "name".to_string()),
+                (10, "jobs".to_string()),
+                (6, "test".to_string()),
+                (5, "steps".to_string()),
+                (1, "uses".to_string()),
+                (3, "run".to_string()),
+                (2, "lint".to_string()),
+                (1, "runs-on".to_string()),
+                (1, "quoted key".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toml_sections() {
+        let text = concat!(
+            "name = \"app\"\n",
+            "description = \"\"\"\n",
+            "[not a table]\n",
+            "\"\"\"\n",
+            "\n",
+            "[dependencies]\n",
+            "serde = { version = \"1\", features = [\n",
+            "    \"derive\",\n",
+            "] }\n",
+            "log = \"0.4\"\n",
+            "\n",
+            "[[bin]]\n",
+            "name = \"app\"\n",
+        );
+        assert_eq!(
+            section_texts(text, "Cargo.toml"),
+            [
+                ("name = \"app\"\n", "name".to_string()),
+                (
+                    "description = \"\"\"\n[not a table]\n\"\"\"\n",
+                    "description".to_string()
+                ),
+                (
+                    "[dependencies]\n\
+                    serde = { version = \"1\", features = [\n    \"derive\",\n] }\n\
+                    log = \"0.4\"\n",
+                    "dependencies".to_string()
+                ),
+                (
+                    "serde = { version = \"1\", features = [\n    \"derive\",\n] }\n",
+                    "serde".to_string()
+                ),
+                ("log = \"0.4\"\n", "log".to_string()),
+                ("[[bin]]\nname = \"app\"\n", "bin".to_string()),
+                ("name = \"app\"\n", "name".to_string()),
+            ]
+        );
+    }
+}
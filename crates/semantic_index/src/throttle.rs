@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use sysinfo::{CpuRefreshKind, Pid, ProcessRefreshKind, RefreshKind, System};
+
+use crate::SemanticIndexSettings;
+
+/// How often to check whether indexing should yield to the rest of the system.
+pub(crate) const THROTTLE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Why indexing was paused to leave the machine's resources to something else.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ThrottleReason {
+    /// The machine is running on battery power.
+    OnBattery,
+    /// Other processes are keeping the CPU busy, like a build.
+    CpuBusy,
+}
+
+/// When to pause indexing, from the settings.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub(crate) struct ThrottlePolicy {
+    pub pause_on_battery: bool,
+    /// The percentage of the CPU other processes can use before indexing pauses.
+    pub max_cpu_usage: Option<f32>,
+}
+
+impl ThrottlePolicy {
+    pub fn from_settings(settings: &SemanticIndexSettings) -> Self {
+        Self {
+            pause_on_battery: settings.pause_on_battery,
+            max_cpu_usage: settings.max_cpu_usage,
+        }
+    }
+}
+
+/// Samples the power source and CPU usage of the machine, to tell when indexing
+/// should be throttled.
+pub(crate) struct ResourceMonitor {
+    system: Option<System>,
+    pid: Pid,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self {
+            system: None,
+            pid: Pid::from_u32(std::process::id()),
+        }
+    }
+
+    /// Returns why indexing should be paused under the given policy, if it should.
+    /// CPU usage is measured since the previous call, so the first call never
+    /// reports the CPU as busy.
+    pub fn throttle_reason(&mut self, policy: ThrottlePolicy) -> Option<ThrottleReason> {
+        if policy.pause_on_battery && is_on_battery() {
+            return Some(ThrottleReason::OnBattery);
+        }
+
+        let Some(max_cpu_usage) = policy.max_cpu_usage else {
+            // Stop sampling, so that re-enabling the policy starts from a fresh sample.
+            self.system = None;
+            return None;
+        };
+        let refresh_kind = ProcessRefreshKind::new().with_cpu();
+        let Some(system) = self.system.as_mut() else {
+            let mut system = System::new_with_specifics(
+                RefreshKind::new().with_cpu(CpuRefreshKind::new().with_cpu_usage()),
+            );
+            system.refresh_process_specifics(self.pid, refresh_kind);
+            self.system = Some(system);
+            return None;
+        };
+        system.refresh_cpu_specifics(CpuRefreshKind::new().with_cpu_usage());
+        system.refresh_process_specifics(self.pid, refresh_kind);
+        let own_cpu_usage = system
+            .process(self.pid)
+            .map_or(0., |process| process.cpu_usage());
+        let other_cpu_usage = other_cpu_usage(
+            system.global_cpu_info().cpu_usage(),
+            own_cpu_usage,
+            system.cpus().len(),
+        );
+        (other_cpu_usage > max_cpu_usage).then_some(ThrottleReason::CpuBusy)
+    }
+}
+
+/// The percentage of the whole CPU used by other processes, given the usage of
+/// all processes and the usage of this one, which is a percentage of a single core.
+fn other_cpu_usage(global_cpu_usage: f32, own_cpu_usage: f32, cpu_count: usize) -> f32 {
+    (global_cpu_usage - own_cpu_usage / cpu_count.max(1) as f32).max(0.)
+}
+
+#[cfg(target_os = "linux")]
+fn is_on_battery() -> bool {
+    let Ok(power_supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut discharging = false;
+    for power_supply in power_supplies.flatten() {
+        let path = power_supply.path();
+        match read(path.join("type")).as_str() {
+            "Mains" | "USB" if read(path.join("online")) == "1" => return false,
+            "Battery" => discharging |= read(path.join("status")) == "Discharging",
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(target_os = "macos")]
+fn is_on_battery() -> bool {
+    std::process::Command::new("pmset")
+        .args(["-g", "ps"])
+        .output()
+        .map_or(false, |output| {
+            String::from_utf8_lossy(&output.stdout).contains("'Battery Power'")
+        })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_on_battery() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_other_cpu_usage() {
+        // Indexing on two of four cores doesn't count as the CPU being busy.
+        assert_eq!(other_cpu_usage(50., 200., 4), 0.);
+        assert_eq!(other_cpu_usage(75., 200., 4), 25.);
+        assert_eq!(other_cpu_usage(40., 100., 0), 0.);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use collections::BTreeMap;
+use heed::{
+    byteorder::BigEndian,
+    types::{SerdeBincode, U64},
+};
+use serde::{Deserialize, Serialize};
+use smol::channel;
+use std::{
+    ops::AddAssign,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::db_connection::DbConnection;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How much an embedding provider was used to index files. Providers don't report
+/// how many tokens they were sent, so tokens are estimated from the length of the
+/// texts embedded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub request_count: u64,
+    pub chunk_count: u64,
+    pub token_count: u64,
+}
+
+impl EmbeddingUsage {
+    /// The usage of a single request embedding the given texts.
+    pub(crate) fn request<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut usage = Self {
+            request_count: 1,
+            ..Default::default()
+        };
+        for text in texts {
+            usage.chunk_count += 1;
+            usage.token_count += estimate_token_count(text);
+        }
+        usage
+    }
+}
+
+impl AddAssign for EmbeddingUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.request_count += other.request_count;
+        self.chunk_count += other.chunk_count;
+        self.token_count += other.token_count;
+    }
+}
+
+/// The usage of an embedding provider on a single day, in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DailyUsage {
+    /// The start of the day.
+    pub day: SystemTime,
+    pub usage: EmbeddingUsage,
+}
+
+/// Estimates how many tokens a text is split into, assuming about four bytes per
+/// token like most tokenizers average on code and English.
+pub(crate) fn estimate_token_count(text: &str) -> u64 {
+    text.len().div_ceil(4) as u64
+}
+
+/// The persisted usage of an embedding provider to index a worktree, by day.
+#[derive(Clone)]
+pub(crate) struct UsageLog {
+    db_connection: DbConnection,
+    db: heed::Database<U64<BigEndian>, SerdeBincode<EmbeddingUsage>>,
+    usage_tx: channel::Sender<EmbeddingUsage>,
+}
+
+impl UsageLog {
+    pub fn create(
+        db_connection: &DbConnection,
+        txn: &mut heed::RwTxn,
+        name: &str,
+        usage_tx: channel::Sender<EmbeddingUsage>,
+    ) -> Result<Self> {
+        let db = db_connection.create_database(txn, Some(&format!("{name}:usage")))?;
+        Ok(Self {
+            db_connection: db_connection.clone(),
+            db,
+            usage_tx,
+        })
+    }
+
+    /// Adds to today's usage, and reports it to whoever is listening.
+    pub fn record(&self, usage: EmbeddingUsage) -> Result<()> {
+        let day = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / SECONDS_PER_DAY;
+        self.db_connection.write(|txn| {
+            let mut daily_usage = self.db.get(txn, &day)?.unwrap_or_default();
+            daily_usage += usage;
+            self.db.put(txn, &day, &daily_usage)?;
+            Ok(())
+        })?;
+        self.usage_tx.try_send(usage).ok();
+        Ok(())
+    }
+
+    pub fn daily(&self, txn: &heed::RoTxn) -> Result<Vec<DailyUsage>> {
+        self.db
+            .iter(txn)?
+            .map(|entry| {
+                let (day, usage) = entry?;
+                Ok(DailyUsage {
+                    day: UNIX_EPOCH + Duration::from_secs(day * SECONDS_PER_DAY),
+                    usage,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Adds up the usage of several worktrees by day, oldest first.
+pub(crate) fn merge_daily_usage(usages: impl IntoIterator<Item = DailyUsage>) -> Vec<DailyUsage> {
+    let mut usage_by_day = BTreeMap::<SystemTime, EmbeddingUsage>::default();
+    for daily_usage in usages {
+        *usage_by_day.entry(daily_usage.day).or_default() += daily_usage.usage;
+    }
+    usage_by_day
+        .into_iter()
+        .map(|(day, usage)| DailyUsage { day, usage })
+        .collect()
+}
+
+/// Describes how much the embedding provider was used to index a project, given its
+/// usage by day.
+pub(crate) fn usage_summary(daily_usage: &[DailyUsage]) -> String {
+    let mut total = EmbeddingUsage::default();
+    for daily_usage in daily_usage {
+        total += daily_usage.usage;
+    }
+    let days = match daily_usage.len() {
+        0 => return "The embedding provider wasn't used to index this project".to_string(),
+        1 => "1 day".to_string(),
+        day_count => format!("{day_count} days"),
+    };
+    format!(
+        "Indexing this project embedded {} chunks, about {} tokens in {} requests, over {days}",
+        total.chunk_count, total.token_count, total.request_count
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_connection::{TEST_MAP_SIZE, TEST_MAX_DBS};
+
+    #[test]
+    fn test_usage_log() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection =
+            DbConnection::open(temp_dir.path(), TEST_MAP_SIZE, TEST_MAX_DBS).unwrap();
+        let (usage_tx, usage_rx) = channel::unbounded();
+        let mut txn = db_connection.write_txn().unwrap();
+        let usage_log = UsageLog::create(&db_connection, &mut txn, "worktree", usage_tx).unwrap();
+        txn.commit().unwrap();
+
+        let first_request = EmbeddingUsage::request(["fn main() {}", "a"]);
+        assert_eq!(
+            first_request,
+            EmbeddingUsage {
+                request_count: 1,
+                chunk_count: 2,
+                token_count: 4,
+            }
+        );
+        usage_log.record(first_request).unwrap();
+        usage_log.record(EmbeddingUsage::request(["b"])).unwrap();
+        assert_eq!(usage_rx.try_recv(), Ok(first_request));
+
+        let txn = db_connection.read_txn().unwrap();
+        let daily = usage_log.daily(&txn).unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(
+            daily[0].usage,
+            EmbeddingUsage {
+                request_count: 2,
+                chunk_count: 3,
+                token_count: 5,
+            }
+        );
+
+        let yesterday = DailyUsage {
+            day: daily[0].day - Duration::from_secs(SECONDS_PER_DAY),
+            usage: first_request,
+        };
+        let merged = merge_daily_usage([daily[0], yesterday, daily[0]]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0], yesterday);
+        assert_eq!(merged[1].usage.request_count, 4);
+        assert_eq!(
+            usage_summary(&merged),
+            "Indexing this project embedded 8 chunks, about 14 tokens in 5 requests, over 2 days"
+        );
+        assert_eq!(
+            usage_summary(&[]),
+            "The embedding provider wasn't used to index this project"
+        );
+    }
+}
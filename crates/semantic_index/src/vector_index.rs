@@ -0,0 +1,467 @@
+use anyhow::Result;
+use collections::HashMap;
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, ops::Bound, path::Path, sync::Arc, time::SystemTime};
+
+use crate::{
+    encryption::{EncryptedDatabase, IndexCipher},
+    indexing::{EmbeddedChunk, EmbeddedFile},
+    Embedding,
+};
+
+/// Below this many chunks, scanning every embedding is fast enough that the index
+/// isn't trained, and searches fall back to brute force.
+pub(crate) const MIN_CHUNKS_FOR_APPROXIMATE_SEARCH: u64 = 10_000;
+/// How much a worktree has to grow after training before its clusters are retrained.
+const RETRAINING_GROWTH_FACTOR: u64 = 4;
+const MIN_CLUSTER_COUNT: usize = 16;
+const MAX_CLUSTER_COUNT: usize = 1024;
+const TRAINING_SAMPLE_SIZE: usize = 4096;
+const TRAINING_ITERATIONS: usize = 8;
+/// How many files are read at once when assigning every file to clusters.
+const ASSIGNMENT_BATCH_SIZE: usize = 256;
+const META_KEY: &str = "meta";
+
+/// An inverted file index over the embeddings of a worktree's chunks, for finding
+/// approximate nearest neighbors without comparing the query to every chunk.
+///
+/// Embeddings are grouped into clusters around centroids, trained with spherical
+/// k-means once a worktree has enough chunks. Searches only scan the clusters whose
+/// centroids are most similar to the query.
+#[derive(Clone, Copy)]
+pub(crate) struct VectorIndex {
+    meta: heed::Database<Str, SerdeBincode<VectorIndexMeta>>,
+    /// The chunks of a file that belong to a cluster, keyed by cluster followed by file.
+    clusters: EncryptedDatabase<ClusterEntry>,
+    files: heed::Database<Str, SerdeBincode<IndexedFile>>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct VectorIndexMeta {
+    centroids: Vec<Embedding>,
+    chunk_count: u64,
+    trained_chunk_count: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ClusterEntry {
+    pub path: Arc<Path>,
+    pub mtime: Option<SystemTime>,
+    pub chunks: Vec<EmbeddedChunk>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    chunk_count: u32,
+    clusters: Vec<u32>,
+}
+
+impl VectorIndexMeta {
+    fn is_trained(&self) -> bool {
+        !self.centroids.is_empty()
+    }
+
+    pub fn needs_training(&self) -> bool {
+        self.chunk_count >= MIN_CHUNKS_FOR_APPROXIMATE_SEARCH
+            && (!self.is_trained()
+                || self.chunk_count >= self.trained_chunk_count * RETRAINING_GROWTH_FACTOR)
+    }
+
+    /// Returns the clusters to scan for a query, most similar first, or `None` if the
+    /// index isn't trained.
+    fn clusters_to_probe(&self, query: &Embedding) -> Option<Vec<u32>> {
+        if !self.is_trained() {
+            return None;
+        }
+        let probe_count = (self.centroids.len() as f32).sqrt().ceil() as usize;
+        let mut clusters = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(ix, centroid)| (ix as u32, centroid.similarity(query)))
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        Some(
+            clusters
+                .into_iter()
+                .take(probe_count)
+                .map(|(ix, _)| ix)
+                .collect(),
+        )
+    }
+}
+
+impl VectorIndex {
+    /// Opens the index of the embeddings in `db`, counting the chunks in it if the
+    /// index didn't exist yet.
+    pub fn create(
+        env: &heed::Env,
+        txn: &mut heed::RwTxn,
+        name: &str,
+        db: EncryptedDatabase<EmbeddedFile>,
+        cipher: Option<IndexCipher>,
+    ) -> Result<Self> {
+        let this = Self {
+            meta: env.create_database(txn, Some(&format!("{name}:vector_meta")))?,
+            clusters: EncryptedDatabase::create(
+                env,
+                txn,
+                &format!("{name}:vector_clusters"),
+                cipher,
+            )?,
+            files: env.create_database(txn, Some(&format!("{name}:vector_files")))?,
+        };
+        if this.meta.get(txn, META_KEY)?.is_none() {
+            let chunk_counts = db
+                .iter(txn)?
+                .map(|entry| {
+                    let (db_key, file) = entry?;
+                    Ok((db_key.to_string(), file.chunks.len()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut meta = VectorIndexMeta::default();
+            for (db_key, chunk_count) in chunk_counts {
+                meta.chunk_count += chunk_count as u64;
+                this.files.put(
+                    txn,
+                    &db_key,
+                    &IndexedFile {
+                        chunk_count: chunk_count as u32,
+                        clusters: Vec::new(),
+                    },
+                )?;
+            }
+            this.save_meta(txn, &meta)?;
+        }
+        Ok(this)
+    }
+
+    pub fn load_meta(&self, txn: &heed::RoTxn) -> Result<VectorIndexMeta> {
+        Ok(self.meta.get(txn, META_KEY)?.unwrap_or_default())
+    }
+
+    pub fn save_meta(&self, txn: &mut heed::RwTxn, meta: &VectorIndexMeta) -> Result<()> {
+        self.meta.put(txn, META_KEY, meta)?;
+        Ok(())
+    }
+
+    /// Indexes the chunks of a file, replacing whatever was indexed for it before.
+    pub fn insert(
+        &self,
+        txn: &mut heed::RwTxn,
+        meta: &mut VectorIndexMeta,
+        db_key: &str,
+        file: &EmbeddedFile,
+    ) -> Result<()> {
+        self.delete(txn, meta, db_key)?;
+
+        let mut chunks_by_cluster = HashMap::<u32, Vec<EmbeddedChunk>>::default();
+        if meta.is_trained() {
+            for chunk in &file.chunks {
+                let cluster = nearest_centroid(&meta.centroids, &chunk.embedding.dequantize());
+                chunks_by_cluster
+                    .entry(cluster as u32)
+                    .or_default()
+                    .push(chunk.clone());
+            }
+        }
+
+        let clusters = chunks_by_cluster.keys().copied().collect();
+        for (cluster, chunks) in chunks_by_cluster {
+            self.clusters.put(
+                txn,
+                &cluster_key(cluster, db_key),
+                &ClusterEntry {
+                    path: file.path.clone(),
+                    mtime: file.mtime,
+                    chunks,
+                },
+            )?;
+        }
+        self.files.put(
+            txn,
+            db_key,
+            &IndexedFile {
+                chunk_count: file.chunks.len() as u32,
+                clusters,
+            },
+        )?;
+        meta.chunk_count += file.chunks.len() as u64;
+        Ok(())
+    }
+
+    pub fn delete(
+        &self,
+        txn: &mut heed::RwTxn,
+        meta: &mut VectorIndexMeta,
+        db_key: &str,
+    ) -> Result<()> {
+        let Some(file) = self.files.get(txn, db_key)? else {
+            return Ok(());
+        };
+        for cluster in file.clusters {
+            self.clusters.delete(txn, &cluster_key(cluster, db_key))?;
+        }
+        self.files.delete(txn, db_key)?;
+        meta.chunk_count = meta.chunk_count.saturating_sub(file.chunk_count as u64);
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        txn: &mut heed::RwTxn,
+        meta: &mut VectorIndexMeta,
+        range: &(Bound<&str>, Bound<&str>),
+    ) -> Result<()> {
+        let db_keys = self
+            .files
+            .range(txn, range)?
+            .map(|entry| Ok(entry?.0.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        for db_key in db_keys {
+            self.delete(txn, meta, &db_key)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets every indexed chunk along with the centroids, which only make sense for
+    /// the embeddings they were trained on.
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<()> {
+        self.clusters.clear(txn)?;
+        self.files.clear(txn)?;
+        self.save_meta(txn, &VectorIndexMeta::default())
+    }
+
+    /// Trains the centroids on a sample of the embeddings in `db`, then assigns every
+    /// chunk in it to its nearest centroid.
+    pub fn train(
+        &self,
+        txn: &mut heed::RwTxn,
+        meta: &mut VectorIndexMeta,
+        db: EncryptedDatabase<EmbeddedFile>,
+    ) -> Result<()> {
+        let stride = (meta.chunk_count as usize / TRAINING_SAMPLE_SIZE).max(1);
+        let mut sample = Vec::new();
+        let mut chunk_ix = 0;
+        for entry in db.iter(txn)? {
+            let (_, file) = entry?;
+            for chunk in file.chunks {
+                if chunk_ix % stride == 0 {
+                    sample.push(chunk.embedding.dequantize());
+                }
+                chunk_ix += 1;
+            }
+        }
+
+        let cluster_count = ((chunk_ix as f32).sqrt() as usize)
+            .clamp(MIN_CLUSTER_COUNT, MAX_CLUSTER_COUNT)
+            .min(sample.len());
+        log::info!(
+            "training vector index with {cluster_count} clusters on {} of {chunk_ix} chunks",
+            sample.len()
+        );
+        *meta = VectorIndexMeta {
+            centroids: train_centroids(&sample, cluster_count, TRAINING_ITERATIONS),
+            chunk_count: 0,
+            trained_chunk_count: chunk_ix as u64,
+        };
+
+        self.clusters.clear(txn)?;
+        self.files.clear(txn)?;
+        let mut start = Bound::Unbounded;
+        loop {
+            let batch = db
+                .range(txn, &(start.as_ref().map(String::as_str), Bound::Unbounded))?
+                .take(ASSIGNMENT_BATCH_SIZE)
+                .map(|entry| {
+                    let (db_key, file) = entry?;
+                    Ok((db_key.to_string(), file))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let Some((last_key, _)) = batch.last() else {
+                break;
+            };
+            start = Bound::Excluded(last_key.clone());
+            for (db_key, file) in &batch {
+                self.insert(txn, meta, db_key, file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the entries of the clusters closest to the query, or `None` if the index
+    /// isn't trained and every embedding needs to be scanned instead.
+    pub fn approximate_neighbors(
+        &self,
+        txn: &heed::RoTxn,
+        query: &Embedding,
+    ) -> Result<Option<Vec<ClusterEntry>>> {
+        let meta = self.load_meta(txn)?;
+        let Some(clusters) = meta.clusters_to_probe(query) else {
+            return Ok(None);
+        };
+        let mut entries = Vec::new();
+        for cluster in clusters {
+            for entry in self.clusters.prefix_iter(txn, &cluster_key(cluster, ""))? {
+                entries.push(entry?.1);
+            }
+        }
+        Ok(Some(entries))
+    }
+}
+
+fn cluster_key(cluster: u32, db_key: &str) -> String {
+    format!("{cluster:08x}\0{db_key}")
+}
+
+fn nearest_centroid(centroids: &[Embedding], embedding: &Embedding) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            a.similarity(embedding)
+                .partial_cmp(&b.similarity(embedding))
+                .unwrap_or(Ordering::Equal)
+        })
+        .map_or(0, |(ix, _)| ix)
+}
+
+/// Clusters normalized embeddings with spherical k-means, starting from centroids
+/// spread evenly across the sample.
+fn train_centroids(
+    sample: &[Embedding],
+    cluster_count: usize,
+    iterations: usize,
+) -> Vec<Embedding> {
+    if sample.is_empty() || cluster_count == 0 {
+        return Vec::new();
+    }
+    let mut centroids = (0..cluster_count)
+        .map(|ix| sample[ix * sample.len() / cluster_count].clone())
+        .collect::<Vec<_>>();
+    let dimensions = sample[0].as_slice().len();
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0f32; dimensions]; cluster_count];
+        let mut counts = vec![0usize; cluster_count];
+        for embedding in sample {
+            let cluster = nearest_centroid(&centroids, embedding);
+            for (sum, value) in sums[cluster].iter_mut().zip(embedding.as_slice()) {
+                *sum += value;
+            }
+            counts[cluster] += 1;
+        }
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            // Empty clusters keep their centroid rather than collapsing to zero.
+            if count > 0 {
+                *centroid = Embedding::new(sum);
+            }
+        }
+    }
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::Chunk;
+
+    fn embedded_file(path: &str, embeddings: &[[f32; 2]]) -> EmbeddedFile {
+        EmbeddedFile {
+            path: Path::new(path).into(),
+            mtime: None,
+            digest: [0; 32],
+            chunks: embeddings
+                .iter()
+                .enumerate()
+                .map(|(ix, embedding)| EmbeddedChunk {
+                    chunk: Chunk {
+                        range: ix * 10..(ix + 1) * 10,
+                        header: String::new(),
+                        digest: Default::default(),
+                        overlap: 0,
+                        cell: None,
+                    },
+                    embedding: Embedding::new(embedding.to_vec()).into(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_train_centroids() {
+        let sample = [[1., 0.1], [1., -0.1], [-0.1, 1.], [0.1, 1.], [1., 0.]]
+            .map(|embedding| Embedding::new(embedding.to_vec()));
+        let centroids = train_centroids(&sample, 2, 4);
+        assert_eq!(centroids.len(), 2);
+        assert_eq!(nearest_centroid(&centroids, &sample[0]), 0);
+        assert_eq!(nearest_centroid(&centroids, &sample[2]), 1);
+        assert_eq!(nearest_centroid(&centroids, &sample[3]), 1);
+    }
+
+    #[test]
+    fn test_vector_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let env = unsafe {
+            heed::EnvOpenOptions::new()
+                .map_size(1024 * 1024 * 1024)
+                .max_dbs(8)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let mut txn = env.write_txn().unwrap();
+        let db = EncryptedDatabase::create(&env, &mut txn, "worktree", None).unwrap();
+        let index = VectorIndex::create(&env, &mut txn, "worktree", db, None).unwrap();
+        let mut meta = index.load_meta(&txn).unwrap();
+
+        let files = [
+            ("a.rs", embedded_file("a.rs", &[[1., 0.], [0., 1.]])),
+            ("b.rs", embedded_file("b.rs", &[[1., 0.1]])),
+            ("c.rs", embedded_file("c.rs", &[[0.1, 1.], [0., 1.]])),
+        ];
+        for (db_key, file) in &files {
+            db.put(&mut txn, db_key, file).unwrap();
+            index.insert(&mut txn, &mut meta, db_key, file).unwrap();
+        }
+        assert_eq!(meta.chunk_count, 5);
+        assert!(!meta.needs_training());
+        let query = Embedding::new(vec![1., 0.05]);
+        assert!(index.approximate_neighbors(&txn, &query).unwrap().is_none());
+
+        index.train(&mut txn, &mut meta, db).unwrap();
+        assert_eq!(meta.chunk_count, 5);
+        assert_eq!(meta.centroids.len(), 5);
+        index.save_meta(&mut txn, &meta).unwrap();
+
+        let neighbors = |txn: &heed::RoTxn| {
+            index
+                .approximate_neighbors(txn, &query)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .flat_map(|entry| {
+                    entry
+                        .chunks
+                        .into_iter()
+                        .map(move |chunk| (entry.path.clone(), chunk.chunk.range))
+                })
+                .map(|(path, range)| (path.to_string_lossy().into_owned(), range))
+                .collect::<Vec<_>>()
+        };
+        let found = neighbors(&txn);
+        assert!(found.contains(&("a.rs".to_string(), 0..10)));
+        assert!(found.contains(&("b.rs".to_string(), 0..10)));
+        assert!(found.len() < 5);
+
+        index
+            .delete_range(
+                &mut txn,
+                &mut meta,
+                &(Bound::Included("b.rs"), Bound::Unbounded),
+            )
+            .unwrap();
+        index.save_meta(&mut txn, &meta).unwrap();
+        assert_eq!(meta.chunk_count, 2);
+        assert!(neighbors(&txn).iter().all(|(path, _)| path == "a.rs"));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{index_verification::repair_summary, usage::usage_summary, SemanticIndex};
+use gpui::{actions, BorrowAppContext, PathPromptOptions, ViewContext};
+use workspace::{notifications::NotificationId, Toast, Workspace};
+
+actions!(
+    semantic_index,
+    [
+        EstimateIndex,
+        RepairIndex,
+        ReembedWithCurrentModel,
+        ExportIndexDiagnostics,
+        PauseIndexing,
+        ResumeIndexing,
+        CompactIndex,
+        ExportIndex,
+        ImportIndex,
+        ShowEmbeddingUsage
+    ]
+);
+
+pub(crate) fn register(workspace: &mut Workspace) {
+    workspace.register_action(estimate_index);
+    workspace.register_action(repair_index);
+    workspace.register_action(reembed_with_current_model);
+    workspace.register_action(pause_indexing);
+    workspace.register_action(resume_indexing);
+    workspace.register_action(compact_index);
+    workspace.register_action(export_index);
+    workspace.register_action(import_index);
+    workspace.register_action(show_embedding_usage);
+}
+
+/// Shows what indexing the workspace's project from scratch would take, for users to
+/// exclude files before embedding them.
+fn estimate_index(workspace: &mut Workspace, _: &EstimateIndex, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let project_index = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.project_index(project, cx)
+    });
+    let estimate = project_index.read(cx).estimate(cx);
+    cx.spawn(|workspace, mut cx| async move {
+        let message = match estimate.await {
+            Ok(estimate) => estimate.summary(),
+            Err(error) => format!("Failed to estimate the semantic index: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<EstimateIndex>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Repairs the index of the workspace's project, for when search results are off
+/// because the index got out of sync with the project's files.
+fn repair_index(workspace: &mut Workspace, _: &RepairIndex, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let project_index = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.project_index(project, cx)
+    });
+    let repair = project_index.update(cx, |project_index, cx| project_index.repair(cx));
+    cx.spawn(|workspace, mut cx| async move {
+        let message = match repair.await {
+            Ok(verifications) => repair_summary(&verifications),
+            Err(error) => format!("Failed to repair the semantic index: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<RepairIndex>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Embeds the worktrees of the workspace's project that are pinned to another model
+/// again with the current one, so that searches include them again.
+fn reembed_with_current_model(
+    workspace: &mut Workspace,
+    _: &ReembedWithCurrentModel,
+    cx: &mut ViewContext<Workspace>,
+) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let project_index = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.project_index(project, cx)
+    });
+    let worktree_count = project_index.update(cx, |project_index, cx| {
+        project_index.reembed_pinned_worktrees(cx)
+    });
+    let message = match worktree_count {
+        0 => "No worktree is pinned to another embedding model".to_string(),
+        1 => "Embedding 1 worktree again with the current model".to_string(),
+        count => format!("Embedding {count} worktrees again with the current model"),
+    };
+    workspace.show_toast(
+        Toast::new(NotificationId::unique::<ReembedWithCurrentModel>(), message),
+        cx,
+    );
+}
+
+/// Stops indexing changes to the files of the workspace's project until indexing is
+/// resumed, like while switching branches.
+fn pause_indexing(workspace: &mut Workspace, _: &PauseIndexing, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let project_index = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.project_index(project, cx)
+    });
+    project_index.update(cx, |project_index, cx| project_index.pause(cx));
+}
+
+fn resume_indexing(workspace: &mut Workspace, _: &ResumeIndexing, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let project_index = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.project_index(project, cx)
+    });
+    project_index.update(cx, |project_index, cx| project_index.resume(cx));
+}
+
+/// Removes stale files from the index of every project and compacts its databases,
+/// to give back the space they took on disk.
+fn compact_index(_: &mut Workspace, _: &CompactIndex, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let compact = cx
+        .update_global::<SemanticIndex, _>(|semantic_index, cx| semantic_index.compact(|_| {}, cx));
+    cx.spawn(|workspace, mut cx| async move {
+        let message = match compact.await {
+            Ok(compaction) => compaction.summary(),
+            Err(error) => format!("Failed to compact the semantic index: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<CompactIndex>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Writes the embeddings of the workspace's project to an archive chosen by the user,
+/// for importing them on another machine.
+fn export_index(workspace: &mut Workspace, _: &ExportIndex, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let path = cx.prompt_for_new_path(util::paths::home_dir());
+    cx.spawn(|workspace, mut cx| async move {
+        let Some(path) = path.await?? else {
+            return Ok(());
+        };
+        let export = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+            semantic_index.export(&project, path, cx)
+        })?;
+        let message = match export.await {
+            Ok(()) => "Exported the semantic index".to_string(),
+            Err(error) => format!("Failed to export the semantic index: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<ExportIndex>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Imports the embeddings of an archive chosen by the user, written by
+/// [`ExportIndex`], so that the chunks in it aren't embedded again.
+fn import_index(_: &mut Workspace, _: &ImportIndex, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let paths = cx.prompt_for_paths(PathPromptOptions {
+        files: true,
+        directories: false,
+        multiple: false,
+    });
+    cx.spawn(|workspace, mut cx| async move {
+        let Some(path) = paths.await??.and_then(|paths| paths.into_iter().next()) else {
+            return Ok(());
+        };
+        let import = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+            semantic_index.import(path, cx)
+        })?;
+        let message = match import.await {
+            Ok(count) => format!("Imported {count} embeddings into the semantic index"),
+            Err(error) => format!("Failed to import into the semantic index: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<ImportIndex>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Shows how much the embedding provider was used to index the workspace's project,
+/// for users to keep track of what indexing costs them.
+fn show_embedding_usage(
+    workspace: &mut Workspace,
+    _: &ShowEmbeddingUsage,
+    cx: &mut ViewContext<Workspace>,
+) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+    let project = workspace.project().clone();
+    let usage_stats = cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+        semantic_index.usage_stats(&project, cx)
+    });
+    cx.spawn(|workspace, mut cx| async move {
+        let message = match usage_stats.await {
+            Ok(daily_usage) => usage_summary(&daily_usage),
+            Err(error) => format!("Failed to read the usage of the embedding provider: {error}"),
+        };
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.show_toast(
+                Toast::new(NotificationId::unique::<ShowEmbeddingUsage>(), message),
+                cx,
+            )
+        })
+    })
+    .detach_and_log_err(cx);
+}
@@ -99,7 +99,7 @@ impl PickerDelegate for Delegate {
             .block(fuzzy::match_strings(
                 &candidates,
                 &query,
-                true,
+                fuzzy::CaseSensitivity::Smart,
                 100,
                 &Default::default(),
                 cx.background_executor().clone(),
@@ -284,7 +284,7 @@ impl PickerDelegate for TasksModalDelegate {
             let matches = fuzzy::match_strings(
                 &candidates,
                 &query,
-                true,
+                fuzzy::CaseSensitivity::Smart,
                 1000,
                 &Default::default(),
                 cx.background_executor().clone(),
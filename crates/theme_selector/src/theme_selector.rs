@@ -1,7 +1,7 @@
 use client::telemetry::Telemetry;
 use feature_flags::FeatureFlagAppExt;
 use fs::Fs;
-use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatch, StringMatchCandidate};
 use gpui::{
     actions, impl_actions, AppContext, DismissEvent, EventEmitter, FocusableView, Render,
     UpdateGlobal, View, ViewContext, VisualContext, WeakView,
@@ -262,7 +262,7 @@ impl PickerDelegate for ThemeSelectorDelegate {
                 match_strings(
                     &candidates,
                     &query,
-                    false,
+                    CaseSensitivity::Insensitive,
                     100,
                     &Default::default(),
                     background,
@@ -184,7 +184,7 @@ impl PickerDelegate for BranchListDelegate {
                 fuzzy::match_strings(
                     &candidates,
                     &query,
-                    true,
+                    fuzzy::CaseSensitivity::Smart,
                     10000,
                     &Default::default(),
                     cx.background_executor().clone(),
@@ -1,6 +1,6 @@
 use super::base_keymap_setting::BaseKeymap;
 use client::telemetry::Telemetry;
-use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use fuzzy::{match_strings, CaseSensitivity, StringMatch, StringMatchCandidate};
 use gpui::{
     actions, AppContext, DismissEvent, EventEmitter, FocusableView, Render, Task, View,
     ViewContext, VisualContext, WeakView,
@@ -150,7 +150,7 @@ impl PickerDelegate for BaseKeymapSelectorDelegate {
                 match_strings(
                     &candidates,
                     &query,
-                    false,
+                    CaseSensitivity::Insensitive,
                     100,
                     &Default::default(),
                     background,
@@ -5096,6 +5096,13 @@ impl<'a> Traversal<'a> {
         false
     }
 
+    /// Moves past the entry at the given path and every entry within it, when the
+    /// traversal is at one of them.
+    pub fn advance_past(&mut self, path: &Path) -> bool {
+        self.cursor
+            .seek_forward(&TraversalTarget::PathSuccessor(path), Bias::Left, &())
+    }
+
     pub fn back_to_parent(&mut self) -> bool {
         let Some(parent_path) = self.cursor.item().and_then(|entry| entry.path.parent()) else {
             return false;